@@ -0,0 +1,808 @@
+//! Importing bibliographies
+//!
+//! Literature notes are easier to keep when the sources they discuss are thoughts too.
+//! This module reads BibTeX files and CSV exports from Zotero and turns each entry into a
+//! thought whose ID is the entry's citation key, tagged `reference`. The entry's fields
+//! (author, year, journal, DOI and so on) are stored in the thought's metadata, and its
+//! content is a formatted citation, so that other thoughts can link to the source with
+//! `[citation-key]`.
+//!
+//! Importing the same bibliography again updates the metadata and titles of the thoughts
+//! it created before, but keeps their content, tags and references, which may have been
+//! extended with notes since. An entry with a new citation key but the same fields as a
+//! source already in the graph, as happens when keys are regenerated or the same source
+//! is imported from BibTeX and from Zotero, is skipped as a duplicate. An import can be
+//! planned first, to see what it would do without changing the graph.
+//!
+//! Other thoughts cite imported sources as `@citation-key`. When a thought is published
+//! (see `ThoughtGraph::to_markdown`), its citations become author-year citations such as
+//! "(Knuth 1984)" and a list of the cited sources is appended.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::{ChangeSet, Command, Result, Tag, TagID, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// The tag given to imported entries
+pub const REFERENCE_TAG: &str = "reference";
+
+/// The metadata key holding an entry's type, such as "article" or "book"
+pub const ENTRY_TYPE_KEY: &str = "entry-type";
+
+/// What importing an entry does
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportOutcome {
+    /// A thought is created for the entry
+    Create,
+    /// The title or metadata of the entry's thought is updated
+    Update,
+    /// The entry's thought is already up to date
+    Unchanged,
+    /// The entry is skipped, since it has the same fields as the source with this ID
+    Duplicate(ThoughtID),
+}
+
+/// What importing some entries would do, as returned by
+/// `ThoughtGraph::plan_bibliography_import`
+#[derive(Clone, Debug)]
+pub struct ImportPlan {
+    /// The citation key of each entry, as a thought ID, with what importing it does
+    pub outcomes: Vec<(ThoughtID, ImportOutcome)>,
+    /// The commands that carry out the import
+    pub commands: Vec<Command>,
+}
+
+/// A hash of the fields and type of an entry, as stored in a thought's metadata,
+/// ignoring case and whitespace, for recognizing a source imported under another key
+fn content_hash(metadata: &BTreeMap<String, String>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for (key, value) in metadata {
+        key.hash(&mut hasher);
+        value.split_whitespace().for_each(|word| word.to_lowercase().hash(&mut hasher));
+        0u8.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// An entry of a bibliography
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BibEntry {
+    /// The citation key, used as the thought ID
+    pub key: String,
+    /// The BibTeX entry type, lowercase, e.g. "article"
+    pub entry_type: String,
+    /// The fields of the entry by lowercase name, with braces and extra whitespace
+    /// removed from their values
+    pub fields: BTreeMap<String, String>,
+}
+
+impl BibEntry {
+    /// The entry that a bibliography thought was imported from, or `None` if the thought
+    /// is not tagged `reference`
+    pub fn from_thought(id: &ThoughtID, thought: &Thought) -> Option<BibEntry> {
+        if !thought.tags.iter().any(|tag| tag.as_str() == REFERENCE_TAG) {
+            return None;
+        }
+        let mut fields = thought.metadata.clone();
+        let entry_type = fields.remove(ENTRY_TYPE_KEY).unwrap_or_else(|| "misc".to_string());
+        if let (false, Some(title)) = (fields.contains_key("title"), &thought.title) {
+            fields.insert("title".to_string(), title.clone());
+        }
+        Some(BibEntry { key: id.as_str().to_string(), entry_type, fields })
+    }
+
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str).filter(|value| !value.is_empty())
+    }
+
+    /// A citation such as "Knuth, Donald E. (1984). Literate Programming. The Computer
+    /// Journal."
+    pub fn citation(&self) -> String {
+        let mut citation = String::new();
+        let author = self.field("author").or_else(|| self.field("editor")).map(|a| a.replace(" and ", "; "));
+        match (author, self.field("year")) {
+            (Some(author), Some(year)) => citation.push_str(&format!("{} ({}). ", author, year)),
+            (Some(author), None) => citation.push_str(&format!("{}. ", author.trim_end_matches('.'))),
+            (None, Some(year)) => citation.push_str(&format!("({}). ", year)),
+            (None, None) => {},
+        }
+        if let Some(title) = self.field("title") {
+            citation.push_str(title.trim_end_matches('.'));
+            citation.push_str(". ");
+        }
+        let venue = ["journal", "booktitle", "publisher", "school", "institution"]
+            .iter()
+            .find_map(|name| self.field(name));
+        if let Some(venue) = venue {
+            citation.push_str(venue.trim_end_matches('.'));
+            citation.push_str(". ");
+        }
+        citation.trim_end().to_string()
+    }
+
+    /// A short in-text citation such as "Knuth 1984", "Abelson and Sussman 1985" or
+    /// "Knuth et al. 1984", or the citation key if the entry has no authors
+    pub fn short_citation(&self) -> String {
+        let last_names: Vec<&str> = self
+            .field("author")
+            .or_else(|| self.field("editor"))
+            .map(|authors| authors.split(" and ").map(|author| author.split(',').next().unwrap_or(author).trim()).collect())
+            .unwrap_or_default();
+        let names = match last_names.as_slice() {
+            [] => return self.key.clone(),
+            [name] => name.to_string(),
+            [first, second] => format!("{} and {}", first, second),
+            [first, ..] => format!("{} et al.", first),
+        };
+        match self.field("year") {
+            Some(year) => format!("{} {}", names, year),
+            None => names,
+        }
+    }
+
+    /// The content of a new thought for the entry: the citation, then the DOI or URL and
+    /// the abstract, if known
+    fn contents(&self) -> String {
+        let mut contents = self.citation();
+        if let Some(doi) = self.field("doi") {
+            contents.push_str(&format!("\n\nhttps://doi.org/{}", doi.trim_start_matches("https://doi.org/")));
+        } else if let Some(url) = self.field("url") {
+            contents.push_str(&format!("\n\n{}", url));
+        }
+        if let Some(abstract_note) = self.field("abstract") {
+            contents.push_str(&format!("\n\n{}", abstract_note));
+        }
+        contents
+    }
+}
+
+/// Pattern matching `@citation-key`, after the start of the text or a character that
+/// cannot be part of an email address
+fn citation_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"(?:^|[^\w@.])@(\w(?:[\w:./-]*\w)?)").unwrap())
+}
+
+/// Extract the citation keys cited as `@citation-key` in the given content.
+///
+/// Keys are returned in the order in which they appear, including duplicates. Email
+/// addresses are not mistaken for citations.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::bibliography::extract_citations;
+///
+/// let keys = extract_citations("As @knuth1984 argued (see also @sicp). Mail me@example.com");
+/// assert_eq!(keys, vec!["knuth1984", "sicp"]);
+/// ```
+pub fn extract_citations(contents: &str) -> Vec<&str> {
+    citation_pattern()
+        .captures_iter(contents)
+        .filter_map(|cap| cap.get(1))
+        .map(|m| m.as_str())
+        .collect()
+}
+
+/// Parse the entries of a BibTeX file.
+///
+/// `@string` abbreviations are expanded, `@comment` and `@preamble` blocks and text
+/// outside of entries are ignored, and values concatenated with `#` are joined.
+///
+/// # Returns
+///
+/// The entries in the order they appear, or `ParseError` describing the first malformed
+/// entry
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::bibliography::parse_bibtex;
+///
+/// let entries = parse_bibtex(r#"
+///     @article{knuth1984,
+///       author = {Knuth, Donald E.},
+///       title = {Literate {P}rogramming},
+///       journal = "The Computer Journal",
+///       year = 1984,
+///     }
+/// "#).unwrap();
+///
+/// assert_eq!(entries[0].key, "knuth1984");
+/// assert_eq!(entries[0].fields["title"], "Literate Programming");
+/// assert_eq!(entries[0].citation(), "Knuth, Donald E. (1984). Literate Programming. The Computer Journal.");
+/// ```
+pub fn parse_bibtex(text: &str) -> Result<Vec<BibEntry>> {
+    let mut parser = BibParser { chars: text.char_indices().peekable(), text, strings: BTreeMap::new() };
+    let mut entries = Vec::new();
+    while parser.skip_to_entry() {
+        if let Some(entry) = parser.entry()? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+struct BibParser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+    /// Abbreviations defined with `@string`
+    strings: BTreeMap<String, String>,
+}
+
+impl BibParser<'_> {
+    fn error(&mut self, message: &str) -> ThoughtGraphError {
+        let offset = self.chars.peek().map_or(self.text.len(), |(i, _)| *i);
+        let line = self.text[..offset].matches('\n').count() + 1;
+        ThoughtGraphError::ParseError(format!("BibTeX line {}: {}", line, message))
+    }
+
+    /// Move past the next `@`, returning false at the end of the text
+    fn skip_to_entry(&mut self) -> bool {
+        self.chars.by_ref().any(|(_, c)| c == '@')
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    /// A key, field name, entry type or abbreviation
+    fn name(&mut self) -> String {
+        self.skip_whitespace();
+        let mut name = String::new();
+        while let Some((_, c)) = self.chars.next_if(|(_, c)| !c.is_whitespace() && !"{}(),=#\"@".contains(*c)) {
+            name.push(c);
+        }
+        name
+    }
+
+    /// Text up to the brace matching one that was just read
+    fn braced(&mut self) -> Result<String> {
+        let mut depth = 1;
+        let mut value = String::new();
+        for (_, c) in self.chars.by_ref() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(value);
+                    }
+                },
+                _ => {},
+            }
+            value.push(c);
+        }
+        Err(self.error("unclosed '{'"))
+    }
+
+    /// A field value: braced or quoted strings, numbers and abbreviations, joined by `#`
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek().map(|(_, c)| *c) {
+                Some('{') => {
+                    self.chars.next();
+                    value.push_str(&self.braced()?);
+                },
+                Some('"') => {
+                    self.chars.next();
+                    let mut depth = 0;
+                    loop {
+                        match self.chars.next() {
+                            Some((_, '"')) if depth == 0 => break,
+                            Some((_, c)) => {
+                                depth += match c {
+                                    '{' => 1,
+                                    '}' => -1,
+                                    _ => 0,
+                                };
+                                value.push(c);
+                            },
+                            None => return Err(self.error("unclosed '\"'")),
+                        }
+                    }
+                },
+                _ => {
+                    let name = self.name();
+                    if name.is_empty() {
+                        return Err(self.error("expected a value"));
+                    }
+                    match self.strings.get(&name.to_lowercase()) {
+                        Some(expansion) => value.push_str(expansion),
+                        None => value.push_str(&name),
+                    }
+                },
+            }
+            self.skip_whitespace();
+            if self.chars.next_if(|(_, c)| *c == '#').is_none() {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// The rest of an entry after its `@`, or `None` for blocks that are not entries
+    fn entry(&mut self) -> Result<Option<BibEntry>> {
+        let entry_type = self.name().to_lowercase();
+        self.skip_whitespace();
+        let close = match self.chars.next() {
+            Some((_, '{')) => '}',
+            Some((_, '(')) => ')',
+            _ => return Err(self.error(&format!("expected '{{' after @{}", entry_type))),
+        };
+
+        match entry_type.as_str() {
+            "comment" | "preamble" => {
+                self.braced()?;
+                return Ok(None);
+            },
+            "string" => {
+                let name = self.name().to_lowercase();
+                self.expect('=')?;
+                let value = self.value()?;
+                self.strings.insert(name, value);
+                self.expect(close)?;
+                return Ok(None);
+            },
+            _ => {},
+        }
+
+        let key = self.name();
+        if key.is_empty() {
+            return Err(self.error(&format!("@{} entry has no citation key", entry_type)));
+        }
+        let mut fields = BTreeMap::new();
+        loop {
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, c)) if c == close => break,
+                Some((_, ',')) => {},
+                _ => return Err(self.error(&format!("expected ',' or '{}' in entry '{}'", close, key))),
+            }
+            self.skip_whitespace();
+            if self.chars.next_if(|(_, c)| *c == close).is_some() {
+                break;
+            }
+            let name = self.name().to_lowercase();
+            if name.is_empty() {
+                return Err(self.error(&format!("expected a field name in entry '{}'", key)));
+            }
+            self.expect('=')?;
+            fields.insert(name, clean_value(&self.value()?));
+        }
+        Ok(Some(BibEntry { key, entry_type, fields }))
+    }
+}
+
+/// Remove the braces BibTeX uses to protect capitalization, and collapse whitespace
+fn clean_value(value: &str) -> String {
+    let value: String = value.chars().filter(|c| *c != '{' && *c != '}').collect();
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parse a CSV export of a Zotero library.
+///
+/// Zotero exports have no citation keys, so a key is taken from a `Citation Key` column
+/// or a `Citation Key:` line in the `Extra` column (as added by Better BibTeX) when there
+/// is one, and is otherwise made from the first author's last name, the year and the
+/// first word of the title, e.g. `knuth1984literate`. Keys are made unique by appending
+/// letters.
+///
+/// # Returns
+///
+/// The entries in the order of the rows, or `ParseError` if the file is not valid CSV
+pub fn parse_zotero_csv(text: &str) -> Result<Vec<BibEntry>> {
+    let mut rows = parse_csv(text)?.into_iter();
+    let header = rows.next().unwrap_or_default();
+    let column = |name: &str| header.iter().position(|h| h.trim_start_matches('\u{feff}') == name);
+
+    // Zotero's columns and the BibTeX fields they correspond to
+    let mapping = [
+        ("Title", "title"),
+        ("Publication Year", "year"),
+        ("Publication Title", "journal"),
+        ("Publisher", "publisher"),
+        ("Volume", "volume"),
+        ("Issue", "number"),
+        ("Pages", "pages"),
+        ("DOI", "doi"),
+        ("Url", "url"),
+        ("ISBN", "isbn"),
+        ("ISSN", "issn"),
+        ("Abstract Note", "abstract"),
+        ("Editor", "editor"),
+    ];
+    let columns: Vec<(usize, &str)> =
+        mapping.iter().filter_map(|(header, field)| column(header).map(|i| (i, *field))).collect();
+    let (author, item_type, extra, citation_key) =
+        (column("Author"), column("Item Type"), column("Extra"), column("Citation Key"));
+
+    let mut entries = Vec::new();
+    let mut used = HashSet::new();
+    for row in rows {
+        let cell = |i: Option<usize>| i.and_then(|i| row.get(i)).map(|v| v.trim()).unwrap_or_default();
+        let mut fields = BTreeMap::new();
+        for (i, field) in &columns {
+            if let Some(value) = row.get(*i).map(|v| v.trim()).filter(|v| !v.is_empty()) {
+                fields.insert(field.to_string(), value.to_string());
+            }
+        }
+        // Zotero separates authors with semicolons, BibTeX with "and"
+        let authors: Vec<&str> = cell(author).split(';').map(str::trim).filter(|a| !a.is_empty()).collect();
+        if !authors.is_empty() {
+            fields.insert("author".to_string(), authors.join(" and "));
+        }
+        if fields.is_empty() {
+            continue;
+        }
+
+        let explicit_key = Some(cell(citation_key)).filter(|key| !key.is_empty()).or_else(|| {
+            cell(extra).lines().find_map(|line| line.strip_prefix("Citation Key:")).map(str::trim)
+        });
+        let key = match explicit_key {
+            Some(key) => key.to_string(),
+            None => unique_key(generated_key(&fields), &used),
+        };
+        used.insert(key.clone());
+        entries.push(BibEntry { key, entry_type: bibtex_type(cell(item_type)).to_string(), fields });
+    }
+    Ok(entries)
+}
+
+/// The BibTeX entry type closest to a Zotero item type
+fn bibtex_type(item_type: &str) -> &'static str {
+    match item_type {
+        "journalArticle" | "magazineArticle" | "newspaperArticle" => "article",
+        "book" => "book",
+        "bookSection" => "incollection",
+        "conferencePaper" => "inproceedings",
+        "thesis" => "phdthesis",
+        "report" => "techreport",
+        _ => "misc",
+    }
+}
+
+/// A key made from the first author's last name, the year and the first word of the
+/// title, keeping only ASCII letters and digits
+fn generated_key(fields: &BTreeMap<String, String>) -> String {
+    let last_name = fields
+        .get("author")
+        .and_then(|authors| authors.split(" and ").next())
+        .map(|author| author.split(',').next().unwrap_or(author))
+        .unwrap_or("anon");
+    let year = fields.get("year").map(String::as_str).unwrap_or("");
+    let first_word = fields
+        .get("title")
+        .and_then(|title| title.split_whitespace().find(|word| word.chars().any(char::is_alphanumeric)))
+        .unwrap_or("");
+    let key: String = format!("{}{}{}", last_name, year, first_word)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+    if key.is_empty() { "entry".to_string() } else { key }
+}
+
+/// `key`, or `key` followed by the first letter that makes it unused
+fn unique_key(key: String, used: &HashSet<String>) -> String {
+    if !used.contains(&key) {
+        return key;
+    }
+    let mut suffix = String::new();
+    loop {
+        // a, b, ..., z, za, zb, ...
+        match suffix.pop() {
+            Some('z') => suffix.push_str("za"),
+            Some(c) => suffix.push((c as u8 + 1) as char),
+            None => suffix.push('a'),
+        }
+        let candidate = format!("{}{}", key, suffix);
+        if !used.contains(&candidate) {
+            return candidate;
+        }
+    }
+}
+
+/// Split CSV text into rows of fields, handling quoted fields with commas, quotes and
+/// line breaks
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {},
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            },
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(ThoughtGraphError::ParseError("CSV ends inside a quoted field".to_string()));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+impl ThoughtGraph {
+    /// The bibliography entry of the thought cited as `@key`, if it is a bibliography
+    /// thought
+    pub fn cited_entry(&self, key: &str) -> Option<BibEntry> {
+        let (id, thought) = self.thoughts.get_key_value(&ThoughtID::new(key.to_string()))?;
+        BibEntry::from_thought(id, thought)
+    }
+
+    /// Replace each `@key` citing a bibliography thought with an author-year citation
+    /// such as "(Knuth 1984)". Citations of anything else are left as they are.
+    ///
+    /// # Returns
+    ///
+    /// The text with citations replaced, and the cited entries in order of their first
+    /// citation
+    pub fn resolve_citations(&self, text: &str) -> (String, Vec<BibEntry>) {
+        let mut cited: Vec<BibEntry> = Vec::new();
+        let mut resolved = String::with_capacity(text.len());
+        let mut last_end = 0;
+        for cap in citation_pattern().captures_iter(text) {
+            let key = cap.get(1).unwrap();
+            let Some(entry) = self.cited_entry(key.as_str()) else { continue };
+            resolved.push_str(&text[last_end..key.start() - 1]);
+            resolved.push_str(&format!("({})", entry.short_citation()));
+            last_end = key.end();
+            if !cited.iter().any(|c| c.key == entry.key) {
+                cited.push(entry);
+            }
+        }
+        resolved.push_str(&text[last_end..]);
+        (resolved, cited)
+    }
+
+    /// Create or update a thought for each bibliography entry.
+    ///
+    /// New thoughts get the entry's citation as their content. Existing thoughts keep
+    /// their content, tags and references, and only have their title and metadata
+    /// updated. All of them are tagged `reference`, which is created if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The entries to import, e.g. from `parse_bibtex`
+    ///
+    /// # Returns
+    ///
+    /// The changes made, or `InvalidThoughtID` if a citation key cannot be used as a
+    /// thought ID, in which case nothing is imported
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::ThoughtGraph;
+    /// use thoughtgraph::bibliography::parse_bibtex;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let entries = parse_bibtex("@book{sicp, title = {Structure and Interpretation of Computer Programs}, year = 1985}").unwrap();
+    /// let changes = graph.import_bibliography(entries).unwrap();
+    ///
+    /// assert_eq!(changes.thoughts_created[0].as_str(), "sicp");
+    /// let thought = graph.get_thought(&changes.thoughts_created[0]).unwrap();
+    /// assert_eq!(thought.metadata["year"], "1985");
+    /// ```
+    pub fn import_bibliography(&mut self, entries: Vec<BibEntry>) -> Result<ChangeSet> {
+        let plan = self.plan_bibliography_import(entries)?;
+        Ok(self.apply(Command::Batch(plan.commands)))
+    }
+
+    /// Work out what importing bibliography entries would do, without changing the graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The entries to import
+    ///
+    /// # Returns
+    ///
+    /// The plan, whose commands `import_bibliography` applies, or `InvalidThoughtID` if a
+    /// citation key cannot be used as a thought ID
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use thoughtgraph::bibliography::{parse_bibtex, ImportOutcome};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.import_bibliography(parse_bibtex("@book{sicp, title = {SICP}, year = 1985}").unwrap()).unwrap();
+    ///
+    /// let entries = parse_bibtex("@book{abelson1985, title = {SICP}, year = 1985} @book{htdp, title = {HtDP}}").unwrap();
+    /// let plan = graph.plan_bibliography_import(entries).unwrap();
+    /// assert_eq!(plan.outcomes[0].1, ImportOutcome::Duplicate(ThoughtID::new("sicp".to_string())));
+    /// assert_eq!(plan.outcomes[1].1, ImportOutcome::Create);
+    /// ```
+    pub fn plan_bibliography_import(&self, entries: Vec<BibEntry>) -> Result<ImportPlan> {
+        let tag = TagID::new(REFERENCE_TAG.to_string());
+        let mut plan = ImportPlan { outcomes: Vec::new(), commands: Vec::new() };
+        if !self.tags.contains_key(&tag) {
+            plan.commands.push(Command::PutTag { id: tag.clone(), tag: Tag::new("Sources such as books and papers".to_string()) });
+        }
+
+        // The sources already in the graph, and those imported earlier in this batch
+        let mut sources: HashMap<u64, ThoughtID> = self
+            .thoughts
+            .iter()
+            .filter(|(_, thought)| thought.tags.contains(&tag))
+            .map(|(id, thought)| (content_hash(&thought.metadata), id.clone()))
+            .collect();
+
+        for entry in entries {
+            let id = ThoughtID::parse(&entry.key)?;
+            let title = entry.field("title").map(str::to_string);
+            let mut metadata = entry.fields.clone();
+            metadata.insert(ENTRY_TYPE_KEY.to_string(), entry.entry_type.clone());
+            let hash = content_hash(&metadata);
+
+            let (outcome, thought) = match self.thoughts.get(&id) {
+                Some(existing) => {
+                    let mut thought = existing.clone();
+                    if thought.title != title || thought.metadata != metadata {
+                        thought.update_title(title);
+                        thought.metadata = metadata;
+                    }
+                    thought.add_tag(tag.clone());
+                    match &thought == existing {
+                        true => (ImportOutcome::Unchanged, None),
+                        false => (ImportOutcome::Update, Some(thought)),
+                    }
+                },
+                None => match sources.get(&hash) {
+                    Some(original) if *original != id => (ImportOutcome::Duplicate(original.clone()), None),
+                    _ => {
+                        let mut thought = Thought::new(title, entry.contents(), vec![tag.clone()], vec![]);
+                        thought.metadata = metadata;
+                        (ImportOutcome::Create, Some(thought))
+                    },
+                },
+            };
+            sources.entry(hash).or_insert_with(|| id.clone());
+            if let Some(thought) = thought {
+                plan.commands.push(Command::PutThought { id: id.clone(), thought });
+            }
+            plan.outcomes.push((id, outcome));
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BIBTEX: &str = r#"
+        Comments outside of entries are ignored.
+        @string{cj = "The Computer Journal"}
+        @comment{ @article{ignored, title = {Ignored}} }
+        @article{knuth1984,
+          author = {Knuth, Donald E.},
+          title = {Literate {Programming}},
+          journal = cj # ", Oxford",
+          year = 1984,
+          doi = {10.1093/comjnl/27.2.97}
+        }
+        @Book(sicp, title = "Structure and {I}nterpretation of
+              Computer Programs", author = "Abelson, Harold and Sussman, Gerald Jay",)
+    "#;
+
+    #[test]
+    fn test_parse_bibtex() {
+        let entries = parse_bibtex(BIBTEX).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].fields["journal"], "The Computer Journal, Oxford");
+        assert_eq!(entries[0].fields["year"], "1984");
+        assert_eq!(entries[1].entry_type, "book");
+        assert_eq!(entries[1].fields["title"], "Structure and Interpretation of Computer Programs");
+        assert_eq!(entries[1].citation(), "Abelson, Harold; Sussman, Gerald Jay. Structure and Interpretation of Computer Programs.");
+
+        match parse_bibtex("@article{broken,\n  title = {Unclosed\n") {
+            Err(ThoughtGraphError::ParseError(message)) => assert!(message.contains("unclosed '{'"), "{}", message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_zotero_csv() {
+        let csv = "\u{feff}\"Key\",\"Item Type\",\"Publication Year\",\"Author\",\"Title\",\"Publication Title\",\"Extra\"\r\n\
+            \"ABCD1234\",\"journalArticle\",\"1984\",\"Knuth, Donald E.\",\"Literate Programming\",\"The Computer Journal\",\"\"\r\n\
+            \"EFGH5678\",\"journalArticle\",\"1984\",\"Knuth, Donald E.; Other, A.\",\"Literate, \"\"programming\"\"\",\"\",\"\"\r\n\
+            \"IJKL9012\",\"book\",\"1985\",\"Abelson, Harold\",\"SICP\",\"\",\"Citation Key: sicp\nOther: x\"\r\n";
+        let entries = parse_zotero_csv(csv).unwrap();
+
+        let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, vec!["knuth1984literate", "knuth1984literatea", "sicp"]);
+        assert_eq!(entries[0].entry_type, "article");
+        assert_eq!(entries[0].fields["journal"], "The Computer Journal");
+        assert_eq!(entries[1].fields["author"], "Knuth, Donald E. and Other, A.");
+        assert_eq!(entries[1].fields["title"], "Literate, \"programming\"");
+        assert!(!entries[1].fields.contains_key("journal"));
+    }
+
+    #[test]
+    fn test_reimport_keeps_notes() {
+        let mut graph = ThoughtGraph::new();
+        let changes = graph.import_bibliography(parse_bibtex(BIBTEX).unwrap()).unwrap();
+        assert_eq!(changes.thoughts_created.len(), 2);
+        assert_eq!(changes.tags_created, vec![TagID::new(REFERENCE_TAG.to_string())]);
+
+        let id = ThoughtID::new("knuth1984".to_string());
+        let thought = graph.get_thought(&id).unwrap();
+        assert!(thought.contents.ends_with("https://doi.org/10.1093/comjnl/27.2.97"));
+        assert_eq!(thought.metadata[ENTRY_TYPE_KEY], "article");
+
+        let mut annotated = thought.clone();
+        annotated.contents.push_str("\n\nMy notes");
+        graph.apply(Command::PutThought { id: id.clone(), thought: annotated });
+        assert!(graph.import_bibliography(parse_bibtex(BIBTEX).unwrap()).unwrap().is_empty());
+
+        let changed = BIBTEX.replace("year = 1984", "year = 1983");
+        let changes = graph.import_bibliography(parse_bibtex(&changed).unwrap()).unwrap();
+        assert_eq!(changes.thoughts_updated, vec![id.clone()]);
+        let thought = graph.get_thought(&id).unwrap();
+        assert_eq!(thought.metadata["year"], "1983");
+        assert!(thought.contents.ends_with("My notes"));
+
+        let invalid = vec![BibEntry { key: "has space".to_string(), entry_type: "misc".to_string(), fields: BTreeMap::new() }];
+        assert!(graph.import_bibliography(invalid).is_err());
+    }
+
+    #[test]
+    fn test_plan_import() {
+        let mut graph = ThoughtGraph::new();
+        graph.import_bibliography(parse_bibtex(BIBTEX).unwrap()).unwrap();
+
+        // The same sources under new keys, one of them twice, and a new source
+        let bibtex = BIBTEX.replace("knuth1984", "knuth84").replace("sicp", "SICP-1985").replace("Computer Programs", "computer   programs")
+            + "@misc{new, title = {New}} @misc{again, title = {New}}";
+        let plan = graph.plan_bibliography_import(parse_bibtex(&bibtex).unwrap()).unwrap();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        assert_eq!(
+            plan.outcomes,
+            vec![
+                (id("knuth84"), ImportOutcome::Duplicate(id("knuth1984"))),
+                (id("SICP-1985"), ImportOutcome::Duplicate(id("sicp"))),
+                (id("new"), ImportOutcome::Create),
+                (id("again"), ImportOutcome::Duplicate(id("new"))),
+            ]
+        );
+        assert_eq!(plan.commands.len(), 1);
+        assert!(graph.get_thought(&id("new")).is_none());
+
+        let changed = BIBTEX.replace("year = 1984", "year = 1983");
+        let outcomes: Vec<ImportOutcome> =
+            graph.plan_bibliography_import(parse_bibtex(&changed).unwrap()).unwrap().outcomes.into_iter().map(|(_, o)| o).collect();
+        assert_eq!(outcomes, vec![ImportOutcome::Update, ImportOutcome::Unchanged]);
+    }
+}
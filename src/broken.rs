@@ -0,0 +1,255 @@
+//! Broken links
+//!
+//! A link breaks when the thought it points to is deleted or moved: a reference keeps
+//! the ID of a thought that no longer exists, and so does a mention of the thought in
+//! the content of another, written `[id]` or `[[id]]`. This module finds those links
+//! and fixes each one by removing it, creating an empty stub thought for it to point to,
+//! or pointing it at another thought.
+//!
+//! `[text](url)` Markdown links and `[x]` task list checkboxes are not mentions.
+
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::{ChangeSet, Command, Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Where a broken link is written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LinkSource {
+    /// A reference of the thought
+    Reference,
+    /// One or more mentions in the content of the thought
+    Mention,
+}
+
+/// A link from a thought to a thought that does not exist
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// The thought with the link
+    pub from: ThoughtID,
+    /// The missing thought it links to
+    pub to: ThoughtID,
+    /// Whether the link is a reference or a mention
+    pub source: LinkSource,
+}
+
+/// How to fix a broken link
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkFix {
+    /// Remove the reference, or turn the mentions into plain text
+    Remove,
+    /// Create an empty thought with the missing ID
+    Stub,
+    /// Link to another thought instead
+    Retarget(ThoughtID),
+}
+
+/// Pattern matching a mention of a thought, `[[id]]` or `[id]`
+fn mention_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\[\[([\w/-]+)\]\]|\[([\w/-]+)\]").unwrap())
+}
+
+/// A mention of a thought in some text
+struct Mention<'a> {
+    /// Where the mention is, brackets included
+    span: Range<usize>,
+    /// The ID mentioned
+    id: &'a str,
+    /// Whether the mention is written `[[id]]`
+    double: bool,
+}
+
+/// The mentions of thoughts in text, in order
+fn mentions(text: &str) -> Vec<Mention<'_>> {
+    mention_pattern()
+        .captures_iter(text)
+        .filter_map(|captures| {
+            let whole = captures.get(0).unwrap();
+            let (id, double) = match captures.get(1) {
+                Some(id) => (id.as_str(), true),
+                None => (captures.get(2).unwrap().as_str(), false),
+            };
+            if text[whole.end()..].starts_with('(') {
+                return None;
+            }
+            let line = &text[text[..whole.start()].rfind('\n').map_or(0, |i| i + 1)..whole.start()];
+            if id.eq_ignore_ascii_case("x") && line.trim_end().ends_with(['-', '*', '+']) {
+                return None;
+            }
+            Some(Mention { span: whole.range(), id, double })
+        })
+        .collect()
+}
+
+/// Rewrite the mentions of `id` in text
+fn replace_mentions(text: &str, id: &ThoughtID, replacement: impl Fn(bool) -> String) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut end = 0;
+    for mention in mentions(text) {
+        if mention.id == id.as_str() {
+            output.push_str(&text[end..mention.span.start]);
+            output.push_str(&replacement(mention.double));
+            end = mention.span.end;
+        }
+    }
+    output.push_str(&text[end..]);
+    output
+}
+
+impl ThoughtGraph {
+    /// Find the references and mentions of thoughts that do not exist.
+    ///
+    /// # Returns
+    ///
+    /// The broken links, in order of the thought they are in, then the missing thought,
+    /// with a reference before mentions of the same thought
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::broken::LinkSource;
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let plan = ThoughtID::new("plan".to_string());
+    /// graph.create_thought(plan.clone(), None, "See [[budget]] and [goals].".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(ThoughtID::new("goals".to_string()), None, String::new(), vec![], vec![]).unwrap();
+    ///
+    /// let links = graph.broken_links();
+    /// assert_eq!(links.len(), 1);
+    /// assert_eq!((links[0].to.as_str(), links[0].source), ("budget", LinkSource::Mention));
+    /// ```
+    pub fn broken_links(&self) -> Vec<BrokenLink> {
+        let mut links = Vec::new();
+        for (from, thought) in &self.thoughts {
+            let mut found: Vec<(ThoughtID, LinkSource)> = Vec::new();
+            for reference in &thought.references {
+                found.push((reference.id.clone(), LinkSource::Reference));
+            }
+            for mention in mentions(&thought.contents) {
+                found.push((ThoughtID::new(mention.id.to_string()), LinkSource::Mention));
+            }
+            found.retain(|(to, _)| !self.thoughts.contains_key(to));
+            found.sort_by(|(a, a_source), (b, b_source)| a.cmp(b).then(a_source.cmp(b_source)));
+            found.dedup();
+            links.extend(found.into_iter().map(|(to, source)| BrokenLink { from: from.clone(), to, source }));
+        }
+        links
+    }
+
+    /// Fix a broken link. Creating a stub does nothing if the missing thought has been
+    /// created since the link was found.
+    ///
+    /// # Arguments
+    ///
+    /// * `link` - The link, from `broken_links`
+    /// * `fix` - What to do about it
+    ///
+    /// # Returns
+    ///
+    /// The changes made, `ThoughtNotFound` if the thought with the link or the thought to
+    /// retarget it to does not exist, or `ThoughtLocked` if the thought with the link is
+    /// locked
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::broken::LinkFix;
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let plan = ThoughtID::new("plan".to_string());
+    /// let goals = ThoughtID::new("goals".to_string());
+    /// graph.create_thought(plan.clone(), None, "See [[budget]].".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(goals.clone(), None, String::new(), vec![], vec![]).unwrap();
+    ///
+    /// let link = graph.broken_links().remove(0);
+    /// graph.fix_broken_link(&link, &LinkFix::Retarget(goals)).unwrap();
+    /// assert_eq!(graph.get_thought(&plan).unwrap().contents, "See [[goals]].");
+    /// assert!(graph.broken_links().is_empty());
+    /// ```
+    pub fn fix_broken_link(&mut self, link: &BrokenLink, fix: &LinkFix) -> Result<ChangeSet> {
+        let thought = self.get_thought(&link.from).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(link.from.id.to_string()))?;
+        let patch = |contents: Option<String>, remove: bool, add| Command::PatchThought {
+            id: link.from.clone(),
+            title: None,
+            contents,
+            add_tags: vec![],
+            remove_tags: vec![],
+            add_references: add,
+            remove_references: if remove { vec![link.to.clone()] } else { vec![] },
+        };
+        let command = match (fix, link.source) {
+            (LinkFix::Stub, _) if self.thoughts.contains_key(&link.to) => return Ok(ChangeSet::default()),
+            (LinkFix::Stub, _) => Command::PutThought {
+                id: link.to.clone(),
+                thought: Thought::new(None, String::new(), vec![], vec![]),
+            },
+            (LinkFix::Retarget(target), _) if !self.thoughts.contains_key(target) => {
+                return Err(ThoughtGraphError::ThoughtNotFound(target.id.to_string()));
+            },
+            (LinkFix::Remove, LinkSource::Reference) => patch(None, true, vec![]),
+            (LinkFix::Remove, LinkSource::Mention) => {
+                patch(Some(replace_mentions(&thought.contents, &link.to, |_| link.to.id.to_string())), false, vec![])
+            },
+            (LinkFix::Retarget(target), LinkSource::Reference) => {
+                let references = thought.references.iter().filter(|reference| reference.id == link.to);
+                let add = references.map(|reference| crate::Reference { id: target.clone(), ..reference.clone() }).collect();
+                patch(None, true, add)
+            },
+            (LinkFix::Retarget(target), LinkSource::Mention) => {
+                let contents = replace_mentions(&thought.contents, &link.to, |double| match double {
+                    true => format!("[[{}]]", target.id),
+                    false => format!("[{}]", target.id),
+                });
+                patch(Some(contents), false, vec![])
+            },
+        };
+        self.try_command(&command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+    use chrono::Utc;
+
+    #[test]
+    fn test_broken_links() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let contents = "- [x] Read [[gone]] and [gone], [docs](https://example.com), [kept]";
+        let references = vec![Reference::new(id("gone"), "Notes".to_string(), Utc::now()), Reference::new(id("kept"), String::new(), Utc::now())];
+        graph.create_thought(id("note"), None, contents.to_string(), vec![], references).unwrap();
+        graph.create_thought(id("kept"), None, String::new(), vec![], vec![]).unwrap();
+        graph.create_thought(id("other"), None, String::new(), vec![], vec![]).unwrap();
+
+        let link = |source| BrokenLink { from: id("note"), to: id("gone"), source };
+        assert_eq!(graph.broken_links(), vec![link(LinkSource::Reference), link(LinkSource::Mention)]);
+
+        graph.fix_broken_link(&link(LinkSource::Mention), &LinkFix::Remove).unwrap();
+        assert_eq!(graph.get_thought(&id("note")).unwrap().contents, "- [x] Read gone and gone, [docs](https://example.com), [kept]");
+        graph.fix_broken_link(&link(LinkSource::Reference), &LinkFix::Retarget(id("other"))).unwrap();
+        let note = graph.get_thought(&id("note")).unwrap();
+        let references: Vec<(&str, &str)> = note.references.iter().map(|r| (r.id.as_str(), r.notes.as_str())).collect();
+        assert_eq!(references, vec![("kept", ""), ("other", "Notes")]);
+        assert!(graph.broken_links().is_empty());
+
+        graph.command(&Command::DeleteThought { id: id("kept") });
+        let stub = BrokenLink { from: id("note"), to: id("kept"), source: LinkSource::Mention };
+        assert!(matches!(graph.fix_broken_link(&stub, &LinkFix::Retarget(id("nowhere"))), Err(ThoughtGraphError::ThoughtNotFound(_))));
+        graph.fix_broken_link(&stub, &LinkFix::Stub).unwrap();
+        assert!(graph.get_thought(&id("kept")).is_some());
+        assert!(graph.broken_links().is_empty());
+        assert!(graph.fix_broken_link(&stub, &LinkFix::Stub).unwrap().is_empty());
+
+        graph.create_thought(id("locked"), None, "[missing]".to_string(), vec![], vec![]).unwrap();
+        graph.command(&Command::LockThought { id: id("locked") });
+        let locked = graph.broken_links().remove(0);
+        assert!(matches!(graph.fix_broken_link(&locked, &LinkFix::Remove), Err(ThoughtGraphError::ThoughtLocked(_))));
+    }
+}
@@ -0,0 +1,525 @@
+//! Query result caching
+//!
+//! Interactive front ends tend to run the same queries over and over (for example on
+//! every keystroke), while the graph itself changes comparatively rarely. The query
+//! cache remembers the results of recently used queries and, whenever a command
+//! modifies the graph, forgets only the results that the command could have changed.
+//! Results are remembered under the normalized form of their query, so that queries
+//! which differ only in the order or nesting of their conditions share one entry.
+//!
+//! The cache is disabled by default and is enabled with
+//! `ThoughtGraph::enable_query_cache`.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::search::{compile_pattern, glob_matches, TextTerm};
+use crate::{Command, Query, TagID, Thought, ThoughtGraph, ThoughtID};
+
+/// Number of queries remembered by `ThoughtGraph::enable_query_cache` by default
+pub const DEFAULT_QUERY_CACHE_CAPACITY: usize = 128;
+
+/// Statistics about the use of a query cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryCacheStats {
+    /// Number of queries answered from the cache
+    pub hits: u64,
+    /// Number of queries that had to be evaluated
+    pub misses: u64,
+    /// Number of results currently cached
+    pub entries: usize,
+}
+
+/// A cached query result
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    result: HashSet<ThoughtID>,
+    /// Value of the use counter when the entry was last used
+    last_used: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+struct CacheState {
+    /// Maximum number of entries; zero means the cache is disabled
+    capacity: usize,
+    entries: HashMap<Query, CacheEntry>,
+    uses: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// A least-recently-used cache of query results.
+///
+/// The cache uses interior mutability so that `ThoughtGraph::query` can keep taking
+/// `&self`; it is protected by a mutex so that graphs can still be shared between threads.
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    state: Mutex<CacheState>,
+}
+
+impl Clone for QueryCache {
+    fn clone(&self) -> Self {
+        Self { state: Mutex::new(self.lock().clone()) }
+    }
+}
+
+/// The part of the graph touched by a command, as far as queries are concerned
+struct Change<'a> {
+    thought: Option<(&'a ThoughtID, Option<&'a Thought>, Option<&'a Thought>)>,
+    tag: Option<&'a TagID>,
+}
+
+impl QueryCache {
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        // A panic while holding the lock cannot leave the cache inconsistent in a way
+        // that matters, since every entry is a complete query result
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Whether the cache is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.lock().capacity > 0
+    }
+
+    /// Enable the cache with the given capacity, or disable it with a capacity of zero
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        let mut state = self.lock();
+        state.capacity = capacity;
+        if capacity == 0 {
+            state.entries.clear();
+        }
+        while state.entries.len() > capacity {
+            evict_least_recently_used(&mut state);
+        }
+    }
+
+    /// Forget every cached result
+    pub(crate) fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    /// Usage statistics of the cache
+    pub(crate) fn stats(&self) -> QueryCacheStats {
+        let state = self.lock();
+        QueryCacheStats { hits: state.hits, misses: state.misses, entries: state.entries.len() }
+    }
+
+    /// Look up a query, counting a hit or a miss
+    pub(crate) fn get(&self, query: &Query) -> Option<HashSet<ThoughtID>> {
+        let mut state = self.lock();
+        state.uses += 1;
+        let uses = state.uses;
+        match state.entries.get_mut(query) {
+            Some(entry) => {
+                entry.last_used = uses;
+                let result = entry.result.clone();
+                state.hits += 1;
+                Some(result)
+            },
+            None => {
+                state.misses += 1;
+                None
+            },
+        }
+    }
+
+    /// Whether the result of a query is cached, without counting a use
+    pub(crate) fn contains(&self, query: &Query) -> bool {
+        self.lock().entries.contains_key(query)
+    }
+
+    /// Remember the result of a query
+    pub(crate) fn insert(&self, query: Query, result: &HashSet<ThoughtID>) {
+        let mut state = self.lock();
+        if state.capacity == 0 {
+            return;
+        }
+        if !state.entries.contains_key(&query) && state.entries.len() >= state.capacity {
+            evict_least_recently_used(&mut state);
+        }
+        let last_used = state.uses;
+        state.entries.insert(query, CacheEntry { result: result.clone(), last_used });
+    }
+
+    /// Forget the results that applying `command` to `graph` could change.
+    ///
+    /// Must be called before the command is applied.
+    pub(crate) fn invalidate(&self, graph: &ThoughtGraph, command: &Command) {
+        let mut state = self.lock();
+        if state.entries.is_empty() {
+            return;
+        }
+
+        let change = match command {
+            Command::PutThought { id, thought } => Change {
+                thought: Some((id, graph.thoughts.get(id), Some(thought))),
+                tag: None,
+            },
+            Command::DeleteThought { id } => Change {
+                thought: Some((id, graph.thoughts.get(id), None)),
+                tag: None,
+            },
+            Command::PutTag { id, .. } | Command::DeleteTag { id } => Change { thought: None, tag: Some(id) },
+            Command::PutTagRule { .. }
+            | Command::DeleteTagRule { .. }
+            | Command::PutSavedQuery { .. }
+            | Command::DeleteSavedQuery { .. }
+            | Command::LockThought { .. }
+            | Command::UnlockThought { .. } => return,
+            // `apply` resolves partial changes and batches before invalidating, so this is only reached by
+            // direct callers; be conservative
+            Command::PatchThought { .. }
+            | Command::AddTag { .. }
+            | Command::RemoveTag { .. }
+            | Command::AddReference { .. }
+            | Command::RemoveReference { .. }
+            | Command::Batch(_) => {
+                state.entries.clear();
+                return;
+            },
+        };
+
+        state.entries.retain(|query, _| !depends_on(graph, query, &change));
+    }
+}
+
+/// Remove the entry that was used least recently
+fn evict_least_recently_used(state: &mut CacheState) {
+    let oldest = state
+        .entries
+        .iter()
+        .min_by_key(|(_, entry)| entry.last_used)
+        .map(|(query, _)| query.clone());
+    if let Some(query) = oldest {
+        state.entries.remove(&query);
+    }
+}
+
+/// Whether the result of `query` may differ after `change` is applied to `graph`
+fn depends_on(graph: &ThoughtGraph, query: &Query, change: &Change) -> bool {
+    let versions = || {
+        change
+            .thought
+            .iter()
+            .flat_map(|(_, old, new)| old.iter().chain(new.iter()).copied())
+    };
+
+    match query {
+        Query::All => change.thought.is_some_and(|(_, old, new)| old.is_none() || new.is_none()),
+        Query::Id(target) => change.thought.is_some_and(|(id, _, _)| id == target),
+        // A change to any tag may change what a smart tag's query, or one it mentions,
+        // stands for
+        Query::Tag(tag) => match graph.smart_tag_query(tag) {
+            Some(query) => change.tag.is_some() || depends_on(graph, &query, change),
+            None => change.tag == Some(tag) || versions().any(|thought| thought.tags.contains(tag)),
+        },
+        Query::References(target) => {
+            // Deleting a thought also drops the backreferences pointing at it
+            change.thought.is_some_and(|(id, _, _)| id == target)
+                || versions().any(|thought| thought.references.iter().any(|r| &r.id == target))
+        },
+        Query::ReferencedBy(source) => match change.thought {
+            // Either the source itself changed, or a thought it references was added or
+            // removed (only existing thoughts are returned)
+            Some((id, _, _)) => {
+                id == source
+                    || graph
+                        .thoughts
+                        .get(source)
+                        .is_some_and(|thought| thought.references.iter().any(|r| &r.id == id))
+            },
+            None => false,
+        },
+        Query::And(subqueries) | Query::Or(subqueries) => {
+            subqueries.iter().any(|subquery| depends_on(graph, subquery, change))
+        },
+        Query::TitleContains(text) => {
+            let term = TextTerm::new(text);
+            versions().any(|thought| term.found_in(thought.title.as_deref()))
+        },
+        Query::ContentContains(text) => {
+            let term = TextTerm::new(text);
+            versions().any(|thought| term.found_in(Some(&thought.contents)))
+        },
+        // A pattern that does not compile never matches anything
+        Query::TitleRegex(pattern) => compile_pattern(pattern).is_ok_and(|regex| {
+            versions().any(|thought| thought.title.as_deref().is_some_and(|title| regex.is_match(title)))
+        }),
+        Query::ContentRegex(pattern) => compile_pattern(pattern).is_ok_and(|regex| {
+            versions().any(|thought| regex.is_match(&thought.contents))
+        }),
+        Query::ReferenceNotesContain(text) => {
+            let term = TextTerm::new(text);
+            versions().any(|thought| term.found_in_notes(thought))
+        },
+        Query::TagGlob(glob) => {
+            let matching = |tag: &TagID| glob_matches(glob, tag.as_str());
+            change.tag.is_some_and(matching) || versions().any(|thought| thought.tags.iter().any(matching))
+        },
+        Query::IdGlob(glob) => change.thought.is_some_and(|(id, _, _)| glob_matches(glob, id.as_str())),
+        Query::Untagged => versions().any(|thought| thought.tags.is_empty()),
+        // A thought's references make the thoughts they point at connected, so any change
+        // to a thought may change which thoughts are orphans or are reachable from others
+        Query::Orphan | Query::ReachableFrom { .. } | Query::ReachableTo { .. } => change.thought.is_some(),
+        // Backlinks only change when a thought's references do, but creating or deleting
+        // a thought also adds it to or removes it from the results
+        Query::MinBacklinks(_) | Query::MaxBacklinks(_) => change.thought.is_some_and(|(_, old, new)| match (old, new) {
+            (Some(old), Some(new)) => old.references != new.references,
+            _ => true,
+        }),
+        // The complement also gains or loses every thought that is created or deleted
+        Query::Not(subquery) => {
+            change.thought.is_some_and(|(_, old, new)| old.is_none() || new.is_none())
+                || depends_on(graph, subquery, change)
+        },
+    }
+}
+
+impl Query {
+    /// The normalized form of a query, which has the same results: nested `And`s and
+    /// `Or`s are flattened into their parents, their conditions are sorted and repeated
+    /// conditions dropped, and an `And` or `Or` of a single condition is replaced by that
+    /// condition. Queries that match nothing because they combine no conditions become
+    /// `Or([])`.
+    ///
+    /// # Returns
+    ///
+    /// The normalized query
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::Query;
+    ///
+    /// let a = Query::parse("tag:rust AND (tag:draft AND tag:rust)").unwrap();
+    /// let b = Query::parse("tag:draft AND tag:rust").unwrap();
+    /// assert_ne!(a, b);
+    /// assert_eq!(a.normalized(), b.normalized());
+    /// ```
+    pub fn normalized(&self) -> Query {
+        // Combine the normalized conditions of an `And` or an `Or`, after sorting them
+        // and dropping repeated ones
+        let combine = |mut conditions: Vec<Box<Query>>, combinator: fn(Vec<Box<Query>>) -> Query| {
+            conditions.sort_by_cached_key(|query| format!("{:?}", query));
+            conditions.dedup();
+            match conditions.len() {
+                0 => Query::Or(conditions),
+                1 => *conditions.remove(0),
+                _ => combinator(conditions),
+            }
+        };
+        match self {
+            Query::And(queries) => {
+                let mut conditions = Vec::new();
+                for query in queries {
+                    match query.normalized() {
+                        Query::And(inner) => conditions.extend(inner),
+                        // One condition matching nothing makes the whole conjunction match nothing
+                        Query::Or(inner) if inner.is_empty() => return Query::Or(inner),
+                        query => conditions.push(Box::new(query)),
+                    }
+                }
+                combine(conditions, Query::And)
+            },
+            Query::Or(queries) => {
+                let mut conditions = Vec::new();
+                for query in queries {
+                    match query.normalized() {
+                        Query::Or(inner) => conditions.extend(inner),
+                        query => conditions.push(Box::new(query)),
+                    }
+                }
+                combine(conditions, Query::Or)
+            },
+            Query::Not(query) => Query::Not(Box::new(query.normalized())),
+            query => query.clone(),
+        }
+    }
+}
+
+impl ThoughtGraph {
+    /// Enable caching of query results.
+    ///
+    /// While the cache is enabled, `query` (and everything built on it) answers repeated
+    /// queries from the cache. Commands keep the cache consistent by discarding only the
+    /// results they could affect. Modifying the graph's fields directly bypasses this, so
+    /// call `clear_query_cache` after doing so.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of query results to remember; the least recently used
+    ///   result is dropped when the cache is full
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, TagID, Query};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let tag = TagID::new("idea".to_string());
+    /// graph.create_tag(tag.clone(), "Ideas".to_string()).unwrap();
+    /// graph.enable_query_cache(64);
+    ///
+    /// let query = Query::Tag(tag.clone());
+    /// assert!(graph.query(&query).is_empty());
+    /// assert!(graph.query(&query).is_empty());
+    /// assert_eq!(graph.query_cache_stats().hits, 1);
+    ///
+    /// // Adding a tagged thought invalidates the cached result
+    /// graph.create_thought(ThoughtID::new("t".to_string()), None, "x".to_string(), vec![tag], vec![]).unwrap();
+    /// assert_eq!(graph.query(&query).len(), 1);
+    /// ```
+    pub fn enable_query_cache(&mut self, capacity: usize) {
+        self.query_cache.set_capacity(capacity);
+    }
+
+    /// Disable query caching and drop all cached results
+    pub fn disable_query_cache(&mut self) {
+        self.query_cache.set_capacity(0);
+    }
+
+    /// Drop all cached query results
+    pub fn clear_query_cache(&self) {
+        self.query_cache.clear();
+    }
+
+    /// Get usage statistics of the query cache
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        self.query_cache.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Reference, Tag};
+    use chrono::Utc;
+
+    fn thought(tags: &[&str], refs: &[&str]) -> Thought {
+        Thought::new(
+            None,
+            String::new(),
+            tags.iter().map(|t| TagID::new(t.to_string())).collect(),
+            refs.iter()
+                .map(|r| Reference::new(ThoughtID::new(r.to_string()), String::new(), Utc::now()))
+                .collect(),
+        )
+    }
+
+    fn put(graph: &mut ThoughtGraph, id: &str, tags: &[&str], refs: &[&str]) {
+        graph.command(&Command::PutThought { id: ThoughtID::new(id.to_string()), thought: thought(tags, refs) });
+    }
+
+    fn tag_query(tag: &str) -> Query {
+        Query::Tag(TagID::new(tag.to_string()))
+    }
+
+    fn setup() -> ThoughtGraph {
+        let mut graph = ThoughtGraph::new();
+        for tag in ["a", "b"] {
+            graph.command(&Command::PutTag { id: TagID::new(tag.to_string()), tag: Tag::new(String::new()) });
+        }
+        put(&mut graph, "x", &["a"], &["y"]);
+        put(&mut graph, "y", &["b"], &[]);
+        graph.enable_query_cache(DEFAULT_QUERY_CACHE_CAPACITY);
+        graph
+    }
+
+    #[test]
+    fn test_only_affected_entries_are_invalidated() {
+        let mut graph = setup();
+        graph.query(&tag_query("a"));
+        graph.query(&tag_query("b"));
+        assert_eq!(graph.query_cache_stats().entries, 2);
+
+        // Changing a thought tagged "b" leaves the "a" result alone
+        put(&mut graph, "y", &["b"], &["x"]);
+        assert_eq!(graph.query_cache_stats().entries, 1);
+        graph.query(&tag_query("a"));
+        assert_eq!(graph.query_cache_stats().hits, 1);
+
+        // Deleting the tag invalidates its queries
+        graph.command(&Command::DeleteTag { id: TagID::new("a".to_string()) });
+        assert!(graph.query(&tag_query("a")).is_empty());
+    }
+
+    #[test]
+    fn test_results_stay_correct() {
+        let mut graph = setup();
+        let x = ThoughtID::new("x".to_string());
+        let y = ThoughtID::new("y".to_string());
+        let queries = [
+            tag_query("a"),
+            Query::References(y.clone()),
+            Query::ReferencedBy(x.clone()),
+            Query::Or(vec![Box::new(tag_query("b")), Box::new(Query::References(x.clone()))]),
+            Query::Not(Box::new(tag_query("a"))),
+            Query::ContentContains(String::new()),
+            Query::ContentRegex("^$".to_string()),
+            Query::Untagged,
+            Query::Orphan,
+            Query::All,
+            Query::Id(y.clone()),
+            Query::ReachableFrom { id: x.clone(), depth: usize::MAX },
+            Query::ReachableTo { id: y.clone(), depth: 1 },
+            Query::TagGlob("*b".to_string()),
+            Query::IdGlob("*y".to_string()),
+            Query::ReferenceNotesContain(String::new()),
+            Query::MinBacklinks(1),
+            Query::MaxBacklinks(0),
+        ];
+        let check = |graph: &ThoughtGraph| {
+            for query in &queries {
+                let cached = graph.query(query);
+                let mut uncached = graph.clone();
+                uncached.disable_query_cache();
+                assert_eq!(cached, uncached.query(query), "{:?}", query);
+            }
+        };
+
+        check(&graph);
+        put(&mut graph, "z", &["a"], &["x"]);
+        check(&graph);
+        graph.command(&Command::DeleteThought { id: y.clone() });
+        check(&graph);
+        put(&mut graph, "y", &[], &[]);
+        check(&graph);
+        put(&mut graph, "x", &["b"], &[]);
+        check(&graph);
+    }
+
+    #[test]
+    fn test_equivalent_queries_share_an_entry() {
+        let graph = setup();
+        let parse = |text: &str| Query::parse(text).unwrap();
+        assert_eq!(graph.query(&parse("tag:a OR (tag:b OR tag:a)")).len(), 2);
+        assert_eq!(graph.query(&parse("tag:b OR tag:a")).len(), 2);
+        assert_eq!(graph.query(&parse("(tag:a OR tag:a)")).len(), 1);
+        assert_eq!(graph.query(&tag_query("a")).len(), 1);
+        let stats = graph.query_cache_stats();
+        assert_eq!((stats.hits, stats.entries), (2, 2));
+
+        // A conjunction with a condition matching nothing matches nothing
+        let empty = Query::And(vec![Box::new(tag_query("a")), Box::new(Query::And(vec![]))]);
+        assert_eq!(empty.normalized(), Query::Or(vec![]));
+        assert!(graph.query(&empty).is_empty());
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut graph = setup();
+        graph.enable_query_cache(2);
+
+        graph.query(&tag_query("a"));
+        graph.query(&tag_query("b"));
+        graph.query(&tag_query("a"));
+        graph.query(&tag_query("c"));
+
+        let stats = graph.query_cache_stats();
+        assert_eq!(stats.entries, 2);
+        graph.query(&tag_query("a"));
+        assert_eq!(graph.query_cache_stats().hits, stats.hits + 1);
+        graph.query(&tag_query("b"));
+        assert_eq!(graph.query_cache_stats().misses, stats.misses + 1);
+    }
+}
@@ -0,0 +1,313 @@
+//! Export to the JSON Canvas format
+//!
+//! [JSON Canvas](https://jsoncanvas.org) is an open format for infinite canvases, used
+//! most notably by Obsidian Canvas. Exporting a (sub)graph as a canvas turns every thought
+//! into a text card and every reference into an arrow, so the graph can be rearranged and
+//! annotated visually in other tools.
+//!
+//! Cards are laid out in columns following the direction of references: thoughts that
+//! nothing in the exported graph references come first, and every other thought is
+//! placed one column to the right of the nearest thought referencing it.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::visualization::GraphData;
+use crate::{ThoughtGraph, ThoughtID};
+
+/// Width of a card
+const CARD_WIDTH: i64 = 400;
+
+/// Minimum height of a card
+const MIN_CARD_HEIGHT: i64 = 100;
+
+/// Maximum height of a card; longer content scrolls inside the card
+const MAX_CARD_HEIGHT: i64 = 480;
+
+/// Approximate height of one line of card text
+const LINE_HEIGHT: i64 = 24;
+
+/// Approximate number of characters that fit on one line of a card
+const LINE_CHARACTERS: usize = 45;
+
+/// Horizontal space between columns of cards
+const COLUMN_GAP: i64 = 200;
+
+/// Vertical space between cards in a column
+const ROW_GAP: i64 = 60;
+
+/// A node of a JSON Canvas document
+#[derive(Debug, Serialize)]
+struct CanvasNode {
+    id: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    text: String,
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+}
+
+/// An edge of a JSON Canvas document
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CanvasEdge {
+    id: String,
+    from_node: String,
+    from_side: &'static str,
+    to_node: String,
+    to_side: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+
+/// A JSON Canvas document
+#[derive(Debug, Serialize)]
+struct Canvas {
+    nodes: Vec<CanvasNode>,
+    edges: Vec<CanvasEdge>,
+}
+
+/// Markdown text of the card for a thought
+fn card_text(graph: &ThoughtGraph, id: &str, label: &str) -> String {
+    let thought = match graph.get_thought(&ThoughtID::new(id.to_string())) {
+        Some(thought) => thought,
+        None => return format!("# {}", label),
+    };
+
+    let mut text = String::new();
+    if let Some(title) = &thought.title {
+        text.push_str(&format!("# {}\n\n", title));
+    }
+    text.push_str(thought.contents.trim_end());
+    if !thought.tags.is_empty() {
+        let tags: Vec<String> = thought.tags.iter().map(|t| format!("#{}", t.id)).collect();
+        text.push_str(&format!("\n\n{}", tags.join(" ")));
+    }
+    text
+}
+
+/// Estimate the height a card needs to show its text
+fn card_height(text: &str) -> i64 {
+    let lines: usize = text
+        .lines()
+        .map(|line| line.chars().count().div_ceil(LINE_CHARACTERS).max(1))
+        .sum();
+    (lines as i64 * LINE_HEIGHT + 2 * LINE_HEIGHT).clamp(MIN_CARD_HEIGHT, MAX_CARD_HEIGHT)
+}
+
+/// Assign every node a column: its distance from the nearest node without incoming edges.
+///
+/// Nodes that are only reachable through cycles are started from in ID order.
+fn columns(ids: &[&str], edges: &[(&str, &str)]) -> HashMap<String, usize> {
+    let mut outgoing: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut has_incoming: HashSet<&str> = HashSet::new();
+    for (source, target) in edges {
+        outgoing.entry(source).or_default().push(target);
+        has_incoming.insert(target);
+    }
+
+    let mut column: HashMap<String, usize> = HashMap::new();
+    let mut queue: VecDeque<(&str, usize)> = ids
+        .iter()
+        .filter(|id| !has_incoming.contains(*id))
+        .map(|id| (*id, 0))
+        .collect();
+
+    loop {
+        while let Some((id, depth)) = queue.pop_front() {
+            if column.contains_key(id) {
+                continue;
+            }
+            column.insert(id.to_string(), depth);
+            for target in outgoing.get(id).into_iter().flatten() {
+                if !column.contains_key(*target) {
+                    queue.push_back((target, depth + 1));
+                }
+            }
+        }
+
+        match ids.iter().find(|id| !column.contains_key(**id)) {
+            Some(id) => queue.push_back((id, 0)),
+            None => break,
+        }
+    }
+
+    column
+}
+
+/// Convert graph data into a JSON Canvas document.
+///
+/// Each node of `data` becomes a text card containing the thought's title, content and
+/// tags, and each edge becomes an arrow labeled with the reference notes. Edges to
+/// thoughts outside of `data` are left out, as are duplicate edges.
+///
+/// # Arguments
+///
+/// * `graph` - The graph the data was generated from, used for the card contents
+/// * `data` - The nodes and edges to export, e.g. from `generate_focused_graph`
+///
+/// # Returns
+///
+/// The canvas as a JSON string
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::{ThoughtGraph, ThoughtID, Reference};
+/// use thoughtgraph::canvas::to_json_canvas;
+/// use thoughtgraph::visualization::generate_graph_data;
+/// use chrono::Utc;
+///
+/// let mut graph = ThoughtGraph::new();
+/// let a = ThoughtID::new("a".to_string());
+/// graph.create_thought(a.clone(), Some("A".to_string()), "First".to_string(), vec![], vec![]).unwrap();
+/// graph.create_thought(
+///     ThoughtID::new("b".to_string()), None, "Second".to_string(), vec![],
+///     vec![Reference::new(a, "builds on".to_string(), Utc::now())],
+/// ).unwrap();
+///
+/// let canvas = to_json_canvas(&graph, &generate_graph_data(&graph));
+/// assert!(canvas.contains("\"fromNode\": \"b\""));
+/// assert!(canvas.contains("\"label\": \"builds on\""));
+/// ```
+pub fn to_json_canvas(graph: &ThoughtGraph, data: &GraphData) -> String {
+    let mut ids: Vec<&str> = data.nodes.iter().map(|node| node.id.as_str()).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let known: HashSet<&str> = ids.iter().copied().collect();
+
+    let mut seen_edges = HashSet::new();
+    let edges: Vec<_> = data
+        .edges
+        .iter()
+        .filter(|edge| known.contains(edge.source.as_str()) && known.contains(edge.target.as_str()))
+        .filter(|edge| seen_edges.insert((edge.source.as_str(), edge.target.as_str())))
+        .collect();
+
+    let pairs: Vec<(&str, &str)> = edges.iter().map(|e| (e.source.as_str(), e.target.as_str())).collect();
+    let column = columns(&ids, &pairs);
+
+    let labels: HashMap<&str, &str> = data.nodes.iter().map(|n| (n.id.as_str(), n.label.as_str())).collect();
+    let mut next_y: HashMap<usize, i64> = HashMap::new();
+    let mut nodes = Vec::with_capacity(ids.len());
+    for id in &ids {
+        let text = card_text(graph, id, labels[id]);
+        let height = card_height(&text);
+        let col = column[*id];
+        let y = next_y.entry(col).or_default();
+
+        nodes.push(CanvasNode {
+            id: id.to_string(),
+            kind: "text",
+            text,
+            x: col as i64 * (CARD_WIDTH + COLUMN_GAP),
+            y: *y,
+            width: CARD_WIDTH,
+            height,
+        });
+        *y += height + ROW_GAP;
+    }
+
+    let edges = edges
+        .into_iter()
+        .map(|edge| {
+            // Arrows between cards of the same column would cross the card, so they
+            // leave and enter on the bottom and top instead of the sides
+            let same_column = column[&edge.source] == column[&edge.target];
+            let backwards = column[&edge.source] > column[&edge.target];
+            let (from_side, to_side) = match (same_column, backwards) {
+                (true, _) => ("bottom", "top"),
+                (false, false) => ("right", "left"),
+                (false, true) => ("left", "right"),
+            };
+            CanvasEdge {
+                id: edge.id.clone(),
+                from_node: edge.source.clone(),
+                from_side,
+                to_node: edge.target.clone(),
+                to_side,
+                label: Some(edge.label.clone()).filter(|label| !label.is_empty()),
+            }
+        })
+        .collect();
+
+    let canvas = Canvas { nodes, edges };
+    serde_json::to_string_pretty(&canvas).expect("canvas serialization cannot fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visualization::{generate_focused_graph, generate_graph_data};
+    use crate::{Reference, TagID};
+    use chrono::Utc;
+    use serde_json::Value;
+
+    fn add(graph: &mut ThoughtGraph, id: &str, refs: &[&str]) {
+        let refs = refs
+            .iter()
+            .map(|r| Reference::new(ThoughtID::new(r.to_string()), String::new(), Utc::now()))
+            .collect();
+        graph
+            .create_thought(ThoughtID::new(id.to_string()), None, format!("about {}", id), vec![], refs)
+            .unwrap();
+    }
+
+    fn node<'a>(canvas: &'a Value, id: &str) -> &'a Value {
+        canvas["nodes"].as_array().unwrap().iter().find(|n| n["id"] == id).unwrap()
+    }
+
+    #[test]
+    fn test_layout_follows_references() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "root", &["child1", "child2"]);
+        add(&mut graph, "child1", &["grandchild"]);
+        add(&mut graph, "child2", &[]);
+        add(&mut graph, "grandchild", &[]);
+
+        let canvas: Value = serde_json::from_str(&to_json_canvas(&graph, &generate_graph_data(&graph))).unwrap();
+
+        assert_eq!(node(&canvas, "root")["x"], 0);
+        assert_eq!(node(&canvas, "child1")["x"], CARD_WIDTH + COLUMN_GAP);
+        assert_eq!(node(&canvas, "grandchild")["x"], 2 * (CARD_WIDTH + COLUMN_GAP));
+        // Cards in the same column do not overlap
+        let child1 = node(&canvas, "child1");
+        let child2 = node(&canvas, "child2");
+        assert!(child2["y"].as_i64().unwrap() >= child1["y"].as_i64().unwrap() + child1["height"].as_i64().unwrap());
+        assert_eq!(canvas["edges"].as_array().unwrap().len(), 3);
+        assert_eq!(canvas["edges"][0]["fromSide"], "right");
+    }
+
+    #[test]
+    fn test_cycles_and_dangling_edges() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "a", &["b", "missing"]);
+        add(&mut graph, "b", &["a"]);
+
+        let canvas: Value = serde_json::from_str(&to_json_canvas(&graph, &generate_graph_data(&graph))).unwrap();
+        assert_eq!(canvas["nodes"].as_array().unwrap().len(), 2);
+        assert_eq!(canvas["edges"].as_array().unwrap().len(), 2);
+        assert_eq!(node(&canvas, "a")["x"], 0);
+    }
+
+    #[test]
+    fn test_focused_subgraph_cards() {
+        let mut graph = ThoughtGraph::new();
+        let tag = TagID::new("idea".to_string());
+        graph.create_tag(tag.clone(), String::new()).unwrap();
+        graph
+            .create_thought(ThoughtID::new("center".to_string()), Some("Center".to_string()), "Body".to_string(), vec![tag], vec![])
+            .unwrap();
+        add(&mut graph, "near", &["center"]);
+        add(&mut graph, "far", &["near"]);
+
+        let data = generate_focused_graph(&graph, &ThoughtID::new("center".to_string()), 1);
+        let canvas: Value = serde_json::from_str(&to_json_canvas(&graph, &data)).unwrap();
+
+        assert_eq!(canvas["nodes"].as_array().unwrap().len(), 2);
+        assert_eq!(node(&canvas, "center")["text"], "# Center\n\nBody\n\n#idea");
+        assert_eq!(canvas["edges"].as_array().unwrap().len(), 1);
+    }
+}
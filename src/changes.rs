@@ -0,0 +1,268 @@
+//! Descriptions of the effects of commands
+//!
+//! Applying a command returns a `ChangeSet` listing what actually changed: which thoughts
+//! and tags were created, updated or deleted, and which backlinks appeared or disappeared.
+//! Commands that turn out to have no effect, such as adding a tag a thought already has,
+//! return an empty change set.
+
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+use crate::{TagID, ThoughtGraph, ThoughtID};
+
+/// The effects of applying one or more commands to a graph
+///
+/// Each list is in the order in which the changes happened. Backlinks are given as
+/// `(from, to)` pairs, meaning that the thought `from` references the thought `to`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ChangeSet {
+    /// Thoughts that did not exist before
+    pub thoughts_created: Vec<ThoughtID>,
+    /// Existing thoughts that were replaced or modified
+    pub thoughts_updated: Vec<ThoughtID>,
+    /// Thoughts that were removed
+    pub thoughts_deleted: Vec<ThoughtID>,
+    /// Thoughts that were locked
+    pub thoughts_locked: Vec<ThoughtID>,
+    /// Thoughts that were unlocked
+    pub thoughts_unlocked: Vec<ThoughtID>,
+    /// Backlinks that were added to the graph
+    pub backlinks_added: Vec<(ThoughtID, ThoughtID)>,
+    /// Backlinks that were removed from the graph
+    pub backlinks_removed: Vec<(ThoughtID, ThoughtID)>,
+    /// Tags that did not exist before
+    pub tags_created: Vec<TagID>,
+    /// Existing tags whose description was replaced
+    pub tags_updated: Vec<TagID>,
+    /// Tags that were removed
+    pub tags_deleted: Vec<TagID>,
+    /// Names of the tag rules that were added, replaced or removed
+    pub rules_changed: Vec<String>,
+    /// Names of the saved queries that were added, replaced or removed
+    pub saved_queries_changed: Vec<String>,
+}
+
+impl ChangeSet {
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.thoughts_created.is_empty()
+            && self.thoughts_updated.is_empty()
+            && self.thoughts_deleted.is_empty()
+            && self.thoughts_locked.is_empty()
+            && self.thoughts_unlocked.is_empty()
+            && self.backlinks_added.is_empty()
+            && self.backlinks_removed.is_empty()
+            && self.tags_created.is_empty()
+            && self.tags_updated.is_empty()
+            && self.tags_deleted.is_empty()
+            && self.rules_changed.is_empty()
+            && self.saved_queries_changed.is_empty()
+    }
+
+    /// The changes that turn one version of a graph into another.
+    ///
+    /// This finds the combined effect of any number of commands, however they were made,
+    /// by comparing the graphs. Each list is ordered by ID (or name) rather than by when
+    /// the changes happened, and a thought whose changes cancel out is not listed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ChangeSet, ThoughtGraph, ThoughtID};
+    ///
+    /// let before = ThoughtGraph::new();
+    /// let mut after = before.clone();
+    /// after.create_thought(ThoughtID::new("a".to_string()), None, String::new(), vec![], vec![]).unwrap();
+    ///
+    /// assert_eq!(ChangeSet::between(&before, &after).to_string(), "1 thought created");
+    /// ```
+    pub fn between(before: &ThoughtGraph, after: &ThoughtGraph) -> ChangeSet {
+        let (thoughts_created, thoughts_updated, thoughts_deleted) = diff_maps(&before.thoughts, &after.thoughts);
+        let (tags_created, tags_updated, tags_deleted) = diff_maps(&before.tags, &after.tags);
+        let (rules_created, rules_updated, rules_deleted) = diff_maps(&before.rules, &after.rules);
+        let (queries_created, queries_updated, queries_deleted) = diff_maps(&before.saved_queries, &after.saved_queries);
+
+        let backlinks = |graph: &ThoughtGraph| -> BTreeSet<(ThoughtID, ThoughtID)> {
+            graph
+                .backreferences
+                .iter()
+                .flat_map(|(to, sources)| sources.iter().map(move |from| (from.clone(), to.clone())))
+                .collect()
+        };
+        let (backlinks_before, backlinks_after) = (backlinks(before), backlinks(after));
+        let locked_before: BTreeSet<&ThoughtID> = before.locked.iter().collect();
+        let locked_after: BTreeSet<&ThoughtID> = after.locked.iter().collect();
+
+        ChangeSet {
+            thoughts_created,
+            thoughts_updated,
+            thoughts_deleted,
+            thoughts_locked: locked_after.difference(&locked_before).map(|id| (*id).clone()).collect(),
+            thoughts_unlocked: locked_before.difference(&locked_after).map(|id| (*id).clone()).collect(),
+            backlinks_added: backlinks_after.difference(&backlinks_before).cloned().collect(),
+            backlinks_removed: backlinks_before.difference(&backlinks_after).cloned().collect(),
+            tags_created,
+            tags_updated,
+            tags_deleted,
+            rules_changed: [rules_created, rules_updated, rules_deleted].concat(),
+            saved_queries_changed: [queries_created, queries_updated, queries_deleted].concat(),
+        }
+    }
+
+    /// Append the changes of a later command to this change set
+    pub fn extend(&mut self, later: ChangeSet) {
+        self.thoughts_created.extend(later.thoughts_created);
+        self.thoughts_updated.extend(later.thoughts_updated);
+        self.thoughts_deleted.extend(later.thoughts_deleted);
+        self.thoughts_locked.extend(later.thoughts_locked);
+        self.thoughts_unlocked.extend(later.thoughts_unlocked);
+        self.backlinks_added.extend(later.backlinks_added);
+        self.backlinks_removed.extend(later.backlinks_removed);
+        self.tags_created.extend(later.tags_created);
+        self.tags_updated.extend(later.tags_updated);
+        self.tags_deleted.extend(later.tags_deleted);
+        self.rules_changed.extend(later.rules_changed);
+        self.saved_queries_changed.extend(later.saved_queries_changed);
+    }
+}
+
+/// The keys that were added, changed and removed between two maps, each sorted
+fn diff_maps<K: Clone + Ord + Hash, V: PartialEq>(before: &HashMap<K, V>, after: &HashMap<K, V>) -> (Vec<K>, Vec<K>, Vec<K>) {
+    let mut created = Vec::new();
+    let mut updated = Vec::new();
+    for (key, value) in after {
+        match before.get(key) {
+            None => created.push(key.clone()),
+            Some(old) if old != value => updated.push(key.clone()),
+            Some(_) => {},
+        }
+    }
+    let mut deleted: Vec<K> = before.keys().filter(|key| !after.contains_key(*key)).cloned().collect();
+    created.sort();
+    updated.sort();
+    deleted.sort();
+    (created, updated, deleted)
+}
+
+/// A one-line summary such as "1 thought created, 2 backlinks added"
+impl fmt::Display for ChangeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = [
+            (self.thoughts_created.len(), "thought", "thoughts", "created"),
+            (self.thoughts_updated.len(), "thought", "thoughts", "updated"),
+            (self.thoughts_deleted.len(), "thought", "thoughts", "deleted"),
+            (self.thoughts_locked.len(), "thought", "thoughts", "locked"),
+            (self.thoughts_unlocked.len(), "thought", "thoughts", "unlocked"),
+            (self.backlinks_added.len(), "backlink", "backlinks", "added"),
+            (self.backlinks_removed.len(), "backlink", "backlinks", "removed"),
+            (self.tags_created.len(), "tag", "tags", "created"),
+            (self.tags_updated.len(), "tag", "tags", "updated"),
+            (self.tags_deleted.len(), "tag", "tags", "deleted"),
+            (self.rules_changed.len(), "rule", "rules", "changed"),
+            (self.saved_queries_changed.len(), "saved query", "saved queries", "changed"),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(count, _, _, _)| *count > 0)
+            .map(|(count, singular, plural, verb)| {
+                let noun = if *count == 1 { singular } else { plural };
+                format!("{} {} {}", count, noun, verb)
+            })
+            .collect();
+
+        if parts.is_empty() {
+            write!(f, "no changes")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Reference, Tag, Thought, ThoughtGraph};
+    use chrono::Utc;
+
+    fn id(name: &str) -> ThoughtID {
+        ThoughtID::new(name.to_string())
+    }
+
+    fn put(name: &str, refs: &[&str]) -> Command {
+        let references = refs.iter().map(|r| Reference::new(id(r), String::new(), Utc::now())).collect();
+        Command::PutThought { id: id(name), thought: Thought::new(None, String::new(), vec![], references) }
+    }
+
+    #[test]
+    fn test_thought_changes() {
+        let mut graph = ThoughtGraph::new();
+
+        let changes = graph.apply(put("a", &["b", "c"]));
+        assert_eq!(changes.thoughts_created, vec![id("a")]);
+        assert_eq!(changes.backlinks_added.len(), 2);
+
+        let changes = graph.apply(put("a", &["b", "d"]));
+        assert_eq!(changes.thoughts_updated, vec![id("a")]);
+        assert_eq!(changes.backlinks_added, vec![(id("a"), id("d"))]);
+        assert_eq!(changes.backlinks_removed, vec![(id("a"), id("c"))]);
+        assert_eq!(changes.to_string(), "1 thought updated, 1 backlink added, 1 backlink removed");
+
+        graph.apply(put("b", &[]));
+        let changes = graph.apply(Command::DeleteThought { id: id("b") });
+        assert_eq!(changes.thoughts_deleted, vec![id("b")]);
+        assert_eq!(changes.backlinks_removed, vec![(id("a"), id("b"))]);
+
+        let changes = graph.apply(Command::DeleteThought { id: id("b") });
+        assert!(changes.is_empty());
+        assert_eq!(changes.to_string(), "no changes");
+    }
+
+    #[test]
+    fn test_batches_and_tags() {
+        let mut graph = ThoughtGraph::new();
+        let tag = TagID::new("tag".to_string());
+
+        let changes = graph.apply(Command::Batch(vec![
+            Command::PutTag { id: tag.clone(), tag: Tag::new(String::new()) },
+            Command::PutTag { id: tag.clone(), tag: Tag::new("Described".to_string()) },
+            put("a", &[]),
+            Command::AddTag { id: id("a"), tag: tag.clone() },
+            Command::AddTag { id: id("a"), tag: tag.clone() },
+        ]));
+        assert_eq!(changes.tags_created, vec![tag.clone()]);
+        assert_eq!(changes.tags_updated, vec![tag.clone()]);
+        assert_eq!(changes.thoughts_created, vec![id("a")]);
+        assert_eq!(changes.thoughts_updated, vec![id("a")]);
+
+        let changes = graph.apply(Command::DeleteTag { id: tag.clone() });
+        assert_eq!(changes.tags_deleted, vec![tag]);
+    }
+
+    #[test]
+    fn test_changes_between_graphs() {
+        let mut before = ThoughtGraph::new();
+        before.apply(put("a", &["b"]));
+        before.apply(put("b", &[]));
+        before.apply(put("c", &[]));
+
+        let mut after = before.clone();
+        let applied = after.apply(Command::Batch(vec![
+            put("a", &["c"]),
+            Command::DeleteThought { id: id("b") },
+            put("d", &["a"]),
+            Command::LockThought { id: id("c") },
+        ]));
+
+        let between = ChangeSet::between(&before, &after);
+        assert_eq!(between.thoughts_created, vec![id("d")]);
+        assert_eq!(between.thoughts_updated, vec![id("a")]);
+        assert_eq!(between.thoughts_deleted, vec![id("b")]);
+        assert_eq!(between.thoughts_locked, vec![id("c")]);
+        assert_eq!(between.backlinks_added, vec![(id("a"), id("c")), (id("d"), id("a"))]);
+        assert_eq!(between.backlinks_removed, vec![(id("a"), id("b"))]);
+        assert_eq!(between.to_string(), applied.to_string());
+        assert!(ChangeSet::between(&after, &after).is_empty());
+    }
+}
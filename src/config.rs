@@ -0,0 +1,566 @@
+//! The configuration file of the `thoughts` command
+//!
+//! Settings are read from a TOML file, by default `thoughtgraph/config.toml` in the
+//! platform's configuration directory (`~/.config/thoughtgraph/config.toml` on Linux).
+//! Every setting is optional, and a missing file means the defaults. For example:
+//!
+//! ```toml
+//! editor = "code --wait"
+//!
+//! [hooks]
+//! on-create = "notify-send 'New thought'"
+//! on-delete = "jq -r .id >> ~/deleted-thoughts.txt"
+//!
+//! [browse]
+//! backlinks = "b"
+//!
+//! [dates]
+//! timezone = "UTC"
+//!
+//! [search]
+//! stop-words = ["the", "a", "of", "der", "die", "das"]
+//!
+//! [profiles.work]
+//! file = "~/work/thoughts.bin"
+//! tags = ["work"]
+//! editor = "nano"
+//! color = "never"
+//! ```
+//!
+//! The profile in use is the one given with `--profile`, or else the one in the
+//! `THOUGHTS_PROFILE` environment variable, or else the one last switched to, which is
+//! kept as `profile` in the file. The graph file is the one given with `--file`, or else
+//! the file of the profile given with `--profile`, or else the `THOUGHTS_FILE`
+//! environment variable, the file of the profile in `THOUGHTS_PROFILE`, `thoughts.bin`
+//! in the `THOUGHTS_DIR` directory, the file of the profile switched to, and finally
+//! `thoughtgraph/thoughts.bin` in the platform's data directory.
+//!
+//! Thoughts are edited with the editor given with `--editor`, or else the one of the
+//! profile in use, the `editor` setting, the `EDITOR` environment variable, and finally
+//! `vim`. An editor may include arguments, quoted as in a POSIX shell.
+//!
+//! The text the editor starts with for a new thought, or for text appended to one, can
+//! be replaced by the Markdown files `create.md` and `append.md` in a `templates`
+//! directory next to the configuration file. Comments in `<!-- -->` at the top of the
+//! text are removed when it is saved.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use toml_edit::{value, Array, DocumentMut, Item, Table};
+
+use crate::dates::DateFormat;
+use crate::hooks::Hooks;
+use crate::keys::BrowseKeys;
+use crate::text;
+#[cfg(feature = "webhooks")]
+use crate::webhooks::Webhooks;
+use crate::{Result, ThoughtGraphError};
+
+/// Name of the configuration file in the configuration directory
+pub const CONFIG_FILENAME: &str = "config.toml";
+
+/// Name of the graph file in the data directory or `THOUGHTS_DIR`
+pub const GRAPH_FILENAME: &str = "thoughts.bin";
+
+/// Name of the directory of editor templates, next to the configuration file
+pub const TEMPLATES_DIRNAME: &str = "templates";
+
+/// When to color the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Color the output when it is a terminal
+    Auto,
+    /// Always color the output
+    Always,
+    /// Never color the output
+    Never,
+}
+
+impl ColorMode {
+    /// The name of the mode in the configuration file
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColorMode::Auto => "auto",
+            ColorMode::Always => "always",
+            ColorMode::Never => "never",
+        }
+    }
+}
+
+impl std::str::FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("unknown color mode '{}' (expected 'auto', 'always' or 'never')", s)),
+        }
+    }
+}
+
+/// A named graph and the settings used with it, selected with `--profile`, the
+/// `THOUGHTS_PROFILE` environment variable, or `thoughts profile switch`
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// Path to the graph file, where a leading `~/` is the home directory
+    pub file: Option<PathBuf>,
+    /// Tags added to every thought created
+    pub tags: Vec<String>,
+    /// Command used to edit thoughts, instead of `$EDITOR`
+    pub editor: Option<String>,
+    /// When to color the output
+    pub color: Option<ColorMode>,
+}
+
+/// How words are compared by `thoughts search --stem` and by tag and link suggestions
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SearchSettings {
+    /// The stop words to leave out, instead of `text::STOP_WORDS`
+    pub stop_words: Option<Vec<String>>,
+}
+
+impl SearchSettings {
+    /// The configured stop words as words are compared, in lowercase without accents, or
+    /// `None` for `text::STOP_WORDS`
+    pub fn stop_word_set(&self) -> Option<HashSet<String>> {
+        let words = self.stop_words.as_ref()?;
+        Some(words.iter().map(|word| text::fold(word.trim(), true)).collect())
+    }
+}
+
+/// Settings of the `thoughts` command
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Command used to edit thoughts, instead of `$EDITOR`
+    pub editor: Option<String>,
+    /// Commands run when thoughts change
+    pub hooks: Hooks,
+    /// Key bindings of `thoughts browse`
+    pub browse: BrowseKeys,
+    /// How times are shown
+    pub dates: DateFormat,
+    /// How words are compared in searches and suggestions
+    pub search: SearchSettings,
+    /// URLs notified when the graph changes
+    #[cfg(feature = "webhooks")]
+    pub webhooks: Webhooks,
+    /// The profile last switched to
+    pub profile: Option<String>,
+    /// Named graphs, by name
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    /// The default location of the configuration file, if the platform has a
+    /// configuration directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("thoughtgraph").join(CONFIG_FILENAME))
+    }
+
+    /// Find the graph file from the command-line options and environment variables, in
+    /// the order described in the `config` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The `--file` option
+    /// * `profile` - The `--profile` option
+    /// * `var` - Looks up an environment variable, where unset and empty are the same
+    ///
+    /// # Returns
+    ///
+    /// The path to the graph file, `None` for the default in the data directory, or
+    /// `ConfigError` if the profile is not in the configuration
+    pub fn graph_path(
+        &self,
+        file: Option<PathBuf>,
+        profile: Option<&str>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<PathBuf>> {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+        if file.is_some() {
+            return Ok(file);
+        }
+        if let Some(path) = self.profile_file(profile)? {
+            return Ok(Some(path));
+        }
+        if let Some(path) = var("THOUGHTS_FILE") {
+            return Ok(Some(expand_home(Path::new(&path))));
+        }
+        if let Some(path) = self.profile_file(var("THOUGHTS_PROFILE").as_deref())? {
+            return Ok(Some(path));
+        }
+        if let Some(dir) = var("THOUGHTS_DIR") {
+            return Ok(Some(expand_home(Path::new(&dir)).join(GRAPH_FILENAME)));
+        }
+        self.profile_file(self.profile.as_deref())
+    }
+
+    /// The profile in use: the one given with `--profile`, or else the one in
+    /// `THOUGHTS_PROFILE`, or else the one last switched to.
+    ///
+    /// # Returns
+    ///
+    /// The name and settings of the profile, `None` if no profile is in use, or
+    /// `ConfigError` if the profile is not in the configuration
+    pub fn active_profile(
+        &self,
+        profile: Option<&str>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<(String, &Profile)>> {
+        let name = match profile {
+            Some(name) => Some(name.to_string()),
+            None => var("THOUGHTS_PROFILE").filter(|value| !value.is_empty()).or_else(|| self.profile.clone()),
+        };
+        match name {
+            Some(name) => {
+                let profile = self.get_profile(&name)?;
+                Ok(Some((name, profile)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn get_profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| ThoughtGraphError::ConfigError(format!("there is no profile named '{}'", name)))
+    }
+
+    /// The graph file of a profile, if it names one
+    fn profile_file(&self, name: Option<&str>) -> Result<Option<PathBuf>> {
+        let Some(name) = name else {
+            return Ok(None);
+        };
+        Ok(self.get_profile(name)?.file.as_deref().map(expand_home))
+    }
+
+    /// Whether anything should be done when the graph changes, which means keeping a
+    /// copy of the graph from before each command to compare against
+    pub fn watches_changes(&self) -> bool {
+        #[cfg(feature = "webhooks")]
+        if !self.webhooks.is_empty() {
+            return true;
+        }
+        !self.hooks.is_empty()
+    }
+
+    /// Read the configuration file at `path`.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, the default configuration if there is no file at `path`, or
+    /// `ConfigError` if the file is not valid
+    pub fn load(path: &Path) -> Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| match e {
+                ThoughtGraphError::ConfigError(message) => {
+                    ThoughtGraphError::ConfigError(format!("{}: {}", path.display(), message))
+                },
+                e => e,
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parse a configuration from TOML
+    pub fn parse(text: &str) -> Result<Config> {
+        let config: Config = toml::from_str(text).map_err(|e| ThoughtGraphError::ConfigError(e.message().to_string()))?;
+        config.browse.check()?;
+        config.dates.check()?;
+        if let Some(name) = &config.profile {
+            config.get_profile(name)?;
+        }
+        let editors = config.profiles.values().filter_map(|profile| profile.editor.as_deref());
+        for editor in config.editor.as_deref().into_iter().chain(editors) {
+            editor_command(editor)?;
+        }
+        Ok(config)
+    }
+}
+
+/// Read the configuration file at `path` as a TOML document that keeps its formatting,
+/// or an empty one if there is no file
+fn read_document(path: &Path) -> Result<DocumentMut> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    text.parse::<DocumentMut>()
+        .map_err(|e| ThoughtGraphError::ConfigError(format!("{}: {}", path.display(), e.message())))
+}
+
+/// Write a TOML document to the configuration file at `path`, checking that it is still
+/// a valid configuration
+fn write_document(path: &Path, document: &DocumentMut) -> Result<()> {
+    let text = document.to_string();
+    Config::parse(&text)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Add a profile to the configuration file at `path`, keeping the rest of the file as it
+/// is.
+///
+/// # Returns
+///
+/// `ConfigError` if the name is not made of letters, digits, `-` and `_`, a profile with
+/// this name already exists, or the file is not valid
+pub fn add_profile(path: &Path, name: &str, profile: &Profile) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(ThoughtGraphError::ConfigError(format!(
+            "'{}' is not a valid profile name (use letters, digits, '-' and '_')",
+            name
+        )));
+    }
+    let mut document = read_document(path)?;
+    let profiles = document
+        .entry("profiles")
+        .or_insert_with(|| {
+            let mut profiles = Table::new();
+            profiles.set_implicit(true);
+            Item::Table(profiles)
+        })
+        .as_table_mut()
+        .ok_or_else(|| ThoughtGraphError::ConfigError("'profiles' is not a table".to_string()))?;
+    if profiles.contains_key(name) {
+        return Err(ThoughtGraphError::ConfigError(format!("there is already a profile named '{}'", name)));
+    }
+
+    let mut table = Table::new();
+    if let Some(file) = &profile.file {
+        table["file"] = value(file.display().to_string());
+    }
+    if !profile.tags.is_empty() {
+        table["tags"] = value(profile.tags.iter().collect::<Array>());
+    }
+    if let Some(editor) = &profile.editor {
+        table["editor"] = value(editor);
+    }
+    if let Some(color) = profile.color {
+        table["color"] = value(color.as_str());
+    }
+    profiles.insert(name, Item::Table(table));
+    write_document(path, &document)
+}
+
+/// Make a profile the one in use when neither `--profile` nor `THOUGHTS_PROFILE` is
+/// given, by setting `profile` in the configuration file at `path`.
+///
+/// # Arguments
+///
+/// * `path` - The configuration file
+/// * `name` - The profile, or `None` to go back to the default graph
+///
+/// # Returns
+///
+/// `ConfigError` if there is no such profile or the file is not valid
+pub fn switch_profile(path: &Path, name: Option<&str>) -> Result<()> {
+    let mut document = read_document(path)?;
+    match name {
+        Some(name) => document["profile"] = value(name),
+        None => {
+            document.remove("profile");
+        },
+    }
+    write_document(path, &document)
+}
+
+/// Split an editor setting such as `code --wait` into the program and its arguments,
+/// which are quoted as in a POSIX shell.
+///
+/// # Returns
+///
+/// The program and its arguments, or `ConfigError` if the setting is empty or a quote
+/// is not closed
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::config::editor_command;
+///
+/// let (program, args) = editor_command("emacsclient -t --alternate-editor ''").unwrap();
+/// assert_eq!(program, "emacsclient");
+/// assert_eq!(args, vec!["-t", "--alternate-editor", ""]);
+/// ```
+pub fn editor_command(editor: &str) -> Result<(String, Vec<String>)> {
+    let mut words = shell_words::split(editor)
+        .map_err(|e| ThoughtGraphError::ConfigError(format!("editor '{}': {}", editor, e)))?;
+    if words.is_empty() {
+        return Err(ThoughtGraphError::ConfigError("the editor is empty".to_string()));
+    }
+    let program = words.remove(0);
+    Ok((program, words))
+}
+
+/// Read the editor template `name`, such as `create`, from `name.md` in `dir`.
+///
+/// # Returns
+///
+/// The text of the template, `None` if there is no such template, or `IoError` if it
+/// could not be read
+pub fn editor_template(dir: &Path, name: &str) -> Result<Option<String>> {
+    match fs::read_to_string(dir.join(name).with_extension("md")) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Remove the comments in `<!-- -->` at the top of text written in an editor, along with
+/// the blank lines after them.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::config::strip_editor_comments;
+///
+/// assert_eq!(strip_editor_comments("<!-- Write here -->\n\nHello"), "Hello");
+/// assert_eq!(strip_editor_comments("Hello <!-- there -->"), "Hello <!-- there -->");
+/// ```
+pub fn strip_editor_comments(text: &str) -> &str {
+    let mut rest = text;
+    while let Some(comment) = rest.trim_start().strip_prefix("<!--") {
+        match comment.find("-->") {
+            Some(end) => rest = comment[end + "-->".len()..].trim_start_matches([' ', '\t']).trim_start_matches(['\r', '\n']),
+            None => break,
+        }
+    }
+    rest
+}
+
+/// Replace a leading `~` in a path with the home directory
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILENAME);
+        assert!(Config::load(&path).unwrap().hooks.is_empty());
+
+        fs::write(&path, "[hooks]\non-edit = \"cat\"\n").unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.hooks.on_edit.as_deref(), Some("cat"));
+        assert!(config.hooks.on_create.is_none());
+
+        fs::write(&path, "[hooks]\non-edit = 3\n").unwrap();
+        assert!(matches!(Config::load(&path), Err(ThoughtGraphError::ConfigError(_))));
+
+        fs::write(&path, "[browse]\nbacklinks = \"b\"\n").unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!((config.browse.backlinks.as_str(), config.browse.down.as_str()), ("b", "j"));
+        fs::write(&path, "[browse]\nbacklinks = \"j\"\n").unwrap();
+        assert!(matches!(Config::load(&path), Err(ThoughtGraphError::ConfigError(_))));
+
+        fs::write(&path, "[search]\nstop-words = [\"Über\", \"und\"]\n").unwrap();
+        let stop_words = Config::load(&path).unwrap().search.stop_word_set();
+        assert_eq!(stop_words, Some(HashSet::from(["uber".to_string(), "und".to_string()])));
+        assert!(Config::default().search.stop_word_set().is_none());
+
+        fs::write(&path, "editor = \"code --wait\"\n").unwrap();
+        assert_eq!(Config::load(&path).unwrap().editor.as_deref(), Some("code --wait"));
+        for editor in ["editor = \"vim '\"", "editor = \" \"", "[profiles.work]\neditor = \"'nano\""] {
+            fs::write(&path, editor).unwrap();
+            assert!(matches!(Config::load(&path), Err(ThoughtGraphError::ConfigError(_))), "{:?} should not load", editor);
+        }
+    }
+
+    #[test]
+    fn test_editor_template() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(editor_template(dir.path(), "create").unwrap(), None);
+        fs::write(dir.path().join("create.md"), "<!-- New thought -->\n## Context\n").unwrap();
+        let template = editor_template(dir.path(), "create").unwrap().unwrap();
+        assert_eq!(strip_editor_comments(&template), "## Context\n");
+
+        assert_eq!(strip_editor_comments("<!-- a -->\n<!--\nb\n-->\n\n  indented"), "  indented");
+        assert_eq!(strip_editor_comments("<!-- unclosed\ntext"), "<!-- unclosed\ntext");
+        assert_eq!(strip_editor_comments("# Title: x\n\ntext"), "# Title: x\n\ntext");
+    }
+
+    #[test]
+    fn test_graph_path() {
+        let config = Config::parse("[profiles.work]\nfile = \"/work/thoughts.bin\"\n\n[profiles.plain]\n").unwrap();
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+        };
+        let path = |config: &Config, file: Option<&str>, profile: Option<&str>, vars| {
+            config.graph_path(file.map(PathBuf::from), profile, env(vars)).unwrap()
+        };
+
+        assert_eq!(path(&config, None, None, &[]), None);
+        assert_eq!(path(&config, Some("a.bin"), Some("work"), &[("THOUGHTS_FILE", "b.bin")]), Some(PathBuf::from("a.bin")));
+        assert_eq!(path(&config, None, Some("work"), &[("THOUGHTS_FILE", "b.bin")]), Some(PathBuf::from("/work/thoughts.bin")));
+        assert_eq!(path(&config, None, None, &[("THOUGHTS_FILE", "b.bin"), ("THOUGHTS_PROFILE", "work")]), Some(PathBuf::from("b.bin")));
+        assert_eq!(path(&config, None, None, &[("THOUGHTS_FILE", ""), ("THOUGHTS_PROFILE", "work")]), Some(PathBuf::from("/work/thoughts.bin")));
+        assert_eq!(path(&config, None, Some("plain"), &[("THOUGHTS_DIR", "/notes")]), Some(PathBuf::from("/notes/thoughts.bin")));
+        assert!(matches!(
+            config.graph_path(None, Some("home"), env(&[])),
+            Err(ThoughtGraphError::ConfigError(message)) if message.contains("'home'")
+        ));
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(path(&config, None, None, &[("THOUGHTS_FILE", "~/t.bin")]), Some(home.join("t.bin")));
+        }
+
+        let config = Config { profile: Some("work".to_string()), ..config };
+        assert_eq!(path(&config, None, None, &[]), Some(PathBuf::from("/work/thoughts.bin")));
+        assert_eq!(path(&config, None, None, &[("THOUGHTS_DIR", "/notes")]), Some(PathBuf::from("/notes/thoughts.bin")));
+        let active = |profile, vars| config.active_profile(profile, env(vars)).unwrap().map(|(name, _)| name);
+        assert_eq!(active(None, &[]).as_deref(), Some("work"));
+        assert_eq!(active(None, &[("THOUGHTS_PROFILE", "plain")]).as_deref(), Some("plain"));
+        assert_eq!(active(Some("plain"), &[]).as_deref(), Some("plain"));
+    }
+
+    #[test]
+    fn test_edit_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILENAME);
+        fs::write(&path, "# My settings\n[hooks]\non-edit = \"cat\"\n").unwrap();
+
+        let work = Profile {
+            file: Some(PathBuf::from("~/work.bin")),
+            tags: vec!["work".to_string()],
+            editor: Some("nano".to_string()),
+            color: Some(ColorMode::Never),
+        };
+        add_profile(&path, "work", &work).unwrap();
+        add_profile(&path, "home", &Profile::default()).unwrap();
+        assert!(matches!(add_profile(&path, "work", &work), Err(ThoughtGraphError::ConfigError(_))));
+        assert!(matches!(add_profile(&path, "a b", &work), Err(ThoughtGraphError::ConfigError(_))));
+        assert!(matches!(switch_profile(&path, Some("play")), Err(ThoughtGraphError::ConfigError(_))));
+
+        switch_profile(&path, Some("work")).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("profile = \"work\"\n# My settings\n[hooks]\n"), "{}", text);
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.hooks.on_edit.as_deref(), Some("cat"));
+        assert_eq!((config.profile.as_deref(), &config.profiles["work"]), (Some("work"), &work));
+        assert_eq!(config.profiles["home"], Profile::default());
+
+        switch_profile(&path, None).unwrap();
+        assert_eq!(Config::load(&path).unwrap().profile, None);
+    }
+}
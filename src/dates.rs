@@ -0,0 +1,342 @@
+//! How times are shown
+//!
+//! Thoughts are timestamped in UTC. The `thoughts` command shows times in the local
+//! timezone, or the one set in the `[dates]` section of the configuration file, and
+//! recent times relative to now, such as "3 days ago":
+//!
+//! ```toml
+//! [dates]
+//! format = "%d.%m.%Y %H:%M"
+//! timezone = "Europe/Berlin"
+//! relative = false
+//! ```
+//!
+//! Dates given on the command line are read with `parse_date`, which accepts dates such
+//! as `2025-02-01`, and relative ones such as `yesterday`, `last monday`, `3 days ago`,
+//! `7d` or `in 2 weeks`.
+
+use chrono::format::StrftimeItems;
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::{Result, ThoughtGraphError};
+
+/// How long ago a time may be and still be shown relative to now, in days
+pub const RELATIVE_DAYS: i64 = 7;
+
+/// Settings for showing times
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DateFormat {
+    /// The `strftime` format of times, e.g. `%Y-%m-%d %H:%M`
+    pub format: String,
+    /// The timezone times are shown in: `local`, or a name such as `UTC` or
+    /// `America/New_York`
+    pub timezone: String,
+    /// Whether to show times of the last week relative to now
+    pub relative: bool,
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        DateFormat { format: "%Y-%m-%d %H:%M".to_string(), timezone: "local".to_string(), relative: true }
+    }
+}
+
+impl DateFormat {
+    /// Check that the format and the timezone are valid, returning `ConfigError` if not
+    pub fn check(&self) -> Result<()> {
+        StrftimeItems::new(&self.format)
+            .parse()
+            .map_err(|_| ThoughtGraphError::ConfigError(format!("'{}' is not a valid date format", self.format)))?;
+        self.zone()?;
+        Ok(())
+    }
+
+    /// The configured timezone, or `None` for the local one
+    fn zone(&self) -> Result<Option<Tz>> {
+        if self.timezone.eq_ignore_ascii_case("local") {
+            return Ok(None);
+        }
+        self.timezone
+            .parse::<Tz>()
+            .map(Some)
+            .map_err(|_| ThoughtGraphError::ConfigError(format!("unknown timezone '{}'", self.timezone)))
+    }
+
+    /// A time in the configured format and timezone, e.g. `2025-02-26 10:00`
+    pub fn absolute(&self, at: DateTime<Utc>) -> String {
+        match self.zone() {
+            Ok(Some(zone)) => at.with_timezone(&zone).format(&self.format).to_string(),
+            _ => at.with_timezone(&Local).format(&self.format).to_string(),
+        }
+    }
+
+    /// A time for a column of a table: relative to `now` if it is recent and relative
+    /// times are on, and otherwise absolute.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::dates::DateFormat;
+    /// use chrono::{Duration, TimeZone, Utc};
+    ///
+    /// let now = Utc.with_ymd_and_hms(2025, 2, 26, 12, 0, 0).unwrap();
+    /// let format = DateFormat { timezone: "UTC".to_string(), ..DateFormat::default() };
+    /// assert_eq!(format.short(now - Duration::hours(3), now), "3 hours ago");
+    /// assert_eq!(format.short(now - Duration::days(30), now), "2025-01-27 12:00");
+    /// ```
+    pub fn short(&self, at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        match self.relative.then(|| time_ago(at, now)).flatten() {
+            Some(ago) => ago,
+            None => self.absolute(at),
+        }
+    }
+
+    /// A time in full: absolute, followed by how long ago it was if it is recent and
+    /// relative times are on, e.g. `2025-02-26 10:00 (2 hours ago)`
+    pub fn long(&self, at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        match self.relative.then(|| time_ago(at, now)).flatten() {
+            Some(ago) => format!("{} ({})", self.absolute(at), ago),
+            None => self.absolute(at),
+        }
+    }
+}
+
+/// How long before `now` a time was, such as "just now", "5 minutes ago" or
+/// "yesterday", or `None` if it is in the future or more than `RELATIVE_DAYS` ago
+pub fn time_ago(at: DateTime<Utc>, now: DateTime<Utc>) -> Option<String> {
+    let elapsed = now.signed_duration_since(at);
+    let plural = |n: i64, unit: &str| format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" });
+    if elapsed.num_seconds() < 0 || elapsed.num_days() >= RELATIVE_DAYS {
+        None
+    } else if elapsed.num_minutes() < 1 {
+        Some("just now".to_string())
+    } else if elapsed.num_hours() < 1 {
+        Some(plural(elapsed.num_minutes(), "minute"))
+    } else if elapsed.num_days() < 1 {
+        Some(plural(elapsed.num_hours(), "hour"))
+    } else if elapsed.num_days() < 2 {
+        Some("yesterday".to_string())
+    } else {
+        Some(plural(elapsed.num_days(), "day"))
+    }
+}
+
+fn date_error(message: String) -> ThoughtGraphError {
+    ThoughtGraphError::ParseError(format!("date: {}", message))
+}
+
+/// Move a time by a number of a unit such as `days` or `week`, or `None` if the unit is
+/// unknown or the result is out of range
+fn shift<Tz: TimeZone>(at: DateTime<Tz>, amount: i64, unit: &str) -> Option<DateTime<Tz>> {
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+    let months = |months: i64| {
+        let months = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+        match amount < 0 {
+            true => at.clone().checked_sub_months(months),
+            false => at.clone().checked_add_months(months),
+        }
+    };
+    match unit {
+        "m" | "min" | "minute" => at.clone().checked_add_signed(Duration::try_minutes(amount)?),
+        "h" | "hour" => at.clone().checked_add_signed(Duration::try_hours(amount)?),
+        "d" | "day" => at.clone().checked_add_signed(Duration::try_days(amount)?),
+        "w" | "week" => at.clone().checked_add_signed(Duration::try_weeks(amount)?),
+        "month" => months(amount),
+        "year" => months(amount.checked_mul(12)?),
+        _ => None,
+    }
+}
+
+/// The start of a day in the timezone of `now`
+fn midnight<Tz: TimeZone>(date: NaiveDate, now: &DateTime<Tz>) -> Option<DateTime<Utc>> {
+    now.timezone().from_local_datetime(&date.and_hms_opt(0, 0, 0)?).earliest().map(|at| at.with_timezone(&Utc))
+}
+
+/// Read a date or time given on the command line.
+///
+/// Dates without a time, such as `yesterday` or `last friday`, are the start of the day
+/// in the timezone of `now`. A bare duration such as `7d` or `12h` is that long ago.
+///
+/// # Arguments
+///
+/// * `text` - The date: `now`, `today`, `yesterday`, `tomorrow`, a date such as
+///   `2025-02-01` with an optional time such as `14:30`, a weekday with an optional
+///   `last` or `next`, `last week`, `next month`, `3 days ago`, `in 2 weeks`, or `7d`
+/// * `now` - The current time, in the timezone dates are read in
+///
+/// # Returns
+///
+/// The time, or `ParseError` describing what is wrong with the text
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::dates::parse_date;
+/// use chrono::{TimeZone, Utc};
+///
+/// // A Wednesday
+/// let now = Utc.with_ymd_and_hms(2025, 2, 26, 15, 30, 0).unwrap();
+/// assert_eq!(parse_date("yesterday", now).unwrap(), Utc.with_ymd_and_hms(2025, 2, 25, 0, 0, 0).unwrap());
+/// assert_eq!(parse_date("last monday", now).unwrap(), Utc.with_ymd_and_hms(2025, 2, 24, 0, 0, 0).unwrap());
+/// assert_eq!(parse_date("in 2 weeks", now).unwrap(), Utc.with_ymd_and_hms(2025, 3, 12, 15, 30, 0).unwrap());
+/// assert!(parse_date("someday", now).is_err());
+/// ```
+pub fn parse_date<Tz: TimeZone>(text: &str, now: DateTime<Tz>) -> Result<DateTime<Utc>> {
+    let lower = text.trim().to_lowercase();
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    let today = now.date_naive();
+    let day = |date: Option<NaiveDate>| {
+        date.and_then(|date| midnight(date, &now)).ok_or_else(|| date_error(format!("'{}' is out of range", text.trim())))
+    };
+    let weekday = |name: &str| name.parse::<Weekday>().ok();
+    let amount = |word: &str| match word {
+        "a" | "an" | "one" => Some(1),
+        word => word.parse::<i64>().ok(),
+    };
+    let shifted = |amount: i64, unit: &str| {
+        shift(now.clone(), amount, unit).map(|at| at.with_timezone(&Utc)).ok_or_else(|| {
+            date_error(format!("unknown unit '{}' (expected minutes, hours, days, weeks, months or years)", unit))
+        })
+    };
+
+    match words.as_slice() {
+        [] => Err(date_error("the date is empty".to_string())),
+        ["now"] => Ok(now.with_timezone(&Utc)),
+        ["today"] => day(Some(today)),
+        ["yesterday"] => day(today.pred_opt()),
+        ["tomorrow"] => day(today.succ_opt()),
+        [date] if date.contains('-') => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| date_error(format!("'{}' is not a valid date (expected e.g. 2025-02-01)", date)))?;
+            day(Some(date))
+        },
+        [date, time] if date.contains('-') && time.contains(':') => {
+            let at = NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").map_err(|_| {
+                date_error(format!("'{} {}' is not a valid time (expected e.g. 2025-02-01 14:30)", date, time))
+            })?;
+            now.timezone()
+                .from_local_datetime(&at)
+                .earliest()
+                .map(|at| at.with_timezone(&Utc))
+                .ok_or_else(|| date_error(format!("'{} {}' does not exist in this timezone", date, time)))
+        },
+        [name] if weekday(name).is_some() => {
+            let target = weekday(name).map(|day| day.num_days_from_monday()).unwrap_or_default();
+            let back = (7 + today.weekday().num_days_from_monday() - target) % 7;
+            day(today.checked_sub_signed(Duration::days(back.into())))
+        },
+        ["last", name] if weekday(name).is_some() => {
+            let target = weekday(name).map(|day| day.num_days_from_monday()).unwrap_or_default();
+            let back = match (7 + today.weekday().num_days_from_monday() - target) % 7 {
+                0 => 7,
+                back => back,
+            };
+            day(today.checked_sub_signed(Duration::days(back.into())))
+        },
+        ["next", name] if weekday(name).is_some() => {
+            let target = weekday(name).map(|day| day.num_days_from_monday()).unwrap_or_default();
+            let ahead = match (7 + target - today.weekday().num_days_from_monday()) % 7 {
+                0 => 7,
+                ahead => ahead,
+            };
+            day(today.checked_add_signed(Duration::days(ahead.into())))
+        },
+        ["last", unit] => shifted(-1, unit),
+        ["next", unit] => shifted(1, unit),
+        [count, unit, "ago"] => match amount(count) {
+            Some(count) => shifted(-count, unit),
+            None => Err(date_error(format!("'{}' is not a number", count))),
+        },
+        ["in", count, unit] => match amount(count) {
+            Some(count) => shifted(count, unit),
+            None => Err(date_error(format!("'{}' is not a number", count))),
+        },
+        [duration] if duration.starts_with(|c: char| c.is_ascii_digit()) => {
+            let unit_start = duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len());
+            let (count, unit) = duration.split_at(unit_start);
+            match (count.parse::<i64>(), unit) {
+                (Ok(count), "m" | "h" | "d" | "w") => shifted(-count, unit),
+                _ => Err(date_error(format!(
+                    "'{}' is not a duration (expected a number followed by m, h, d or w, e.g. 7d)",
+                    duration
+                ))),
+            }
+        },
+        _ => Err(date_error(format!(
+            "'{}' is not a date (try e.g. 2025-02-01, yesterday, last monday, 3 days ago, 7d or in 2 weeks)",
+            text.trim()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_date_format() {
+        let now = Utc.with_ymd_and_hms(2025, 2, 26, 12, 0, 0).unwrap();
+        let ago = |duration| time_ago(now - duration, now);
+        assert_eq!(ago(Duration::seconds(20)).as_deref(), Some("just now"));
+        assert_eq!(ago(Duration::minutes(1)).as_deref(), Some("1 minute ago"));
+        assert_eq!(ago(Duration::hours(23)).as_deref(), Some("23 hours ago"));
+        assert_eq!(ago(Duration::hours(30)).as_deref(), Some("yesterday"));
+        assert_eq!(ago(Duration::days(6)).as_deref(), Some("6 days ago"));
+        assert_eq!(ago(Duration::days(7)), None);
+        assert_eq!(ago(Duration::minutes(-5)), None);
+
+        let format = DateFormat { format: "%d.%m.%Y %H:%M".to_string(), timezone: "Europe/Berlin".to_string(), relative: true };
+        format.check().unwrap();
+        assert_eq!(format.absolute(now), "26.02.2025 13:00");
+        assert_eq!(format.long(now - Duration::hours(2), now), "26.02.2025 11:00 (2 hours ago)");
+        let format = DateFormat { relative: false, ..format };
+        assert_eq!(format.short(now - Duration::hours(2), now), "26.02.2025 11:00");
+
+        let bad_zone = DateFormat { timezone: "Mars/Olympus".to_string(), ..DateFormat::default() };
+        assert!(matches!(bad_zone.check(), Err(ThoughtGraphError::ConfigError(_))));
+        let bad_format = DateFormat { format: "%Q".to_string(), ..DateFormat::default() };
+        assert!(matches!(bad_format.check(), Err(ThoughtGraphError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_parse_date() {
+        // A Wednesday
+        let now = Utc.with_ymd_and_hms(2025, 2, 26, 15, 30, 0).unwrap();
+        let date = |text: &str| parse_date(text, now).unwrap();
+        let day = |month, day| Utc.with_ymd_and_hms(2025, month, day, 0, 0, 0).unwrap();
+
+        assert_eq!(date(" Today "), day(2, 26));
+        assert_eq!(date("tomorrow"), day(2, 27));
+        assert_eq!(date("2025-01-31"), day(1, 31));
+        assert_eq!(date("2025-01-31 09:15"), Utc.with_ymd_and_hms(2025, 1, 31, 9, 15, 0).unwrap());
+        assert_eq!(date("wednesday"), day(2, 26));
+        assert_eq!(date("friday"), day(2, 21));
+        assert_eq!(date("last wednesday"), day(2, 19));
+        assert_eq!(date("next wed"), day(3, 5));
+        assert_eq!(date("next friday"), day(2, 28));
+        assert_eq!(date("last month"), Utc.with_ymd_and_hms(2025, 1, 26, 15, 30, 0).unwrap());
+        assert_eq!(date("a week ago"), now - Duration::weeks(1));
+        assert_eq!(date("2 years ago"), Utc.with_ymd_and_hms(2023, 2, 26, 15, 30, 0).unwrap());
+        assert_eq!(date("in 3 hours"), now + Duration::hours(3));
+        assert_eq!(date("12h"), now - Duration::hours(12));
+
+        // Days start at midnight in the timezone of now
+        let tokyo = chrono::FixedOffset::east_opt(9 * 3600).unwrap();
+        assert_eq!(parse_date("today", now.with_timezone(&tokyo)).unwrap(), Utc.with_ymd_and_hms(2025, 2, 26, 15, 0, 0).unwrap());
+
+        let error = |text: &str| match parse_date(text, now) {
+            Err(ThoughtGraphError::ParseError(message)) => message,
+            result => panic!("{:?} should not parse: {:?}", text, result),
+        };
+        assert!(error("2025-02-30").contains("not a valid date"));
+        assert!(error("3 fortnights ago").contains("unknown unit 'fortnights'"));
+        assert!(error("some days ago").contains("'some' is not a number"));
+        assert!(error("7x").contains("not a duration"));
+        assert!(error("someday").contains("try e.g."));
+        assert!(error("").contains("empty"));
+    }
+}
@@ -0,0 +1,142 @@
+//! Line diffs between versions of a thought
+//!
+//! Edits made in an external editor are shown as a unified diff before they are saved,
+//! so that accidental deletions can be caught. The diff is computed over lines with a
+//! longest common subsequence, after skipping the lines the two versions share at their
+//! start and end.
+
+/// A line of a diff
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    /// A line in both versions
+    Same(&'a str),
+    /// A line only in the old version
+    Removed(&'a str),
+    /// A line only in the new version
+    Added(&'a str),
+}
+
+/// Compare two texts line by line.
+///
+/// # Returns
+///
+/// Every line of both texts in order, with removed lines before the lines added in their
+/// place
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    // lengths[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] { lengths[i + 1][j + 1] + 1 } else { lengths[i + 1][j].max(lengths[i][j + 1]) };
+        }
+    }
+
+    let mut lines: Vec<DiffLine> = old[..prefix].iter().map(|line| DiffLine::Same(line)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push(DiffLine::Same(a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            lines.push(DiffLine::Removed(a[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(b[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|line| DiffLine::Same(line)));
+    lines
+}
+
+/// Format the changes between two texts as the hunks of a unified diff.
+///
+/// # Arguments
+///
+/// * `old` - The old text
+/// * `new` - The new text
+/// * `context` - The number of unchanged lines to show around each change
+///
+/// # Returns
+///
+/// The hunks, each starting with a `@@ -start,count +start,count @@` line, or an empty
+/// string if the texts have the same lines
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::diff::unified_diff;
+///
+/// let diff = unified_diff("tea\ncoffee\nwater\n", "tea\ncocoa\nwater\n", 1);
+/// assert_eq!(diff, "@@ -1,3 +1,3 @@\n tea\n-coffee\n+cocoa\n water\n");
+/// ```
+pub fn unified_diff(old: &str, new: &str, context: usize) -> String {
+    let lines = diff_lines(old, new);
+    let changed: Vec<usize> = (0..lines.len()).filter(|&i| !matches!(lines[i], DiffLine::Same(_))).collect();
+
+    // Group the changes whose context overlaps into hunks of line ranges
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut output = String::new();
+    for (start, end) in hunks {
+        // Line numbers in each version where the hunk starts
+        let old_start = lines[..start].iter().filter(|line| !matches!(line, DiffLine::Added(_))).count();
+        let new_start = lines[..start].iter().filter(|line| !matches!(line, DiffLine::Removed(_))).count();
+        let old_count = lines[start..end].iter().filter(|line| !matches!(line, DiffLine::Added(_))).count();
+        let new_count = lines[start..end].iter().filter(|line| !matches!(line, DiffLine::Removed(_))).count();
+        output.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_count > 0),
+            old_count,
+            new_start + usize::from(new_count > 0),
+            new_count
+        ));
+        for line in &lines[start..end] {
+            let (marker, text) = match line {
+                DiffLine::Same(text) => (' ', text),
+                DiffLine::Removed(text) => ('-', text),
+                DiffLine::Added(text) => ('+', text),
+            };
+            output.push(marker);
+            output.push_str(text);
+            output.push('\n');
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(
+            diff_lines("a\nb\nc\nd", "a\nc\nx\nd"),
+            vec![DiffLine::Same("a"), DiffLine::Removed("b"), DiffLine::Same("c"), DiffLine::Added("x"), DiffLine::Same("d")]
+        );
+        assert_eq!(diff_lines("", "new"), vec![DiffLine::Added("new")]);
+        assert_eq!(diff_lines("same\n", "same"), vec![DiffLine::Same("same")]);
+
+        let old: String = (1..=10).map(|n| format!("{}\n", n)).collect();
+        let new = old.replace("2\n", "two\n").replace("9\n", "");
+        assert_eq!(unified_diff(&old, &new, 1), "@@ -1,3 +1,3 @@\n 1\n-2\n+two\n 3\n@@ -8,3 +8,2 @@\n 8\n-9\n 10\n");
+        assert_eq!(unified_diff("gone\n", "", 3), "@@ -1,1 +0,0 @@\n-gone\n");
+        assert_eq!(unified_diff(&old, &old, 3), "");
+    }
+}
@@ -0,0 +1,111 @@
+//! Duplicating thoughts
+//!
+//! Recurring notes, such as a weekly review or a meeting agenda, are easiest to start
+//! from a copy of the last one. A duplicate has the title, contents and tags of the
+//! original, fresh timestamps, and a reference back to the thought it was cloned from.
+
+use chrono::Utc;
+
+use crate::{Command, Reference, Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// The notes of the reference from a duplicate to its original
+pub const CLONED_FROM: &str = "Cloned from";
+
+impl ThoughtGraph {
+    /// An unused ID for a copy of a thought, such as `weekly-review-copy` or
+    /// `weekly-review-copy-2`
+    pub fn copy_id(&self, id: &ThoughtID) -> ThoughtID {
+        let base = format!("{}-copy", id.id);
+        let mut copy = ThoughtID::new(base.clone());
+        let mut n = 2;
+        while self.thoughts.contains_key(&copy) {
+            copy = ThoughtID::new(format!("{}-{}", base, n));
+            n += 1;
+        }
+        copy
+    }
+
+    /// Create a copy of a thought.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The thought to copy
+    /// * `to` - The ID of the copy, which must be valid and not yet in use
+    /// * `with_references` - Whether to copy the references of the original as well
+    ///
+    /// # Returns
+    ///
+    /// The copy, `ThoughtNotFound` if `from` does not exist, or `InvalidThoughtID` if `to`
+    /// is invalid or already in use
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let review = ThoughtID::new("review-2025-02-21".to_string());
+    /// graph.create_thought(review.clone(), Some("Weekly Review".to_string()), "- Wins:\n- Next:".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let next = ThoughtID::new("review-2025-02-28".to_string());
+    /// let copy = graph.clone_thought(&review, &next, false).unwrap();
+    /// assert_eq!(copy.contents, "- Wins:\n- Next:");
+    /// assert_eq!(copy.references[0].id, review);
+    /// ```
+    pub fn clone_thought(&mut self, from: &ThoughtID, to: &ThoughtID, with_references: bool) -> Result<&Thought> {
+        let original = self.get_thought(from).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(from.id.to_string()))?;
+        ThoughtID::parse(to.as_str())?;
+        if self.thoughts.contains_key(to) {
+            return Err(ThoughtGraphError::InvalidThoughtID(format!("'{}' is already in use", to.id)));
+        }
+
+        let mut references: Vec<Reference> = match with_references {
+            true => original.references.iter().filter(|r| r.id != *to).cloned().collect(),
+            false => Vec::new(),
+        };
+        references.retain(|r| r.id != *from);
+        references.push(Reference::new(from.clone(), CLONED_FROM.to_string(), Utc::now()));
+        let thought = Thought::new(original.title.clone(), original.contents.clone(), original.tags.clone(), references);
+        self.try_command(&Command::PutThought { id: to.clone(), thought })?;
+        self.get_thought(to).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(to.id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TagID;
+
+    #[test]
+    fn test_clone_thought() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let tag = TagID::new("meeting".to_string());
+        graph.create_tag(tag.clone(), "Meetings".to_string()).unwrap();
+        graph.create_thought(id("project"), None, String::new(), vec![], vec![]).unwrap();
+        graph
+            .create_thought(
+                id("agenda"),
+                Some("Agenda".to_string()),
+                "1. [project]".to_string(),
+                vec![tag.clone()],
+                vec![Reference::new(id("project"), "Auto-reference from [project]".to_string(), Utc::now())],
+            )
+            .unwrap();
+
+        assert_eq!(graph.copy_id(&id("agenda")), id("agenda-copy"));
+        let copy = graph.clone_thought(&id("agenda"), &id("agenda-copy"), true).unwrap();
+        assert_eq!((copy.title.as_deref(), copy.tags.clone()), (Some("Agenda"), vec![tag]));
+        let references: Vec<(&str, &str)> = copy.references.iter().map(|r| (r.id.as_str(), r.notes.as_str())).collect();
+        assert_eq!(references, vec![("project", "Auto-reference from [project]"), ("agenda", CLONED_FROM)]);
+        assert_eq!(graph.copy_id(&id("agenda")), id("agenda-copy-2"));
+
+        let copy = graph.clone_thought(&id("agenda"), &id("agenda-2"), false).unwrap();
+        assert_eq!(copy.references.len(), 1);
+        assert!(graph.get_backlinks(&id("agenda")).contains(&id("agenda-2")));
+
+        assert!(matches!(graph.clone_thought(&id("agenda"), &id("agenda-2"), false), Err(ThoughtGraphError::InvalidThoughtID(_))));
+        assert!(matches!(graph.clone_thought(&id("agenda"), &id("bad id"), false), Err(ThoughtGraphError::InvalidThoughtID(_))));
+        assert!(matches!(graph.clone_thought(&id("missing"), &id("new"), false), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+}
@@ -0,0 +1,240 @@
+//! Passphrase encryption of graph files
+//!
+//! With the `encryption` feature, a graph can be saved encrypted with a key derived from
+//! a passphrase. An encrypted file starts with its own header, followed by the random
+//! salt the key was derived with (Argon2id), the nonce, and the graph in the usual file
+//! format encrypted with ChaCha20-Poly1305. The header and salt are authenticated along
+//! with the graph, so a damaged or tampered file fails to decrypt instead of loading.
+//!
+//! Deriving the key is deliberately slow. The `thoughts` tool keeps the derived key (see
+//! `GraphKey::to_secret`) in the OS keyring after the first unlock, so that the
+//! passphrase is only asked for again after `thoughts lock`.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+
+use crate::format::ENCRYPTED_MAGIC;
+use crate::progress::ProgressFn;
+use crate::{storage, Result, ThoughtGraph, ThoughtGraphError};
+
+/// Version of the encrypted format written by `GraphKey::encrypt`
+const ENCRYPTED_VERSION: u16 = 1;
+
+/// Length of the salt the key is derived with
+const SALT_LEN: usize = 16;
+
+/// Length of a ChaCha20-Poly1305 nonce
+const NONCE_LEN: usize = 12;
+
+/// Length of the authenticated header: the magic number, a little-endian `u16` version
+/// and the salt
+const HEADER_LEN: usize = ENCRYPTED_MAGIC.len() + 2 + SALT_LEN;
+
+/// Whether `data` is an encrypted graph
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENCRYPTED_MAGIC)
+}
+
+/// Whether the file at `path` holds an encrypted graph, reading only its first bytes
+pub fn file_is_encrypted<P: AsRef<Path>>(path: P) -> Result<bool> {
+    let mut magic = [0; ENCRYPTED_MAGIC.len()];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(is_encrypted(&magic)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A key derived from a passphrase, along with the salt it was derived with
+#[derive(Clone)]
+pub struct GraphKey {
+    salt: [u8; SALT_LEN],
+    key: Key,
+}
+
+impl std::fmt::Debug for GraphKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GraphKey").finish_non_exhaustive()
+    }
+}
+
+impl GraphKey {
+    /// Derive a key from a passphrase with a new random salt, to encrypt a graph with.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::encryption::GraphKey;
+    /// use thoughtgraph::ThoughtGraph;
+    ///
+    /// let key = GraphKey::new("correct horse battery staple").unwrap();
+    /// let data = ThoughtGraph::new().to_encrypted_bytes(&key).unwrap();
+    ///
+    /// let key = GraphKey::for_data(&data, "correct horse battery staple").unwrap();
+    /// assert!(ThoughtGraph::from_encrypted_bytes(&data, &key).unwrap().thoughts.is_empty());
+    /// ```
+    pub fn new(passphrase: &str) -> Result<Self> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, salt)
+    }
+
+    /// Derive the key of an encrypted graph from its passphrase, with the salt stored in
+    /// the graph.
+    ///
+    /// # Returns
+    ///
+    /// The key, or `Encrypted` if `data` is not an encrypted graph. A wrong passphrase
+    /// only shows when decrypting.
+    pub fn for_data(data: &[u8], passphrase: &str) -> Result<Self> {
+        Self::derive(passphrase, salt_of(data)?)
+    }
+
+    /// Derive a key with Argon2id
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> Result<Self> {
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| ThoughtGraphError::DecryptionFailed(e.to_string()))?;
+        Ok(Self { salt, key })
+    }
+
+    /// Whether the key was derived with the salt of the encrypted graph `data`, so that
+    /// it may decrypt it. A graph saved under a new passphrase has a new salt.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        salt_of(data).is_ok_and(|salt| salt == self.salt)
+    }
+
+    /// Encode the key and its salt as text, e.g. to keep it in a keyring
+    pub fn to_secret(&self) -> String {
+        self.salt.iter().chain(self.key.iter()).map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Decode a key encoded by `to_secret`
+    pub fn from_secret(secret: &str) -> Result<Self> {
+        let invalid = || ThoughtGraphError::DecryptionFailed("the stored key is not valid".to_string());
+        if secret.len() != 2 * (SALT_LEN + 32) || !secret.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = (0..secret.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&secret[i..i + 2], 16).map_err(|_| invalid()))
+            .collect::<Result<Vec<u8>>>()?;
+        let (salt, key) = bytes.split_at(SALT_LEN);
+        Ok(Self { salt: salt.try_into().map_err(|_| invalid())?, key: *Key::from_slice(key) })
+    }
+
+    /// Encrypt `plaintext` with a new random nonce
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(HEADER_LEN + NONCE_LEN + plaintext.len() + 16);
+        data.extend_from_slice(ENCRYPTED_MAGIC);
+        data.extend_from_slice(&ENCRYPTED_VERSION.to_le_bytes());
+        data.extend_from_slice(&self.salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, Payload { msg: plaintext, aad: &data })
+            .map_err(|_| ThoughtGraphError::EncodingError("encryption failed".to_string()))?;
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    /// Decrypt data encrypted by `encrypt`.
+    ///
+    /// # Returns
+    ///
+    /// The plaintext, or `DecryptionFailed` if the key is wrong or the data was changed
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        salt_of(data)?;
+        if data.len() < HEADER_LEN + NONCE_LEN {
+            return Err(ThoughtGraphError::DecryptionFailed("the file is truncated".to_string()));
+        }
+        let (header, rest) = data.split_at(HEADER_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        ChaCha20Poly1305::new(&self.key)
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| ThoughtGraphError::DecryptionFailed("wrong passphrase, or the file is damaged".to_string()))
+    }
+}
+
+/// The salt in the header of an encrypted graph
+fn salt_of(data: &[u8]) -> Result<[u8; SALT_LEN]> {
+    if !is_encrypted(data) || data.len() < HEADER_LEN {
+        return Err(ThoughtGraphError::Encrypted("the data is not an encrypted graph".to_string()));
+    }
+    let version = u16::from_le_bytes([data[ENCRYPTED_MAGIC.len()], data[ENCRYPTED_MAGIC.len() + 1]]);
+    if version != ENCRYPTED_VERSION {
+        return Err(ThoughtGraphError::EncodingError(format!(
+            "unsupported encrypted format version {} (newest supported is {})",
+            version, ENCRYPTED_VERSION
+        )));
+    }
+    let mut salt = [0; SALT_LEN];
+    salt.copy_from_slice(&data[HEADER_LEN - SALT_LEN..HEADER_LEN]);
+    Ok(salt)
+}
+
+impl ThoughtGraph {
+    /// Encode the graph like `to_bytes` and encrypt it with `key`
+    pub fn to_encrypted_bytes(&self, key: &GraphKey) -> Result<Vec<u8>> {
+        key.encrypt(&self.to_bytes()?)
+    }
+
+    /// Decrypt a graph encrypted by `to_encrypted_bytes` and decode it.
+    ///
+    /// # Returns
+    ///
+    /// The graph, or `DecryptionFailed` if the key is wrong or the data was changed
+    pub fn from_encrypted_bytes(data: &[u8], key: &GraphKey) -> Result<Self> {
+        Self::from_bytes(&key.decrypt(data)?)
+    }
+
+    /// Save the graph encrypted with `key`, replacing the file like `save_to_file` and
+    /// reporting the progress of writing it in bytes
+    pub fn save_encrypted_with_progress<P: AsRef<Path>>(&self, path: P, key: &GraphKey, progress: ProgressFn) -> Result<()> {
+        storage::write_atomically(path.as_ref(), &self.to_encrypted_bytes(key)?, progress)
+    }
+
+    /// Load a graph saved by `save_encrypted_with_progress`
+    pub fn load_encrypted<P: AsRef<Path>>(path: P, key: &GraphKey) -> Result<Self> {
+        Self::from_encrypted_bytes(&std::fs::read(path)?, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThoughtID;
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let mut graph = ThoughtGraph::new();
+        graph.create_thought(ThoughtID::new("secret".to_string()), None, "Hidden".to_string(), vec![], vec![]).unwrap();
+        let key = GraphKey::new("passphrase").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("thoughts.bin");
+        graph.save_encrypted_with_progress(&path, &key, &mut |_| {}).unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        assert!(file_is_encrypted(&path).unwrap());
+        assert!(!data.windows(6).any(|window| window == b"Hidden"));
+        assert!(matches!(ThoughtGraph::from_bytes(&data), Err(ThoughtGraphError::Encrypted(_))));
+
+        let stored = GraphKey::from_secret(&key.to_secret()).unwrap();
+        assert!(stored.matches(&data));
+        assert_eq!(ThoughtGraph::load_encrypted(&path, &stored).unwrap().thoughts, graph.thoughts);
+
+        let wrong = GraphKey::for_data(&data, "wrong").unwrap();
+        assert!(matches!(ThoughtGraph::from_encrypted_bytes(&data, &wrong), Err(ThoughtGraphError::DecryptionFailed(_))));
+        let mut damaged = data.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(matches!(ThoughtGraph::from_encrypted_bytes(&damaged, &key), Err(ThoughtGraphError::DecryptionFailed(_))));
+        assert!(!GraphKey::new("passphrase").unwrap().matches(&data));
+    }
+}
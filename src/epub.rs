@@ -0,0 +1,233 @@
+//! EPUB export of thought collections
+//!
+//! With the `epub` feature, a set of thoughts can be compiled into an EPUB book for
+//! reading on an e-reader. Each thought becomes a chapter with its published contents
+//! (see [`ThoughtGraph::published_contents`]), followed by its links and backlinks.
+//!
+//! Links between thoughts in the book are kept as links between chapters: the lists of
+//! links and backlinks point to the chapters of the thoughts they name, and so do
+//! Markdown links whose target is the ID of a thought in the book, such as
+//! `[the previous chapter](notes/intro)`. Links to thoughts outside the book are shown as
+//! plain text.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Cursor, Write};
+
+use pulldown_cmark::{CowStr, Event, Parser, Tag, TagEnd};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::footnotes::{markdown_options, push_html_events};
+use crate::publish::escape_html;
+use crate::{Result, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Style sheet shared by all chapters
+const STYLESHEET: &str = "body { font-family: serif; line-height: 1.4; }\n\
+    .details { color: #666; font-size: 0.85em; }\n\
+    .footnotes { font-size: 0.85em; border-top: 1px solid #ccc; }\n";
+
+/// The file name of a chapter
+fn chapter_file(number: usize) -> String {
+    format!("chapter-{}.xhtml", number)
+}
+
+/// Wrap the body of a page in an XHTML document
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+         <head>\n<meta charset=\"utf-8\" />\n<title>{}</title>\n\
+         <link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\" />\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+impl ThoughtGraph {
+    /// Compile thoughts into an EPUB book, one chapter per thought.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the book
+    /// * `ids` - The thoughts to include, in reading order
+    ///
+    /// # Returns
+    ///
+    /// The EPUB file, or the errors of `to_markdown` for any of the thoughts
+    pub fn to_epub(&self, title: &str, ids: &[ThoughtID]) -> Result<Vec<u8>> {
+        let chapters: HashMap<&ThoughtID, usize> = ids.iter().enumerate().map(|(i, id)| (id, i + 1)).collect();
+        let chapter_title = |id: &ThoughtID| {
+            self.get_thought(id).and_then(|t| t.title.clone()).unwrap_or_else(|| id.id.to_string())
+        };
+        let link = |id: &ThoughtID| match chapters.get(id) {
+            Some(number) => format!("<a href=\"{}\">{}</a>", chapter_file(*number), escape_html(&chapter_title(id))),
+            None => escape_html(&chapter_title(id)),
+        };
+
+        let mut pages = Vec::new();
+        let mut modified = None;
+        for (i, id) in ids.iter().enumerate() {
+            let thought = self.get_thought(id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+            modified = modified.max(Some(thought.updated_at));
+
+            let mut body = format!("<h1>{}</h1>\n<p class=\"details\">{}", escape_html(&chapter_title(id)), escape_html(id.as_str()));
+            for tag in &thought.tags {
+                body.push_str(&format!(" #{}", escape_html(tag.as_str())));
+            }
+            body.push_str("</p>\n");
+
+            let contents = self.published_contents(id)?;
+            let mut unlinked = false;
+            let events = Parser::new_ext(&contents, markdown_options()).filter_map(|event| match event {
+                Event::Start(Tag::Link { link_type, dest_url, title, id: link_id }) => {
+                    let target = ThoughtID::new(dest_url.to_string());
+                    match chapters.get(&target) {
+                        Some(number) => {
+                            let dest_url = CowStr::from(chapter_file(*number));
+                            Some(Event::Start(Tag::Link { link_type, dest_url, title, id: link_id }))
+                        },
+                        None if self.get_thought(&target).is_some() => {
+                            unlinked = true;
+                            None
+                        },
+                        None => Some(Event::Start(Tag::Link { link_type, dest_url, title, id: link_id })),
+                    }
+                },
+                Event::End(TagEnd::Link) if unlinked => {
+                    unlinked = false;
+                    None
+                },
+                event => Some(event),
+            });
+            push_html_events(&mut body, events);
+
+            let links: Vec<ThoughtID> = thought.references.iter().map(|reference| reference.id.clone()).collect();
+            for (heading, ids) in [("Links", links), ("Backlinks", self.get_backlinks(id))] {
+                if ids.is_empty() {
+                    continue;
+                }
+                body.push_str(&format!("<h2>{}</h2>\n<ul>\n", heading));
+                for linked in ids {
+                    body.push_str(&format!("<li>{}</li>\n", link(&linked)));
+                }
+                body.push_str("</ul>\n");
+            }
+            pages.push((chapter_file(i + 1), xhtml(&chapter_title(id), &body)));
+        }
+
+        let mut hasher = DefaultHasher::new();
+        title.hash(&mut hasher);
+        ids.hash(&mut hasher);
+        let identifier = format!("urn:thoughtgraph:{:016x}", hasher.finish());
+        let modified = modified.unwrap_or_default().format("%Y-%m-%dT%H:%M:%SZ");
+
+        let mut manifest = String::from(
+            "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\" />\n\
+             <item id=\"style\" href=\"style.css\" media-type=\"text/css\" />\n",
+        );
+        let mut spine = String::new();
+        let mut contents = String::new();
+        for (i, id) in ids.iter().enumerate() {
+            let number = i + 1;
+            manifest.push_str(&format!(
+                "<item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\" />\n",
+                number,
+                chapter_file(number)
+            ));
+            spine.push_str(&format!("<itemref idref=\"chapter-{}\" />\n", number));
+            contents.push_str(&format!("<li>{}</li>\n", link(id)));
+        }
+        let package = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\">\n\
+             <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+             <dc:identifier id=\"id\">{}</dc:identifier>\n<dc:title>{}</dc:title>\n<dc:language>en</dc:language>\n\
+             <meta property=\"dcterms:modified\">{}</meta>\n</metadata>\n\
+             <manifest>\n{}</manifest>\n<spine>\n{}</spine>\n</package>\n",
+            identifier,
+            escape_html(title),
+            modified,
+            manifest,
+            spine
+        );
+        let nav = xhtml(
+            title,
+            &format!("<nav epub:type=\"toc\">\n<h1>{}</h1>\n<ol>\n{}</ol>\n</nav>\n", escape_html(title), contents),
+        );
+
+        let mut files = vec![
+            ("META-INF/container.xml".to_string(), String::from(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                 <container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+                 <rootfiles>\n<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\" />\n\
+                 </rootfiles>\n</container>\n",
+            )),
+            ("OEBPS/content.opf".to_string(), package),
+            ("OEBPS/nav.xhtml".to_string(), nav),
+            ("OEBPS/style.css".to_string(), STYLESHEET.to_string()),
+        ];
+        files.extend(pages.into_iter().map(|(name, page)| (format!("OEBPS/{}", name), page)));
+        write_epub(&files).map_err(|e| ThoughtGraphError::IoError(io::Error::other(e)))
+    }
+}
+
+/// Package files into an EPUB container
+fn write_epub(files: &[(String, String)]) -> zip::result::ZipResult<Vec<u8>> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // Readers identify EPUB files by this entry, which must come first
+    zip.start_file("mimetype", options)?;
+    zip.write_all(b"application/epub+zip")?;
+    for (name, contents) in files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+    use chrono::Utc;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_to_epub() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        graph.create_thought(id("intro"), Some("Intro & Overview".to_string()), "Start here.".to_string(), vec![], vec![]).unwrap();
+        graph
+            .create_thought(
+                id("body"),
+                None,
+                "As the [intro](intro) says, and [elsewhere](outside).".to_string(),
+                vec![],
+                vec![Reference::new(id("intro"), String::new(), Utc::now()), Reference::new(id("outside"), String::new(), Utc::now())],
+            )
+            .unwrap();
+        graph.create_thought(id("outside"), Some("Outside".to_string()), String::new(), vec![], vec![]).unwrap();
+
+        let epub = graph.to_epub("Book", &[id("intro"), id("body")]).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(epub)).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut text).unwrap();
+            text
+        };
+
+        assert!(read("OEBPS/content.opf").contains("<itemref idref=\"chapter-2\" />"));
+        assert!(read("OEBPS/nav.xhtml").contains("<li><a href=\"chapter-1.xhtml\">Intro &amp; Overview</a></li>"));
+        let chapter = read("OEBPS/chapter-2.xhtml");
+        assert!(chapter.contains("<a href=\"chapter-1.xhtml\">intro</a>"));
+        assert!(chapter.contains("and elsewhere."));
+        assert!(chapter.contains("<li><a href=\"chapter-1.xhtml\">Intro &amp; Overview</a></li>\n<li>Outside</li>"));
+        assert!(read("OEBPS/chapter-1.xhtml").contains("<h2>Backlinks</h2>\n<ul>\n<li><a href=\"chapter-2.xhtml\">body</a></li>"));
+
+        assert!(matches!(graph.to_epub("Book", &[id("missing")]), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+}
@@ -0,0 +1,328 @@
+//! Query plans
+//!
+//! A compound query on a large graph can be slow, or come back empty, for reasons that
+//! are hard to see from its text. `ThoughtGraph::explain` evaluates a query the way
+//! `query` does and reports, for each part of it, how its results were found (by
+//! scanning every thought or by looking them up in an index), how many thoughts were
+//! considered, how many matched, and in which order the parts were evaluated. When a
+//! query finds nothing, `QueryPlan::why_empty` points at the parts to blame.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::{Query, ThoughtGraph, ThoughtID};
+
+/// How the results of a query, or a part of one, are found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Every thought is checked
+    Scan,
+    /// The results are looked up in the named index
+    Index(&'static str),
+    /// The results of the subqueries are intersected
+    Intersection,
+    /// The results of the subqueries are united
+    Union,
+    /// The thoughts that are not results of the subquery are taken
+    Complement,
+    /// The results of a smart tag's query, the subquery, are taken
+    SmartTag,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Scan => write!(f, "scan"),
+            Access::Index(index) => write!(f, "{} index", index),
+            Access::Intersection => write!(f, "intersection"),
+            Access::Union => write!(f, "union"),
+            Access::Complement => write!(f, "complement"),
+            Access::SmartTag => write!(f, "smart tag"),
+        }
+    }
+}
+
+/// How a query was evaluated, from `ThoughtGraph::explain`
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryPlan {
+    /// The query, or part of a query, this step evaluated
+    pub query: Query,
+    /// When this step was evaluated, counting from 1; the subqueries of a compound query
+    /// come before it
+    pub step: usize,
+    /// How the results were found
+    pub access: Access,
+    /// Number of thoughts considered: all thoughts for a scan or a complement, the entries
+    /// read for an index, and the results of the subqueries for a compound query
+    pub candidates: usize,
+    /// Number of thoughts that matched
+    pub results: usize,
+    /// Whether `query` would have answered from the query cache instead (only ever true
+    /// for the whole query, since parts of queries are not cached)
+    pub cached: bool,
+    /// The plans of the subqueries of a compound query, in order of evaluation
+    pub subplans: Vec<QueryPlan>,
+}
+
+impl QueryPlan {
+    /// The steps that made this step find nothing: the parts of an `AND` that found
+    /// nothing themselves, or the `AND` if they all found something but had nothing in
+    /// common; every alternative of an `OR`; and any other step that found nothing
+    /// itself. Each blamed step is followed down to the parts to blame within it.
+    ///
+    /// # Returns
+    ///
+    /// The steps in order of evaluation, or none if this step found something
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Query, ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, String::new(), vec![], vec![]).unwrap();
+    ///
+    /// let plan = graph.explain(&Query::parse("is:all AND (tag:missing OR references:a)").unwrap());
+    /// let blamed: Vec<String> = plan.why_empty().iter().map(|step| step.query.to_string()).collect();
+    /// assert_eq!(blamed, vec!["tag:missing", "references:a"]);
+    /// ```
+    pub fn why_empty(&self) -> Vec<&QueryPlan> {
+        if self.results > 0 {
+            return Vec::new();
+        }
+        let empty_parts: Vec<&QueryPlan> = self.subplans.iter().filter(|plan| plan.results == 0).collect();
+        match self.access {
+            Access::Intersection | Access::Union if !empty_parts.is_empty() => {
+                empty_parts.into_iter().flat_map(QueryPlan::why_empty).collect()
+            },
+            Access::SmartTag => self.subplans.iter().flat_map(QueryPlan::why_empty).collect(),
+            _ => vec![self],
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let label = match &self.query {
+            Query::And(_) => "AND".to_string(),
+            Query::Or(_) => "OR".to_string(),
+            Query::Not(_) => "NOT".to_string(),
+            query => query.to_string(),
+        };
+        let count = |n: usize, noun: &str| format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" });
+        write!(
+            f,
+            "{:indent$}{}. {}: {}, {}, {}",
+            "",
+            self.step,
+            label,
+            self.access,
+            count(self.candidates, "candidate"),
+            count(self.results, "result"),
+            indent = depth * 2
+        )?;
+        if self.cached {
+            write!(f, " (cached)")?;
+        }
+        writeln!(f)?;
+        for subplan in &self.subplans {
+            subplan.write(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// The plan as an indented tree, one step per line
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+impl ThoughtGraph {
+    /// Evaluate a query and report how each part of it was evaluated.
+    ///
+    /// The query cache is neither used nor updated, so the plan always shows the work
+    /// that evaluating the query takes.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to explain
+    ///
+    /// # Returns
+    ///
+    /// The plan of the whole query, whose `results` is the number of thoughts `query`
+    /// returns
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Query, TagID, ThoughtGraph, ThoughtID};
+    /// use thoughtgraph::explain::Access;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let rust = TagID::new("rust".to_string());
+    /// graph.create_tag(rust.clone(), String::new()).unwrap();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, String::new(), vec![rust], vec![]).unwrap();
+    /// graph.create_thought(ThoughtID::new("b".to_string()), None, String::new(), vec![], vec![]).unwrap();
+    ///
+    /// let plan = graph.explain(&Query::parse("tag:rust AND references:b").unwrap());
+    /// assert_eq!((plan.access, plan.step, plan.results), (Access::Intersection, 3, 0));
+    /// assert_eq!((plan.subplans[0].access, plan.subplans[0].candidates), (Access::Index("tag"), 1));
+    /// ```
+    pub fn explain(&self, query: &Query) -> QueryPlan {
+        let mut step = 0;
+        let (mut plan, _) = self.plan(query, &mut step);
+        plan.cached = self.query_cache.contains(&query.normalized());
+        plan
+    }
+
+    /// Evaluate a query as `evaluate_query` does, numbering the steps from `step`
+    fn plan(&self, query: &Query, step: &mut usize) -> (QueryPlan, HashSet<ThoughtID>) {
+        let mut subplans = Vec::new();
+        let (access, candidates, result) = match query {
+            Query::All => (Access::Scan, self.thoughts.len(), self.evaluate_query(query)),
+            Query::Id(id) => {
+                let result = self.evaluate_query(query);
+                (Access::Index("thought"), usize::from(self.thoughts.contains_key(id)), result)
+            },
+            Query::Tag(tag) if self.smart_tag_query(tag).is_some() => {
+                let expanded = self.smart_tag_query(tag).unwrap_or(Query::Or(Vec::new()));
+                let (subplan, result) = self.plan(&expanded, step);
+                subplans.push(subplan);
+                (Access::SmartTag, result.len(), result)
+            },
+            Query::Tag(tag) => {
+                let candidates = match self.tags.contains_key(tag) {
+                    true => self.tag_index.get(tag).map_or(0, HashSet::len),
+                    false => 0,
+                };
+                (Access::Index("tag"), candidates, self.evaluate_query(query))
+            },
+            Query::TitleContains(_)
+            | Query::ContentContains(_)
+            | Query::TitleRegex(_)
+            | Query::ContentRegex(_)
+            | Query::ReferenceNotesContain(_)
+            | Query::IdGlob(_)
+            | Query::Untagged
+            | Query::Orphan => {
+                (Access::Scan, self.thoughts.len(), self.evaluate_query(query))
+            },
+            Query::References(_) => {
+                let result = self.evaluate_query(query);
+                (Access::Index("backreference"), result.len(), result)
+            },
+            Query::MinBacklinks(_) | Query::MaxBacklinks(_) => {
+                (Access::Index("backreference"), self.thoughts.len(), self.evaluate_query(query))
+            },
+            Query::TagGlob(_) => {
+                let result = self.evaluate_query(query);
+                (Access::Index("tag"), self.tags.len() + result.len(), result)
+            },
+            Query::ReachableFrom { .. } | Query::ReachableTo { .. } => {
+                let result = self.evaluate_query(query);
+                let index = match query {
+                    Query::ReachableFrom { .. } => "thought",
+                    _ => "backreference",
+                };
+                (Access::Index(index), result.len(), result)
+            },
+            Query::ReferencedBy(source) => {
+                let candidates = self.thoughts.get(source).map_or(0, |thought| thought.references.len());
+                (Access::Index("thought"), candidates, self.evaluate_query(query))
+            },
+            Query::And(subqueries) | Query::Or(subqueries) => {
+                let mut results = Vec::new();
+                for subquery in subqueries {
+                    let (subplan, result) = self.plan(subquery, step);
+                    subplans.push(subplan);
+                    results.push(result);
+                }
+                let candidates = results.iter().map(HashSet::len).sum();
+                match query {
+                    Query::And(_) => {
+                        let result = results
+                            .into_iter()
+                            .reduce(|accum, item| accum.intersection(&item).cloned().collect())
+                            .unwrap_or_default();
+                        (Access::Intersection, candidates, result)
+                    },
+                    _ => (Access::Union, candidates, results.into_iter().flatten().collect()),
+                }
+            },
+            Query::Not(subquery) => {
+                let (subplan, excluded) = self.plan(subquery, step);
+                subplans.push(subplan);
+                let result = self.thoughts.keys().filter(|id| !excluded.contains(*id)).cloned().collect();
+                (Access::Complement, self.thoughts.len(), result)
+            },
+        };
+        *step += 1;
+        let plan = QueryPlan {
+            query: query.clone(),
+            step: *step,
+            access,
+            candidates,
+            results: result.len(),
+            cached: false,
+            subplans,
+        };
+        (plan, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Reference, TagID};
+    use chrono::Utc;
+
+    #[test]
+    fn test_explain() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let rust = TagID::new("rust".to_string());
+        graph.create_tag(rust.clone(), String::new()).unwrap();
+        graph.create_thought(id("cargo"), None, String::new(), vec![rust.clone()], vec![]).unwrap();
+        for name in ["a", "b", "c"] {
+            let reference = Reference::new(id("cargo"), String::new(), Utc::now());
+            graph.create_thought(id(name), None, String::new(), vec![rust.clone()], vec![reference]).unwrap();
+        }
+        graph.create_thought(id("d"), None, String::new(), vec![], vec![]).unwrap();
+
+        let query = Query::parse("tag:rust AND (references:cargo OR referenced-by:a OR tag:missing)").unwrap();
+        let plan = graph.explain(&query);
+        assert_eq!(plan.results, graph.query(&query).len());
+        assert_eq!((plan.step, plan.candidates, plan.results), (6, 8, 4));
+        let or = &plan.subplans[1];
+        assert_eq!((or.step, or.access, or.candidates, or.results), (5, Access::Union, 4, 4));
+        let steps: Vec<(usize, Access, usize, usize)> =
+            or.subplans.iter().map(|plan| (plan.step, plan.access, plan.candidates, plan.results)).collect();
+        assert_eq!(steps, vec![
+            (2, Access::Index("backreference"), 3, 3),
+            (3, Access::Index("thought"), 1, 1),
+            (4, Access::Index("tag"), 0, 0),
+        ]);
+        assert!(!plan.cached);
+
+        assert_eq!(
+            plan.to_string().lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "6. AND: intersection, 8 candidates, 4 results",
+                "  1. tag:rust: tag index, 4 candidates, 4 results",
+                "  5. OR: union, 4 candidates, 4 results",
+            ]
+        );
+
+        graph.enable_query_cache(8);
+        graph.query(&query);
+        assert!(graph.explain(&query).cached);
+        assert!(plan.why_empty().is_empty());
+
+        // Parts that find something but have nothing in common are blamed together
+        let plan = graph.explain(&Query::parse("tag:rust AND id:d AND NOT (tag:missing OR is:all)").unwrap());
+        let blamed: Vec<(usize, String)> = plan.why_empty().iter().map(|step| (step.step, step.query.to_string())).collect();
+        assert_eq!(blamed, vec![(6, "NOT (tag:missing OR is:all)".to_string())]);
+        let plan = graph.explain(&Query::parse("tag:rust AND id:d").unwrap());
+        assert_eq!(plan.why_empty()[0].access, Access::Intersection);
+    }
+}
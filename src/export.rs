@@ -0,0 +1,183 @@
+//! Pluggable export formats for graph visualizations
+//!
+//! Each output format of `thoughts visualize` is a `GraphExporter`. The CLI looks formats
+//! up by name in an `ExporterRegistry`, which starts out with the built-in formats (DOT,
+//! JSON and JSON Canvas). Other formats, including ones defined outside this crate, are
+//! added by implementing the trait and registering the implementation.
+
+use crate::canvas::to_json_canvas;
+use crate::visualization::GraphData;
+use crate::ThoughtGraph;
+
+/// A format that graph visualization data can be exported to
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::ThoughtGraph;
+/// use thoughtgraph::export::{ExporterRegistry, GraphExporter};
+/// use thoughtgraph::visualization::{generate_graph_data, GraphData};
+///
+/// struct Mermaid;
+///
+/// impl GraphExporter for Mermaid {
+///     fn name(&self) -> &str { "mermaid" }
+///     fn extension(&self) -> &str { "mmd" }
+///     fn export(&self, _graph: &ThoughtGraph, data: &GraphData) -> String {
+///         let edges: Vec<String> = data.edges.iter().map(|e| format!("  {} --> {}", e.source, e.target)).collect();
+///         format!("graph LR\n{}\n", edges.join("\n"))
+///     }
+/// }
+///
+/// let mut registry = ExporterRegistry::default();
+/// registry.register(Box::new(Mermaid));
+/// assert_eq!(registry.names(), vec!["dot", "json", "canvas", "mermaid"]);
+///
+/// let graph = ThoughtGraph::new();
+/// let output = registry.get("Mermaid").unwrap().export(&graph, &generate_graph_data(&graph));
+/// assert!(output.starts_with("graph LR"));
+/// ```
+pub trait GraphExporter: Send + Sync {
+    /// Short, lowercase name used to select the format, e.g. "dot"
+    fn name(&self) -> &str;
+
+    /// File extension for exported files, without the dot
+    fn extension(&self) -> &str;
+
+    /// Render the visualization data.
+    ///
+    /// `graph` is the graph the data was generated from, for formats that include more
+    /// than the data itself (such as the contents of thoughts).
+    fn export(&self, graph: &ThoughtGraph, data: &GraphData) -> String;
+}
+
+/// Graphviz DOT
+pub struct DotExporter;
+
+impl GraphExporter for DotExporter {
+    fn name(&self) -> &str {
+        "dot"
+    }
+
+    fn extension(&self) -> &str {
+        "dot"
+    }
+
+    fn export(&self, _graph: &ThoughtGraph, data: &GraphData) -> String {
+        data.to_dot()
+    }
+}
+
+/// JSON with lists of nodes and edges, suitable for D3.js and similar libraries
+pub struct JsonExporter;
+
+impl GraphExporter for JsonExporter {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn extension(&self) -> &str {
+        "json"
+    }
+
+    fn export(&self, _graph: &ThoughtGraph, data: &GraphData) -> String {
+        data.to_json()
+    }
+}
+
+/// JSON Canvas, as used by Obsidian
+pub struct CanvasExporter;
+
+impl GraphExporter for CanvasExporter {
+    fn name(&self) -> &str {
+        "canvas"
+    }
+
+    fn extension(&self) -> &str {
+        "canvas"
+    }
+
+    fn export(&self, graph: &ThoughtGraph, data: &GraphData) -> String {
+        to_json_canvas(graph, data)
+    }
+}
+
+/// The available export formats, looked up by name
+pub struct ExporterRegistry {
+    exporters: Vec<Box<dyn GraphExporter>>,
+}
+
+impl ExporterRegistry {
+    /// A registry without any formats
+    pub fn empty() -> Self {
+        Self { exporters: Vec::new() }
+    }
+
+    /// Add a format, replacing any registered format with the same name
+    pub fn register(&mut self, exporter: Box<dyn GraphExporter>) {
+        self.exporters.retain(|existing| existing.name() != exporter.name());
+        self.exporters.push(exporter);
+    }
+
+    /// The format with the given name, ignoring case
+    pub fn get(&self, name: &str) -> Option<&dyn GraphExporter> {
+        self.exporters
+            .iter()
+            .find(|exporter| exporter.name().eq_ignore_ascii_case(name))
+            .map(|exporter| exporter.as_ref())
+    }
+
+    /// The names of all registered formats, in the order they were registered
+    pub fn names(&self) -> Vec<&str> {
+        self.exporters.iter().map(|exporter| exporter.name()).collect()
+    }
+}
+
+/// A registry with the built-in formats
+impl Default for ExporterRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Box::new(DotExporter));
+        registry.register(Box::new(JsonExporter));
+        registry.register(Box::new(CanvasExporter));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visualization::generate_graph_data;
+
+    #[test]
+    fn test_builtin_formats() {
+        let registry = ExporterRegistry::default();
+        let graph = ThoughtGraph::new();
+        let data = generate_graph_data(&graph);
+
+        assert_eq!(registry.get("DOT").unwrap().export(&graph, &data), data.to_dot());
+        assert_eq!(registry.get("json").unwrap().extension(), "json");
+        assert!(registry.get("gexf").is_none());
+    }
+
+    #[test]
+    fn test_register_replaces_by_name() {
+        struct Plain;
+        impl GraphExporter for Plain {
+            fn name(&self) -> &str {
+                "dot"
+            }
+            fn extension(&self) -> &str {
+                "txt"
+            }
+            fn export(&self, _graph: &ThoughtGraph, data: &GraphData) -> String {
+                format!("{} nodes", data.nodes.len())
+            }
+        }
+
+        let mut registry = ExporterRegistry::default();
+        registry.register(Box::new(Plain));
+        assert_eq!(registry.names(), vec!["json", "canvas", "dot"]);
+        assert_eq!(registry.get("dot").unwrap().extension(), "txt");
+    }
+}
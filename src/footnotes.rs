@@ -0,0 +1,323 @@
+//! Markdown footnotes in thought contents
+//!
+//! Thoughts can use the footnote syntax of GitHub-flavored Markdown: `[^label]` marks a
+//! footnote in the text, and a `[^label]: text` paragraph anywhere in the thought defines
+//! it. Footnotes are numbered in the order they are first referenced, followed by any
+//! that are defined but never referenced. Both the terminal view and HTML export move the
+//! definitions to the end and link each footnote back to where it is referenced.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+
+/// A footnote defined in some Markdown text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Footnote {
+    /// The number the footnote is shown with
+    pub number: usize,
+    /// The label the footnote is defined with
+    pub label: String,
+    /// The text of the definition
+    pub text: String,
+    /// The lines of the text, counting from 1, where the footnote is referenced
+    pub references: Vec<usize>,
+}
+
+/// The Markdown extensions thoughts are rendered with
+pub fn markdown_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_FOOTNOTES
+}
+
+/// Labels match regardless of case, as in GitHub-flavored Markdown
+fn normalize_label(label: &str) -> String {
+    label.to_lowercase()
+}
+
+/// Assigns footnote numbers in the order labels are first referenced
+#[derive(Default)]
+struct Numbering {
+    numbers: HashMap<String, usize>,
+}
+
+impl Numbering {
+    fn number(&mut self, label: &str) -> usize {
+        let next = self.numbers.len() + 1;
+        *self.numbers.entry(normalize_label(label)).or_insert(next)
+    }
+}
+
+/// The text of a footnote definition, without its label and indentation
+fn definition_text(source: &str) -> String {
+    let text = source.split_once("]:").map_or(source, |(_, text)| text);
+    text.lines()
+        .map(|line| line.strip_prefix("    ").unwrap_or(line.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Append to text, collapsing the runs of blank lines that removing definitions leaves
+fn push_collapsed(text: &mut String, segment: &str) {
+    text.push_str(segment);
+    while text.contains("\n\n\n") {
+        *text = text.replace("\n\n\n", "\n\n");
+    }
+}
+
+/// Separate the footnotes from some Markdown text, for display as plain text.
+///
+/// # Arguments
+///
+/// * `contents` - The Markdown text
+///
+/// # Returns
+///
+/// The text with each footnote reference replaced by its number in brackets and the
+/// definitions removed, and the footnotes in order of their numbers
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::footnotes::extract_footnotes;
+///
+/// let (text, footnotes) = extract_footnotes("Tea is old.[^tea]\n\n[^tea]: Very old.\n");
+/// assert_eq!(text, "Tea is old.[1]");
+/// assert_eq!(footnotes[0].text, "Very old.");
+/// assert_eq!(footnotes[0].references, vec![1]);
+/// ```
+pub fn extract_footnotes(contents: &str) -> (String, Vec<Footnote>) {
+    let mut definitions: Vec<(String, Range<usize>)> = Vec::new();
+    let mut references: Vec<(String, Range<usize>)> = Vec::new();
+    for (event, range) in Parser::new_ext(contents, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => definitions.push((label.to_string(), range)),
+            Event::FootnoteReference(label) => references.push((label.to_string(), range)),
+            _ => {},
+        }
+    }
+    // References inside definitions stay as they are written
+    references.retain(|(_, range)| !definitions.iter().any(|(_, def)| def.start <= range.start && range.end <= def.end));
+
+    let mut numbering = Numbering::default();
+    let mut edits: Vec<(Range<usize>, Option<usize>)> = references
+        .iter()
+        .map(|(label, range)| (range.clone(), Some(numbering.number(label))))
+        .collect();
+    edits.extend(definitions.iter().map(|(_, range)| (range.clone(), None)));
+    edits.sort_by_key(|(range, _)| range.start);
+
+    let mut footnotes: Vec<Footnote> = Vec::new();
+    for (label, range) in &definitions {
+        let number = numbering.number(label);
+        if footnotes.iter().all(|footnote| footnote.number != number) {
+            footnotes.push(Footnote {
+                number,
+                label: label.clone(),
+                text: definition_text(&contents[range.clone()]),
+                references: Vec::new(),
+            });
+        }
+    }
+
+    let mut text = String::new();
+    let mut position = 0;
+    for (range, number) in edits {
+        push_collapsed(&mut text, &contents[position..range.start]);
+        if let Some(number) = number {
+            let line = text.matches('\n').count() + 1;
+            if let Some(footnote) = footnotes.iter_mut().find(|footnote| footnote.number == number) {
+                footnote.references.push(line);
+            }
+            text.push_str(&format!("[{}]", number));
+        }
+        position = range.end;
+    }
+    push_collapsed(&mut text, &contents[position..]);
+    footnotes.sort_by_key(|footnote| footnote.number);
+    (text.trim_end().to_string(), footnotes)
+}
+
+/// Add a prefix to the label of every footnote in some Markdown text, so that it can be
+/// combined with other text without its footnotes being confused with theirs.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::footnotes::prefix_labels;
+///
+/// assert_eq!(prefix_labels("Tea.[^a]\n\n[^a]: Old.", "2-"), "Tea.[^2-a]\n\n[^2-a]: Old.");
+/// ```
+pub fn prefix_labels(contents: &str, prefix: &str) -> String {
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    for (event, range) in Parser::new_ext(contents, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                if let Some(end) = contents[range.start..].find("]:") {
+                    edits.push((range.start..range.start + end + 1, format!("[^{}{}]", prefix, label)));
+                }
+            },
+            Event::FootnoteReference(label) => edits.push((range, format!("[^{}{}]", prefix, label))),
+            _ => {},
+        }
+    }
+    edits.sort_by_key(|(range, _)| range.start);
+
+    let mut text = String::new();
+    let mut position = 0;
+    for (range, replacement) in edits {
+        text.push_str(&contents[position..range.start]);
+        text.push_str(&replacement);
+        position = range.end;
+    }
+    text.push_str(&contents[position..]);
+    text
+}
+
+/// Render Markdown as HTML, with the footnotes collected in a numbered list at the end.
+///
+/// Each footnote reference links to its footnote, and each footnote links back to every
+/// place it is referenced.
+///
+/// # Arguments
+///
+/// * `output` - The string to append the HTML to
+/// * `markdown` - The Markdown text
+pub fn push_html(output: &mut String, markdown: &str) {
+    push_html_events(output, Parser::new_ext(markdown, markdown_options()));
+}
+
+/// Render parsed Markdown as HTML like `push_html`, for callers that adjust the events
+/// first, such as to rewrite link targets
+pub fn push_html_events<'a>(output: &mut String, events: impl Iterator<Item = Event<'a>>) {
+    let mut numbering = Numbering::default();
+    let mut reference_counts: HashMap<usize, usize> = HashMap::new();
+    let mut definitions: Vec<(String, Vec<Event>)> = Vec::new();
+    let mut definition: Option<(String, Vec<Event>)> = None;
+    let mut body = Vec::new();
+
+    for event in events {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => definition = Some((normalize_label(&label), Vec::new())),
+            Event::End(TagEnd::FootnoteDefinition) => {
+                if let Some((label, events)) = definition.take() {
+                    if definitions.iter().all(|(l, _)| *l != label) {
+                        definitions.push((label, events));
+                    }
+                }
+            },
+            Event::FootnoteReference(label) => {
+                let number = numbering.number(&label);
+                let count = reference_counts.entry(number).or_insert(0);
+                *count += 1;
+                let id = reference_id(number, *count);
+                let html = format!(
+                    "<sup class=\"footnote-reference\" id=\"{}\"><a href=\"#fn-{}\">{}</a></sup>",
+                    id, number, number
+                );
+                match &mut definition {
+                    Some((_, events)) => events.push(Event::InlineHtml(CowStr::from(html))),
+                    None => body.push(Event::InlineHtml(CowStr::from(html))),
+                }
+            },
+            event => match &mut definition {
+                Some((_, events)) => events.push(event),
+                None => body.push(event),
+            },
+        }
+    }
+    html::push_html(output, body.into_iter());
+
+    if definitions.is_empty() {
+        return;
+    }
+    // Number definitions only now, so that references decide the order
+    let mut definitions: Vec<(usize, Vec<Event>)> =
+        definitions.into_iter().map(|(label, events)| (numbering.number(&label), events)).collect();
+    definitions.sort_by_key(|(number, _)| *number);
+    output.push_str("<section class=\"footnotes\">\n<ol>\n");
+    for (number, mut events) in definitions {
+        let backlinks: Vec<String> = (1..=reference_counts.get(&number).copied().unwrap_or(0))
+            .map(|count| {
+                let marker = if count == 1 { String::new() } else { format!("<sup>{}</sup>", count) };
+                format!(
+                    " <a href=\"#{}\" class=\"footnote-backref\">↩{}</a>",
+                    reference_id(number, count),
+                    marker
+                )
+            })
+            .collect();
+        let backlinks = Event::InlineHtml(CowStr::from(backlinks.concat()));
+        // Keep the links inside the last paragraph, as is usual for footnotes
+        if matches!(events.last(), Some(Event::End(TagEnd::Paragraph))) {
+            events.insert(events.len() - 1, backlinks);
+        } else {
+            events.push(backlinks);
+        }
+
+        output.push_str(&format!("<li id=\"fn-{}\">\n", number));
+        html::push_html(output, events.into_iter());
+        output.push_str("</li>\n");
+    }
+    output.push_str("</ol>\n</section>\n");
+}
+
+/// The HTML ID of the `count`th reference to footnote `number`
+fn reference_id(number: usize, count: usize) -> String {
+    if count == 1 {
+        format!("fnref-{}", number)
+    } else {
+        format!("fnref-{}-{}", number, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "[^unused]: Never referenced.\n\n\
+        First[^b] and second[^A].\n\n\
+        [^a]: The second footnote,\n    over two lines.\n\n\
+        Again[^B], but not `[^b]` in code.\n\n\
+        [^b]: The first footnote.\n";
+
+    #[test]
+    fn test_extract_footnotes() {
+        let (text, footnotes) = extract_footnotes(TEXT);
+        assert_eq!(text, "First[1] and second[2].\n\nAgain[1], but not `[^b]` in code.");
+
+        let summary: Vec<(usize, &str, &str, Vec<usize>)> = footnotes
+            .iter()
+            .map(|f| (f.number, f.label.as_str(), f.text.as_str(), f.references.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, "b", "The first footnote.", vec![1, 3]),
+                (2, "a", "The second footnote,\nover two lines.", vec![1]),
+                (3, "unused", "Never referenced.", vec![]),
+            ]
+        );
+
+        assert_eq!(extract_footnotes("No footnotes [^here]."), ("No footnotes [^here].".to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_push_html() {
+        let mut output = String::new();
+        push_html(&mut output, TEXT);
+
+        assert!(output.starts_with(
+            "<p>First<sup class=\"footnote-reference\" id=\"fnref-1\"><a href=\"#fn-1\">1</a></sup> and second"
+        ));
+        assert!(output.contains("<p>Again<sup class=\"footnote-reference\" id=\"fnref-1-2\"><a href=\"#fn-1\">1</a></sup>"));
+        let footnotes = &output[output.find("<section class=\"footnotes\">").unwrap()..];
+        assert!(footnotes.contains(
+            "<li id=\"fn-1\">\n<p>The first footnote. <a href=\"#fnref-1\" class=\"footnote-backref\">↩</a> \
+             <a href=\"#fnref-1-2\" class=\"footnote-backref\">↩<sup>2</sup></a></p>\n</li>"
+        ));
+        assert!(footnotes.contains("<li id=\"fn-3\">\n<p>Never referenced.</p>\n</li>"));
+        assert!(footnotes.find("fn-1").unwrap() < footnotes.find("fn-2").unwrap());
+    }
+}
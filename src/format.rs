@@ -0,0 +1,243 @@
+//! On-disk file format for thought graphs
+//!
+//! Graph files start with a short header (a magic number followed by a format version)
+//! and contain the graph encoded as MessagePack with named fields. Because fields are
+//! stored by name, new fields can be added to the graph over time and older files keep
+//! loading, with the missing fields taking their default values.
+//!
+//! Files written before the header was introduced are plain bincode encodings of the
+//! graph. They are recognized by the missing header and still load. Their backreferences
+//! were stored as lists, which may contain duplicates; these are converted to sets.
+//!
+//! Encrypted graph files (see `encryption`) start with `ENCRYPTED_MAGIC` instead and are
+//! only decoded once they have been decrypted.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{Reference, Result, Tag, TagID, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Magic number at the start of every graph file
+const MAGIC: &[u8; 4] = b"TGPH";
+
+/// Magic number at the start of encrypted graph files
+pub(crate) const ENCRYPTED_MAGIC: &[u8; 4] = b"TGPE";
+
+/// Version of the format written by `encode`
+pub(crate) const FORMAT_VERSION: u16 = 1;
+
+/// Length of the header: the magic number followed by a little-endian `u16` version
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+/// Layout of graph files written before the versioned header existed (version 0)
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct LegacyGraph {
+    thoughts: HashMap<ThoughtID, LegacyThought>,
+    backreferences: HashMap<ThoughtID, Vec<ThoughtID>>,
+    tags: HashMap<TagID, LegacyTag>,
+}
+
+/// Layout of a tag in version 0 files
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct LegacyTag {
+    description: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<LegacyTag> for Tag {
+    fn from(legacy: LegacyTag) -> Self {
+        Tag {
+            description: legacy.description,
+            parent: None,
+            color: None,
+            aliases: Vec::new(),
+            query: None,
+            created_at: legacy.created_at,
+            updated_at: legacy.updated_at,
+        }
+    }
+}
+
+/// Layout of a thought in version 0 files.
+///
+/// Bincode stores fields by position, so thoughts and references in these files never
+/// contain fields added later.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct LegacyThought {
+    title: Option<String>,
+    contents: String,
+    tags: Vec<TagID>,
+    references: Vec<LegacyReference>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+/// Layout of a reference in version 0 files
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct LegacyReference {
+    id: ThoughtID,
+    notes: String,
+    access_date: DateTime<Utc>,
+}
+
+impl From<LegacyThought> for Thought {
+    fn from(legacy: LegacyThought) -> Self {
+        Thought {
+            title: legacy.title,
+            contents: legacy.contents,
+            tags: legacy.tags,
+            references: legacy
+                .references
+                .into_iter()
+                .map(|r| Reference::new(r.id, r.notes, r.access_date))
+                .collect(),
+            metadata: Default::default(),
+            created_at: legacy.created_at,
+            updated_at: legacy.updated_at,
+        }
+    }
+}
+
+/// Encode a graph in the current file format
+pub(crate) fn encode(graph: &ThoughtGraph) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(HEADER_LEN);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    rmp_serde::encode::write_named(&mut data, graph)
+        .map_err(|e| ThoughtGraphError::EncodingError(e.to_string()))?;
+    Ok(data)
+}
+
+/// Decode a graph written in the current or any earlier file format.
+///
+/// Derived indexes are not part of the file; callers are expected to rebuild them.
+pub(crate) fn decode(data: &[u8]) -> Result<ThoughtGraph> {
+    if data.starts_with(ENCRYPTED_MAGIC) {
+        return Err(ThoughtGraphError::Encrypted("decrypt it with its passphrase to read it".to_string()));
+    }
+    if data.len() < HEADER_LEN || &data[..MAGIC.len()] != MAGIC {
+        return decode_legacy(data);
+    }
+
+    let version = u16::from_le_bytes([data[MAGIC.len()], data[MAGIC.len() + 1]]);
+    match version {
+        1 => rmp_serde::from_slice(&data[HEADER_LEN..])
+            .map_err(|e| ThoughtGraphError::EncodingError(e.to_string())),
+        _ => Err(ThoughtGraphError::EncodingError(format!(
+            "unsupported file format version {} (newest supported is {})",
+            version, FORMAT_VERSION
+        ))),
+    }
+}
+
+/// Decode a headerless bincode file (format version 0)
+fn decode_legacy(data: &[u8]) -> Result<ThoughtGraph> {
+    let legacy: LegacyGraph = bincode::deserialize(data)?;
+    Ok(ThoughtGraph {
+        thoughts: legacy.thoughts.into_iter().map(|(id, thought)| (id, thought.into())).collect(),
+        backreferences: legacy
+            .backreferences
+            .into_iter()
+            .map(|(id, backrefs)| (id, backrefs.into_iter().collect::<HashSet<_>>()))
+            .collect(),
+        tags: legacy.tags.into_iter().map(|(id, tag)| (id, tag.into())).collect(),
+        ..ThoughtGraph::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The version 0 layout of a thought, used to produce legacy files
+    fn legacy_thought(thought: &Thought) -> LegacyThought {
+        LegacyThought {
+            title: thought.title.clone(),
+            contents: thought.contents.clone(),
+            tags: thought.tags.clone(),
+            references: thought
+                .references
+                .iter()
+                .map(|r| LegacyReference { id: r.id.clone(), notes: r.notes.clone(), access_date: r.access_date })
+                .collect(),
+            created_at: thought.created_at,
+            updated_at: thought.updated_at,
+        }
+    }
+
+    fn sample_graph() -> ThoughtGraph {
+        let mut graph = ThoughtGraph::new();
+        let tag = TagID::new("tag".to_string());
+        graph.create_tag(tag.clone(), "A tag".to_string()).unwrap();
+        graph
+            .create_thought(ThoughtID::new("a".to_string()), None, "A".to_string(), vec![tag], vec![])
+            .unwrap();
+        graph
+            .create_thought(
+                ThoughtID::new("b".to_string()),
+                Some("B".to_string()),
+                "See https://example.com".to_string(),
+                vec![],
+                vec![Reference::new(ThoughtID::new("a".to_string()), "note".to_string(), Utc::now())],
+            )
+            .unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let graph = sample_graph();
+        let data = encode(&graph).unwrap();
+        assert_eq!(&data[..4], MAGIC);
+
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.thoughts.len(), 2);
+        assert_eq!(decoded.tags.len(), 1);
+        assert_eq!(
+            decoded.get_backlinks(&ThoughtID::new("a".to_string())),
+            vec![ThoughtID::new("b".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_decodes_legacy_bincode() {
+        let graph = sample_graph();
+        // Old versions could record the same backreference more than once
+        let a = ThoughtID::new("a".to_string());
+        let b = ThoughtID::new("b".to_string());
+        let backreferences = HashMap::from([(a.clone(), vec![b.clone(), b.clone()])]);
+        let legacy = bincode::serialize(&LegacyGraph {
+            thoughts: graph.thoughts.iter().map(|(id, thought)| (id.clone(), legacy_thought(thought))).collect(),
+            backreferences,
+            tags: graph
+                .tags
+                .iter()
+                .map(|(id, tag)| {
+                    let tag = LegacyTag { description: tag.description.clone(), created_at: tag.created_at, updated_at: tag.updated_at };
+                    (id.clone(), tag)
+                })
+                .collect(),
+        })
+        .unwrap();
+
+        let decoded = decode(&legacy).unwrap();
+        assert_eq!(decoded.thoughts.len(), 2);
+        assert_eq!(decoded.tags.len(), 1);
+        assert_eq!(decoded.get_backlinks(&a), vec![b]);
+    }
+
+    #[test]
+    fn test_rejects_newer_versions() {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+        assert!(matches!(decode(&data), Err(ThoughtGraphError::EncodingError(_))));
+    }
+}
@@ -0,0 +1,140 @@
+//! Fuzzy search
+//!
+//! `search` only finds thoughts that contain a term exactly; `fuzzy_search` also finds
+//! the ones that contain it misspelled, so that "browish" finds "brownish". How far a
+//! word is from a term is its edit distance: the number of characters that have to be
+//! inserted, deleted or replaced to turn one into the other. A term of several words is
+//! compared to runs of as many words. Text is compared as `search` compares it by
+//! default, ignoring case and accents, and text that contains the term exactly is at
+//! distance zero.
+
+use crate::parallel;
+use crate::search::SearchOptions;
+use crate::{ThoughtGraph, ThoughtID};
+
+/// How many characters a word may differ from a term by in `thoughts search --fuzzy`
+/// unless told otherwise
+pub const DEFAULT_MAX_DISTANCE: usize = 2;
+
+/// The words of normalized text: its runs of letters and digits
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect()
+}
+
+/// The edit distance between two strings, or `None` if it is more than `max_distance`
+fn edit_distance(a: &[char], b: &[char], max_distance: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max_distance {
+        return None;
+    }
+    // The distances between the first i characters of `a` and each prefix of `b`
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        // Distances never shrink from one row to the next
+        if current.iter().all(|&distance| distance > max_distance) {
+            return None;
+        }
+        previous = current;
+    }
+    Some(previous[b.len()]).filter(|&distance| distance <= max_distance)
+}
+
+/// The smallest distance between a normalized term and the words of a text, if it is
+/// at most `max_distance`
+fn distance_in(text: &str, term: &str, term_words: usize, max_distance: usize) -> Option<usize> {
+    let text = SearchOptions::default().normalize(text);
+    if text.contains(term) {
+        return Some(0);
+    }
+    let term: Vec<char> = term.chars().collect();
+    words(&text)
+        .windows(term_words)
+        .filter_map(|run| edit_distance(&run.join(" ").chars().collect::<Vec<_>>(), &term, max_distance))
+        .min()
+}
+
+impl ThoughtGraph {
+    /// Find the thoughts whose title or content contains a term, allowing for
+    /// misspellings.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - The text to look for
+    /// * `max_distance` - The largest number of characters that may be inserted, deleted
+    ///   or replaced in a word of a thought for it to match the term
+    ///
+    /// # Returns
+    ///
+    /// The matching thoughts with the distance of their closest match, closest first and
+    /// by ID among equal distances. The result is empty if the term has no words.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("fox".to_string()), None, "The quick brownish fox".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// assert!(graph.search(&["browish"]).is_empty());
+    /// let found = graph.fuzzy_search("browish", 1);
+    /// assert_eq!(found.len(), 1);
+    /// assert_eq!((found[0].0.as_str(), found[0].1), ("fox", 1));
+    /// assert!(graph.fuzzy_search("bluish", 1).is_empty());
+    /// ```
+    pub fn fuzzy_search(&self, term: &str, max_distance: usize) -> Vec<(&ThoughtID, usize)> {
+        let term = words(&SearchOptions::default().normalize(term)).join(" ");
+        if term.is_empty() {
+            return Vec::new();
+        }
+        let term_words = term.split(' ').count();
+
+        let mut found = parallel::filter_map(&self.thoughts, |id, thought| {
+            let in_title = thought.title.as_deref().and_then(|title| distance_in(title, &term, term_words, max_distance));
+            let in_contents = distance_in(&thought.contents, &term, term_words, max_distance);
+            let distance = in_title.into_iter().chain(in_contents).min()?;
+            Some((id, distance))
+        });
+        found.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        let distance = |a: &str, b: &str, max| edit_distance(&a.chars().collect::<Vec<_>>(), &b.chars().collect::<Vec<_>>(), max);
+        assert_eq!(distance("browish", "brownish", 2), Some(1));
+        assert_eq!(distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(distance("kitten", "sitting", 2), None);
+        assert_eq!(distance("", "abc", 3), Some(3));
+        assert_eq!(distance("same", "same", 0), Some(0));
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let mut graph = ThoughtGraph::new();
+        let mut put = |id: &str, title: Option<&str>, contents: &str| {
+            graph.create_thought(ThoughtID::new(id.to_string()), title.map(str::to_string), contents.to_string(), vec![], vec![]).unwrap();
+        };
+        put("exact", None, "Graph theory");
+        put("typo", Some("Grpah Theory"), "");
+        put("far", None, "Gravity theories");
+        let ids = |term, max_distance| -> Vec<String> {
+            graph.fuzzy_search(term, max_distance).into_iter().map(|(id, _)| id.id.to_string()).collect()
+        };
+
+        assert_eq!(ids("graph theory", 2), vec!["exact", "typo"]);
+        assert_eq!(ids("Graph, theory!", 0), vec!["exact"]);
+        assert_eq!(ids("theor", 0), vec!["exact", "far", "typo"]);
+        assert_eq!(ids("teory", 1), vec!["exact", "typo"]);
+        assert!(ids("  ", 3).is_empty());
+    }
+}
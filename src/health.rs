@@ -0,0 +1,206 @@
+//! Graph health
+//!
+//! A graph collects clutter as it grows: thoughts linked to nothing, links to thoughts
+//! that are gone, tags nobody uses, notes nobody has touched in a long time, and the
+//! same note written twice. A health report counts each of these, along with how the
+//! connections are spread between hubs and leaves, and sums them up as a score out of
+//! 100. The score loses up to 30 points for orphans, 25 for broken links, 20 for
+//! duplicates, 15 for stale thoughts and 10 for unused tags, each in proportion to how
+//! much of the graph is affected.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use crate::broken::BrokenLink;
+use crate::{TagID, ThoughtGraph, ThoughtID};
+
+/// Degree from which a thought counts as a hub
+pub const HUB_DEGREE: usize = 10;
+
+/// A health report of a graph, from `ThoughtGraph::health`
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthReport {
+    /// Number of thoughts
+    pub thoughts: usize,
+    /// Number of tags
+    pub tags: usize,
+    /// Thoughts that neither reference nor are referenced by another thought
+    pub orphans: Vec<ThoughtID>,
+    /// References and mentions of thoughts that do not exist
+    pub broken_links: Vec<BrokenLink>,
+    /// Tags that no thought has
+    pub unused_tags: Vec<TagID>,
+    /// Thoughts not updated since the time given, least recently updated first
+    pub stale: Vec<ThoughtID>,
+    /// Pairs of thoughts with the same title or the same content, ignoring case and
+    /// surrounding whitespace
+    pub duplicates: Vec<(ThoughtID, ThoughtID)>,
+    /// Thoughts with at least `HUB_DEGREE` references to and from them, most connected
+    /// first
+    pub hubs: Vec<ThoughtID>,
+    /// Number of thoughts with a single reference to or from them
+    pub leaves: usize,
+    /// The overall score, from 0 to 100, as described in the `health` module
+    pub score: u32,
+}
+
+impl HealthReport {
+    /// Whether nothing needs cleaning up
+    pub fn is_healthy(&self) -> bool {
+        self.orphans.is_empty()
+            && self.broken_links.is_empty()
+            && self.unused_tags.is_empty()
+            && self.stale.is_empty()
+            && self.duplicates.is_empty()
+    }
+}
+
+/// Normalize a title or content for comparison, or `None` if it is empty
+fn normalize(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_lowercase())
+}
+
+impl ThoughtGraph {
+    /// Check the health of the graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `stale_before` - Thoughts last updated before this time are stale
+    ///
+    /// # Returns
+    ///
+    /// The report, where each list is in order of ID unless stated otherwise
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use chrono::{Duration, Utc};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, "See [gone]".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let report = graph.health(Utc::now() - Duration::days(365));
+    /// assert_eq!(report.orphans.len(), 1);
+    /// assert_eq!(report.broken_links.len(), 1);
+    /// assert!(report.score < 100);
+    /// ```
+    pub fn health(&self, stale_before: DateTime<Utc>) -> HealthReport {
+        let mut ids: Vec<&ThoughtID> = self.thoughts.keys().collect();
+        ids.sort();
+
+        let orphans: Vec<ThoughtID> = ids.iter().filter(|id| self.degree(id) == 0).map(|id| (*id).clone()).collect();
+
+        let used: HashSet<&TagID> = self.thoughts.values().flat_map(|thought| &thought.tags).collect();
+        let mut unused_tags: Vec<TagID> = self.tags.keys().filter(|tag| !used.contains(tag)).cloned().collect();
+        unused_tags.sort();
+
+        let mut stale: Vec<(&ThoughtID, DateTime<Utc>)> = self.thoughts
+            .iter()
+            .filter(|(_, thought)| thought.updated_at < stale_before)
+            .map(|(id, thought)| (id, thought.updated_at))
+            .collect();
+        stale.sort_by(|(a, a_updated), (b, b_updated)| a_updated.cmp(b_updated).then_with(|| a.cmp(b)));
+
+        let mut duplicates = Vec::new();
+        let mut titles: HashMap<String, &ThoughtID> = HashMap::new();
+        let mut contents: HashMap<String, &ThoughtID> = HashMap::new();
+        for id in &ids {
+            let thought = &self.thoughts[*id];
+            let same_title = thought.title.as_deref().and_then(normalize).and_then(|title| match titles.get(&title) {
+                Some(first) => Some(*first),
+                None => {
+                    titles.insert(title, id);
+                    None
+                },
+            });
+            let same_content = normalize(&thought.contents).and_then(|content| match contents.get(&content) {
+                Some(first) => Some(*first),
+                None => {
+                    contents.insert(content, id);
+                    None
+                },
+            });
+            if let Some(first) = same_title.or(same_content) {
+                duplicates.push((first.clone(), (*id).clone()));
+            }
+        }
+
+        let mut hubs: Vec<(&ThoughtID, usize)> =
+            ids.iter().map(|id| (*id, self.degree(id))).filter(|(_, degree)| *degree >= HUB_DEGREE).collect();
+        hubs.sort_by(|(a, a_degree), (b, b_degree)| b_degree.cmp(a_degree).then_with(|| a.cmp(b)));
+        let leaves = ids.iter().filter(|id| self.degree(id) == 1).count();
+
+        let broken_links = self.broken_links();
+        let share = |count: usize, total: usize| if total == 0 { 0.0 } else { (count as f64 / total as f64).min(1.0) };
+        let thoughts = self.thoughts.len();
+        let penalty = 30.0 * share(orphans.len(), thoughts)
+            + 25.0 * share(broken_links.len(), thoughts)
+            + 20.0 * share(duplicates.len(), thoughts)
+            + 15.0 * share(stale.len(), thoughts)
+            + 10.0 * share(unused_tags.len(), self.tags.len());
+
+        HealthReport {
+            thoughts,
+            tags: self.tags.len(),
+            orphans,
+            broken_links,
+            unused_tags,
+            stale: stale.into_iter().map(|(id, _)| id.clone()).collect(),
+            duplicates,
+            hubs: hubs.into_iter().map(|(id, _)| id.clone()).collect(),
+            leaves,
+            score: (100.0 - penalty).round().max(0.0) as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Reference, Thought};
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_health() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let tag = |name: &str| TagID::new(name.to_string());
+        let now = Utc.with_ymd_and_hms(2025, 2, 26, 9, 30, 0).unwrap();
+        let mut put = |name: &str, title: Option<&str>, contents: &str, references: Vec<&str>, age: i64| {
+            let references = references.into_iter().map(|to| Reference::new(id(to), String::new(), now)).collect();
+            let mut thought = Thought::new(title.map(str::to_string), contents.to_string(), vec![tag("used")], references);
+            thought.updated_at = now - Duration::days(age);
+            graph.command(&Command::PutThought { id: id(name), thought });
+        };
+        let spokes: Vec<String> = (0..HUB_DEGREE).map(|i| format!("spoke{}", i)).collect();
+        put("hub", Some("Index"), "All of it", spokes.iter().map(String::as_str).collect(), 0);
+        for spoke in &spokes {
+            put(spoke, None, spoke, vec![], 0);
+        }
+        put("old", Some("index "), "Different", vec![], 400);
+        put("older", None, "  ALL of it", vec!["missing"], 500);
+        put("copy", None, "", vec![], 0);
+        graph.create_tag(tag("used"), String::new()).unwrap();
+        graph.create_tag(tag("unused"), String::new()).unwrap();
+
+        let report = graph.health(now - Duration::days(365));
+        assert_eq!(report.thoughts, 14);
+        assert_eq!(report.orphans, vec![id("copy"), id("old")]);
+        assert_eq!(report.broken_links.len(), 1);
+        assert_eq!(report.unused_tags, vec![tag("unused")]);
+        assert_eq!(report.stale, vec![id("older"), id("old")]);
+        assert_eq!(report.duplicates, vec![(id("hub"), id("old")), (id("hub"), id("older"))]);
+        assert_eq!(report.hubs, vec![id("hub")]);
+        assert_eq!(report.leaves, HUB_DEGREE + 1);
+        // 30 * 2/14 + 25 * 1/14 + 20 * 2/14 + 15 * 2/14 + 10 * 1/2 = 16.07
+        assert_eq!(report.score, 84);
+        assert!(!report.is_healthy());
+
+        let empty = ThoughtGraph::new().health(now);
+        assert_eq!(empty.score, 100);
+        assert!(empty.is_healthy());
+    }
+}
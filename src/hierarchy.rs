@@ -0,0 +1,257 @@
+//! Hierarchies of thoughts built from parent references
+//!
+//! A reference of kind `ReferenceKind::Parent` makes the referenced thought a parent of
+//! the referencing one. Thoughts may have several parents, so the hierarchy is a directed
+//! acyclic graph rather than a tree. This module orders thoughts so that parents come
+//! before their children, groups them into layers, and exports the hierarchy as a nested
+//! Markdown list. All of these fail with `HierarchyCycle` if a thought is its own
+//! ancestor.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{ReferenceKind, Result, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+impl ThoughtGraph {
+    /// The existing thoughts that `id` names as its parents, ordered by ID
+    pub fn parents(&self, id: &ThoughtID) -> Vec<&ThoughtID> {
+        let mut parents: Vec<&ThoughtID> = match self.thoughts.get(id) {
+            Some(thought) => thought
+                .references
+                .iter()
+                .filter(|r| r.kind == ReferenceKind::Parent)
+                .filter_map(|r| self.thoughts.get_key_value(&r.id))
+                .map(|(id, _)| id)
+                .collect(),
+            None => Vec::new(),
+        };
+        parents.sort();
+        parents.dedup();
+        parents
+    }
+
+    /// The thoughts that name `id` as a parent, ordered by ID
+    pub fn children(&self, id: &ThoughtID) -> Vec<&ThoughtID> {
+        let mut children: Vec<&ThoughtID> = self
+            .backreferences
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter_map(|source| self.thoughts.get_key_value(source))
+            .filter(|(_, thought)| {
+                thought.references.iter().any(|r| &r.id == id && r.kind == ReferenceKind::Parent)
+            })
+            .map(|(source, _)| source)
+            .collect();
+        children.sort();
+        children
+    }
+
+    /// Group all thoughts into layers of the hierarchy.
+    ///
+    /// The first layer holds the thoughts without parents. Every other thought is in the
+    /// layer after that of its deepest parent, so each thought comes after all of its
+    /// ancestors. Thoughts within a layer are ordered by ID.
+    ///
+    /// # Returns
+    ///
+    /// The layers, or `HierarchyCycle` naming a cycle if the parent references have one
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Reference, ReferenceKind};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = |name: &str| ThoughtID::new(name.to_string());
+    /// let parent = |name: &str| Reference::new(id(name), String::new(), Utc::now()).with_kind(ReferenceKind::Parent);
+    /// graph.create_thought(id("book"), None, String::new(), vec![], vec![]).unwrap();
+    /// graph.create_thought(id("chapter"), None, String::new(), vec![], vec![parent("book")]).unwrap();
+    /// graph.create_thought(id("section"), None, String::new(), vec![], vec![parent("chapter")]).unwrap();
+    ///
+    /// let layers = graph.hierarchy_layers().unwrap();
+    /// assert_eq!(layers, vec![vec![&id("book")], vec![&id("chapter")], vec![&id("section")]]);
+    /// ```
+    pub fn hierarchy_layers(&self) -> Result<Vec<Vec<&ThoughtID>>> {
+        let mut remaining_parents: HashMap<&ThoughtID, usize> =
+            self.thoughts.keys().map(|id| (id, self.parents(id).len())).collect();
+
+        let mut layer: Vec<&ThoughtID> = remaining_parents
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut layers = Vec::new();
+        let mut placed = 0;
+
+        while !layer.is_empty() {
+            layer.sort();
+            let mut next = Vec::new();
+            for id in &layer {
+                for child in self.children(id) {
+                    let count = remaining_parents.get_mut(child).expect("children exist");
+                    *count -= 1;
+                    if *count == 0 {
+                        next.push(child);
+                    }
+                }
+            }
+            placed += layer.len();
+            layers.push(layer);
+            layer = next;
+        }
+
+        if placed < self.thoughts.len() {
+            let unplaced: HashSet<&ThoughtID> =
+                remaining_parents.into_iter().filter(|(_, count)| *count > 0).map(|(id, _)| id).collect();
+            return Err(ThoughtGraphError::HierarchyCycle(self.find_cycle(&unplaced)));
+        }
+        Ok(layers)
+    }
+
+    /// Order all thoughts so that every thought comes after its parents.
+    ///
+    /// The order is that of `hierarchy_layers`, flattened.
+    ///
+    /// # Returns
+    ///
+    /// The ordered thoughts, or `HierarchyCycle` if the parent references have a cycle
+    pub fn topological_order(&self) -> Result<Vec<&ThoughtID>> {
+        Ok(self.hierarchy_layers()?.into_iter().flatten().collect())
+    }
+
+    /// Export the hierarchy as a nested Markdown list.
+    ///
+    /// Each thought is listed as `- [id] Title` beneath each of its parents, starting
+    /// from the thoughts without parents (or from `root` alone, if given). Children are
+    /// ordered by ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The thought to start from, or `None` for the whole hierarchy
+    ///
+    /// # Returns
+    ///
+    /// The outline, `ThoughtNotFound` if `root` does not exist, or `HierarchyCycle` if the
+    /// parent references have a cycle
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Reference, ReferenceKind};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = |name: &str| ThoughtID::new(name.to_string());
+    /// let parent = Reference::new(id("book"), String::new(), Utc::now()).with_kind(ReferenceKind::Parent);
+    /// graph.create_thought(id("book"), Some("A Book".to_string()), String::new(), vec![], vec![]).unwrap();
+    /// graph.create_thought(id("intro"), None, String::new(), vec![], vec![parent]).unwrap();
+    ///
+    /// assert_eq!(graph.outline(None).unwrap(), "- [book] A Book\n  - [intro]\n");
+    /// ```
+    pub fn outline(&self, root: Option<&ThoughtID>) -> Result<String> {
+        let layers = self.hierarchy_layers()?;
+        let roots: Vec<&ThoughtID> = match root {
+            Some(root) => match self.thoughts.get_key_value(root) {
+                Some((root, _)) => vec![root],
+                None => return Err(ThoughtGraphError::ThoughtNotFound(root.id.to_string())),
+            },
+            None => layers.into_iter().next().unwrap_or_default(),
+        };
+
+        let mut outline = String::new();
+        for root in roots {
+            self.write_outline(root, 0, &mut outline);
+        }
+        Ok(outline)
+    }
+
+    /// Append the outline of `id` and its descendants at the given indentation level
+    fn write_outline(&self, id: &ThoughtID, level: usize, outline: &mut String) {
+        outline.push_str(&"  ".repeat(level));
+        outline.push_str(&format!("- [{}]", id.as_str()));
+        if let Some(title) = self.thoughts.get(id).and_then(|t| t.title.as_deref()) {
+            outline.push(' ');
+            outline.push_str(title);
+        }
+        outline.push('\n');
+
+        for child in self.children(id) {
+            self.write_outline(child, level + 1, outline);
+        }
+    }
+
+    /// Describe a cycle among thoughts that all have a parent in `unplaced`
+    fn find_cycle(&self, unplaced: &HashSet<&ThoughtID>) -> String {
+        let mut start: Vec<&&ThoughtID> = unplaced.iter().collect();
+        start.sort();
+        let mut path: Vec<&ThoughtID> = start.first().map(|id| **id).into_iter().collect();
+
+        // Every unplaced thought has an unplaced parent, so following parents must
+        // eventually revisit a thought
+        loop {
+            let current = *path.last().expect("path is not empty");
+            let parent = self
+                .parents(current)
+                .into_iter()
+                .find(|parent| unplaced.contains(parent))
+                .expect("unplaced thoughts have unplaced parents");
+            if let Some(position) = path.iter().position(|id| *id == parent) {
+                let mut cycle: Vec<&str> = path[position..].iter().map(|id| id.as_str()).collect();
+                cycle.push(parent.as_str());
+                return cycle.join(" -> ");
+            }
+            path.push(parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+    use chrono::Utc;
+
+    fn id(name: &str) -> ThoughtID {
+        ThoughtID::new(name.to_string())
+    }
+
+    fn add(graph: &mut ThoughtGraph, name: &str, parents: &[&str], related: &[&str]) {
+        let kinds = parents.iter().map(|p| (p, ReferenceKind::Parent)).chain(related.iter().map(|r| (r, ReferenceKind::Related)));
+        let references = kinds.map(|(r, kind)| Reference::new(id(r), String::new(), Utc::now()).with_kind(kind)).collect();
+        graph.create_thought(id(name), None, String::new(), vec![], references).unwrap();
+    }
+
+    #[test]
+    fn test_layers_use_the_deepest_parent() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "root", &[], &[]);
+        add(&mut graph, "a", &["root"], &["c"]);
+        add(&mut graph, "b", &["root", "a"], &[]);
+        add(&mut graph, "c", &["b"], &["root"]);
+        add(&mut graph, "loose", &[], &["a"]);
+
+        let layers = graph.hierarchy_layers().unwrap();
+        assert_eq!(layers, vec![vec![&id("loose"), &id("root")], vec![&id("a")], vec![&id("b")], vec![&id("c")]]);
+        assert_eq!(graph.children(&id("root")), vec![&id("a"), &id("b")]);
+        assert_eq!(
+            graph.outline(Some(&id("a"))).unwrap(),
+            "- [a]\n  - [b]\n    - [c]\n"
+        );
+        assert!(matches!(graph.outline(Some(&id("missing"))), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+
+    #[test]
+    fn test_cycles_are_reported() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "root", &[], &[]);
+        add(&mut graph, "a", &["root", "c"], &[]);
+        add(&mut graph, "b", &["a"], &[]);
+        add(&mut graph, "c", &["b"], &[]);
+
+        match graph.topological_order() {
+            Err(ThoughtGraphError::HierarchyCycle(cycle)) => assert_eq!(cycle, "a -> c -> b -> a"),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Hook scripts run when thoughts change
+//!
+//! The `[hooks]` section of the configuration file names shell commands to run after a
+//! thought is created (`on-create`), edited (`on-edit`) or deleted (`on-delete`). Each
+//! command runs once per affected thought, after the graph has been saved, with a JSON
+//! object on standard input:
+//!
+//! ```json
+//! {"event": "create", "id": "ideas/tea", "thought": {"title": "Tea", ...}}
+//! ```
+//!
+//! For deletions, `thought` is the thought as it was before it was deleted. The event and
+//! ID are also passed in the `THOUGHTS_EVENT` and `THOUGHTS_ID` environment variables.
+//! Changes are found by comparing the graph before and after a command, so hooks see the
+//! effects of every command, however it changed the graph.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// A kind of change to a thought that hooks can react to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookEvent {
+    /// A thought was created
+    Create,
+    /// An existing thought was changed
+    Edit,
+    /// A thought was deleted
+    Delete,
+}
+
+impl HookEvent {
+    /// The name of the event, as passed to hooks
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::Create => "create",
+            HookEvent::Edit => "edit",
+            HookEvent::Delete => "delete",
+        }
+    }
+}
+
+/// A change to a thought, as passed to hooks
+#[derive(Clone, Debug, Serialize)]
+pub struct ThoughtEvent<'a> {
+    pub event: HookEvent,
+    pub id: &'a ThoughtID,
+    /// The thought after the change, or before it for deletions
+    pub thought: &'a Thought,
+}
+
+/// The thoughts that differ between two versions of a graph: created thoughts, then
+/// edited ones, then deleted ones, each ordered by ID
+pub fn thought_events<'a>(before: &'a ThoughtGraph, after: &'a ThoughtGraph) -> Vec<ThoughtEvent<'a>> {
+    let mut created = Vec::new();
+    let mut edited = Vec::new();
+    for (id, thought) in &after.thoughts {
+        match before.thoughts.get(id) {
+            None => created.push(ThoughtEvent { event: HookEvent::Create, id, thought }),
+            Some(old) if old != thought => edited.push(ThoughtEvent { event: HookEvent::Edit, id, thought }),
+            Some(_) => {},
+        }
+    }
+    let mut deleted: Vec<ThoughtEvent> = before
+        .thoughts
+        .iter()
+        .filter(|(id, _)| !after.thoughts.contains_key(*id))
+        .map(|(id, thought)| ThoughtEvent { event: HookEvent::Delete, id, thought })
+        .collect();
+
+    let mut events = Vec::new();
+    for group in [&mut created, &mut edited, &mut deleted] {
+        group.sort_by(|a, b| a.id.cmp(b.id));
+        events.append(group);
+    }
+    events
+}
+
+/// The commands to run for each event, from the `[hooks]` section of the configuration
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Hooks {
+    /// Run after a thought is created
+    pub on_create: Option<String>,
+    /// Run after a thought is changed
+    pub on_edit: Option<String>,
+    /// Run after a thought is deleted
+    pub on_delete: Option<String>,
+}
+
+impl Hooks {
+    /// Whether no hooks are configured
+    pub fn is_empty(&self) -> bool {
+        self.on_create.is_none() && self.on_edit.is_none() && self.on_delete.is_none()
+    }
+
+    /// The command to run for an event, if any
+    pub fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::Create => self.on_create.as_deref(),
+            HookEvent::Edit => self.on_edit.as_deref(),
+            HookEvent::Delete => self.on_delete.as_deref(),
+        }
+    }
+
+    /// Run the hooks for each event, in order.
+    ///
+    /// A failing hook does not stop the others from running.
+    ///
+    /// # Returns
+    ///
+    /// `HookFailed` describing every hook that could not be run or exited unsuccessfully
+    pub fn run(&self, events: &[ThoughtEvent]) -> Result<()> {
+        let mut failures = Vec::new();
+        for event in events {
+            if let Some(command) = self.command(event.event) {
+                if let Err(e) = run_hook(command, event) {
+                    failures.push(format!("on-{} for '{}': {}", event.event.name(), event.id.id, e));
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(ThoughtGraphError::HookFailed(failures.join("; ")))
+        }
+    }
+}
+
+/// Run a hook command through the shell, passing the event on standard input
+fn run_hook(command: &str, event: &ThoughtEvent) -> io::Result<()> {
+    let input = serde_json::to_vec(event)?;
+    let mut child = shell(command)
+        .env("THOUGHTS_EVENT", event.event.name())
+        .env("THOUGHTS_ID", event.id.as_str())
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    match stdin.write_all(&input) {
+        // Hooks need not read their input
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+        _ => drop(stdin),
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("exited with {}", status)))
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(name: &str) -> ThoughtID {
+        ThoughtID::new(name.to_string())
+    }
+
+    fn sample_graph() -> ThoughtGraph {
+        let mut graph = ThoughtGraph::new();
+        for name in ["kept", "edited", "deleted"] {
+            graph.create_thought(id(name), None, name.to_string(), vec![], vec![]).unwrap();
+        }
+        graph
+    }
+
+    #[test]
+    fn test_thought_events() {
+        let before = sample_graph();
+        let mut after = before.clone();
+        after.apply(crate::Command::DeleteThought { id: id("deleted") });
+        after.create_thought(id("new"), None, String::new(), vec![], vec![]).unwrap();
+        let mut edited = after.get_thought(&id("edited")).unwrap().clone();
+        edited.contents.push_str(" again");
+        after.apply(crate::Command::PutThought { id: id("edited"), thought: edited });
+
+        let events: Vec<(HookEvent, &str)> =
+            thought_events(&before, &after).iter().map(|e| (e.event, e.id.as_str())).collect();
+        assert_eq!(events, vec![(HookEvent::Create, "new"), (HookEvent::Edit, "edited"), (HookEvent::Delete, "deleted")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("events");
+        let before = sample_graph();
+        let mut after = before.clone();
+        after.apply(crate::Command::DeleteThought { id: id("deleted") });
+
+        let hooks = Hooks {
+            on_delete: Some(format!("cat >> '{}'; echo \" $THOUGHTS_EVENT\" >> '{}'", output.display(), output.display())),
+            on_create: Some("exit 3".to_string()),
+            ..Hooks::default()
+        };
+        hooks.run(&thought_events(&before, &after)).unwrap();
+        let written = std::fs::read_to_string(&output).unwrap();
+        assert!(written.starts_with(r#"{"event":"delete","id":"deleted","thought":{"#));
+        assert!(written.ends_with(" delete\n"));
+
+        let events = thought_events(&after, &before);
+        assert!(matches!(hooks.run(&events), Err(ThoughtGraphError::HookFailed(_))));
+    }
+}
@@ -0,0 +1,328 @@
+//! Hub detection
+//!
+//! Some notes grow until they do too much: an index that dozens of thoughts link to, or
+//! a running document thousands of words long. Such hubs are easier to navigate and to
+//! link to precisely once they are split. This module finds them and suggests where to
+//! split: long hubs at their headings (or, without headings, where the subject of the
+//! paragraphs changes), and highly linked hubs along the clusters their neighbors form.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::health::HUB_DEGREE;
+use crate::text::keywords;
+use crate::topics::{Topic, MIN_TOPIC_SIZE};
+use crate::{TagID, ThoughtGraph, ThoughtID};
+
+/// Number of words from which a thought counts as a hub by default
+pub const HUB_WORDS: usize = 2000;
+
+/// Number of keywords of a paragraph compared with the paragraph before it
+const PARAGRAPH_KEYWORDS: usize = 5;
+
+/// When a thought counts as a hub
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HubThresholds {
+    /// Minimum number of references to and from a thought
+    pub min_degree: usize,
+    /// Minimum number of words in the content of a thought
+    pub min_words: usize,
+}
+
+impl Default for HubThresholds {
+    fn default() -> Self {
+        Self { min_degree: HUB_DEGREE, min_words: HUB_WORDS }
+    }
+}
+
+/// A place where a long thought could be split
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitPoint {
+    /// The line the new thought would start at, counting from 1
+    pub line: usize,
+    /// The heading at that line, or the start of the paragraph there
+    pub heading: String,
+    /// Number of words from that line to the next split point
+    pub words: usize,
+}
+
+/// A thought that is too long or too connected, from `ThoughtGraph::hubs`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hub {
+    /// The thought
+    pub id: ThoughtID,
+    /// Number of references of the thought
+    pub references: usize,
+    /// Number of thoughts referencing it
+    pub backlinks: usize,
+    /// Number of words in its content
+    pub words: usize,
+    /// Where to split it, if it has at least `min_words` words
+    pub split_points: Vec<SplitPoint>,
+    /// Groups of its neighbors that are related to each other, largest first, if it has
+    /// at least `min_degree` references and backlinks
+    pub clusters: Vec<Topic>,
+}
+
+/// Split points at the Markdown headings of a text, or where the keywords of a paragraph
+/// share nothing with the paragraph before it if there are fewer than two headings
+fn split_points(text: &str) -> Vec<SplitPoint> {
+    let lines: Vec<&str> = text.lines().collect();
+    let is_heading = |line: &str| {
+        let line = line.trim_start();
+        let level = line.chars().take_while(|c| *c == '#').count();
+        (1..=6).contains(&level) && line[level..].starts_with(' ')
+    };
+    let mut starts: Vec<(usize, String)> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| is_heading(line))
+        .map(|(i, line)| (i, line.trim_start().trim_start_matches('#').trim().to_string()))
+        .collect();
+
+    if starts.len() < 2 {
+        starts.clear();
+        let mut previous: Option<HashSet<String>> = None;
+        let mut i = 0;
+        while i < lines.len() {
+            if lines[i].trim().is_empty() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < lines.len() && !lines[i].trim().is_empty() {
+                i += 1;
+            }
+            let paragraph = lines[start..i].join("\n");
+            let words: HashSet<String> =
+                keywords(&paragraph, PARAGRAPH_KEYWORDS).into_iter().map(|(word, _)| word).collect();
+            if previous.as_ref().is_some_and(|previous| previous.is_disjoint(&words) && !words.is_empty()) {
+                let opening: Vec<&str> = lines[start].split_whitespace().take(8).collect();
+                starts.push((start, opening.join(" ")));
+            }
+            if !words.is_empty() {
+                previous = Some(words);
+            }
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(k, (start, heading))| {
+            let end = starts.get(k + 1).map_or(lines.len(), |(next, _)| *next);
+            let words = lines[*start..end].iter().map(|line| line.split_whitespace().count()).sum();
+            SplitPoint { line: start + 1, heading: heading.clone(), words }
+        })
+        .collect()
+}
+
+impl ThoughtGraph {
+    /// Find the thoughts that are long or connected enough to be worth splitting.
+    ///
+    /// Two neighbors of a hub (thoughts it references or that reference it) are related
+    /// when one references the other or they share a tag the hub does not have; the
+    /// clusters are the groups of at least `MIN_TOPIC_SIZE` neighbors related directly
+    /// or through each other, labelled with the tag they share most, or else their most
+    /// frequent keyword.
+    ///
+    /// # Arguments
+    ///
+    /// * `thresholds` - The degree or length from which a thought is a hub
+    ///
+    /// # Returns
+    ///
+    /// The hubs, most connected first, then longest first
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::hubs::HubThresholds;
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let content = "# Setup\nInstall it.\n\n# Usage\nRun it.".to_string();
+    /// graph.create_thought(ThoughtID::new("manual".to_string()), None, content, vec![], vec![]).unwrap();
+    ///
+    /// let hubs = graph.hubs(&HubThresholds { min_degree: 10, min_words: 5 });
+    /// assert_eq!(hubs[0].words, 8);
+    /// assert_eq!(hubs[0].split_points[1].heading, "Usage");
+    /// ```
+    pub fn hubs(&self, thresholds: &HubThresholds) -> Vec<Hub> {
+        let mut hubs: Vec<Hub> = self
+            .thoughts
+            .iter()
+            .filter_map(|(id, thought)| {
+                let words = thought.contents.split_whitespace().count();
+                let long = words >= thresholds.min_words;
+                let connected = self.degree(id) >= thresholds.min_degree;
+                (long || connected).then(|| Hub {
+                    id: id.clone(),
+                    references: thought.references.len(),
+                    backlinks: self.backreferences.get(id).map_or(0, HashSet::len),
+                    words,
+                    split_points: if long { split_points(&thought.contents) } else { Vec::new() },
+                    clusters: if connected { self.neighbor_clusters(id) } else { Vec::new() },
+                })
+            })
+            .collect();
+        hubs.sort_by(|a, b| {
+            (b.references + b.backlinks)
+                .cmp(&(a.references + a.backlinks))
+                .then_with(|| b.words.cmp(&a.words))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        hubs
+    }
+
+    /// Group the neighbors of a thought that are related to each other
+    fn neighbor_clusters(&self, hub: &ThoughtID) -> Vec<Topic> {
+        let thought = &self.thoughts[hub];
+        let mut neighbors: Vec<&ThoughtID> = thought
+            .references
+            .iter()
+            .map(|reference| &reference.id)
+            .chain(self.backreferences.get(hub).into_iter().flatten())
+            .filter(|id| *id != hub && self.thoughts.contains_key(*id))
+            .collect();
+        neighbors.sort();
+        neighbors.dedup();
+        let position: HashMap<&ThoughtID, usize> = neighbors.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        // Union-find over the positions of the neighbors
+        let mut parent: Vec<usize> = (0..neighbors.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        fn join(parent: &mut [usize], a: usize, b: usize) {
+            let (a, b) = (root(parent, a), root(parent, b));
+            parent[a.max(b)] = a.min(b);
+        }
+
+        let hub_tags: HashSet<&TagID> = thought.tags.iter().collect();
+        let mut by_tag: HashMap<&TagID, usize> = HashMap::new();
+        for (i, id) in neighbors.iter().enumerate() {
+            let neighbor = &self.thoughts[*id];
+            for reference in &neighbor.references {
+                if let Some(j) = position.get(&reference.id) {
+                    join(&mut parent, i, *j);
+                }
+            }
+            for tag in neighbor.tags.iter().filter(|tag| !hub_tags.contains(tag)) {
+                match by_tag.get(tag) {
+                    Some(j) => join(&mut parent, i, *j),
+                    None => {
+                        by_tag.insert(tag, i);
+                    },
+                }
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..neighbors.len() {
+            groups.entry(root(&mut parent, i)).or_default().push(i);
+        }
+        let mut clusters: Vec<Topic> = groups
+            .into_values()
+            .filter(|members| members.len() >= MIN_TOPIC_SIZE)
+            .map(|members| {
+                let ids: Vec<ThoughtID> = members.iter().map(|i| neighbors[*i].clone()).collect();
+                Topic { label: self.cluster_label(&ids, &hub_tags), thoughts: ids }
+            })
+            .collect();
+        clusters.sort_by(|a, b| b.thoughts.len().cmp(&a.thoughts.len()).then_with(|| a.thoughts.cmp(&b.thoughts)));
+        clusters
+    }
+
+    /// The tag shared by most of a cluster (other than the hub's), or its top keyword
+    fn cluster_label(&self, ids: &[ThoughtID], hub_tags: &HashSet<&TagID>) -> String {
+        let mut counts: HashMap<&TagID, usize> = HashMap::new();
+        for id in ids {
+            let tags: HashSet<&TagID> = self.thoughts[id].tags.iter().filter(|tag| !hub_tags.contains(tag)).collect();
+            for tag in tags {
+                *counts.entry(tag).or_default() += 1;
+            }
+        }
+        let shared = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)));
+        if let Some((tag, _)) = shared {
+            return tag.id.to_string();
+        }
+        let text: Vec<String> = ids
+            .iter()
+            .map(|id| {
+                let thought = &self.thoughts[id];
+                format!("{} {}", thought.title.as_deref().unwrap_or_default(), thought.contents)
+            })
+            .collect();
+        keywords(&text.join("\n"), 1)
+            .into_iter()
+            .next()
+            .map_or_else(|| ids[0].id.to_string(), |(word, _)| word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Reference, Thought};
+    use chrono::Utc;
+
+    #[test]
+    fn test_split_points() {
+        let headings = "Intro text\n# One\nfirst part here\n\n## Two\nsecond\n#hashtag is not a heading";
+        let points = split_points(headings);
+        assert_eq!(points, vec![
+            SplitPoint { line: 2, heading: "One".to_string(), words: 5 },
+            SplitPoint { line: 5, heading: "Two".to_string(), words: 8 },
+        ]);
+
+        let paragraphs = "Sourdough bread needs starter.\n\nStarter feeds on flour daily.\n\nCompilers parse tokens into trees.";
+        let points = split_points(paragraphs);
+        assert_eq!(points.len(), 1);
+        assert_eq!((points[0].line, points[0].heading.as_str()), (5, "Compilers parse tokens into trees."));
+        assert!(split_points("Just one paragraph").is_empty());
+    }
+
+    #[test]
+    fn test_hubs() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let tag = |name: &str| TagID::new(name.to_string());
+        for name in ["index", "rust", "bread"] {
+            graph.create_tag(tag(name), String::new()).unwrap();
+        }
+        let link = |to: &str| Reference::new(id(to), String::new(), Utc::now());
+        let mut index = Thought::new(None, String::new(), vec![tag("index")], vec![]);
+        for (name, tags, references) in [
+            ("a", vec!["rust", "index"], vec!["b"]),
+            ("b", vec![], vec![]),
+            ("c", vec!["rust"], vec![]),
+            ("d", vec!["bread", "index"], vec![]),
+            ("e", vec!["bread"], vec![]),
+            ("f", vec!["index"], vec![]),
+        ] {
+            let tags = tags.into_iter().map(tag).collect();
+            graph.create_thought(id(name), None, format!("Note {}", name), tags, references.into_iter().map(link).collect()).unwrap();
+            index.references.push(link(name));
+        }
+        graph.apply(crate::Command::PutThought { id: id("index"), thought: index });
+        graph.create_thought(id("x"), None, String::new(), vec![], vec![link("index")]).unwrap();
+
+        let hubs = graph.hubs(&HubThresholds { min_degree: 7, min_words: 1000 });
+        assert_eq!(hubs.len(), 1);
+        let hub = &hubs[0];
+        assert_eq!((&hub.id, hub.references, hub.backlinks, hub.words), (&id("index"), 6, 1, 0));
+        assert!(hub.split_points.is_empty());
+        let clusters: Vec<(&str, Vec<ThoughtID>)> =
+            hub.clusters.iter().map(|topic| (topic.label.as_str(), topic.thoughts.clone())).collect();
+        assert_eq!(clusters, vec![("rust", vec![id("a"), id("b"), id("c")]), ("bread", vec![id("d"), id("e")])]);
+
+        assert!(graph.hubs(&HubThresholds { min_degree: 8, min_words: 1000 }).is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+//! Quick capture into an inbox
+//!
+//! Capturing a thought should not interrupt whatever prompted it, so captured thoughts
+//! get an ID generated from the time they were captured and the `inbox` tag, with no
+//! other decisions to make. Later, each thought in the inbox is triaged: tagged, linked
+//! to other thoughts, and filed by removing it from the inbox, or archived with the
+//! `archive` tag when it needs nothing more.
+//!
+//! Running logs and meeting notes grow instead: text appended to an existing thought is
+//! added as a block headed by the time it was written.
+
+use chrono::{DateTime, Utc};
+
+use crate::{Command, Result, TagID, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// The tag of thoughts waiting to be triaged
+pub const INBOX_TAG: &str = "inbox";
+
+/// The tag of thoughts archived from the inbox
+pub const ARCHIVE_TAG: &str = "archive";
+
+impl ThoughtGraph {
+    /// Make sure a tag exists, creating it with the given description if it does not
+    fn ensure_tag(&mut self, tag: &str, description: &str) -> Result<TagID> {
+        let tag = TagID::new(tag.to_string());
+        if !self.tags.contains_key(&tag) {
+            self.create_tag(tag.clone(), description.to_string())?;
+        }
+        Ok(tag)
+    }
+
+    /// Capture a thought into the inbox.
+    ///
+    /// The thought is given an ID like `capture-2025-02-26-093015` from the time it was
+    /// captured, with a number appended if that ID is already taken, and the `inbox` tag,
+    /// which is created if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - The contents of the thought
+    /// * `at` - The time of capture
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new thought
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::ThoughtGraph;
+    /// use chrono::{TimeZone, Utc};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let at = Utc.with_ymd_and_hms(2025, 2, 26, 9, 30, 15).unwrap();
+    /// let id = graph.capture("Call the plumber".to_string(), at).unwrap();
+    /// assert_eq!(id.as_str(), "capture-2025-02-26-093015");
+    /// assert_eq!(graph.inbox(), vec![&id]);
+    /// ```
+    pub fn capture(&mut self, contents: String, at: DateTime<Utc>) -> Result<ThoughtID> {
+        let inbox = self.ensure_tag(INBOX_TAG, "Captured thoughts waiting to be triaged")?;
+        let base = format!("capture-{}", at.format("%Y-%m-%d-%H%M%S"));
+        let mut id = ThoughtID::new(base.clone());
+        let mut n = 2;
+        while self.thoughts.contains_key(&id) {
+            id = ThoughtID::new(format!("{}-{}", base, n));
+            n += 1;
+        }
+        self.create_thought(id.clone(), None, contents, vec![inbox], vec![])?;
+        Ok(id)
+    }
+
+    /// Append a block of text to a thought, headed by the time it was written, and add
+    /// references to the thoughts it mentions.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The thought to append to
+    /// * `text` - The text to append
+    /// * `at` - The time the text was written
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the thoughts newly referenced, `ThoughtNotFound` if the thought does
+    /// not exist, or `ThoughtLocked` if it is locked
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use chrono::{TimeZone, Utc};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let log = ThoughtID::new("log".to_string());
+    /// graph.create_thought(log.clone(), None, "Started the project.".to_string(), vec![], vec![]).unwrap();
+    /// let at = Utc.with_ymd_and_hms(2025, 2, 26, 9, 30, 0).unwrap();
+    /// graph.append(&log, "Fixed the build.", at).unwrap();
+    ///
+    /// assert_eq!(graph.get_thought(&log).unwrap().contents, "Started the project.\n\n**2025-02-26 09:30**\n\nFixed the build.");
+    /// ```
+    pub fn append(&mut self, id: &ThoughtID, text: &str, at: DateTime<Utc>) -> Result<Vec<ThoughtID>> {
+        let thought = self.get_thought(id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+        let mut contents = thought.contents.trim_end().to_string();
+        if !contents.is_empty() {
+            contents.push_str("\n\n");
+        }
+        contents.push_str(&format!("**{}**\n\n{}", at.format("%Y-%m-%d %H:%M"), text.trim()));
+        self.try_command(&Command::PatchThought {
+            id: id.clone(),
+            title: None,
+            contents: Some(contents),
+            add_tags: vec![],
+            remove_tags: vec![],
+            add_references: vec![],
+            remove_references: vec![],
+        })?;
+        self.process_auto_references(id)
+    }
+
+    /// The thoughts in the inbox, oldest first, in the order they are best triaged
+    pub fn inbox(&self) -> Vec<&ThoughtID> {
+        let inbox = TagID::new(INBOX_TAG.to_string());
+        let mut thoughts: Vec<_> = self.thoughts.iter().filter(|(_, thought)| thought.tags.contains(&inbox)).collect();
+        thoughts.sort_by(|(a_id, a), (b_id, b)| a.created_at.cmp(&b.created_at).then_with(|| a_id.cmp(b_id)));
+        thoughts.into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// Take a thought out of the inbox once it has been triaged.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The thought to file
+    /// * `archive` - Whether to also tag the thought `archive`, which is created if needed
+    ///
+    /// # Returns
+    ///
+    /// `ThoughtNotFound` if the thought does not exist, or `ThoughtLocked` if it is locked
+    pub fn file_from_inbox(&mut self, id: &ThoughtID, archive: bool) -> Result<()> {
+        if !self.thoughts.contains_key(id) {
+            return Err(ThoughtGraphError::ThoughtNotFound(id.id.to_string()));
+        }
+        let add_tags = match archive {
+            true => vec![self.ensure_tag(ARCHIVE_TAG, "Thoughts archived from the inbox")?],
+            false => vec![],
+        };
+        self.try_command(&Command::PatchThought {
+            id: id.clone(),
+            title: None,
+            contents: None,
+            add_tags,
+            remove_tags: vec![TagID::new(INBOX_TAG.to_string())],
+            add_references: vec![],
+            remove_references: vec![],
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_inbox() {
+        let mut graph = ThoughtGraph::new();
+        let at = Utc.with_ymd_and_hms(2025, 2, 26, 9, 30, 15).unwrap();
+        let first = graph.capture("First".to_string(), at).unwrap();
+        let second = graph.capture("Second".to_string(), at).unwrap();
+        assert_eq!(second.as_str(), "capture-2025-02-26-093015-2");
+        assert_eq!(graph.inbox(), vec![&first, &second]);
+
+        graph.file_from_inbox(&first, false).unwrap();
+        graph.file_from_inbox(&second, true).unwrap();
+        assert!(graph.inbox().is_empty());
+        assert!(graph.get_thought(&first).unwrap().tags.is_empty());
+        assert_eq!(graph.get_thought(&second).unwrap().tags, vec![TagID::new(ARCHIVE_TAG.to_string())]);
+
+        let missing = ThoughtID::new("missing".to_string());
+        assert!(matches!(graph.file_from_inbox(&missing, true), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+
+    #[test]
+    fn test_append() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let at = Utc.with_ymd_and_hms(2025, 2, 26, 9, 30, 0).unwrap();
+        graph.create_thought(id("meeting"), None, String::new(), vec![], vec![]).unwrap();
+        graph.create_thought(id("roadmap"), None, "Plans".to_string(), vec![], vec![]).unwrap();
+
+        assert_eq!(graph.append(&id("meeting"), "  Agreed on the [roadmap].\n", at).unwrap(), vec![id("roadmap")]);
+        assert_eq!(graph.append(&id("meeting"), "Again [roadmap].", at).unwrap(), vec![]);
+        let meeting = graph.get_thought(&id("meeting")).unwrap();
+        assert_eq!(meeting.contents, "**2025-02-26 09:30**\n\nAgreed on the [roadmap].\n\n**2025-02-26 09:30**\n\nAgain [roadmap].");
+        assert_eq!(meeting.references.len(), 1);
+
+        graph.command(&Command::LockThought { id: id("roadmap") });
+        assert!(matches!(graph.append(&id("roadmap"), "More", at), Err(ThoughtGraphError::ThoughtLocked(_))));
+        assert!(matches!(graph.append(&id("missing"), "More", at), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+}
@@ -0,0 +1,192 @@
+//! Thoughts described as JSON
+//!
+//! Programs that create thoughts can describe one in full as JSON rather than answering
+//! the prompts of `thoughts create`:
+//!
+//! ```json
+//! {
+//!   "id": "rust-ownership",
+//!   "title": "Ownership",
+//!   "content": "Every value has a single owner.",
+//!   "tags": ["rust"],
+//!   "references": ["rust", {"id": "borrowing", "notes": "Next up", "kind": "next"}],
+//!   "metadata": {"source": "The Book"}
+//! }
+//! ```
+//!
+//! Only `id` is required. Tags that do not exist yet are created with an empty
+//! description, and references may name thoughts that do not exist yet.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{ChangeSet, Command, Reference, ReferenceKind, Result, Tag, TagID, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// A reference in a thought described as JSON: either the ID of the referenced thought,
+/// or an object with the ID and optional notes and kind
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ReferenceInput {
+    /// A reference with no notes
+    Id(String),
+    /// A reference with notes and a kind
+    Full {
+        /// ID of the referenced thought
+        id: String,
+        /// Notes about the reference
+        #[serde(default)]
+        notes: String,
+        /// The kind of relationship: `related` (the default), `parent` or `next`
+        #[serde(default)]
+        kind: Option<String>,
+    },
+}
+
+/// A new thought described as JSON, as in the `input` module
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThoughtInput {
+    /// ID of the thought
+    pub id: String,
+    /// Optional title
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Main content
+    #[serde(default, alias = "contents")]
+    pub content: String,
+    /// IDs of the thought's tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// References to other thoughts
+    #[serde(default)]
+    pub references: Vec<ReferenceInput>,
+    /// Named values describing the thought
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl ThoughtInput {
+    /// Read a thought from JSON, returning `ParseError` if it is not one
+    pub fn from_json(json: &str) -> Result<ThoughtInput> {
+        serde_json::from_str(json).map_err(|e| ThoughtGraphError::ParseError(format!("thought JSON: {}", e)))
+    }
+}
+
+impl ThoughtGraph {
+    /// Create a thought described as JSON, along with any of its tags that do not exist
+    /// yet. Nothing changes if any part of the thought is invalid.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The thought
+    /// * `at` - When the thought and its references were created
+    ///
+    /// # Returns
+    ///
+    /// The changes made, `InvalidThoughtID` if the ID, or the ID of a reference, is
+    /// invalid or the ID is already in use, `InvalidTagID` if a tag is invalid, or
+    /// `ParseError` if the kind of a reference is unknown
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::input::ThoughtInput;
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let input = ThoughtInput::from_json(r#"{"id": "idea", "content": "Write it down", "tags": ["inbox"]}"#).unwrap();
+    /// graph.create_from_input(input, Utc::now()).unwrap();
+    ///
+    /// let thought = graph.get_thought(&ThoughtID::new("idea".to_string())).unwrap();
+    /// assert_eq!(thought.contents, "Write it down");
+    /// assert_eq!(thought.tags[0].as_str(), "inbox");
+    /// ```
+    pub fn create_from_input(&mut self, input: ThoughtInput, at: DateTime<Utc>) -> Result<ChangeSet> {
+        let id = ThoughtID::parse(&input.id)?;
+        if self.thoughts.contains_key(&id) {
+            return Err(ThoughtGraphError::InvalidThoughtID(format!("'{}' is already in use", id.id)));
+        }
+
+        let mut commands = Vec::new();
+        let mut tags: Vec<TagID> = Vec::new();
+        for tag in &input.tags {
+            let tag = TagID::parse(tag)?;
+            if tags.contains(&tag) {
+                continue;
+            }
+            if !self.tags.contains_key(&tag) {
+                commands.push(Command::PutTag { id: tag.clone(), tag: Tag::new(String::new()) });
+            }
+            tags.push(tag);
+        }
+        let mut references = Vec::new();
+        for reference in input.references {
+            let (target, notes, kind) = match reference {
+                ReferenceInput::Id(target) => (target, String::new(), ReferenceKind::default()),
+                ReferenceInput::Full { id, notes, kind: None } => (id, notes, ReferenceKind::default()),
+                ReferenceInput::Full { id, notes, kind: Some(kind) } => {
+                    (id, notes, kind.parse().map_err(ThoughtGraphError::ParseError)?)
+                },
+            };
+            references.push(Reference { kind, ..Reference::new(ThoughtID::parse(&target)?, notes, at) });
+        }
+
+        let mut thought = Thought::new(input.title, input.content, tags, references);
+        thought.metadata = input.metadata;
+        thought.created_at = at;
+        thought.updated_at = at;
+        commands.push(Command::PutThought { id, thought });
+        self.try_command(&Command::Batch(commands))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_from_input() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        graph.create_tag(TagID::new("rust".to_string()), "Rust".to_string()).unwrap();
+        graph.create_thought(id("borrowing"), None, String::new(), vec![], vec![]).unwrap();
+        let json = r#"{
+            "id": "ownership",
+            "title": "Ownership",
+            "contents": "One owner",
+            "tags": ["rust", "memory", "memory"],
+            "references": ["book", {"id": "borrowing", "notes": "Next up", "kind": "next"}],
+            "metadata": {"source": "The Book"}
+        }"#;
+
+        let changes = graph.create_from_input(ThoughtInput::from_json(json).unwrap(), Utc::now()).unwrap();
+        assert_eq!(changes.thoughts_created, vec![id("ownership")]);
+        let thought = graph.get_thought(&id("ownership")).unwrap();
+        let tags: Vec<&str> = thought.tags.iter().map(|tag| tag.as_str()).collect();
+        assert_eq!(tags, vec!["rust", "memory"]);
+        assert_eq!(graph.tags[&TagID::new("rust".to_string())].description, "Rust");
+        assert!(graph.tags.contains_key(&TagID::new("memory".to_string())));
+        let references: Vec<(&str, &str, ReferenceKind)> =
+            thought.references.iter().map(|r| (r.id.as_str(), r.notes.as_str(), r.kind)).collect();
+        assert_eq!(references, vec![("book", "", ReferenceKind::Related), ("borrowing", "Next up", ReferenceKind::Next)]);
+        assert_eq!(thought.metadata["source"], "The Book");
+        assert_eq!(graph.get_backlinks(&id("borrowing")), vec![id("ownership")]);
+
+        let input = |json: &str| ThoughtInput::from_json(json).unwrap();
+        assert!(matches!(graph.create_from_input(input(r#"{"id": "ownership"}"#), Utc::now()), Err(ThoughtGraphError::InvalidThoughtID(_))));
+        assert!(matches!(
+            graph.create_from_input(input(r#"{"id": "new", "tags": ["fresh", "bad tag"]}"#), Utc::now()),
+            Err(ThoughtGraphError::InvalidTagID(_))
+        ));
+        assert!(!graph.tags.contains_key(&TagID::new("fresh".to_string())));
+        assert!(matches!(
+            graph.create_from_input(input(r#"{"id": "new", "references": [{"id": "a", "kind": "sibling"}]}"#), Utc::now()),
+            Err(ThoughtGraphError::ParseError(_))
+        ));
+        assert!(matches!(ThoughtInput::from_json(r#"{"id": "x", "author": "me"}"#), Err(ThoughtGraphError::ParseError(_))));
+        assert!(matches!(ThoughtInput::from_json(r#"{"title": "No ID"}"#), Err(ThoughtGraphError::ParseError(_))));
+    }
+}
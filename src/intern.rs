@@ -0,0 +1,119 @@
+//! Sharing of ID strings
+//!
+//! `ThoughtID` and `TagID` keep their string behind an `Arc`, so cloning an ID only bumps
+//! a reference count. Two IDs created separately from the same text still own separate
+//! strings, though. To keep large graphs from storing the same ID thousands of times, the
+//! IDs inside thoughts (their tags and references) are replaced by the equal ID already
+//! used as a key in the graph, whenever there is one.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::{TagID, Thought, ThoughtGraph, ThoughtID};
+
+/// A collection of canonical IDs
+pub(crate) trait Pool<K> {
+    /// The stored ID equal to `id`, if any
+    fn canonical(&self, id: &K) -> Option<&K>;
+}
+
+impl<K: Eq + Hash, V> Pool<K> for HashMap<K, V> {
+    fn canonical(&self, id: &K) -> Option<&K> {
+        self.get_key_value(id).map(|(key, _)| key)
+    }
+}
+
+impl<K: Eq + Hash> Pool<K> for HashSet<K> {
+    fn canonical(&self, id: &K) -> Option<&K> {
+        self.get(id)
+    }
+}
+
+/// Replace `id` by the equal ID from `pool`, if there is one
+pub(crate) fn intern<K: Clone>(pool: &impl Pool<K>, id: &mut K) {
+    if let Some(canonical) = pool.canonical(id) {
+        *id = canonical.clone();
+    }
+}
+
+/// Share the IDs inside `thought` with the given pools
+pub(crate) fn intern_thought(thoughts: &impl Pool<ThoughtID>, tags: &impl Pool<TagID>, thought: &mut Thought) {
+    for reference in &mut thought.references {
+        intern(thoughts, &mut reference.id);
+    }
+    for tag in &mut thought.tags {
+        intern(tags, tag);
+    }
+}
+
+impl ThoughtGraph {
+    /// Share the strings of all IDs stored in the graph with the keys of the graph's maps
+    pub(crate) fn intern_ids(&mut self) {
+        // Cloning the keys only copies pointers
+        let mut thought_ids: HashSet<ThoughtID> = self.thoughts.keys().cloned().collect();
+        thought_ids.extend(self.backreferences.keys().cloned());
+        let tag_ids: HashSet<TagID> = self.tags.keys().cloned().collect();
+
+        for thought in self.thoughts.values_mut() {
+            intern_thought(&thought_ids, &tag_ids, thought);
+        }
+        for backrefs in self.backreferences.values_mut() {
+            *backrefs = backrefs
+                .drain()
+                .map(|mut id| {
+                    intern(&thought_ids, &mut id);
+                    id
+                })
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+    use chrono::Utc;
+    use std::sync::Arc;
+
+    fn graph_with_reference() -> ThoughtGraph {
+        let mut graph = ThoughtGraph::new();
+        graph.create_tag(TagID::new("tag".to_string()), String::new()).unwrap();
+        graph
+            .create_thought(ThoughtID::new("target".to_string()), None, String::new(), vec![], vec![])
+            .unwrap();
+        graph
+            .create_thought(
+                ThoughtID::new("source".to_string()),
+                None,
+                String::new(),
+                vec![TagID::new("tag".to_string())],
+                vec![Reference::new(ThoughtID::new("target".to_string()), String::new(), Utc::now())],
+            )
+            .unwrap();
+        graph
+    }
+
+    fn assert_shared(graph: &ThoughtGraph) {
+        let (target, _) = graph.thoughts.get_key_value(&ThoughtID::new("target".to_string())).unwrap();
+        let (source, thought) = graph.thoughts.get_key_value(&ThoughtID::new("source".to_string())).unwrap();
+        let (tag, _) = graph.tags.get_key_value(&TagID::new("tag".to_string())).unwrap();
+
+        assert!(Arc::ptr_eq(&thought.references[0].id.id, &target.id));
+        assert!(Arc::ptr_eq(&thought.tags[0].id, &tag.id));
+        let backref = graph.backreferences[target].iter().next().unwrap();
+        assert!(Arc::ptr_eq(&backref.id, &source.id));
+    }
+
+    #[test]
+    fn test_commands_share_ids() {
+        assert_shared(&graph_with_reference());
+    }
+
+    #[test]
+    fn test_loaded_graphs_share_ids() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        graph_with_reference().save_to_file(file.path()).unwrap();
+        assert_shared(&ThoughtGraph::load_from_file(file.path()).unwrap());
+    }
+}
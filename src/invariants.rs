@@ -0,0 +1,147 @@
+//! Invariants of a graph
+//!
+//! The backreferences and the external link index are derived from the thoughts, and
+//! every command that changes a thought has to keep them up to date. `assert_invariants`
+//! checks that they are, along with a few properties of the thoughts themselves, so that
+//! tests and fuzzing harnesses can call it after each step and catch a slip in index
+//! maintenance where it happens. It only exists in builds with debug assertions.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::links::{self, LinkIndex};
+use crate::{TagID, ThoughtGraph, ThoughtID};
+
+impl ThoughtGraph {
+    /// Panic if the graph is inconsistent, naming the first problem found.
+    ///
+    /// The graph is consistent when
+    ///
+    /// * each backreference is backed by a reference of an existing thought,
+    /// * each reference to an existing thought has a backreference, if the thought was
+    ///   created before the reference was last written (deleting a thought drops the
+    ///   backreferences to it, and only restoring it brings them back, so references to
+    ///   missing thoughts and to thoughts created again since need not have one),
+    /// * no thought has an empty set of backreferences,
+    /// * no thought was updated before it was created, and
+    /// * the external link index is what indexing every thought again would give.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Reference, ThoughtGraph, ThoughtID};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = |name: &str| ThoughtID::new(name.to_string());
+    /// graph.create_thought(id("b"), None, String::new(), vec![], vec![]).unwrap();
+    /// graph.create_thought(id("a"), None, String::new(), vec![], vec![Reference::new(id("b"), String::new(), Utc::now())]).unwrap();
+    /// graph.assert_invariants();
+    ///
+    /// graph.backreferences.clear();
+    /// assert!(std::panic::catch_unwind(|| graph.assert_invariants()).is_err());
+    /// ```
+    pub fn assert_invariants(&self) {
+        for (target, sources) in &self.backreferences {
+            assert!(!sources.is_empty(), "'{}' has an empty set of backreferences", target.id);
+            for source in sources {
+                let thought = self.thoughts.get(source).unwrap_or_else(|| {
+                    panic!("'{}' has a backreference from '{}', which does not exist", target.id, source.id)
+                });
+                assert!(
+                    thought.references.iter().any(|reference| &reference.id == target),
+                    "'{}' has a backreference from '{}', which does not reference it",
+                    target.id,
+                    source.id
+                );
+            }
+        }
+
+        let mut expected: HashMap<&ThoughtID, HashSet<&ThoughtID>> = HashMap::new();
+        let mut link_index = LinkIndex::new();
+        let mut tag_index: HashMap<TagID, HashSet<ThoughtID>> = HashMap::new();
+        for (id, thought) in &self.thoughts {
+            assert!(
+                thought.created_at <= thought.updated_at,
+                "'{}' was updated at {} before it was created at {}",
+                id.id,
+                thought.updated_at,
+                thought.created_at
+            );
+            for reference in &thought.references {
+                let target = self.thoughts.get(&reference.id);
+                if target.is_some_and(|target| target.created_at <= thought.updated_at) {
+                    expected.entry(&reference.id).or_default().insert(id);
+                }
+            }
+            links::index_links(&mut link_index, id, thought);
+            for tag in &thought.tags {
+                tag_index.entry(tag.clone()).or_default().insert(id.clone());
+            }
+        }
+        for (target, sources) in expected {
+            for source in sources {
+                assert!(
+                    self.backreferences.get(target).is_some_and(|backrefs| backrefs.contains(source)),
+                    "'{}' references '{}', but '{}' has no backreference from it",
+                    source.id,
+                    target.id,
+                    target.id
+                );
+            }
+        }
+
+        assert!(self.link_index == link_index, "the external link index is out of date");
+        assert!(self.tag_index == tag_index, "the tag index is out of date");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Reference, Thought};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_commands_keep_invariants() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let put = |name: &str, contents: &str, references: &[&str]| Command::PutThought {
+            id: id(name),
+            thought: Thought::new(
+                None,
+                contents.to_string(),
+                vec![],
+                references.iter().map(|to| Reference::new(id(to), String::new(), Utc::now())).collect(),
+            ),
+        };
+        let commands = vec![
+            put("a", "See https://example.com", &["b", "c"]),
+            put("b", "", &["a", "a", "b"]),
+            put("a", "Now https://example.org", &["b"]),
+            Command::RemoveReference { id: id("b"), target: id("a") },
+            Command::AddReference { id: id("c"), reference: Reference::new(id("a"), String::new(), Utc::now()) },
+            put("c", "", &["a"]),
+            Command::DeleteThought { id: id("a") },
+            put("d", "https://example.com", &["a", "c"]),
+            Command::Batch(vec![Command::DeleteThought { id: id("c") }, put("c", "", &["d"])]),
+        ];
+        for command in commands {
+            graph.apply(command);
+            graph.assert_invariants();
+        }
+        graph.trash_thought(&id("d"), Utc::now()).unwrap();
+        graph.assert_invariants();
+        graph.restore_thought(&id("d")).unwrap();
+        graph.assert_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "updated at")]
+    fn test_updated_before_created() {
+        let mut graph = ThoughtGraph::new();
+        let mut thought = Thought::new(None, String::new(), vec![], vec![]);
+        thought.updated_at = thought.created_at - Duration::seconds(1);
+        graph.apply(Command::PutThought { id: ThoughtID::new("a".to_string()), thought });
+        graph.assert_invariants();
+    }
+}
@@ -0,0 +1,441 @@
+//! The command journal and undo
+//!
+//! While the graph is recording, every command it applies also records the command that
+//! reverses it. Finishing the recording adds those reversing commands to the journal as
+//! one entry, so an operation is undone as a whole however many commands it took. The
+//! journal is saved with the graph and keeps the `JOURNAL_LIMIT` most recent entries.
+//!
+//! Only the thoughts, tags, rules, saved queries and locks are journaled. Emptying the
+//! trash cannot be undone.
+//!
+//! Each entry also lists what happened to the thoughts it changed, for a log of recent
+//! activity, and undoing the entries made after a point in time on a copy of the graph
+//! shows the graph as it was then.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{ChangeSet, Command, Result, TagID, ThoughtGraph, ThoughtID};
+
+/// The number of entries the journal keeps
+pub const JOURNAL_LIMIT: usize = 50;
+
+/// An operation recorded in the journal
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// What the operation was, such as the command line that ran it
+    pub description: String,
+    /// When the operation finished
+    pub at: DateTime<Utc>,
+    /// The commands that reverse the operation, in the order they must be applied
+    pub undo: Vec<Command>,
+    /// What the operation did to thoughts, in the order it did it
+    #[serde(default)]
+    pub activity: Vec<Activity>,
+}
+
+/// Something that happened to a thought
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Activity {
+    /// The thought was created
+    Created(ThoughtID),
+    /// The title, contents, references or metadata of the thought changed
+    Edited(ThoughtID),
+    /// The thought was given a tag
+    Tagged(ThoughtID, TagID),
+    /// A tag was removed from the thought
+    Untagged(ThoughtID, TagID),
+    /// The thought was deleted
+    Deleted(ThoughtID),
+}
+
+/// What a reversing command restores, as a kind of thing and its ID or name
+fn undone_item(command: &Command) -> (&'static str, String) {
+    match command {
+        Command::PutThought { id, .. } | Command::DeleteThought { id } => ("thought", id.id.to_string()),
+        Command::PutTag { id, .. } | Command::DeleteTag { id } => ("tag", id.id.to_string()),
+        Command::PutTagRule { name, .. } | Command::DeleteTagRule { name } => ("rule", name.clone()),
+        Command::PutSavedQuery { name, .. } | Command::DeleteSavedQuery { name } => ("query", name.clone()),
+        Command::LockThought { id } | Command::UnlockThought { id } => ("lock", id.id.to_string()),
+        _ => ("other", String::new()),
+    }
+}
+
+impl ThoughtGraph {
+    /// The command that reverses a basic command, if it would change anything
+    fn inverse(&self, command: &Command) -> Option<Command> {
+        match command {
+            Command::PutThought { id, .. } => Some(self.put_thought_inverse(id)),
+            Command::DeleteThought { id } => {
+                self.thoughts.get(id).map(|old| Command::PutThought { id: id.clone(), thought: old.clone() })
+            },
+            Command::PutTag { id, .. } => Some(match self.tags.get(id) {
+                Some(old) => Command::PutTag { id: id.clone(), tag: old.clone() },
+                None => Command::DeleteTag { id: id.clone() },
+            }),
+            Command::DeleteTag { id } => self.tags.get(id).map(|old| Command::PutTag { id: id.clone(), tag: old.clone() }),
+            Command::PutTagRule { name, .. } => Some(match self.rules.get(name) {
+                Some(old) => Command::PutTagRule { name: name.clone(), rule: old.clone() },
+                None => Command::DeleteTagRule { name: name.clone() },
+            }),
+            Command::DeleteTagRule { name } => {
+                self.rules.get(name).map(|old| Command::PutTagRule { name: name.clone(), rule: old.clone() })
+            },
+            Command::PutSavedQuery { name, .. } => Some(match self.saved_queries.get(name) {
+                Some(old) => Command::PutSavedQuery { name: name.clone(), query: old.clone() },
+                None => Command::DeleteSavedQuery { name: name.clone() },
+            }),
+            Command::DeleteSavedQuery { name } => {
+                self.saved_queries.get(name).map(|old| Command::PutSavedQuery { name: name.clone(), query: old.clone() })
+            },
+            Command::LockThought { id } => {
+                (self.thoughts.contains_key(id) && !self.locked.contains(id)).then(|| Command::UnlockThought { id: id.clone() })
+            },
+            Command::UnlockThought { id } => self.locked.contains(id).then(|| Command::LockThought { id: id.clone() }),
+            _ => None,
+        }
+    }
+
+    /// The command that reverses storing a thought under `id`
+    fn put_thought_inverse(&self, id: &ThoughtID) -> Command {
+        match self.thoughts.get(id) {
+            Some(old) => Command::PutThought { id: id.clone(), thought: old.clone() },
+            None => Command::DeleteThought { id: id.clone() },
+        }
+    }
+
+    /// Record the command that reverses storing a thought under `id` without a command,
+    /// as `bulk_insert` does, if the graph is recording
+    pub(crate) fn record_put_thought(&mut self, id: &ThoughtID) {
+        if let Some(mut recording) = self.recording.take() {
+            recording.push(self.put_thought_inverse(id));
+            self.recording = Some(recording);
+        }
+    }
+
+    /// Record the command that reverses a basic command about to be applied, if the
+    /// graph is recording
+    pub(crate) fn record_inverse(&mut self, command: &Command) {
+        if let Some(mut recording) = self.recording.take() {
+            recording.extend(self.inverse(command));
+            self.recording = Some(recording);
+        }
+    }
+
+    /// Start recording the changes made to the graph for the journal.
+    ///
+    /// Does nothing if the graph is already recording.
+    pub fn start_recording(&mut self) {
+        self.recording.get_or_insert_with(Vec::new);
+    }
+
+    /// Stop recording, and add the changes made since `start_recording` to the journal
+    /// as one entry, dropping the oldest entries beyond `JOURNAL_LIMIT`.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - What the changes were, as shown by `history`
+    /// * `at` - When the changes were made
+    ///
+    /// # Returns
+    ///
+    /// Whether an entry was added, which it is not if nothing changed
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = ThoughtID::new("idea".to_string());
+    /// graph.start_recording();
+    /// graph.create_thought(id.clone(), None, "An idea".to_string(), vec![], vec![]).unwrap();
+    /// assert!(graph.finish_recording("create idea".to_string(), Utc::now()));
+    /// assert_eq!(graph.history()[0].description, "create idea");
+    ///
+    /// graph.undo(1).unwrap();
+    /// assert!(graph.get_thought(&id).is_none());
+    /// ```
+    pub fn finish_recording(&mut self, description: String, at: DateTime<Utc>) -> bool {
+        let recorded = match self.recording.take() {
+            Some(recorded) if !recorded.is_empty() => recorded,
+            _ => return false,
+        };
+        // The first change to each thing is the one to reverse, which restores it as it
+        // was before all of them
+        let mut seen = HashSet::new();
+        let mut undo: Vec<Command> = recorded.into_iter().filter(|command| seen.insert(undone_item(command))).collect();
+        let activity = undo.iter().flat_map(|command| self.activity(command)).collect();
+        undo.reverse();
+        self.journal.push(JournalEntry { description, at, undo, activity });
+        if self.journal.len() > JOURNAL_LIMIT {
+            self.journal.drain(..self.journal.len() - JOURNAL_LIMIT);
+            self.journal_truncated = true;
+        }
+        true
+    }
+
+    /// What happened to a thought, given the command that restores it as it was before
+    fn activity(&self, undo: &Command) -> Vec<Activity> {
+        let (id, old) = match undo {
+            Command::PutThought { id, thought } => (id, Some(thought)),
+            Command::DeleteThought { id } => (id, None),
+            _ => return Vec::new(),
+        };
+        let (old, new) = match (old, self.thoughts.get(id)) {
+            (None, Some(_)) => return vec![Activity::Created(id.clone())],
+            (Some(_), None) => return vec![Activity::Deleted(id.clone())],
+            (Some(old), Some(new)) => (old, new),
+            (None, None) => return Vec::new(),
+        };
+        let mut activity = Vec::new();
+        if old.title != new.title || old.contents != new.contents || old.references != new.references || old.metadata != new.metadata {
+            activity.push(Activity::Edited(id.clone()));
+        }
+        for tag in new.tags.iter().filter(|tag| !old.tags.contains(tag)) {
+            activity.push(Activity::Tagged(id.clone(), tag.clone()));
+        }
+        for tag in old.tags.iter().filter(|tag| !new.tags.contains(tag)) {
+            activity.push(Activity::Untagged(id.clone(), tag.clone()));
+        }
+        activity
+    }
+
+    /// The entries of the journal, most recent first
+    pub fn history(&self) -> Vec<&JournalEntry> {
+        self.journal.iter().rev().collect()
+    }
+
+    /// Undo the most recent operations in the journal, removing them from it.
+    ///
+    /// Thoughts brought back by undoing their deletion get the backlinks of the thoughts
+    /// that still reference them, and are taken out of the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - How many operations to undo; fewer are undone if the journal is shorter
+    ///
+    /// # Returns
+    ///
+    /// Each operation undone, most recent first, with the changes undoing it made. If an
+    /// operation cannot be undone, for example because a thought it changed has been
+    /// locked since, the error is returned and that operation stays in the journal, while
+    /// the more recent ones stay undone.
+    pub fn undo(&mut self, count: usize) -> Result<Vec<(JournalEntry, ChangeSet)>> {
+        let mut undone = Vec::new();
+        for _ in 0..count {
+            let entry = match self.journal.last() {
+                Some(entry) => entry.clone(),
+                None => break,
+            };
+            let mut changes = self.try_command(&Command::Batch(entry.undo.clone()))?;
+            self.journal.pop();
+            for id in changes.thoughts_created.clone() {
+                self.trash.remove(&id);
+                changes.backlinks_added.extend(self.relink_backreferences(&id));
+            }
+            undone.push((entry, changes));
+        }
+        Ok(undone)
+    }
+
+    /// Reconstruct the graph as it was at a past time, by undoing the journal entries
+    /// made after it on a copy of the graph.
+    ///
+    /// Changes that were not journaled are not undone, except that thoughts created after
+    /// the time are left out. Locks do not prevent undoing the changes to locked thoughts.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - The time to go back to
+    ///
+    /// # Returns
+    ///
+    /// The graph as it was, with the journal up to that time, or `None` if the journal
+    /// has dropped entries since then and so cannot go back that far
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Command, ThoughtGraph, ThoughtID};
+    /// use chrono::{Duration, Utc};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = ThoughtID::new("idea".to_string());
+    /// graph.create_thought(id.clone(), None, "First draft".to_string(), vec![], vec![]).unwrap();
+    /// let before = Utc::now();
+    /// graph.start_recording();
+    /// graph.command(&Command::PatchThought { id: id.clone(), title: None, contents: Some("Second draft".to_string()),
+    ///     add_tags: vec![], remove_tags: vec![], add_references: vec![], remove_references: vec![] });
+    /// graph.finish_recording("edit idea".to_string(), before + Duration::seconds(1));
+    ///
+    /// let past = graph.as_of(before).unwrap();
+    /// assert_eq!(past.get_thought(&id).unwrap().contents, "First draft");
+    /// assert_eq!(graph.get_thought(&id).unwrap().contents, "Second draft");
+    /// ```
+    pub fn as_of(&self, at: DateTime<Utc>) -> Option<ThoughtGraph> {
+        let kept = self.journal.iter().take_while(|entry| entry.at <= at).count();
+        if kept == 0 && self.journal_truncated {
+            return None;
+        }
+
+        let mut past = self.clone();
+        past.recording = None;
+        past.query_cache.clear();
+        let mut created = Vec::new();
+        for entry in past.journal.split_off(kept).into_iter().rev() {
+            for command in entry.undo {
+                created.extend(past.apply_ignoring_locks(command).thoughts_created);
+            }
+        }
+        let later: Vec<ThoughtID> =
+            past.thoughts.iter().filter(|(_, thought)| thought.created_at > at).map(|(id, _)| id.clone()).collect();
+        for id in later {
+            past.apply_ignoring_locks(Command::DeleteThought { id });
+        }
+        for id in created {
+            if past.thoughts.contains_key(&id) {
+                past.trash.remove(&id);
+                past.relink_backreferences(&id);
+            }
+        }
+        Some(past)
+    }
+
+    /// Apply a command, changing a locked thought as if it were not locked
+    fn apply_ignoring_locks(&mut self, command: Command) -> ChangeSet {
+        let id = match &command {
+            Command::PutThought { id, .. } | Command::DeleteThought { id } => id.clone(),
+            _ => return self.apply(command),
+        };
+        let locked = self.locked.remove(&id);
+        let changes = self.apply(command);
+        if locked && self.thoughts.contains_key(&id) {
+            self.locked.insert(id);
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Reference, TagID, Thought, ThoughtGraphError};
+
+    #[test]
+    fn test_undo() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let tag = TagID::new("draft".to_string());
+        graph.create_thought(id("a"), None, "A".to_string(), vec![], vec![]).unwrap();
+        graph
+            .create_thought(id("b"), None, "See [a]".to_string(), vec![], vec![Reference::new(id("a"), String::new(), Utc::now())])
+            .unwrap();
+
+        // Changes made while not recording are not journaled
+        assert!(!graph.finish_recording("nothing".to_string(), Utc::now()));
+        graph.start_recording();
+        assert!(!graph.finish_recording("nothing".to_string(), Utc::now()));
+
+        graph.start_recording();
+        graph.create_tag(tag.clone(), "Drafts".to_string()).unwrap();
+        graph.command(&Command::AddTag { id: id("a"), tag: tag.clone() });
+        graph.command(&Command::RemoveTag { id: id("a"), tag: tag.clone() });
+        graph.command(&Command::AddTag { id: id("a"), tag: tag.clone() });
+        graph.finish_recording("tag a".to_string(), Utc::now());
+        graph.start_recording();
+        graph.trash_thought(&id("a"), Utc::now()).unwrap();
+        graph.finish_recording("delete a".to_string(), Utc::now());
+        let history: Vec<&str> = graph.history().into_iter().map(|entry| entry.description.as_str()).collect();
+        assert_eq!(history, vec!["delete a", "tag a"]);
+        assert_eq!(graph.history()[1].undo.len(), 2);
+        assert_eq!(graph.history()[1].activity, vec![Activity::Tagged(id("a"), tag.clone())]);
+        assert_eq!(graph.history()[0].activity, vec![Activity::Deleted(id("a"))]);
+
+        let undone = graph.undo(1).unwrap();
+        assert_eq!(undone[0].0.description, "delete a");
+        assert_eq!(graph.get_backlinks(&id("a")), vec![id("b")]);
+        assert!(graph.trash.is_empty());
+
+        // The journal is saved with the graph
+        let mut graph = ThoughtGraph::from_bytes(&graph.to_bytes().unwrap()).unwrap();
+        graph.command(&Command::LockThought { id: id("a") });
+        assert!(matches!(graph.undo(1), Err(ThoughtGraphError::ThoughtLocked(_))));
+        assert_eq!(graph.history().len(), 1);
+        graph.command(&Command::UnlockThought { id: id("a") });
+        assert_eq!(graph.undo(5).unwrap().len(), 1);
+        assert!(graph.get_thought(&id("a")).unwrap().tags.is_empty());
+        assert!(!graph.tags.contains_key(&tag));
+        assert!(graph.undo(1).unwrap().is_empty());
+
+        graph.start_recording();
+        for n in 0..JOURNAL_LIMIT + 1 {
+            graph.create_thought(id(&format!("t{}", n)), None, String::new(), vec![], vec![]).unwrap();
+            graph.finish_recording(format!("create t{}", n), Utc::now());
+            graph.start_recording();
+        }
+        assert_eq!(graph.history().len(), JOURNAL_LIMIT);
+        assert_eq!(graph.history()[JOURNAL_LIMIT - 1].description, "create t1");
+        assert_eq!(graph.history()[0].activity, vec![Activity::Created(id(&format!("t{}", JOURNAL_LIMIT)))]);
+    }
+
+    #[test]
+    fn test_as_of() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let start = Utc::now();
+        let day = |n: i64| start + chrono::Duration::days(n);
+        let step = |graph: &mut ThoughtGraph, n: i64, change: &dyn Fn(&mut ThoughtGraph)| {
+            graph.start_recording();
+            change(graph);
+            graph.finish_recording(format!("day {}", n), day(n));
+        };
+
+        graph.create_thought(id("old"), None, "Before the journal".to_string(), vec![], vec![]).unwrap();
+        graph.thoughts.get_mut(&id("old")).unwrap().created_at = day(-1);
+        step(&mut graph, 1, &|graph| {
+            let reference = Reference::new(id("old"), String::new(), Utc::now());
+            graph.create_thought(id("a"), None, "Version 1".to_string(), vec![], vec![reference]).unwrap();
+        });
+        step(&mut graph, 2, &|graph| {
+            graph.command(&Command::PutThought { id: id("a"), thought: Thought::new(None, "Version 2".to_string(), vec![], vec![]) });
+            graph.command(&Command::LockThought { id: id("a") });
+        });
+        step(&mut graph, 3, &|graph| {
+            graph.trash_thought(&id("old"), Utc::now()).unwrap();
+        });
+        let mut unjournaled = Thought::new(None, String::new(), vec![], vec![]);
+        unjournaled.created_at = day(5);
+        unjournaled.updated_at = day(5);
+        graph.apply(Command::PutThought { id: id("unjournaled"), thought: unjournaled });
+
+        let past = graph.as_of(day(1)).unwrap();
+        past.assert_invariants();
+        assert_eq!(past.get_thought(&id("a")).unwrap().contents, "Version 1");
+        assert_eq!(past.get_backlinks(&id("old")), vec![id("a")]);
+        assert!(past.get_thought(&id("unjournaled")).is_none());
+        assert!(past.locked.is_empty() && past.trash.is_empty());
+        assert_eq!(past.history().len(), 1);
+
+        let before = graph.as_of(start).unwrap();
+        assert_eq!(before.thoughts.keys().collect::<Vec<_>>(), vec![&id("old")]);
+        assert_eq!(graph.as_of(day(2)).unwrap().locked, HashSet::from([id("a")]));
+        assert_eq!(graph.get_thought(&id("a")).unwrap().contents, "Version 2");
+
+        // A full journal still reaches back to its first entry, until it drops one
+        let filled = JOURNAL_LIMIT - graph.history().len();
+        for n in 0..JOURNAL_LIMIT {
+            if n == filled {
+                assert!(graph.as_of(start).is_some());
+            }
+            step(&mut graph, 4, &|graph| {
+                graph.create_thought(id(&format!("t{}", n)), None, String::new(), vec![], vec![]).unwrap();
+            });
+        }
+        assert!(graph.as_of(day(3)).is_none());
+        assert_eq!(graph.as_of(day(4)).unwrap().thoughts.len(), graph.thoughts.len() - 1);
+    }
+}
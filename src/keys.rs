@@ -0,0 +1,222 @@
+//! Key bindings of the interactive browser
+//!
+//! `thoughts browse` is driven by single keys and short key sequences in the style of
+//! Vim. The bindings can be changed in the `[browse]` section of the configuration file,
+//! where each action is bound to a sequence of characters or to one of the named keys
+//! `enter`, `backspace`, `escape`, `tab`, `space`, `up`, `down`, `left` and `right`:
+//!
+//! ```toml
+//! [browse]
+//! down = "n"
+//! up = "e"
+//! backlinks = "space"
+//! ```
+//!
+//! The arrow keys, Enter, Backspace and Escape also work as their usual actions unless
+//! they are bound to something else.
+
+use console::Key;
+use serde::Deserialize;
+
+use crate::{Result, ThoughtGraphError};
+
+/// Something the browser can be asked to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrowseAction {
+    /// Move the selection down the list of links
+    Down,
+    /// Move the selection up the list of links
+    Up,
+    /// Go to the selected thought
+    Open,
+    /// Go back to the previously viewed thought
+    Back,
+    /// List the thoughts the current thought references
+    References,
+    /// List the thoughts that reference the current thought
+    Backlinks,
+    /// Search for a thought to go to
+    Search,
+    /// Edit the current thought
+    Edit,
+    /// Leave the browser
+    Quit,
+}
+
+/// Names of the keys that are not characters, as used in bindings
+const NAMED_KEYS: &[&str] = &["enter", "backspace", "escape", "tab", "space", "up", "down", "left", "right"];
+
+/// The keys bound to each action, from the `[browse]` section of the configuration
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct BrowseKeys {
+    pub down: String,
+    pub up: String,
+    pub open: String,
+    pub back: String,
+    pub references: String,
+    pub backlinks: String,
+    pub search: String,
+    pub edit: String,
+    pub quit: String,
+}
+
+impl Default for BrowseKeys {
+    fn default() -> Self {
+        BrowseKeys {
+            down: "j".to_string(),
+            up: "k".to_string(),
+            open: "l".to_string(),
+            back: "h".to_string(),
+            references: "gr".to_string(),
+            backlinks: "gb".to_string(),
+            search: "/".to_string(),
+            edit: "e".to_string(),
+            quit: "q".to_string(),
+        }
+    }
+}
+
+impl BrowseKeys {
+    /// Every binding with its action
+    pub fn bindings(&self) -> [(&str, BrowseAction); 9] {
+        [
+            (&self.down, BrowseAction::Down),
+            (&self.up, BrowseAction::Up),
+            (&self.open, BrowseAction::Open),
+            (&self.back, BrowseAction::Back),
+            (&self.references, BrowseAction::References),
+            (&self.backlinks, BrowseAction::Backlinks),
+            (&self.search, BrowseAction::Search),
+            (&self.edit, BrowseAction::Edit),
+            (&self.quit, BrowseAction::Quit),
+        ]
+        .map(|(binding, action)| (binding.as_str(), action))
+    }
+
+    /// Check that every action can be reached.
+    ///
+    /// # Returns
+    ///
+    /// `ConfigError` if a binding is empty, or if one binding is the same as another or
+    /// starts with another, since the longer one could never be typed
+    pub fn check(&self) -> Result<()> {
+        let bindings = self.bindings();
+        for (binding, action) in &bindings {
+            if binding.is_empty() {
+                return Err(ThoughtGraphError::ConfigError(format!("browse: no key bound to {:?}", action)));
+            }
+            for (other, other_action) in &bindings {
+                let sequences = !NAMED_KEYS.contains(binding) && !NAMED_KEYS.contains(other);
+                if action != other_action && (other == binding || (sequences && other.starts_with(binding))) {
+                    return Err(ThoughtGraphError::ConfigError(format!(
+                        "browse: '{}' for {:?} hides '{}' for {:?}",
+                        binding, action, other, other_action
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns key presses into actions, waiting for the rest of a key sequence when the keys
+/// pressed so far start one
+pub struct KeyMatcher<'a> {
+    keys: &'a BrowseKeys,
+    pending: String,
+}
+
+impl<'a> KeyMatcher<'a> {
+    pub fn new(keys: &'a BrowseKeys) -> Self {
+        KeyMatcher { keys, pending: String::new() }
+    }
+
+    /// The keys pressed so far of an unfinished sequence
+    pub fn pending(&self) -> &str {
+        &self.pending
+    }
+
+    /// Handle a key press, returning the action it completes, if any
+    pub fn press(&mut self, key: Key) -> Option<BrowseAction> {
+        let name = match key {
+            Key::Char(' ') => Some("space"),
+            Key::Char(_) => None,
+            Key::Enter => Some("enter"),
+            Key::Backspace => Some("backspace"),
+            Key::Escape => Some("escape"),
+            Key::Tab => Some("tab"),
+            Key::ArrowUp => Some("up"),
+            Key::ArrowDown => Some("down"),
+            Key::ArrowLeft => Some("left"),
+            Key::ArrowRight => Some("right"),
+            _ => return None,
+        };
+        if let Some(name) = name {
+            if let Some((_, action)) = self.keys.bindings().into_iter().find(|(binding, _)| *binding == name) {
+                self.pending.clear();
+                return Some(action);
+            }
+        }
+
+        let c = match key {
+            Key::Char(c) => c,
+            key => {
+                self.pending.clear();
+                return match key {
+                    Key::ArrowDown => Some(BrowseAction::Down),
+                    Key::ArrowUp => Some(BrowseAction::Up),
+                    Key::Enter | Key::ArrowRight => Some(BrowseAction::Open),
+                    Key::Backspace | Key::ArrowLeft => Some(BrowseAction::Back),
+                    Key::Escape => Some(BrowseAction::Quit),
+                    _ => None,
+                };
+            },
+        };
+
+        self.pending.push(c);
+        while !self.pending.is_empty() {
+            let bindings = self.keys.bindings();
+            if let Some((_, action)) = bindings.iter().find(|(binding, _)| *binding == self.pending) {
+                self.pending.clear();
+                return Some(*action);
+            }
+            if bindings.iter().any(|(binding, _)| binding.starts_with(self.pending.as_str())) {
+                return None;
+            }
+            // No binding starts this way, so start again from the next key
+            self.pending.remove(0);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_matcher() {
+        let keys = BrowseKeys { quit: "escape".to_string(), ..BrowseKeys::default() };
+        keys.check().unwrap();
+        let mut matcher = KeyMatcher::new(&keys);
+
+        assert_eq!(matcher.press(Key::Char('j')), Some(BrowseAction::Down));
+        assert_eq!(matcher.press(Key::Char('g')), None);
+        assert_eq!(matcher.pending(), "g");
+        assert_eq!(matcher.press(Key::Char('b')), Some(BrowseAction::Backlinks));
+        // An unknown sequence is dropped, and the next key starts again
+        assert_eq!(matcher.press(Key::Char('g')), None);
+        assert_eq!(matcher.press(Key::Char('k')), Some(BrowseAction::Up));
+        assert_eq!(matcher.press(Key::Char('x')), None);
+        assert_eq!(matcher.pending(), "");
+        assert_eq!(matcher.press(Key::ArrowDown), Some(BrowseAction::Down));
+        assert_eq!(matcher.press(Key::Escape), Some(BrowseAction::Quit));
+        assert_eq!(matcher.press(Key::Char('q')), None);
+
+        let keys = BrowseKeys { back: "g".to_string(), ..BrowseKeys::default() };
+        assert!(matches!(keys.check(), Err(ThoughtGraphError::ConfigError(_))));
+        let keys = BrowseKeys { edit: String::new(), ..BrowseKeys::default() };
+        assert!(matches!(keys.check(), Err(ThoughtGraphError::ConfigError(_))));
+    }
+}
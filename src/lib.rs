@@ -13,7 +13,11 @@
 //! - **Tagging System**: Organize thoughts with customizable tags
 //! - **Flexible Queries**: Search for thoughts using complex boolean expressions
 //! - **Command-based Modifications**: Modify the graph via a command interface
-//! - **Persistence**: Store and retrieve graph data using Serde and Bincode serialization
+//! - **Transclusion**: Assemble documents by embedding thoughts with `{{embed:thought-id}}`
+//! - **Tagging Rules**: Tag thoughts automatically when they match a pattern
+//! - **Text Search**: Find thoughts by words in their titles and content, optionally
+//!   in parallel with the `parallel` feature
+//! - **Persistence**: Store and retrieve graph data in a versioned, forward-compatible file format
 //!
 //! ## Example
 //!
@@ -83,14 +87,95 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::fs;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io;
+#[cfg(not(target_arch = "wasm32"))]
 use std::path::Path;
+use std::sync::Arc;
 use thiserror::Error;
+#[cfg(not(target_arch = "wasm32"))]
+use progress::ProgressFn;
+use traverse::Direction;
 
 pub mod visualization;
+#[cfg(feature = "cli")]
 pub mod ui;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod hooks;
+#[cfg(feature = "cli")]
+pub mod keys;
+#[cfg(feature = "cli")]
+pub mod dates;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod transclusion;
+pub mod links;
+pub mod text;
+pub mod wordcloud;
+pub mod suggest;
+pub mod rules;
+pub mod rank;
+pub mod saved;
+pub mod smart;
+pub mod syntax;
+pub mod template;
+pub mod locked;
+pub mod inbox;
+pub mod duplicate;
+pub mod broken;
+pub mod trash;
+pub mod journal;
+pub mod input;
+pub mod bibliography;
+pub mod publish;
+pub mod footnotes;
+pub mod diff;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+#[cfg(feature = "epub")]
+pub mod epub;
+pub mod semantic;
+pub mod topics;
+pub mod resurface;
+pub mod canvas;
+pub mod cache;
+pub mod explain;
+pub mod matcher;
+pub mod search;
+pub mod fuzzy;
+pub mod namespace;
+pub mod sort;
+pub mod stats;
+pub mod health;
+pub mod hubs;
+pub mod validate;
+#[cfg(debug_assertions)]
+pub mod invariants;
+pub mod changes;
+pub mod traverse;
+pub mod hierarchy;
+pub mod taxonomy;
+pub mod export;
+pub mod ndjson;
+pub mod progress;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod storage;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod snapshot;
+#[cfg(feature = "llm")]
+pub mod llm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod format;
+mod intern;
+mod parallel;
+
+pub use changes::ChangeSet;
 
 /// Error types for ThoughtGraph operations
 #[derive(Error, Debug)]
@@ -107,28 +192,96 @@ pub enum ThoughtGraphError {
     #[error("Tag not found: {0}")]
     TagNotFound(String),
     
+    #[error("Saved query not found: {0}")]
+    SavedQueryNotFound(String),
+    
+    #[error("Snapshot not found: {0}")]
+    SnapshotNotFound(String),
+    
+    #[error("Thought is locked: {0} (unlock it to change it)")]
+    ThoughtLocked(String),
+    
     #[error("Invalid thought ID: {0}")]
     InvalidThoughtID(String),
     
+    #[error("Invalid tag ID: {0}")]
+    InvalidTagID(String),
+    
     #[error("External editor error: {0}")]
     EditorError(String),
+    
+    #[error("Transclusion cycle detected: {0}")]
+    TransclusionCycle(String),
+    
+    #[error("Hierarchy cycle detected: {0}")]
+    HierarchyCycle(String),
+    
+    #[error("Smart tag cycle detected: {0}")]
+    SmartTagCycle(String),
+    
+    #[error("Graph file is locked by another program: {0}")]
+    FileLocked(String),
+    
+    #[error("Graph is encrypted: {0}")]
+    Encrypted(String),
+    
+    #[cfg(feature = "encryption")]
+    #[error("Could not decrypt the graph: {0}")]
+    DecryptionFailed(String),
+    
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+    
+    #[error("Encoding error: {0}")]
+    EncodingError(String),
+    
+    #[error("Parse error: {0}")]
+    ParseError(String),
+    
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+    
+    #[error("Hook failed: {0}")]
+    HookFailed(String),
+    
+    #[cfg(feature = "llm")]
+    #[error("Language model error: {0}")]
+    LlmError(String),
 }
 
 /// Result type for ThoughtGraph operations
 pub type Result<T> = std::result::Result<T, ThoughtGraphError>;
 
+/// Characters that may not appear in thought and tag IDs, besides whitespace and control
+/// characters
+const RESERVED_ID_CHARACTERS: &[char] = &['[', ']', '{', '}', '"'];
+
+/// Check that a string is usable as a thought or tag ID, describing the problem if not
+fn check_id(id: &str) -> std::result::Result<(), String> {
+    if id.is_empty() {
+        return Err("ID must not be empty".to_string());
+    }
+    match id.chars().find(|c| c.is_whitespace() || c.is_control() || RESERVED_ID_CHARACTERS.contains(c)) {
+        Some(c) => Err(format!("'{}' contains the character {:?}", id, c)),
+        None => Ok(()),
+    }
+}
+
 /// Unique identifier for a thought in the graph.
 ///
 /// Each thought has a unique string identifier that is used to reference it within the graph.
 /// This ID is used for creating references between thoughts and for querying the graph.
-#[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
 pub struct ThoughtID {
-    /// The unique string identifier
-    pub id: String,
+    /// The unique string identifier, shared between all copies of the ID
+    pub id: Arc<str>,
 }
 
 impl ThoughtID {
-    /// Creates a new ThoughtID with the given string identifier.
+    /// Creates a new ThoughtID with the given string identifier, in Unicode normalization
+    /// form C so that an ID typed with combining accents finds the same thought as one
+    /// typed with precomposed characters.
     ///
     /// # Arguments
     ///
@@ -142,7 +295,91 @@ impl ThoughtID {
     /// let thought_id = ThoughtID::new("unique-thought-123".to_string());
     /// ```
     pub fn new(id: String) -> Self {
-        Self { id }
+        match text::normalize(&id) {
+            Cow::Borrowed(_) => Self { id: id.into() },
+            Cow::Owned(normalized) => Self { id: normalized.into() },
+        }
+    }
+
+    /// Returns the identifier as a string slice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thoughtgraph::ThoughtID;
+    ///
+    /// let thought_id = ThoughtID::new("unique-thought-123".to_string());
+    /// assert_eq!(thought_id.as_str(), "unique-thought-123");
+    /// ```
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+
+    /// Creates a new ThoughtID after checking that it is well-formed.
+    ///
+    /// IDs must be non-empty and may contain any characters except whitespace, control
+    /// characters, square brackets, curly braces and double quotes. Square brackets mark
+    /// automatic references, curly braces mark embeds, and quotes would break the
+    /// visualization output.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier to check
+    ///
+    /// # Returns
+    ///
+    /// The ID, or `InvalidThoughtID` describing the problem
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thoughtgraph::ThoughtID;
+    ///
+    /// assert!(ThoughtID::parse("meeting-notes_2024").is_ok());
+    /// assert!(ThoughtID::parse("meeting notes").is_err());
+    /// assert!(ThoughtID::parse("[meeting]").is_err());
+    /// ```
+    pub fn parse(id: &str) -> Result<Self> {
+        check_id(id).map_err(ThoughtGraphError::InvalidThoughtID)?;
+        Ok(Self::new(id.to_string()))
+    }
+}
+
+// IDs are normalized when they are read too, so that IDs saved by older versions or
+// written by hand in JSON are found by lookups of the normalized form
+impl<'de> Deserialize<'de> for ThoughtID {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::new)
+    }
+}
+
+/// The kind of relationship a reference expresses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ReferenceKind {
+    /// The thoughts are related in some way (the default)
+    #[default]
+    Related,
+    /// The referenced thought is a parent of the referencing thought.
+    ///
+    /// Parent references arrange thoughts in a hierarchy; see the `hierarchy` module.
+    Parent,
+    /// The referenced thought comes after the referencing thought.
+    ///
+    /// Next references put thoughts in a reading order, such as the sections of an essay;
+    /// see `ThoughtGraph::compile`.
+    Next,
+}
+
+impl std::str::FromStr for ReferenceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "related" => Ok(ReferenceKind::Related),
+            "parent" => Ok(ReferenceKind::Parent),
+            "next" => Ok(ReferenceKind::Next),
+            _ => Err(format!("unknown reference kind '{}' (expected 'related', 'parent' or 'next')", s)),
+        }
     }
 }
 
@@ -151,7 +388,7 @@ impl ThoughtID {
 /// References create connections between thoughts, establishing a graph-like structure.
 /// Each reference includes the target thought's ID, optional notes about the relationship,
 /// and a timestamp for when the reference was created or last accessed.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Reference {
     /// ID of the thought being referenced
     pub id: ThoughtID,
@@ -159,6 +396,9 @@ pub struct Reference {
     pub notes: String,
     /// When this reference was created or last accessed
     pub access_date: DateTime<Utc>,
+    /// The kind of relationship
+    #[serde(default)]
+    pub kind: ReferenceKind,
 }
 
 impl Reference {
@@ -184,7 +424,24 @@ impl Reference {
     /// );
     /// ```
     pub fn new(id: ThoughtID, notes: String, access_date: DateTime<Utc>) -> Self {
-        Self { id, notes, access_date }
+        Self { id, notes, access_date, kind: ReferenceKind::default() }
+    }
+    
+    /// Returns the reference with the given kind.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thoughtgraph::{Reference, ReferenceKind, ThoughtID};
+    /// use chrono::Utc;
+    ///
+    /// let reference = Reference::new(ThoughtID::new("chapter".to_string()), String::new(), Utc::now())
+    ///     .with_kind(ReferenceKind::Parent);
+    /// assert_eq!(reference.kind, ReferenceKind::Parent);
+    /// ```
+    pub fn with_kind(mut self, kind: ReferenceKind) -> Self {
+        self.kind = kind;
+        self
     }
 }
 
@@ -193,7 +450,7 @@ impl Reference {
 /// Thoughts are the primary nodes in the ThoughtGraph system. Each thought can have
 /// a title, content text, associated tags for categorization, and references to other 
 /// thoughts, creating a web of interconnected knowledge.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Thought {
     /// Optional title for the thought
     pub title: Option<String>,
@@ -203,6 +460,9 @@ pub struct Thought {
     pub tags: Vec<TagID>,
     /// References to other thoughts, creating connections in the graph
     pub references: Vec<Reference>,
+    /// Named values describing the thought, such as the author and year of a source
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
     /// Creation timestamp
     pub created_at: DateTime<Utc>,
     /// Last modified timestamp
@@ -249,11 +509,21 @@ impl Thought {
             contents, 
             tags, 
             references,
+            metadata: BTreeMap::new(),
             created_at: now,
             updated_at: now,
         }
     }
     
+    /// Sets a metadata value, replacing any previous value for the key, and updates the
+    /// modified timestamp if the value changed
+    pub fn set_metadata(&mut self, key: String, value: String) {
+        if self.metadata.get(&key) != Some(&value) {
+            self.metadata.insert(key, value);
+            self.updated_at = Utc::now();
+        }
+    }
+    
     /// Updates the content of the thought and its modified timestamp
     pub fn update_content(&mut self, new_content: String) {
         self.contents = new_content;
@@ -310,9 +580,10 @@ impl Thought {
     /// This supports the auto-reference feature which allows creating connections between
     /// thoughts by simply mentioning their IDs in square brackets.
     ///
-    /// The regex pattern matches alphanumeric characters, underscores, and hyphens between
-    /// square brackets. For example, `[my-thought-123]` would be extracted as a reference
-    /// to the thought with ID "my-thought-123".
+    /// The regex pattern matches letters and digits in any script, underscores, hyphens,
+    /// and namespace separators (`/`) between square brackets. For example, `[my-thought-123]`
+    /// would be extracted as a reference to the thought with ID "my-thought-123", and
+    /// `[projects/alpha]` as a reference to "projects/alpha".
     ///
     /// # Returns
     ///
@@ -332,12 +603,12 @@ impl Thought {
     ///
     /// let refs = thought.extract_references_from_content();
     /// assert_eq!(refs.len(), 2);
-    /// assert_eq!(refs[0].id, "thought1");
-    /// assert_eq!(refs[1].id, "another-thought-2");
+    /// assert_eq!(refs[0].as_str(), "thought1");
+    /// assert_eq!(refs[1].as_str(), "another-thought-2");
     /// ```
     pub fn extract_references_from_content(&self) -> Vec<ThoughtID> {
         let mut found_refs = Vec::new();
-        let re = regex::Regex::new(r"\[([a-zA-Z0-9_-]+)\]").unwrap();
+        let re = regex::Regex::new(r"\[([\w/-]+)\]").unwrap();
         
         for cap in re.captures_iter(&self.contents) {
             if let Some(thought_id) = cap.get(1) {
@@ -353,14 +624,16 @@ impl Thought {
 ///
 /// Tags are used to categorize and group thoughts. Each tag has a unique string identifier
 /// that is used to reference it within the graph.
-#[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
 pub struct TagID {
-    /// The unique string identifier for the tag
-    pub id: String,
+    /// The unique string identifier for the tag, shared between all copies of the ID
+    pub id: Arc<str>,
 }
 
 impl TagID {
-    /// Creates a new TagID with the given string identifier.
+    /// Creates a new TagID with the given string identifier, in Unicode normalization
+    /// form C (see `ThoughtID::new`).
     ///
     /// # Arguments
     ///
@@ -374,7 +647,55 @@ impl TagID {
     /// let tag_id = TagID::new("concept".to_string());
     /// ```
     pub fn new(id: String) -> Self {
-        Self { id }
+        match text::normalize(&id) {
+            Cow::Borrowed(_) => Self { id: id.into() },
+            Cow::Owned(normalized) => Self { id: normalized.into() },
+        }
+    }
+
+    /// Returns the identifier as a string slice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thoughtgraph::TagID;
+    ///
+    /// let tag_id = TagID::new("concept".to_string());
+    /// assert_eq!(tag_id.as_str(), "concept");
+    /// ```
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+
+    /// Creates a new TagID after checking that it is well-formed.
+    ///
+    /// Tag IDs follow the same rules as thought IDs (see `ThoughtID::parse`).
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier to check
+    ///
+    /// # Returns
+    ///
+    /// The ID, or `InvalidTagID` describing the problem
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thoughtgraph::TagID;
+    ///
+    /// assert!(TagID::parse("machine-learning").is_ok());
+    /// assert!(TagID::parse("").is_err());
+    /// ```
+    pub fn parse(id: &str) -> Result<Self> {
+        check_id(id).map_err(ThoughtGraphError::InvalidTagID)?;
+        Ok(Self::new(id.to_string()))
+    }
+}
+
+impl<'de> Deserialize<'de> for TagID {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::new)
     }
 }
 
@@ -383,10 +704,23 @@ impl TagID {
 /// Tags provide a way to categorize and group related thoughts. Each tag has
 /// a description that explains what the tag represents and what kinds of thoughts
 /// it should be applied to.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Tag {
     /// Description of what this tag represents
     pub description: String,
+    /// The broader tag this tag is a kind of, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<TagID>,
+    /// The color to show the tag in, such as "red"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Other names the tag is known by, such as abbreviations
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// The query of a smart tag, which stands for the thoughts the query finds instead
+    /// of the thoughts it is assigned to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<Query>,
     /// Creation timestamp
     pub created_at: DateTime<Utc>,
     /// Last updated timestamp
@@ -411,6 +745,10 @@ impl Tag {
         let now = Utc::now();
         Self { 
             description,
+            parent: None,
+            color: None,
+            aliases: Vec::new(),
+            query: None,
             created_at: now,
             updated_at: now,
         }
@@ -437,18 +775,59 @@ pub struct ThoughtGraph {
     /// Map of thought IDs to thoughts
     pub thoughts: HashMap<ThoughtID, Thought>,
     /// Map of thought IDs to thoughts that reference them (inverse index of references)
-    pub backreferences: HashMap<ThoughtID, Vec<ThoughtID>>,
+    pub backreferences: HashMap<ThoughtID, HashSet<ThoughtID>>,
     /// Map of tag IDs to tags
     pub tags: HashMap<TagID, Tag>,
+    /// Named rules that tag thoughts automatically
+    #[serde(default)]
+    pub rules: HashMap<String, rules::TagRule>,
+    /// Named queries, run with `ThoughtGraph::run_saved_query`
+    #[serde(default)]
+    pub saved_queries: HashMap<String, Query>,
+    /// Thoughts that may not be replaced or deleted until they are unlocked
+    #[serde(default)]
+    pub locked: HashSet<ThoughtID>,
+    /// Deleted thoughts that can still be restored
+    #[serde(default)]
+    pub trash: HashMap<ThoughtID, trash::TrashedThought>,
+    /// Recent operations, with the commands that undo them
+    #[serde(default)]
+    pub journal: Vec<journal::JournalEntry>,
+    /// Whether the oldest entries of the journal have been dropped
+    #[serde(default)]
+    pub journal_truncated: bool,
+    /// Commands that undo the changes made since recording started, if it has
+    #[serde(skip)]
+    pub(crate) recording: Option<Vec<Command>>,
+    /// Map of external URLs to the thoughts that mention them (derived from contents)
+    #[serde(skip)]
+    pub(crate) link_index: links::LinkIndex,
+    /// Map of tag IDs to the thoughts that have them (inverse index of thought tags)
+    #[serde(skip)]
+    pub(crate) tag_index: HashMap<TagID, HashSet<ThoughtID>>,
+    /// Cache of recent query results (disabled unless enabled explicitly)
+    #[serde(skip)]
+    pub(crate) query_cache: cache::QueryCache,
 }
 
 /// Query operations for retrieving thoughts from the graph.
 ///
 /// The `Query` enum provides a flexible way to search for thoughts in the graph.
-/// Queries can be combined using logical AND and OR operations to create complex
+/// Queries can be combined using logical AND, OR and NOT operations to create complex
 /// search criteria.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Query {
+    /// Find every thought.
+    ///
+    /// Useful as the start of a negation, `And([All, Not(Tag(x))])`, or to list every
+    /// thought through the query engine.
+    All,
+    
+    /// Find the thought with the given ID, if it exists.
+    ///
+    /// Combined with `Or`, this makes sure a particular thought is in a result.
+    Id(ThoughtID),
+    
     /// Find thoughts with the given tag.
     ///
     /// Returns all thoughts that have the specified tag.
@@ -475,6 +854,78 @@ pub enum Query {
     ///
     /// Returns thoughts that match ANY of the subqueries.
     Or(Vec<Box<Query>>),
+
+    /// Find thoughts reachable from the given thought by following references, up to
+    /// `depth` references away (`usize::MAX` for no limit).
+    ///
+    /// The starting thought itself is not included, so with a depth of 1 this is
+    /// `ReferencedBy`; combine it with `Id` to select a whole cluster with its root.
+    ReachableFrom { id: ThoughtID, depth: usize },
+    
+    /// Find thoughts from which the given thought can be reached by following
+    /// references, up to `depth` references away (`usize::MAX` for no limit).
+    ///
+    /// The target itself is not included, so with a depth of 1 this is `References`.
+    ReachableTo { id: ThoughtID, depth: usize },
+    
+    /// Find thoughts whose title contains the given text.
+    ///
+    /// Text is matched as `search` matches it, ignoring case and accents; untitled
+    /// thoughts never match.
+    TitleContains(String),
+    
+    /// Find thoughts whose contents contain the given text.
+    ///
+    /// Text is matched as `search` matches it, ignoring case and accents.
+    ContentContains(String),
+    
+    /// Find thoughts whose title matches the given regular expression.
+    ///
+    /// A pattern that does not compile matches nothing; build the query with
+    /// `Query::title_regex` or `Query::parse` to be told about it instead.
+    TitleRegex(String),
+    
+    /// Find thoughts whose contents match the given regular expression, such as
+    /// `TODO|FIXME` or `[A-Z]+-\d+` for ticket numbers.
+    ///
+    /// A pattern that does not compile matches nothing; build the query with
+    /// `Query::content_regex` or `Query::parse` to be told about it instead.
+    ContentRegex(String),
+    
+    /// Find thoughts with a reference whose notes contain the given text, such as all
+    /// the thoughts with a link annotated "contradicts".
+    ///
+    /// Text is matched as `search` matches it, ignoring case and accents.
+    ReferenceNotesContain(String),
+    
+    /// Find thoughts with a tag whose ID matches a glob, in which `*` stands for any run
+    /// of characters, such as `project/*`.
+    TagGlob(String),
+    
+    /// Find thoughts whose ID matches a glob, in which `*` stands for any run of
+    /// characters, such as `book-chapter-*`.
+    IdGlob(String),
+    
+    /// Find thoughts without any tags.
+    Untagged,
+    
+    /// Find thoughts that neither reference another thought nor are referenced by one,
+    /// the orphans of `ThoughtGraph::health`.
+    Orphan,
+    
+    /// Find thoughts referenced by at least the given number of thoughts, such as the
+    /// hubs of the graph.
+    MinBacklinks(usize),
+    
+    /// Find thoughts referenced by at most the given number of thoughts, such as the
+    /// barely linked thoughts that are yet to be connected to the rest.
+    MaxBacklinks(usize),
+    
+    /// Logical NOT of a query.
+    ///
+    /// Returns every thought in the graph that does not match the subquery, so
+    /// `And([Tag(project), Not(Tag(archived))])` finds the projects that are not archived.
+    Not(Box<Query>),
 }
 
 /// Commands for modifying the graph.
@@ -508,6 +959,148 @@ pub enum Command {
     /// will continue to do so, but the tag will be treated as non-existent
     /// for query purposes.
     DeleteTag { id: TagID },
+    
+    /// Add or update a named tag rule.
+    ///
+    /// If a rule with the given name already exists, it will be replaced.
+    /// Rules are applied by `ThoughtGraph::apply_tag_rules`, not by this command.
+    PutTagRule { name: String, rule: rules::TagRule },
+    
+    /// Remove a tag rule from the graph.
+    ///
+    /// Tags previously applied by the rule stay on their thoughts.
+    DeleteTagRule { name: String },
+    
+    /// Add or update a named query.
+    ///
+    /// If a query with the given name already exists, it will be replaced.
+    PutSavedQuery { name: String, query: Query },
+    
+    /// Remove a named query from the graph.
+    DeleteSavedQuery { name: String },
+    
+    /// Protect a thought from being replaced, changed or deleted.
+    ///
+    /// Commands against a locked thought have no effect, and `ThoughtGraph::try_command`
+    /// rejects them. Locking a thought that does not exist does nothing.
+    LockThought { id: ThoughtID },
+    
+    /// Allow a locked thought to be changed again.
+    UnlockThought { id: ThoughtID },
+    
+    /// Update some fields of an existing thought, leaving the others unchanged.
+    ///
+    /// Fields that are `None` or empty are not touched. Tags are removed before tags are
+    /// added, and likewise for references; adding a reference to a thought that is
+    /// already referenced replaces that reference. The thought's modification time is
+    /// updated. Patching a thought that does not exist does nothing.
+    PatchThought {
+        id: ThoughtID,
+        /// The new title; `Some(None)` removes the title
+        #[serde(default)]
+        title: Option<Option<String>>,
+        /// The new content
+        #[serde(default)]
+        contents: Option<String>,
+        /// Tags to add
+        #[serde(default)]
+        add_tags: Vec<TagID>,
+        /// Tags to remove
+        #[serde(default)]
+        remove_tags: Vec<TagID>,
+        /// References to add
+        #[serde(default)]
+        add_references: Vec<Reference>,
+        /// IDs of the thoughts whose references should be removed
+        #[serde(default)]
+        remove_references: Vec<ThoughtID>,
+    },
+    
+    /// Tag a thought; does nothing if the thought already has the tag
+    AddTag { id: ThoughtID, tag: TagID },
+    
+    /// Remove a tag from a thought; does nothing if the thought does not have the tag
+    RemoveTag { id: ThoughtID, tag: TagID },
+    
+    /// Add a reference to a thought, replacing any reference it has to the same target
+    AddReference { id: ThoughtID, reference: Reference },
+    
+    /// Remove a thought's references to the `target` thought
+    RemoveReference { id: ThoughtID, target: ThoughtID },
+    
+    /// Apply several commands in order, as one logical change.
+    ///
+    /// Operations made of several steps, such as moving thoughts, are expressed as a batch
+    /// so that they are recorded and validated as a whole. `ThoughtGraph::try_command`
+    /// rejects the whole batch if any of its commands would be rejected at the point
+    /// where it is applied.
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    /// Express the command in terms of the basic commands that `apply` implements.
+    ///
+    /// Commands that change part of a thought become a `PutThought` of the whole changed
+    /// thought, so that only `PutThought` needs to maintain the backreferences and
+    /// indexes. Returns `None` if the command has no effect on the graph.
+    pub(crate) fn resolve(self, graph: &ThoughtGraph) -> Option<Command> {
+        match self {
+            Command::PatchThought { id, title, contents, add_tags, remove_tags, add_references, remove_references } => {
+                let mut thought = graph.thoughts.get(&id)?.clone();
+                if let Some(title) = title {
+                    thought.title = title;
+                }
+                if let Some(contents) = contents {
+                    thought.contents = contents;
+                }
+                thought.tags.retain(|tag| !remove_tags.contains(tag));
+                for tag in add_tags {
+                    thought.add_tag(tag);
+                }
+                thought.references.retain(|r| !remove_references.contains(&r.id));
+                for reference in add_references {
+                    thought.references.retain(|r| r.id != reference.id);
+                    thought.references.push(reference);
+                }
+                thought.updated_at = Utc::now();
+                Some(Command::PutThought { id, thought })
+            },
+            Command::AddTag { id, tag } => {
+                let thought = graph.thoughts.get(&id)?;
+                if thought.tags.contains(&tag) {
+                    return None;
+                }
+                let mut thought = thought.clone();
+                thought.add_tag(tag);
+                Some(Command::PutThought { id, thought })
+            },
+            Command::RemoveTag { id, tag } => {
+                let thought = graph.thoughts.get(&id)?;
+                if !thought.tags.contains(&tag) {
+                    return None;
+                }
+                let mut thought = thought.clone();
+                thought.remove_tag(&tag);
+                Some(Command::PutThought { id, thought })
+            },
+            Command::AddReference { id, reference } => {
+                let mut thought = graph.thoughts.get(&id)?.clone();
+                thought.references.retain(|r| r.id != reference.id);
+                thought.add_reference(reference);
+                Some(Command::PutThought { id, thought })
+            },
+            Command::RemoveReference { id, target } => {
+                let thought = graph.thoughts.get(&id)?;
+                if !thought.references.iter().any(|r| r.id == target) {
+                    return None;
+                }
+                let mut thought = thought.clone();
+                thought.remove_references_to(&target);
+                Some(Command::PutThought { id, thought })
+            },
+            command => Some(command),
+        }
+    }
 }
 
 impl ThoughtGraph {
@@ -533,10 +1126,16 @@ impl ThoughtGraph {
     /// It handles all the necessary updates to maintain consistency, particularly
     /// with backreferences when thoughts are added, updated, or removed.
     ///
+    /// The command is cloned before it is applied; use `apply()` to hand it over instead.
+    ///
     /// # Arguments
     ///
     /// * `command` - The command to apply to the graph
     ///
+    /// # Returns
+    ///
+    /// A `ChangeSet` describing what the command changed
+    ///
     /// # Examples
     ///
     /// ```
@@ -558,69 +1157,279 @@ impl ThoughtGraph {
     /// });
     ///
     /// // Delete the thought
-    /// graph.command(&Command::DeleteThought { id: thought_id });
+    /// let changes = graph.command(&Command::DeleteThought { id: thought_id.clone() });
+    /// assert_eq!(changes.thoughts_deleted, vec![thought_id]);
+    /// ```
+    pub fn command(&mut self, command: &Command) -> ChangeSet {
+        self.apply(command.clone())
+    }
+
+    /// Apply a command to modify the graph, taking ownership of it.
+    ///
+    /// This behaves exactly like `command()`, but moves the thought, tag or rule carried
+    /// by the command into the graph instead of cloning it. Prefer it when the command is
+    /// not needed afterwards, for example when importing many thoughts at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to apply to the graph
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Thought, Command};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// for i in 0..3 {
+    ///     let thought = Thought::new(None, format!("Thought {}", i), vec![], vec![]);
+    ///     graph.apply(Command::PutThought { id: ThoughtID::new(format!("t{}", i)), thought });
+    /// }
+    /// assert_eq!(graph.list_thoughts().len(), 3);
     /// ```
-    pub fn command(&mut self, command: &Command) {
+    pub fn apply(&mut self, command: Command) -> ChangeSet {
+        let mut changes = ChangeSet::default();
+        let command = match command.resolve(self) {
+            Some(command) => command,
+            None => return changes,
+        };
+        
+        if let Command::Batch(commands) = command {
+            for command in commands {
+                changes.extend(self.apply(command));
+            }
+            return changes;
+        }
+        
+        // Locked thoughts stay as they are, even for callers that skip validation
+        if let Command::PutThought { id, .. } | Command::DeleteThought { id } = &command {
+            if self.locked.contains(id) {
+                return changes;
+            }
+        }
+        
+        if self.query_cache.is_enabled() {
+            self.query_cache.invalidate(self, &command);
+        }
+        self.record_inverse(&command);
+        
         match command {
-            Command::PutThought { id, thought } => {
+            Command::PutThought { mut id, mut thought } => {
+                // Share the ID strings with the copies already stored in the graph
+                intern::intern(&self.thoughts, &mut id);
+                intern::intern_thought(&self.thoughts, &self.tags, &mut thought);
+                for reference in &mut thought.references {
+                    // Targets that do not exist yet may still have backreferences
+                    if !self.thoughts.contains_key(&reference.id) {
+                        intern::intern(&self.backreferences, &mut reference.id);
+                    }
+                }
+                
                 // First, update backreferences
                 // Remove old backreferences if this thought already exists
-                if let Some(old_thought) = self.thoughts.get(id) {
-                    for reference in &old_thought.references {
-                        if let Some(backrefs) = self.backreferences.get_mut(&reference.id) {
-                            backrefs.retain(|ref_id| ref_id != id);
-                            // Clean up empty backreference entries
-                            if backrefs.is_empty() {
-                                self.backreferences.remove(&reference.id);
-                            }
-                        }
+                let old_targets: HashSet<ThoughtID> = match self.thoughts.remove(&id) {
+                    Some(old_thought) => {
+                        self.unindex_thought(&id, &old_thought);
+                        changes.thoughts_updated.push(id.clone());
+                        old_thought.references.into_iter().map(|r| r.id).collect()
+                    },
+                    None => {
+                        changes.thoughts_created.push(id.clone());
+                        HashSet::new()
+                    },
+                };
+                let new_targets: HashSet<&ThoughtID> = thought.references.iter().map(|r| &r.id).collect();
+                for target in &old_targets {
+                    if !new_targets.contains(target) {
+                        changes.backlinks_removed.push((id.clone(), target.clone()));
                     }
                 }
-                
-                // Add new backreferences
+                let mut seen = HashSet::new();
                 for reference in &thought.references {
-                    self.backreferences
-                        .entry(reference.id.clone())
-                        .or_default()
-                        .push(id.clone());
+                    if !old_targets.contains(&reference.id) && seen.insert(&reference.id) {
+                        changes.backlinks_added.push((id.clone(), reference.id.clone()));
+                    }
                 }
                 
-                // Now insert or update the thought
-                self.thoughts.insert(id.clone(), thought.clone());
+                // Add new backreferences, then insert or update the thought
+                self.index_thought(&id, &thought);
+                self.thoughts.insert(id, thought);
             },
             
             Command::DeleteThought { id } => {
                 // First, remove backreferences created by this thought
-                if let Some(thought) = self.thoughts.get(id) {
-                    for reference in &thought.references {
-                        if let Some(backrefs) = self.backreferences.get_mut(&reference.id) {
-                            backrefs.retain(|ref_id| ref_id != id);
-                            // Clean up empty backreference entries
-                            if backrefs.is_empty() {
-                                self.backreferences.remove(&reference.id);
-                            }
-                        }
-                    }
+                if let Some(thought) = self.thoughts.remove(&id) {
+                    self.unindex_thought(&id, &thought);
+                    let targets: HashSet<&ThoughtID> = thought.references.iter().map(|r| &r.id).collect();
+                    changes.backlinks_removed.extend(targets.into_iter().map(|target| (id.clone(), target.clone())));
+                    changes.thoughts_deleted.push(id.clone());
                 }
                 
-                // Remove the thought itself
-                self.thoughts.remove(id);
-                
                 // Remove any backreferences to this thought
-                self.backreferences.remove(id);
+                if let Some(sources) = self.backreferences.remove(&id) {
+                    changes.backlinks_removed.extend(sources.into_iter().map(|source| (source, id.clone())));
+                }
             },
             
             Command::PutTag { id, tag } => {
                 // Simply insert or update the tag
-                self.tags.insert(id.clone(), tag.clone());
+                if self.tags.contains_key(&id) {
+                    changes.tags_updated.push(id.clone());
+                } else {
+                    changes.tags_created.push(id.clone());
+                }
+                self.tags.insert(id, tag);
             },
             
             Command::DeleteTag { id } => {
                 // Just remove the tag - no need to modify thoughts
                 // as they will simply reference a non-existent tag
-                self.tags.remove(id);
+                if self.tags.remove(&id).is_some() {
+                    changes.tags_deleted.push(id);
+                }
+            },
+            
+            Command::PutTagRule { name, rule } => {
+                changes.rules_changed.push(name.clone());
+                self.rules.insert(name, rule);
+            },
+            
+            Command::DeleteTagRule { name } => {
+                if self.rules.remove(&name).is_some() {
+                    changes.rules_changed.push(name);
+                }
+            },
+            
+            Command::PutSavedQuery { name, query } => {
+                changes.saved_queries_changed.push(name.clone());
+                self.saved_queries.insert(name, query);
+            },
+            
+            Command::DeleteSavedQuery { name } => {
+                if self.saved_queries.remove(&name).is_some() {
+                    changes.saved_queries_changed.push(name);
+                }
             },
+            
+            Command::LockThought { mut id } => {
+                if self.thoughts.contains_key(&id) && !self.locked.contains(&id) {
+                    intern::intern(&self.thoughts, &mut id);
+                    changes.thoughts_locked.push(id.clone());
+                    self.locked.insert(id);
+                }
+            },
+            
+            Command::UnlockThought { id } => {
+                if self.locked.remove(&id) {
+                    changes.thoughts_unlocked.push(id);
+                }
+            },
+            
+            Command::PatchThought { .. }
+            | Command::AddTag { .. }
+            | Command::RemoveTag { .. }
+            | Command::AddReference { .. }
+            | Command::RemoveReference { .. } => unreachable!("changes to part of a thought are resolved into PutThought"),
+            Command::Batch(_) => unreachable!("batches are applied command by command"),
+        }
+        changes
+    }
+
+    /// Insert many thoughts at once.
+    ///
+    /// The result is the same as applying a `PutThought` command for each thought in
+    /// order, but the derived indexes are updated in a single pass at the end and the
+    /// query cache is cleared once, which makes this much faster for importing.
+    /// References may point to thoughts that come later in the same batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `thoughts` - The thoughts to insert, with their IDs; later entries replace
+    ///   earlier ones with the same ID
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Thought, Reference};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let a = ThoughtID::new("a".to_string());
+    /// let b = ThoughtID::new("b".to_string());
+    /// graph.bulk_insert(vec![
+    ///     (a.clone(), Thought::new(None, "First".to_string(), vec![], vec![
+    ///         Reference::new(b.clone(), "see".to_string(), Utc::now()),
+    ///     ])),
+    ///     (b.clone(), Thought::new(None, "Second".to_string(), vec![], vec![])),
+    /// ]);
+    ///
+    /// assert_eq!(graph.get_backlinks(&b), vec![a]);
+    /// ```
+    pub fn bulk_insert<I: IntoIterator<Item = (ThoughtID, Thought)>>(&mut self, thoughts: I) {
+        self.query_cache.clear();
+        
+        let thoughts = thoughts.into_iter();
+        self.thoughts.reserve(thoughts.size_hint().0);
+        let mut inserted = HashSet::new();
+        for (mut id, thought) in thoughts {
+            intern::intern(&self.thoughts, &mut id);
+            if !inserted.contains(&id) {
+                self.record_put_thought(&id);
+            }
+            // Only the version that was in the graph before has been indexed
+            if let Some(old_thought) = self.thoughts.insert(id.clone(), thought) {
+                if !inserted.contains(&id) {
+                    self.unindex_thought(&id, &old_thought);
+                }
+            }
+            inserted.insert(id);
+        }
+        
+        // Every new thought is in the map now, so references within the batch are shared too
+        let mut pool: HashSet<ThoughtID> = self.thoughts.keys().cloned().collect();
+        pool.extend(self.backreferences.keys().cloned());
+        for id in &inserted {
+            if let Some(mut thought) = self.thoughts.remove(id) {
+                intern::intern_thought(&pool, &self.tags, &mut thought);
+                self.index_thought(id, &thought);
+                self.thoughts.insert(id.clone(), thought);
+            }
+        }
+    }
+    
+    /// Add the backreferences, tags and links of a thought to the derived indexes
+    fn index_thought(&mut self, id: &ThoughtID, thought: &Thought) {
+        for reference in &thought.references {
+            self.backreferences
+                .entry(reference.id.clone())
+                .or_default()
+                .insert(id.clone());
         }
+        for tag in &thought.tags {
+            self.tag_index.entry(tag.clone()).or_default().insert(id.clone());
+        }
+        links::index_links(&mut self.link_index, id, thought);
+    }
+    
+    /// Remove the backreferences, tags and links of a thought from the derived indexes
+    fn unindex_thought(&mut self, id: &ThoughtID, thought: &Thought) {
+        for reference in &thought.references {
+            if let Some(backrefs) = self.backreferences.get_mut(&reference.id) {
+                backrefs.remove(id);
+                // Clean up empty backreference entries
+                if backrefs.is_empty() {
+                    self.backreferences.remove(&reference.id);
+                }
+            }
+        }
+        for tag in &thought.tags {
+            if let Some(tagged) = self.tag_index.get_mut(tag) {
+                tagged.remove(id);
+                if tagged.is_empty() {
+                    self.tag_index.remove(tag);
+                }
+            }
+        }
+        links::unindex_links(&mut self.link_index, id, thought);
     }
 
     /// Execute a query against the graph and return matching thought IDs.
@@ -676,19 +1485,39 @@ impl ThoughtGraph {
     /// assert_eq!(complex_results.len(), 0); // Should be empty since one condition doesn't match
     /// ```
     pub fn query(&self, query: &Query) -> HashSet<ThoughtID> {
+        if !self.query_cache.is_enabled() {
+            return self.evaluate_query(query);
+        }
+        
+        let key = query.normalized();
+        if let Some(result) = self.query_cache.get(&key) {
+            return result;
+        }
+        let result = self.evaluate_query(query);
+        self.query_cache.insert(key, &result);
+        result
+    }
+    
+    /// Evaluate a query against the graph, bypassing the query cache
+    fn evaluate_query(&self, query: &Query) -> HashSet<ThoughtID> {
         match query {
+            Query::All => self.thoughts.keys().cloned().collect(),
+            
+            Query::Id(id) => match self.thoughts.contains_key(id) {
+                true => HashSet::from([id.clone()]),
+                false => HashSet::new(),
+            },
+            
             Query::Tag(tag_id) => {
                 // Find all thoughts that have this tag
                 // Only return thoughts if the tag still exists in the tags map
                 if !self.tags.contains_key(tag_id) {
                     return HashSet::new();
                 }
-                
-                self.thoughts
-                    .iter()
-                    .filter(|(_, thought)| thought.tags.contains(tag_id))
-                    .map(|(id, _)| id.clone())
-                    .collect()
+                if let Some(query) = self.smart_tag_query(tag_id) {
+                    return self.evaluate_query(&query);
+                }
+                self.tag_index.get(tag_id).cloned().unwrap_or_default()
             },
             
             Query::References(thought_id) => {
@@ -698,10 +1527,8 @@ impl ThoughtGraph {
                 // even if that ID doesn't exist yet
                 self.backreferences
                     .get(thought_id)
-                    .map_or_else(
-                        HashSet::new,
-                        |backrefs| backrefs.iter().cloned().collect()
-                    )
+                    .cloned()
+                    .unwrap_or_default()
             },
             
             Query::ReferencedBy(thought_id) => {
@@ -727,9 +1554,8 @@ impl ThoughtGraph {
                 }
                 
                 // Take the intersection of all subquery results
-                subqueries
-                    .iter()
-                    .map(|subquery| self.query(subquery))
+                self.evaluate_subqueries(subqueries)
+                    .into_iter()
                     .reduce(|accum, item| {
                         accum.intersection(&item).cloned().collect()
                     })
@@ -739,14 +1565,199 @@ impl ThoughtGraph {
             Query::Or(subqueries) => {
                 // Take the union of all subquery results
                 let mut result = HashSet::new();
-                for subquery in subqueries {
-                    result.extend(self.query(subquery));
+                for subresult in self.evaluate_subqueries(subqueries) {
+                    result.extend(subresult);
                 }
                 result
             },
+            
+            Query::ReachableFrom { id, depth } => self.reachable(id, *depth, Direction::Outgoing),
+            
+            Query::ReachableTo { id, depth } => self.reachable(id, *depth, Direction::Incoming),
+            
+            Query::TitleContains(text) => self.scan_text(text, |thought| thought.title.as_deref()),
+            
+            Query::ContentContains(text) => self.scan_text(text, |thought| Some(&thought.contents)),
+            
+            Query::TitleRegex(pattern) => self.scan_pattern(pattern, |thought| thought.title.as_deref()),
+            
+            Query::ContentRegex(pattern) => self.scan_pattern(pattern, |thought| Some(&thought.contents)),
+            
+            Query::ReferenceNotesContain(text) => {
+                let term = search::TextTerm::new(text);
+                parallel::filter_map(&self.thoughts, |id, thought| {
+                    term.found_in_notes(thought).then(|| id.clone())
+                })
+                .into_iter()
+                .collect()
+            },
+            
+            // Only tags that still exist count, as for `Tag`
+            Query::TagGlob(glob) => self
+                .tags
+                .keys()
+                .filter(|tag| search::glob_matches(glob, tag.as_str()))
+                .filter_map(|tag| self.tag_index.get(tag))
+                .flatten()
+                .cloned()
+                .collect(),
+            
+            Query::IdGlob(glob) => parallel::filter_map(&self.thoughts, |id, _| {
+                search::glob_matches(glob, id.as_str()).then(|| id.clone())
+            })
+            .into_iter()
+            .collect(),
+            
+            Query::Untagged => parallel::filter_map(&self.thoughts, |id, thought| {
+                thought.tags.is_empty().then(|| id.clone())
+            })
+            .into_iter()
+            .collect(),
+            
+            Query::Orphan => parallel::filter_map(&self.thoughts, |id, _| {
+                (self.degree(id) == 0).then(|| id.clone())
+            })
+            .into_iter()
+            .collect(),
+            
+            Query::MinBacklinks(count) => self
+                .thoughts
+                .keys()
+                .filter(|id| self.backlink_count(id) >= *count)
+                .cloned()
+                .collect(),
+            
+            Query::MaxBacklinks(count) => self
+                .thoughts
+                .keys()
+                .filter(|id| self.backlink_count(id) <= *count)
+                .cloned()
+                .collect(),
+            
+            Query::Not(subquery) => {
+                // Take the complement against all thoughts in the graph
+                let excluded = self.evaluate_query(subquery);
+                self.thoughts
+                    .keys()
+                    .filter(|id| !excluded.contains(*id))
+                    .cloned()
+                    .collect()
+            },
         }
     }
     
+    /// The thoughts at most `depth` links from `start` in `direction`, without `start`
+    fn reachable(&self, start: &ThoughtID, depth: usize, direction: Direction) -> HashSet<ThoughtID> {
+        self.walk(start)
+            .direction(direction)
+            .max_depth(depth)
+            .skip(1)
+            .map(|(id, _, _)| id.clone())
+            .collect()
+    }
+    
+    /// Find the thoughts where `field` contains `text`, matched as `search` matches it
+    fn scan_text(&self, text: &str, field: fn(&Thought) -> Option<&str>) -> HashSet<ThoughtID> {
+        let term = search::TextTerm::new(text);
+        parallel::filter_map(&self.thoughts, |id, thought| {
+            term.found_in(field(thought)).then(|| id.clone())
+        })
+        .into_iter()
+        .collect()
+    }
+    
+    /// Find the thoughts where `field` matches a pattern, or none if it does not compile
+    fn scan_pattern(&self, pattern: &str, field: fn(&Thought) -> Option<&str>) -> HashSet<ThoughtID> {
+        let Ok(regex) = search::compile_pattern(pattern) else {
+            return HashSet::new();
+        };
+        parallel::filter_map(&self.thoughts, |id, thought| {
+            field(thought).is_some_and(|text| regex.is_match(text)).then(|| id.clone())
+        })
+        .into_iter()
+        .collect()
+    }
+    
+    /// Evaluate the subqueries of a compound query, in parallel on large graphs
+    fn evaluate_subqueries(&self, subqueries: &[Box<Query>]) -> Vec<HashSet<ThoughtID>> {
+        let expensive = parallel::worth_parallelizing(self.thoughts.len());
+        parallel::map(subqueries, expensive, |subquery| self.evaluate_query(subquery))
+    }
+    
+    /// Iterate lazily over the thoughts matching a query.
+    ///
+    /// Unlike `query()`, this does not collect the full result first: each thought is
+    /// checked when the iterator reaches it, so taking only the first few matches stops
+    /// the work early. The order of the results is unspecified. The query cache is not
+    /// used.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to execute
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the IDs of the matching thoughts, or `InvalidPattern` if one of
+    /// the patterns of the query does not compile
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, TagID, Query};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let tag = TagID::new("daily".to_string());
+    /// graph.create_tag(tag.clone(), "Daily notes".to_string()).unwrap();
+    /// for day in 1..=30 {
+    ///     let id = ThoughtID::new(format!("day-{}", day));
+    ///     graph.create_thought(id, None, "Notes".to_string(), vec![tag.clone()], vec![]).unwrap();
+    /// }
+    ///
+    /// let query = Query::Tag(tag);
+    /// let first_page: Vec<&ThoughtID> = graph.query_iter(&query).unwrap().take(10).collect();
+    /// assert_eq!(first_page.len(), 10);
+    /// ```
+    pub fn query_iter(&self, query: &Query) -> Result<impl Iterator<Item = &ThoughtID> + '_> {
+        let matcher = self.matcher(query)?;
+        Ok(self.thoughts.keys().filter(move |id| matcher.matches(id)))
+    }
+    
+    /// Check whether a single thought matches a query.
+    ///
+    /// This agrees with `query()`: a thought matches exactly when `query()` would include
+    /// it. Thoughts that are not in the graph never match. To check many thoughts against
+    /// the same query, prepare it once with `matcher()` instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to check
+    /// * `query` - The query to check against
+    ///
+    /// # Returns
+    ///
+    /// Whether the thought matches, or `InvalidPattern` if one of the patterns of the
+    /// query does not compile
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Reference, Query};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let a = ThoughtID::new("a".to_string());
+    /// let b = ThoughtID::new("b".to_string());
+    /// graph.create_thought(a.clone(), None, "A".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(b.clone(), None, "B".to_string(), vec![],
+    ///     vec![Reference::new(a.clone(), String::new(), Utc::now())]).unwrap();
+    ///
+    /// assert!(graph.matches(&b, &Query::References(a.clone())).unwrap());
+    /// assert!(!graph.matches(&a, &Query::References(a.clone())).unwrap());
+    /// ```
+    pub fn matches(&self, id: &ThoughtID, query: &Query) -> Result<bool> {
+        Ok(self.matcher(query)?.matches(id))
+    }
+    
     /// Get a thought by its ID
     pub fn get_thought(&self, id: &ThoughtID) -> Option<&Thought> {
         self.thoughts.get(id)
@@ -757,26 +1768,94 @@ impl ThoughtGraph {
         self.tags.get(id)
     }
     
-    /// Get all thoughts that reference the given thought ID
+    /// Get all thoughts that reference the given thought ID, sorted by ID
     pub fn get_backlinks(&self, id: &ThoughtID) -> Vec<ThoughtID> {
-        self.backreferences
+        let mut backlinks: Vec<ThoughtID> = self.backreferences
             .get(id)
-            .cloned()
-            .unwrap_or_else(Vec::new)
+            .map(|backrefs| backrefs.iter().cloned().collect())
+            .unwrap_or_default();
+        backlinks.sort();
+        backlinks
+    }
+    
+    /// Encode the graph in the binary file format.
+    ///
+    /// The data starts with a versioned header followed by the graph encoded as
+    /// MessagePack, so that it remains readable as the graph gains new fields.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        format::encode(self)
     }
     
-    /// Save the graph to a file in binary format
+    /// Decode a graph from the binary file format.
+    ///
+    /// Data written by older versions of this library, including the original
+    /// headerless bincode format, is also accepted.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut graph = format::decode(data)?;
+        graph.rebuild_indexes();
+        Ok(graph)
+    }
+    
+    /// Save the graph to a file in binary format (see `to_bytes`).
+    ///
+    /// The graph is written to a temporary file next to `path` (with `.tmp` appended to
+    /// its name), which then replaces the file, so that a crash while saving never leaves
+    /// a partially written graph behind. Use `storage::FileStorage::lock` to keep other
+    /// programs from saving the same file at the same time.
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let encoded = bincode::serialize(self)?;
-        fs::write(path, encoded)?;
-        Ok(())
+        self.save_to_file_with_progress(path, &mut |_| {})
+    }
+    
+    /// Save the graph to a file like `save_to_file`, reporting the progress of writing it
+    /// in bytes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use thoughtgraph::ThoughtGraph;
+    ///
+    /// let graph = ThoughtGraph::new();
+    /// graph.save_to_file_with_progress("thoughts.bin", &mut |progress| {
+    ///     eprint!("\r{:.0}%", 100.0 * progress.fraction().unwrap_or_default());
+    /// }).unwrap();
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_to_file_with_progress<P: AsRef<Path>>(&self, path: P, progress: ProgressFn) -> Result<()> {
+        storage::write_atomically(path.as_ref(), &self.to_bytes()?, progress)
     }
     
-    /// Load a graph from a binary file
+    /// Load a graph from a binary file (see `from_bytes`)
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let data = fs::read(path)?;
-        let graph = bincode::deserialize(&data)?;
-        Ok(graph)
+        Self::load_from_file_with_progress(path, &mut |_| {})
+    }
+    
+    /// Load a graph from a file like `load_from_file`, reporting the progress of reading
+    /// it in bytes. The graph is decoded once it has been read.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_file_with_progress<P: AsRef<Path>>(path: P, progress: ProgressFn) -> Result<Self> {
+        Self::from_bytes(&storage::read_with_progress(path.as_ref(), progress)?)
+    }
+    
+    /// Rebuild the derived indexes (the tag index and the external link index) from the
+    /// thoughts.
+    ///
+    /// Derived indexes are not persisted. They are maintained automatically by `command()`
+    /// and rebuilt by `load_from_file()`, so this only needs to be called after modifying
+    /// the `thoughts` map directly or deserializing a graph by other means. The query cache
+    /// is cleared as well, and equal IDs are made to share their strings again.
+    pub fn rebuild_indexes(&mut self) {
+        self.intern_ids();
+        self.query_cache.clear();
+        self.link_index.clear();
+        self.tag_index.clear();
+        for (id, thought) in &self.thoughts {
+            links::index_links(&mut self.link_index, id, thought);
+            for tag in &thought.tags {
+                self.tag_index.entry(tag.clone()).or_default().insert(id.clone());
+            }
+        }
     }
     
     /// Create a new thought with the given parameters
@@ -789,12 +1868,12 @@ impl ThoughtGraph {
         references: Vec<Reference>,
     ) -> Result<&Thought> {
         let thought = Thought::new(title, contents, tags, references);
-        self.command(&Command::PutThought {
+        self.apply(Command::PutThought {
             id: id.clone(),
             thought,
         });
         
-        self.thoughts.get(&id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.clone()))
+        self.thoughts.get(&id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))
     }
     
     /// Process automatic references from content (in [thought_id] format)
@@ -877,7 +1956,7 @@ impl ThoughtGraph {
             
             // Update the thought with new references
             if !added_refs.is_empty() {
-                self.command(&Command::PutThought {
+                self.apply(Command::PutThought {
                     id: thought_id.clone(),
                     thought: updated_thought,
                 });
@@ -890,12 +1969,12 @@ impl ThoughtGraph {
     /// Create a new tag with the given parameters
     pub fn create_tag(&mut self, id: TagID, description: String) -> Result<&Tag> {
         let tag = Tag::new(description);
-        self.command(&Command::PutTag {
+        self.apply(Command::PutTag {
             id: id.clone(),
             tag,
         });
         
-        self.tags.get(&id).ok_or_else(|| ThoughtGraphError::TagNotFound(id.id.clone()))
+        self.tags.get(&id).ok_or_else(|| ThoughtGraphError::TagNotFound(id.id.to_string()))
     }
     
     /// Get a list of all thought IDs in the graph
@@ -1001,6 +2080,11 @@ mod tests {
         assert!(refs.contains(&create_thought_id("thought1")));
         assert!(refs.contains(&create_thought_id("thought2")));
         assert!(refs.contains(&create_thought_id("invalid-")));
+
+        // IDs in other scripts are linked too
+        let thought = Thought::new(None, "See [café] and [заметка/2]".to_string(), vec![], vec![]);
+        let refs = thought.extract_references_from_content();
+        assert_eq!(refs, vec![create_thought_id("café"), create_thought_id("заметка/2")]);
     }
     
     #[test]
@@ -1778,6 +2862,275 @@ mod tests {
         assert!(and_single_result.contains(&thought_id));
     }
 
+    #[test]
+    fn test_text_queries() {
+        let mut graph = ThoughtGraph::new();
+        let tag = create_tag_id("math");
+        graph.command(&Command::PutTag { id: tag.clone(), tag: Tag::new(String::new()) });
+        graph.command(&Command::PutThought {
+            id: create_thought_id("a"),
+            thought: Thought::new(Some("Graph Théory".to_string()), "Cycles and trees".to_string(), vec![tag.clone()], vec![]),
+        });
+        graph.command(&Command::PutThought {
+            id: create_thought_id("b"),
+            thought: Thought::new(None, "A graph of crates".to_string(), vec![], vec![]),
+        });
+
+        let title = Query::TitleContains("graph theory".to_string());
+        let content = Query::ContentContains("GRAPH".to_string());
+        assert_eq!(graph.query(&title), HashSet::from([create_thought_id("a")]));
+        assert_eq!(graph.query(&content), HashSet::from([create_thought_id("b")]));
+        let combined = Query::Or(vec![
+            Box::new(Query::And(vec![Box::new(Query::Tag(tag)), Box::new(Query::ContentContains("trees".to_string()))])),
+            Box::new(content.clone()),
+        ]);
+        assert_eq!(graph.query(&combined).len(), 2);
+        assert!(graph.matches(&create_thought_id("a"), &title).unwrap());
+        assert!(!graph.matches(&create_thought_id("b"), &title).unwrap());
+        assert!(graph.matches(&create_thought_id("b"), &content).unwrap());
+    }
+
+    #[test]
+    fn test_regex_queries() {
+        let mut graph = ThoughtGraph::new();
+        for (name, title, contents) in [("a", Some("Draft plan"), "TODO: call PROJ-12"), ("b", None, "todo later"), ("c", Some("Plan"), "Done")] {
+            graph.command(&Command::PutThought {
+                id: create_thought_id(name),
+                thought: Thought::new(title.map(str::to_string), contents.to_string(), vec![], vec![]),
+            });
+        }
+
+        let ticket = Query::content_regex(r"\b[A-Z]+-\d+\b").unwrap();
+        assert_eq!(graph.query(&ticket), HashSet::from([create_thought_id("a")]));
+        assert_eq!(graph.query(&Query::content_regex("(?i)^todo").unwrap()).len(), 2);
+        let plans = Query::title_regex("Plan$").unwrap();
+        assert_eq!(graph.query(&plans).len(), 1);
+        assert!(graph.matches(&create_thought_id("c"), &plans).unwrap());
+        assert!(!graph.matches(&create_thought_id("b"), &Query::TitleRegex(".*".to_string())).unwrap());
+
+        // Invalid patterns are reported when building or saving a query, and match nothing
+        assert!(matches!(Query::title_regex("["), Err(ThoughtGraphError::InvalidPattern(_))));
+        let invalid = Query::ContentRegex("(".to_string());
+        assert!(graph.query(&invalid).is_empty());
+        let save = Command::PutSavedQuery { name: "bad".to_string(), query: invalid };
+        assert!(matches!(graph.try_command(&save), Err(ThoughtGraphError::InvalidPattern(_))));
+    }
+
+    #[test]
+    fn test_untagged_and_orphan_queries() {
+        let mut graph = ThoughtGraph::new();
+        let tag = create_tag_id("inbox");
+        graph.command(&Command::PutTag { id: tag.clone(), tag: Tag::new(String::new()) });
+        graph.command(&Command::PutThought { id: create_thought_id("tagged"), thought: Thought::new(None, String::new(), vec![tag], vec![]) });
+        graph.command(&Command::PutThought { id: create_thought_id("alone"), thought: Thought::new(None, String::new(), vec![], vec![]) });
+        graph.command(&Command::PutThought {
+            id: create_thought_id("linking"),
+            thought: Thought::new(None, String::new(), vec![], vec![create_reference("target", "")]),
+        });
+        graph.command(&Command::PutThought { id: create_thought_id("target"), thought: Thought::new(None, String::new(), vec![], vec![]) });
+
+        let ids = |query: &Query| {
+            let mut ids: Vec<String> = graph.query(query).into_iter().map(|id| id.id.to_string()).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&Query::Untagged), vec!["alone", "linking", "target"]);
+        assert_eq!(ids(&Query::Orphan), vec!["alone", "tagged"]);
+        assert_eq!(ids(&Query::And(vec![Box::new(Query::Untagged), Box::new(Query::Orphan)])), vec!["alone"]);
+        assert!(graph.matches(&create_thought_id("alone"), &Query::Orphan).unwrap());
+        assert!(!graph.matches(&create_thought_id("target"), &Query::Orphan).unwrap());
+        assert!(!graph.matches(&create_thought_id("tagged"), &Query::Untagged).unwrap());
+        assert_eq!(ids(&Query::Orphan), graph.health(Utc::now()).orphans.iter().map(|id| id.id.to_string()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_all_and_id_queries() {
+        let mut graph = ThoughtGraph::new();
+        let draft = create_tag_id("draft");
+        graph.command(&Command::PutTag { id: draft.clone(), tag: Tag::new(String::new()) });
+        for (name, tags) in [("a", vec![draft.clone()]), ("b", vec![]), ("c", vec![])] {
+            graph.command(&Command::PutThought { id: create_thought_id(name), thought: Thought::new(None, String::new(), tags, vec![]) });
+        }
+
+        assert_eq!(graph.query(&Query::All).len(), 3);
+        let not_draft = Query::And(vec![Box::new(Query::All), Box::new(Query::Not(Box::new(Query::Tag(draft.clone()))))]);
+        assert_eq!(graph.query(&not_draft).len(), 2);
+        assert_eq!(graph.query(&Query::Id(create_thought_id("b"))), HashSet::from([create_thought_id("b")]));
+        assert!(graph.query(&Query::Id(create_thought_id("missing"))).is_empty());
+
+        // Always include a particular thought alongside the drafts
+        let with_index = Query::Or(vec![Box::new(Query::Tag(draft)), Box::new(Query::Id(create_thought_id("c")))]);
+        assert_eq!(graph.query(&with_index), HashSet::from([create_thought_id("a"), create_thought_id("c")]));
+        assert!(graph.matches(&create_thought_id("c"), &with_index).unwrap());
+        assert!(!graph.matches(&create_thought_id("b"), &with_index).unwrap());
+        assert!(graph.matches(&create_thought_id("b"), &Query::All).unwrap());
+        assert!(!graph.matches(&create_thought_id("missing"), &Query::All).unwrap());
+    }
+
+    #[test]
+    fn test_tag_index() {
+        let mut graph = ThoughtGraph::new();
+        let rust = create_tag_id("rust");
+        graph.command(&Command::PutTag { id: rust.clone(), tag: Tag::new(String::new()) });
+        for name in ["a", "b"] {
+            graph.command(&Command::PutThought { id: create_thought_id(name), thought: Thought::new(None, String::new(), vec![rust.clone()], vec![]) });
+        }
+        let tagged = |graph: &ThoughtGraph| graph.query(&Query::Tag(rust.clone()));
+        assert_eq!(tagged(&graph), HashSet::from([create_thought_id("a"), create_thought_id("b")]));
+
+        graph.command(&Command::RemoveTag { id: create_thought_id("a"), tag: rust.clone() });
+        graph.command(&Command::DeleteThought { id: create_thought_id("b") });
+        assert!(tagged(&graph).is_empty());
+        assert!(graph.tag_index.is_empty());
+
+        // The index is not saved, but rebuilt when the graph is loaded
+        graph.command(&Command::AddTag { id: create_thought_id("a"), tag: rust.clone() });
+        let loaded = ThoughtGraph::from_bytes(&graph.to_bytes().unwrap()).unwrap();
+        assert_eq!(tagged(&loaded), HashSet::from([create_thought_id("a")]));
+        loaded.assert_invariants();
+    }
+
+    #[test]
+    fn test_deserialized_ids_are_normalized() {
+        // Written without going through `new`, as by an older version or by hand
+        let decomposed = ThoughtID { id: "cafe\u{301}".into() };
+        let decomposed_tag = TagID { id: "re\u{301}sume\u{301}".into() };
+        let mut graph = ThoughtGraph::new();
+        graph.command(&Command::PutTag { id: decomposed_tag.clone(), tag: Tag::new(String::new()) });
+        graph.command(&Command::PutThought {
+            id: decomposed.clone(),
+            thought: Thought::new(None, String::new(), vec![decomposed_tag], vec![]),
+        });
+        graph.command(&Command::PutThought {
+            id: create_thought_id("menu"),
+            thought: Thought::new(None, String::new(), vec![], vec![Reference::new(decomposed, String::new(), Utc::now())]),
+        });
+
+        let loaded = ThoughtGraph::from_bytes(&graph.to_bytes().unwrap()).unwrap();
+        let cafe = create_thought_id("café");
+        assert!(loaded.get_thought(&cafe).is_some());
+        assert_eq!(loaded.query(&Query::Tag(create_tag_id("résumé"))), HashSet::from([cafe.clone()]));
+        assert_eq!(loaded.query(&Query::References(cafe)), HashSet::from([create_thought_id("menu")]));
+        loaded.assert_invariants();
+
+        let from_json: ThoughtID = serde_json::from_str("\"cafe\\u0301\"").unwrap();
+        assert_eq!(from_json.as_str(), "café");
+    }
+
+    #[test]
+    fn test_glob_queries() {
+        let mut graph = ThoughtGraph::new();
+        for tag in ["project/a", "project/b", "projects"] {
+            graph.command(&Command::PutTag { id: create_tag_id(tag), tag: Tag::new(String::new()) });
+        }
+        for (name, tags) in [("book-chapter-1", vec!["project/a"]), ("book-chapter-2", vec!["projects"]), ("book-index", vec!["project/b", "gone"])] {
+            let tags = tags.into_iter().map(create_tag_id).collect();
+            graph.command(&Command::PutThought { id: create_thought_id(name), thought: Thought::new(None, String::new(), tags, vec![]) });
+        }
+        let ids = |names: &[&str]| names.iter().map(|name| create_thought_id(name)).collect::<HashSet<_>>();
+
+        assert_eq!(graph.query(&Query::TagGlob("project/*".to_string())), ids(&["book-chapter-1", "book-index"]));
+        assert_eq!(graph.query(&Query::IdGlob("book-chapter-*".to_string())), ids(&["book-chapter-1", "book-chapter-2"]));
+        // Tags that do not exist are not matched, as for `Tag`
+        assert!(graph.query(&Query::TagGlob("g*".to_string())).is_empty());
+        assert!(graph.matches(&create_thought_id("book-index"), &Query::TagGlob("*/b".to_string())).unwrap());
+        assert!(!graph.matches(&create_thought_id("book-index"), &Query::TagGlob("gone".to_string())).unwrap());
+        assert_eq!(graph.query(&Query::IdGlob("book-*-*".to_string())), ids(&["book-chapter-1", "book-chapter-2"]));
+        assert!(graph.query(&Query::IdGlob("book-index*x".to_string())).is_empty());
+    }
+
+    #[test]
+    fn test_reference_notes_query() {
+        let mut graph = ThoughtGraph::new();
+        for (name, notes) in [("a", "Contradicts the premise"), ("b", "builds on it"), ("c", "")] {
+            let references = match notes.is_empty() {
+                true => vec![],
+                false => vec![create_reference("c", notes)],
+            };
+            graph.command(&Command::PutThought { id: create_thought_id(name), thought: Thought::new(None, String::new(), vec![], references) });
+        }
+
+        let query = Query::ReferenceNotesContain("contradicts".to_string());
+        assert_eq!(graph.query(&query), HashSet::from([create_thought_id("a")]));
+        assert!(graph.matches(&create_thought_id("a"), &query).unwrap());
+        // Only the thought a reference belongs to matches its notes, not the one it points at
+        assert!(!graph.matches(&create_thought_id("c"), &Query::ReferenceNotesContain("builds".to_string())).unwrap());
+    }
+
+    #[test]
+    fn test_backlink_queries() {
+        let mut graph = ThoughtGraph::new();
+        // a, b and c reference hub, and a references b
+        for (name, references) in [("hub", vec![]), ("a", vec!["hub", "b"]), ("b", vec!["hub"]), ("c", vec!["hub"])] {
+            let references = references.into_iter().map(|to| create_reference(to, "")).collect();
+            graph.command(&Command::PutThought { id: create_thought_id(name), thought: Thought::new(None, String::new(), vec![], references) });
+        }
+        let ids = |names: &[&str]| names.iter().map(|name| create_thought_id(name)).collect::<HashSet<_>>();
+
+        assert_eq!(graph.query(&Query::MinBacklinks(2)), ids(&["hub"]));
+        assert_eq!(graph.query(&Query::MinBacklinks(0)), graph.query(&Query::All));
+        assert_eq!(graph.query(&Query::MaxBacklinks(0)), ids(&["a", "c"]));
+        assert!(graph.matches(&create_thought_id("b"), &Query::And(vec![Box::new(Query::MinBacklinks(1)), Box::new(Query::MaxBacklinks(1))])).unwrap());
+    }
+
+    #[test]
+    fn test_reachable_queries() {
+        let mut graph = ThoughtGraph::new();
+        // root -> a -> b -> c, and d -> b
+        for (name, references) in [("root", vec!["a"]), ("a", vec!["b"]), ("b", vec!["c"]), ("c", vec![]), ("d", vec!["b"])] {
+            let references = references.into_iter().map(|to| create_reference(to, "")).collect();
+            graph.command(&Command::PutThought { id: create_thought_id(name), thought: Thought::new(None, String::new(), vec![], references) });
+        }
+        let ids = |names: &[&str]| names.iter().map(|name| create_thought_id(name)).collect::<HashSet<_>>();
+
+        let from = |depth| Query::ReachableFrom { id: create_thought_id("root"), depth };
+        assert_eq!(graph.query(&from(1)), graph.query(&Query::ReferencedBy(create_thought_id("root"))));
+        assert_eq!(graph.query(&from(2)), ids(&["a", "b"]));
+        assert_eq!(graph.query(&from(usize::MAX)), ids(&["a", "b", "c"]));
+        assert_eq!(graph.query(&Query::ReachableTo { id: create_thought_id("c"), depth: usize::MAX }), ids(&["root", "a", "b", "d"]));
+        assert_eq!(graph.query(&Query::ReachableTo { id: create_thought_id("b"), depth: 1 }), ids(&["a", "d"]));
+        assert!(graph.query(&Query::ReachableFrom { id: create_thought_id("missing"), depth: 5 }).is_empty());
+
+        // The whole cluster under the root, root included
+        let cluster = Query::Or(vec![Box::new(Query::Id(create_thought_id("root"))), Box::new(from(usize::MAX))]);
+        assert_eq!(graph.query(&cluster), ids(&["root", "a", "b", "c"]));
+        assert!(graph.matches(&create_thought_id("c"), &from(3)).unwrap());
+        assert!(!graph.matches(&create_thought_id("c"), &from(2)).unwrap());
+    }
+
+    #[test]
+    fn test_not_query() {
+        let mut graph = ThoughtGraph::new();
+        let project = create_tag_id("project");
+        let archived = create_tag_id("archived");
+        for tag in [&project, &archived] {
+            graph.command(&Command::PutTag { id: tag.clone(), tag: Tag::new(String::new()) });
+        }
+        for (name, tags) in [("live", vec![project.clone()]), ("old", vec![project.clone(), archived.clone()]), ("note", vec![])] {
+            graph.command(&Command::PutThought {
+                id: create_thought_id(name),
+                thought: Thought::new(None, String::new(), tags, vec![]),
+            });
+        }
+
+        // Projects that are not archived
+        let query = Query::And(vec![
+            Box::new(Query::Tag(project.clone())),
+            Box::new(Query::Not(Box::new(Query::Tag(archived.clone())))),
+        ]);
+        assert_eq!(graph.query(&query), HashSet::from([create_thought_id("live")]));
+        assert!(graph.matches(&create_thought_id("live"), &query).unwrap());
+        assert!(!graph.matches(&create_thought_id("old"), &query).unwrap());
+
+        // The complement is taken against all thoughts, and NOT of nothing is everything
+        let not_archived = Query::Not(Box::new(Query::Tag(archived)));
+        assert_eq!(graph.find_thoughts(&not_archived).len(), 2);
+        assert_eq!(graph.query(&Query::Not(Box::new(Query::Or(vec![])))).len(), 3);
+        assert!(graph.query(&Query::Not(Box::new(not_archived.clone()))).contains(&create_thought_id("old")));
+        assert_eq!(query.to_string(), "tag:project AND NOT tag:archived");
+        assert_eq!(Query::Not(Box::new(query)).to_string(), "NOT (tag:project AND NOT tag:archived)");
+    }
+
     #[test]
     fn test_nonexistent_references() {
         // Test handling of references to thoughts that don't exist
@@ -1931,4 +3284,210 @@ mod tests {
         let backrefs_after = graph.get_backlinks(&thought_id);
         assert_eq!(backrefs_after.len(), 0);
     }
+    
+    #[test]
+    fn test_backreferences_are_deduplicated() {
+        let mut graph = ThoughtGraph::new();
+        let target_id = create_thought_id("target");
+        let source_id = create_thought_id("source");
+        
+        // A thought that lists the same reference twice, put several times
+        let reference = Reference::new(target_id.clone(), "".to_string(), Utc::now());
+        for _ in 0..3 {
+            graph.command(&Command::PutThought {
+                id: source_id.clone(),
+                thought: Thought::new(None, "Source".to_string(), vec![], vec![reference.clone(), reference.clone()]),
+            });
+        }
+        
+        assert_eq!(graph.get_backlinks(&target_id), vec![source_id.clone()]);
+        
+        // Removing the reference removes the backlink entirely
+        graph.command(&Command::PutThought {
+            id: source_id.clone(),
+            thought: Thought::new(None, "Source".to_string(), vec![], vec![]),
+        });
+        assert!(graph.get_backlinks(&target_id).is_empty());
+        assert!(!graph.backreferences.contains_key(&target_id));
+    }
+    
+    #[test]
+    fn test_apply_matches_command() {
+        let target_id = ThoughtID::new("target".to_string());
+        let commands = vec![
+            Command::PutTag { id: TagID::new("tag".to_string()), tag: Tag::new("A tag".to_string()) },
+            Command::PutThought {
+                id: ThoughtID::new("source".to_string()),
+                thought: Thought::new(
+                    None,
+                    "See https://example.com".to_string(),
+                    vec![TagID::new("tag".to_string())],
+                    vec![Reference::new(target_id.clone(), "note".to_string(), Utc::now())],
+                ),
+            },
+            Command::PutThought {
+                id: target_id.clone(),
+                thought: Thought::new(None, "Target".to_string(), vec![], vec![]),
+            },
+            Command::DeleteThought { id: target_id.clone() },
+        ];
+        
+        let mut borrowed = ThoughtGraph::new();
+        let mut owned = ThoughtGraph::new();
+        for command in commands {
+            borrowed.command(&command);
+            owned.apply(command);
+        }
+        
+        assert_eq!(borrowed.list_thoughts().len(), owned.list_thoughts().len());
+        assert_eq!(borrowed.backreferences, owned.backreferences);
+        assert_eq!(borrowed.tags.len(), owned.tags.len());
+        assert_eq!(owned.thoughts_linking_to("https://example.com").len(), 1);
+    }
+    
+    #[test]
+    fn test_bulk_insert_matches_commands() {
+        let reference = |target: &str| Reference::new(ThoughtID::new(target.to_string()), String::new(), Utc::now());
+        let batch = vec![
+            (ThoughtID::new("a".to_string()), Thought::new(None, "https://a.example".to_string(), vec![], vec![reference("b")])),
+            (ThoughtID::new("b".to_string()), Thought::new(None, "B".to_string(), vec![], vec![reference("c")])),
+            // Replaces the thought added before the batch
+            (ThoughtID::new("c".to_string()), Thought::new(None, "C".to_string(), vec![], vec![reference("a")])),
+            // Replaces an earlier entry of the same batch
+            (ThoughtID::new("b".to_string()), Thought::new(None, "B again".to_string(), vec![], vec![reference("a")])),
+        ];
+        
+        let mut expected = ThoughtGraph::new();
+        let mut bulk = ThoughtGraph::new();
+        for graph in [&mut expected, &mut bulk] {
+            graph
+                .create_thought(ThoughtID::new("c".to_string()), None, "https://c.example".to_string(), vec![], vec![reference("b")])
+                .unwrap();
+        }
+        for (id, thought) in batch.clone() {
+            expected.apply(Command::PutThought { id, thought });
+        }
+        bulk.bulk_insert(batch);
+        bulk.assert_invariants();
+        
+        assert_eq!(bulk.backreferences, expected.backreferences);
+        assert_eq!(bulk.external_links(), expected.external_links());
+        assert_eq!(bulk.get_thought(&ThoughtID::new("b".to_string())).unwrap().contents, "B again");
+        assert_eq!(
+            bulk.get_backlinks(&ThoughtID::new("a".to_string())),
+            vec![ThoughtID::new("b".to_string()), ThoughtID::new("c".to_string())]
+        );
+    }
+    
+    #[test]
+    fn test_query_iter_agrees_with_query() {
+        let mut graph = ThoughtGraph::new();
+        let tag = TagID::new("tag".to_string());
+        graph.create_tag(tag.clone(), String::new()).unwrap();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let reference = |target: &str| Reference::new(id(target), String::new(), Utc::now());
+        graph.create_thought(id("a"), None, String::new(), vec![tag.clone()], vec![reference("b")]).unwrap();
+        graph.create_thought(id("b"), None, String::new(), vec![], vec![reference("a"), reference("missing")]).unwrap();
+        graph.create_thought(id("c"), None, String::new(), vec![tag.clone()], vec![]).unwrap();
+        
+        let queries = vec![
+            Query::Tag(tag.clone()),
+            Query::Tag(TagID::new("unknown".to_string())),
+            Query::References(id("a")),
+            Query::ReferencedBy(id("b")),
+            Query::ReferencedBy(id("missing")),
+            Query::And(vec![]),
+            Query::And(vec![Box::new(Query::Tag(tag.clone())), Box::new(Query::References(id("b")))]),
+            Query::Or(vec![Box::new(Query::References(id("a"))), Box::new(Query::Tag(tag.clone()))]),
+            Query::ReachableFrom { id: id("a"), depth: usize::MAX },
+            Query::Not(Box::new(Query::ReachableTo { id: id("b"), depth: 1 })),
+        ];
+        for query in &queries {
+            let lazy: HashSet<ThoughtID> = graph.query_iter(query).unwrap().cloned().collect();
+            assert_eq!(lazy, graph.query(query), "{:?}", query);
+        }
+        assert_eq!(graph.query_iter(&Query::Tag(tag)).unwrap().take(1).count(), 1);
+    }
+    
+    #[test]
+    fn test_patch_thought() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let reference = |target: &str, note: &str| Reference::new(id(target), note.to_string(), Utc::now());
+        let (old, new) = (TagID::new("old".to_string()), TagID::new("new".to_string()));
+        graph.create_tag(old.clone(), String::new()).unwrap();
+        graph.create_tag(new.clone(), String::new()).unwrap();
+        graph
+            .create_thought(id("a"), Some("Title".to_string()), "Contents".to_string(), vec![old.clone()], vec![reference("b", "first"), reference("c", "")])
+            .unwrap();
+        
+        graph.command(&Command::PatchThought {
+            id: id("a"),
+            title: Some(None),
+            contents: None,
+            add_tags: vec![new.clone()],
+            remove_tags: vec![old],
+            add_references: vec![reference("b", "second"), reference("d", "")],
+            remove_references: vec![id("c")],
+        });
+        
+        let thought = graph.get_thought(&id("a")).unwrap();
+        assert_eq!(thought.title, None);
+        assert_eq!(thought.contents, "Contents");
+        assert_eq!(thought.tags, vec![new]);
+        let references: Vec<(&str, &str)> = thought.references.iter().map(|r| (r.id.as_str(), r.notes.as_str())).collect();
+        assert_eq!(references, vec![("b", "second"), ("d", "")]);
+        assert!(graph.get_backlinks(&id("c")).is_empty());
+        assert_eq!(graph.get_backlinks(&id("d")), vec![id("a")]);
+        
+        // Patching a missing thought does nothing, and is rejected by validation
+        let patch = Command::PatchThought {
+            id: id("missing"),
+            title: None,
+            contents: Some("New".to_string()),
+            add_tags: vec![],
+            remove_tags: vec![],
+            add_references: vec![],
+            remove_references: vec![],
+        };
+        graph.command(&patch);
+        assert!(graph.get_thought(&id("missing")).is_none());
+        assert!(matches!(graph.try_command(&patch), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+    
+    #[test]
+    fn test_fine_grained_commands() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let tag = TagID::new("tag".to_string());
+        graph.create_tag(tag.clone(), String::new()).unwrap();
+        graph.create_thought(id("a"), None, String::new(), vec![], vec![]).unwrap();
+        graph.create_thought(id("b"), None, String::new(), vec![], vec![]).unwrap();
+        
+        graph.command(&Command::AddTag { id: id("a"), tag: tag.clone() });
+        graph.command(&Command::AddTag { id: id("a"), tag: tag.clone() });
+        assert_eq!(graph.get_thought(&id("a")).unwrap().tags, vec![tag.clone()]);
+        graph.command(&Command::RemoveTag { id: id("a"), tag: tag.clone() });
+        assert!(graph.get_thought(&id("a")).unwrap().tags.is_empty());
+        
+        graph.command(&Command::AddReference { id: id("a"), reference: Reference::new(id("b"), "first".to_string(), Utc::now()) });
+        graph.command(&Command::AddReference { id: id("a"), reference: Reference::new(id("b"), "second".to_string(), Utc::now()) });
+        let references = &graph.get_thought(&id("a")).unwrap().references;
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].notes, "second");
+        assert_eq!(graph.get_backlinks(&id("b")), vec![id("a")]);
+        
+        graph.command(&Command::RemoveReference { id: id("a"), target: id("b") });
+        assert!(graph.get_thought(&id("a")).unwrap().references.is_empty());
+        assert!(graph.get_backlinks(&id("b")).is_empty());
+        
+        assert!(matches!(
+            graph.try_command(&Command::AddTag { id: id("a"), tag: TagID::new("missing".to_string()) }),
+            Err(ThoughtGraphError::TagNotFound(_))
+        ));
+        assert!(matches!(
+            graph.try_command(&Command::RemoveReference { id: id("missing"), target: id("b") }),
+            Err(ThoughtGraphError::ThoughtNotFound(_))
+        ));
+    }
 }
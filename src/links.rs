@@ -0,0 +1,212 @@
+//! External link extraction and indexing
+//!
+//! Thoughts frequently mention web pages, papers and other external resources. This
+//! module extracts those URLs from thought contents (both Markdown links such as
+//! `[docs](https://example.com)` and bare URLs) and maintains an index from each URL
+//! to the thoughts that mention it.
+//!
+//! The index is derived entirely from thought contents, so it is not persisted; it is
+//! kept up to date by `ThoughtGraph::command` and rebuilt when a graph is loaded.
+
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use crate::{Thought, ThoughtGraph, ThoughtID};
+
+/// Pattern matching the target of a Markdown link, e.g. `[text](https://example.com)`
+fn markdown_link_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"\[[^\]]*\]\(<?([a-zA-Z][a-zA-Z0-9+.-]*://[^)\s>]+)>?\)").unwrap()
+    })
+}
+
+/// Pattern matching a bare URL appearing anywhere in text
+fn bare_url_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r#"\b(?:https?|ftp)://[^\s<>()\[\]"'`]+"#).unwrap())
+}
+
+/// Extract the external URLs mentioned in a piece of text.
+///
+/// Both Markdown link targets and bare `http(s)://`/`ftp://` URLs are recognized.
+/// Trailing sentence punctuation is not considered part of a bare URL. Each URL is
+/// returned once, in order of first appearance.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::links::extract_links;
+///
+/// let links = extract_links("See [the book](https://doc.rust-lang.org/book/) and https://crates.io.");
+/// assert_eq!(links, vec!["https://doc.rust-lang.org/book/", "https://crates.io"]);
+/// ```
+pub fn extract_links(text: &str) -> Vec<String> {
+    let mut found: Vec<(usize, String)> = Vec::new();
+
+    for cap in markdown_link_pattern().captures_iter(text) {
+        let url = cap.get(1).unwrap();
+        found.push((url.start(), url.as_str().to_string()));
+    }
+
+    for m in bare_url_pattern().find_iter(text) {
+        let url = m.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        found.push((m.start(), url.to_string()));
+    }
+
+    found.sort_by_key(|(start, _)| *start);
+
+    let mut seen = HashSet::new();
+    found
+        .into_iter()
+        .map(|(_, url)| url)
+        .filter(|url| seen.insert(url.clone()))
+        .collect()
+}
+
+impl Thought {
+    /// Extract the external URLs mentioned in this thought's content
+    pub fn extract_links(&self) -> Vec<String> {
+        extract_links(&self.contents)
+    }
+}
+
+/// Index from external URLs to the thoughts that mention them
+pub(crate) type LinkIndex = HashMap<String, HashSet<ThoughtID>>;
+
+/// Add the links of a thought to a link index
+pub(crate) fn index_links(index: &mut LinkIndex, id: &ThoughtID, thought: &Thought) {
+    for url in thought.extract_links() {
+        index.entry(url).or_default().insert(id.clone());
+    }
+}
+
+/// Remove the links of a thought from a link index
+pub(crate) fn unindex_links(index: &mut LinkIndex, id: &ThoughtID, thought: &Thought) {
+    for url in thought.extract_links() {
+        if let Some(ids) = index.get_mut(&url) {
+            ids.remove(id);
+            if ids.is_empty() {
+                index.remove(&url);
+            }
+        }
+    }
+}
+
+impl ThoughtGraph {
+    /// Get every external URL in the graph together with the thoughts that mention it.
+    ///
+    /// URLs are sorted alphabetically and the thought IDs for each URL are sorted as well,
+    /// so the output is stable across runs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(
+    ///     ThoughtID::new("reading".to_string()),
+    ///     None,
+    ///     "Read https://example.com/post today".to_string(),
+    ///     vec![],
+    ///     vec![],
+    /// ).unwrap();
+    ///
+    /// let links = graph.external_links();
+    /// assert_eq!(links.len(), 1);
+    /// assert_eq!(links[0].0, "https://example.com/post");
+    /// assert_eq!(links[0].1[0].as_str(), "reading");
+    /// ```
+    pub fn external_links(&self) -> Vec<(&str, Vec<&ThoughtID>)> {
+        let mut links: Vec<(&str, Vec<&ThoughtID>)> = self
+            .link_index
+            .iter()
+            .map(|(url, ids)| {
+                let mut ids: Vec<&ThoughtID> = ids.iter().collect();
+                ids.sort();
+                (url.as_str(), ids)
+            })
+            .collect();
+        links.sort_by(|a, b| a.0.cmp(b.0));
+        links
+    }
+
+    /// Get the thoughts that mention the given URL, sorted by ID
+    pub fn thoughts_linking_to(&self, url: &str) -> Vec<&ThoughtID> {
+        let mut ids: Vec<&ThoughtID> = self
+            .link_index
+            .get(url)
+            .map(|ids| ids.iter().collect())
+            .unwrap_or_default();
+        ids.sort();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+
+    fn put(graph: &mut ThoughtGraph, id: &str, contents: &str) -> ThoughtID {
+        let thought_id = ThoughtID::new(id.to_string());
+        graph.command(&Command::PutThought {
+            id: thought_id.clone(),
+            thought: Thought::new(None, contents.to_string(), vec![], vec![]),
+        });
+        thought_id
+    }
+
+    #[test]
+    fn test_extract_markdown_and_bare_links() {
+        let links = extract_links(
+            "Docs: [guide](https://example.com/guide), <https://example.org>, \
+             ftp://files.example.com/a.txt! and again https://example.com/guide",
+        );
+        assert_eq!(
+            links,
+            vec![
+                "https://example.com/guide",
+                "https://example.org",
+                "ftp://files.example.com/a.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_links() {
+        assert!(extract_links("Just [a-thought] reference and no urls").is_empty());
+    }
+
+    #[test]
+    fn test_index_follows_updates_and_deletes() {
+        let mut graph = ThoughtGraph::new();
+        let a = put(&mut graph, "a", "https://one.example");
+        let b = put(&mut graph, "b", "https://one.example and https://two.example");
+
+        assert_eq!(graph.thoughts_linking_to("https://one.example"), vec![&a, &b]);
+        assert_eq!(graph.external_links().len(), 2);
+
+        // Replacing a thought's content drops its old links
+        put(&mut graph, "b", "no more links");
+        assert_eq!(graph.thoughts_linking_to("https://one.example"), vec![&a]);
+        assert!(graph.thoughts_linking_to("https://two.example").is_empty());
+
+        graph.command(&Command::DeleteThought { id: a.clone() });
+        assert!(graph.external_links().is_empty());
+    }
+
+    #[test]
+    fn test_index_rebuilt_after_load() {
+        let mut graph = ThoughtGraph::new();
+        put(&mut graph, "a", "https://one.example");
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        graph.save_to_file(file.path()).unwrap();
+        let loaded = ThoughtGraph::load_from_file(file.path()).unwrap();
+
+        assert_eq!(loaded.thoughts_linking_to("https://one.example").len(), 1);
+    }
+}
@@ -0,0 +1,426 @@
+//! Optional language model integration
+//!
+//! This module (enabled with the `llm` feature) lets a language model help with the
+//! chores of note taking: summarizing a thought, proposing a title for it, and suggesting
+//! which existing thoughts it should reference.
+//!
+//! Models are reached through the `LlmProvider` trait. `OpenAiCompatible` implements it
+//! for any server speaking the OpenAI chat completions API, which covers hosted services
+//! as well as local runners such as Ollama or llama.cpp.
+
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::env;
+use std::time::Duration;
+
+use crate::semantic::Embedder;
+use crate::text::tokenize;
+use crate::{Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Base URL used when `THOUGHTS_LLM_BASE_URL` is not set
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Model used when `THOUGHTS_LLM_MODEL` is not set
+pub const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+/// Embedding model used when `THOUGHTS_EMBEDDING_MODEL` is not set
+pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// Maximum number of texts sent in a single embeddings request
+const EMBEDDING_BATCH_SIZE: usize = 64;
+
+/// Maximum number of candidate thoughts offered to the model when suggesting references
+const MAX_REFERENCE_CANDIDATES: usize = 50;
+
+/// Maximum number of characters of a thought's content included in a prompt
+const MAX_PROMPT_CONTENT: usize = 8000;
+
+/// A language model that can complete a prompt
+pub trait LlmProvider {
+    /// Complete a prompt given a system instruction, returning the model's reply
+    fn complete(&self, system: &str, prompt: &str) -> Result<String>;
+}
+
+/// A provider for servers implementing the OpenAI chat completions API
+#[derive(Clone, Debug)]
+pub struct OpenAiCompatible {
+    /// Base URL of the API, e.g. `https://api.openai.com/v1`
+    pub base_url: String,
+    /// Name of the model to use
+    pub model: String,
+    /// API key sent as a bearer token, if the server requires one
+    pub api_key: Option<String>,
+    /// Timeout for a single request
+    pub timeout: Duration,
+}
+
+impl OpenAiCompatible {
+    /// Creates a provider for the given server and model
+    pub fn new(base_url: String, model: String) -> Self {
+        Self {
+            base_url,
+            model,
+            api_key: None,
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the API key used to authenticate requests
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    /// Creates a provider configured from the environment.
+    ///
+    /// * `THOUGHTS_LLM_BASE_URL` - API base URL (default: `DEFAULT_BASE_URL`)
+    /// * `THOUGHTS_LLM_MODEL` - model name (default: `DEFAULT_MODEL`)
+    /// * `THOUGHTS_LLM_API_KEY` or `OPENAI_API_KEY` - API key
+    ///
+    /// An API key is required unless a custom base URL is configured, since local
+    /// servers usually do not need one.
+    pub fn from_env() -> Result<Self> {
+        let base_url = env::var("THOUGHTS_LLM_BASE_URL").ok();
+        let model = env::var("THOUGHTS_LLM_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string());
+        let api_key = env::var("THOUGHTS_LLM_API_KEY")
+            .or_else(|_| env::var("OPENAI_API_KEY"))
+            .ok();
+
+        if base_url.is_none() && api_key.is_none() {
+            return Err(ThoughtGraphError::LlmError(
+                "no API key configured; set THOUGHTS_LLM_API_KEY or THOUGHTS_LLM_BASE_URL".to_string(),
+            ));
+        }
+
+        let provider = Self::new(base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()), model);
+        Ok(match api_key {
+            Some(key) => provider.with_api_key(key),
+            None => provider,
+        })
+    }
+}
+
+/// The parts of a chat completions response that are used
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: String,
+}
+
+impl LlmProvider for OpenAiCompatible {
+    fn complete(&self, system: &str, prompt: &str) -> Result<String> {
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let body = serde_json::json!({
+            "model": self.model,
+            "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": prompt },
+            ],
+        });
+
+        let mut request = ureq::post(&url).timeout(self.timeout);
+        if let Some(key) = &self.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", key));
+        }
+
+        let response: ChatResponse = request
+            .send_json(body)
+            .map_err(|e| ThoughtGraphError::LlmError(e.to_string()))?
+            .into_json()?;
+
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content.trim().to_string())
+            .ok_or_else(|| ThoughtGraphError::LlmError("response contained no choices".to_string()))
+    }
+}
+
+/// An embedder using the embeddings endpoint of an OpenAI-compatible API
+#[derive(Clone, Debug)]
+pub struct OpenAiEmbedder {
+    /// Connection settings; the `model` field names the embedding model
+    pub client: OpenAiCompatible,
+}
+
+impl OpenAiEmbedder {
+    /// Creates an embedder configured from the environment.
+    ///
+    /// Uses the same variables as `OpenAiCompatible::from_env`, except that the model is
+    /// read from `THOUGHTS_EMBEDDING_MODEL` (default: `DEFAULT_EMBEDDING_MODEL`).
+    pub fn from_env() -> Result<Self> {
+        let mut client = OpenAiCompatible::from_env()?;
+        client.model = env::var("THOUGHTS_EMBEDDING_MODEL")
+            .unwrap_or_else(|_| DEFAULT_EMBEDDING_MODEL.to_string());
+        Ok(Self { client })
+    }
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl Embedder for OpenAiEmbedder {
+    fn name(&self) -> String {
+        format!("openai:{}@{}", self.client.model, self.client.base_url)
+    }
+
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/embeddings", self.client.base_url.trim_end_matches('/'));
+        let mut vectors = Vec::with_capacity(texts.len());
+
+        for batch in texts.chunks(EMBEDDING_BATCH_SIZE) {
+            let inputs: Vec<&str> = batch.iter().map(|text| prompt_content(text)).collect();
+            let body = serde_json::json!({ "model": self.client.model, "input": inputs });
+
+            let mut request = ureq::post(&url).timeout(self.client.timeout);
+            if let Some(key) = &self.client.api_key {
+                request = request.set("Authorization", &format!("Bearer {}", key));
+            }
+
+            let mut response: EmbeddingResponse = request
+                .send_json(body)
+                .map_err(|e| ThoughtGraphError::LlmError(e.to_string()))?
+                .into_json()?;
+            if response.data.len() != batch.len() {
+                return Err(ThoughtGraphError::LlmError(format!(
+                    "expected {} embeddings, got {}",
+                    batch.len(),
+                    response.data.len()
+                )));
+            }
+
+            response.data.sort_by_key(|data| data.index);
+            vectors.extend(response.data.into_iter().map(|data| data.embedding));
+        }
+
+        Ok(vectors)
+    }
+}
+
+/// Truncate content to a size that comfortably fits in a prompt
+fn prompt_content(contents: &str) -> &str {
+    match contents.char_indices().nth(MAX_PROMPT_CONTENT) {
+        Some((end, _)) => &contents[..end],
+        None => contents,
+    }
+}
+
+/// Format a thought for inclusion in a prompt
+fn describe_thought(thought: &Thought) -> String {
+    match &thought.title {
+        Some(title) => format!("Title: {}\n\n{}", title, prompt_content(&thought.contents)),
+        None => prompt_content(&thought.contents).to_string(),
+    }
+}
+
+/// Ask the model for a short title for the given content.
+///
+/// Surrounding quotes and a trailing period are removed from the reply.
+pub fn suggest_title(provider: &dyn LlmProvider, contents: &str) -> Result<String> {
+    let reply = provider.complete(
+        "You write concise titles for personal notes. Reply with the title only, \
+         at most eight words, without quotes.",
+        prompt_content(contents),
+    )?;
+
+    let title = reply.lines().next().unwrap_or_default();
+    Ok(title.trim().trim_matches(['"', '\'']).trim_end_matches('.').to_string())
+}
+
+impl ThoughtGraph {
+    /// Summarize a thought using a language model.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The language model to use
+    /// * `id` - The ID of the thought to summarize
+    ///
+    /// # Returns
+    ///
+    /// The model's summary, or `ThoughtNotFound` if the thought does not exist
+    pub fn summarize_thought(&self, provider: &dyn LlmProvider, id: &ThoughtID) -> Result<String> {
+        let thought = self
+            .get_thought(id)
+            .ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+
+        provider.complete(
+            "You summarize personal notes. Reply with a summary of two or three sentences.",
+            &describe_thought(thought),
+        )
+    }
+
+    /// Ask a language model which existing thoughts a thought should reference.
+    ///
+    /// The model is shown the thought together with the IDs and titles of the thoughts
+    /// that share the most keywords with it. Only IDs of existing thoughts that are not
+    /// already referenced are returned, so a confused model cannot invent references.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The language model to use
+    /// * `id` - The ID of the thought to suggest references for
+    ///
+    /// # Returns
+    ///
+    /// The suggested thought IDs in the order the model gave them, or `ThoughtNotFound`
+    /// if the thought does not exist
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Result, ThoughtGraph, ThoughtID};
+    /// use thoughtgraph::llm::LlmProvider;
+    ///
+    /// struct Canned;
+    /// impl LlmProvider for Canned {
+    ///     fn complete(&self, _system: &str, _prompt: &str) -> Result<String> {
+    ///         Ok("ownership\nmade-up-id".to_string())
+    ///     }
+    /// }
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let ownership = ThoughtID::new("ownership".to_string());
+    /// let note = ThoughtID::new("note".to_string());
+    /// graph.create_thought(ownership.clone(), None, "Rust ownership rules".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(note.clone(), None, "Moving values in Rust".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// assert_eq!(graph.suggest_references(&Canned, &note).unwrap(), vec![ownership]);
+    /// ```
+    pub fn suggest_references(&self, provider: &dyn LlmProvider, id: &ThoughtID) -> Result<Vec<ThoughtID>> {
+        let thought = self
+            .get_thought(id)
+            .ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+        let already_referenced: HashSet<&ThoughtID> = thought.references.iter().map(|r| &r.id).collect();
+
+        // Offer the thoughts sharing the most keywords with this one
+        let tokens: HashSet<String> = tokenize(&describe_thought(thought)).into_iter().collect();
+        let mut candidates: Vec<(usize, &ThoughtID, &Thought)> = self
+            .thoughts
+            .iter()
+            .filter(|(other_id, _)| *other_id != id && !already_referenced.contains(other_id))
+            .map(|(other_id, other)| {
+                let shared = tokenize(&describe_thought(other))
+                    .into_iter()
+                    .collect::<HashSet<String>>()
+                    .intersection(&tokens)
+                    .count();
+                (shared, other_id, other)
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        candidates.truncate(MAX_REFERENCE_CANDIDATES);
+
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut prompt = format!("Note:\n{}\n\nCandidate notes:\n", describe_thought(thought));
+        for (_, other_id, other) in &candidates {
+            prompt.push_str(&format!(
+                "- {}: {}\n",
+                other_id.id,
+                other.title.as_deref().unwrap_or_else(|| other.contents.lines().next().unwrap_or_default())
+            ));
+        }
+
+        let reply = provider.complete(
+            "You help connect personal notes. Given a note and a list of candidate notes, \
+             reply with the IDs of the candidates the note should link to, one per line, \
+             most relevant first. Reply with nothing if none are relevant.",
+            &prompt,
+        )?;
+
+        let candidate_ids: HashSet<&ThoughtID> = candidates.iter().map(|(_, other_id, _)| *other_id).collect();
+        let mut suggestions: Vec<ThoughtID> = Vec::new();
+        for line in reply.lines() {
+            let cleaned = line.trim().trim_start_matches(['-', '*', ' ']).trim_matches(['`', '[', ']']);
+            let candidate = ThoughtID::new(cleaned.split(':').next().unwrap_or_default().trim().to_string());
+            if candidate_ids.contains(&candidate) && !suggestions.contains(&candidate) {
+                suggestions.push(candidate);
+            }
+        }
+
+        Ok(suggestions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// A provider that replays a fixed reply and records the prompts it was given
+    struct Canned {
+        reply: String,
+        prompts: RefCell<Vec<String>>,
+    }
+
+    impl Canned {
+        fn new(reply: &str) -> Self {
+            Self { reply: reply.to_string(), prompts: RefCell::new(Vec::new()) }
+        }
+    }
+
+    impl LlmProvider for Canned {
+        fn complete(&self, _system: &str, prompt: &str) -> Result<String> {
+            self.prompts.borrow_mut().push(prompt.to_string());
+            Ok(self.reply.clone())
+        }
+    }
+
+    fn add(graph: &mut ThoughtGraph, id: &str, title: Option<&str>, contents: &str) -> ThoughtID {
+        let thought_id = ThoughtID::new(id.to_string());
+        graph
+            .create_thought(thought_id.clone(), title.map(String::from), contents.to_string(), vec![], vec![])
+            .unwrap();
+        thought_id
+    }
+
+    #[test]
+    fn test_summarize_sends_title_and_content() {
+        let mut graph = ThoughtGraph::new();
+        let id = add(&mut graph, "a", Some("Retries"), "Retry with exponential backoff");
+        let provider = Canned::new("A note about retries.");
+
+        assert_eq!(graph.summarize_thought(&provider, &id).unwrap(), "A note about retries.");
+        assert!(provider.prompts.borrow()[0].contains("Title: Retries"));
+        assert!(graph.summarize_thought(&provider, &ThoughtID::new("missing".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_suggest_title_cleans_reply() {
+        let provider = Canned::new("\"Backoff Strategies.\"\nextra line");
+        assert_eq!(suggest_title(&provider, "content").unwrap(), "Backoff Strategies");
+    }
+
+    #[test]
+    fn test_suggested_references_are_filtered() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "backoff", Some("Backoff"), "exponential backoff");
+        add(&mut graph, "jitter", None, "jitter for retries");
+        let id = add(&mut graph, "note", None, "retries need backoff and jitter");
+        let provider = Canned::new("- backoff: Backoff\n[jitter]\nnote\ninvented\nbackoff");
+
+        let suggestions = graph.suggest_references(&provider, &id).unwrap();
+        let ids: Vec<&str> = suggestions.iter().map(|s| s.as_str()).collect();
+        assert_eq!(ids, vec!["backoff", "jitter"]);
+    }
+}
@@ -0,0 +1,83 @@
+//! Locked thoughts
+//!
+//! Canonical notes, such as a glossary or a reading list kept for reference, can be
+//! locked with `Command::LockThought` so that bulk operations do not overwrite them by
+//! accident. While a thought is locked, `PutThought`, `DeleteThought` and the commands
+//! that change part of a thought have no effect on it, and `ThoughtGraph::try_command`
+//! rejects them with `ThoughtLocked`. Moving a locked thought, or a thought that a locked
+//! one references or mentions, fails for the same reason. `Command::UnlockThought` lifts
+//! the lock.
+
+use crate::{ThoughtGraph, ThoughtID};
+
+impl ThoughtGraph {
+    /// Whether the thought is locked
+    pub fn is_locked(&self, id: &ThoughtID) -> bool {
+        self.locked.contains(id)
+    }
+
+    /// The locked thoughts, ordered by ID
+    pub fn locked_thoughts(&self) -> Vec<&ThoughtID> {
+        let mut locked: Vec<&ThoughtID> = self.locked.iter().collect();
+        locked.sort();
+        locked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Thought, ThoughtGraphError};
+
+    fn id(name: &str) -> ThoughtID {
+        ThoughtID::new(name.to_string())
+    }
+
+    #[test]
+    fn test_locked_thoughts_cannot_change() {
+        let mut graph = ThoughtGraph::new();
+        graph.create_thought(id("glossary"), None, "Terms".to_string(), vec![], vec![]).unwrap();
+        graph.create_thought(id("draft"), None, "See [glossary]".to_string(), vec![], vec![]).unwrap();
+
+        let changes = graph.try_command(&Command::LockThought { id: id("glossary") }).unwrap();
+        assert_eq!(changes.to_string(), "1 thought locked");
+        assert!(matches!(graph.try_command(&Command::LockThought { id: id("missing") }), Err(ThoughtGraphError::ThoughtNotFound(_))));
+        assert_eq!(graph.locked_thoughts(), vec![&id("glossary")]);
+
+        let put = Command::PutThought { id: id("glossary"), thought: Thought::new(None, "Overwritten".to_string(), vec![], vec![]) };
+        let delete = Command::DeleteThought { id: id("glossary") };
+        let patch = Command::PatchThought {
+            id: id("glossary"),
+            title: Some(Some("Renamed".to_string())),
+            contents: None,
+            add_tags: vec![],
+            remove_tags: vec![],
+            add_references: vec![],
+            remove_references: vec![],
+        };
+        for command in [&put, &delete, &patch] {
+            assert!(matches!(graph.try_command(command), Err(ThoughtGraphError::ThoughtLocked(_))));
+            assert!(graph.command(command).is_empty());
+        }
+        assert_eq!(graph.get_thought(&id("glossary")).unwrap().contents, "Terms");
+        assert!(matches!(graph.move_thought(&id("glossary"), &id("terms")), Err(ThoughtGraphError::ThoughtLocked(_))));
+
+        // Moving the glossary would rewrite the draft that mentions it
+        graph.command(&Command::UnlockThought { id: id("glossary") });
+        graph.command(&Command::LockThought { id: id("draft") });
+        match graph.move_thought(&id("glossary"), &id("terms")) {
+            Err(ThoughtGraphError::ThoughtLocked(locked)) => assert_eq!(locked, "draft"),
+            other => panic!("expected the draft to be locked, got {:?}", other),
+        }
+        graph.command(&Command::UnlockThought { id: id("draft") });
+        graph.command(&Command::LockThought { id: id("glossary") });
+
+        // Locks are saved with the graph
+        let mut graph = ThoughtGraph::from_bytes(&graph.to_bytes().unwrap()).unwrap();
+        assert!(graph.is_locked(&id("glossary")));
+
+        graph.try_command(&Command::UnlockThought { id: id("glossary") }).unwrap();
+        assert!(!graph.try_command(&delete).unwrap().is_empty());
+        assert!(graph.locked_thoughts().is_empty());
+    }
+}
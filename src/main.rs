@@ -1,33 +1,211 @@
 use anyhow::{Context, Result};
-use chrono::Utc;
+use chrono::{Datelike, Duration, Local, Utc};
 use clap::{Parser, Subcommand};
 use colored::*;
 use console::{style, Term};
 use dialoguer::Input;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{self, Write, IsTerminal};
+use std::io::{self, Read, Write, IsTerminal};
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use tempfile::NamedTempFile;
-use thoughtgraph::{Reference, Tag, TagID, Thought, ThoughtGraph, ThoughtID};
+use std::fmt;
+use std::process::{Command, ExitCode};
+use std::sync::OnceLock;
+use std::time::Instant;
+use thoughtgraph::{ChangeSet, Reference, ReferenceKind, Tag, TagID, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+use thoughtgraph::namespace::NAMESPACE_SEPARATOR;
+use thoughtgraph::resurface::Anniversary;
+use thoughtgraph::rules::TagRule;
+use thoughtgraph::semantic::{Embedder, HashingEmbedder, VectorIndex};
+use thoughtgraph::sort::{QueryOptions, SortBy};
 use thoughtgraph::ui;
-use thoughtgraph::visualization::{generate_graph_data, generate_focused_graph};
-
-/// Default filename for the thought graph
-const DEFAULT_FILENAME: &str = "thoughts.bin";
+use thoughtgraph::validate::ValidationOptions;
+use thoughtgraph::cache::DEFAULT_QUERY_CACHE_CAPACITY;
+use thoughtgraph::export::ExporterRegistry;
+use thoughtgraph::config::{self, ColorMode, Config, Profile, GRAPH_FILENAME};
+use thoughtgraph::dates;
+use thoughtgraph::input::ThoughtInput;
+use thoughtgraph::search::SearchOptions;
+use thoughtgraph::fuzzy::DEFAULT_MAX_DISTANCE;
+use thoughtgraph::snapshot::Snapshots;
+#[cfg(feature = "encryption")]
+use thoughtgraph::encryption::{self, GraphKey};
+use thoughtgraph::storage::{FileStorage, DEFAULT_LOCK_TIMEOUT};
+use thoughtgraph::taxonomy;
+use thoughtgraph::template::Template;
+use thoughtgraph::keys::BrowseKeys;
+use thoughtgraph::bibliography::{self, ImportOutcome};
+use thoughtgraph::broken::{BrokenLink, LinkFix, LinkSource};
+use thoughtgraph::hooks::thought_events;
+use thoughtgraph::health;
+use thoughtgraph::hubs::{self, HubThresholds};
+use thoughtgraph::wordcloud;
+use thoughtgraph::visualization::{generate_graph_data, generate_focused_graph, generate_subgraph_data};
 
 /// Maximum length of thought content to display in list view
 const MAX_DISPLAY_LENGTH: usize = 70;
 
+/// Maximum number of results shown by semantic search
+const MAX_SEMANTIC_RESULTS: usize = 10;
+
+/// The order of thoughts listed while browsing: every thought, most recently modified first
+const RECENT_FIRST: QueryOptions = QueryOptions { sort: SortBy::Updated, reverse: false, offset: 0, limit: None };
+
+/// The editor chosen with `--editor`, the profile or the configuration file, which
+/// takes precedence over `$EDITOR`
+static EDITOR: OnceLock<String> = OnceLock::new();
+
+/// The directory of editor templates next to the configuration file
+static EDITOR_TEMPLATES: OnceLock<PathBuf> = OnceLock::new();
+
+/// The stop words of the configuration file, which take the place of the built-in ones
+/// in stemmed searches and in suggestions
+static STOP_WORDS: OnceLock<HashSet<String>> = OnceLock::new();
+
+/// The key of the graph file once an encrypted graph has been unlocked or the graph has
+/// been encrypted, so that it is saved encrypted
+#[cfg(feature = "encryption")]
+static GRAPH_KEY: std::sync::Mutex<Option<GraphKey>> = std::sync::Mutex::new(None);
+
+/// Keyring service under which the keys of encrypted graphs are kept, one per graph file
+#[cfg(feature = "encryption")]
+const KEYRING_SERVICE: &str = "thoughtgraph";
+
+/// Why a command failed, as its exit code, so that scripts can branch on the outcome.
+/// Clap exits with 2 when the arguments are wrong.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Failure {
+    /// Any other error
+    Error = 1,
+    /// A thought, tag, saved query or snapshot does not exist
+    NotFound = 3,
+    /// A command that lists thoughts or words found none
+    NoResults = 4,
+    /// An ID, query, template or other input is invalid
+    Invalid = 5,
+    /// The graph file or the thought is locked, or the graph could not be decrypted
+    Locked = 6,
+    /// The graph file could not be read as a graph
+    Corrupted = 7,
+}
+
+impl Failure {
+    /// The failure behind an error, from the first graph error in its chain
+    fn of(error: &anyhow::Error) -> Failure {
+        if error.is::<NoResults>() {
+            return Failure::NoResults;
+        }
+        if let Some(failed) = error.downcast_ref::<Failed>() {
+            return failed.failure;
+        }
+        let graph_error = error.chain().find_map(|cause| cause.downcast_ref::<ThoughtGraphError>());
+        match graph_error {
+            Some(
+                ThoughtGraphError::ThoughtNotFound(_)
+                | ThoughtGraphError::TagNotFound(_)
+                | ThoughtGraphError::SavedQueryNotFound(_)
+                | ThoughtGraphError::SnapshotNotFound(_),
+            ) => Failure::NotFound,
+            Some(
+                ThoughtGraphError::InvalidThoughtID(_)
+                | ThoughtGraphError::InvalidTagID(_)
+                | ThoughtGraphError::InvalidPattern(_)
+                | ThoughtGraphError::ParseError(_)
+                | ThoughtGraphError::ConfigError(_)
+                | ThoughtGraphError::TransclusionCycle(_)
+                | ThoughtGraphError::HierarchyCycle(_)
+                | ThoughtGraphError::SmartTagCycle(_),
+            ) => Failure::Invalid,
+            Some(
+                ThoughtGraphError::ThoughtLocked(_)
+                | ThoughtGraphError::FileLocked(_)
+                | ThoughtGraphError::Encrypted(_),
+            ) => Failure::Locked,
+            #[cfg(feature = "encryption")]
+            Some(ThoughtGraphError::DecryptionFailed(_)) => Failure::Locked,
+            Some(ThoughtGraphError::SerializationError(_) | ThoughtGraphError::EncodingError(_)) => Failure::Corrupted,
+            _ => Failure::Error,
+        }
+    }
+}
+
+/// Returned by the commands that list thoughts when they found none, after saying so,
+/// to exit with `Failure::NoResults`
+#[derive(Debug)]
+struct NoResults;
+
+impl fmt::Display for NoResults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no thoughts found")
+    }
+}
+
+impl std::error::Error for NoResults {}
+
+/// An error of the command line tool itself rather than of the graph, with the failure
+/// it exits with
+#[derive(Debug)]
+struct Failed {
+    failure: Failure,
+    message: String,
+}
+
+impl Failed {
+    /// Something named on the command line does not exist
+    fn not_found(message: String) -> anyhow::Error {
+        Failed { failure: Failure::NotFound, message }.into()
+    }
+
+    /// An argument is not one of the values it can take
+    fn invalid(message: String) -> anyhow::Error {
+        Failed { failure: Failure::Invalid, message }.into()
+    }
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Failed {}
+
+/// Fail with `NoResults` if a command found no thoughts
+fn require_results<T>(results: &[T]) -> Result<()> {
+    match results.is_empty() {
+        true => Err(NoResults.into()),
+        false => Ok(()),
+    }
+}
+
 /// Command-line arguments
 #[derive(Parser)]
 #[command(author, version, about = "Command-line tool for managing thoughts in a graph", long_about = None)]
 struct Cli {
-    /// Path to the thoughts binary file
+    /// Path to the thoughts binary file (default: $THOUGHTS_FILE, or thoughts.bin in
+    /// $THOUGHTS_DIR or the platform's data directory)
     #[arg(short, long, value_name = "FILE")]
     file: Option<PathBuf>,
 
+    /// Use the graph and settings of a profile in the configuration file (default:
+    /// $THOUGHTS_PROFILE, or the profile last switched to)
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Show IDs in full in tables, instead of shortening them to fit the terminal
+    #[arg(long)]
+    full_ids: bool,
+
+    /// Command used to edit thoughts, which may include arguments, e.g. "code --wait"
+    /// (default: the profile's or configuration's editor, or $EDITOR)
+    #[arg(long, value_name = "COMMAND")]
+    editor: Option<String>,
+
+    /// Path to the configuration file (default: thoughtgraph/config.toml in the
+    /// platform's configuration directory)
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -55,19 +233,96 @@ enum Commands {
         /// IDs of thoughts to reference (can be repeated)
         #[arg(long = "ref")]
         references: Vec<String>,
+
+        /// Ask a language model for a title and references (requires the `llm` feature)
+        #[arg(long)]
+        assist: bool,
+
+        /// Read the whole thought as JSON from a file, or from standard input if no file
+        /// or "-" is given: {"id", "title", "content", "tags", "references", "metadata"}
+        #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-",
+            conflicts_with_all = ["id", "title", "content", "references", "assist"])]
+        from_json: Option<PathBuf>,
+    },
+
+    /// Capture a thought into the inbox, with an ID generated from the time
+    Capture {
+        /// Content of the thought (read from standard input if not given)
+        text: Vec<String>,
+
+        /// Capture the contents of the system clipboard, titled with its first words
+        /// (requires the `clipboard` feature)
+        #[arg(long, conflicts_with = "text")]
+        from_clipboard: bool,
+    },
+
+    /// Append a timestamped block of text to a thought
+    Append {
+        /// ID of the thought to append to
+        id: String,
+
+        /// Text to append (read from standard input, or written in an editor, if not given)
+        text: Vec<String>,
+    },
+
+    /// List the thoughts in the inbox, oldest first
+    Inbox {
+        /// Go through the inbox one thought at a time to tag, link, file or archive it
+        #[arg(long)]
+        triage: bool,
     },
 
     /// List thoughts in the graph
     List {
-        /// Filter by tag
+        /// Only list thoughts with this tag (can be repeated)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
+        /// With several tags, list the thoughts that have all of them (the default)
+        #[arg(long, conflicts_with = "any_tag")]
+        all_tags: bool,
+
+        /// With several tags, list the thoughts that have any of them
+        #[arg(long)]
+        any_tag: bool,
+
+        /// Only list thoughts in this namespace (e.g. projects/alpha/)
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Order of the thoughts: id, title, created, updated (newest first) or backlinks
+        /// (most referenced first)
+        #[arg(long, default_value = "updated")]
+        sort: String,
+
+        /// List the thoughts in the opposite order
+        #[arg(long)]
+        reverse: bool,
+
+        /// Skip this many thoughts from the start of the list
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// List at most this many thoughts
         #[arg(long)]
-        tag: Option<String>,
+        limit: Option<usize>,
+
+        /// Print each thought through a template instead of as a table, e.g.
+        /// "{id}\t{title}\t{updated}" (fields: id, title, content, tags, references,
+        /// backlinks, created, updated)
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
     },
 
     /// View details of a specific thought
     View {
         /// ID of the thought to view
         id: String,
+
+        /// Show the thought as it was at a past date (e.g. "2025-02-01" or "3 days ago"),
+        /// as far back as the history goes
+        #[arg(long)]
+        as_of: Option<String>,
     },
 
     /// Edit an existing thought
@@ -76,7 +331,20 @@ enum Commands {
         id: String,
     },
 
-    /// Delete a thought
+    /// Copy a thought, with a reference back to the original
+    Clone {
+        /// ID of the thought to copy
+        id: String,
+
+        /// ID of the copy (default: the original's ID with "-copy" appended)
+        new_id: Option<String>,
+
+        /// Copy the original's references as well
+        #[arg(long)]
+        with_references: bool,
+    },
+
+    /// Delete a thought, moving it to the trash
     Delete {
         /// ID of the thought to delete
         id: String,
@@ -84,6 +352,46 @@ enum Commands {
         /// Skip confirmation prompt
         #[arg(long)]
         force: bool,
+
+        /// Delete the thought for good instead of moving it to the trash
+        #[arg(long)]
+        permanent: bool,
+    },
+
+    /// Restore a deleted thought from the trash
+    Restore {
+        /// ID of the thought to restore
+        id: String,
+    },
+
+    /// Manage deleted thoughts
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
+    },
+
+    /// Show the recent commands that changed the graph, most recent first
+    History,
+
+    /// Undo the most recent commands that changed the graph
+    Undo {
+        /// How many commands to undo
+        #[arg(default_value = "1")]
+        count: usize,
+    },
+
+    /// Show recent creations, edits, taggings and deletions, most recent first
+    Log {
+        /// Only show activity since a date (e.g. "2025-02-01", "yesterday" or "last monday")
+        /// or for a duration (e.g. "7d")
+        #[arg(long)]
+        since: Option<String>,
+    },
+
+    /// Manage the profiles of the configuration file
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
     },
 
     /// Add a tag to a thought
@@ -108,6 +416,20 @@ enum Commands {
         tag: String,
     },
 
+    /// Make a tag stand for the thoughts a query finds, e.g. "tag:rust AND tag:draft"
+    SmartTag {
+        /// ID of the smart tag
+        tag: String,
+
+        /// The query, written as for 'thoughts query'; leave it out to make the tag an
+        /// ordinary tag again
+        query: Option<String>,
+
+        /// Description of the tag
+        #[arg(long)]
+        description: Option<String>,
+    },
+
     /// Add a reference from one thought to another
     Reference {
         /// ID of the thought that will contain the reference
@@ -121,23 +443,362 @@ enum Commands {
         /// Notes about the reference
         #[arg(long)]
         notes: Option<String>,
+        
+        /// Kind of reference: 'related', 'parent' to make TO the parent of FROM, or 'next' to make TO follow FROM
+        #[arg(long, default_value = "related")]
+        kind: String,
     },
 
     /// Search for thoughts matching a query
     Search {
         /// Search query terms (searches in titles and content)
         query: Vec<String>,
+
+        /// Find thoughts related in meaning rather than containing the terms
+        #[arg(long)]
+        semantic: bool,
+
+        /// Only match text in the same case as the terms
+        #[arg(long, conflicts_with_all = ["ignore_case", "semantic"])]
+        case_sensitive: bool,
+
+        /// Match text in any case (the default)
+        #[arg(long)]
+        ignore_case: bool,
+
+        /// Only match text with the same accents as the terms, so that "café" does not find "cafe"
+        #[arg(long, conflicts_with = "semantic")]
+        match_accents: bool,
+
+        /// Only match whole words, so that "graph" does not find "graphs"
+        #[arg(long, conflicts_with = "semantic")]
+        word: bool,
+
+        /// Match the stems of words, so that "running" finds "runs" and "ran", leaving out
+        /// stop words such as "the"
+        #[arg(long, conflicts_with_all = ["semantic", "case_sensitive", "match_accents"])]
+        stem: bool,
+
+        /// Also find misspellings of the terms, so that "browish" finds "brownish"
+        #[arg(long, conflicts_with_all = ["semantic", "case_sensitive", "match_accents", "word", "stem"])]
+        fuzzy: bool,
+
+        /// How many characters a word may differ from a term by with --fuzzy
+        #[arg(long, default_value_t = DEFAULT_MAX_DISTANCE, requires = "fuzzy")]
+        max_distance: usize,
+
+        /// Order of the results: relevance (best matches, then recently updated and often
+        /// referenced thoughts first), id, title, created, updated or backlinks
+        #[arg(long, default_value = "relevance", conflicts_with = "semantic")]
+        sort: String,
+
+        /// Show the results in the opposite order
+        #[arg(long, conflicts_with = "semantic")]
+        reverse: bool,
+
+        /// Skip this many results from the start
+        #[arg(long, default_value_t = 0, conflicts_with = "semantic")]
+        offset: usize,
+
+        /// Show at most this many results
+        #[arg(long, conflicts_with = "semantic")]
+        limit: Option<usize>,
+
+        /// Print each thought through a template instead of as a table, e.g.
+        /// "{id}\t{title}\t{updated}" (fields: id, title, content, tags, references,
+        /// backlinks, created, updated)
+        #[arg(long, value_name = "TEMPLATE", conflicts_with = "semantic")]
+        format: Option<String>,
+    },
+
+    /// Show thoughts as a tree grouped by namespace
+    Tree {
+        /// Namespace to show (default: all thoughts)
+        namespace: Option<String>,
+    },
+
+    /// Print the hierarchy formed by parent references as a nested Markdown list
+    Outline {
+        /// Only show this thought and its descendants
+        root: Option<String>,
+
+        /// Output file (if not specified, outputs to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Publish a thought as a document, with its citations listed at the end
+    Export {
+        /// ID of the thought to publish, or select several with --tag, --saved, --query or --chain
+        /// (ndjson exports the whole graph if none are selected)
+        id: Option<String>,
+
+        /// Format of the document (markdown, html, ndjson with a JSON object per line, or pdf and epub with the features of the same names)
+        #[arg(short = 'm', long, default_value = "markdown")]
+        format: String,
+
+        /// Title of the document (default: the title of the first thought, or the saved query, query or tags)
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Publish the thoughts with all of these tags (can be repeated)
+        #[arg(long = "tag", conflicts_with = "id")]
+        tags: Vec<String>,
+
+        /// Publish the thoughts found by a saved query
+        #[arg(long, conflicts_with_all = ["id", "tags"])]
+        saved: Option<String>,
+
+        /// Publish the thoughts matching a query, e.g. "tag:rust AND references:cargo"
+        #[arg(long, conflicts_with_all = ["id", "tags", "saved"])]
+        query: Option<String>,
+
+        /// Publish a chain of thoughts, starting here and following the first reference of --kind from each thought
+        #[arg(long, conflicts_with_all = ["id", "tags", "saved", "query"])]
+        chain: Option<String>,
+
+        /// Kind of reference the chain follows (related, parent or next)
+        #[arg(long, default_value = "related", requires = "chain")]
+        kind: String,
+
+        /// Output file (if not specified, outputs to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Concatenate thoughts into one Markdown document, following 'next' references from
+    /// a single thought or taking the thoughts in the order given
+    Compile {
+        /// The first thought, or every thought in order
+        #[arg(required = true)]
+        ids: Vec<String>,
+
+        /// Kind of reference followed from a single thought (related, parent or next)
+        #[arg(long, default_value = "next")]
+        kind: String,
+
+        /// Title of the document
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Output file (if not specified, outputs to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Rename a thought, or move a whole namespace if FROM ends with '/'
+    #[command(name = "mv")]
+    Move {
+        /// Current ID of the thought, or namespace to move
+        from: String,
+
+        /// New ID of the thought, or namespace to move to
+        to: String,
+    },
+
+    /// Lock a thought so that it cannot be edited, retagged, moved or deleted. Without an
+    /// ID, forget the key of an encrypted graph kept in the OS keyring, so that the next
+    /// command asks for its passphrase.
+    Lock {
+        /// ID of the thought to lock
+        id: Option<String>,
+    },
+
+    /// Unlock a locked thought
+    Unlock {
+        /// ID of the thought to unlock
+        id: String,
     },
 
+    /// Encrypt the graph file with a passphrase, or change its passphrase. The passphrase
+    /// is read from $THOUGHTS_NEW_PASSPHRASE when set.
+    #[cfg(feature = "encryption")]
+    Encrypt,
+
+    /// Save the graph file unencrypted again, and forget its key
+    #[cfg(feature = "encryption")]
+    Decrypt,
+
     /// List all available tags
     Tags,
 
+    /// List external URLs mentioned in thoughts
+    Links {
+        /// Only show URLs mentioned by this thought
+        #[arg(long)]
+        thought: Option<String>,
+    },
+
+    /// Show counts of thoughts, tags and references, with charts of tag usage, thoughts
+    /// created per month and connections per thought
+    Stats {
+        /// Number of tags to chart, most used first
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+
+        /// Number of months to chart, up to the latest
+        #[arg(long, default_value_t = 12)]
+        months: usize,
+    },
+
+    /// Score the health of the graph and suggest how to clean it up
+    Health {
+        /// Number of days after which a thought that has not been updated is stale
+        #[arg(long, default_value_t = 365)]
+        stale_days: i64,
+    },
+
+    /// Find thoughts that are long or linked enough to split, and suggest how
+    Hubs {
+        /// Number of references and backlinks from which a thought is a hub
+        #[arg(long, default_value_t = health::HUB_DEGREE)]
+        min_links: usize,
+
+        /// Number of words from which a thought is a hub
+        #[arg(long, default_value_t = hubs::HUB_WORDS)]
+        min_words: usize,
+    },
+
+    /// Show the most used words, as a weighted list or an SVG word cloud
+    Wordcloud {
+        /// Only count the words of thoughts matching a query, e.g. "tag:project-x"
+        #[arg(long)]
+        query: Option<String>,
+
+        /// Number of words to show
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+
+        /// Output format: list (a count and a word per line) or svg
+        #[arg(long, default_value = "list")]
+        format: String,
+
+        /// File to write to (default: standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// List references and [id] mentions of thoughts that do not exist, and fix them
+    CheckLinks {
+        /// Remove every broken reference, and turn broken mentions into plain text
+        #[arg(long, conflicts_with = "stub")]
+        remove: bool,
+
+        /// Create an empty thought for every missing thought
+        #[arg(long)]
+        stub: bool,
+    },
+
+    /// Summarize a thought using a language model
+    #[cfg(feature = "llm")]
+    Summarize {
+        /// ID of the thought to summarize
+        id: String,
+    },
+
+    /// Show thoughts created on this day in previous years
+    #[command(name = "onthisday")]
+    OnThisDay {
+        /// Look at the same day in previous months instead of previous years
+        #[arg(long)]
+        monthly: bool,
+
+        /// Look back from another day than today, e.g. "2025-02-01" or "last friday"
+        #[arg(long)]
+        date: Option<String>,
+    },
+
+    /// Group related thoughts into topics
+    Topics {
+        /// Cluster by similarity of content instead of tags, links and references
+        #[arg(long)]
+        semantic: bool,
+
+        /// Minimum similarity for two thoughts to be related (with --semantic)
+        #[arg(long, default_value = "0.35")]
+        threshold: f32,
+    },
+
+    /// Manage rules that tag thoughts automatically
+    Rules {
+        #[command(subcommand)]
+        command: RuleCommands,
+    },
+
+    /// List the thoughts matching a query, e.g. "tag:rust AND NOT references:cargo"
+    Query {
+        /// The query: id:, tag:, references:, referenced-by:, reachable-from:, reachable-to:
+        /// (with an optional depth, e.g. reachable-from[2]:index), title:, content:,
+        /// title-regex:, content-regex:, notes: (text in the notes of references), tag-glob:,
+        /// id-glob: (with * wildcards), min-backlinks: and max-backlinks: (a number) and is:
+        /// (all, untagged or orphan) conditions
+        /// combined with NOT, AND, OR and parentheses
+        query: String,
+
+        /// Show how each part of the query was evaluated instead of the thoughts
+        #[arg(long)]
+        explain: bool,
+
+        /// Print each thought through a template instead of as a table, e.g.
+        /// "{id}\t{title}\t{updated}" (fields: id, title, content, tags, references,
+        /// backlinks, created, updated)
+        #[arg(long, value_name = "TEMPLATE", conflicts_with = "explain")]
+        format: Option<String>,
+    },
+
+    /// Run a saved query by name
+    Run {
+        /// Name of the saved query
+        name: String,
+
+        /// Print each thought through a template instead of as a table, e.g.
+        /// "{id}\t{title}\t{updated}" (fields: id, title, content, tags, references,
+        /// backlinks, created, updated)
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
+    },
+
+    /// Manage saved queries
+    Saved {
+        #[command(subcommand)]
+        command: SavedCommands,
+    },
+
+    /// Take, list and restore named copies of the graph
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+
+    /// Share the tags of the graph, with their descriptions, hierarchy, colors and aliases
+    Taxonomy {
+        #[command(subcommand)]
+        command: TaxonomyCommands,
+    },
+
+    /// Import thoughts from other programs
+    Import {
+        #[command(subcommand)]
+        command: ImportCommands,
+    },
+
+    /// Apply commands given as JSON, for use by scripts and other programs
+    Apply {
+        /// Read the commands from standard input
+        #[arg(long)]
+        stdin: bool,
+
+        /// Also reject references to thoughts that do not exist
+        #[arg(long)]
+        strict: bool,
+    },
+
     /// Initialize a new empty thought graph
     Init,
     
     /// Visualize the thought graph
     Visualize {
-        /// Format for visualization (dot or json)
+        /// Format for visualization (dot, json, canvas, or another registered format)
         #[arg(short = 'm', long, default_value = "dot")]
         format: String,
 
@@ -145,6 +806,11 @@ enum Commands {
         #[arg(short, long)]
         focus: Option<String>,
 
+        /// Only include the thoughts matching a query, e.g. "tag:rust OR tag:cargo", and
+        /// the references between them
+        #[arg(long, conflicts_with = "focus")]
+        query: Option<String>,
+
         /// Depth limit for focused visualization (default: 1)
         #[arg(short, long, default_value = "1")]
         depth: usize,
@@ -161,49 +827,263 @@ enum Commands {
     Browse,
 }
 
-/// Interactive CLI interface for ThoughtGraph
-fn interactive_mode(file_path: &Path) -> Result<()> {
-    let term = Term::stdout();
-    
-    // Display welcome message
-    term.clear_screen()?;
-    println!("{}", style("Welcome to ThoughtGraph Interactive Mode").bold().cyan());
-    println!("Managing thoughts at: {}", style(file_path.display()).green());
-    println!("");
-    
-    // Load the graph
-    let mut graph = load_or_create_graph(file_path)?;
-    
-    loop {
-        // Display stats
-        let thought_count = graph.thoughts.len();
-        let tag_count = graph.tags.len();
-        println!("\n{} | {} | {}", 
-            style(format!("Thoughts: {}", thought_count)).dim(),
-            style(format!("Tags: {}", tag_count)).dim(),
-            style(format!("File: {}", file_path.display())).dim()
-        );
-        
-        // Show command selector
-        let command_index = ui::command_selector()?;
-        term.clear_screen()?;
-        
-        let result = match command_index {
-            0 => {
-                // Create a new thought
-                let id = Input::with_theme(&ui::get_theme())
-                    .with_prompt("Enter a unique ID for the thought")
-                    .interact()?;
-                
-                let title_str: String = Input::with_theme(&ui::get_theme())
-                    .with_prompt("Enter a title (optional, press Enter to skip)")
-                    .allow_empty(true)
-                    .interact()?;
-                
-                let title = if title_str.is_empty() { None } else { Some(title_str) };
-                
+#[derive(Subcommand)]
+enum RuleCommands {
+    /// List all tagging rules
+    List,
+
+    /// Add or replace a tagging rule
+    Add {
+        /// Name of the rule
+        name: String,
+
+        /// Regular expression matched against titles and content, e.g. "/kubernetes|k8s/"
+        pattern: String,
+
+        /// Tag applied to matching thoughts
+        tag: String,
+    },
+
+    /// Remove a tagging rule
+    Remove {
+        /// Name of the rule to remove
+        name: String,
+    },
+
+    /// Apply all rules to every existing thought
+    Apply,
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// List the thoughts in the trash, most recently deleted first
+    List,
+
+    /// Permanently remove thoughts from the trash
+    Empty {
+        /// Only remove thoughts deleted before a date or longer ago than a duration,
+        /// e.g. "30d", "2w" or "last month"
+        #[arg(long)]
+        older_than: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// List the profiles, marking the one in use
+    List,
+
+    /// Add a profile to the configuration file
+    Create {
+        /// Name of the profile
+        name: String,
+
+        /// Path to the graph file (default: NAME.bin in the platform's data directory)
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+
+        /// Tags to add to every thought created (can be repeated)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
+        /// Command used to edit thoughts, instead of $EDITOR
+        #[arg(long)]
+        editor: Option<String>,
+
+        /// When to color the output: auto, always or never
+        #[arg(long)]
+        color: Option<ColorMode>,
+    },
+
+    /// Use a profile when neither --profile nor $THOUGHTS_PROFILE is given
+    Switch {
+        /// Name of the profile (omit to go back to the default graph)
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportCommands {
+    /// Import the entries of a BibTeX file as thoughts tagged 'reference'
+    Bibtex {
+        /// Path to the .bib file
+        file: PathBuf,
+
+        /// Show what would be created, updated and skipped without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Import a CSV export of a Zotero library as thoughts tagged 'reference'
+    Zotero {
+        /// Path to the .csv file
+        file: PathBuf,
+
+        /// Show what would be created, updated and skipped without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Import tags and thoughts from NDJSON, as written by `export --format ndjson`,
+    /// replacing any with the same IDs
+    Ndjson {
+        /// Path to the .ndjson file, or - for standard input
+        file: PathBuf,
+
+        /// Show how many tags and thoughts would be created and updated without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommands {
+    /// Take a snapshot of the graph, replacing any snapshot with that name
+    Create {
+        /// Name of the snapshot, e.g. "before-cleanup"
+        name: String,
+
+        /// Why the snapshot is taken
+        #[arg(long, short)]
+        reason: Option<String>,
+    },
+
+    /// List the snapshots, oldest first
+    List,
+
+    /// Replace the graph with a snapshot, after taking a snapshot of it as it is
+    Restore {
+        /// Name of the snapshot to restore
+        name: String,
+    },
+
+    /// Remove a snapshot
+    Remove {
+        /// Name of the snapshot to remove
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TaxonomyCommands {
+    /// Write the tags of the graph to a TOML or JSON file
+    Export {
+        /// Path of the file to write (default: standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Format of the file (toml or json; default: from the extension of --output, else toml)
+        #[arg(short = 'm', long)]
+        format: Option<String>,
+    },
+
+    /// Create and update tags from a TOML or JSON file, keeping the other tags
+    Import {
+        /// Path to the .toml or .json file
+        file: PathBuf,
+
+        /// Show which tags would be created and updated without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SavedCommands {
+    /// List all saved queries
+    List,
+
+    /// Run a saved query by name, as 'thoughts run' does
+    Run {
+        /// Name of the saved query
+        name: String,
+
+        /// Print each thought through a template instead of as a table, e.g.
+        /// "{id}\t{title}\t{updated}" (fields: id, title, content, tags, references,
+        /// backlinks, created, updated)
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
+    },
+
+    /// Save a query under a name, replacing any query with that name
+    Add {
+        /// Name of the query, e.g. "inbox"
+        name: String,
+
+        /// The query, written as for 'thoughts query', e.g. "tag:project AND NOT tag:done"
+        #[arg(long, conflicts_with_all = ["tags", "references", "referenced_by", "any"])]
+        query: Option<String>,
+
+        /// Match thoughts with this tag (can be repeated)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
+        /// Match thoughts that reference this thought (can be repeated)
+        #[arg(long)]
+        references: Vec<String>,
+
+        /// Match thoughts referenced by this thought (can be repeated)
+        #[arg(long)]
+        referenced_by: Vec<String>,
+
+        /// Match thoughts meeting any of the conditions, instead of all of them
+        #[arg(long)]
+        any: bool,
+    },
+
+    /// Remove a saved query
+    Remove {
+        /// Name of the query to remove
+        name: String,
+    },
+}
+
+/// Interactive CLI interface for ThoughtGraph
+fn interactive_mode(file_path: &Path, config: &Config, default_tags: &[String]) -> Result<()> {
+    let term = Term::stdout();
+    
+    // Display welcome message
+    term.clear_screen()?;
+    println!("{}", style("Welcome to ThoughtGraph Interactive Mode").bold().cyan());
+    println!("Managing thoughts at: {}", style(file_path.display()).green());
+    println!();
+    
+    // Load the graph; the same queries run repeatedly in a session, so cache them
+    let mut graph = load_or_create_graph(file_path)?;
+    graph.enable_query_cache(DEFAULT_QUERY_CACHE_CAPACITY);
+    
+    loop {
+        // Display stats
+        let thought_count = graph.thoughts.len();
+        let tag_count = graph.tags.len();
+        println!("\n{} | {} | {}", 
+            style(format!("Thoughts: {}", thought_count)).dim(),
+            style(format!("Tags: {}", tag_count)).dim(),
+            style(format!("File: {}", file_path.display())).dim()
+        );
+        
+        // Show command selector
+        let command_index = ui::command_selector()?;
+        term.clear_screen()?;
+        let before = config.watches_changes().then(|| graph.clone());
+        graph.start_recording();
+        
+        let result = match command_index {
+            0 => {
+                // Create a new thought
+                let id = Input::with_theme(&ui::get_theme())
+                    .with_prompt("Enter a unique ID for the thought")
+                    .interact()?;
+                
+                let title_str: String = Input::with_theme(&ui::get_theme())
+                    .with_prompt("Enter a title (optional, press Enter to skip)")
+                    .allow_empty(true)
+                    .interact()?;
+                
+                let title = if title_str.is_empty() { None } else { Some(title_str) };
+                
                 // Get content by opening an editor
-                let content = edit_in_external_editor("", "# Enter your thought content here")?;
+                let content = edit_in_external_editor(&new_text("create", "Enter your thought content here")?)?;
                 
                 // Suggest tags based on existing ones
                 let tags = if tag_count > 0 {
@@ -238,22 +1118,22 @@ fn interactive_mode(file_path: &Path) -> Result<()> {
                 
                 // Create the thought
                 create_thought(&mut graph, Some(id), title, Some(content), 
-                    tags.iter().map(|t| t.id.clone()).collect(), 
-                    references.iter().map(|r| r.id.id.clone()).collect())
+                    with_default_tags(default_tags, tags.iter().map(|t| t.id.to_string()).collect()), 
+                    references.iter().map(|r| r.id.id.to_string()).collect(), false)
             },
             1 => {
                 // List thoughts
                 if tag_count > 0 && ui::confirm("Would you like to filter by tag?", false)? {
                     let (tag_id, _) = ui::tag_selector(&graph)?;
-                    list_thoughts(&graph, Some(tag_id.id))
+                    list_thoughts(&mut graph, vec![tag_id.id.to_string()], false, None, RECENT_FIRST, None, &config.browse)
                 } else {
-                    list_thoughts(&graph, None)
+                    list_thoughts(&mut graph, vec![], false, None, RECENT_FIRST, None, &config.browse)
                 }
             },
             2 => {
                 // View thought
                 if let Some(id) = ui::select_thought(&graph, "Select a thought to view")? {
-                    view_thought(&graph, &id.id)
+                    view_thought(&mut graph, &id.id, &config.browse)
                 } else {
                     println!("No thought selected.");
                     Ok(())
@@ -271,7 +1151,7 @@ fn interactive_mode(file_path: &Path) -> Result<()> {
             4 => {
                 // Delete thought
                 if let Some(id) = ui::select_thought(&graph, "Select a thought to delete")? {
-                    delete_thought(&mut graph, &id.id, false)
+                    delete_thought(&mut graph, &id.id, false, false)
                 } else {
                     println!("No thought selected.");
                     Ok(())
@@ -344,7 +1224,7 @@ fn interactive_mode(file_path: &Path) -> Result<()> {
                     
                     let notes = if notes.is_empty() { None } else { Some(notes) };
                     
-                    add_reference(&mut graph, &from_id.id, &to_id.id, notes)
+                    add_reference(&mut graph, &from_id.id, &to_id.id, notes, ReferenceKind::Related)
                 }
             },
             8 => {
@@ -353,15 +1233,15 @@ fn interactive_mode(file_path: &Path) -> Result<()> {
                     .with_prompt("Enter search terms")
                     .interact()?;
                 
-                search_thoughts(&graph, &query.split_whitespace().map(String::from).collect::<Vec<_>>())
+                search_thoughts(&mut graph, &query.split_whitespace().map(String::from).collect::<Vec<_>>(), TermMatch::Exact(search_options()), QueryOptions::default(), true, None, &config.browse)
             },
             9 => {
                 // Browse thoughts interactively
-                ui::browse_thoughts(&graph)
+                ui::browse_thoughts(&mut graph, None, &config.browse, &mut |g, id| edit_thought(g, &id.id))
             },
             10 => {
                 // List tags
-                list_tags(&graph)
+                list_tags(&mut graph, &config.browse)
             },
             11 => {
                 // Visualize
@@ -405,9 +1285,13 @@ fn interactive_mode(file_path: &Path) -> Result<()> {
                     None
                 };
                 
-                visualize_graph(&graph, format, None, depth, output)
+                visualize_graph(&graph, format, None, None, depth, output)
             },
-            12 | _ => {
+            12 => {
+                // Browse namespaces
+                show_namespace_tree(&graph, "")
+            },
+            _ => {
                 // Exit
                 if ui::confirm("Are you sure you want to exit?", false)? {
                     return Ok(());
@@ -417,11 +1301,15 @@ fn interactive_mode(file_path: &Path) -> Result<()> {
             }
         };
         
+        // Changes made before a command failed stay in the graph, so journal them either way
+        graph.finish_recording(format!("interactive: {}", ui::INTERACTIVE_COMMANDS[command_index]), Utc::now());
+
         // Save graph changes if the command succeeded
         if result.is_ok() {
-            ui::with_loading_progress("Saving changes...", || {
-                graph.save_to_file(file_path)
-            })?;
+            save_graph(&graph, file_path)?;
+            if let Some(before) = &before {
+                run_hooks(config, before, &graph);
+            }
 
             // Add a pause after successful commands so users can see the output
             println!("\n{}", style("Press any key to continue...").dim());
@@ -436,11 +1324,85 @@ fn interactive_mode(file_path: &Path) -> Result<()> {
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if !e.is::<NoResults>() {
+                eprintln!("Error: {:?}", e);
+            }
+            ExitCode::from(Failure::of(&e) as u8)
+        }
+    }
+}
+
+/// Run the command given on the command line
+impl Commands {
+    /// Whether the command only reads the graph. Commands that can browse thoughts and
+    /// edit them from there only read it when there is no terminal to browse from.
+    fn reads_only(&self) -> bool {
+        match self {
+            Commands::Tree { .. }
+            | Commands::Outline { .. }
+            | Commands::Export { .. }
+            | Commands::Compile { .. }
+            | Commands::View { as_of: Some(_), .. }
+            | Commands::History
+            | Commands::Log { .. }
+            | Commands::Links { .. }
+            | Commands::Stats { .. }
+            | Commands::Health { .. }
+            | Commands::Hubs { .. }
+            | Commands::Wordcloud { .. }
+            | Commands::OnThisDay { .. }
+            | Commands::Topics { .. }
+            | Commands::Query { .. }
+            | Commands::Run { .. }
+            | Commands::Visualize { .. }
+            | Commands::Lock { id: None } => true,
+            #[cfg(feature = "llm")]
+            Commands::Summarize { .. } => true,
+            Commands::List { .. } | Commands::Search { .. } | Commands::View { .. } | Commands::Tags => {
+                !io::stdin().is_terminal()
+            },
+            _ => false,
+        }
+    }
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
     
-    // Determine file path: either from argument or default
-    let file_path = match cli.file {
+    let config_path = cli.config.or_else(Config::default_path);
+    let config = match &config_path {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    ui::set_date_format(config.dates.clone());
+    ui::show_full_ids(cli.full_ids);
+    let var = |name: &str| std::env::var(name).ok();
+    let profile = config.active_profile(cli.profile.as_deref(), var)?.map(|(name, profile)| (name, profile.clone()));
+    if let Some((_, profile)) = &profile {
+        use_profile_settings(profile);
+    }
+    let profile_editor = profile.as_ref().and_then(|(_, profile)| profile.editor.clone());
+    if let Some(editor) = cli.editor.or(profile_editor).or_else(|| config.editor.clone()) {
+        config::editor_command(&editor)?;
+        EDITOR.get_or_init(|| editor);
+    }
+    if let Some(dir) = config_path.as_deref().and_then(Path::parent) {
+        EDITOR_TEMPLATES.get_or_init(|| dir.join(config::TEMPLATES_DIRNAME));
+    }
+    if let Some(stop_words) = config.search.stop_word_set() {
+        STOP_WORDS.get_or_init(|| stop_words);
+    }
+    if let Commands::Profile { command } = cli.command {
+        return manage_profiles(command, &config, config_path.as_deref(), profile.map(|(name, _)| name));
+    }
+    let default_tags = profile.map(|(_, profile)| profile.tags).unwrap_or_default();
+
+    // Determine file path: from the arguments, the environment, or the default
+    let file_path = match config.graph_path(cli.file, cli.profile.as_deref(), var)? {
         Some(path) => path,
         None => {
             let data_dir = dirs::data_dir()
@@ -448,44 +1410,143 @@ fn main() -> Result<()> {
             let app_dir = data_dir.join("thoughtgraph");
             fs::create_dir_all(&app_dir)
                 .context("Failed to create application data directory")?;
-            app_dir.join(DEFAULT_FILENAME)
+            app_dir.join(GRAPH_FILENAME)
         }
     };
     
+    // Other instances wait for this one to finish with the graph, so that they do not
+    // overwrite each other's changes. Commands that only read it share it with each other,
+    // and do not save it.
+    let storage = FileStorage::new(&file_path);
+    let read_only = cli.command.reads_only();
+    let _lock = match read_only {
+        true => storage.lock_shared(DEFAULT_LOCK_TIMEOUT)?,
+        false => storage.lock(DEFAULT_LOCK_TIMEOUT)?,
+    };
     match cli.command {
         Commands::Init => init_graph(&file_path),
-        Commands::Interactive => interactive_mode(&file_path),
-        Commands::Browse => {
-            let graph = load_or_create_graph(&file_path)?;
-            ui::browse_thoughts(&graph)
-        },
+        Commands::Interactive => interactive_mode(&file_path, &config, &default_tags),
+        Commands::Lock { id: None } => forget_graph_key(&file_path),
         _ => {
             // For all other commands, load the existing graph or create a new one
             let mut graph = load_or_create_graph(&file_path)?;
+            let before = config.watches_changes().then(|| graph.clone());
+            if !matches!(cli.command, Commands::History | Commands::Undo { .. } | Commands::Log { .. }) {
+                graph.start_recording();
+            }
             
             let result = match cli.command {
-                Commands::Create { id, title, content, tags, references } => {
-                    create_thought(&mut graph, id, title, content, tags, references)
+                Commands::Create { from_json: Some(file), tags, .. } => {
+                    create_thought_from_json(&mut graph, &file, with_default_tags(&default_tags, tags))
                 }
-                Commands::List { tag } => list_thoughts(&graph, tag),
-                Commands::View { id } => view_thought(&graph, &id),
+                Commands::Create { id, title, content, tags, references, assist, from_json: None } => {
+                    create_thought(&mut graph, id, title, content, with_default_tags(&default_tags, tags), references, assist)
+                }
+                Commands::Capture { text, from_clipboard } => capture_thought(&mut graph, text, from_clipboard),
+                Commands::Append { id, text } => append_to_thought(&mut graph, &id, text),
+                Commands::Inbox { triage } => show_inbox(&mut graph, triage),
+                Commands::List { tags, all_tags: _, any_tag, namespace, sort, reverse, offset, limit, format } => {
+                    let template = format.as_deref().map(Template::parse).transpose()?;
+                    parse_sort(&sort).and_then(|sort| {
+                        let page = QueryOptions { sort, reverse, offset, limit };
+                        list_thoughts(&mut graph, tags, any_tag, namespace, page, template.as_ref(), &config.browse)
+                    })
+                }
+                Commands::Tree { namespace } => show_namespace_tree(&graph, namespace.as_deref().unwrap_or("")),
+                Commands::Outline { root, output } => export_outline(&graph, root, output),
+                Commands::Export { id, format, title: _, tags, saved, query, chain, kind, output } if format == "ndjson" => {
+                    let selected = id.is_some() || !tags.is_empty() || saved.is_some() || query.is_some() || chain.is_some();
+                    match selected {
+                        true => select_thoughts(&graph, id, tags, saved, query, chain, &kind).and_then(|(ids, _)| export_ndjson(&graph, Some(&ids), output)),
+                        false => export_ndjson(&graph, None, output),
+                    }
+                }
+                Commands::Export { id, format, title, tags, saved, query, chain, kind, output } => {
+                    select_thoughts(&graph, id, tags, saved, query, chain, &kind)
+                        .and_then(|(ids, name)| export_thoughts(&graph, &ids, &format, &title.unwrap_or(name), output))
+                }
+                Commands::Compile { ids, kind, title, output } => compile_document(&graph, ids, &kind, title, output),
+                Commands::Move { from, to } => move_thoughts(&mut graph, &from, &to),
+                Commands::Lock { id: Some(id) } => lock_thought(&mut graph, &id, true),
+                Commands::Unlock { id } => lock_thought(&mut graph, &id, false),
+                Commands::View { id, as_of: Some(date) } => view_thought_as_of(&graph, &id, &date),
+                Commands::View { id, as_of: None } => view_thought(&mut graph, &id, &config.browse),
+                Commands::Browse => {
+                    ui::browse_thoughts(&mut graph, None, &config.browse, &mut |g, id| edit_thought(g, &id.id))
+                },
                 Commands::Edit { id } => edit_thought(&mut graph, &id),
-                Commands::Delete { id, force } => delete_thought(&mut graph, &id, force),
+                Commands::Clone { id, new_id, with_references } => clone_thought(&mut graph, &id, new_id, with_references),
+                Commands::Delete { id, force, permanent } => delete_thought(&mut graph, &id, force, permanent),
+                Commands::Restore { id } => restore_thought(&mut graph, &id),
+                Commands::Trash { command } => manage_trash(&mut graph, command),
+                Commands::History => show_history(&graph),
+                Commands::Undo { count } => undo_commands(&mut graph, count),
+                Commands::Log { since } => show_log(&graph, since),
                 Commands::Tag { id, tag, description } => tag_thought(&mut graph, &id, &tag, description),
                 Commands::Untag { id, tag } => untag_thought(&mut graph, &id, &tag),
-                Commands::Reference { from_id, to_id, notes } => add_reference(&mut graph, &from_id, &to_id, notes),
-                Commands::Search { query } => search_thoughts(&graph, &query),
-                Commands::Tags => list_tags(&graph),
-                Commands::Visualize { format, focus, depth, output } => 
-                    visualize_graph(&graph, &format, focus, depth, output),
-                Commands::Init | Commands::Interactive | Commands::Browse => unreachable!(), // Handled above
+                Commands::SmartTag { tag, query, description } => set_smart_tag(&mut graph, &tag, query, description),
+                Commands::Reference { from_id, to_id, notes, kind } => {
+                    parse_reference_kind(&kind).and_then(|kind| add_reference(&mut graph, &from_id, &to_id, notes, kind))
+                }
+                Commands::Search { query, semantic, case_sensitive, ignore_case: _, match_accents, word, stem, fuzzy, max_distance, sort, reverse, offset, limit, format } => {
+                    if semantic {
+                        semantic_search(&graph, &file_path, &query)
+                    } else {
+                        let matching = match fuzzy {
+                            true => TermMatch::Fuzzy(max_distance),
+                            false => TermMatch::Exact(SearchOptions { case_sensitive, diacritic_sensitive: match_accents, whole_word: word, stem, ..search_options() }),
+                        };
+                        // Relevance is not an order of thoughts by themselves, so it is kept apart
+                        let ranked = sort.eq_ignore_ascii_case("relevance");
+                        let sort = if ranked { SortBy::default() } else { parse_sort(&sort)? };
+                        let page = QueryOptions { sort, reverse, offset, limit };
+                        let template = format.as_deref().map(Template::parse).transpose()?;
+                        search_thoughts(&mut graph, &query, matching, page, ranked, template.as_ref(), &config.browse)
+                    }
+                }
+                Commands::Tags => list_tags(&mut graph, &config.browse),
+                Commands::Links { thought } => list_links(&graph, thought),
+                Commands::CheckLinks { remove, stub } => check_links(&mut graph, remove, stub),
+                Commands::Stats { top, months } => show_stats(&graph, top, months),
+                Commands::Health { stale_days } => show_health(&graph, stale_days),
+                Commands::Hubs { min_links, min_words } => show_hubs(&graph, HubThresholds { min_degree: min_links, min_words }),
+                Commands::Wordcloud { query, limit, format, output } => word_cloud(&graph, query, limit, &format, output),
+                Commands::OnThisDay { monthly, date } => on_this_day(&graph, monthly, date),
+                Commands::Topics { semantic, threshold } => list_topics(&graph, &file_path, semantic, threshold),
+                Commands::Rules { command } => manage_rules(&mut graph, command),
+                Commands::Query { query, explain, format } => {
+                    let template = format.as_deref().map(Template::parse).transpose()?;
+                    run_query(&graph, &query, explain, template.as_ref())
+                }
+                Commands::Run { name, format } => {
+                    let template = format.as_deref().map(Template::parse).transpose()?;
+                    run_saved_query(&graph, &name, template.as_ref())
+                }
+                Commands::Saved { command } => manage_saved_queries(&mut graph, command),
+                Commands::Snapshot { command } => manage_snapshots(&mut graph, &file_path, command),
+                Commands::Taxonomy { command } => manage_taxonomy(&mut graph, command),
+                Commands::Import { command } => import(&mut graph, command),
+                Commands::Apply { stdin, strict } => apply_commands(&mut graph, stdin, strict),
+                #[cfg(feature = "llm")]
+                Commands::Summarize { id } => summarize_thought(&graph, &id),
+                Commands::Visualize { format, focus, query, depth, output } => 
+                    visualize_graph(&graph, &format, focus, query, depth, output),
+                #[cfg(feature = "encryption")]
+                Commands::Encrypt => encrypt_graph(&file_path),
+                #[cfg(feature = "encryption")]
+                Commands::Decrypt => decrypt_graph(&file_path),
+                Commands::Init | Commands::Interactive | Commands::Profile { .. } | Commands::Lock { id: None } => {
+                    unreachable!() // Handled above
+                },
             };
             
             // Save graph changes if the command succeeded
-            if result.is_ok() {
-                ui::with_loading_progress("Saving changes...", || {
-                    graph.save_to_file(&file_path)
-                })?;
+            if result.is_ok() && !read_only {
+                graph.finish_recording(command_line(), Utc::now());
+                save_graph(&graph, &file_path)?;
+                if let Some(before) = &before {
+                    run_hooks(&config, before, &graph);
+                }
             }
             
             result
@@ -493,6 +1554,44 @@ fn main() -> Result<()> {
     }
 }
 
+/// The command line that ran the current command, without the options before the
+/// subcommand that choose the files, as recorded in the journal
+fn command_line() -> String {
+    let mut words = vec!["thoughts".to_string()];
+    let mut args = std::env::args().skip(1);
+    let mut in_subcommand = false;
+    while let Some(arg) = args.next() {
+        if !in_subcommand {
+            if matches!(arg.as_str(), "-f" | "--file" | "--profile" | "--editor" | "--config") {
+                args.next();
+                continue;
+            }
+            if arg.starts_with('-') {
+                continue;
+            }
+            in_subcommand = true;
+        }
+        match arg.is_empty() || arg.contains(char::is_whitespace) {
+            true => words.push(format!("{:?}", arg)),
+            false => words.push(arg),
+        }
+    }
+    words.join(" ")
+}
+
+/// Run the configured hooks and webhooks for the changes made by a command, warning
+/// about any that fail; the changes are already saved, so failing hooks do not fail the
+/// command
+fn run_hooks(config: &Config, before: &ThoughtGraph, after: &ThoughtGraph) {
+    if let Err(e) = config.hooks.run(&thought_events(before, after)) {
+        eprintln!("{}", style(format!("Warning: {}", e)).yellow());
+    }
+    #[cfg(feature = "webhooks")]
+    if let Err(e) = config.webhooks.notify(&ChangeSet::between(before, after)) {
+        eprintln!("{}", style(format!("Warning: {}", e)).yellow());
+    }
+}
+
 /// Initialize a new empty thought graph
 fn init_graph(file_path: &Path) -> Result<()> {
     if file_path.exists() {
@@ -520,8 +1619,12 @@ fn init_graph(file_path: &Path) -> Result<()> {
 /// Load an existing graph or create a new one
 fn load_or_create_graph(file_path: &Path) -> Result<ThoughtGraph> {
     if file_path.exists() {
-        ui::with_loading_progress("Loading thought graph...", || {
-            ThoughtGraph::load_from_file(file_path)
+        #[cfg(feature = "encryption")]
+        if encryption::file_is_encrypted(file_path)? {
+            return load_encrypted_graph(file_path);
+        }
+        ui::with_progress_bar("Loading thought graph...", |progress| {
+            ThoughtGraph::load_from_file_with_progress(file_path, progress)
                 .context(format!("Failed to load thought graph from {}", file_path.display()))
         })
     } else {
@@ -534,6 +1637,146 @@ fn load_or_create_graph(file_path: &Path) -> Result<ThoughtGraph> {
     }
 }
 
+/// Save the graph file, encrypted if the graph was loaded encrypted or has been encrypted
+fn save_graph(graph: &ThoughtGraph, file_path: &Path) -> Result<()> {
+    ui::with_progress_bar("Saving changes...", |progress| {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = graph_key() {
+            return graph.save_encrypted_with_progress(file_path, &key, progress);
+        }
+        graph.save_to_file_with_progress(file_path, progress)
+    })?;
+    Ok(())
+}
+
+/// The key of the graph file, if it is encrypted
+#[cfg(feature = "encryption")]
+fn graph_key() -> Option<GraphKey> {
+    GRAPH_KEY.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// Save the graph encrypted with `key` from now on, or unencrypted with `None`, returning
+/// the key it was encrypted with
+#[cfg(feature = "encryption")]
+fn set_graph_key(key: Option<GraphKey>) -> Option<GraphKey> {
+    std::mem::replace(&mut GRAPH_KEY.lock().unwrap_or_else(|poisoned| poisoned.into_inner()), key)
+}
+
+/// The keyring entry holding the key of the graph file
+#[cfg(feature = "encryption")]
+fn keyring_entry(file_path: &Path) -> keyring::Result<keyring::Entry> {
+    let path = file_path.canonicalize().unwrap_or_else(|_| file_path.to_path_buf());
+    keyring::Entry::new(KEYRING_SERVICE, &path.to_string_lossy())
+}
+
+/// Read a passphrase from the environment variable `var`, or else ask for it
+#[cfg(feature = "encryption")]
+fn read_passphrase(var: &str, prompt: &str, confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(var) {
+        return Ok(passphrase);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(ThoughtGraphError::Encrypted(format!("set ${} to give the passphrase without a terminal", var)).into());
+    }
+    let mut password = dialoguer::Password::new().with_prompt(prompt);
+    if confirm {
+        password = password.with_confirmation("Repeat the passphrase", "The passphrases do not match");
+    }
+    Ok(password.interact()?)
+}
+
+/// Load an encrypted graph file with the key kept in the keyring, or else with a key
+/// derived from its passphrase, which is then kept in the keyring
+#[cfg(feature = "encryption")]
+fn load_encrypted_graph(file_path: &Path) -> Result<ThoughtGraph> {
+    let data = fs::read(file_path)?;
+    // The graph may have been encrypted again under another passphrase since the key
+    // was kept
+    let kept = keyring_entry(file_path)
+        .and_then(|entry| entry.get_password())
+        .ok()
+        .and_then(|secret| GraphKey::from_secret(&secret).ok())
+        .filter(|key| key.matches(&data));
+    let (key, derived) = match kept {
+        Some(key) => (key, false),
+        None => {
+            let prompt = format!("Passphrase of {}", file_path.display());
+            let passphrase = read_passphrase("THOUGHTS_PASSPHRASE", &prompt, false)?;
+            let key = ui::with_loading_progress("Unlocking thought graph...", || GraphKey::for_data(&data, &passphrase))?;
+            (key, true)
+        },
+    };
+    let graph = ui::with_loading_progress("Loading thought graph...", || ThoughtGraph::from_encrypted_bytes(&data, &key))
+        .context(format!("Failed to load thought graph from {}", file_path.display()))?;
+    if derived {
+        remember_graph_key(file_path, key);
+    } else {
+        set_graph_key(Some(key));
+    }
+    Ok(graph)
+}
+
+/// Save the graph encrypted with `key` from now on, and keep the key in the keyring,
+/// warning if there is no keyring to keep it in
+#[cfg(feature = "encryption")]
+fn remember_graph_key(file_path: &Path, key: GraphKey) {
+    if let Err(e) = keyring_entry(file_path).and_then(|entry| entry.set_password(&key.to_secret())) {
+        eprintln!("{}", style(format!("Warning: Could not keep the key in the OS keyring ({}), so the passphrase will be asked for again", e)).yellow());
+    }
+    set_graph_key(Some(key));
+}
+
+/// Forget the key of an encrypted graph kept in the keyring, for `thoughts lock` without
+/// an ID
+#[cfg(feature = "encryption")]
+fn forget_graph_key(file_path: &Path) -> Result<()> {
+    match keyring_entry(file_path).and_then(|entry| entry.delete_credential()) {
+        Ok(()) => println!("Forgot the key of {}; the next command asks for its passphrase", file_path.display()),
+        Err(keyring::Error::NoEntry) => println!("No key of {} is kept", file_path.display()),
+        Err(e) => return Err(e).context("Failed to remove the key from the OS keyring"),
+    }
+    Ok(())
+}
+
+/// Without the `encryption` feature there is no key to forget
+#[cfg(not(feature = "encryption"))]
+fn forget_graph_key(_file_path: &Path) -> Result<()> {
+    Err(Failed::invalid("Name the thought to lock".to_string()))
+}
+
+/// Encrypt the graph file with a new passphrase, once the command saves it
+#[cfg(feature = "encryption")]
+fn encrypt_graph(file_path: &Path) -> Result<()> {
+    let changing = graph_key().is_some();
+    let passphrase = read_passphrase("THOUGHTS_NEW_PASSPHRASE", "New passphrase", true)?;
+    if passphrase.is_empty() {
+        return Err(Failed::invalid("The passphrase is empty".to_string()));
+    }
+    let key = ui::with_loading_progress("Deriving the key...", || GraphKey::new(&passphrase))?;
+    remember_graph_key(file_path, key);
+    match changing {
+        true => println!("Changed the passphrase of {}", file_path.display()),
+        false => println!("Encrypted {}", file_path.display()),
+    }
+    println!("{}", style("Snapshots taken before are not encrypted again").dim());
+    Ok(())
+}
+
+/// Save the graph file unencrypted once the command saves it, and forget its key
+#[cfg(feature = "encryption")]
+fn decrypt_graph(file_path: &Path) -> Result<()> {
+    if set_graph_key(None).is_none() {
+        println!("{} is not encrypted", file_path.display());
+        return Ok(());
+    }
+    match keyring_entry(file_path).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {},
+        Err(e) => eprintln!("{}", style(format!("Warning: Could not remove the key from the OS keyring ({})", e)).yellow()),
+    }
+    println!("Decrypted {}", file_path.display());
+    Ok(())
+}
+
 /// Create a new thought, prompting for any missing information
 fn create_thought(
     graph: &mut ThoughtGraph,
@@ -542,7 +1785,12 @@ fn create_thought(
     content: Option<String>,
     tags: Vec<String>,
     references: Vec<String>,
+    assist: bool,
 ) -> Result<()> {
+    if assist && !cfg!(feature = "llm") {
+        return Err(anyhow::anyhow!("--assist requires thoughts to be built with the 'llm' feature"));
+    }
+    
     // Ask for ID if not provided
     let id = match id {
         Some(id) => id,
@@ -555,6 +1803,8 @@ fn create_thought(
                 .interact()?
         }
     };
+    // Check the ID before asking for content that would otherwise be lost
+    let thought_id = ThoughtID::parse(&id)?;
     
     // Ask for title if not provided
     let title = match title {
@@ -584,18 +1834,19 @@ fn create_thought(
             if !io::stdin().is_terminal() {
                 return Err(anyhow::anyhow!("Content is required in non-interactive mode"));
             }
-            edit_in_external_editor("", "# Enter your thought content here")?
+            edit_in_external_editor(&new_text("create", "Enter your thought content here")?)?
         },
     };
     
     // Convert tags to TagIDs
     let tag_ids: Vec<TagID> = tags.into_iter()
-        .map(|t| TagID::new(t))
+        .map(TagID::new)
         .collect();
     
     // Create any tags that don't exist yet
     for tag_id in &tag_ids {
         if !graph.tags.contains_key(tag_id) {
+            TagID::parse(tag_id.as_str())?;
             let description = match std::io::stdin().is_terminal() {
                 true => Input::<String>::new()
                     .with_prompt(format!("Enter description for new tag '{}'", tag_id.id))
@@ -626,8 +1877,6 @@ fn create_thought(
         .collect();
     
     // Create the thought
-    let thought_id = ThoughtID::new(id.clone());
-    
     ui::with_loading_progress("Creating thought...", || {
         graph.create_thought(
             thought_id.clone(),
@@ -653,73 +1902,454 @@ fn create_thought(
         }
     }
     
+    apply_tag_rules(graph, &thought_id)?;
+    print_tag_suggestions(graph, &thought_id);
+    offer_link_suggestions(graph, &thought_id)?;
+    
+    #[cfg(feature = "llm")]
+    if assist {
+        assist_with_thought(graph, &thought_id)?;
+    }
+    
     Ok(())
 }
 
-/// List thoughts in the graph, optionally filtering by tag
-fn list_thoughts(graph: &ThoughtGraph, tag_filter: Option<String>) -> Result<()> {
-    let thoughts = match tag_filter {
-        Some(tag) => {
-            let tag_id = TagID::new(tag.clone());
-            if !graph.tags.contains_key(&tag_id) {
-                return Err(anyhow::anyhow!("Tag '{}' not found", tag));
+/// Use a language model to propose a title and references for a new thought
+#[cfg(feature = "llm")]
+fn assist_with_thought(graph: &mut ThoughtGraph, thought_id: &ThoughtID) -> Result<()> {
+    use thoughtgraph::llm::{self, OpenAiCompatible};
+    
+    let provider = OpenAiCompatible::from_env()?;
+    let mut thought = graph.get_thought(thought_id)
+        .cloned()
+        .ok_or_else(|| ThoughtGraphError::ThoughtNotFound(thought_id.id.to_string()))?;
+    let mut changed = false;
+    
+    if thought.title.is_none() {
+        let title = ui::with_loading_progress("Asking for a title...", || {
+            llm::suggest_title(&provider, &thought.contents)
+        })?;
+        if !title.is_empty() {
+            println!("Suggested title: {}", title.cyan());
+            if !io::stdin().is_terminal() || ui::confirm("Use this title?", true)? {
+                thought.update_title(Some(title));
+                changed = true;
             }
-
-            // Use the query functionality to find thoughts with this tag
-            graph.find_thoughts(&thoughtgraph::Query::Tag(tag_id))
-        },
-        None => graph.thoughts.iter().map(|(id, thought)| (id, thought)).collect(),
-    };
-
-    // Use the enhanced display function
-    ui::display_thought_list(graph, &thoughts, MAX_DISPLAY_LENGTH)?;
-
-    // If in interactive mode, offer to select a thought to view
-    if io::stdin().is_terminal() && !thoughts.is_empty() {
-        if ui::confirm("Would you like to view one of these thoughts?", false)? {
-            if let Some(id) = ui::select_thought(graph, "Select a thought to view")? {
-                return view_thought(graph, &id.id);
+        }
+    }
+    
+    let suggestions = ui::with_loading_progress("Asking for related thoughts...", || {
+        graph.suggest_references(&provider, thought_id)
+    })?;
+    if !suggestions.is_empty() {
+        println!("Suggested references:");
+        for id in &suggestions {
+            println!("  → {}", id.id.blue());
+        }
+        if io::stdin().is_terminal() && ui::confirm("Add these references?", true)? {
+            for id in suggestions {
+                thought.add_reference(Reference::new(id, "Suggested by language model".to_string(), Utc::now()));
             }
+            changed = true;
         }
     }
-
+    
+    if changed {
+        graph.apply(thoughtgraph::Command::PutThought { id: thought_id.clone(), thought });
+    }
+    
     Ok(())
 }
 
-/// View details of a specific thought
-fn view_thought(graph: &ThoughtGraph, id: &str) -> Result<()> {
+/// Print a language model summary of a thought
+#[cfg(feature = "llm")]
+fn summarize_thought(graph: &ThoughtGraph, id: &str) -> Result<()> {
+    let provider = thoughtgraph::llm::OpenAiCompatible::from_env()?;
     let thought_id = ThoughtID::new(id.to_string());
-    let thought = graph.get_thought(&thought_id)
-        .ok_or_else(|| anyhow::anyhow!("Thought '{}' not found", id))?;
-    
-    // Use the enhanced display function
-    ui::display_thought_details(graph, &thought_id, thought)?;
     
-    // Ask if the user wants to explore related thoughts
-    if io::stdin().is_terminal() && !thought.references.is_empty() && graph.get_backlinks(&thought_id).len() > 0 {
-        if ui::confirm("Would you like to explore related thoughts?", false)? {
-            ui::browse_thoughts(graph)?;
-        }
-    }
+    let summary = ui::with_loading_progress("Summarizing...", || {
+        graph.summarize_thought(&provider, &thought_id)
+    })?;
+    println!("{}", summary);
     
     Ok(())
 }
 
+/// Apply the graph's tagging rules to a thought and report the rules that fired
+fn apply_tag_rules(graph: &mut ThoughtGraph, thought_id: &ThoughtID) -> Result<()> {
+    let applied = graph.apply_tag_rules(thought_id)?;
+    if !applied.is_empty() {
+        println!("Applied tagging rules:");
+        for rule in applied {
+            println!("  {} → #{}", rule.rule.cyan(), rule.tag.id.yellow());
+        }
+    }
+    Ok(())
+}
+
+/// Print tags that would fit a thought, to help keep tagging consistent
+fn print_tag_suggestions(graph: &ThoughtGraph, thought_id: &ThoughtID) {
+    let suggestions = graph.suggest_tags_with(thought_id, STOP_WORDS.get());
+    if !suggestions.is_empty() {
+        let names: Vec<&str> = suggestions.iter().map(|t| t.as_str()).collect();
+        println!("Suggested tags: {}", names.join(", ").yellow());
+    }
+}
+
+/// Show thoughts a thought could link to and, in a terminal, add the chosen ones as
+/// references
+fn offer_link_suggestions(graph: &mut ThoughtGraph, thought_id: &ThoughtID) -> Result<()> {
+    let suggestions = graph.suggest_links_with(thought_id, STOP_WORDS.get());
+    if suggestions.is_empty() {
+        return Ok(());
+    }
+    let items: Vec<String> = suggestions
+        .iter()
+        .map(|id| {
+            let title = graph.get_thought(id).and_then(|t| t.title.as_deref()).unwrap_or("(Untitled)");
+            format!("{} - {}", id.id, title)
+        })
+        .collect();
+    
+    if !io::stdin().is_terminal() {
+        println!("Related thoughts: {}", suggestions.iter().map(|id| id.as_str()).collect::<Vec<_>>().join(", ").blue());
+        return Ok(());
+    }
+    let selection = dialoguer::MultiSelect::with_theme(&ui::get_theme())
+        .with_prompt("Link to related thoughts? (space to select, enter to confirm)")
+        .items(&items)
+        .interact()?;
+    for index in selection {
+        graph.command(&thoughtgraph::Command::AddReference {
+            id: thought_id.clone(),
+            reference: Reference::new(suggestions[index].clone(), String::new(), Utc::now()),
+        });
+        println!("  → {}", suggestions[index].id.blue());
+    }
+    Ok(())
+}
+
+/// Parse the name of a thought ordering
+fn parse_sort(sort: &str) -> Result<SortBy> {
+    match sort.to_lowercase().as_str() {
+        "id" => Ok(SortBy::Id),
+        "title" => Ok(SortBy::Title),
+        "created" => Ok(SortBy::Created),
+        "updated" => Ok(SortBy::Updated),
+        "backlinks" => Ok(SortBy::Backlinks),
+        _ => Err(ThoughtGraphError::ParseError(format!(
+            "Unknown sort order '{}'. Use 'id', 'title', 'created', 'updated' or 'backlinks'.", sort
+        )).into()),
+    }
+}
+
+/// List thoughts in the graph, optionally filtering by tag
+fn list_thoughts(graph: &mut ThoughtGraph, tags: Vec<String>, any_tag: bool, namespace: Option<String>, page: QueryOptions, template: Option<&Template>, keys: &BrowseKeys) -> Result<()> {
+    let mut queries = Vec::new();
+    for tag in tags {
+        let tag_id = TagID::new(tag.clone());
+        if !graph.tags.contains_key(&tag_id) {
+            return Err(ThoughtGraphError::TagNotFound(tag).into());
+        }
+        queries.push(Box::new(thoughtgraph::Query::Tag(tag_id)));
+    }
+
+    // Use the query functionality to find thoughts with the tags
+    let mut thoughts = match queries.len() {
+        0 => graph.thoughts.iter().collect(),
+        1 => graph.find_thoughts(&queries[0]),
+        _ if any_tag => graph.find_thoughts(&thoughtgraph::Query::Or(queries)),
+        _ => graph.find_thoughts(&thoughtgraph::Query::And(queries)),
+    };
+    if let Some(namespace) = namespace {
+        let in_namespace: HashSet<&ThoughtID> = graph.list_namespace(&namespace).into_iter().collect();
+        thoughts.retain(|(id, _)| in_namespace.contains(id));
+    }
+    let thoughts = page.apply(graph, thoughts);
+    if let Some(template) = template {
+        print_with_template(graph, &thoughts, template);
+        return require_results(&thoughts);
+    }
+
+    // Use the enhanced display function
+    ui::display_thought_list(graph, &thoughts, MAX_DISPLAY_LENGTH)?;
+
+    // If in interactive mode, offer to select a thought to view
+    if io::stdin().is_terminal()
+        && !thoughts.is_empty()
+        && ui::confirm("Would you like to view one of these thoughts?", false)?
+    {
+        if let Some(id) = ui::select_thought(graph, "Select a thought to view")? {
+            return view_thought(graph, &id.id, keys);
+        }
+    }
+
+    require_results(&thoughts)
+}
+
+/// Show the thoughts in a namespace as a tree
+fn show_namespace_tree(graph: &ThoughtGraph, namespace: &str) -> Result<()> {
+    let tree = graph.namespace_tree(namespace);
+    if tree.is_empty() {
+        println!("{}", style("No thoughts found").italic());
+        return Ok(());
+    }
+    
+    let root = if namespace.is_empty() { "." } else { namespace };
+    println!("{} {}", style(root).bold(), style(format!("({})", tree.len())).dim());
+    ui::display_namespace_tree(graph, &tree, 1);
+    Ok(())
+}
+
+/// Parse the reference kind given on the command line
+fn parse_reference_kind(kind: &str) -> Result<ReferenceKind> {
+    Ok(kind.parse().map_err(ThoughtGraphError::ParseError)?)
+}
+
+/// Print or save the outline of the parent hierarchy
+fn export_outline(graph: &ThoughtGraph, root: Option<String>, output: Option<PathBuf>) -> Result<()> {
+    let root = root.map(ThoughtID::new);
+    let outline = graph.outline(root.as_ref())?;
+    
+    if let Some(output_path) = output {
+        fs::write(&output_path, &outline)?;
+        println!("{}", style(format!("Outline saved to {}", output_path.display())).green());
+    } else {
+        print!("{}", outline);
+    }
+    Ok(())
+}
+
+/// The thoughts to export, with a name for the selection: one thought, a chain of
+/// references, or the thoughts with all of some tags or found by a saved query or a
+/// query, ordered by ID
+fn select_thoughts(
+    graph: &ThoughtGraph,
+    id: Option<String>,
+    tags: Vec<String>,
+    saved: Option<String>,
+    query: Option<String>,
+    chain: Option<String>,
+    kind: &str,
+) -> Result<(Vec<ThoughtID>, String)> {
+    let title_of = |id: &ThoughtID| graph.get_thought(id).and_then(|t| t.title.clone()).unwrap_or_else(|| id.id.to_string());
+    let (ids, name): (Vec<ThoughtID>, String) = if let Some(id) = id {
+        let id = ThoughtID::new(id);
+        let name = title_of(&id);
+        (vec![id], name)
+    } else if let Some(start) = chain {
+        let start = ThoughtID::parse(&start)?;
+        let chain = graph.reference_chain(&start, parse_reference_kind(kind)?).into_iter().cloned().collect();
+        (chain, title_of(&start))
+    } else if let Some(name) = saved {
+        (graph.run_saved_query(&name)?.into_iter().map(|(id, _)| id.clone()).collect(), name)
+    } else if let Some(query) = query {
+        let query = thoughtgraph::Query::parse(&query)?;
+        let mut ids: Vec<ThoughtID> = graph.find_thoughts(&query).into_iter().map(|(id, _)| id.clone()).collect();
+        ids.sort();
+        (ids, query.to_string())
+    } else if !tags.is_empty() {
+        let conditions = tags
+            .iter()
+            .map(|tag| Ok(Box::new(thoughtgraph::Query::Tag(TagID::parse(tag)?))))
+            .collect::<Result<Vec<_>>>()?;
+        let mut ids: Vec<ThoughtID> =
+            graph.find_thoughts(&thoughtgraph::Query::And(conditions)).into_iter().map(|(id, _)| id.clone()).collect();
+        ids.sort();
+        let names: Vec<String> = tags.iter().map(|tag| format!("#{}", tag)).collect();
+        (ids, names.join(" "))
+    } else {
+        return Err(anyhow::anyhow!("Give a thought ID, --tag, --saved, --query or --chain"));
+    };
+    
+    if ids.is_empty() {
+        return Err(anyhow::anyhow!("No thoughts to export"));
+    }
+    Ok((ids, name))
+}
+
+/// Print or save thoughts published as a document
+#[cfg_attr(not(any(feature = "pdf", feature = "epub")), allow(unused_variables))]
+fn export_thoughts(graph: &ThoughtGraph, ids: &[ThoughtID], format: &str, title: &str, output: Option<PathBuf>) -> Result<()> {
+    let document = match (format, ids) {
+        ("markdown" | "md", [id]) => graph.to_markdown(id)?.into_bytes(),
+        ("html", [id]) => graph.to_html(id)?.into_bytes(),
+        ("markdown" | "md", ids) => graph.compile(Some(title), ids)?.into_bytes(),
+        ("html", ids) => graph.compile_html(title, ids)?.into_bytes(),
+        #[cfg(feature = "pdf")]
+        ("pdf", ids) => {
+            if output.is_none() {
+                return Err(anyhow::anyhow!("PDF export needs an output file (--output)"));
+            }
+            graph.to_pdf(title, ids)?
+        },
+        #[cfg(not(feature = "pdf"))]
+        ("pdf", _) => return Err(anyhow::anyhow!("PDF export requires thoughts to be built with the 'pdf' feature")),
+        #[cfg(feature = "epub")]
+        ("epub", ids) => {
+            if output.is_none() {
+                return Err(anyhow::anyhow!("EPUB export needs an output file (--output)"));
+            }
+            graph.to_epub(title, ids)?
+        },
+        #[cfg(not(feature = "epub"))]
+        ("epub", _) => return Err(anyhow::anyhow!("EPUB export requires thoughts to be built with the 'epub' feature")),
+        _ => return Err(Failed::invalid(format!("Unknown export format: {} (expected markdown, html, ndjson, pdf or epub)", format))),
+    };
+    
+    if let Some(output_path) = output {
+        fs::write(&output_path, &document)?;
+        println!("{}", style(format!("Document saved to {}", output_path.display())).green());
+    } else {
+        io::stdout().write_all(&document)?;
+    }
+    Ok(())
+}
+
+/// Stream thoughts, or the whole graph, as NDJSON to a file or stdout
+fn export_ndjson(graph: &ThoughtGraph, ids: Option<&[ThoughtID]>, output: Option<PathBuf>) -> Result<()> {
+    let write = |writer: &mut dyn Write| match ids {
+        Some(ids) => graph.write_ndjson_thoughts(ids, writer),
+        None => graph.write_ndjson(writer),
+    };
+    match output {
+        Some(output_path) => {
+            let file = fs::File::create(&output_path).with_context(|| format!("Could not create {}", output_path.display()))?;
+            let count = write(&mut io::BufWriter::new(file))?;
+            println!("{}", style(format!("{} records saved to {}", count, output_path.display())).green());
+        },
+        None => {
+            write(&mut io::BufWriter::new(io::stdout().lock()))?;
+        },
+    }
+    Ok(())
+}
+
+/// Print or save the Markdown document compiled from a chain or list of thoughts
+fn compile_document(graph: &ThoughtGraph, ids: Vec<String>, kind: &str, title: Option<String>, output: Option<PathBuf>) -> Result<()> {
+    let ids: Vec<ThoughtID> = match ids.as_slice() {
+        [start] => {
+            let start = ThoughtID::parse(start)?;
+            if graph.get_thought(&start).is_none() {
+                return Err(ThoughtGraphError::ThoughtNotFound(start.id.to_string()).into());
+            }
+            graph.reference_chain(&start, parse_reference_kind(kind)?).into_iter().cloned().collect()
+        },
+        ids => ids.iter().map(|id| ThoughtID::parse(id)).collect::<thoughtgraph::Result<_>>()?,
+    };
+    let document = graph.compile(title.as_deref(), &ids)?;
+    
+    if let Some(output_path) = output {
+        fs::write(&output_path, &document)?;
+        let noun = if ids.len() == 1 { "thought" } else { "thoughts" };
+        println!("{}", style(format!("Compiled {} {} into {}", ids.len(), noun, output_path.display())).green());
+    } else {
+        print!("{}", document);
+    }
+    Ok(())
+}
+
+/// Rename a thought, or move a namespace when `from` ends with the separator
+fn move_thoughts(graph: &mut ThoughtGraph, from: &str, to: &str) -> Result<()> {
+    if from.ends_with(NAMESPACE_SEPARATOR) {
+        let moves = ui::with_loading_progress("Moving thoughts...", || graph.move_namespace(from, to))?;
+        if moves.is_empty() {
+            return Err(Failed::not_found(format!("No thoughts found in namespace '{}'", from)));
+        }
+        for (old, new) in &moves {
+            println!("{} -> {}", old.id, new.id.green());
+        }
+        println!("Moved {} thoughts", moves.len());
+    } else {
+        let from_id = ThoughtID::new(from.to_string());
+        let to_id = ThoughtID::parse(to)?;
+        ui::with_loading_progress("Moving thought...", || graph.move_thought(&from_id, &to_id))?;
+        println!("Moved '{}' to '{}'", from, to.green());
+    }
+    Ok(())
+}
+
+/// Lock or unlock a thought
+fn lock_thought(graph: &mut ThoughtGraph, id: &str, lock: bool) -> Result<()> {
+    let thought_id = ThoughtID::new(id.to_string());
+    let command = if lock {
+        thoughtgraph::Command::LockThought { id: thought_id }
+    } else {
+        thoughtgraph::Command::UnlockThought { id: thought_id }
+    };
+    let changes = graph.try_command(&command)?;
+    
+    match (lock, changes.is_empty()) {
+        (true, false) => println!("Locked thought '{}'", id.green()),
+        (true, true) => println!("Thought '{}' is already locked", id.green()),
+        (false, false) => println!("Unlocked thought '{}'", id.green()),
+        (false, true) => println!("Thought '{}' is not locked", id.green()),
+    }
+    Ok(())
+}
+
+/// Fail if the thought is locked, before asking the user for any changes to it
+fn check_unlocked(graph: &ThoughtGraph, id: &ThoughtID) -> Result<()> {
+    if graph.is_locked(id) {
+        return Err(thoughtgraph::ThoughtGraphError::ThoughtLocked(id.id.to_string()).into());
+    }
+    Ok(())
+}
+
+/// View details of a specific thought
+fn view_thought(graph: &mut ThoughtGraph, id: &str, keys: &BrowseKeys) -> Result<()> {
+    let thought_id = ThoughtID::new(id.to_string());
+    let thought = graph.get_thought(&thought_id)
+        .ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.to_string()))?;
+    
+    // Use the enhanced display function
+    ui::display_thought_details(graph, &thought_id, thought)?;
+    
+    // Ask if the user wants to explore related thoughts
+    if io::stdin().is_terminal()
+        && !thought.references.is_empty()
+        && !graph.get_backlinks(&thought_id).is_empty()
+        && ui::confirm("Would you like to explore related thoughts?", false)?
+    {
+        ui::browse_thoughts(graph, Some(thought_id), keys, &mut |g, id| edit_thought(g, &id.id))?;
+    }
+    
+    Ok(())
+}
+
+/// View a thought as it was at a past date, reconstructed from the journal
+fn view_thought_as_of(graph: &ThoughtGraph, id: &str, date: &str) -> Result<()> {
+    let at = parse_date(date)?;
+    let past = graph.as_of(at).ok_or_else(|| {
+        let oldest = graph.history().last().map(|entry| entry.at.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string());
+        anyhow::anyhow!("The history only goes back to {}", oldest.unwrap_or_default())
+    })?;
+    let thought_id = ThoughtID::new(id.to_string());
+    let thought = past.get_thought(&thought_id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.to_string()))?;
+    println!("{}", style(format!("As of {}", at.with_timezone(&Local).format("%Y-%m-%d %H:%M"))).dim());
+    ui::display_thought_details(&past, &thought_id, thought)?;
+    Ok(())
+}
+
 /// Edit a thought using an external editor
 fn edit_thought(graph: &mut ThoughtGraph, id: &str) -> Result<()> {
     let thought_id = ThoughtID::new(id.to_string());
     let thought = graph.get_thought(&thought_id)
-        .ok_or_else(|| anyhow::anyhow!("Thought '{}' not found", id))?;
+        .ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.to_string()))?;
+    check_unlocked(graph, &thought_id)?;
     
     // Check if we're in non-interactive mode
     if !io::stdin().is_terminal() {
         // For non-interactive testing, just update the timestamp
-        let mut updated_thought = thought.clone();
-        updated_thought.update_content(format!("{}\n(Updated non-interactively)", thought.contents));
-        
-        graph.command(&thoughtgraph::Command::PutThought {
+        let contents = format!("{}\n(Updated non-interactively)", thought.contents);
+        graph.command(&thoughtgraph::Command::PatchThought {
             id: thought_id.clone(),
-            thought: updated_thought,
+            title: None,
+            contents: Some(contents),
+            add_tags: vec![],
+            remove_tags: vec![],
+            add_references: vec![],
+            remove_references: vec![],
         });
         
         println!("Thought '{}' updated non-interactively", id.green());
@@ -734,13 +2364,13 @@ fn edit_thought(graph: &mut ThoughtGraph, id: &str) -> Result<()> {
     );
     
     // Open in external editor
-    let edited_content = edit_in_external_editor(&initial_content, "")?;
+    let edited_content = edit_in_external_editor(&initial_content)?;
     
     // Parse the edited content
     let mut lines = edited_content.lines();
     let title_line = lines.next().unwrap_or_default();
-    let title = if title_line.starts_with("# Title:") {
-        let title_str = title_line["# Title:".len()..].trim();
+    let title = if let Some(title_str) = title_line.strip_prefix("# Title:") {
+        let title_str = title_str.trim();
         if title_str.is_empty() {
             None
         } else {
@@ -762,15 +2392,29 @@ fn edit_thought(graph: &mut ThoughtGraph, id: &str) -> Result<()> {
     }
     let content = content_lines.join("\n");
     
-    // Update the thought
-    let mut updated_thought = thought.clone();
-    updated_thought.update_title(title);
-    updated_thought.update_content(content);
+    // Show what changed, so that accidental deletions can be caught before saving
+    let edited = format!("# Title: {}\n\n{}", title.clone().unwrap_or_default(), content);
+    let diff = thoughtgraph::diff::unified_diff(&initial_content, &edited, 3);
+    if diff.is_empty() {
+        println!("No changes made to thought '{}'", id.green());
+        return Ok(());
+    }
+    print_diff(&diff);
+    if !ui::confirm("Save these changes?", true)? {
+        println!("Edit discarded");
+        return Ok(());
+    }
     
+    // Update the thought
     ui::with_loading_progress("Updating thought...", || {
-        graph.command(&thoughtgraph::Command::PutThought {
+        graph.command(&thoughtgraph::Command::PatchThought {
             id: thought_id.clone(),
-            thought: updated_thought,
+            title: Some(title),
+            contents: Some(content),
+            add_tags: vec![],
+            remove_tags: vec![],
+            add_references: vec![],
+            remove_references: vec![],
         });
     });
     
@@ -788,255 +2432,1597 @@ fn edit_thought(graph: &mut ThoughtGraph, id: &str) -> Result<()> {
             println!("  → {}", ref_id.id.blue());
         }
     }
-    
+    
+    apply_tag_rules(graph, &thought_id)?;
+    print_tag_suggestions(graph, &thought_id);
+    offer_link_suggestions(graph, &thought_id)?;
+    
+    Ok(())
+}
+
+/// Copy a thought
+fn clone_thought(graph: &mut ThoughtGraph, id: &str, new_id: Option<String>, with_references: bool) -> Result<()> {
+    let thought_id = ThoughtID::new(id.to_string());
+    if !graph.thoughts.contains_key(&thought_id) {
+        return Err(ThoughtGraphError::ThoughtNotFound(id.to_string()).into());
+    }
+    let new_id = match new_id {
+        Some(new_id) => ThoughtID::parse(&new_id)?,
+        None => graph.copy_id(&thought_id),
+    };
+
+    graph.clone_thought(&thought_id, &new_id, with_references)?;
+    println!("Cloned '{}' into '{}'", id.green(), new_id.id.green());
+    Ok(())
+}
+
+/// Print a unified diff with removed lines in red and added lines in green
+fn print_diff(diff: &str) {
+    for line in diff.lines() {
+        if line.starts_with("@@") {
+            println!("{}", line.cyan());
+        } else if line.starts_with('-') {
+            println!("{}", line.red());
+        } else if line.starts_with('+') {
+            println!("{}", line.green());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Delete a thought, moving it to the trash unless `permanent` is set
+fn delete_thought(graph: &mut ThoughtGraph, id: &str, force: bool, permanent: bool) -> Result<()> {
+    let thought_id = ThoughtID::new(id.to_string());
+    
+    // Check if thought exists
+    if !graph.thoughts.contains_key(&thought_id) {
+        return Err(ThoughtGraphError::ThoughtNotFound(id.to_string()).into());
+    }
+    check_unlocked(graph, &thought_id)?;
+    
+    // Confirm deletion if not forced
+    if !force {
+        if io::stdin().is_terminal() {
+            if !ui::confirm(&format!("Are you sure you want to delete thought '{}'?", id), false)? {
+                println!("Deletion cancelled");
+                return Ok(());
+            }
+        } else {
+            // In non-interactive mode, we should require the --force flag
+            return Err(anyhow::anyhow!("Deletion requires --force flag in non-interactive mode"));
+        }
+    }
+    
+    if !permanent {
+        let changes = graph.trash_thought(&thought_id, Utc::now())?;
+        println!("Moved thought '{}' to the trash (restore it with 'thoughts restore {}')", id.green(), id);
+        print_changes(&changes);
+        return Ok(());
+    }
+
+    // Delete the thought with progress indicator
+    let changes = ui::with_loading_progress(&format!("Deleting thought '{}'...", id), || {
+        graph.command(&thoughtgraph::Command::DeleteThought {
+            id: thought_id.clone(),
+        })
+    });
+    
+    println!("Thought '{}' deleted successfully", id.green());
+    print_changes(&changes);
+    Ok(())
+}
+
+/// Restore a thought from the trash
+fn restore_thought(graph: &mut ThoughtGraph, id: &str) -> Result<()> {
+    let thought_id = ThoughtID::new(id.to_string());
+    if !graph.trash.contains_key(&thought_id) {
+        return Err(Failed::not_found(format!("Thought '{}' is not in the trash", id)));
+    }
+    let changes = graph.restore_thought(&thought_id)?;
+    println!("Restored thought '{}'", id.green());
+    print_changes(&changes);
+    Ok(())
+}
+
+/// Show the journal of recent commands
+fn show_history(graph: &ThoughtGraph) -> Result<()> {
+    let history = graph.history();
+    if history.is_empty() {
+        println!("{}", style("No commands to undo").italic());
+        return Ok(());
+    }
+
+    println!("{} {} {}",
+        style(ui::format_column("#", 4)).bold().underlined(),
+        style(ui::format_column("WHEN", 18)).bold().underlined(),
+        style(ui::format_column("COMMAND", 60)).bold().underlined()
+    );
+    for (n, entry) in history.iter().enumerate() {
+        println!("{} {} {}",
+            style(ui::format_column(&(n + 1).to_string(), 4)).dim(),
+            style(ui::format_column(&ui::format_time(entry.at), 18)),
+            style(ui::format_column(&entry.description, 60)).cyan()
+        );
+    }
+    println!("\nUndo the most recent N with 'thoughts undo N'");
+    Ok(())
+}
+
+/// Undo the most recent commands in the journal
+fn undo_commands(graph: &mut ThoughtGraph, count: usize) -> Result<()> {
+    let undone = graph.undo(count)?;
+    if undone.is_empty() {
+        println!("{}", style("No commands to undo").italic());
+        return Ok(());
+    }
+    for (entry, changes) in &undone {
+        println!("Undid '{}'", entry.description.green());
+        print_changes(changes);
+    }
+    if undone.len() < count {
+        println!("{}", style("No more commands to undo").yellow());
+    }
+    Ok(())
+}
+
+/// Parse a date given on the command line, such as "2025-02-01", "yesterday", "last
+/// monday" or "7d", with dates starting at local midnight
+fn parse_date(date: &str) -> Result<chrono::DateTime<Utc>> {
+    Ok(dates::parse_date(date, Local::now())?)
+}
+
+/// Show what recent commands did to thoughts
+fn show_log(graph: &ThoughtGraph, since: Option<String>) -> Result<()> {
+    use thoughtgraph::journal::Activity;
+
+    let since = since.as_deref().map(parse_date).transpose()?;
+    let entries: Vec<_> = graph
+        .history()
+        .into_iter()
+        .filter(|entry| since.is_none_or(|since| entry.at >= since) && !entry.activity.is_empty())
+        .collect();
+    if entries.is_empty() {
+        println!("{}", style("No activity").italic());
+        return Ok(());
+    }
+
+    for entry in entries {
+        println!("{}  {}",
+            style(ui::format_time(entry.at)).yellow(),
+            style(&entry.description).dim()
+        );
+        for activity in &entry.activity {
+            let (what, id, tag) = match activity {
+                Activity::Created(id) => (style("created ").green(), id, None),
+                Activity::Edited(id) => (style("edited  ").cyan(), id, None),
+                Activity::Tagged(id, tag) => (style("tagged  ").magenta(), id, Some(tag)),
+                Activity::Untagged(id, tag) => (style("untagged").magenta(), id, Some(tag)),
+                Activity::Deleted(id) => (style("deleted ").red(), id, None),
+            };
+            match tag {
+                Some(tag) => println!("  {} {} #{}", what, id.id, tag.id),
+                None => println!("  {} {}", what, id.id),
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// List or empty the trash
+fn manage_trash(graph: &mut ThoughtGraph, command: TrashCommands) -> Result<()> {
+    match command {
+        TrashCommands::List => {
+            let trashed = graph.trashed_thoughts();
+            if trashed.is_empty() {
+                println!("{}", style("The trash is empty").italic());
+                return Ok(());
+            }
+
+            println!("{} {} {}",
+                style(ui::format_column("ID", 30)).bold().underlined(),
+                style(ui::format_column("TITLE", 40)).bold().underlined(),
+                style(ui::format_column("DELETED", 20)).bold().underlined()
+            );
+            for (id, trashed) in trashed {
+                let title = trashed.thought.title.as_deref().unwrap_or("");
+                println!("{} {} {}",
+                    style(ui::format_column(&id.id, 30)).cyan(),
+                    style(ui::format_column(title, 40)),
+                    style(ui::format_time(trashed.deleted_at)).dim()
+                );
+            }
+        },
+        TrashCommands::Empty { older_than } => {
+            let before = older_than.as_deref().map(parse_date).transpose()?;
+            let removed = graph.empty_trash(before);
+            match removed.len() {
+                0 => println!("{}", style("Nothing to remove from the trash").italic()),
+                1 => println!("Permanently deleted 1 thought"),
+                n => println!("Permanently deleted {} thoughts", n),
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Apply the color settings of the profile in use
+fn use_profile_settings(profile: &Profile) {
+    let enabled = match profile.color {
+        Some(ColorMode::Always) => true,
+        Some(ColorMode::Never) => false,
+        Some(ColorMode::Auto) | None => return,
+    };
+    colored::control::set_override(enabled);
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+/// The tags of the profile in use followed by the given tags, without repeats
+fn with_default_tags(default_tags: &[String], tags: Vec<String>) -> Vec<String> {
+    let mut all: Vec<String> = default_tags.to_vec();
+    for tag in tags {
+        if !all.contains(&tag) {
+            all.push(tag);
+        }
+    }
+    all
+}
+
+/// List, create and switch between the profiles of the configuration file
+fn manage_profiles(command: ProfileCommands, config: &Config, config_path: Option<&Path>, active: Option<String>) -> Result<()> {
+    let config_path = || config_path.context("Could not determine the configuration directory for your platform; use --config");
+    match command {
+        ProfileCommands::List => {
+            if config.profiles.is_empty() {
+                println!("{}", style("No profiles (add one with 'thoughts profile create NAME')").italic());
+                return Ok(());
+            }
+
+            println!("  {} {} {} {}",
+                style(ui::format_column("NAME", 20)).bold().underlined(),
+                style(ui::format_column("FILE", 40)).bold().underlined(),
+                style(ui::format_column("TAGS", 20)).bold().underlined(),
+                style(ui::format_column("EDITOR", 15)).bold().underlined()
+            );
+            for (name, profile) in &config.profiles {
+                let marker = match active.as_deref() == Some(name.as_str()) {
+                    true => "*".green().to_string(),
+                    false => " ".to_string(),
+                };
+                let file = profile.file.as_ref().map(|file| file.display().to_string()).unwrap_or_else(|| "(default)".to_string());
+                println!("{} {} {} {} {}",
+                    marker,
+                    style(ui::format_column(name, 20)).cyan(),
+                    style(ui::format_column(&file, 40)),
+                    style(ui::format_column(&profile.tags.join(", "), 20)),
+                    style(ui::format_column(profile.editor.as_deref().unwrap_or(""), 15)).dim()
+                );
+            }
+        },
+        ProfileCommands::Create { name, file, tags, editor, color } => {
+            let file = match file {
+                Some(file) => file,
+                None => dirs::data_dir()
+                    .context("Could not determine data directory for your platform; use --file")?
+                    .join("thoughtgraph")
+                    .join(format!("{}.bin", name)),
+            };
+            for tag in &tags {
+                TagID::parse(tag)?;
+            }
+            let path = config_path()?;
+            config::add_profile(path, &name, &Profile { file: Some(file.clone()), tags, editor, color })?;
+            println!("Created profile '{}' using {}", name.green(), file.display());
+            println!("Use it with 'thoughts --profile {} ...' or 'thoughts profile switch {}'", name, name);
+        },
+        ProfileCommands::Switch { name } => {
+            let path = config_path()?;
+            config::switch_profile(path, name.as_deref())?;
+            match name {
+                Some(name) => println!("Switched to profile '{}'", name.green()),
+                None => println!("Switched to the default graph"),
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Read text from the system clipboard
+#[cfg(feature = "clipboard")]
+fn read_clipboard() -> Result<String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|e| anyhow::anyhow!("Could not read the clipboard: {}", e))
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn read_clipboard() -> Result<String> {
+    Err(anyhow::anyhow!("--from-clipboard requires thoughts to be built with the 'clipboard' feature"))
+}
+
+/// Create a thought described as JSON in a file, or on standard input if the file is "-"
+fn create_thought_from_json(graph: &mut ThoughtGraph, file: &Path, tags: Vec<String>) -> Result<()> {
+    let json = match file.to_str() {
+        Some("-") => {
+            let mut json = String::new();
+            io::stdin().read_to_string(&mut json)?;
+            json
+        },
+        _ => fs::read_to_string(file).with_context(|| format!("Could not read {}", file.display()))?,
+    };
+    let mut input = ThoughtInput::from_json(&json)?;
+    input.tags = with_default_tags(&tags, input.tags);
+    let id = ThoughtID::parse(&input.id)?;
+    graph.create_from_input(input, Utc::now())?;
+    graph.process_auto_references(&id)?;
+    println!("Created thought '{}' successfully", id.id.green());
+    Ok(())
+}
+
+/// Capture a thought into the inbox
+fn capture_thought(graph: &mut ThoughtGraph, text: Vec<String>, from_clipboard: bool) -> Result<()> {
+    let content = if from_clipboard {
+        read_clipboard()?
+    } else if !text.is_empty() {
+        text.join(" ")
+    } else if io::stdin().is_terminal() {
+        Input::with_theme(&ui::get_theme())
+            .with_prompt("Capture")
+            .interact_text()?
+    } else {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        content
+    };
+    let content = content.trim().to_string();
+    if content.is_empty() {
+        return Err(anyhow::anyhow!("Nothing to capture"));
+    }
+
+    let title = from_clipboard.then(|| ui::suggest_title_from_content(&content));
+    let id = graph.capture(content, Utc::now())?;
+    if title.is_some() {
+        graph.command(&thoughtgraph::Command::PatchThought {
+            id: id.clone(),
+            title: Some(title),
+            contents: None,
+            add_tags: vec![],
+            remove_tags: vec![],
+            add_references: vec![],
+            remove_references: vec![],
+        });
+    }
+    graph.process_auto_references(&id)?;
+    println!("Captured '{}' into the inbox", id.id.green());
+    Ok(())
+}
+
+/// Append text to a thought
+fn append_to_thought(graph: &mut ThoughtGraph, id: &str, text: Vec<String>) -> Result<()> {
+    let thought_id = ThoughtID::new(id.to_string());
+    if !graph.thoughts.contains_key(&thought_id) {
+        return Err(ThoughtGraphError::ThoughtNotFound(id.to_string()).into());
+    }
+    check_unlocked(graph, &thought_id)?;
+
+    let text = if !text.is_empty() {
+        text.join(" ")
+    } else if io::stdin().is_terminal() {
+        edit_in_external_editor(&new_text("append", "Enter the text to append here")?)?
+    } else {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        text
+    };
+    if text.trim().is_empty() {
+        return Err(anyhow::anyhow!("Nothing to append"));
+    }
+
+    let auto_refs = graph.append(&thought_id, &text, Utc::now())?;
+    println!("Appended to thought '{}'", id.green());
+    
+    // Report any auto-references that were added
+    if !auto_refs.is_empty() {
+        println!("Auto-added references to:");
+        for ref_id in auto_refs {
+            println!("  → {}", ref_id.id.blue());
+        }
+    }
+    
+    apply_tag_rules(graph, &thought_id)?;
+    Ok(())
+}
+
+/// List the thoughts in the inbox, and triage them one by one if asked
+fn show_inbox(graph: &mut ThoughtGraph, triage: bool) -> Result<()> {
+    let inbox: Vec<ThoughtID> = graph.inbox().into_iter().cloned().collect();
+    if !triage {
+        let thoughts: Vec<(&ThoughtID, &Thought)> =
+            inbox.iter().filter_map(|id| graph.get_thought(id).map(|thought| (id, thought))).collect();
+        println!("{} in the inbox\n", style(format!("{} thoughts", thoughts.len())).bold());
+        return ui::display_thought_list(graph, &thoughts, MAX_DISPLAY_LENGTH);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!("Triage is interactive; run it in a terminal"));
+    }
+
+    let term = Term::stdout();
+    let actions = &[
+        "Add a tag",
+        "Link to a thought",
+        "Edit",
+        "File (remove from the inbox)",
+        "Archive",
+        "Delete",
+        "Skip",
+        "Stop triaging",
+    ];
+    for (i, id) in inbox.iter().enumerate() {
+        while let Some(thought) = graph.get_thought(id) {
+            term.clear_screen()?;
+            println!("{}\n", style(format!("Inbox {} of {}", i + 1, inbox.len())).dim());
+            ui::display_thought_details(graph, id, thought)?;
+
+            let action = dialoguer::Select::with_theme(&ui::get_theme())
+                .with_prompt("Triage")
+                .items(actions)
+                .default(0)
+                .interact()?;
+            let result = match action {
+                0 => ui::tag_selector(graph).and_then(|(tag, description)| tag_thought(graph, &id.id, &tag.id, description)),
+                1 => match ui::select_thought(graph, "Select a thought to link to")? {
+                    Some(to) => add_reference(graph, &id.id, &to.id, None, ReferenceKind::Related),
+                    None => Ok(()),
+                },
+                2 => edit_thought(graph, &id.id),
+                3 | 4 => {
+                    graph.file_from_inbox(id, action == 4)?;
+                    break;
+                },
+                5 => {
+                    delete_thought(graph, &id.id, false, false)?;
+                    continue;
+                },
+                6 => break,
+                _ => return Ok(()),
+            };
+            if let Err(e) = result {
+                println!("{}", style(format!("Error: {}", e)).red());
+                term.read_key()?;
+            }
+        }
+    }
+
+    term.clear_screen()?;
+    println!("{} thoughts left in the inbox", graph.inbox().len());
+    Ok(())
+}
+
+/// Print a dimmed summary of the changes made by a command
+fn print_changes(changes: &ChangeSet) {
+    println!("{}", style(format!("({})", changes)).dim());
+}
+
+/// Add a tag to a thought
+fn tag_thought(graph: &mut ThoughtGraph, id: &str, tag: &str, description: Option<String>) -> Result<()> {
+    let thought_id = ThoughtID::new(id.to_string());
+    let tag_id = TagID::new(tag.to_string());
+    
+    // Check if thought exists
+    if !graph.thoughts.contains_key(&thought_id) {
+        return Err(ThoughtGraphError::ThoughtNotFound(id.to_string()).into());
+    }
+    check_unlocked(graph, &thought_id)?;
+    
+    // Create the tag if it doesn't exist
+    if !graph.tags.contains_key(&tag_id) {
+        TagID::parse(tag)?;
+        let desc = match description {
+            Some(d) => d,
+            None => Input::with_theme(&ui::get_theme())
+                .with_prompt(format!("Enter description for new tag '{}'", tag))
+                .interact()?,
+        };
+        
+        ui::with_loading_progress("Creating tag...", || {
+            graph.create_tag(tag_id.clone(), desc)
+        })?;
+    }
+    
+    // Add the tag to the thought
+    let changes = ui::with_loading_progress("Updating thought...", || {
+        graph.command(&thoughtgraph::Command::AddTag {
+            id: thought_id.clone(),
+            tag: tag_id.clone(),
+        })
+    });
+    
+    println!("Added tag '{}' to thought '{}'", tag.yellow(), id.green());
+    print_changes(&changes);
+    Ok(())
+}
+
+/// Remove a tag from a thought
+fn untag_thought(graph: &mut ThoughtGraph, id: &str, tag: &str) -> Result<()> {
+    let thought_id = ThoughtID::new(id.to_string());
+    let tag_id = TagID::new(tag.to_string());
+    
+    // Check if thought exists
+    let thought = match graph.get_thought(&thought_id) {
+        Some(t) => t,
+        None => return Err(ThoughtGraphError::ThoughtNotFound(id.to_string()).into()),
+    };
+    check_unlocked(graph, &thought_id)?;
+    
+    // Check if the thought actually has this tag
+    if !thought.tags.contains(&tag_id) {
+        return Err(anyhow::anyhow!("Thought '{}' doesn't have tag '{}'", id, tag));
+    }
+    
+    // Remove the tag from the thought
+    let changes = ui::with_loading_progress("Updating thought...", || {
+        graph.command(&thoughtgraph::Command::RemoveTag {
+            id: thought_id.clone(),
+            tag: tag_id.clone(),
+        })
+    });
+    
+    println!("Removed tag '{}' from thought '{}'", tag.yellow(), id.green());
+    print_changes(&changes);
+    Ok(())
+}
+
+/// Give a tag a query, making it a smart tag, or take its query away
+fn set_smart_tag(graph: &mut ThoughtGraph, tag: &str, query: Option<String>, description: Option<String>) -> Result<()> {
+    let tag_id = TagID::parse(tag)?;
+    let query = query.as_deref().map(thoughtgraph::Query::parse).transpose()?;
+    let existing = match (graph.get_tag(&tag_id), &query) {
+        (Some(existing), _) => Tag { updated_at: Utc::now(), ..existing.clone() },
+        (None, Some(_)) => Tag::new(String::new()),
+        (None, None) => return Err(ThoughtGraphError::TagNotFound(tag.to_string()).into()),
+    };
+    let smart = query.is_some();
+    let updated = Tag { description: description.unwrap_or(existing.description.clone()), query, ..existing };
+    let changes = graph.try_command(&thoughtgraph::Command::PutTag { id: tag_id.clone(), tag: updated })?;
+
+    match smart {
+        true => {
+            let count = graph.query(&thoughtgraph::Query::Tag(tag_id)).len();
+            println!("Smart tag '{}' stands for {} thoughts", tag.yellow(), count);
+        },
+        false => println!("'{}' is an ordinary tag again", tag.yellow()),
+    }
+    print_changes(&changes);
+    Ok(())
+}
+
+/// Add a reference from one thought to another
+fn add_reference(graph: &mut ThoughtGraph, from: &str, to: &str, notes: Option<String>, kind: ReferenceKind) -> Result<()> {
+    let from_id = ThoughtID::new(from.to_string());
+    let to_id = ThoughtID::new(to.to_string());
+    
+    // Check if both thoughts exist
+    if !graph.thoughts.contains_key(&from_id) {
+        return Err(ThoughtGraphError::ThoughtNotFound(from.to_string()).into());
+    }
+    check_unlocked(graph, &from_id)?;
+    
+    if !graph.thoughts.contains_key(&to_id) {
+        return Err(ThoughtGraphError::ThoughtNotFound(to.to_string()).into());
+    }
+    
+    // Create a new reference
+    let reference = Reference::new(
+        to_id,
+        notes.unwrap_or_default(),
+        Utc::now(),
+    ).with_kind(kind);
+    
+    // Add the reference to the thought
+    let changes = ui::with_loading_progress("Adding reference...", || {
+        graph.command(&thoughtgraph::Command::AddReference {
+            id: from_id.clone(),
+            reference,
+        })
+    });
+    
+    println!("Added reference from '{}' to '{}'", from.green(), to.green());
+    print_changes(&changes);
+    Ok(())
+}
+
+/// The default search options, with the stop words of the configuration file
+fn search_options() -> SearchOptions {
+    SearchOptions { stop_words: STOP_WORDS.get().cloned(), ..SearchOptions::default() }
+}
+
+/// How the terms of a search are matched
+enum TermMatch {
+    /// As the options say
+    Exact(SearchOptions),
+    /// Allowing each word to differ from a term by at most this many characters
+    Fuzzy(usize),
+}
+
+/// Find the thoughts containing all of the terms, most relevant first if `ranked`. Fuzzy
+/// matches are always ordered closest first.
+fn find_terms<'a>(graph: &'a ThoughtGraph, terms: &[String], matching: &TermMatch, ranked: bool) -> Vec<(&'a ThoughtID, &'a Thought)> {
+    let options = match matching {
+        TermMatch::Exact(options) => options,
+        TermMatch::Fuzzy(max_distance) => {
+            // Keep the thoughts that match every term, by their total distance
+            let mut found: Option<HashMap<&ThoughtID, usize>> = None;
+            for term in terms {
+                let matches: HashMap<&ThoughtID, usize> = graph.fuzzy_search(term, *max_distance).into_iter().collect();
+                found = Some(match found {
+                    None => matches,
+                    Some(found) => found.into_iter().filter_map(|(id, distance)| Some((id, distance + matches.get(id)?))).collect(),
+                });
+            }
+            let mut found: Vec<(&ThoughtID, usize)> = found.unwrap_or_default().into_iter().collect();
+            found.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+            return found.into_iter().filter_map(|(id, _)| graph.thoughts.get_key_value(id)).collect();
+        },
+    };
+    match ranked {
+        true => graph
+            .search_ranked(terms, options)
+            .into_iter()
+            .filter_map(|(id, _)| graph.thoughts.get_key_value(&id))
+            .collect(),
+        false => graph.search_with(terms, options),
+    }
+}
+
+/// Search for thoughts matching a query, most relevant first if `ranked` or else in the
+/// order `page` sorts them in
+fn search_thoughts(graph: &mut ThoughtGraph, query_terms: &[String], matching: TermMatch, page: QueryOptions, ranked: bool, template: Option<&Template>, keys: &BrowseKeys) -> Result<()> {
+    if query_terms.is_empty() {
+        return Err(anyhow::anyhow!("Please provide search terms"));
+    }
+    
+    let case_sensitive = matches!(&matching, TermMatch::Exact(options) if options.case_sensitive);
+    let search_terms: Vec<String> = query_terms.iter()
+        .map(|s| if case_sensitive { s.clone() } else { s.to_lowercase() })
+        .collect();
+    if let Some(template) = template {
+        let matching_thoughts = find_terms(graph, &search_terms, &matching, ranked);
+        let matching_thoughts = match ranked {
+            true => page.page(matching_thoughts),
+            false => page.apply(graph, matching_thoughts),
+        };
+        print_with_template(graph, &matching_thoughts, template);
+        return require_results(&matching_thoughts);
+    }
+    
+    println!("Searching for: {}", search_terms.join(" ").cyan());
+    
+    // Create a progress bar for the search operation
+    let matching_thoughts = ui::with_loading_progress("Searching thoughts...", || find_terms(graph, &search_terms, &matching, ranked));
+    
+    if matching_thoughts.is_empty() {
+        println!("No thoughts found matching query: {}", search_terms.join(" "));
+        return Err(NoResults.into());
+    }
+    
+    println!("Found {} matching thoughts", matching_thoughts.len());
+    let matching_thoughts = match ranked {
+        true => page.page(matching_thoughts),
+        false => page.apply(graph, matching_thoughts),
+    };
+    
+    // Display results with enhanced formatting
+    ui::display_thought_list(graph, &matching_thoughts, MAX_DISPLAY_LENGTH)?;
+    
+    // If in interactive mode, allow selecting a thought to view
+    if io::stdin().is_terminal()
+        && !matching_thoughts.is_empty()
+        && ui::confirm("Would you like to view one of these thoughts?", true)?
+    {
+        let selected_id = ui::select_thought(graph, "Select a thought to view")?;
+        
+        if let Some(thought_id) = selected_id {
+            return view_thought(graph, &thought_id.id, keys);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Choose the embedder for semantic search: an embedding API when one is configured,
+/// otherwise the local hashing embedder
+fn semantic_embedder() -> Box<dyn Embedder> {
+    #[cfg(feature = "llm")]
+    if let Ok(embedder) = thoughtgraph::llm::OpenAiEmbedder::from_env() {
+        return Box::new(embedder);
+    }
+    Box::new(HashingEmbedder::default())
+}
+
+/// Load the vector index stored next to the graph file and bring it up to date
+fn load_vector_index(graph: &ThoughtGraph, file_path: &Path, embedder: &dyn Embedder) -> Result<VectorIndex> {
+    let mut index_path = file_path.as_os_str().to_owned();
+    index_path.push(".vectors");
+    let index_path = PathBuf::from(index_path);
+    
+    // The index is only a cache, so an unreadable index is simply rebuilt
+    let mut index = VectorIndex::load_from_file(&index_path).unwrap_or_default();
+    let embedded = ui::with_progress_bar("Updating search index...", |progress| {
+        index.update_with_progress(graph, embedder, progress)
+    })?;
+    if embedded > 0 {
+        index.save_to_file(&index_path)?;
+    }
+    
+    Ok(index)
+}
+
+/// Search for thoughts by meaning, using a vector index stored next to the graph file
+fn semantic_search(graph: &ThoughtGraph, file_path: &Path, query_terms: &[String]) -> Result<()> {
+    if query_terms.is_empty() {
+        return Err(anyhow::anyhow!("Please provide search terms"));
+    }
+    let query = query_terms.join(" ");
+    println!("Semantic search for: {}", query.cyan());
+    
+    let embedder = semantic_embedder();
+    let index = load_vector_index(graph, file_path, embedder.as_ref())?;
+    
+    let results = ui::with_loading_progress("Searching thoughts...", || {
+        index.search(embedder.as_ref(), &query, MAX_SEMANTIC_RESULTS)
+    })?;
+    let results: Vec<(&ThoughtID, &Thought, f32)> = results.iter()
+        .filter(|(_, score)| *score > 0.0)
+        .filter_map(|(id, score)| graph.thoughts.get_key_value(id).map(|(id, t)| (id, t, *score)))
+        .collect();
+    
+    if results.is_empty() {
+        println!("No related thoughts found");
+        return Err(NoResults.into());
+    }
+    
+    for (id, thought, score) in results {
+        println!("{} {} {}",
+            style(format!("{:.2}", score)).dim(),
+            style(ui::format_column(&id.id, 20)).blue(),
+            thought.title.as_deref().unwrap_or("(Untitled)")
+        );
+    }
+    
+    Ok(())
+}
+
+/// Print the thoughts created on this day in earlier years or months
+fn on_this_day(graph: &ThoughtGraph, monthly: bool, date: Option<String>) -> Result<()> {
+    let period = if monthly { Anniversary::Monthly } else { Anniversary::Yearly };
+    let today = match date {
+        Some(date) => parse_date(&date)?.with_timezone(&Local),
+        None => Local::now(),
+    };
+    let found = graph.on_this_day(&today, period);
+    
+    if found.is_empty() {
+        println!("{}", style("Nothing from this day in the past").italic());
+        return Ok(());
+    }
+    
+    let today = today.date_naive();
+    for (id, thought) in found {
+        let created = thought.created_at.with_timezone(&Local).date_naive();
+        let months = (today.year() - created.year()) * 12 + today.month() as i32 - created.month() as i32;
+        let ago = match (months / 12, months % 12) {
+            (years, 0) if years > 0 => format!("{} year{} ago", years, if years == 1 { "" } else { "s" }),
+            _ => format!("{} month{} ago", months, if months == 1 { "" } else { "s" }),
+        };
+        
+        println!("{} {} {}",
+            style(format!("{} ({})", ago, created)).dim(),
+            style(&id.id).blue(),
+            thought.title.as_deref().unwrap_or("(Untitled)")
+        );
+        if let Some(first_line) = thought.contents.lines().find(|l| !l.trim().is_empty()) {
+            let preview = console::truncate_str(first_line, MAX_DISPLAY_LENGTH, "");
+            println!("    {}", style(preview).dim());
+        }
+    }
+    
+    Ok(())
+}
+
+/// Print the topics (clusters of related thoughts) found in the graph
+fn list_topics(graph: &ThoughtGraph, file_path: &Path, semantic: bool, threshold: f32) -> Result<()> {
+    let topics = if semantic {
+        let embedder = semantic_embedder();
+        let index = load_vector_index(graph, file_path, embedder.as_ref())?;
+        ui::with_loading_progress("Clustering thoughts...", || {
+            graph.topics_by_similarity(&index, threshold)
+        })
+    } else {
+        ui::with_loading_progress("Clustering thoughts...", || graph.topics())
+    };
+    
+    if topics.is_empty() {
+        println!("{}", style("No topics found").italic());
+        return Ok(());
+    }
+    
+    let mut clustered = 0;
+    for topic in &topics {
+        clustered += topic.thoughts.len();
+        println!("{} {}",
+            style(&topic.label).bold().cyan(),
+            style(format!("({} thoughts)", topic.thoughts.len())).dim()
+        );
+        for id in &topic.thoughts {
+            let title = graph.get_thought(id)
+                .and_then(|t| t.title.clone())
+                .unwrap_or_else(|| "(Untitled)".to_string());
+            println!("  {} {}", style(ui::format_column(&id.id, 20)).blue(), title);
+        }
+        println!();
+    }
+    
+    let unclustered = graph.thoughts.len() - clustered;
+    if unclustered > 0 {
+        println!("{}", style(format!("{} thoughts are not part of any topic", unclustered)).italic());
+    }
+    
+    Ok(())
+}
+
+/// List all available tags
+fn list_tags(graph: &mut ThoughtGraph, keys: &BrowseKeys) -> Result<()> {
+    let hierarchy = graph.tags_in_hierarchy();
+    let tags: Vec<(&TagID, &Tag)> = hierarchy.iter().map(|(_, id, tag)| (*id, *tag)).collect();
+    
+    if tags.is_empty() {
+        println!("{}", style("No tags found").italic());
+        return Ok(());
+    }
+    
+    // Pre-compute counts to avoid repeated iterations
+    let counts = ui::with_loading_progress("Counting tag usage...", || {
+        tags.iter().map(|(id, _)| {
+            let count = graph.query(&thoughtgraph::Query::Tag((*id).clone())).len();
+            ((*id).clone(), count)
+        }).collect::<HashMap<_, _>>()
+    });
+
+    // Display tags with enhanced formatting, sized to the terminal, children indented
+    // under their parents
+    let names: Vec<String> = hierarchy.iter().map(|(depth, id, _)| format!("{:indent$}#{}", "", id.id, indent = depth * 2)).collect();
+    let descriptions: Vec<String> = tags
+        .iter()
+        .map(|(_, tag)| {
+            let description = match tag.aliases.is_empty() {
+                true => tag.description.clone(),
+                false => format!("{} (also {})", tag.description, tag.aliases.join(", ")).trim_start().to_string(),
+            };
+            match &tag.query {
+                Some(query) => format!("{} [{}]", description, query).trim_start().to_string(),
+                None => description,
+            }
+        })
+        .collect();
+    let name_width = ui::column_width("TAG", names.iter().map(String::as_str));
+    let count_width = "COUNT".len().max(counts.values().max().unwrap_or(&0).to_string().len());
+    let widths = ui::fit_columns(
+        &[name_width, ui::column_width("DESCRIPTION", descriptions.iter().map(String::as_str)), count_width],
+        &[ui::min_id_width(name_width), 10, count_width],
+    );
+    println!("{} {} {}",
+        style(ui::format_column("TAG", widths[0])).bold().underlined(),
+        style(ui::format_column("DESCRIPTION", widths[1])).bold().underlined(),
+        style(ui::format_column("COUNT", widths[2])).bold().underlined()
+    );
+
+    for (((id, tag), name), description) in tags.iter().zip(&names).zip(&descriptions) {
+        let count = counts.get(*id).unwrap_or(&0);
+        let name = ui::fit_column(name, widths[0]);
+        let name = match tag.color.as_deref().and_then(|color| color.parse::<Color>().ok()) {
+            Some(color) => name.color(color),
+            None => name.yellow(),
+        };
+        
+        println!("{} {} {}",
+            name,
+            style(ui::fit_column(description, widths[1])),
+            style(ui::fit_column(&count.to_string(), widths[2]))
+        );
+    }
+    
+    // If in interactive mode, offer to view thoughts with a specific tag
+    if io::stdin().is_terminal()
+        && !tags.is_empty()
+        && ui::confirm("Would you like to view thoughts with a specific tag?", false)?
+    {
+        let tag_items: Vec<String> = tags.iter()
+            .map(|(id, tag)| format!("#{} - {}", id.id, tag.description))
+            .collect();
+        
+        let selection = dialoguer::Select::with_theme(&ui::get_theme())
+            .with_prompt("Select a tag")
+            .default(0)
+            .items(&tag_items)
+            .interact_opt()?;
+        
+        if let Some(idx) = selection {
+            let selected_tag = &tags[idx].0.id;
+            return list_thoughts(graph, vec![selected_tag.to_string()], false, None, RECENT_FIRST, None, keys);
+        }
+    }
+    
+    Ok(())
+}
+
+/// List external URLs mentioned in thoughts, with the thoughts that mention them
+fn list_links(graph: &ThoughtGraph, thought_filter: Option<String>) -> Result<()> {
+    let links = match &thought_filter {
+        Some(id) => {
+            let thought_id = ThoughtID::new(id.clone());
+            let thought = graph.get_thought(&thought_id)
+                .ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.to_string()))?;
+            
+            thought.extract_links()
+                .into_iter()
+                .map(|url| {
+                    let ids = graph.thoughts_linking_to(&url);
+                    (url, ids)
+                })
+                .collect::<Vec<_>>()
+        },
+        None => graph.external_links()
+            .into_iter()
+            .map(|(url, ids)| (url.to_string(), ids))
+            .collect(),
+    };
+    
+    if links.is_empty() {
+        println!("{}", style("No external links found").italic());
+        return Ok(());
+    }
+    
+    for (url, ids) in &links {
+        println!("{}", style(url).cyan());
+        for id in ids {
+            let title = graph.get_thought(id)
+                .and_then(|t| t.title.clone())
+                .unwrap_or_else(|| "(Untitled)".to_string());
+            println!("  ← {} {}", style(&id.id).blue(), title);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Print statistics about the graph, with bar charts
+fn show_stats(graph: &ThoughtGraph, top: usize, months: usize) -> Result<()> {
+    let stats = graph.stats();
+    println!("{} | {} | {}",
+        style(format!("Thoughts: {}", stats.thoughts)).bold(),
+        style(format!("Tags: {}", stats.tags)).bold(),
+        style(format!("References: {}", stats.references)).bold()
+    );
+    if stats.thoughts == 0 {
+        println!("{}", style("No thoughts yet").italic());
+        return Ok(());
+    }
+
+    if !stats.tag_usage.is_empty() {
+        println!();
+        let rows: Vec<(String, usize)> = stats.tag_usage.iter().take(top).map(|(tag, count)| (tag.id.to_string(), *count)).collect();
+        let heading = match stats.tag_usage.len() > rows.len() {
+            true => format!("Top {} of {} tags in use", rows.len(), stats.tag_usage.len()),
+            false => "Tags in use".to_string(),
+        };
+        ui::print_bar_chart(&heading, &rows);
+    }
+
+    println!();
+    let skip = stats.created_per_month.len().saturating_sub(months);
+    let rows: Vec<(String, usize)> = stats.created_per_month[skip..]
+        .iter()
+        .map(|((year, month), count)| (format!("{}-{:02}", year, month), *count))
+        .collect();
+    ui::print_bar_chart("Thoughts created per month", &rows);
+
+    println!();
+    let rows: Vec<(String, usize)> = stats.degrees
+        .iter()
+        .map(|((low, high), count)| (if low == high { low.to_string() } else { format!("{}-{}", low, high) }, *count))
+        .collect();
+    ui::print_bar_chart("Thoughts by number of connections", &rows);
+    Ok(())
+}
+
+/// Number of examples to list for each problem in a health report
+const HEALTH_EXAMPLES: usize = 3;
+
+/// Print a health report of the graph with commands to clean it up
+fn show_health(graph: &ThoughtGraph, stale_days: i64) -> Result<()> {
+    let report = graph.health(Utc::now() - Duration::days(stale_days));
+    let score = match report.score {
+        90.. => style(report.score).green(),
+        70..=89 => style(report.score).yellow(),
+        _ => style(report.score).red(),
+    };
+    println!("{} {}/100", style("Graph health:").bold(), score.bold());
+    println!();
+    let line = |label: &str, value: String| println!("  {:<16} {}", label, value);
+    line("Orphans", format!("{} of {} thoughts", report.orphans.len(), report.thoughts));
+    line("Broken links", report.broken_links.len().to_string());
+    line("Unused tags", format!("{} of {} tags", report.unused_tags.len(), report.tags));
+    line("Stale thoughts", format!("{} (not updated in {} days)", report.stale.len(), stale_days));
+    line("Duplicates", format!("{} pairs", report.duplicates.len()));
+    line("Hubs", format!("{} ({}+ connections)", report.hubs.len(), health::HUB_DEGREE));
+    line("Leaves", format!("{} (1 connection)", report.leaves));
+
+    if report.is_healthy() {
+        println!();
+        println!("{}", style("Nothing to clean up").italic());
+        return Ok(());
+    }
+    println!();
+    println!("{}", style("Suggested cleanup").bold());
+    let suggest = |action: String, commands: Vec<String>| {
+        if commands.is_empty() {
+            return;
+        }
+        println!("  • {}:", action);
+        for command in commands.iter().take(HEALTH_EXAMPLES) {
+            println!("      {}", style(command).cyan());
+        }
+        if commands.len() > HEALTH_EXAMPLES {
+            println!("      {}", style(format!("... and {} more", commands.len() - HEALTH_EXAMPLES)).dim());
+        }
+    };
+    if !report.broken_links.is_empty() {
+        suggest(format!("Fix {} broken links", report.broken_links.len()), vec!["thoughts check-links".to_string()]);
+    }
+    suggest(
+        format!("Link {} orphans to related thoughts, or delete them", report.orphans.len()),
+        report.orphans.iter().map(|id| format!("thoughts reference {} <other>", id.id)).collect(),
+    );
+    suggest(
+        format!("Compare {} pairs of possible duplicates and delete one of each", report.duplicates.len()),
+        report.duplicates.iter().map(|(a, b)| format!("thoughts view {a}; thoughts view {b}; thoughts delete {b}", a = a.id, b = b.id)).collect(),
+    );
+    if !report.hubs.is_empty() {
+        suggest(format!("Consider splitting {} hubs", report.hubs.len()), vec!["thoughts hubs".to_string()]);
+    }
+    suggest(
+        format!("Review {} stale thoughts, oldest first", report.stale.len()),
+        report.stale.iter().map(|id| format!("thoughts edit {}", id.id)).collect(),
+    );
+    suggest(
+        format!("Delete {} unused tags", report.unused_tags.len()),
+        report.unused_tags
+            .iter()
+            .map(|tag| format!("echo '{}' | thoughts apply --stdin", serde_json::json!({ "DeleteTag": { "id": tag.id } })))
+            .collect(),
+    );
+    Ok(())
+}
+
+/// List the hubs of the graph with where to split them
+fn show_hubs(graph: &ThoughtGraph, thresholds: HubThresholds) -> Result<()> {
+    let hubs = graph.hubs(&thresholds);
+    if hubs.is_empty() {
+        println!(
+            "{}",
+            style(format!("No thoughts with {}+ links or {}+ words", thresholds.min_degree, thresholds.min_words)).italic()
+        );
+        return Ok(());
+    }
+    let plural = |count: usize, noun: &str| format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" });
+    for (i, hub) in hubs.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let title = graph.get_thought(&hub.id).and_then(|thought| thought.title.as_deref()).unwrap_or("(Untitled)");
+        println!("{} {}", style(hub.id.as_str()).cyan().bold(), title);
+        println!(
+            "  {}, {}, {}",
+            plural(hub.backlinks, "backlink"),
+            plural(hub.references, "reference"),
+            plural(hub.words, "word")
+        );
+        if !hub.split_points.is_empty() {
+            println!("  {}", style("Split at:").bold());
+            for point in &hub.split_points {
+                println!("    line {:<5} {} {}", point.line, point.heading, style(format!("({})", plural(point.words, "word"))).dim());
+            }
+        }
+        if !hub.clusters.is_empty() {
+            println!("  {}", style("Related clusters:").bold());
+            for cluster in &hub.clusters {
+                let mut members: Vec<&str> = cluster.thoughts.iter().take(HEALTH_EXAMPLES).map(|id| id.as_str()).collect();
+                if cluster.thoughts.len() > HEALTH_EXAMPLES {
+                    members.push("...");
+                }
+                println!("    {} {}: {}", style(&cluster.label).yellow(), style(format!("({})", cluster.thoughts.len())).dim(), members.join(", "));
+            }
+        }
+    }
     Ok(())
 }
 
-/// Delete a thought
-fn delete_thought(graph: &mut ThoughtGraph, id: &str, force: bool) -> Result<()> {
-    let thought_id = ThoughtID::new(id.to_string());
-    
-    // Check if thought exists
-    if !graph.thoughts.contains_key(&thought_id) {
-        return Err(anyhow::anyhow!("Thought '{}' not found", id));
+/// Print or save the most used words of the thoughts matching a query, or of all thoughts
+fn word_cloud(graph: &ThoughtGraph, query: Option<String>, limit: usize, format: &str, output: Option<PathBuf>) -> Result<()> {
+    let ids: Vec<ThoughtID> = match query {
+        Some(query) => graph.find_thoughts(&thoughtgraph::Query::parse(&query)?).into_iter().map(|(id, _)| id.clone()).collect(),
+        None => graph.thoughts.keys().cloned().collect(),
+    };
+    let terms = graph.term_frequencies(&ids, limit);
+    if terms.is_empty() {
+        eprintln!("{}", style("No words found").italic());
+        return Err(NoResults.into());
     }
-    
-    // Confirm deletion if not forced
-    if !force {
-        if io::stdin().is_terminal() {
-            if !ui::confirm(&format!("Are you sure you want to delete thought '{}'?", id), false)? {
-                println!("Deletion cancelled");
-                return Ok(());
-            }
-        } else {
-            // In non-interactive mode, we should require the --force flag
-            return Err(anyhow::anyhow!("Deletion requires --force flag in non-interactive mode"));
-        }
+    let text = match format {
+        "list" => terms.iter().map(|(term, count)| format!("{}\t{}\n", count, term)).collect(),
+        "svg" => wordcloud::render_svg(&terms),
+        _ => return Err(ThoughtGraphError::ParseError(format!("unknown word cloud format '{}' (expected list or svg)", format)).into()),
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(&path, text).context(format!("Failed to write {}", path.display()))?;
+            println!("Wrote {} words to {}", terms.len(), style(path.display()).green());
+        },
+        None => print!("{}", text),
     }
-    
-    // Delete the thought with progress indicator
-    ui::with_loading_progress(&format!("Deleting thought '{}'...", id), || {
-        graph.command(&thoughtgraph::Command::DeleteThought {
-            id: thought_id.clone(),
-        });
-    });
-    
-    println!("Thought '{}' deleted successfully", id.green());
     Ok(())
 }
 
-/// Add a tag to a thought
-fn tag_thought(graph: &mut ThoughtGraph, id: &str, tag: &str, description: Option<String>) -> Result<()> {
-    let thought_id = ThoughtID::new(id.to_string());
-    let tag_id = TagID::new(tag.to_string());
-    
-    // Check if thought exists
-    let thought = match graph.get_thought(&thought_id) {
-        Some(t) => t.clone(),
-        None => return Err(anyhow::anyhow!("Thought '{}' not found", id)),
+/// List the broken links, and fix them all with `--remove` or `--stub`, or one by one
+fn check_links(graph: &mut ThoughtGraph, remove: bool, stub: bool) -> Result<()> {
+    let links = graph.broken_links();
+    if links.is_empty() {
+        println!("{}", style("No broken links found").italic());
+        return Ok(());
+    }
+
+    let from_width = ui::column_width("FROM", links.iter().map(|link| link.from.as_str()));
+    let to_width = ui::column_width("MISSING", links.iter().map(|link| link.to.as_str()));
+    println!("{} {} {}",
+        style(ui::format_column("FROM", from_width)).bold().underlined(),
+        style(ui::format_column("MISSING", to_width)).bold().underlined(),
+        style("KIND").bold().underlined()
+    );
+    let kind = |link: &BrokenLink| match link.source {
+        LinkSource::Reference => "reference",
+        LinkSource::Mention => "mention",
     };
-    
-    // Create the tag if it doesn't exist
-    if !graph.tags.contains_key(&tag_id) {
-        let desc = match description {
-            Some(d) => d,
-            None => Input::with_theme(&ui::get_theme())
-                .with_prompt(format!("Enter description for new tag '{}'", tag))
-                .interact()?,
+    for link in &links {
+        println!("{} {} {}",
+            style(ui::format_column(&link.from.id, from_width)).blue(),
+            style(ui::format_column(&link.to.id, to_width)).red(),
+            kind(link)
+        );
+    }
+
+    let fix = match (remove, stub) {
+        (true, _) => Some(LinkFix::Remove),
+        (_, true) => Some(LinkFix::Stub),
+        _ => None,
+    };
+    if let Some(fix) = fix {
+        let before = graph.clone();
+        for link in &links {
+            graph.fix_broken_link(link, &fix)?;
+        }
+        print_changes(&ChangeSet::between(&before, graph));
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() || !ui::confirm("Fix them one by one?", false)? {
+        return Ok(());
+    }
+
+    let actions = &["Remove the link", "Create an empty thought", "Link to another thought", "Skip", "Stop"];
+    for link in &links {
+        let action = dialoguer::Select::with_theme(&ui::get_theme())
+            .with_prompt(format!("Broken {} '{}' in '{}'", kind(link), link.to.id, link.from.id))
+            .items(actions)
+            .default(0)
+            .interact()?;
+        let fix = match action {
+            0 => LinkFix::Remove,
+            1 => LinkFix::Stub,
+            2 => match ui::select_thought(graph, "Select the thought to link to")? {
+                Some(target) => LinkFix::Retarget(target),
+                None => continue,
+            },
+            3 => continue,
+            _ => break,
         };
-        
-        ui::with_loading_progress("Creating tag...", || {
-            graph.create_tag(tag_id.clone(), desc)
-        })?;
+        print_changes(&graph.fix_broken_link(link, &fix)?);
     }
-    
-    // Add the tag to the thought
-    let mut updated_thought = thought.clone();
-    updated_thought.add_tag(tag_id.clone());
-    
-    ui::with_loading_progress("Updating thought...", || {
-        graph.command(&thoughtgraph::Command::PutThought {
-            id: thought_id.clone(),
-            thought: updated_thought,
-        });
-    });
-    
-    println!("Added tag '{}' to thought '{}'", tag.yellow(), id.green());
     Ok(())
 }
 
-/// Remove a tag from a thought
-fn untag_thought(graph: &mut ThoughtGraph, id: &str, tag: &str) -> Result<()> {
-    let thought_id = ThoughtID::new(id.to_string());
-    let tag_id = TagID::new(tag.to_string());
-    
-    // Check if thought exists
-    let thought = match graph.get_thought(&thought_id) {
-        Some(t) => t.clone(),
-        None => return Err(anyhow::anyhow!("Thought '{}' not found", id)),
-    };
-    
-    // Check if the thought actually has this tag
-    if !thought.tags.contains(&tag_id) {
-        return Err(anyhow::anyhow!("Thought '{}' doesn't have tag '{}'", id, tag));
+/// Apply a stream of JSON-encoded commands read from standard input.
+///
+/// The commands are applied as a unit: if any of them fails to parse or is rejected by
+/// validation, an error line is printed for it and none of the changes are saved. If all
+/// of them succeed, one result line per command is printed, containing its changes.
+fn apply_commands(graph: &mut ThoughtGraph, stdin: bool, strict: bool) -> Result<()> {
+    if !stdin {
+        return Err(anyhow::anyhow!("Commands can only be read from standard input; pass --stdin"));
     }
     
-    // Remove the tag from the thought
-    let mut updated_thought = thought.clone();
-    updated_thought.remove_tag(&tag_id);
+    let options = ValidationOptions { require_existing_references: strict };
+    let input = io::stdin().lock();
+    let commands = serde_json::Deserializer::from_reader(input).into_iter::<thoughtgraph::Command>();
     
-    ui::with_loading_progress("Updating thought...", || {
-        graph.command(&thoughtgraph::Command::PutThought {
-            id: thought_id.clone(),
-            thought: updated_thought,
-        });
-    });
+    let mut results = Vec::new();
+    for (index, command) in commands.enumerate() {
+        let outcome = command
+            .map_err(anyhow::Error::from)
+            .and_then(|command| Ok(graph.try_command_with(&command, &options)?));
+        match outcome {
+            Ok(changes) => results.push(serde_json::json!({ "index": index, "ok": true, "changes": changes })),
+            Err(e) => {
+                println!("{}", serde_json::json!({ "index": index, "ok": false, "error": e.to_string() }));
+                return Err(anyhow::anyhow!("Command {} failed, so no changes were saved", index));
+            }
+        }
+    }
     
-    println!("Removed tag '{}' from thought '{}'", tag.yellow(), id.green());
+    for result in results {
+        println!("{}", result);
+    }
     Ok(())
 }
 
-/// Add a reference from one thought to another
-fn add_reference(graph: &mut ThoughtGraph, from: &str, to: &str, notes: Option<String>) -> Result<()> {
-    let from_id = ThoughtID::new(from.to_string());
-    let to_id = ThoughtID::new(to.to_string());
-    
-    // Check if both thoughts exist
-    let from_thought = match graph.get_thought(&from_id) {
-        Some(t) => t.clone(),
-        None => return Err(anyhow::anyhow!("Thought '{}' not found", from)),
+/// Import thoughts from a file written by another program
+fn import(graph: &mut ThoughtGraph, command: ImportCommands) -> Result<()> {
+    let (file, entries, dry_run) = match command {
+        ImportCommands::Ndjson { file, dry_run } => return import_ndjson(graph, &file, dry_run),
+        ImportCommands::Bibtex { file, dry_run } => {
+            let text = fs::read_to_string(&file).with_context(|| format!("Could not read {}", file.display()))?;
+            let entries = bibliography::parse_bibtex(&text)?;
+            (file, entries, dry_run)
+        },
+        ImportCommands::Zotero { file, dry_run } => {
+            let text = fs::read_to_string(&file).with_context(|| format!("Could not read {}", file.display()))?;
+            let entries = bibliography::parse_zotero_csv(&text)?;
+            (file, entries, dry_run)
+        },
     };
     
-    if !graph.thoughts.contains_key(&to_id) {
-        return Err(anyhow::anyhow!("Thought '{}' not found", to));
+    let count = entries.len();
+    let plan = graph.plan_bibliography_import(entries)?;
+    for (id, outcome) in &plan.outcomes {
+        match outcome {
+            ImportOutcome::Create => println!("{} {}", "+".green(), id.id.blue()),
+            ImportOutcome::Update => println!("{} {}", "~".yellow(), id.id.blue()),
+            ImportOutcome::Unchanged => {},
+            ImportOutcome::Duplicate(original) => {
+                println!("{} {} {}", "=".dimmed(), id.id.blue(), format!("(same as {}, skipped)", original.id).dimmed())
+            },
+        }
     }
     
-    // Create a new reference
-    let reference = Reference::new(
-        to_id,
-        notes.unwrap_or_default(),
-        Utc::now(),
+    let noun = if count == 1 { "entry" } else { "entries" };
+    let count_of = |matches: fn(&ImportOutcome) -> bool| plan.outcomes.iter().filter(|(_, outcome)| matches(outcome)).count();
+    let summary = format!(
+        "{} to create, {} to update, {} skipped as unchanged or duplicates",
+        count_of(|outcome| matches!(outcome, ImportOutcome::Create)),
+        count_of(|outcome| matches!(outcome, ImportOutcome::Update)),
+        count_of(|outcome| matches!(outcome, ImportOutcome::Unchanged | ImportOutcome::Duplicate(_)))
     );
+    if dry_run {
+        println!("Dry run of importing {} {} from {}: {}", count, noun, file.display(), summary);
+        println!("{}", style("Nothing was changed").italic());
+        return Ok(());
+    }
     
-    // Add the reference to the thought
-    let mut updated_thought = from_thought.clone();
-    updated_thought.add_reference(reference);
-    
-    ui::with_loading_progress("Adding reference...", || {
-        graph.command(&thoughtgraph::Command::PutThought {
-            id: from_id.clone(),
-            thought: updated_thought,
-        });
-    });
-    
-    println!("Added reference from '{}' to '{}'", from.green(), to.green());
+    let changes = ui::with_loading_progress("Importing entries...", || graph.apply(thoughtgraph::Command::Batch(plan.commands)));
+    println!("Imported {} {} from {}", count, noun, file.display());
+    print_changes(&changes);
     Ok(())
 }
 
-/// Search for thoughts matching a query
-fn search_thoughts(graph: &ThoughtGraph, query_terms: &[String]) -> Result<()> {
-    if query_terms.is_empty() {
-        return Err(anyhow::anyhow!("Please provide search terms"));
+/// Import tags and thoughts from an NDJSON file or stdin, a line at a time
+fn import_ndjson(graph: &mut ThoughtGraph, file: &Path, dry_run: bool) -> Result<()> {
+    let (reader, source): (Box<dyn io::BufRead>, String) = match file.to_str() {
+        Some("-") => (Box::new(io::stdin().lock()), "standard input".to_string()),
+        _ => {
+            let reader = fs::File::open(file).with_context(|| format!("Could not read {}", file.display()))?;
+            (Box::new(io::BufReader::new(reader)), file.display().to_string())
+        },
+    };
+    let imported = ui::with_progress_bar("Importing records...", |progress| match dry_run {
+        true => graph.clone().import_ndjson_with_progress(reader, progress),
+        false => graph.import_ndjson_with_progress(reader, progress),
+    })?;
+    let summary = format!(
+        "{} tags created, {} updated; {} thoughts created, {} updated",
+        imported.tags_created, imported.tags_updated, imported.thoughts_created, imported.thoughts_updated
+    );
+    if dry_run {
+        println!("Dry run of importing {}: {}", source, summary);
+        println!("{}", style("Nothing was changed").italic());
+        return Ok(());
     }
-    
-    let search_terms: Vec<String> = query_terms.iter()
-        .map(|s| s.to_lowercase())
-        .collect();
-    
-    println!("Searching for: {}", search_terms.join(" ").cyan());
-    
-    // Create a progress bar for the search operation
-    let matching_thoughts = ui::with_loading_progress("Searching thoughts...", || {
-        // Simple search in titles and contents
-        graph.thoughts.iter()
-            .filter(|(_, thought)| {
-                let title_text = thought.title.clone().unwrap_or_default().to_lowercase();
-                let content_text = thought.contents.to_lowercase();
-                let combined_text = format!("{} {}", title_text, content_text);
-                
-                search_terms.iter().all(|term| combined_text.contains(term))
-            })
-            .collect::<Vec<(&ThoughtID, &Thought)>>()
-    });
-    
-    if matching_thoughts.is_empty() {
-        println!("No thoughts found matching query: {}", search_terms.join(" "));
+    println!("Imported {}: {}", source, summary);
+    Ok(())
+}
+
+/// List the thoughts matching a saved query
+fn run_saved_query(graph: &ThoughtGraph, name: &str, template: Option<&Template>) -> Result<()> {
+    let thoughts = graph.run_saved_query(name)?;
+    match template {
+        Some(template) => print_with_template(graph, &thoughts, template),
+        None => ui::display_thought_list(graph, &thoughts, MAX_DISPLAY_LENGTH)?,
+    }
+    require_results(&thoughts)
+}
+
+/// List the thoughts matching a query, or explain how it is evaluated
+fn run_query(graph: &ThoughtGraph, query: &str, explain: bool, template: Option<&Template>) -> Result<()> {
+    let query = thoughtgraph::Query::parse(query)?;
+    if explain {
+        let started = Instant::now();
+        let plan = graph.explain(&query);
+        print!("{}", plan);
+        println!("{}", style(format!("Evaluated in {:.1?} over {} thoughts", started.elapsed(), graph.thoughts.len())).dim());
+        for step in plan.why_empty() {
+            let reason = match step.access {
+                thoughtgraph::explain::Access::Intersection => "its parts have no thought in common",
+                thoughtgraph::explain::Access::Complement => "its subquery finds every thought",
+                _ => "it finds nothing",
+            };
+            println!("{}", style(format!("No results because of step {}: {}", step.step, reason)).yellow());
+        }
         return Ok(());
     }
-    
-    println!("Found {} matching thoughts", matching_thoughts.len());
-    
-    // Display results with enhanced formatting
-    ui::display_thought_list(graph, &matching_thoughts, MAX_DISPLAY_LENGTH)?;
-    
-    // If in interactive mode, allow selecting a thought to view
-    if io::stdin().is_terminal() && !matching_thoughts.is_empty() {
-        if ui::confirm("Would you like to view one of these thoughts?", true)? {
-            let selected_id = ui::select_thought(graph, "Select a thought to view")?;
-            
-            if let Some(thought_id) = selected_id {
-                return view_thought(graph, &thought_id.id);
+    let mut thoughts = graph.find_thoughts(&query);
+    thoughts.sort_by_key(|(id, _)| *id);
+    match template {
+        Some(template) => print_with_template(graph, &thoughts, template),
+        None => ui::display_thought_list(graph, &thoughts, MAX_DISPLAY_LENGTH)?,
+    }
+    require_results(&thoughts)
+}
+
+/// Print each thought through a template, one after the other
+fn print_with_template(graph: &ThoughtGraph, thoughts: &[(&ThoughtID, &Thought)], template: &Template) {
+    for (id, thought) in thoughts {
+        println!("{}", template.render(graph, id, thought));
+    }
+}
+
+/// Manage the snapshots of the graph file
+fn manage_snapshots(graph: &mut ThoughtGraph, file_path: &Path, command: SnapshotCommands) -> Result<()> {
+    let snapshots = Snapshots::of_graph(file_path);
+    #[cfg(feature = "encryption")]
+    let snapshots = match graph_key() {
+        Some(key) => snapshots.encrypted_with(key),
+        None => snapshots,
+    };
+    match command {
+        SnapshotCommands::Create { name, reason } => {
+            let info = snapshots.create(&name, graph, reason, Utc::now())?;
+            println!("Took snapshot '{}' of {} thoughts", info.name.green(), info.thoughts);
+        },
+        SnapshotCommands::List => {
+            let list = snapshots.list()?;
+            if list.is_empty() {
+                println!("{}", style("No snapshots").italic());
+                return Ok(());
             }
-        }
+            let width = ui::column_width("NAME", list.iter().map(|info| info.name.as_str()));
+            println!("{} {} {} {}",
+                style(ui::format_column("NAME", width)).bold().underlined(),
+                style(ui::format_column("TAKEN", 20)).bold().underlined(),
+                style(ui::format_column("THOUGHTS", 9)).bold().underlined(),
+                style("REASON").bold().underlined()
+            );
+            for info in list {
+                println!("{} {} {} {}",
+                    style(ui::format_column(&info.name, width)).cyan(),
+                    ui::format_column(&ui::format_time(info.taken_at), 20),
+                    ui::format_column(&info.thoughts.to_string(), 9),
+                    info.reason.unwrap_or_default()
+                );
+            }
+        },
+        SnapshotCommands::Restore { name } => {
+            let restored = snapshots.load(&name)?;
+            let now = Utc::now();
+            let backup = format!("before-restore-{}", now.with_timezone(&Local).format("%Y%m%d-%H%M%S"));
+            snapshots.create(&backup, graph, Some(format!("Before restoring '{}'", name)), now)?;
+            *graph = restored;
+            println!("Restored snapshot '{}' of {} thoughts", name.green(), graph.thoughts.len());
+            println!("{}", style(format!("The graph as it was is in snapshot '{}'", backup)).dim());
+        },
+        SnapshotCommands::Remove { name } => {
+            snapshots.remove(&name)?;
+            println!("Removed snapshot '{}'", name);
+        },
     }
-    
     Ok(())
 }
 
-/// List all available tags
-fn list_tags(graph: &ThoughtGraph) -> Result<()> {
-    let tags: Vec<(&TagID, &Tag)> = graph.tags.iter().collect();
-    
-    if tags.is_empty() {
-        println!("{}", style("No tags found").italic());
-        return Ok(());
+/// Whether a taxonomy file is JSON rather than TOML, from its format or else its extension
+fn taxonomy_is_json(format: Option<&str>, path: Option<&Path>) -> Result<bool> {
+    match format {
+        Some("json") => Ok(true),
+        Some("toml") => Ok(false),
+        Some(format) => Err(ThoughtGraphError::ParseError(format!("Unknown taxonomy format '{}' (use toml or json)", format)).into()),
+        None => Ok(path.and_then(Path::extension).is_some_and(|extension| extension == "json")),
     }
-    
-    // Display tags with enhanced formatting
-    println!("{} {} {}",
-        style(ui::format_column("TAG", 20)).bold().underlined(),
-        style(ui::format_column("DESCRIPTION", 40)).bold().underlined(),
-        style(ui::format_column("COUNT", 10)).bold().underlined()
-    );
-    
-    // Pre-compute counts to avoid repeated iterations
-    let counts = ui::with_loading_progress("Counting tag usage...", || {
-        tags.iter().map(|(id, _)| {
-            let count = graph.thoughts.values()
-                .filter(|thought| thought.tags.contains(*id))
-                .count();
-            ((*id).clone(), count)
-        }).collect::<HashMap<_, _>>()
-    });
+}
 
-    for (id, tag) in &tags {
-        let count = counts.get(id).unwrap_or(&0);
-        
-        println!("{} {} {}",
-            style(ui::format_column(&format!("#{}", id.id), 20)).yellow(),
-            style(ui::format_column(&tag.description, 40)),
-            style(ui::format_column(&count.to_string(), 10))
-        );
+/// Export the tags of the graph, or import tags shared by someone else
+fn manage_taxonomy(graph: &mut ThoughtGraph, command: TaxonomyCommands) -> Result<()> {
+    match command {
+        TaxonomyCommands::Export { output, format } => {
+            let taxonomy = graph.taxonomy();
+            let text = match taxonomy_is_json(format.as_deref(), output.as_deref())? {
+                true => serde_json::to_string_pretty(&taxonomy)? + "\n",
+                false => toml::to_string_pretty(&taxonomy)?,
+            };
+            match output {
+                Some(path) => {
+                    fs::write(&path, text).with_context(|| format!("Could not write {}", path.display()))?;
+                    println!("Exported {} tags to {}", taxonomy.tags.len(), path.display());
+                },
+                None => print!("{}", text),
+            }
+        },
+        TaxonomyCommands::Import { file, dry_run } => {
+            let text = fs::read_to_string(&file).with_context(|| format!("Could not read {}", file.display()))?;
+            let taxonomy = match taxonomy_is_json(None, Some(&file))? {
+                true => taxonomy::from_json(&text)?,
+                false => toml::from_str(&text).map_err(|e| ThoughtGraphError::ParseError(format!("taxonomy: {}", e.message())))?,
+            };
+            let imported = match dry_run {
+                true => graph.clone().import_taxonomy(&taxonomy)?,
+                false => graph.import_taxonomy(&taxonomy)?,
+            };
+            for id in &imported.created {
+                println!("{} #{}", "+".green(), id.id.yellow());
+            }
+            for id in &imported.updated {
+                println!("{} #{}", "~".yellow(), id.id.yellow());
+            }
+            let summary = format!("{} tags created, {} updated", imported.created.len(), imported.updated.len());
+            if dry_run {
+                println!("Dry run of importing {}: {}", file.display(), summary);
+                println!("{}", style("Nothing was changed").italic());
+                return Ok(());
+            }
+            println!("Imported {}: {}", file.display(), summary);
+        },
+    }
+    Ok(())
+}
+
+/// Manage the graph's saved queries
+fn manage_saved_queries(graph: &mut ThoughtGraph, command: SavedCommands) -> Result<()> {
+    match command {
+        SavedCommands::List => {
+            let queries = graph.list_saved_queries();
+            if queries.is_empty() {
+                println!("{}", style("No saved queries").italic());
+                return Ok(());
+            }
+            
+            println!("{} {}",
+                style(ui::format_column("NAME", 20)).bold().underlined(),
+                style(ui::format_column("QUERY", 60)).bold().underlined()
+            );
+            for (name, query) in queries {
+                println!("{} {}",
+                    style(ui::format_column(name, 20)).cyan(),
+                    style(ui::format_column(&query.to_string(), 60))
+                );
+            }
+        },
+        SavedCommands::Run { name, format } => {
+            let template = format.as_deref().map(Template::parse).transpose()?;
+            return run_saved_query(graph, &name, template.as_ref());
+        },
+        SavedCommands::Add { name, query, tags, references, referenced_by, any } => {
+            let mut conditions = Vec::new();
+            for tag in tags {
+                conditions.push(Box::new(thoughtgraph::Query::Tag(TagID::parse(&tag)?)));
+            }
+            for id in references {
+                conditions.push(Box::new(thoughtgraph::Query::References(ThoughtID::parse(&id)?)));
+            }
+            for id in referenced_by {
+                conditions.push(Box::new(thoughtgraph::Query::ReferencedBy(ThoughtID::parse(&id)?)));
+            }
+            let query = match (query, conditions.len()) {
+                (Some(query), _) => thoughtgraph::Query::parse(&query)?,
+                (None, 0) => return Err(anyhow::anyhow!("Give --query or at least one of --tag, --references or --referenced-by")),
+                (None, 1) => *conditions.remove(0),
+                _ if any => thoughtgraph::Query::Or(conditions),
+                _ => thoughtgraph::Query::And(conditions),
+            };
+            
+            let replaced = graph.saved_queries.contains_key(&name);
+            graph.try_command(&thoughtgraph::Command::PutSavedQuery { name: name.clone(), query })?;
+            if replaced {
+                println!("Updated saved query '{}'", name.green());
+            } else {
+                println!("Saved query '{}'", name.green());
+            }
+            println!("Run it with 'thoughts run {}'", name);
+        },
+        SavedCommands::Remove { name } => {
+            if !graph.saved_queries.contains_key(&name) {
+                return Err(ThoughtGraphError::SavedQueryNotFound(name).into());
+            }
+            graph.command(&thoughtgraph::Command::DeleteSavedQuery { name: name.clone() });
+            println!("Removed saved query '{}'", name.green());
+        },
     }
     
-    // If in interactive mode, offer to view thoughts with a specific tag
-    if io::stdin().is_terminal() && !tags.is_empty() {
-        if ui::confirm("Would you like to view thoughts with a specific tag?", false)? {
-            let tag_items: Vec<String> = tags.iter()
-                .map(|(id, tag)| format!("#{} - {}", id.id, tag.description))
-                .collect();
+    Ok(())
+}
+
+/// Manage the graph's tagging rules
+fn manage_rules(graph: &mut ThoughtGraph, command: RuleCommands) -> Result<()> {
+    match command {
+        RuleCommands::List => {
+            let rules = graph.list_rules();
+            if rules.is_empty() {
+                println!("{}", style("No tagging rules defined").italic());
+                return Ok(());
+            }
             
-            let selection = dialoguer::Select::with_theme(&ui::get_theme())
-                .with_prompt("Select a tag")
-                .default(0)
-                .items(&tag_items)
-                .interact_opt()?;
+            println!("{} {} {}",
+                style(ui::format_column("RULE", 20)).bold().underlined(),
+                style(ui::format_column("PATTERN", 40)).bold().underlined(),
+                style(ui::format_column("TAG", 20)).bold().underlined()
+            );
+            for (name, rule) in rules {
+                println!("{} {} {}",
+                    style(ui::format_column(name, 20)).cyan(),
+                    style(ui::format_column(&format!("/{}/", rule.pattern), 40)),
+                    style(ui::format_column(&format!("#{}", rule.tag.id), 20)).yellow()
+                );
+            }
+        },
+        RuleCommands::Add { name, pattern, tag } => {
+            let rule = TagRule::new(pattern, TagID::parse(&tag)?)?;
+            let replaced = graph.rules.contains_key(&name);
+            graph.apply(thoughtgraph::Command::PutTagRule { name: name.clone(), rule });
             
-            if let Some(idx) = selection {
-                let selected_tag = &tags[idx].0.id;
-                return list_thoughts(graph, Some(selected_tag.clone()));
+            if replaced {
+                println!("Updated rule '{}'", name.green());
+            } else {
+                println!("Added rule '{}'", name.green());
             }
-        }
+            println!("Run 'thoughts rules apply' to apply it to existing thoughts");
+        },
+        RuleCommands::Remove { name } => {
+            if !graph.rules.contains_key(&name) {
+                return Err(Failed::not_found(format!("Rule '{}' not found", name)));
+            }
+            graph.command(&thoughtgraph::Command::DeleteTagRule { name: name.clone() });
+            println!("Removed rule '{}'", name.green());
+        },
+        RuleCommands::Apply => {
+            let applied = ui::with_loading_progress("Applying tagging rules...", || {
+                graph.apply_tag_rules_to_all()
+            })?;
+            
+            if applied.is_empty() {
+                println!("{}", style("No thoughts needed new tags").italic());
+            }
+            for (id, rule) in &applied {
+                println!("{} {} → #{}", style(&id.id).blue(), rule.rule.cyan(), rule.tag.id.yellow());
+            }
+        },
     }
     
     Ok(())
@@ -1047,14 +4033,17 @@ fn visualize_graph(
     graph: &ThoughtGraph,
     format: &str,
     focus: Option<String>,
+    query: Option<String>,
     depth: usize,
     output: Option<PathBuf>,
 ) -> Result<()> {
-    // If focus is not provided but we're in interactive mode, offer to select a focus
-    let focus_id_str = if focus.is_none() && io::stdin().is_terminal() && !graph.thoughts.is_empty() {
+    let query = query.as_deref().map(thoughtgraph::Query::parse).transpose()?;
+    
+    // If neither focus nor query is provided but we're in interactive mode, offer to select a focus
+    let focus_id_str = if focus.is_none() && query.is_none() && io::stdin().is_terminal() && !graph.thoughts.is_empty() {
         if ui::confirm("Would you like to focus on a specific thought?", true)? {
             match ui::select_thought(graph, "Select a thought to focus on")? {
-                Some(id) => Some(id.id),
+                Some(id) => Some(id.id.to_string()),
                 None => None
             }
         } else {
@@ -1071,22 +4060,25 @@ fn visualize_graph(
             
             // Check if the focused thought exists
             if !graph.thoughts.contains_key(&focus_id) {
-                return Err(anyhow::anyhow!("Thought '{}' not found", focus_str));
+                return Err(ThoughtGraphError::ThoughtNotFound(focus_str.to_string()));
             }
             
             Ok(generate_focused_graph(graph, &focus_id, depth))
+        } else if let Some(query) = &query {
+            let ids: HashSet<ThoughtID> = graph.find_thoughts(query).into_iter().map(|(id, _)| id.clone()).collect();
+            Ok(generate_subgraph_data(graph, &ids))
         } else {
             Ok(generate_graph_data(graph))
         }
     })?;
     
     // Generate output in the requested format
-    let format = format.to_lowercase();
-    let output_text = match format.as_str() {
-        "dot" => graph_data.to_dot(),
-        "json" => graph_data.to_json(),
-        _ => return Err(anyhow::anyhow!("Unsupported visualization format: {}. Use 'dot' or 'json'.", format)),
-    };
+    let exporters = ExporterRegistry::default();
+    let exporter = exporters.get(format).ok_or_else(|| {
+        Failed::invalid(format!("Unsupported visualization format: {}. Use one of: {}.", format, exporters.names().join(", ")))
+    })?;
+    let format = exporter.name();
+    let output_text = exporter.export(graph, &graph_data);
     
     // Output to file or stdout with progress indicator
     if let Some(output_path) = output {
@@ -1100,6 +4092,8 @@ fn visualize_graph(
         if format == "dot" {
             println!("\nTip: To render this file with Graphviz, run:");
             println!("  dot -Tpng {} -o graph.png", output_path.display());
+        } else if format == "canvas" {
+            println!("\nTip: Save the file with a .canvas extension inside an Obsidian vault to open it there.");
         }
     } else {
         println!("{}", output_text);
@@ -1108,25 +4102,34 @@ fn visualize_graph(
     Ok(())
 }
 
-/// Edit text in an external editor
-fn edit_in_external_editor(initial_content: &str, header_comment: &str) -> Result<String> {
-    // Create a temporary file
-    let mut temp_file = NamedTempFile::new()?;
-    
-    // Write initial content to the file
-    if !header_comment.is_empty() {
-        writeln!(temp_file, "{}", header_comment)?;
-        writeln!(temp_file)?;
+/// The text an editor starts with when writing something new: the editor template
+/// `name`, or else `comment` as a comment
+fn new_text(name: &str, comment: &str) -> Result<String> {
+    if let Some(dir) = EDITOR_TEMPLATES.get() {
+        if let Some(template) = config::editor_template(dir, name)? {
+            return Ok(template);
+        }
     }
-    
+    Ok(format!("<!-- {} -->\n\n", comment))
+}
+
+/// Edit text in an external editor, as a Markdown file, without the comments at its top
+fn edit_in_external_editor(initial_content: &str) -> Result<String> {
+    // Create a temporary Markdown file, so that editors highlight it as Markdown
+    let mut temp_file = tempfile::Builder::new().prefix("thought-").suffix(".md").tempfile()?;
     write!(temp_file, "{}", initial_content)?;
     temp_file.flush()?;
     
-    // Get the editor command from environment or use a default
-    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vim".to_string());
+    // Get the editor command from the options, the environment or use a default
+    let editor = match EDITOR.get() {
+        Some(editor) => editor.clone(),
+        None => std::env::var("EDITOR").ok().filter(|editor| !editor.trim().is_empty()).unwrap_or_else(|| "vim".to_string()),
+    };
+    let (program, args) = config::editor_command(&editor)?;
     
     // Open the file in the editor
-    let status = Command::new(&editor)
+    let status = Command::new(&program)
+        .args(&args)
         .arg(temp_file.path())
         .status()
         .context(format!("Failed to open editor: {}", editor))?;
@@ -1135,20 +4138,7 @@ fn edit_in_external_editor(initial_content: &str, header_comment: &str) -> Resul
         return Err(anyhow::anyhow!("Editor exited with error status"));
     }
     
-    // Read the updated content
+    // Read the updated content, without the comments
     let content = fs::read_to_string(temp_file.path())?;
-    
-    // Filter out header comment
-    let content = if !header_comment.is_empty() && content.starts_with(header_comment) {
-        let lines: Vec<&str> = content.lines().collect();
-        if lines.len() > 1 && lines[1].trim().is_empty() {
-            lines[2..].join("\n")
-        } else {
-            lines[1..].join("\n")
-        }
-    } else {
-        content
-    };
-    
-    Ok(content)
+    Ok(config::strip_editor_comments(&content).trim_end_matches(['\r', '\n']).to_string())
 }
@@ -0,0 +1,174 @@
+//! Prepared queries
+//!
+//! `matches` and `query_iter` check thoughts against a query one at a time. A `Matcher`
+//! works out once, before the first thought is checked, whatever a query needs that
+//! does not depend on the thought being checked, such as its normalized text, compiled
+//! patterns and the thoughts reachable from a thought, so that checking many thoughts does not repeat it
+//! for each of them.
+
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use crate::search::{self, compile_pattern, TextTerm};
+use crate::traverse::Direction;
+use crate::{Query, Result, ThoughtGraph, ThoughtID};
+
+/// The text of a thought that a `Condition::Text` looks in
+#[derive(Clone, Copy)]
+pub(crate) enum Field {
+    Title,
+    Content,
+    ReferenceNotes,
+}
+
+/// A condition of a prepared query
+pub(crate) enum Condition {
+    /// Checked against each thought as it stands
+    Plain(Query),
+    /// The field contains the normalized term
+    Text(TextTerm, Field),
+    /// The title matches the compiled pattern
+    TitleRegex(Regex),
+    /// The contents match the compiled pattern
+    ContentRegex(Regex),
+    /// The thought is one of the thoughts reachable from or to another
+    Reachable(HashSet<ThoughtID>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+/// A query prepared for checking thoughts against it
+pub struct Matcher<'a> {
+    pub(crate) graph: &'a ThoughtGraph,
+    pub(crate) condition: Condition,
+}
+
+impl ThoughtGraph {
+    /// Prepare a query for checking many thoughts against it.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to prepare
+    ///
+    /// # Returns
+    ///
+    /// A `Matcher` for the query, or `InvalidPattern` if one of its patterns does not
+    /// compile
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Query, ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = ThoughtID::new("todo".to_string());
+    /// graph.create_thought(id.clone(), None, "TODO: write tests".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let matcher = graph.matcher(&Query::ContentRegex("^TODO:".to_string())).unwrap();
+    /// assert!(matcher.matches(&id));
+    /// assert!(graph.matcher(&Query::ContentRegex("(".to_string())).is_err());
+    /// ```
+    pub fn matcher(&self, query: &Query) -> Result<Matcher<'_>> {
+        Ok(Matcher { graph: self, condition: self.prepare(query)? })
+    }
+
+    fn prepare(&self, query: &Query) -> Result<Condition> {
+        let prepare_all = |queries: &[Box<Query>]| queries.iter().map(|query| self.prepare(query)).collect::<Result<Vec<_>>>();
+        Ok(match query {
+            Query::Tag(tag_id) => match self.smart_tag_query(tag_id) {
+                Some(query) => self.prepare(&query)?,
+                None => Condition::Plain(query.clone()),
+            },
+            Query::And(subqueries) => Condition::And(prepare_all(subqueries)?),
+            Query::Or(subqueries) => Condition::Or(prepare_all(subqueries)?),
+            Query::Not(subquery) => Condition::Not(Box::new(self.prepare(subquery)?)),
+            Query::TitleContains(text) => Condition::Text(TextTerm::new(text), Field::Title),
+            Query::ContentContains(text) => Condition::Text(TextTerm::new(text), Field::Content),
+            Query::ReferenceNotesContain(text) => Condition::Text(TextTerm::new(text), Field::ReferenceNotes),
+            Query::TitleRegex(pattern) => Condition::TitleRegex(compile_pattern(pattern)?),
+            Query::ContentRegex(pattern) => Condition::ContentRegex(compile_pattern(pattern)?),
+            Query::ReachableFrom { id, depth } => Condition::Reachable(self.reachable(id, *depth, Direction::Outgoing)),
+            Query::ReachableTo { id, depth } => Condition::Reachable(self.reachable(id, *depth, Direction::Incoming)),
+            query => Condition::Plain(query.clone()),
+        })
+    }
+}
+
+impl Matcher<'_> {
+    /// Check whether a thought matches the query, as `ThoughtGraph::matches` does
+    pub fn matches(&self, id: &ThoughtID) -> bool {
+        self.graph.thoughts.contains_key(id) && self.check(id, &self.condition)
+    }
+
+    /// Whether a thought in the graph meets a condition of the query
+    pub(crate) fn check(&self, id: &ThoughtID, condition: &Condition) -> bool {
+        let graph = self.graph;
+        let thought = &graph.thoughts[id];
+        match condition {
+            // An empty conjunction matches nothing, as in `query()`
+            Condition::And(conditions) => !conditions.is_empty() && conditions.iter().all(|condition| self.check(id, condition)),
+            Condition::Or(conditions) => conditions.iter().any(|condition| self.check(id, condition)),
+            Condition::Not(condition) => !self.check(id, condition),
+            Condition::Text(term, Field::Title) => term.found_in(thought.title.as_deref()),
+            Condition::Text(term, Field::Content) => term.found_in(Some(&thought.contents)),
+            Condition::Text(term, Field::ReferenceNotes) => term.found_in_notes(thought),
+            Condition::TitleRegex(regex) => thought.title.as_deref().is_some_and(|title| regex.is_match(title)),
+            Condition::ContentRegex(regex) => regex.is_match(&thought.contents),
+            Condition::Reachable(reachable) => reachable.contains(id),
+            Condition::Plain(query) => match query {
+                Query::All => true,
+                Query::Id(target) => target == id,
+                Query::Tag(tag_id) => graph.tags.contains_key(tag_id) && thought.tags.contains(tag_id),
+                Query::References(target) => thought.references.iter().any(|r| &r.id == target),
+                Query::ReferencedBy(source) => graph
+                    .thoughts
+                    .get(source)
+                    .is_some_and(|source| source.references.iter().any(|r| &r.id == id)),
+                Query::TagGlob(glob) => thought
+                    .tags
+                    .iter()
+                    .any(|tag| graph.tags.contains_key(tag) && search::glob_matches(glob, tag.as_str())),
+                Query::IdGlob(glob) => search::glob_matches(glob, id.as_str()),
+                Query::Untagged => thought.tags.is_empty(),
+                Query::Orphan => graph.degree(id) == 0,
+                Query::MinBacklinks(count) => graph.backlink_count(id) >= *count,
+                Query::MaxBacklinks(count) => graph.backlink_count(id) <= *count,
+                // Compound queries, text, patterns and reachability are prepared into their
+                // own conditions
+                Query::And(_)
+                | Query::Or(_)
+                | Query::Not(_)
+                | Query::TitleContains(_)
+                | Query::ContentContains(_)
+                | Query::ReferenceNotesContain(_)
+                | Query::TitleRegex(_)
+                | Query::ContentRegex(_)
+                | Query::ReachableFrom { .. }
+                | Query::ReachableTo { .. } => unreachable!("prepared separately"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_patterns_are_errors() {
+        let mut graph = ThoughtGraph::new();
+        let id = ThoughtID::new("a".to_string());
+        graph.create_thought(id.clone(), Some("Plans".to_string()), String::new(), vec![], vec![]).unwrap();
+
+        let invalid = Query::Or(vec![Box::new(Query::All), Box::new(Query::TitleRegex("[".to_string()))]);
+        assert!(graph.matches(&id, &invalid).is_err());
+        assert!(graph.query_iter(&invalid).is_err());
+
+        let valid = Query::Not(Box::new(Query::TitleRegex("^P".to_string())));
+        let matcher = graph.matcher(&valid).unwrap();
+        assert!(!matcher.matches(&id));
+        assert!(!matcher.matches(&ThoughtID::new("missing".to_string())));
+    }
+}
@@ -0,0 +1,320 @@
+//! Namespaced thought IDs
+//!
+//! Thought IDs may be organized hierarchically, like paths in a file system:
+//! `projects/alpha/kickoff` is the thought `kickoff` in the namespace `projects/alpha/`.
+//! Namespaces need not be created; a namespace exists as long as some thought's ID
+//! starts with it. This module lists thoughts by namespace, builds a tree of namespaces
+//! for display, and moves thoughts between namespaces while keeping references intact.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::transclusion::extract_embeds;
+use crate::{Command, Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Separator between the segments of a namespaced ID
+pub const NAMESPACE_SEPARATOR: char = '/';
+
+impl ThoughtID {
+    /// The namespace of the ID, including the trailing separator, if it has one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::ThoughtID;
+    ///
+    /// assert_eq!(ThoughtID::new("projects/alpha/kickoff".to_string()).namespace(), Some("projects/alpha/"));
+    /// assert_eq!(ThoughtID::new("inbox".to_string()).namespace(), None);
+    /// ```
+    pub fn namespace(&self) -> Option<&str> {
+        self.id.rfind(NAMESPACE_SEPARATOR).map(|i| &self.id[..=i])
+    }
+
+    /// The ID without its namespace
+    pub fn name(&self) -> &str {
+        match self.id.rfind(NAMESPACE_SEPARATOR) {
+            Some(i) => &self.id[i + 1..],
+            None => &self.id,
+        }
+    }
+}
+
+/// Make sure a namespace prefix ends with the separator, so that `projects` does not also
+/// match `projects-old/...`
+fn normalize_prefix(prefix: &str) -> String {
+    if prefix.is_empty() || prefix.ends_with(NAMESPACE_SEPARATOR) {
+        prefix.to_string()
+    } else {
+        format!("{}{}", prefix, NAMESPACE_SEPARATOR)
+    }
+}
+
+/// A namespace and everything below it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Namespace {
+    /// Thoughts directly in this namespace, sorted by ID
+    pub thoughts: Vec<ThoughtID>,
+    /// Nested namespaces, by their name (without separators)
+    pub children: BTreeMap<String, Namespace>,
+}
+
+impl Namespace {
+    /// Number of thoughts in this namespace and all nested namespaces
+    pub fn len(&self) -> usize {
+        self.thoughts.len() + self.children.values().map(Namespace::len).sum::<usize>()
+    }
+
+    /// Whether the namespace contains no thoughts at all
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// IDs mentioned in a thought's content, as `[id]` or `{{embed:id}}`
+fn mentions(thought: &Thought) -> impl Iterator<Item = ThoughtID> {
+    thought.extract_references_from_content().into_iter().chain(extract_embeds(&thought.contents))
+}
+
+/// Replace mentions of moved thoughts (`[id]` and `{{embed:id}}`) in a text
+fn rewrite_mentions(text: &str, moves: &HashMap<ThoughtID, ThoughtID>) -> String {
+    let mut text = text.to_string();
+    for (old, new) in moves {
+        if text.contains(old.as_str()) {
+            text = text
+                .replace(&format!("[{}]", old.id), &format!("[{}]", new.id))
+                .replace(&format!("{{{{embed:{}}}}}", old.id), &format!("{{{{embed:{}}}}}", new.id));
+        }
+    }
+    text
+}
+
+impl ThoughtGraph {
+    /// List the thoughts in a namespace, including nested namespaces.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The namespace, e.g. `projects/`; the trailing separator may be left
+    ///   out. An empty prefix lists every thought.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the thoughts in the namespace, sorted
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// for id in ["projects/alpha/kickoff", "projects/beta", "projects-old/gamma", "inbox"] {
+    ///     graph.create_thought(ThoughtID::new(id.to_string()), None, String::new(), vec![], vec![]).unwrap();
+    /// }
+    ///
+    /// let ids: Vec<&str> = graph.list_namespace("projects/").iter().map(|id| id.as_str()).collect();
+    /// assert_eq!(ids, vec!["projects/alpha/kickoff", "projects/beta"]);
+    /// ```
+    pub fn list_namespace(&self, prefix: &str) -> Vec<&ThoughtID> {
+        let prefix = normalize_prefix(prefix);
+        let mut ids: Vec<&ThoughtID> = self.thoughts.keys().filter(|id| id.id.starts_with(&prefix)).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Build the tree of namespaces below a prefix.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The namespace at the root of the tree; empty for the whole graph
+    pub fn namespace_tree(&self, prefix: &str) -> Namespace {
+        let prefix = normalize_prefix(prefix);
+        let mut root = Namespace::default();
+        for id in self.list_namespace(&prefix) {
+            let relative = &id.id[prefix.len()..];
+            let mut segments: Vec<&str> = relative.split(NAMESPACE_SEPARATOR).collect();
+            segments.pop();
+
+            let mut node = &mut root;
+            for segment in segments {
+                node = node.children.entry(segment.to_string()).or_default();
+            }
+            // IDs were listed in sorted order, so every node's thoughts stay sorted
+            node.thoughts.push(id.clone());
+        }
+        root
+    }
+
+    /// Rename a thought, updating every reference to it.
+    ///
+    /// References from other thoughts are redirected to the new ID, and mentions in their
+    /// content and reference notes (`[old-id]` and `{{embed:old-id}}`) are rewritten.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The current ID of the thought
+    /// * `to` - The new ID, which must be valid and not yet in use
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Reference};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let old = ThoughtID::new("kickoff".to_string());
+    /// let new = ThoughtID::new("projects/alpha/kickoff".to_string());
+    /// graph.create_thought(old.clone(), None, "Agenda".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(ThoughtID::new("notes".to_string()), None, "See [kickoff]".to_string(), vec![],
+    ///     vec![Reference::new(old.clone(), String::new(), Utc::now())]).unwrap();
+    ///
+    /// graph.move_thought(&old, &new).unwrap();
+    ///
+    /// let notes = graph.get_thought(&ThoughtID::new("notes".to_string())).unwrap();
+    /// assert_eq!(notes.contents, "See [projects/alpha/kickoff]");
+    /// assert_eq!(notes.references[0].id, new);
+    /// ```
+    pub fn move_thought(&mut self, from: &ThoughtID, to: &ThoughtID) -> Result<()> {
+        self.move_thoughts(HashMap::from([(from.clone(), to.clone())]))
+    }
+
+    /// Move every thought in a namespace to another namespace, updating references.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The namespace to move, e.g. `drafts/`
+    /// * `to` - The namespace to move the thoughts to, e.g. `projects/alpha/`; empty to
+    ///   move them to the top level
+    ///
+    /// # Returns
+    ///
+    /// The old and new IDs of the moved thoughts, sorted by old ID
+    pub fn move_namespace(&mut self, from: &str, to: &str) -> Result<Vec<(ThoughtID, ThoughtID)>> {
+        let from = normalize_prefix(from);
+        let to = normalize_prefix(to);
+        let mut moves: Vec<(ThoughtID, ThoughtID)> = self
+            .list_namespace(&from)
+            .into_iter()
+            .map(|id| (id.clone(), ThoughtID::new(format!("{}{}", to, &id.id[from.len()..]))))
+            .collect();
+        moves.sort();
+
+        self.move_thoughts(moves.iter().cloned().collect())?;
+        Ok(moves)
+    }
+
+    /// Rename several thoughts at once; the new IDs may reuse IDs freed by the same move
+    fn move_thoughts(&mut self, moves: HashMap<ThoughtID, ThoughtID>) -> Result<()> {
+        // Validate everything before changing anything
+        let mut targets = HashSet::new();
+        for (from, to) in &moves {
+            if !self.thoughts.contains_key(from) {
+                return Err(ThoughtGraphError::ThoughtNotFound(from.id.to_string()));
+            }
+            ThoughtID::parse(to.as_str())?;
+            let freed = moves.contains_key(to);
+            if (self.thoughts.contains_key(to) && !freed) || !targets.insert(to) {
+                return Err(ThoughtGraphError::InvalidThoughtID(format!("'{}' is already in use", to.id)));
+            }
+        }
+
+        // Thoughts that are moved, reference a moved thought, or mention one
+        let mut affected: HashSet<ThoughtID> = moves.keys().cloned().collect();
+        for from in moves.keys() {
+            affected.extend(self.get_backlinks(from));
+        }
+        for (id, thought) in &self.thoughts {
+            if mentions(thought).any(|mention| moves.contains_key(&mention)) {
+                affected.insert(id.clone());
+            }
+        }
+        if let Some(locked) = affected.iter().filter(|id| self.is_locked(id)).min() {
+            return Err(ThoughtGraphError::ThoughtLocked(locked.id.to_string()));
+        }
+
+        let mut updated = Vec::new();
+        for id in &affected {
+            let mut thought = self.thoughts[id].clone();
+            for reference in &mut thought.references {
+                if let Some(new) = moves.get(&reference.id) {
+                    reference.id = new.clone();
+                    reference.notes = rewrite_mentions(&reference.notes, &moves);
+                }
+            }
+            thought.contents = rewrite_mentions(&thought.contents, &moves);
+            updated.push((moves.get(id).unwrap_or(id).clone(), thought));
+        }
+
+        let mut batch: Vec<Command> = moves.keys().map(|from| Command::DeleteThought { id: from.clone() }).collect();
+        batch.extend(updated.into_iter().map(|(id, thought)| Command::PutThought { id, thought }));
+        self.apply(Command::Batch(batch));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+    use chrono::Utc;
+
+    fn add(graph: &mut ThoughtGraph, id: &str, contents: &str, refs: &[&str]) {
+        let refs = refs
+            .iter()
+            .map(|r| Reference::new(ThoughtID::new(r.to_string()), String::new(), Utc::now()))
+            .collect();
+        graph
+            .create_thought(ThoughtID::new(id.to_string()), None, contents.to_string(), vec![], refs)
+            .unwrap();
+    }
+
+    fn id(id: &str) -> ThoughtID {
+        ThoughtID::new(id.to_string())
+    }
+
+    #[test]
+    fn test_namespace_tree() {
+        let mut graph = ThoughtGraph::new();
+        for thought in ["projects/alpha/kickoff", "projects/alpha/retro", "projects/beta", "inbox"] {
+            add(&mut graph, thought, "", &[]);
+        }
+
+        let tree = graph.namespace_tree("");
+        assert_eq!(tree.thoughts, vec![id("inbox")]);
+        assert_eq!(tree.len(), 4);
+        let projects = &tree.children["projects"];
+        assert_eq!(projects.thoughts, vec![id("projects/beta")]);
+        assert_eq!(projects.children["alpha"].thoughts.len(), 2);
+
+        assert_eq!(graph.namespace_tree("projects/alpha").thoughts.len(), 2);
+    }
+
+    #[test]
+    fn test_move_namespace_rewrites_references() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "drafts/a", "Links to [drafts/b]", &["drafts/b"]);
+        add(&mut graph, "drafts/b", "Embeds {{embed:drafts/a}}", &[]);
+        add(&mut graph, "index", "Start at [drafts/a]", &["drafts/a"]);
+
+        let moves = graph.move_namespace("drafts", "projects/alpha").unwrap();
+        assert_eq!(moves[0], (id("drafts/a"), id("projects/alpha/a")));
+        assert!(graph.list_namespace("drafts").is_empty());
+
+        let a = graph.get_thought(&id("projects/alpha/a")).unwrap();
+        assert_eq!(a.contents, "Links to [projects/alpha/b]");
+        assert_eq!(a.references[0].id, id("projects/alpha/b"));
+        assert_eq!(graph.get_thought(&id("projects/alpha/b")).unwrap().contents, "Embeds {{embed:projects/alpha/a}}");
+        assert_eq!(graph.get_thought(&id("index")).unwrap().contents, "Start at [projects/alpha/a]");
+        assert_eq!(graph.get_backlinks(&id("projects/alpha/a")), vec![id("index")]);
+        assert!(graph.get_backlinks(&id("drafts/a")).is_empty());
+    }
+
+    #[test]
+    fn test_move_rejects_conflicts() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "a", "", &[]);
+        add(&mut graph, "b", "", &[]);
+
+        assert!(matches!(graph.move_thought(&id("a"), &id("b")), Err(ThoughtGraphError::InvalidThoughtID(_))));
+        assert!(matches!(graph.move_thought(&id("a"), &id("has space")), Err(ThoughtGraphError::InvalidThoughtID(_))));
+        assert!(matches!(graph.move_thought(&id("missing"), &id("c")), Err(ThoughtGraphError::ThoughtNotFound(_))));
+        assert_eq!(graph.list_thoughts().len(), 2);
+    }
+}
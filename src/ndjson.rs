@@ -0,0 +1,291 @@
+//! Newline-delimited JSON
+//!
+//! A graph written as NDJSON has one JSON object per line: first a `"type": "tag"` record
+//! for each tag, then a `"type": "thought"` record for each thought, with the same fields
+//! as `Tag` and `Thought` plus an `"id"`. Records are written and read one at a time, so
+//! a graph of any size can be streamed to or from tools like `jq` without building the
+//! whole document in memory.
+//!
+//! Rules, saved queries, locks, the trash and the journal are not part of the format.
+
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::progress::{Progress, ProgressFn};
+use crate::validate::ValidationOptions;
+use crate::{Command, Result, Tag, TagID, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Number of records imported between reports of progress
+const PROGRESS_INTERVAL: u64 = 1000;
+
+/// A line of NDJSON
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Record {
+    /// A tag
+    Tag {
+        id: TagID,
+        #[serde(flatten)]
+        tag: Tag,
+    },
+    /// A thought
+    Thought {
+        id: ThoughtID,
+        #[serde(flatten)]
+        thought: Thought,
+    },
+}
+
+/// A line of NDJSON borrowed from a graph, for writing without cloning
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum RecordRef<'a> {
+    Tag {
+        id: &'a TagID,
+        #[serde(flatten)]
+        tag: &'a Tag,
+    },
+    Thought {
+        id: &'a ThoughtID,
+        #[serde(flatten)]
+        thought: &'a Thought,
+    },
+}
+
+/// The number of records imported by `ThoughtGraph::import_ndjson`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NdjsonImport {
+    /// Tags that did not exist before
+    pub tags_created: usize,
+    /// Tags that replaced existing tags
+    pub tags_updated: usize,
+    /// Thoughts that did not exist before
+    pub thoughts_created: usize,
+    /// Thoughts that replaced existing thoughts
+    pub thoughts_updated: usize,
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &RecordRef) -> Result<()> {
+    serde_json::to_writer(&mut *writer, record).map_err(|e| ThoughtGraphError::IoError(e.into()))?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Read NDJSON records one line at a time. Blank lines are skipped.
+///
+/// # Returns
+///
+/// An iterator over the records, which yields a `ParseError` naming the line for a line
+/// that is not a record
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::ndjson::{read_ndjson, Record};
+///
+/// let input = "{\"type\":\"thought\",\"id\":\"a\",\"title\":null,\"contents\":\"Hi\",\"tags\":[],\"references\":[],\
+///     \"created_at\":\"2025-01-01T00:00:00Z\",\"updated_at\":\"2025-01-01T00:00:00Z\"}\n\nnot json\n";
+/// let records: Vec<_> = read_ndjson(input.as_bytes()).collect();
+/// assert!(matches!(&records[0], Ok(Record::Thought { thought, .. }) if thought.contents == "Hi"));
+/// assert!(records[1].as_ref().unwrap_err().to_string().contains("line 3"));
+/// ```
+pub fn read_ndjson<R: BufRead>(reader: R) -> impl Iterator<Item = Result<Record>> {
+    reader.lines().enumerate().filter_map(|(i, line)| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(
+            serde_json::from_str(&line).map_err(|e| ThoughtGraphError::ParseError(format!("line {}: {}", i + 1, e))),
+        ),
+        Err(e) => Some(Err(e.into())),
+    })
+}
+
+impl ThoughtGraph {
+    /// Write the tags and thoughts of the graph as NDJSON, each in order of ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where to write; wrap files in a `BufWriter`
+    ///
+    /// # Returns
+    ///
+    /// The number of records written
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, "Hello".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let mut output = Vec::new();
+    /// assert_eq!(graph.write_ndjson(&mut output).unwrap(), 1);
+    /// let copy = ThoughtGraph::from_ndjson(output.as_slice()).unwrap();
+    /// assert_eq!(copy.thoughts, graph.thoughts);
+    /// ```
+    pub fn write_ndjson<W: Write>(&self, writer: W) -> Result<usize> {
+        let mut tags: Vec<&TagID> = self.tags.keys().collect();
+        tags.sort();
+        let mut ids: Vec<&ThoughtID> = self.thoughts.keys().collect();
+        ids.sort();
+        self.write_records(writer, &tags, &ids)
+    }
+
+    /// Write some thoughts as NDJSON, in the order given, after the tags they have.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The thoughts; IDs of thoughts that do not exist are skipped
+    /// * `writer` - Where to write
+    ///
+    /// # Returns
+    ///
+    /// The number of records written
+    pub fn write_ndjson_thoughts<W: Write>(&self, ids: &[ThoughtID], writer: W) -> Result<usize> {
+        let mut tags: Vec<&TagID> = ids
+            .iter()
+            .filter_map(|id| self.thoughts.get(id))
+            .flat_map(|thought| &thought.tags)
+            .filter(|tag| self.tags.contains_key(*tag))
+            .collect();
+        tags.sort();
+        tags.dedup();
+        let ids: Vec<&ThoughtID> = ids.iter().collect();
+        self.write_records(writer, &tags, &ids)
+    }
+
+    fn write_records<W: Write>(&self, mut writer: W, tags: &[&TagID], ids: &[&ThoughtID]) -> Result<usize> {
+        let mut count = 0;
+        for id in tags {
+            write_record(&mut writer, &RecordRef::Tag { id, tag: &self.tags[*id] })?;
+            count += 1;
+        }
+        for id in ids {
+            if let Some(thought) = self.thoughts.get(*id) {
+                write_record(&mut writer, &RecordRef::Thought { id, thought })?;
+                count += 1;
+            }
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Read a graph from NDJSON, as written by `write_ndjson`.
+    pub fn from_ndjson<R: BufRead>(reader: R) -> Result<Self> {
+        let mut graph = ThoughtGraph::new();
+        graph.import_ndjson(reader)?;
+        Ok(graph)
+    }
+
+    /// Add the tags and thoughts in NDJSON to the graph, replacing any with the same IDs.
+    ///
+    /// Each record is validated as by `try_command`, so the tags of a thought must come
+    /// before it or already be in the graph. If a record is rejected, the records before
+    /// it stay imported.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The NDJSON, read a line at a time
+    ///
+    /// # Returns
+    ///
+    /// The number of tags and thoughts created and updated, or the error of the first
+    /// record that could not be read or imported
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut source = ThoughtGraph::new();
+    /// source.create_thought(ThoughtID::new("a".to_string()), None, "Hello".to_string(), vec![], vec![]).unwrap();
+    /// let mut output = Vec::new();
+    /// source.write_ndjson(&mut output).unwrap();
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let imported = graph.import_ndjson(output.as_slice()).unwrap();
+    /// assert_eq!(imported.thoughts_created, 1);
+    /// assert_eq!(graph.import_ndjson(output.as_slice()).unwrap().thoughts_updated, 1);
+    /// ```
+    pub fn import_ndjson<R: BufRead>(&mut self, reader: R) -> Result<NdjsonImport> {
+        self.import_ndjson_with_progress(reader, &mut |_| {})
+    }
+
+    /// Import NDJSON like `import_ndjson`, reporting the number of records imported so far.
+    /// The total is not known until the end.
+    pub fn import_ndjson_with_progress<R: BufRead>(&mut self, reader: R, progress: ProgressFn) -> Result<NdjsonImport> {
+        let mut imported = NdjsonImport::default();
+        let mut done = 0;
+        for record in read_ndjson(reader) {
+            let command = match record? {
+                Record::Tag { id, tag } => {
+                    match self.tags.contains_key(&id) {
+                        true => imported.tags_updated += 1,
+                        false => imported.tags_created += 1,
+                    }
+                    Command::PutTag { id, tag }
+                },
+                Record::Thought { id, thought } => {
+                    match self.thoughts.contains_key(&id) {
+                        true => imported.thoughts_updated += 1,
+                        false => imported.thoughts_created += 1,
+                    }
+                    Command::PutThought { id, thought }
+                },
+            };
+            self.validate_command(&command, &ValidationOptions::default())?;
+            self.apply(command);
+            done += 1;
+            if done % PROGRESS_INTERVAL == 0 {
+                progress(Progress { done, total: None });
+            }
+        }
+        progress(Progress::of(done, done));
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+    use chrono::Utc;
+
+    #[test]
+    fn test_round_trip() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let rust = TagID::new("rust".to_string());
+        graph.create_tag(rust.clone(), "The language".to_string()).unwrap();
+        graph.create_tag(TagID::new("unused".to_string()), String::new()).unwrap();
+        let mut thought = Thought::new(Some("Ownership".to_string()), "Line one\nline \"two\"".to_string(), vec![rust], vec![]);
+        thought.metadata.insert("year".to_string(), "2015".to_string());
+        graph.command(&Command::PutThought { id: id("own"), thought });
+        let reference = Reference::new(id("own"), "Builds on".to_string(), Utc::now());
+        graph.create_thought(id("borrow"), None, String::new(), vec![], vec![reference]).unwrap();
+
+        let mut output = Vec::new();
+        assert_eq!(graph.write_ndjson(&mut output).unwrap(), 4);
+        let text = String::from_utf8(output.clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with(r#"{"type":"tag","id":"rust","#));
+        assert!(lines[2].starts_with(r#"{"type":"thought","id":"borrow","#));
+
+        let copy = ThoughtGraph::from_ndjson(output.as_slice()).unwrap();
+        copy.assert_invariants();
+        assert_eq!((copy.thoughts.clone(), copy.tags.clone()), (graph.thoughts.clone(), graph.tags.clone()));
+        assert_eq!(copy.backreferences, graph.backreferences);
+
+        let mut subset = Vec::new();
+        assert_eq!(graph.write_ndjson_thoughts(&[id("own"), id("missing")], &mut subset).unwrap(), 2);
+
+        let mut target = ThoughtGraph::new();
+        let orphan = lines[3].replace("\"id\":\"own\"", "\"id\":\"copy\"");
+        let error = target.import_ndjson(format!("{}\n{}\n", lines[2], orphan).as_bytes()).unwrap_err();
+        assert!(matches!(error, ThoughtGraphError::TagNotFound(_)));
+        assert!(target.get_thought(&id("borrow")).is_some());
+        assert!(matches!(target.import_ndjson("{}\n".as_bytes()), Err(ThoughtGraphError::ParseError(_))));
+    }
+}
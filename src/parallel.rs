@@ -0,0 +1,75 @@
+//! Optional parallel iteration
+//!
+//! With the `parallel` feature enabled, the helpers in this module spread their work over
+//! all cores using rayon. Without it, or for inputs too small to benefit, they run on the
+//! current thread. Either way they produce the same results, so callers do not need to
+//! care which one is used.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Inputs with fewer items than this are always processed on the current thread, where
+/// the cost of distributing the work would outweigh the gain
+pub(crate) const PARALLEL_THRESHOLD: usize = 512;
+
+/// Whether work over `len` items is worth distributing over several threads
+pub(crate) fn worth_parallelizing(len: usize) -> bool {
+    cfg!(feature = "parallel") && len >= PARALLEL_THRESHOLD
+}
+
+/// Apply `f` to every entry of `map`, keeping the values it returns.
+///
+/// The order of the results is unspecified, as with iteration over a `HashMap`.
+pub(crate) fn filter_map<'a, K, V, U, F>(map: &'a HashMap<K, V>, f: F) -> Vec<U>
+where
+    K: Eq + Hash + Sync,
+    V: Sync,
+    U: Send,
+    F: Fn(&'a K, &'a V) -> Option<U> + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    if worth_parallelizing(map.len()) {
+        return map.par_iter().filter_map(|(k, v)| f(k, v)).collect();
+    }
+    map.iter().filter_map(|(k, v)| f(k, v)).collect()
+}
+
+/// Apply `f` to every item of `items`, keeping the order of the items.
+///
+/// Unlike `filter_map`, this also distributes small inputs when `expensive` is set, for
+/// items that each take a long time to process (such as whole subqueries).
+pub(crate) fn map<T, U, F>(items: &[T], expensive: bool, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    if items.len() > 1 && (expensive || worth_parallelizing(items.len())) {
+        return items.par_iter().map(f).collect();
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = expensive;
+    items.iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_do_not_depend_on_size() {
+        for size in [10, PARALLEL_THRESHOLD * 4] {
+            let entries: HashMap<usize, usize> = (0..size).map(|i| (i, i * 2)).collect();
+            let mut even = filter_map(&entries, |k, v| (k % 2 == 0).then_some(*v));
+            even.sort_unstable();
+            assert_eq!(even, (0..size).step_by(2).map(|i| i * 2).collect::<Vec<_>>());
+
+            let items: Vec<usize> = (0..size).collect();
+            assert_eq!(map(&items, true, |i| i + 1), (1..=size).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,596 @@
+//! PDF export of thoughts
+//!
+//! With the `pdf` feature, thoughts can be published as a PDF document. Each thought
+//! starts on a new page with its title, ID and tags, followed by its published contents
+//! (see [`ThoughtGraph::published_contents`]) and lists of the thoughts it links to and is
+//! linked from. Footnotes are numbered and listed after the contents.
+//!
+//! Documents are typeset with the standard PDF fonts (Helvetica and Courier), which every
+//! PDF reader provides, so nothing needs to be embedded. These fonts only cover Western
+//! European scripts: other characters are shown as `?`.
+
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+
+use crate::footnotes::{extract_footnotes, markdown_options};
+use crate::{Result, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Width and height of an A4 page, in points
+const PAGE_SIZE: (f32, f32) = (595.0, 842.0);
+/// Space between the text and the edges of the page
+const MARGIN: f32 = 64.0;
+/// Size of body text
+const TEXT_SIZE: f32 = 11.0;
+/// Line height as a multiple of the font size
+const LEADING: f32 = 1.4;
+/// Indentation of each level of lists and block quotes
+const INDENT: f32 = 18.0;
+
+/// The fonts documents are typeset with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    Mono,
+}
+
+impl Font {
+    const ALL: [Font; 4] = [Font::Regular, Font::Bold, Font::Italic, Font::Mono];
+
+    /// The name of the font in page resources
+    fn resource(self) -> Name<'static> {
+        match self {
+            Font::Regular => Name(b"F1"),
+            Font::Bold => Name(b"F2"),
+            Font::Italic => Name(b"F3"),
+            Font::Mono => Name(b"F4"),
+        }
+    }
+
+    /// The standard font used
+    fn base_font(self) -> Name<'static> {
+        match self {
+            Font::Regular => Name(b"Helvetica"),
+            Font::Bold => Name(b"Helvetica-Bold"),
+            Font::Italic => Name(b"Helvetica-Oblique"),
+            Font::Mono => Name(b"Courier"),
+        }
+    }
+
+    /// The width of a character, in thousandths of the font size
+    fn char_width(self, c: char) -> f32 {
+        const REGULAR: &[u16; 95] = &[
+            278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556,
+            556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778,
+            722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278,
+            278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+            556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+        ];
+        const BOLD: &[u16; 95] = &[
+            278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556,
+            556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667, 611, 778,
+            722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333,
+            278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+            611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+        ];
+        let widths = match self {
+            Font::Mono => return 600.0,
+            Font::Bold => BOLD,
+            Font::Regular | Font::Italic => REGULAR,
+        };
+        match c {
+            ' '..='~' => f32::from(widths[c as usize - 32]),
+            _ => 556.0,
+        }
+    }
+
+    /// The width of some text at a font size
+    fn width(self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.char_width(c)).sum::<f32>() * size / 1000.0
+    }
+}
+
+/// Encode text in the WinAnsi encoding of the standard fonts
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            '€' => 0x80,
+            '‚' => 0x82,
+            '„' => 0x84,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            '™' => 0x99,
+            '\t' => b' ',
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// A run of text in one font
+#[derive(Clone, Debug, PartialEq)]
+struct Span {
+    font: Font,
+    text: String,
+}
+
+/// A paragraph, heading, list item or other block of text
+#[derive(Clone, Debug)]
+struct Block {
+    size: f32,
+    font: Font,
+    gray: f32,
+    indent: f32,
+    /// Extra indentation of every line but the first, to align list items
+    hanging: f32,
+    space_before: f32,
+    /// Keep line breaks and spaces as they are, as in code blocks
+    preformatted: bool,
+    page_break: bool,
+    spans: Vec<Span>,
+}
+
+impl Block {
+    fn new(size: f32, font: Font) -> Block {
+        Block {
+            size,
+            font,
+            gray: 0.0,
+            indent: 0.0,
+            hanging: 0.0,
+            space_before: size * 0.6,
+            preformatted: false,
+            page_break: false,
+            spans: Vec::new(),
+        }
+    }
+
+    fn text(size: f32, font: Font, text: &str) -> Block {
+        let mut block = Block::new(size, font);
+        block.push(font, text);
+        block
+    }
+
+    fn push(&mut self, font: Font, text: &str) {
+        match self.spans.last_mut() {
+            Some(span) if span.font == font => span.text.push_str(text),
+            _ => self.spans.push(Span { font, text: text.to_string() }),
+        }
+    }
+
+    /// Break the block into lines that fit in `width`
+    fn lines(&self, width: f32) -> Vec<Vec<Span>> {
+        let mut lines = vec![Vec::new()];
+        let mut line_width = 0.0;
+        let available = |lines: &Vec<Vec<Span>>| if lines.len() == 1 { width } else { width - self.hanging };
+
+        for span in &self.spans {
+            if self.preformatted {
+                for (i, text) in span.text.strip_suffix('\n').unwrap_or(&span.text).split('\n').enumerate() {
+                    if i > 0 {
+                        lines.push(Vec::new());
+                    }
+                    if !text.is_empty() {
+                        lines.last_mut().unwrap().push(Span { font: span.font, text: text.to_string() });
+                    }
+                }
+                continue;
+            }
+
+            for (i, part) in span.text.split('\n').enumerate() {
+                if i > 0 {
+                    lines.push(Vec::new());
+                    line_width = 0.0;
+                }
+                let mut space = part.starts_with(char::is_whitespace);
+                for word in part.split_whitespace() {
+                    let mut word = word.to_string();
+                    let spaced = lines.last().unwrap().last().is_some_and(|last: &Span| last.text.ends_with(' '));
+                    let gap = if space && line_width > 0.0 && !spaced { span.font.width(" ", self.size) } else { 0.0 };
+                    let word_width = span.font.width(&word, self.size);
+                    if line_width > 0.0 && line_width + gap + word_width > available(&lines) {
+                        lines.push(Vec::new());
+                        line_width = 0.0;
+                    } else if gap > 0.0 {
+                        word.insert(0, ' ');
+                    }
+                    // Words too long for a line of their own are broken anywhere
+                    while span.font.width(&word, self.size) > available(&lines) && word.chars().count() > 1 {
+                        let mut fits = String::new();
+                        for c in word.chars() {
+                            if span.font.width(&format!("{}{}", fits, c), self.size) > available(&lines) && !fits.is_empty() {
+                                break;
+                            }
+                            fits.push(c);
+                        }
+                        word = word[fits.len()..].to_string();
+                        lines.last_mut().unwrap().push(Span { font: span.font, text: fits });
+                        lines.push(Vec::new());
+                    }
+                    line_width += span.font.width(&word, self.size);
+                    let line = lines.last_mut().unwrap();
+                    match line.last_mut() {
+                        Some(last) if last.font == span.font => last.text.push_str(&word),
+                        _ => line.push(Span { font: span.font, text: word }),
+                    }
+                    space = true;
+                }
+                if part.ends_with(char::is_whitespace) && line_width > 0.0 {
+                    // Keep the space between this span and the next
+                    if let Some(last) = lines.last_mut().unwrap().last_mut() {
+                        last.text.push(' ');
+                        line_width += span.font.width(" ", self.size);
+                    }
+                }
+            }
+        }
+        for line in &mut lines {
+            if let Some(last) = line.last_mut() {
+                last.text.truncate(last.text.trim_end().len());
+            }
+        }
+        lines
+    }
+}
+
+/// Convert Markdown into blocks of text
+fn markdown_blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut current: Option<Block> = None;
+    let mut fonts: Vec<Font> = Vec::new();
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut quotes = 0;
+    let mut table_cells = 0;
+
+    let indent = |lists: &Vec<Option<u64>>, quotes: usize| (lists.len().saturating_sub(1) + quotes) as f32 * INDENT;
+    let paragraph = |lists: &Vec<Option<u64>>, quotes: usize| {
+        let mut block = Block::new(TEXT_SIZE, if quotes > 0 { Font::Italic } else { Font::Regular });
+        block.indent = indent(lists, quotes) + if lists.is_empty() { 0.0 } else { INDENT };
+        if quotes > 0 {
+            block.gray = 0.35;
+        }
+        block
+    };
+
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        match event {
+            // Paragraphs in list items continue the item, after its marker
+            Event::Start(Tag::Paragraph) if matches!(&current, Some(block) if block.hanging > 0.0 && block.spans.len() == 1) => {},
+            Event::Start(Tag::Paragraph) => {
+                blocks.extend(current.take());
+                current = Some(paragraph(&lists, quotes));
+            },
+            Event::Start(Tag::Heading { level, .. }) => {
+                blocks.extend(current.take());
+                let size = match level {
+                    HeadingLevel::H1 => 18.0,
+                    HeadingLevel::H2 => 15.0,
+                    HeadingLevel::H3 => 13.0,
+                    _ => TEXT_SIZE,
+                };
+                let mut block = Block::new(size, Font::Bold);
+                block.space_before = size;
+                current = Some(block);
+            },
+            Event::Start(Tag::List(start)) => {
+                blocks.extend(current.take());
+                lists.push(start);
+            },
+            Event::Start(Tag::Item) => {
+                blocks.extend(current.take());
+                let marker = match lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    },
+                    _ => "• ".to_string(),
+                };
+                let mut block = paragraph(&lists, quotes);
+                block.indent = indent(&lists, quotes);
+                block.space_before = TEXT_SIZE * 0.3;
+                block.hanging = Font::Regular.width(&marker, TEXT_SIZE);
+                block.push(Font::Regular, &marker);
+                current = Some(block);
+            },
+            Event::Start(Tag::CodeBlock(_)) => {
+                blocks.extend(current.take());
+                let mut block = Block::new(TEXT_SIZE * 0.85, Font::Mono);
+                block.indent = indent(&lists, quotes) + INDENT;
+                block.preformatted = true;
+                current = Some(block);
+            },
+            Event::Start(Tag::BlockQuote(_)) => {
+                blocks.extend(current.take());
+                quotes += 1;
+            },
+            Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => {
+                blocks.extend(current.take());
+                let mut block = paragraph(&lists, quotes);
+                block.space_before = TEXT_SIZE * 0.3;
+                current = Some(block);
+                table_cells = 0;
+            },
+            Event::Start(Tag::TableCell) => {
+                if table_cells > 0 {
+                    if let Some(block) = &mut current {
+                        block.push(Font::Regular, "  |  ");
+                    }
+                }
+                table_cells += 1;
+            },
+            Event::Start(Tag::Emphasis) => fonts.push(Font::Italic),
+            Event::Start(Tag::Strong) => fonts.push(Font::Bold),
+            Event::End(TagEnd::Emphasis) | Event::End(TagEnd::Strong) => {
+                fonts.pop();
+            },
+            Event::End(TagEnd::List(_)) => {
+                blocks.extend(current.take());
+                lists.pop();
+            },
+            Event::End(TagEnd::BlockQuote(_)) => {
+                blocks.extend(current.take());
+                quotes -= 1;
+            },
+            Event::End(TagEnd::Paragraph)
+            | Event::End(TagEnd::Heading(_))
+            | Event::End(TagEnd::Item)
+            | Event::End(TagEnd::CodeBlock)
+            | Event::End(TagEnd::TableHead)
+            | Event::End(TagEnd::TableRow)
+            | Event::Rule => blocks.extend(current.take()),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) | Event::FootnoteReference(text) => {
+                let block = current.get_or_insert_with(|| paragraph(&lists, quotes));
+                let font = fonts.last().copied().unwrap_or(block.font);
+                block.push(font, &text);
+            },
+            Event::Code(text) => {
+                current.get_or_insert_with(|| paragraph(&lists, quotes)).push(Font::Mono, &text);
+            },
+            Event::SoftBreak => {
+                if let Some(block) = &mut current {
+                    block.push(block.font, " ");
+                }
+            },
+            Event::HardBreak => {
+                if let Some(block) = &mut current {
+                    block.push(block.font, "\n");
+                }
+            },
+            _ => {},
+        }
+    }
+    blocks.extend(current);
+    blocks
+}
+
+/// Lays out blocks of text on pages
+struct Typesetter {
+    pages: Vec<Content>,
+    /// The top of the next line on the current page
+    y: f32,
+}
+
+impl Typesetter {
+    fn new() -> Typesetter {
+        Typesetter { pages: Vec::new(), y: 0.0 }
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Content::new());
+        self.y = PAGE_SIZE.1 - MARGIN;
+    }
+
+    fn at_top(&self) -> bool {
+        self.y == PAGE_SIZE.1 - MARGIN
+    }
+
+    fn place(&mut self, block: &Block) {
+        if self.pages.is_empty() || (block.page_break && !self.at_top()) {
+            self.new_page();
+        }
+        if !self.at_top() {
+            self.y -= block.space_before;
+        }
+
+        let width = PAGE_SIZE.0 - 2.0 * MARGIN - block.indent;
+        let height = block.size * LEADING;
+        for (i, line) in block.lines(width).into_iter().enumerate() {
+            if self.y - height < MARGIN {
+                self.new_page();
+            }
+            self.y -= height;
+            let baseline = self.y + (height - block.size) / 2.0 + block.size * 0.2;
+            let mut x = MARGIN + block.indent + if i == 0 { 0.0 } else { block.hanging };
+
+            let content = self.pages.last_mut().expect("a page was started");
+            for span in line {
+                content.begin_text();
+                content.set_fill_gray(block.gray);
+                content.set_font(span.font.resource(), block.size);
+                content.set_text_matrix([1.0, 0.0, 0.0, 1.0, x, baseline]);
+                content.show(Str(&encode(&span.text)));
+                content.end_text();
+                x += span.font.width(&span.text, block.size);
+            }
+        }
+    }
+
+    /// Number the pages and write the document
+    fn finish(self, title: &str) -> Vec<u8> {
+        let catalog_id = Ref::new(1);
+        let page_tree_id = Ref::new(2);
+        let info_id = Ref::new(3);
+        let font_ids: Vec<Ref> = (0..Font::ALL.len() as i32).map(|i| Ref::new(4 + i)).collect();
+        let first_page = 4 + Font::ALL.len() as i32;
+        let page_ids: Vec<Ref> = (0..self.pages.len() as i32).map(|i| Ref::new(first_page + 2 * i)).collect();
+
+        let mut pdf = Pdf::new();
+        pdf.catalog(catalog_id).pages(page_tree_id);
+        pdf.document_info(info_id).title(TextStr(title)).producer(TextStr("thoughtgraph"));
+        let mut pages = pdf.pages(page_tree_id);
+        pages.kids(page_ids.iter().copied()).count(page_ids.len() as i32);
+        let mut resources = pages.resources();
+        let mut fonts = resources.fonts();
+        for (font, id) in Font::ALL.iter().zip(&font_ids) {
+            fonts.pair(font.resource(), *id);
+        }
+        fonts.finish();
+        resources.finish();
+        pages.finish();
+        for (font, id) in Font::ALL.iter().zip(&font_ids) {
+            pdf.type1_font(*id).base_font(font.base_font()).encoding_predefined(Name(b"WinAnsiEncoding"));
+        }
+
+        let count = self.pages.len();
+        for (i, (mut content, page_id)) in self.pages.into_iter().zip(page_ids).enumerate() {
+            let number = format!("{} / {}", i + 1, count);
+            let size = TEXT_SIZE * 0.8;
+            content.begin_text();
+            content.set_fill_gray(0.5);
+            content.set_font(Font::Regular.resource(), size);
+            content.set_text_matrix([1.0, 0.0, 0.0, 1.0, (PAGE_SIZE.0 - Font::Regular.width(&number, size)) / 2.0, MARGIN / 2.0]);
+            content.show(Str(number.as_bytes()));
+            content.end_text();
+
+            let content_id = Ref::new(page_id.get() + 1);
+            pdf.page(page_id)
+                .parent(page_tree_id)
+                .media_box(Rect::new(0.0, 0.0, PAGE_SIZE.0, PAGE_SIZE.1))
+                .contents(content_id);
+            pdf.stream(content_id, &content.finish());
+        }
+        pdf.finish()
+    }
+}
+
+impl ThoughtGraph {
+    /// Publish thoughts as a PDF document, each starting on a new page.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the document
+    /// * `ids` - The thoughts to publish, in order
+    ///
+    /// # Returns
+    ///
+    /// The PDF file, or the errors of `to_markdown` for any of the thoughts
+    pub fn to_pdf(&self, title: &str, ids: &[ThoughtID]) -> Result<Vec<u8>> {
+        let mut typesetter = Typesetter::new();
+
+        for id in ids {
+            let thought = self.get_thought(id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+            let mut heading = Block::text(20.0, Font::Bold, thought.title.as_deref().unwrap_or(id.as_str()));
+            heading.page_break = true;
+            typesetter.place(&heading);
+            let mut details = id.id.to_string();
+            for tag in &thought.tags {
+                details.push_str(&format!("   #{}", tag.id));
+            }
+            let mut details = Block::text(TEXT_SIZE * 0.8, Font::Regular, &details);
+            details.gray = 0.45;
+            typesetter.place(&details);
+
+            let (contents, footnotes) = extract_footnotes(&self.published_contents(id)?);
+            for block in markdown_blocks(&contents) {
+                typesetter.place(&block);
+            }
+            if !footnotes.is_empty() {
+                typesetter.place(&Block::text(13.0, Font::Bold, "Footnotes"));
+                for footnote in footnotes {
+                    let mut block = Block::text(TEXT_SIZE * 0.85, Font::Regular, &format!("{}. {}", footnote.number, footnote.text));
+                    block.hanging = Font::Regular.width(&format!("{}. ", footnote.number), block.size);
+                    typesetter.place(&block);
+                }
+            }
+
+            let links: Vec<ThoughtID> = thought.references.iter().map(|reference| reference.id.clone()).collect();
+            for (heading, ids) in [("Links", links), ("Backlinks", self.get_backlinks(id))] {
+                if ids.is_empty() {
+                    continue;
+                }
+                typesetter.place(&Block::text(13.0, Font::Bold, heading));
+                for linked in ids {
+                    let mut block = Block::new(TEXT_SIZE, Font::Regular);
+                    block.space_before = TEXT_SIZE * 0.3;
+                    block.hanging = Font::Regular.width("• ", TEXT_SIZE);
+                    block.push(Font::Regular, "• ");
+                    if let Some(title) = self.get_thought(&linked).and_then(|t| t.title.as_deref()) {
+                        block.push(Font::Regular, &format!("{} ", title));
+                    }
+                    block.push(Font::Mono, linked.as_str());
+                    typesetter.place(&block);
+                }
+            }
+        }
+
+        if typesetter.pages.is_empty() {
+            typesetter.new_page();
+        }
+        Ok(typesetter.finish(title))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+
+    fn text(lines: &[Vec<Span>]) -> Vec<String> {
+        lines.iter().map(|line| line.iter().map(|span| span.text.as_str()).collect()).collect()
+    }
+
+    #[test]
+    fn test_markdown_blocks() {
+        let blocks = markdown_blocks("# Tea\n\nGreen *and* black.\n\n- one\n- two\n\n```\nfn main() {}\n```\n");
+        let summary: Vec<(f32, String)> = blocks
+            .iter()
+            .map(|block| (block.size, block.spans.iter().map(|span| span.text.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (18.0, "Tea".to_string()),
+                (TEXT_SIZE, "Green and black.".to_string()),
+                (TEXT_SIZE, "• one".to_string()),
+                (TEXT_SIZE, "• two".to_string()),
+                (TEXT_SIZE * 0.85, "fn main() {}\n".to_string()),
+            ]
+        );
+        assert_eq!(blocks[1].spans[1], Span { font: Font::Italic, text: "and".to_string() });
+
+        let block = Block::text(TEXT_SIZE, Font::Regular, "one two three four");
+        let width = Font::Regular.width("one two three", TEXT_SIZE);
+        assert_eq!(text(&block.lines(width)), vec!["one two three", "four"]);
+        assert_eq!(text(&block.lines(Font::Regular.width("three", TEXT_SIZE))), vec!["one", "two", "three", "four"]);
+        let block = Block::text(TEXT_SIZE, Font::Regular, "abcdefgh");
+        assert_eq!(text(&block.lines(Font::Regular.width("abcd", TEXT_SIZE))), vec!["abcd", "efgh"]);
+    }
+
+    #[test]
+    fn test_to_pdf() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let long = "Café au lait, (tea) and coffee. ".repeat(400);
+        graph.create_thought(id("tea"), Some("Tea".to_string()), long, vec![], vec![]).unwrap();
+        graph.create_thought(id("short"), None, "Short[^1]\n\n[^1]: Note".to_string(), vec![], vec![Reference::new(id("tea"), String::new(), chrono::Utc::now())]).unwrap();
+
+        let pdf = graph.to_pdf("Drinks", &[id("tea"), id("short")]).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(text.contains("/BaseFont /Helvetica-Bold"));
+        assert!(text.contains("(Backlinks)"));
+        assert!(text.contains("/Title (Drinks)"));
+        assert!(text.contains("(1. Note)"));
+        let pages: usize = text.split("/Count ").nth(1).unwrap().split_whitespace().next().unwrap().parse().unwrap();
+        assert!(pages > 3, "{} pages", pages);
+
+        assert!(matches!(graph.to_pdf("Missing", &[id("missing")]), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+}
@@ -0,0 +1,81 @@
+//! Progress reporting
+//!
+//! Loading, saving and importing a large graph, and building its search index, can take
+//! several seconds. The `_with_progress` variants of those operations take a callback
+//! that is called with a `Progress` as the work goes on, so that a frontend can draw a
+//! progress bar. The callback is called often, so it should be cheap; it is called one
+//! last time with `done` equal to `total` when the work is finished.
+
+/// How far along an operation is, in units that depend on the operation (such as bytes
+/// or thoughts)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The units of work done so far
+    pub done: u64,
+    /// The units of work in total, if known
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// Progress through a known amount of work
+    pub fn of(done: u64, total: u64) -> Self {
+        Self { done, total: Some(total) }
+    }
+
+    /// The fraction of the work done, from 0 to 1, if the total is known. No work at all
+    /// counts as done.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::progress::Progress;
+    ///
+    /// assert_eq!(Progress::of(1, 4).fraction(), Some(0.25));
+    /// assert_eq!(Progress::of(0, 0).fraction(), Some(1.0));
+    /// assert_eq!(Progress { done: 7, total: None }.fraction(), None);
+    /// ```
+    pub fn fraction(&self) -> Option<f64> {
+        self.total.map(|total| match total {
+            0 => 1.0,
+            total => (self.done as f64 / total as f64).min(1.0),
+        })
+    }
+}
+
+/// A callback that is told the progress of an operation
+pub type ProgressFn<'a> = &'a mut dyn FnMut(Progress);
+
+/// Size of the pieces in which files are read and written, so that progress can be
+/// reported between them
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const CHUNK_SIZE: usize = 1 << 16;
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::{ThoughtGraph, ThoughtID};
+
+    #[test]
+    fn test_file_progress() {
+        let mut graph = ThoughtGraph::new();
+        for i in 0..500 {
+            let contents = "A thought long enough to take up some room in the file. ".repeat(10);
+            graph.create_thought(ThoughtID::new(format!("t{}", i)), None, contents, vec![], vec![]).unwrap();
+        }
+        let file = tempfile::NamedTempFile::new().unwrap();
+
+        let mut saved = Vec::new();
+        graph.save_to_file_with_progress(file.path(), &mut |progress| saved.push(progress)).unwrap();
+        let size = std::fs::metadata(file.path()).unwrap().len();
+        assert!(size > CHUNK_SIZE as u64);
+        assert_eq!(saved.first(), Some(&Progress::of(0, size)));
+        assert_eq!(saved.last(), Some(&Progress::of(size, size)));
+        assert!(saved.len() > 2 && saved.windows(2).all(|pair| pair[0].done < pair[1].done));
+
+        let mut loaded = Vec::new();
+        let copy = ThoughtGraph::load_from_file_with_progress(file.path(), &mut |progress| loaded.push(progress)).unwrap();
+        assert_eq!(copy.thoughts, graph.thoughts);
+        assert_eq!(loaded.last(), Some(&Progress::of(size, size)));
+        assert!(loaded.len() > 2);
+    }
+}
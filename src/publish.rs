@@ -0,0 +1,256 @@
+//! Publishing thoughts as standalone documents
+//!
+//! A published thought is rendered for reading outside of ThoughtGraph: its title becomes
+//! a heading, embedded thoughts are resolved, `@citation-key` citations of bibliography
+//! thoughts become author-year citations, and a list of the cited sources is appended.
+//! Documents are produced as Markdown, or as HTML rendered from that Markdown.
+
+use crate::bibliography::BibEntry;
+use crate::footnotes;
+use crate::{Result, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// The heading of the list of cited sources
+pub const REFERENCES_HEADING: &str = "References";
+
+/// Escape text for use in HTML
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The list of cited sources, in alphabetical order, if any sources were cited
+fn references_section(cited: &[BibEntry]) -> Option<String> {
+    if cited.is_empty() {
+        return None;
+    }
+    let mut citations: Vec<String> = cited.iter().map(|entry| entry.citation()).collect();
+    citations.sort();
+    let mut section = format!("## {}\n\n", REFERENCES_HEADING);
+    for citation in citations {
+        section.push_str(&format!("- {}\n", citation));
+    }
+    Some(section)
+}
+
+/// A complete HTML page rendered from a Markdown document
+fn html_document(title: &str, markdown: &str) -> String {
+    let mut body = String::new();
+    footnotes::push_html(&mut body, markdown);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+impl ThoughtGraph {
+    /// Publish a thought as a Markdown document.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to publish
+    ///
+    /// # Returns
+    ///
+    /// The document, `ThoughtNotFound` if `id` does not exist, or `TransclusionCycle` if
+    /// a thought ends up embedding itself
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use thoughtgraph::bibliography::parse_bibtex;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let entries = parse_bibtex("@article{knuth1984, author = {Knuth, Donald E.}, title = {Literate Programming}, year = 1984}").unwrap();
+    /// graph.import_bibliography(entries).unwrap();
+    /// let essay = ThoughtID::new("essay".to_string());
+    /// graph.create_thought(essay.clone(), Some("Essay".to_string()), "Programs are literature @knuth1984.".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// assert_eq!(
+    ///     graph.to_markdown(&essay).unwrap(),
+    ///     "# Essay\n\nPrograms are literature (Knuth 1984).\n\n## References\n\n- Knuth, Donald E. (1984). Literate Programming.\n"
+    /// );
+    /// ```
+    pub fn to_markdown(&self, id: &ThoughtID) -> Result<String> {
+        let thought = self.get_thought(id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+        let mut document = String::new();
+        if let Some(title) = &thought.title {
+            document.push_str(&format!("# {}\n\n", title));
+        }
+        document.push_str(&self.published_contents(id)?);
+        Ok(document)
+    }
+
+    /// The contents of a thought as they are published, without its title: embedded
+    /// thoughts are resolved, citations are replaced, and the cited sources are listed at
+    /// the end.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to publish
+    ///
+    /// # Returns
+    ///
+    /// The Markdown contents, or the errors of `to_markdown`
+    pub fn published_contents(&self, id: &ThoughtID) -> Result<String> {
+        let (contents, cited) = self.resolve_citations(&self.render_contents(id)?);
+        let mut document = contents.trim_end().to_string();
+        document.push('\n');
+        if let Some(references) = references_section(&cited) {
+            document.push('\n');
+            document.push_str(&references);
+        }
+        Ok(document)
+    }
+
+    /// Compile thoughts into one Markdown document, such as an essay drafted as a series
+    /// of atomic notes.
+    ///
+    /// Each thought becomes a section headed by its title, if it has one. Embedded thoughts
+    /// and citations are resolved as in `to_markdown`, the sources cited anywhere are
+    /// listed once at the end, and footnote labels are made unique to each section.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the document, if it should have one
+    /// * `ids` - The thoughts to compile, in order
+    ///
+    /// # Returns
+    ///
+    /// The document, or the errors of `to_markdown` for any of the thoughts
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ReferenceKind, Reference, ThoughtGraph, ThoughtID};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = |name: &str| ThoughtID::new(name.to_string());
+    /// let next = |name: &str| Reference::new(id(name), String::new(), Utc::now()).with_kind(ReferenceKind::Next);
+    /// graph.create_thought(id("end"), Some("Conclusion".to_string()), "Drink tea.".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(id("start"), Some("Introduction".to_string()), "Tea is good.".to_string(), vec![], vec![next("end")]).unwrap();
+    ///
+    /// let chain: Vec<ThoughtID> = graph.reference_chain(&id("start"), ReferenceKind::Next).into_iter().cloned().collect();
+    /// assert_eq!(
+    ///     graph.compile(Some("On Tea"), &chain).unwrap(),
+    ///     "# On Tea\n\n## Introduction\n\nTea is good.\n\n## Conclusion\n\nDrink tea.\n"
+    /// );
+    /// ```
+    pub fn compile(&self, title: Option<&str>, ids: &[ThoughtID]) -> Result<String> {
+        let heading = if title.is_some() { "##" } else { "#" };
+        let mut sections: Vec<String> = title.map(|title| format!("# {}", title)).into_iter().collect();
+        let mut cited: Vec<BibEntry> = Vec::new();
+
+        for (i, id) in ids.iter().enumerate() {
+            let thought = self.get_thought(id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+            if let Some(title) = &thought.title {
+                sections.push(format!("{} {}", heading, title));
+            }
+            let (contents, entries) = self.resolve_citations(&self.render_contents(id)?);
+            let contents = footnotes::prefix_labels(&contents, &format!("{}-", i + 1));
+            if !contents.trim().is_empty() {
+                sections.push(contents.trim().to_string());
+            }
+            for entry in entries {
+                if cited.iter().all(|c| c.key != entry.key) {
+                    cited.push(entry);
+                }
+            }
+        }
+
+        sections.extend(references_section(&cited).map(|references| references.trim_end().to_string()));
+        let mut document = sections.join("\n\n");
+        document.push('\n');
+        Ok(document)
+    }
+
+    /// Publish a thought as an HTML document.
+    ///
+    /// The document is the Markdown of `to_markdown` rendered as HTML, with the thought's
+    /// title (or ID) as the page title and footnotes listed at the end of the page.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to publish
+    ///
+    /// # Returns
+    ///
+    /// The document, or the errors of `to_markdown`
+    pub fn to_html(&self, id: &ThoughtID) -> Result<String> {
+        let markdown = self.to_markdown(id)?;
+        let title = self.get_thought(id).and_then(|t| t.title.as_deref()).unwrap_or(id.as_str());
+        Ok(html_document(title, &markdown))
+    }
+
+    /// Compile thoughts into one HTML document.
+    ///
+    /// The document is the Markdown of `compile` rendered as HTML.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the document, which is also the page title
+    /// * `ids` - The thoughts to compile, in order
+    ///
+    /// # Returns
+    ///
+    /// The document, or the errors of `compile`
+    pub fn compile_html(&self, title: &str, ids: &[ThoughtID]) -> Result<String> {
+        Ok(html_document(title, &self.compile(Some(title), ids)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibliography::parse_bibtex;
+
+    #[test]
+    fn test_publish_with_citations() {
+        let mut graph = ThoughtGraph::new();
+        let bibtex = "@book{sicp, author = {Abelson, Harold and Sussman, Gerald Jay}, title = {SICP}, year = 1985}\n\
+            @misc{talk, author = {A, B and C, D and E, F}, title = {Talk}}";
+        graph.import_bibliography(parse_bibtex(bibtex).unwrap()).unwrap();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        graph.create_thought(id("quote"), None, "Programs must be written for people to read @sicp.".to_string(), vec![], vec![]).unwrap();
+        graph
+            .create_thought(
+                id("essay"),
+                Some("Essays & <Notes>".to_string()),
+                "{{embed:quote}}\nAgain @sicp, and @talk, but not @unknown or a@b.c.".to_string(),
+                vec![],
+                vec![],
+            )
+            .unwrap();
+
+        let markdown = graph.to_markdown(&id("essay")).unwrap();
+        assert!(markdown.contains("people to read (Abelson and Sussman 1985).\nAgain (Abelson and Sussman 1985), and (A et al.), but not @unknown or a@b.c."));
+        assert!(markdown.ends_with("## References\n\n- A, B; C, D; E, F. Talk.\n- Abelson, Harold; Sussman, Gerald Jay (1985). SICP.\n"));
+
+        let html = graph.to_html(&id("essay")).unwrap();
+        assert!(html.contains("<title>Essays &amp; &lt;Notes&gt;</title>"));
+        assert!(html.contains("<h2>References</h2>"));
+        assert!(matches!(graph.to_html(&id("missing")), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+
+    #[test]
+    fn test_compile() {
+        let mut graph = ThoughtGraph::new();
+        graph.import_bibliography(parse_bibtex("@book{sicp, author = {Abelson, Harold}, title = {SICP}, year = 1985}").unwrap()).unwrap();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        graph.create_thought(id("a"), Some("First".to_string()), "One @sicp.[^n]\n\n[^n]: A note.".to_string(), vec![], vec![]).unwrap();
+        graph.create_thought(id("b"), None, "Two @sicp.[^n]\n\n[^n]: Another note.".to_string(), vec![], vec![]).unwrap();
+
+        assert_eq!(
+            graph.compile(None, &[id("a"), id("b")]).unwrap(),
+            "# First\n\nOne (Abelson 1985).[^1-n]\n\n[^1-n]: A note.\n\n\
+             Two (Abelson 1985).[^2-n]\n\n[^2-n]: Another note.\n\n\
+             ## References\n\n- Abelson, Harold (1985). SICP.\n"
+        );
+        assert!(matches!(graph.compile(None, &[id("a"), id("missing")]), Err(ThoughtGraphError::ThoughtNotFound(_))));
+
+        let html = graph.compile_html("Notes", &[id("a"), id("b")]).unwrap();
+        assert!(html.contains("<title>Notes</title>"));
+        assert!(html.contains("<h1>Notes</h1>") && html.contains("<h2>First</h2>"));
+    }
+}
@@ -0,0 +1,260 @@
+//! Relevance ranking
+//!
+//! `query` and `search` find the thoughts that match; `query_ranked` and `search_ranked`
+//! also score them by relevance, most relevant first. A score adds up how strongly a
+//! thought matches (how many of the alternatives of an `Or` it meets, and how often the
+//! text it was found by occurs, with text in the title counting double), how recently it
+//! was modified, and how many thoughts reference it. Searches weigh their terms with
+//! BM25: a term counts for more the fewer thoughts contain it, and each further
+//! occurrence of it, or occurrence in a longer thought, counts for less. Scores only
+//! mean something compared to the other results of the same query or search.
+
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Utc};
+
+use crate::matcher::{Condition, Field, Matcher};
+use crate::parallel;
+use crate::search::SearchOptions;
+use crate::{Query, Result, Thought, ThoughtGraph, ThoughtID};
+
+/// How much more an occurrence in the title counts than one in the contents
+const TITLE_WEIGHT: f32 = 2.0;
+
+/// The score of a thought modified just now, halving every `RECENCY_HALF_LIFE_DAYS`
+const RECENCY_WEIGHT: f32 = 1.0;
+
+/// Days after which the recency part of a score has halved
+const RECENCY_HALF_LIFE_DAYS: f32 = 30.0;
+
+/// The score of each doubling of the number of backlinks
+const BACKLINK_WEIGHT: f32 = 0.5;
+
+/// How quickly further occurrences of a search term stop adding to a score (BM25's `k1`)
+const BM25_SATURATION: f32 = 1.2;
+
+/// How much a thought's length reduces the weight of its occurrences, from not at all
+/// to fully in proportion to it (BM25's `b`)
+const BM25_LENGTH_NORMALIZATION: f32 = 0.75;
+
+/// The number of words of normalized text
+fn word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// The strength of a match found `count` times: each occurrence counts for less than
+/// the one before, so that long thoughts do not win by length alone
+fn frequency(count: usize) -> f32 {
+    match count {
+        0 => 0.0,
+        count => 1.0 + (count as f32).ln(),
+    }
+}
+
+/// Order scored thoughts from the highest score down, and by ID among equal scores
+fn sort_ranked(ranked: &mut [(ThoughtID, f32)]) {
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
+}
+
+impl ThoughtGraph {
+    /// Find the thoughts matching a query, scored by relevance.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to evaluate
+    ///
+    /// # Returns
+    ///
+    /// The matching thoughts with their scores, highest first and by ID among equal
+    /// scores, or `InvalidPattern` if one of the patterns of the query does not compile
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Query, ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("once".to_string()), None, "Rust".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(ThoughtID::new("often".to_string()), Some("Rust".to_string()), "Rust, Rust".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let query = Query::Or(vec![
+    ///     Box::new(Query::TitleContains("rust".to_string())),
+    ///     Box::new(Query::ContentContains("rust".to_string())),
+    /// ]);
+    /// let ranked = graph.query_ranked(&query).unwrap();
+    /// assert_eq!(ranked[0].0.as_str(), "often");
+    /// assert!(ranked[0].1 > ranked[1].1);
+    /// ```
+    pub fn query_ranked(&self, query: &Query) -> Result<Vec<(ThoughtID, f32)>> {
+        let matcher = self.matcher(query)?;
+        let now = Utc::now();
+        let mut ranked = parallel::filter_map(&self.thoughts, |id, thought| {
+            let score = || matcher.strength(id, thought, &matcher.condition) + self.prominence(id, thought, now);
+            matcher.matches(id).then(|| (id.clone(), score()))
+        });
+        sort_ranked(&mut ranked);
+        Ok(ranked)
+    }
+
+    /// Find the thoughts containing all of the given terms, as `search_with` does, scored
+    /// by relevance.
+    ///
+    /// # Arguments
+    ///
+    /// * `terms` - The terms that must all appear in a thought
+    /// * `options` - Whether to match case and accents, and whether to match only whole words
+    ///
+    /// # Returns
+    ///
+    /// The matching thoughts with their scores, highest first and by ID among equal
+    /// scores
+    pub fn search_ranked<S: AsRef<str>>(&self, terms: &[S], options: &SearchOptions) -> Vec<(ThoughtID, f32)> {
+        let found = self.search_with(terms, options);
+        if found.is_empty() {
+            return Vec::new();
+        }
+        let normalized: Vec<String> = terms.iter().map(|term| options.normalize(term.as_ref())).collect();
+
+        // The length of every thought, and which of the terms it contains
+        let corpus = parallel::filter_map(&self.thoughts, |_, thought| {
+            let title = options.normalize(thought.title.as_deref().unwrap_or_default());
+            let contents = options.normalize(&thought.contents);
+            let contains: Vec<bool> =
+                normalized.iter().map(|term| options.matches(&title, term) || options.matches(&contents, term)).collect();
+            Some((word_count(&title) + word_count(&contents), contains))
+        });
+        let total = corpus.len() as f32;
+        let average_length = (corpus.iter().map(|(length, _)| length).sum::<usize>() as f32 / total).max(1.0);
+        let inverse_frequencies: Vec<f32> = (0..normalized.len())
+            .map(|term| {
+                let containing = corpus.iter().filter(|(_, contains)| contains[term]).count() as f32;
+                (1.0 + (total - containing + 0.5) / (containing + 0.5)).ln()
+            })
+            .collect();
+
+        let now = Utc::now();
+        let mut ranked: Vec<(ThoughtID, f32)> = found
+            .into_iter()
+            .map(|(id, thought)| {
+                let title = options.normalize(thought.title.as_deref().unwrap_or_default());
+                let contents = options.normalize(&thought.contents);
+                let length = (word_count(&title) + word_count(&contents)) as f32;
+                let length_factor = 1.0 - BM25_LENGTH_NORMALIZATION + BM25_LENGTH_NORMALIZATION * length / average_length;
+                let strength: f32 = normalized
+                    .iter()
+                    .zip(&inverse_frequencies)
+                    .map(|(term, inverse_frequency)| {
+                        let count = TITLE_WEIGHT * options.count(&title, term) as f32 + options.count(&contents, term) as f32;
+                        inverse_frequency * count * (BM25_SATURATION + 1.0) / (count + BM25_SATURATION * length_factor)
+                    })
+                    .sum();
+                (id.clone(), strength + self.prominence(id, thought, now))
+            })
+            .collect();
+        sort_ranked(&mut ranked);
+        ranked
+    }
+
+    /// The part of a thought's score that does not depend on the query: how recently it
+    /// was modified and how many thoughts reference it
+    fn prominence(&self, id: &ThoughtID, thought: &Thought, now: DateTime<Utc>) -> f32 {
+        let age_days = (now - thought.updated_at).num_seconds().max(0) as f32 / 86_400.0;
+        let recency = RECENCY_WEIGHT * 0.5_f32.powf(age_days / RECENCY_HALF_LIFE_DAYS);
+        recency + BACKLINK_WEIGHT * (1.0 + self.backlink_count(id) as f32).log2()
+    }
+}
+
+impl Matcher<'_> {
+    /// How strongly a thought that matches a condition of the query matches it
+    fn strength(&self, id: &ThoughtID, thought: &Thought, condition: &Condition) -> f32 {
+        match condition {
+            Condition::And(conditions) => conditions.iter().map(|condition| self.strength(id, thought, condition)).sum(),
+            Condition::Or(conditions) => conditions
+                .iter()
+                .filter(|condition| self.check(id, condition))
+                .map(|condition| self.strength(id, thought, condition))
+                .sum(),
+            // Not meeting a condition does not make a thought more relevant
+            Condition::Not(_) => 0.0,
+            Condition::Text(term, Field::Title) => TITLE_WEIGHT * frequency(term.occurrences(thought.title.as_deref())),
+            Condition::Text(term, Field::Content) => frequency(term.occurrences(Some(&thought.contents))),
+            Condition::Text(term, Field::ReferenceNotes) => {
+                frequency(thought.references.iter().map(|reference| term.occurrences(Some(&reference.notes))).sum())
+            },
+            Condition::TitleRegex(regex) => {
+                TITLE_WEIGHT * frequency(thought.title.as_deref().map_or(0, |title| regex.find_iter(title).count()))
+            },
+            Condition::ContentRegex(regex) => frequency(regex.find_iter(&thought.contents).count()),
+            Condition::Plain(_) | Condition::Reachable(_) => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Reference};
+    use chrono::Duration;
+
+    fn put(graph: &mut ThoughtGraph, id: &str, title: Option<&str>, contents: &str, age_days: i64, references: &[&str]) {
+        let references = references.iter().map(|to| Reference::new(ThoughtID::new(to.to_string()), String::new(), Utc::now())).collect();
+        let mut thought = Thought::new(title.map(str::to_string), contents.to_string(), vec![], references);
+        thought.updated_at = Utc::now() - Duration::days(age_days);
+        graph.command(&Command::PutThought { id: ThoughtID::new(id.to_string()), thought });
+    }
+
+    fn order(ranked: Vec<(ThoughtID, f32)>) -> Vec<String> {
+        ranked.into_iter().map(|(id, _)| id.id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_ranking() {
+        let mut graph = ThoughtGraph::new();
+        put(&mut graph, "title", Some("Borrowing"), "", 0, &[]);
+        put(&mut graph, "content", None, "borrowing and more borrowing", 0, &[]);
+        put(&mut graph, "old", None, "borrowing and more borrowing", 365, &[]);
+        put(&mut graph, "cited", None, "borrowing and more borrowing", 365, &[]);
+        for citing in ["x", "y"] {
+            put(&mut graph, citing, None, "", 365, &["cited"]);
+        }
+
+        // A match in the title beats two in the contents; recent beats old; cited beats uncited
+        let search = order(graph.search_ranked(&["borrowing"], &SearchOptions::default()));
+        assert_eq!(search, vec!["title", "content", "cited", "old"]);
+
+        // Meeting more alternatives of an Or ranks higher
+        let query = Query::parse("content:borrowing OR id:cited").unwrap();
+        assert_eq!(order(graph.query_ranked(&query).unwrap()), vec!["cited", "content", "old"]);
+        let scores: Vec<f32> = graph.query_ranked(&Query::All).unwrap().into_iter().map(|(_, score)| score).collect();
+        assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(graph.query_ranked(&Query::Or(vec![Box::new(Query::All), Box::new(Query::TitleRegex("[".to_string()))])).is_err());
+    }
+
+    #[test]
+    fn test_rare_terms_count_more() {
+        let mut graph = ThoughtGraph::new();
+        put(&mut graph, "common", None, "graph graph graph rare", 0, &[]);
+        put(&mut graph, "rare", None, "graph rare rare rare", 0, &[]);
+        for filler in ["x", "y", "z"] {
+            put(&mut graph, filler, None, "graph", 0, &[]);
+        }
+
+        // Both contain both terms as often, but only two thoughts contain "rare"
+        assert_eq!(order(graph.search_ranked(&["graph", "rare"], &SearchOptions::default())), vec!["rare", "common"]);
+        assert!(graph.search_ranked(&["missing"], &SearchOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_stemmed_search_ranking() {
+        let mut graph = ThoughtGraph::new();
+        put(&mut graph, "once", None, "We agreed on a plan", 0, &[]);
+        put(&mut graph, "often", None, "Agreed, agreed and agreed again", 0, &[]);
+        put(&mut graph, "other", None, "Nothing to see", 0, &[]);
+
+        // Occurrences are counted in the stemmed text, so the stems of the thoughts are not stemmed again
+        let stem = SearchOptions { stem: true, ..SearchOptions::default() };
+        let ranked = graph.search_ranked(&["agreeing"], &stem);
+        assert_eq!(order(ranked.clone()), vec!["often", "once"]);
+        assert!(ranked[0].1 > ranked[1].1);
+    }
+}
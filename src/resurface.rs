@@ -0,0 +1,144 @@
+//! Resurfacing of older thoughts
+//!
+//! Looking back at what one was thinking a year (or a month) ago is a lightweight way to
+//! reflect on past ideas. This module finds the thoughts that were created on the same
+//! calendar day in earlier years or months.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone};
+
+use crate::{Thought, ThoughtGraph, ThoughtID};
+
+/// How far apart the "same day" in an earlier period is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anniversary {
+    /// The same month and day in previous years
+    Yearly,
+    /// The same day of the month in previous months
+    Monthly,
+}
+
+/// Whether `date` is the last day of its month
+fn is_last_day_of_month(date: NaiveDate) -> bool {
+    (date + Duration::days(1)).month() != date.month()
+}
+
+/// Whether `day` (a day of the month) falls on the anniversary of `today`.
+///
+/// On the last day of a month, days that do not exist in the current month also match,
+/// so that thoughts from the 31st show up at the end of a 30-day month and thoughts from
+/// February 29th show up on February 28th.
+fn same_day(day: u32, today: NaiveDate) -> bool {
+    day == today.day() || (day > today.day() && is_last_day_of_month(today))
+}
+
+impl ThoughtGraph {
+    /// Get the thoughts created on this day in earlier years or months.
+    ///
+    /// Creation times are compared as calendar dates in the time zone of `today`.
+    ///
+    /// # Arguments
+    ///
+    /// * `today` - The current time, in the time zone the dates should be compared in
+    /// * `period` - Whether to look at previous years or previous months
+    ///
+    /// # Returns
+    ///
+    /// The matching thoughts, most recent first. Thoughts created today are not included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Thought, Command};
+    /// use thoughtgraph::resurface::Anniversary;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let mut thought = Thought::new(None, "A year ago".to_string(), vec![], vec![]);
+    /// thought.created_at = Utc.with_ymd_and_hms(2023, 5, 17, 9, 0, 0).unwrap();
+    /// graph.command(&Command::PutThought { id: ThoughtID::new("old".to_string()), thought });
+    ///
+    /// let today = Utc.with_ymd_and_hms(2024, 5, 17, 12, 0, 0).unwrap();
+    /// let found = graph.on_this_day(&today, Anniversary::Yearly);
+    /// assert_eq!(found.len(), 1);
+    /// assert_eq!(found[0].0.as_str(), "old");
+    /// ```
+    pub fn on_this_day<Tz: TimeZone>(&self, today: &DateTime<Tz>, period: Anniversary) -> Vec<(&ThoughtID, &Thought)> {
+        let timezone = today.timezone();
+        let today = today.date_naive();
+
+        let mut found: Vec<(&ThoughtID, &Thought)> = self
+            .thoughts
+            .iter()
+            .filter(|(_, thought)| {
+                let created = thought.created_at.with_timezone(&timezone).date_naive();
+                if created >= today || !same_day(created.day(), today) {
+                    return false;
+                }
+                match period {
+                    Anniversary::Yearly => created.month() == today.month(),
+                    Anniversary::Monthly => true,
+                }
+            })
+            .collect();
+
+        found.sort_by(|a, b| b.1.created_at.cmp(&a.1.created_at).then_with(|| a.0.cmp(b.0)));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use chrono::{FixedOffset, Utc};
+
+    fn add_at(graph: &mut ThoughtGraph, id: &str, created_at: DateTime<Utc>) {
+        let mut thought = Thought::new(None, id.to_string(), vec![], vec![]);
+        thought.created_at = created_at;
+        graph.command(&Command::PutThought { id: ThoughtID::new(id.to_string()), thought });
+    }
+
+    fn ids(found: Vec<(&ThoughtID, &Thought)>) -> Vec<String> {
+        found.into_iter().map(|(id, _)| id.id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_yearly_and_monthly() {
+        let mut graph = ThoughtGraph::new();
+        add_at(&mut graph, "two-years", Utc.with_ymd_and_hms(2022, 3, 10, 8, 0, 0).unwrap());
+        add_at(&mut graph, "one-year", Utc.with_ymd_and_hms(2023, 3, 10, 8, 0, 0).unwrap());
+        add_at(&mut graph, "last-month", Utc.with_ymd_and_hms(2024, 2, 10, 8, 0, 0).unwrap());
+        add_at(&mut graph, "other-day", Utc.with_ymd_and_hms(2023, 3, 11, 8, 0, 0).unwrap());
+        add_at(&mut graph, "today", Utc.with_ymd_and_hms(2024, 3, 10, 7, 0, 0).unwrap());
+
+        let today = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        assert_eq!(ids(graph.on_this_day(&today, Anniversary::Yearly)), vec!["one-year", "two-years"]);
+        assert_eq!(
+            ids(graph.on_this_day(&today, Anniversary::Monthly)),
+            vec!["last-month", "one-year", "two-years"]
+        );
+    }
+
+    #[test]
+    fn test_end_of_month_catches_missing_days() {
+        let mut graph = ThoughtGraph::new();
+        add_at(&mut graph, "leap-day", Utc.with_ymd_and_hms(2020, 2, 29, 8, 0, 0).unwrap());
+        add_at(&mut graph, "jan-31", Utc.with_ymd_and_hms(2023, 1, 31, 8, 0, 0).unwrap());
+
+        let today = Utc.with_ymd_and_hms(2023, 2, 28, 12, 0, 0).unwrap();
+        assert_eq!(ids(graph.on_this_day(&today, Anniversary::Yearly)), vec!["leap-day"]);
+        assert_eq!(ids(graph.on_this_day(&today, Anniversary::Monthly)), vec!["jan-31", "leap-day"]);
+    }
+
+    #[test]
+    fn test_dates_use_the_given_time_zone() {
+        let mut graph = ThoughtGraph::new();
+        // Late in the evening of March 9th in UTC-5, already March 10th in UTC
+        add_at(&mut graph, "evening", Utc.with_ymd_and_hms(2023, 3, 10, 3, 0, 0).unwrap());
+
+        let offset = FixedOffset::west_opt(5 * 3600).unwrap();
+        let today_utc = Utc.with_ymd_and_hms(2024, 3, 10, 15, 0, 0).unwrap();
+        assert_eq!(graph.on_this_day(&today_utc, Anniversary::Yearly).len(), 1);
+        assert!(graph.on_this_day(&today_utc.with_timezone(&offset), Anniversary::Yearly).is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+//! Automatic tagging rules
+//!
+//! A tag rule says "if a thought matches this pattern, tag it with this tag", for
+//! example `kubernetes|k8s` → `k8s`. Rules are stored in the graph under a name and are
+//! applied to thoughts as they are created or edited, so tagging stays consistent
+//! without having to remember every tag by hand.
+
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{Command, Result, Tag, TagID, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// A rule that tags thoughts whose title or content matches a pattern
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TagRule {
+    /// Regular expression matched (case-insensitively) against the title and content
+    pub pattern: String,
+    /// Tag applied to matching thoughts
+    pub tag: TagID,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+impl TagRule {
+    /// Creates a new tag rule, checking that the pattern is a valid regular expression.
+    ///
+    /// The pattern may optionally be written between slashes (`/kubernetes|k8s/`); the
+    /// slashes are not part of the expression. Matching is case-insensitive.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The regular expression to match against thoughts
+    /// * `tag` - The tag to apply to matching thoughts
+    ///
+    /// # Returns
+    ///
+    /// The rule, or `InvalidPattern` if the pattern does not compile
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::TagID;
+    /// use thoughtgraph::rules::TagRule;
+    ///
+    /// let rule = TagRule::new("/kubernetes|k8s/".to_string(), TagID::new("k8s".to_string())).unwrap();
+    /// assert_eq!(rule.pattern, "kubernetes|k8s");
+    ///
+    /// assert!(TagRule::new("(unclosed".to_string(), TagID::new("x".to_string())).is_err());
+    /// ```
+    pub fn new(pattern: String, tag: TagID) -> Result<Self> {
+        let pattern = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+            Some(inner) => inner.to_string(),
+            None => pattern,
+        };
+        let rule = Self { pattern, tag, created_at: Utc::now() };
+        rule.regex()?;
+        Ok(rule)
+    }
+
+    /// Compile the rule's pattern
+    pub fn regex(&self) -> Result<Regex> {
+        RegexBuilder::new(&self.pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| ThoughtGraphError::InvalidPattern(format!("{}: {}", self.pattern, e)))
+    }
+}
+
+/// A rule that was applied to a thought
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppliedRule {
+    /// Name of the rule
+    pub rule: String,
+    /// Tag that was added to the thought
+    pub tag: TagID,
+}
+
+/// A rule with its pattern compiled, ready to be applied
+struct CompiledRule {
+    name: String,
+    regex: Regex,
+    tag: TagID,
+}
+
+impl CompiledRule {
+    fn matches(&self, thought: &Thought) -> bool {
+        !thought.tags.contains(&self.tag)
+            && (self.regex.is_match(&thought.contents)
+                || thought.title.as_deref().is_some_and(|title| self.regex.is_match(title)))
+    }
+}
+
+impl ThoughtGraph {
+    /// Get all tag rules, sorted by name
+    pub fn list_rules(&self) -> Vec<(&str, &TagRule)> {
+        let mut rules: Vec<(&str, &TagRule)> =
+            self.rules.iter().map(|(name, rule)| (name.as_str(), rule)).collect();
+        rules.sort_by(|a, b| a.0.cmp(b.0));
+        rules
+    }
+
+    /// Compile every rule in the graph, in name order
+    fn compile_rules(&self) -> Result<Vec<CompiledRule>> {
+        self.list_rules()
+            .into_iter()
+            .map(|(name, rule)| {
+                Ok(CompiledRule { name: name.to_string(), regex: rule.regex()?, tag: rule.tag.clone() })
+            })
+            .collect()
+    }
+
+    /// Apply compiled rules to a single thought, returning the rules that added a tag
+    fn apply_compiled_rules(&mut self, id: &ThoughtID, rules: &[CompiledRule]) -> Vec<AppliedRule> {
+        let mut thought = match self.thoughts.get(id) {
+            Some(thought) if !self.is_locked(id) => thought.clone(),
+            _ => return Vec::new(),
+        };
+
+        let mut applied = Vec::new();
+        for rule in rules {
+            if rule.matches(&thought) {
+                thought.add_tag(rule.tag.clone());
+                applied.push(AppliedRule { rule: rule.name.clone(), tag: rule.tag.clone() });
+            }
+        }
+
+        if !applied.is_empty() {
+            // Rules may name tags that have not been created yet
+            for AppliedRule { rule, tag } in &applied {
+                if !self.tags.contains_key(tag) {
+                    self.apply(Command::PutTag {
+                        id: tag.clone(),
+                        tag: Tag::new(format!("Applied automatically by rule '{}'", rule)),
+                    });
+                }
+            }
+            self.apply(Command::PutThought { id: id.clone(), thought });
+        }
+
+        applied
+    }
+
+    /// Apply the graph's tag rules to a thought.
+    ///
+    /// Every rule whose pattern matches the thought's title or content adds its tag to the
+    /// thought, unless the thought already has it. Tags named by a rule that do not exist
+    /// yet are created.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to apply the rules to
+    ///
+    /// # Returns
+    ///
+    /// The rules that added a tag, in name order. Unknown thoughts yield an empty list.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, TagID, Command};
+    /// use thoughtgraph::rules::TagRule;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.command(&Command::PutTagRule {
+    ///     name: "k8s".to_string(),
+    ///     rule: TagRule::new("kubernetes|k8s".to_string(), TagID::new("k8s".to_string())).unwrap(),
+    /// });
+    ///
+    /// let id = ThoughtID::new("cluster".to_string());
+    /// graph.create_thought(id.clone(), None, "Upgrading Kubernetes".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let applied = graph.apply_tag_rules(&id).unwrap();
+    /// assert_eq!(applied.len(), 1);
+    /// assert!(graph.get_thought(&id).unwrap().tags.contains(&TagID::new("k8s".to_string())));
+    /// ```
+    pub fn apply_tag_rules(&mut self, id: &ThoughtID) -> Result<Vec<AppliedRule>> {
+        let rules = self.compile_rules()?;
+        Ok(self.apply_compiled_rules(id, &rules))
+    }
+
+    /// Apply the graph's tag rules to every thought.
+    ///
+    /// # Returns
+    ///
+    /// The rules that were applied, paired with the thought they were applied to, sorted
+    /// by thought ID.
+    pub fn apply_tag_rules_to_all(&mut self) -> Result<Vec<(ThoughtID, AppliedRule)>> {
+        let rules = self.compile_rules()?;
+        let mut ids: Vec<ThoughtID> = self.thoughts.keys().cloned().collect();
+        ids.sort();
+
+        let mut applied = Vec::new();
+        for id in ids {
+            for rule in self.apply_compiled_rules(&id, &rules) {
+                applied.push((id.clone(), rule));
+            }
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_rule(graph: &mut ThoughtGraph, name: &str, pattern: &str, tag: &str) {
+        graph.command(&Command::PutTagRule {
+            name: name.to_string(),
+            rule: TagRule::new(pattern.to_string(), TagID::new(tag.to_string())).unwrap(),
+        });
+    }
+
+    fn add_thought(graph: &mut ThoughtGraph, id: &str, title: Option<&str>, contents: &str) -> ThoughtID {
+        let thought_id = ThoughtID::new(id.to_string());
+        graph
+            .create_thought(thought_id.clone(), title.map(String::from), contents.to_string(), vec![], vec![])
+            .unwrap();
+        thought_id
+    }
+
+    #[test]
+    fn test_rules_match_title_and_content() {
+        let mut graph = ThoughtGraph::new();
+        add_rule(&mut graph, "k8s", "/kubernetes|k8s/", "k8s");
+        add_rule(&mut graph, "rust", r"\brust\b", "rust");
+
+        let id = add_thought(&mut graph, "a", Some("Rust operators"), "Writing a K8s controller");
+        let applied = graph.apply_tag_rules(&id).unwrap();
+
+        assert_eq!(
+            applied,
+            vec![
+                AppliedRule { rule: "k8s".to_string(), tag: TagID::new("k8s".to_string()) },
+                AppliedRule { rule: "rust".to_string(), tag: TagID::new("rust".to_string()) },
+            ]
+        );
+        // Missing tags are created so that tag queries find the thought
+        assert_eq!(graph.query(&crate::Query::Tag(TagID::new("k8s".to_string()))).len(), 1);
+
+        // Applying again is a no-op
+        assert!(graph.apply_tag_rules(&id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_non_matching_thoughts_are_untouched() {
+        let mut graph = ThoughtGraph::new();
+        add_rule(&mut graph, "k8s", "kubernetes", "k8s");
+        let id = add_thought(&mut graph, "a", None, "Nothing to see");
+        let updated_at = graph.get_thought(&id).unwrap().updated_at;
+
+        assert!(graph.apply_tag_rules(&id).unwrap().is_empty());
+        assert_eq!(graph.get_thought(&id).unwrap().updated_at, updated_at);
+        assert!(graph.tags.is_empty());
+    }
+
+    #[test]
+    fn test_apply_to_all_and_delete() {
+        let mut graph = ThoughtGraph::new();
+        add_rule(&mut graph, "k8s", "kubernetes", "k8s");
+        add_thought(&mut graph, "b", None, "kubernetes b");
+        add_thought(&mut graph, "a", None, "kubernetes a");
+        add_thought(&mut graph, "c", None, "other");
+
+        let applied = graph.apply_tag_rules_to_all().unwrap();
+        let ids: Vec<&str> = applied.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+
+        graph.command(&Command::DeleteTagRule { name: "k8s".to_string() });
+        assert!(graph.list_rules().is_empty());
+    }
+
+    #[test]
+    fn test_rules_are_persisted() {
+        let mut graph = ThoughtGraph::new();
+        add_rule(&mut graph, "k8s", "kubernetes", "k8s");
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        graph.save_to_file(file.path()).unwrap();
+        let loaded = ThoughtGraph::load_from_file(file.path()).unwrap();
+
+        let rules = loaded.list_rules();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].0, "k8s");
+        assert_eq!(rules[0].1.pattern, "kubernetes");
+    }
+}
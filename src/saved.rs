@@ -0,0 +1,88 @@
+//! Saved queries
+//!
+//! Queries that are run again and again, such as "everything tagged inbox", can be stored
+//! in the graph under a name with `Command::PutSavedQuery` and run by that name. Saved
+//! queries are saved along with the thoughts, so they are available wherever the graph
+//! file is used.
+
+use crate::{Query, Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+impl ThoughtGraph {
+    /// Get all saved queries, sorted by name
+    pub fn list_saved_queries(&self) -> Vec<(&str, &Query)> {
+        let mut queries: Vec<(&str, &Query)> =
+            self.saved_queries.iter().map(|(name, query)| (name.as_str(), query)).collect();
+        queries.sort_by(|a, b| a.0.cmp(b.0));
+        queries
+    }
+
+    /// Run the saved query with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the query was saved under
+    ///
+    /// # Returns
+    ///
+    /// The matching thoughts ordered by ID, or `SavedQueryNotFound` if there is no query
+    /// with that name
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Command, Query, TagID, ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let inbox = TagID::new("inbox".to_string());
+    /// graph.create_tag(inbox.clone(), "Unsorted thoughts".to_string()).unwrap();
+    /// graph.create_thought(ThoughtID::new("idea".to_string()), None, String::new(), vec![inbox.clone()], vec![]).unwrap();
+    /// graph.command(&Command::PutSavedQuery { name: "inbox".to_string(), query: Query::Tag(inbox) });
+    ///
+    /// let results = graph.run_saved_query("inbox").unwrap();
+    /// assert_eq!(results.len(), 1);
+    /// assert_eq!(results[0].0.as_str(), "idea");
+    /// assert!(graph.run_saved_query("stale").is_err());
+    /// ```
+    pub fn run_saved_query(&self, name: &str) -> Result<Vec<(&ThoughtID, &Thought)>> {
+        let query = self
+            .saved_queries
+            .get(name)
+            .ok_or_else(|| ThoughtGraphError::SavedQueryNotFound(name.to_string()))?;
+        let mut results = self.find_thoughts(query);
+        results.sort_by(|a, b| a.0.cmp(b.0));
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, TagID};
+
+    #[test]
+    fn test_saved_queries() {
+        let mut graph = ThoughtGraph::new();
+        let tag = TagID::new("project".to_string());
+        graph.create_tag(tag.clone(), String::new()).unwrap();
+        for name in ["b", "a"] {
+            graph.create_thought(ThoughtID::new(name.to_string()), None, String::new(), vec![tag.clone()], vec![]).unwrap();
+        }
+
+        let changes = graph.apply(Command::PutSavedQuery { name: "projects".to_string(), query: Query::Tag(tag.clone()) });
+        assert_eq!(changes.to_string(), "1 saved query changed");
+        graph.apply(Command::PutSavedQuery { name: "empty".to_string(), query: Query::Or(vec![]) });
+        assert_eq!(graph.list_saved_queries().iter().map(|(name, _)| *name).collect::<Vec<_>>(), vec!["empty", "projects"]);
+
+        let ids: Vec<&str> = graph.run_saved_query("projects").unwrap().iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+
+        // Saved queries survive saving and loading
+        let loaded = ThoughtGraph::from_bytes(&graph.to_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.run_saved_query("projects").unwrap().len(), 2);
+
+        let changes = graph.apply(Command::DeleteSavedQuery { name: "projects".to_string() });
+        assert_eq!(changes.saved_queries_changed, vec!["projects".to_string()]);
+        assert!(matches!(graph.run_saved_query("projects"), Err(ThoughtGraphError::SavedQueryNotFound(_))));
+        assert!(graph.apply(Command::DeleteSavedQuery { name: "projects".to_string() }).is_empty());
+    }
+}
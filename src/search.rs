@@ -0,0 +1,339 @@
+//! Text search over thoughts
+//!
+//! Finds the thoughts whose title or content contains a set of search terms, ignoring
+//! case and accents unless asked not to, and either anywhere or only as whole words. Text
+//! and terms are Unicode-normalized first, so "café" finds "cafe" and "Café" however its
+//! accent was typed. Searches may also compare words by their stems, so that "running"
+//! finds "runs" and "ran", leaving out stop words such as "the". On large graphs the
+//! search runs on all cores when the `parallel` feature is enabled.
+
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use crate::parallel;
+use crate::text;
+use crate::{Query, Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// How search terms are matched
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Only match text in the same case as the terms
+    pub case_sensitive: bool,
+    /// Only match text with the same accents and other diacritics as the terms, so that
+    /// "café" does not find "cafe"
+    pub diacritic_sensitive: bool,
+    /// Only match whole words, so that "graph" does not find "graphs"
+    pub whole_word: bool,
+    /// Match the stems of whole words, as `text::Tokenizer::stemming` splits them, so that
+    /// "running" finds "runs" and "ran". Stemmed matching ignores case and accents, and
+    /// terms that are only stop words match every thought.
+    pub stem: bool,
+    /// The stop words left out of stemmed matching, written in lowercase without
+    /// accents, instead of `text::STOP_WORDS`
+    pub stop_words: Option<HashSet<String>>,
+}
+
+impl SearchOptions {
+    /// Text as it is compared to the terms
+    pub(crate) fn normalize(&self, text: &str) -> String {
+        if self.stem {
+            return text::stemmed_tokens(text, self.stop_words.as_ref()).join(" ");
+        }
+        match (self.case_sensitive, self.diacritic_sensitive) {
+            (true, true) => text::normalize(text).into_owned(),
+            (false, true) => text::fold(text, false),
+            (true, false) => text::strip_diacritics(text),
+            (false, false) => text::fold(text, true),
+        }
+    }
+
+    /// Whether text, already normalized, contains a normalized term
+    pub(crate) fn matches(&self, text: &str, term: &str) -> bool {
+        if !self.whole_word && !self.stem {
+            return text.contains(term);
+        }
+        text.match_indices(term).any(|(start, _)| is_whole_word(text, start, term.len()))
+    }
+
+    /// How many times text, not yet normalized, contains a normalized term
+    pub(crate) fn occurrences(&self, text: &str, term: &str) -> usize {
+        self.count(&self.normalize(text), term)
+    }
+
+    /// How many times text, already normalized, contains a normalized term
+    pub(crate) fn count(&self, text: &str, term: &str) -> usize {
+        match (term.is_empty(), self.whole_word || self.stem) {
+            (true, _) => 1,
+            (false, false) => text.matches(term).count(),
+            (false, true) => text.match_indices(term).filter(|(start, _)| is_whole_word(text, *start, term.len())).count(),
+        }
+    }
+}
+
+/// Whether the `len` bytes of text at `start` are neither preceded nor followed by a
+/// letter or digit
+fn is_whole_word(text: &str, start: usize, len: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[start + len..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
+
+/// A term of a `TitleContains` or `ContentContains` query, normalized once and matched
+/// as `search` matches terms by default
+pub(crate) struct TextTerm(String);
+
+impl TextTerm {
+    pub(crate) fn new(term: &str) -> Self {
+        Self(SearchOptions::default().normalize(term))
+    }
+
+    /// Whether text, which may be missing, contains the term
+    pub(crate) fn found_in(&self, text: Option<&str>) -> bool {
+        let options = SearchOptions::default();
+        text.is_some_and(|text| options.matches(&options.normalize(text), &self.0))
+    }
+
+    /// How many times text, which may be missing, contains the term
+    pub(crate) fn occurrences(&self, text: Option<&str>) -> usize {
+        text.map_or(0, |text| SearchOptions::default().occurrences(text, &self.0))
+    }
+
+    /// Whether the notes of any of a thought's references contain the term
+    pub(crate) fn found_in_notes(&self, thought: &Thought) -> bool {
+        thought.references.iter().any(|reference| self.found_in(Some(&reference.notes)))
+    }
+}
+
+/// Compile the pattern of a `TitleRegex` or `ContentRegex` query
+pub(crate) fn compile_pattern(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| ThoughtGraphError::InvalidPattern(format!("{}: {}", pattern, e)))
+}
+
+/// Whether `text` matches the glob of a `TagGlob` or `IdGlob` query, in which `*` stands
+/// for any run of characters, possibly empty
+pub(crate) fn glob_matches(glob: &str, text: &str) -> bool {
+    let mut parts = glob.split('*');
+    let Some(mut rest) = text.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(start) => rest = &rest[start + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+impl Query {
+    /// A query for the thoughts whose title matches a regular expression, checking that
+    /// the pattern compiles.
+    ///
+    /// # Returns
+    ///
+    /// The query, or `InvalidPattern` if the pattern does not compile
+    pub fn title_regex(pattern: &str) -> Result<Query> {
+        compile_pattern(pattern)?;
+        Ok(Query::TitleRegex(pattern.to_string()))
+    }
+
+    /// A query for the thoughts whose contents match a regular expression, checking that
+    /// the pattern compiles. Matching is case-sensitive unless the pattern starts with
+    /// `(?i)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The regular expression, in the syntax of the `regex` crate
+    ///
+    /// # Returns
+    ///
+    /// The query, or `InvalidPattern` if the pattern does not compile
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Query, ThoughtGraph, ThoughtGraphError, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, "Fix PROJ-123 first".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let tickets = Query::content_regex(r"[A-Z]+-\d+").unwrap();
+    /// assert_eq!(graph.query(&tickets).len(), 1);
+    /// assert!(matches!(Query::content_regex("(unclosed"), Err(ThoughtGraphError::InvalidPattern(_))));
+    /// ```
+    pub fn content_regex(pattern: &str) -> Result<Query> {
+        compile_pattern(pattern)?;
+        Ok(Query::ContentRegex(pattern.to_string()))
+    }
+}
+
+/// Whether a thought's title or content contains every one of the normalized terms
+fn contains_all(thought: &Thought, terms: &[String], options: &SearchOptions) -> bool {
+    let title = options.normalize(thought.title.as_deref().unwrap_or_default());
+    let contents = options.normalize(&thought.contents);
+    terms.iter().all(|term| options.matches(&title, term) || options.matches(&contents, term))
+}
+
+impl ThoughtGraph {
+    /// Find the thoughts whose title or content contains all of the given terms.
+    ///
+    /// Matching ignores case and accents and looks for each term as a substring, so "graph"
+    /// also finds "graphs" and "thoughtgraph", and "cafe" finds "Café".
+    ///
+    /// # Arguments
+    ///
+    /// * `terms` - The terms that must all appear in a thought
+    ///
+    /// # Returns
+    ///
+    /// The matching thoughts, ordered by ID. The result is empty if no terms are given.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), Some("Rust".to_string()), "Ownership rules".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(ThoughtID::new("b".to_string()), None, "Borrowing rules".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let found = graph.search(&["rust", "RULES"]);
+    /// assert_eq!(found.len(), 1);
+    /// assert_eq!(found[0].0.as_str(), "a");
+    /// ```
+    pub fn search<S: AsRef<str>>(&self, terms: &[S]) -> Vec<(&ThoughtID, &Thought)> {
+        self.search_with(terms, &SearchOptions::default())
+    }
+
+    /// Find the thoughts whose title or content contains all of the given terms, matched
+    /// as the options say.
+    ///
+    /// # Arguments
+    ///
+    /// * `terms` - The terms that must all appear in a thought
+    /// * `options` - Whether to match case and accents, and whether to match only whole words
+    ///
+    /// # Returns
+    ///
+    /// The matching thoughts, ordered by ID. The result is empty if no terms are given.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::search::SearchOptions;
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, "Graphs of Rust crates".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let words = SearchOptions { whole_word: true, ..SearchOptions::default() };
+    /// assert!(graph.search_with(&["graph"], &words).is_empty());
+    /// assert_eq!(graph.search_with(&["graphs"], &words).len(), 1);
+    /// let case = SearchOptions { case_sensitive: true, ..SearchOptions::default() };
+    /// assert!(graph.search_with(&["rust"], &case).is_empty());
+    /// ```
+    pub fn search_with<S: AsRef<str>>(&self, terms: &[S], options: &SearchOptions) -> Vec<(&ThoughtID, &Thought)> {
+        let terms: Vec<String> = terms.iter().map(|term| options.normalize(term.as_ref())).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut found = parallel::filter_map(&self.thoughts, |id, thought| {
+            contains_all(thought, &terms, options).then_some((id, thought))
+        });
+        found.sort_by(|a, b| a.0.cmp(b.0));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parallel::PARALLEL_THRESHOLD;
+
+    #[test]
+    fn test_terms_may_be_split_between_title_and_content() {
+        let mut graph = ThoughtGraph::new();
+        graph
+            .create_thought(ThoughtID::new("a".to_string()), Some("Graph theory".to_string()), "Cycles".to_string(), vec![], vec![])
+            .unwrap();
+
+        assert_eq!(graph.search(&["graph", "cycles"]).len(), 1);
+        assert!(graph.search(&["graph", "trees"]).is_empty());
+        assert!(graph.search::<&str>(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_search_options() {
+        let mut graph = ThoughtGraph::new();
+        graph
+            .create_thought(ThoughtID::new("a".to_string()), Some("Über Graphs".to_string()), "sub-graph (graph)".to_string(), vec![], vec![])
+            .unwrap();
+        graph.create_thought(ThoughtID::new("b".to_string()), None, "thoughtgraph".to_string(), vec![], vec![]).unwrap();
+        let ids = |options: &SearchOptions, terms: &[&str]| -> Vec<String> {
+            graph.search_with(terms, options).into_iter().map(|(id, _)| id.id.to_string()).collect()
+        };
+
+        let words = SearchOptions { whole_word: true, ..SearchOptions::default() };
+        assert_eq!(ids(&SearchOptions::default(), &["graph"]), vec!["a", "b"]);
+        assert_eq!(ids(&words, &["graph"]), vec!["a"]);
+        assert_eq!(ids(&words, &["über", "graphs"]), vec!["a"]);
+        assert!(ids(&words, &["thought"]).is_empty());
+
+        let case = SearchOptions { case_sensitive: true, ..SearchOptions::default() };
+        assert_eq!(ids(&case, &["Graphs"]), vec!["a"]);
+        assert!(ids(&case, &["über"]).is_empty());
+        assert!(ids(&SearchOptions { case_sensitive: true, whole_word: true, ..SearchOptions::default() }, &["Graph"]).is_empty());
+
+        assert_eq!(ids(&SearchOptions::default(), &["uber"]), vec!["a"]);
+        assert_eq!(ids(&case, &["Uber"]), vec!["a"]);
+        let accents = SearchOptions { diacritic_sensitive: true, ..SearchOptions::default() };
+        assert!(ids(&accents, &["uber"]).is_empty());
+        assert_eq!(ids(&accents, &["u\u{308}ber"]), vec!["a"]);
+    }
+
+    #[test]
+    fn test_stemmed_search() {
+        let mut graph = ThoughtGraph::new();
+        for (id, contents) in [("a", "She ran every morning"), ("b", "Running a marathon"), ("c", "The runway was closed")] {
+            graph.create_thought(ThoughtID::new(id.to_string()), None, contents.to_string(), vec![], vec![]).unwrap();
+        }
+        let stemmed = SearchOptions { stem: true, ..SearchOptions::default() };
+        let ids = |terms: &[&str]| -> Vec<String> {
+            graph.search_with(terms, &stemmed).into_iter().map(|(id, _)| id.id.to_string()).collect()
+        };
+
+        assert_eq!(ids(&["runs"]), vec!["a", "b"]);
+        assert_eq!(ids(&["the marathons"]), vec!["b"]);
+        assert_eq!(ids(&["closing", "runway"]), vec!["c"]);
+        assert_eq!(graph.search(&["run"]).len(), 2);
+
+        // Stop words of one's own replace the default ones
+        let own = SearchOptions { stop_words: Some(HashSet::from(["runway".to_string()])), ..stemmed };
+        let ids = |terms: &[&str]| -> Vec<String> {
+            graph.search_with(terms, &own).into_iter().map(|(id, _)| id.id.to_string()).collect()
+        };
+        assert_eq!(ids(&["runway"]), vec!["a", "b", "c"]);
+        assert!(ids(&["the marathons"]).is_empty());
+    }
+
+    #[test]
+    fn test_large_graphs() {
+        let mut graph = ThoughtGraph::new();
+        for i in 0..PARALLEL_THRESHOLD * 2 {
+            let contents = if i % 3 == 0 { "fizz" } else { "buzz" };
+            graph
+                .create_thought(ThoughtID::new(format!("{:05}", i)), None, contents.to_string(), vec![], vec![])
+                .unwrap();
+        }
+
+        let found = graph.search(&["fizz"]);
+        assert_eq!(found.len(), (PARALLEL_THRESHOLD * 2).div_ceil(3));
+        assert!(found.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+}
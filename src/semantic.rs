@@ -0,0 +1,393 @@
+//! Semantic search over thought contents
+//!
+//! Semantic search compares thoughts by meaning rather than by shared keywords. Texts are
+//! turned into vectors by an `Embedder`, and a `VectorIndex` keeps one vector per thought
+//! so that a query only needs a single embedding plus a similarity scan.
+//!
+//! Embedders are pluggable. `HashingEmbedder` runs locally without any model and captures
+//! word and sub-word overlap; with the `llm` feature, `llm::OpenAiEmbedder` uses a real
+//! embedding model through an OpenAI-compatible API, which also relates notes that share
+//! no words at all.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use crate::parallel;
+use crate::progress::{Progress, ProgressFn};
+use crate::text::tokenize;
+use crate::{Result, Thought, ThoughtGraph, ThoughtID};
+
+/// Number of thoughts embedded at a time by `VectorIndex::update`, between reports of
+/// progress
+const EMBED_BATCH_SIZE: usize = 256;
+
+/// Turns texts into embedding vectors
+pub trait Embedder {
+    /// A name identifying the embedder and its configuration.
+    ///
+    /// Vectors produced by different embedders are not comparable, so indexes record
+    /// this name and are rebuilt when it changes.
+    fn name(&self) -> String;
+
+    /// Embed a batch of texts, returning one vector per text
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// A local embedder based on feature hashing of words and character trigrams.
+///
+/// It needs no model or network access. Trigrams let related word forms such as
+/// "recover" and "recovery" contribute to the similarity of two texts.
+#[derive(Clone, Debug)]
+pub struct HashingEmbedder {
+    /// Number of dimensions of the produced vectors
+    pub dimensions: usize,
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        Self { dimensions: 512 }
+    }
+}
+
+impl HashingEmbedder {
+    /// Add a feature to a vector, using the hash to pick both a slot and a sign
+    fn add_feature(&self, vector: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a(feature.as_bytes());
+        let slot = (hash % self.dimensions as u64) as usize;
+        let sign = if hash & (1 << 63) == 0 { 1.0 } else { -1.0 };
+        vector[slot] += sign * weight;
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn name(&self) -> String {
+        format!("hashing-{}", self.dimensions)
+    }
+
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        Ok(parallel::map(texts, false, |text| {
+            let mut vector = vec![0.0; self.dimensions];
+            for token in tokenize(text) {
+                self.add_feature(&mut vector, &token, 1.0);
+
+                let padded: Vec<char> = format!("^{}$", token).chars().collect();
+                for trigram in padded.windows(3) {
+                    let trigram: String = trigram.iter().collect();
+                    self.add_feature(&mut vector, &trigram, 0.5);
+                }
+            }
+            normalize(&mut vector);
+            vector
+        }))
+    }
+}
+
+/// 64-bit FNV-1a hash, used because it is stable across platforms and Rust versions
+/// (vectors are persisted, so the same feature must always land in the same slot)
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Scale a vector to unit length (zero vectors are left unchanged)
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Cosine similarity of two vectors
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// The text of a thought that gets embedded
+fn embedding_text(thought: &Thought) -> String {
+    match &thought.title {
+        Some(title) => format!("{}\n{}", title, thought.contents),
+        None => thought.contents.clone(),
+    }
+}
+
+/// The stored embedding of one thought
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    /// Modification time of the thought when it was embedded
+    updated_at: DateTime<Utc>,
+    vector: Vec<f32>,
+}
+
+/// An index of embedding vectors for the thoughts of a graph.
+///
+/// The index is kept separately from the graph because it can always be recomputed.
+/// `update` only re-embeds thoughts that were added or modified since the last update,
+/// which keeps API-based embedders cheap to use.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct VectorIndex {
+    /// Name of the embedder that produced the vectors
+    embedder: String,
+    entries: HashMap<ThoughtID, IndexEntry>,
+}
+
+impl VectorIndex {
+    /// Creates an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of thoughts in the index
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the index contains no thoughts
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the stored vector of a thought
+    pub fn vector(&self, id: &ThoughtID) -> Option<&[f32]> {
+        self.entries.get(id).map(|entry| entry.vector.as_slice())
+    }
+
+    /// Bring the index up to date with a graph.
+    ///
+    /// Vectors of deleted thoughts are dropped, and new or modified thoughts are embedded.
+    /// If the index was built with a different embedder, everything is re-embedded.
+    ///
+    /// # Returns
+    ///
+    /// The number of thoughts that were embedded
+    pub fn update(&mut self, graph: &ThoughtGraph, embedder: &dyn Embedder) -> Result<usize> {
+        self.update_with_progress(graph, embedder, &mut |_| {})
+    }
+
+    /// Bring the index up to date like `update`, reporting the number of thoughts
+    /// embedded out of those that need it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use thoughtgraph::semantic::{HashingEmbedder, VectorIndex};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, "Sourdough".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let mut reports = Vec::new();
+    /// VectorIndex::new().update_with_progress(&graph, &HashingEmbedder::default(), &mut |p| reports.push(p.done)).unwrap();
+    /// assert_eq!(reports, vec![0, 1]);
+    /// ```
+    pub fn update_with_progress(&mut self, graph: &ThoughtGraph, embedder: &dyn Embedder, progress: ProgressFn) -> Result<usize> {
+        let name = embedder.name();
+        if self.embedder != name {
+            self.entries.clear();
+            self.embedder = name;
+        }
+
+        self.entries.retain(|id, _| graph.thoughts.contains_key(id));
+
+        let mut stale: Vec<(&ThoughtID, &Thought)> = graph
+            .thoughts
+            .iter()
+            .filter(|(id, thought)| {
+                self.entries
+                    .get(*id)
+                    .is_none_or(|entry| entry.updated_at != thought.updated_at)
+            })
+            .collect();
+        stale.sort_by(|a, b| a.0.cmp(b.0));
+
+        let total = stale.len() as u64;
+        let mut done = 0;
+        progress(Progress::of(0, total));
+        for batch in stale.chunks(EMBED_BATCH_SIZE) {
+            let texts: Vec<String> = batch.iter().map(|(_, thought)| embedding_text(thought)).collect();
+            let text_refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+            let vectors = embedder.embed(&text_refs)?;
+
+            for ((id, thought), vector) in batch.iter().zip(vectors) {
+                self.entries.insert(
+                    (*id).clone(),
+                    IndexEntry { updated_at: thought.updated_at, vector },
+                );
+            }
+            done += batch.len() as u64;
+            progress(Progress::of(done, total));
+        }
+
+        Ok(stale.len())
+    }
+
+    /// Find the thoughts most similar to a query text.
+    ///
+    /// # Arguments
+    ///
+    /// * `embedder` - The embedder used to build the index
+    /// * `query` - Free text describing what to look for
+    /// * `limit` - The maximum number of results
+    ///
+    /// # Returns
+    ///
+    /// Thought IDs with their cosine similarity to the query, most similar first
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use thoughtgraph::semantic::{HashingEmbedder, VectorIndex};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, "Recovering from failures".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(ThoughtID::new("b".to_string()), None, "Baking sourdough bread".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let embedder = HashingEmbedder::default();
+    /// let mut index = VectorIndex::new();
+    /// index.update(&graph, &embedder).unwrap();
+    ///
+    /// let results = index.search(&embedder, "failure recovery", 1).unwrap();
+    /// assert_eq!(results[0].0.as_str(), "a");
+    /// ```
+    pub fn search(&self, embedder: &dyn Embedder, query: &str, limit: usize) -> Result<Vec<(ThoughtID, f32)>> {
+        let query_vector = match embedder.embed(&[query])?.into_iter().next() {
+            Some(vector) => vector,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut results: Vec<(ThoughtID, f32)> = parallel::filter_map(&self.entries, |id, entry| {
+            Some((id.clone(), cosine_similarity(&query_vector, &entry.vector)))
+        });
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// Save the index to a file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, bincode::serialize(self)?)?;
+        Ok(())
+    }
+
+    /// Load an index from a file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(bincode::deserialize(&fs::read(path)?)?)
+    }
+}
+
+impl ThoughtGraph {
+    /// Search the graph semantically, embedding every thought on the fly.
+    ///
+    /// This is convenient for one-off searches; use a `VectorIndex` to avoid re-embedding
+    /// the whole graph for every query.
+    pub fn semantic_search(&self, embedder: &dyn Embedder, query: &str, limit: usize) -> Result<Vec<(ThoughtID, f32)>> {
+        let mut index = VectorIndex::new();
+        index.update(self, embedder)?;
+        index.search(embedder, query, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Command;
+    use std::cell::Cell;
+
+    /// Wraps an embedder and counts the texts it embeds
+    struct Counting {
+        inner: HashingEmbedder,
+        embedded: Cell<usize>,
+    }
+
+    impl Embedder for Counting {
+        fn name(&self) -> String {
+            self.inner.name()
+        }
+
+        fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            self.embedded.set(self.embedded.get() + texts.len());
+            self.inner.embed(texts)
+        }
+    }
+
+    fn add(graph: &mut ThoughtGraph, id: &str, contents: &str) {
+        graph
+            .create_thought(ThoughtID::new(id.to_string()), None, contents.to_string(), vec![], vec![])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_similar_texts_rank_first() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "retries", "Retrying failed requests with exponential backoff");
+        add(&mut graph, "garden", "Planting tomatoes in the spring garden");
+        add(&mut graph, "bread", "Sourdough starter feeding schedule");
+
+        let results = graph
+            .semantic_search(&HashingEmbedder::default(), "retry a failing request", 3)
+            .unwrap();
+        assert_eq!(results[0].0.as_str(), "retries");
+        assert!(results[0].1 > results[1].1);
+    }
+
+    #[test]
+    fn test_update_only_embeds_changes() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "a", "alpha");
+        add(&mut graph, "b", "beta");
+
+        let embedder = Counting { inner: HashingEmbedder::default(), embedded: Cell::new(0) };
+        let mut index = VectorIndex::new();
+        assert_eq!(index.update(&graph, &embedder).unwrap(), 2);
+        assert_eq!(index.update(&graph, &embedder).unwrap(), 0);
+
+        let mut changed = graph.get_thought(&ThoughtID::new("a".to_string())).unwrap().clone();
+        changed.update_content("alpha prime".to_string());
+        graph.command(&Command::PutThought { id: ThoughtID::new("a".to_string()), thought: changed });
+        graph.command(&Command::DeleteThought { id: ThoughtID::new("b".to_string()) });
+
+        assert_eq!(index.update(&graph, &embedder).unwrap(), 1);
+        assert_eq!(index.len(), 1);
+        assert_eq!(embedder.embedded.get(), 3);
+    }
+
+    #[test]
+    fn test_changing_embedder_rebuilds() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "a", "alpha");
+
+        let mut index = VectorIndex::new();
+        index.update(&graph, &HashingEmbedder::default()).unwrap();
+        assert_eq!(index.update(&graph, &HashingEmbedder { dimensions: 64 }).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "a", "alpha");
+        let embedder = HashingEmbedder::default();
+        let mut index = VectorIndex::new();
+        index.update(&graph, &embedder).unwrap();
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        index.save_to_file(file.path()).unwrap();
+        let mut loaded = VectorIndex::load_from_file(file.path()).unwrap();
+        assert_eq!(loaded.update(&graph, &embedder).unwrap(), 0);
+    }
+}
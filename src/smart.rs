@@ -0,0 +1,159 @@
+//! Smart tags
+//!
+//! A smart tag is a tag with a query: instead of being assigned to thoughts, it stands
+//! for the thoughts its query finds, worked out whenever it is queried. A smart tag
+//! `rust-drafts` with the query `tag:rust AND tag:draft` stays up to date as thoughts are
+//! tagged and untagged, without anyone retagging them. Smart tags may mention other smart
+//! tags, but not themselves, directly or through others.
+//!
+//! Smart tags apply wherever tags are queried: `Query::Tag`, saved queries and the tags
+//! of `thoughts list`. Assigning a smart tag to a thought has no effect.
+
+use crate::{Query, TagID, ThoughtGraph};
+
+impl ThoughtGraph {
+    /// The query a smart tag stands for, with the smart tags it mentions replaced by
+    /// their own queries in turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag to look up
+    ///
+    /// # Returns
+    ///
+    /// The query, or `None` if the tag does not exist or is not a smart tag
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Command, Query, Tag, TagID, ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let rust = TagID::new("rust".to_string());
+    /// graph.create_tag(rust.clone(), "Rust".to_string()).unwrap();
+    /// let smart = Tag { query: Some(Query::Tag(rust.clone())), ..Tag::new("Everything Rust".to_string()) };
+    /// graph.try_command(&Command::PutTag { id: TagID::new("all-rust".to_string()), tag: smart }).unwrap();
+    /// graph.create_thought(ThoughtID::new("ownership".to_string()), None, String::new(), vec![rust.clone()], vec![]).unwrap();
+    ///
+    /// assert_eq!(graph.smart_tag_query(&TagID::new("all-rust".to_string())), Some(Query::Tag(rust)));
+    /// assert_eq!(graph.query(&Query::Tag(TagID::new("all-rust".to_string()))).len(), 1);
+    /// ```
+    pub fn smart_tag_query(&self, tag: &TagID) -> Option<Query> {
+        let (tag, query) = self.tags.get_key_value(tag).and_then(|(id, tag)| Some((id, tag.query.as_ref()?)))?;
+        Some(self.expand_smart_tags(query, &mut vec![tag]))
+    }
+
+    /// The smart tags of the graph and their queries, ordered by ID
+    pub fn smart_tags(&self) -> Vec<(&TagID, &Query)> {
+        let mut tags: Vec<(&TagID, &Query)> =
+            self.tags.iter().filter_map(|(id, tag)| Some((id, tag.query.as_ref()?))).collect();
+        tags.sort_by(|a, b| a.0.cmp(b.0));
+        tags
+    }
+
+    /// Replace the smart tags in a query by their queries. A smart tag that is already
+    /// being expanded, which only happens in graphs that were not validated, matches
+    /// nothing.
+    fn expand_smart_tags<'a>(&'a self, query: &Query, expanding: &mut Vec<&'a TagID>) -> Query {
+        let expand_all = |queries: &[Box<Query>], expanding: &mut Vec<&'a TagID>| {
+            queries.iter().map(|query| Box::new(self.expand_smart_tags(query, expanding))).collect()
+        };
+        match query {
+            Query::Tag(tag) => match self.tags.get_key_value(tag) {
+                Some((tag, crate::Tag { query: Some(query), .. })) => {
+                    if expanding.contains(&tag) {
+                        return Query::Or(Vec::new());
+                    }
+                    expanding.push(tag);
+                    let expanded = self.expand_smart_tags(query, expanding);
+                    expanding.pop();
+                    expanded
+                },
+                _ => Query::Tag(tag.clone()),
+            },
+            Query::And(queries) => Query::And(expand_all(queries, expanding)),
+            Query::Or(queries) => Query::Or(expand_all(queries, expanding)),
+            Query::Not(query) => Query::Not(Box::new(self.expand_smart_tags(query, expanding))),
+            query => query.clone(),
+        }
+    }
+
+    /// The smart tags a query mentions through which it leads back to `tag`, ending with
+    /// `tag`, if the query were the query of `tag`
+    pub(crate) fn smart_tag_cycle<'a>(&'a self, tag: &'a TagID, query: &'a Query) -> Option<Vec<&'a TagID>> {
+        let mut path = vec![tag];
+        self.find_smart_tag_cycle(query, &mut path).then_some(path)
+    }
+
+    fn find_smart_tag_cycle<'a>(&'a self, query: &'a Query, path: &mut Vec<&'a TagID>) -> bool {
+        match query {
+            Query::Tag(tag) if tag == path[0] => {
+                path.push(tag);
+                true
+            },
+            Query::Tag(tag) => match self.tags.get(tag).and_then(|tag| tag.query.as_ref()) {
+                // Cycles not through the first tag were rejected when they were made
+                Some(query) if !path.contains(&tag) => {
+                    path.push(tag);
+                    let found = self.find_smart_tag_cycle(query, path);
+                    if !found {
+                        path.pop();
+                    }
+                    found
+                },
+                _ => false,
+            },
+            Query::And(queries) | Query::Or(queries) => queries.iter().any(|query| self.find_smart_tag_cycle(query, path)),
+            Query::Not(query) => self.find_smart_tag_cycle(query, path),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Tag, ThoughtGraphError, ThoughtID};
+
+    fn smart(graph: &mut ThoughtGraph, id: &str, query: &str) -> crate::Result<()> {
+        let tag = Tag { query: Some(Query::parse(query)?), ..Tag::new(String::new()) };
+        graph.try_command(&Command::PutTag { id: TagID::new(id.to_string()), tag }).map(|_| ())
+    }
+
+    #[test]
+    fn test_smart_tags() {
+        let mut graph = ThoughtGraph::new();
+        graph.enable_query_cache(16);
+        for tag in ["rust", "draft"] {
+            graph.create_tag(TagID::new(tag.to_string()), String::new()).unwrap();
+        }
+        for (id, tags) in [("a", vec!["rust"]), ("b", vec!["rust", "draft"]), ("c", vec!["draft"])] {
+            let tags = tags.into_iter().map(|tag| TagID::new(tag.to_string())).collect();
+            graph.create_thought(ThoughtID::new(id.to_string()), None, String::new(), tags, vec![]).unwrap();
+        }
+        smart(&mut graph, "rust-drafts", "tag:rust AND tag:draft").unwrap();
+        smart(&mut graph, "finished-rust", "tag:rust AND NOT tag:rust-drafts").unwrap();
+        let ids = |graph: &ThoughtGraph, tag: &str| {
+            let mut ids: Vec<String> = graph.query(&Query::Tag(TagID::new(tag.to_string()))).into_iter().map(|id| id.id.to_string()).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&graph, "rust-drafts"), vec!["b"]);
+        assert_eq!(ids(&graph, "finished-rust"), vec!["a"]);
+        assert!(graph.matches(&ThoughtID::new("a".to_string()), &Query::parse("tag:finished-rust").unwrap()).unwrap());
+        assert_eq!(graph.smart_tags().len(), 2);
+
+        // The collection follows the thoughts' tags
+        graph.command(&Command::RemoveTag { id: ThoughtID::new("b".to_string()), tag: TagID::new("draft".to_string()) });
+        assert!(ids(&graph, "rust-drafts").is_empty());
+        assert_eq!(ids(&graph, "finished-rust"), vec!["a", "b"]);
+
+        // A smart tag may not lead back to itself
+        match smart(&mut graph, "rust-drafts", "tag:draft OR tag:finished-rust") {
+            Err(ThoughtGraphError::SmartTagCycle(cycle)) => assert_eq!(cycle, "rust-drafts -> finished-rust -> rust-drafts"),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        assert!(matches!(smart(&mut graph, "loop", "tag:loop"), Err(ThoughtGraphError::SmartTagCycle(_))));
+        assert_eq!(ids(&graph, "finished-rust"), vec!["a", "b"]);
+    }
+}
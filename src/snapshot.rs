@@ -0,0 +1,236 @@
+//! Named snapshots
+//!
+//! A snapshot is a copy of a graph taken under a name, with when and why it was taken,
+//! so that the graph can be put back as it was before a risky bulk operation. Unlike the
+//! journal, which keeps only the most recent commands, a snapshot is kept until it is
+//! removed. The snapshots of a graph file are kept in a directory next to it, named after
+//! it with `SNAPSHOTS_SUFFIX`: `<name>.bin` holds the graph and `<name>.json` what is
+//! known about it.
+//!
+//! With the `encryption` feature, the snapshots of an encrypted graph are encrypted with
+//! the same key (see `Snapshots::encrypted_with`).
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Result, ThoughtGraph, ThoughtGraphError};
+
+/// Suffix of the snapshot directory of a graph file, after the file's name without its
+/// extension
+pub const SNAPSHOTS_SUFFIX: &str = ".snapshots";
+
+/// What is known about a snapshot
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    /// The name the snapshot was taken under
+    pub name: String,
+    /// When it was taken
+    pub taken_at: DateTime<Utc>,
+    /// Why it was taken, if given
+    pub reason: Option<String>,
+    /// Number of thoughts in the graph
+    pub thoughts: usize,
+    /// Number of tags in the graph
+    pub tags: usize,
+}
+
+/// The snapshots kept in a directory
+#[derive(Clone, Debug)]
+pub struct Snapshots {
+    dir: PathBuf,
+    #[cfg(feature = "encryption")]
+    key: Option<crate::encryption::GraphKey>,
+}
+
+impl Snapshots {
+    /// The snapshots in `dir`, which is created when the first one is taken
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            #[cfg(feature = "encryption")]
+            key: None,
+        }
+    }
+
+    /// Encrypt the snapshots taken from now on with `key`, and decrypt encrypted
+    /// snapshots with it. Snapshots taken before the graph was encrypted still load.
+    #[cfg(feature = "encryption")]
+    pub fn encrypted_with(self, key: crate::encryption::GraphKey) -> Self {
+        Self { key: Some(key), ..self }
+    }
+
+    /// The snapshots of the graph file at `path`, e.g. `thoughts.snapshots` for
+    /// `thoughts.bin`
+    pub fn of_graph(path: &Path) -> Self {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        Self::new(path.with_file_name(format!("{}{}", stem, SNAPSHOTS_SUFFIX)))
+    }
+
+    /// The directory of the snapshots
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path of a file of the snapshot `name`, checking that the name is valid
+    fn path(&self, name: &str, extension: &str) -> Result<PathBuf> {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Err(ThoughtGraphError::ParseError(format!(
+                "'{}' is not a valid snapshot name (use letters, digits, '-' and '_')",
+                name
+            )));
+        }
+        Ok(self.dir.join(format!("{}.{}", name, extension)))
+    }
+
+    /// Save the graph of a snapshot, encrypted if the snapshots are
+    fn save_graph(&self, graph: &ThoughtGraph, path: &Path) -> Result<()> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            return graph.save_encrypted_with_progress(path, key, &mut |_| {});
+        }
+        graph.save_to_file(path)
+    }
+
+    /// Take a snapshot of a graph, replacing any snapshot with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the snapshot, made of letters, digits, `-` and `_`
+    /// * `graph` - The graph to copy
+    /// * `reason` - Why the snapshot is taken
+    /// * `at` - When the snapshot is taken
+    ///
+    /// # Returns
+    ///
+    /// What is known about the snapshot, or `ParseError` if the name is not valid
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::snapshot::Snapshots;
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use chrono::Utc;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let snapshots = Snapshots::of_graph(&dir.path().join("thoughts.bin"));
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), None, String::new(), vec![], vec![]).unwrap();
+    ///
+    /// snapshots.create("before-cleanup", &graph, Some("Deleting old notes".to_string()), Utc::now()).unwrap();
+    /// assert_eq!(snapshots.list().unwrap()[0].thoughts, 1);
+    /// assert_eq!(snapshots.load("before-cleanup").unwrap().thoughts, graph.thoughts);
+    /// ```
+    pub fn create(&self, name: &str, graph: &ThoughtGraph, reason: Option<String>, at: DateTime<Utc>) -> Result<SnapshotInfo> {
+        let graph_path = self.path(name, "bin")?;
+        fs::create_dir_all(&self.dir)?;
+        self.save_graph(graph, &graph_path)?;
+        let info = SnapshotInfo {
+            name: name.to_string(),
+            taken_at: at,
+            reason,
+            thoughts: graph.thoughts.len(),
+            tags: graph.tags.len(),
+        };
+        let json = serde_json::to_string_pretty(&info).map_err(|e| ThoughtGraphError::IoError(e.into()))?;
+        fs::write(self.path(name, "json")?, json)?;
+        Ok(info)
+    }
+
+    /// List the snapshots, oldest first. Files in the directory that do not describe a
+    /// snapshot are ignored.
+    pub fn list(&self) -> Result<Vec<SnapshotInfo>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") || !path.with_extension("bin").exists() {
+                continue;
+            }
+            if let Ok(info) = serde_json::from_str::<SnapshotInfo>(&fs::read_to_string(&path)?) {
+                snapshots.push(info);
+            }
+        }
+        snapshots.sort_by(|a, b| a.taken_at.cmp(&b.taken_at).then_with(|| a.name.cmp(&b.name)));
+        Ok(snapshots)
+    }
+
+    /// Load the graph of a snapshot.
+    ///
+    /// # Returns
+    ///
+    /// The graph, or `SnapshotNotFound` if there is no snapshot with this name
+    pub fn load(&self, name: &str) -> Result<ThoughtGraph> {
+        let path = self.path(name, "bin")?;
+        match path.exists() {
+            #[cfg(feature = "encryption")]
+            true if crate::encryption::file_is_encrypted(&path)? => match &self.key {
+                Some(key) => ThoughtGraph::load_encrypted(path, key),
+                None => Err(ThoughtGraphError::Encrypted(format!("snapshot {}", name))),
+            },
+            true => ThoughtGraph::load_from_file(path),
+            false => Err(ThoughtGraphError::SnapshotNotFound(name.to_string())),
+        }
+    }
+
+    /// Remove a snapshot.
+    ///
+    /// # Returns
+    ///
+    /// `SnapshotNotFound` if there is no snapshot with this name
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let path = self.path(name, "bin")?;
+        if !path.exists() {
+            return Err(ThoughtGraphError::SnapshotNotFound(name.to_string()));
+        }
+        fs::remove_file(self.path(name, "json")?).or_else(|e| match e.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        })?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThoughtID;
+    use chrono::Duration;
+
+    #[test]
+    fn test_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshots = Snapshots::of_graph(&dir.path().join("notes.bin"));
+        assert_eq!(snapshots.dir(), dir.path().join("notes.snapshots"));
+        assert!(snapshots.list().unwrap().is_empty());
+
+        let mut graph = ThoughtGraph::new();
+        let now = Utc::now();
+        snapshots.create("empty", &graph, None, now).unwrap();
+        graph.create_thought(ThoughtID::new("a".to_string()), None, String::new(), vec![], vec![]).unwrap();
+        snapshots.create("one", &graph, Some("Before the import".to_string()), now - Duration::hours(1)).unwrap();
+        fs::write(snapshots.dir().join("notes.txt"), "not a snapshot").unwrap();
+
+        let list = snapshots.list().unwrap();
+        let names: Vec<&str> = list.iter().map(|info| info.name.as_str()).collect();
+        assert_eq!(names, vec!["one", "empty"]);
+        assert_eq!(list[0].reason.as_deref(), Some("Before the import"));
+        assert!(snapshots.load("empty").unwrap().thoughts.is_empty());
+
+        snapshots.create("empty", &graph, None, now).unwrap();
+        assert_eq!(snapshots.load("empty").unwrap().thoughts.len(), 1);
+        snapshots.remove("empty").unwrap();
+        assert_eq!(snapshots.list().unwrap().len(), 1);
+        assert!(matches!(snapshots.load("empty"), Err(ThoughtGraphError::SnapshotNotFound(_))));
+        assert!(matches!(snapshots.remove("empty"), Err(ThoughtGraphError::SnapshotNotFound(_))));
+        assert!(matches!(snapshots.create("../up", &graph, None, now), Err(ThoughtGraphError::ParseError(_))));
+    }
+}
@@ -0,0 +1,220 @@
+//! Deterministic ordering of thoughts and tags
+//!
+//! The graph stores thoughts and tags in hash maps, whose iteration order changes from
+//! run to run. Everything that shows or exports thoughts should use the orderings defined
+//! here, so that the same graph always produces the same output. `QueryOptions` adds a
+//! page to an ordering, for query results shown a screenful at a time.
+
+use std::cmp::Ordering;
+
+use crate::text;
+use crate::{Query, Tag, TagID, Thought, ThoughtGraph, ThoughtID};
+
+/// The order in which to list thoughts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// Alphabetically by ID
+    #[default]
+    Id,
+    /// Alphabetically by title (ignoring case and accents); untitled thoughts use their ID
+    Title,
+    /// Most recently created first
+    Created,
+    /// Most recently modified first
+    Updated,
+    /// Most referenced first, by the number of thoughts referencing each thought
+    Backlinks,
+}
+
+impl SortBy {
+    /// Compare two thoughts of a graph in this order.
+    ///
+    /// Thoughts that compare equal by the chosen key are ordered by ID, so the order is
+    /// always total.
+    pub fn compare(&self, graph: &ThoughtGraph, a: (&ThoughtID, &Thought), b: (&ThoughtID, &Thought)) -> Ordering {
+        let (a_id, a) = a;
+        let (b_id, b) = b;
+        let by_key = match self {
+            SortBy::Id => Ordering::Equal,
+            SortBy::Title => {
+                let a_title = text::fold(a.title.as_deref().unwrap_or(a_id.as_str()), true);
+                let b_title = text::fold(b.title.as_deref().unwrap_or(b_id.as_str()), true);
+                a_title.cmp(&b_title)
+            },
+            SortBy::Created => b.created_at.cmp(&a.created_at),
+            SortBy::Updated => b.updated_at.cmp(&a.updated_at),
+            SortBy::Backlinks => graph.backlink_count(b_id).cmp(&graph.backlink_count(a_id)),
+        };
+        by_key.then_with(|| a_id.cmp(b_id))
+    }
+
+    /// Sort a list of thoughts of a graph in this order
+    pub fn sort(&self, graph: &ThoughtGraph, thoughts: &mut [(&ThoughtID, &Thought)]) {
+        thoughts.sort_by(|a, b| self.compare(graph, *a, *b));
+    }
+}
+
+/// The order of query results, and which of them to return
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryOptions {
+    /// The order to return the thoughts in
+    pub sort: SortBy,
+    /// Return the thoughts in the opposite order, e.g. oldest first with `SortBy::Created`
+    pub reverse: bool,
+    /// The number of thoughts to skip from the start of the order
+    pub offset: usize,
+    /// The most thoughts to return, or all of them
+    pub limit: Option<usize>,
+}
+
+impl QueryOptions {
+    /// Sort thoughts of a graph, then keep the page of them these options select
+    pub fn apply<'a>(&self, graph: &ThoughtGraph, mut thoughts: Vec<(&'a ThoughtID, &'a Thought)>) -> Vec<(&'a ThoughtID, &'a Thought)> {
+        thoughts.sort_by(|a, b| self.sort.compare(graph, *a, *b));
+        self.page(thoughts)
+    }
+
+    /// Keep the page these options select of items that are already in order, such as
+    /// results ranked by relevance, ignoring `sort`
+    pub fn page<T>(&self, mut items: Vec<T>) -> Vec<T> {
+        if self.reverse {
+            items.reverse();
+        }
+        items.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect()
+    }
+}
+
+impl ThoughtGraph {
+    /// Iterate over all thoughts in a deterministic order.
+    ///
+    /// # Arguments
+    ///
+    /// * `sort` - The order to return the thoughts in
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use thoughtgraph::sort::SortBy;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_thought(ThoughtID::new("b".to_string()), Some("Apples".to_string()), String::new(), vec![], vec![]).unwrap();
+    /// graph.create_thought(ThoughtID::new("a".to_string()), Some("Bananas".to_string()), String::new(), vec![], vec![]).unwrap();
+    ///
+    /// let by_id: Vec<&str> = graph.iter_thoughts_sorted(SortBy::Id).map(|(id, _)| id.as_str()).collect();
+    /// assert_eq!(by_id, vec!["a", "b"]);
+    /// let by_title: Vec<&str> = graph.iter_thoughts_sorted(SortBy::Title).map(|(id, _)| id.as_str()).collect();
+    /// assert_eq!(by_title, vec!["b", "a"]);
+    /// ```
+    pub fn iter_thoughts_sorted(&self, sort: SortBy) -> impl Iterator<Item = (&ThoughtID, &Thought)> {
+        let mut thoughts: Vec<(&ThoughtID, &Thought)> = self.thoughts.iter().collect();
+        sort.sort(self, &mut thoughts);
+        thoughts.into_iter()
+    }
+
+    /// Find the thoughts matching a query, in a stable order and a page at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to evaluate
+    /// * `options` - The order of the results, and the page of them to return
+    ///
+    /// # Returns
+    ///
+    /// The matching thoughts in the page, in order
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::sort::{QueryOptions, SortBy};
+    /// use thoughtgraph::{Query, ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// for (id, title) in [("a", "Cherries"), ("b", "Apples"), ("c", "Bananas")] {
+    ///     graph.create_thought(ThoughtID::new(id.to_string()), Some(title.to_string()), String::new(), vec![], vec![]).unwrap();
+    /// }
+    ///
+    /// let options = QueryOptions { sort: SortBy::Title, offset: 1, limit: Some(1), ..QueryOptions::default() };
+    /// let page: Vec<&str> = graph.query_with_options(&Query::All, &options).into_iter().map(|(id, _)| id.as_str()).collect();
+    /// assert_eq!(page, vec!["c"]);
+    /// ```
+    pub fn query_with_options(&self, query: &Query, options: &QueryOptions) -> Vec<(&ThoughtID, &Thought)> {
+        let thoughts = self
+            .query(query)
+            .into_iter()
+            .filter_map(|id| self.thoughts.get_key_value(&id))
+            .collect();
+        options.apply(self, thoughts)
+    }
+
+    /// Iterate over all tags, ordered alphabetically by ID
+    pub fn iter_tags_sorted(&self) -> impl Iterator<Item = (&TagID, &Tag)> {
+        let mut tags: Vec<(&TagID, &Tag)> = self.tags.iter().collect();
+        tags.sort_by(|a, b| a.0.cmp(b.0));
+        tags.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn add(graph: &mut ThoughtGraph, id: &str, title: Option<&str>, age_days: i64) {
+        let mut thought = Thought::new(title.map(str::to_string), String::new(), vec![], vec![]);
+        thought.created_at = Utc::now() - Duration::days(age_days);
+        thought.updated_at = Utc::now() - Duration::days(age_days % 3);
+        graph.apply(crate::Command::PutThought { id: ThoughtID::new(id.to_string()), thought });
+    }
+
+    fn order(graph: &ThoughtGraph, sort: SortBy) -> Vec<&str> {
+        graph.iter_thoughts_sorted(sort).map(|(id, _)| id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_orders() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "c", Some("alpha"), 1);
+        add(&mut graph, "a", None, 5);
+        add(&mut graph, "b", Some("Zeta"), 3);
+
+        assert_eq!(order(&graph, SortBy::Id), vec!["a", "b", "c"]);
+        assert_eq!(order(&graph, SortBy::Title), vec!["a", "c", "b"]);
+        assert_eq!(order(&graph, SortBy::Created), vec!["c", "b", "a"]);
+        assert_eq!(order(&graph, SortBy::Updated), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_query_with_options() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "a", None, 1);
+        add(&mut graph, "b", None, 2);
+        add(&mut graph, "c", None, 3);
+        // c is referenced twice, b once
+        for (from, to) in [("a", "c"), ("b", "c"), ("c", "b")] {
+            let id = ThoughtID::new(from.to_string());
+            let mut thought = graph.thoughts[&id].clone();
+            thought.references.push(crate::Reference::new(ThoughtID::new(to.to_string()), String::new(), Utc::now()));
+            graph.apply(crate::Command::PutThought { id, thought });
+        }
+        let page = |options: QueryOptions| -> Vec<String> {
+            graph.query_with_options(&Query::All, &options).into_iter().map(|(id, _)| id.as_str().to_string()).collect()
+        };
+
+        assert_eq!(page(QueryOptions { sort: SortBy::Backlinks, ..QueryOptions::default() }), vec!["c", "b", "a"]);
+        assert_eq!(page(QueryOptions { sort: SortBy::Created, reverse: true, ..QueryOptions::default() }), vec!["c", "b", "a"]);
+        assert_eq!(page(QueryOptions { offset: 1, limit: Some(1), ..QueryOptions::default() }), vec!["b"]);
+        assert_eq!(page(QueryOptions { offset: 5, ..QueryOptions::default() }), Vec::<String>::new());
+        assert_eq!(page(QueryOptions { limit: Some(0), ..QueryOptions::default() }).len(), 0);
+    }
+
+    #[test]
+    fn test_tags_sorted() {
+        let mut graph = ThoughtGraph::new();
+        for tag in ["rust", "books", "ideas"] {
+            graph.create_tag(TagID::new(tag.to_string()), String::new()).unwrap();
+        }
+        let tags: Vec<&str> = graph.iter_tags_sorted().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(tags, vec!["books", "ideas", "rust"]);
+    }
+}
@@ -0,0 +1,159 @@
+//! Statistics about a graph
+//!
+//! A summary of a graph for `thoughts stats`: how many thoughts, tags and references it
+//! has, how often each tag is used, how many thoughts were created each month, and how
+//! connected the thoughts are.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::Datelike;
+
+use crate::{TagID, ThoughtGraph, ThoughtID};
+
+/// Statistics about a graph, from `ThoughtGraph::stats`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphStats {
+    /// Number of thoughts
+    pub thoughts: usize,
+    /// Number of tags
+    pub tags: usize,
+    /// Number of references between thoughts
+    pub references: usize,
+    /// Tags with the number of thoughts that have them, most used first, with ties in
+    /// order of ID; tags no thought has are left out
+    pub tag_usage: Vec<(TagID, usize)>,
+    /// Months as `(year, month)` with the number of thoughts created in them (in UTC),
+    /// from the first month with a thought to the last, including empty months
+    pub created_per_month: Vec<((i32, u32), usize)>,
+    /// Ranges of degree, the number of references to and from a thought, with the
+    /// number of thoughts whose degree is in them. The ranges are `0`, `1`, `2`, `3-4`,
+    /// `5-8` and so on, doubling up to the highest degree, and are given as inclusive
+    /// `(low, high)` bounds.
+    pub degrees: Vec<((usize, usize), usize)>,
+}
+
+/// The inclusive bounds of the range of degree that `degree` falls in
+fn degree_range(degree: usize) -> (usize, usize) {
+    match degree {
+        0..=2 => (degree, degree),
+        _ => {
+            let high = degree.next_power_of_two();
+            (high / 2 + 1, high)
+        },
+    }
+}
+
+impl ThoughtGraph {
+    /// The degree of a thought: the number of its references plus the number of thoughts
+    /// that reference it
+    pub fn degree(&self, id: &ThoughtID) -> usize {
+        let references = self.thoughts.get(id).map_or(0, |thought| thought.references.len());
+        references + self.backlink_count(id)
+    }
+
+    /// The number of thoughts that reference a thought
+    pub fn backlink_count(&self, id: &ThoughtID) -> usize {
+        self.backreferences.get(id).map_or(0, |backlinks| backlinks.len())
+    }
+
+    /// Gather statistics about the graph.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Reference, ThoughtGraph, ThoughtID};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = |name: &str| ThoughtID::new(name.to_string());
+    /// graph.create_thought(id("a"), None, String::new(), vec![], vec![]).unwrap();
+    /// graph.create_thought(id("b"), None, String::new(), vec![], vec![Reference::new(id("a"), String::new(), Utc::now())]).unwrap();
+    ///
+    /// let stats = graph.stats();
+    /// assert_eq!((stats.thoughts, stats.references), (2, 1));
+    /// assert_eq!(stats.degrees, vec![((0, 0), 0), ((1, 1), 2)]);
+    /// ```
+    pub fn stats(&self) -> GraphStats {
+        let mut tag_usage: HashMap<&TagID, usize> = HashMap::new();
+        let mut months: BTreeMap<(i32, u32), usize> = BTreeMap::new();
+        let mut degrees: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        let mut references = 0;
+        for (id, thought) in &self.thoughts {
+            for tag in &thought.tags {
+                *tag_usage.entry(tag).or_default() += 1;
+            }
+            *months.entry((thought.created_at.year(), thought.created_at.month())).or_default() += 1;
+            *degrees.entry(degree_range(self.degree(id))).or_default() += 1;
+            references += thought.references.len();
+        }
+
+        let mut tag_usage: Vec<(TagID, usize)> = tag_usage.into_iter().map(|(tag, count)| (tag.clone(), count)).collect();
+        tag_usage.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+
+        let mut created_per_month = Vec::new();
+        if let (Some(&first), Some(&last)) = (months.keys().next(), months.keys().next_back()) {
+            let mut month = first;
+            while month <= last {
+                created_per_month.push((month, months.get(&month).copied().unwrap_or(0)));
+                month = if month.1 == 12 { (month.0 + 1, 1) } else { (month.0, month.1 + 1) };
+            }
+        }
+
+        let mut ranges = Vec::new();
+        if let Some(&(_, highest)) = degrees.keys().next_back() {
+            let mut degree = 0;
+            while degree <= highest {
+                let range = degree_range(degree);
+                ranges.push((range, degrees.get(&range).copied().unwrap_or(0)));
+                degree = range.1 + 1;
+            }
+        }
+
+        GraphStats {
+            thoughts: self.thoughts.len(),
+            tags: self.tags.len(),
+            references,
+            tag_usage,
+            created_per_month,
+            degrees: ranges,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Reference, Thought};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(
+            (0..=9).map(degree_range).collect::<Vec<_>>(),
+            vec![(0, 0), (1, 1), (2, 2), (3, 4), (3, 4), (5, 8), (5, 8), (5, 8), (5, 8), (9, 16)]
+        );
+
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let tag = |name: &str| TagID::new(name.to_string());
+        let mut put = |name: &str, tags: Vec<TagID>, references: Vec<&str>, (year, month)| {
+            let references = references.into_iter().map(|to| Reference::new(id(to), String::new(), Utc::now())).collect();
+            let mut thought = Thought::new(None, String::new(), tags, references);
+            thought.created_at = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).unwrap();
+            graph.command(&Command::PutThought { id: id(name), thought });
+        };
+        put("hub", vec![tag("b"), tag("a")], vec!["x", "y", "z", "lonely-ref"], (2024, 11));
+        put("x", vec![tag("a")], vec![], (2025, 2));
+        put("y", vec![tag("b")], vec![], (2025, 2));
+        put("z", vec![], vec![], (2024, 12));
+        put("alone", vec![], vec![], (2025, 2));
+        graph.create_tag(tag("unused"), String::new()).unwrap();
+
+        let stats = graph.stats();
+        assert_eq!((stats.thoughts, stats.tags, stats.references), (5, 1, 4));
+        assert_eq!(stats.tag_usage, vec![(tag("a"), 2), (tag("b"), 2)]);
+        assert_eq!(stats.created_per_month, vec![((2024, 11), 1), ((2024, 12), 1), ((2025, 1), 0), ((2025, 2), 3)]);
+        assert_eq!(stats.degrees, vec![((0, 0), 1), ((1, 1), 3), ((2, 2), 0), ((3, 4), 1)]);
+        assert!(ThoughtGraph::new().stats().degrees.is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+//! Loading and saving graphs
+//!
+//! A `Storage` loads a graph from, and saves it to, some place such as a file. Graphs are
+//! stored in the format of `ThoughtGraph::to_bytes`. Programs that may run several times
+//! at once can take a `FileLock` around a load-modify-save cycle so that they do not
+//! overwrite each other's changes, or a shared one around a load so that they do not
+//! read a graph while it is being replaced.
+//!
+//! With the `tokio` feature, `AsyncStorage` does the same from async code. Encoding and
+//! decoding a large graph takes a while, so `AsyncFileStorage` does it off the async
+//! worker threads, and waits for locks without blocking them.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::progress::{self, Progress, ProgressFn};
+use crate::{Result, ThoughtGraph, ThoughtGraphError};
+
+/// How long to wait for a lock held by someone else before giving up
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to check whether a lock held by someone else has been released
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A place where a graph can be stored
+pub trait Storage {
+    /// Load the stored graph
+    fn load(&self) -> Result<ThoughtGraph>;
+
+    /// Store the graph, replacing the stored one
+    fn save(&self, graph: &ThoughtGraph) -> Result<()>;
+}
+
+/// Storage of a graph in a file
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    /// Store the graph in the file at `path`
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The path of the graph file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Take the exclusive lock on the graph file, waiting up to `timeout` if someone else
+    /// holds the lock, exclusive or shared.
+    ///
+    /// # Returns
+    ///
+    /// The lock, which is released when it is dropped, or `FileLocked` if it could not be
+    /// taken in time
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::storage::{FileStorage, Storage, DEFAULT_LOCK_TIMEOUT};
+    /// use thoughtgraph::ThoughtGraph;
+    /// use std::time::Duration;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let storage = FileStorage::new(dir.path().join("thoughts.bin"));
+    ///
+    /// let lock = storage.lock(DEFAULT_LOCK_TIMEOUT).unwrap();
+    /// storage.save(&ThoughtGraph::new()).unwrap();
+    /// assert!(storage.lock(Duration::ZERO).is_err());
+    /// drop(lock);
+    /// assert!(storage.lock(Duration::ZERO).is_ok());
+    /// ```
+    pub fn lock(&self, timeout: Duration) -> Result<FileLock> {
+        self.wait_for_lock(timeout, false)
+    }
+
+    /// Take a shared lock on the graph file, for reading it without saving, waiting up to
+    /// `timeout` if someone else holds the exclusive lock. Any number of shared locks can
+    /// be held at once.
+    ///
+    /// # Returns
+    ///
+    /// The lock, which is released when it is dropped, or `FileLocked` if it could not be
+    /// taken in time
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::storage::FileStorage;
+    /// use std::time::Duration;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let storage = FileStorage::new(dir.path().join("thoughts.bin"));
+    ///
+    /// let first = storage.lock_shared(Duration::ZERO).unwrap();
+    /// let second = storage.lock_shared(Duration::ZERO).unwrap();
+    /// assert!(storage.lock(Duration::ZERO).is_err());
+    /// ```
+    pub fn lock_shared(&self, timeout: Duration) -> Result<FileLock> {
+        self.wait_for_lock(timeout, true)
+    }
+
+    fn wait_for_lock(&self, timeout: Duration, shared: bool) -> Result<FileLock> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(lock) = FileLock::try_acquire(&self.path, shared)? {
+                return Ok(lock);
+            }
+            if Instant::now() >= deadline {
+                return Err(FileLock::locked_error(&self.path));
+            }
+            std::thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&self) -> Result<ThoughtGraph> {
+        ThoughtGraph::from_bytes(&fs::read(&self.path)?)
+    }
+
+    /// Store the graph as `ThoughtGraph::save_to_file` does: written to a temporary file
+    /// next to the graph file, then moved over it, so that readers never see a partially
+    /// written graph
+    fn save(&self, graph: &ThoughtGraph) -> Result<()> {
+        graph.save_to_file(&self.path)
+    }
+}
+
+/// `path` with `extension` appended to its file name
+pub(crate) fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+/// Write `data` to a temporary file next to `path` and move it into place, so that a
+/// crash while writing leaves the previous file intact, reporting the progress of writing
+/// it in bytes
+pub(crate) fn write_atomically(path: &Path, data: &[u8], progress: ProgressFn) -> Result<()> {
+    use std::io::Write;
+    let total = data.len() as u64;
+    let temporary = sibling(path, "tmp");
+    let mut file = File::create(&temporary)?;
+    let mut written = 0;
+    progress(Progress::of(0, total));
+    for chunk in data.chunks(progress::CHUNK_SIZE) {
+        file.write_all(chunk)?;
+        written += chunk.len() as u64;
+        progress(Progress::of(written, total));
+    }
+    file.sync_all()?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Read the file at `path`, reporting the progress of reading it in bytes
+pub(crate) fn read_with_progress(path: &Path, progress: ProgressFn) -> Result<Vec<u8>> {
+    use std::io::Read;
+    let mut file = File::open(path)?;
+    let total = file.metadata()?.len();
+    let mut data = Vec::with_capacity(total as usize);
+    let mut chunk = vec![0; progress::CHUNK_SIZE];
+    progress(Progress::of(0, total));
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk[..read]);
+        // The file may have grown since its size was read
+        progress(Progress::of(data.len() as u64, total.max(data.len() as u64)));
+    }
+    Ok(data)
+}
+
+/// Exclusive or shared access to a graph file, released when dropped.
+///
+/// The lock is taken by the operating system on a file next to the graph file, with
+/// `.lock` appended to its name, and is released when the file is closed: when the lock
+/// is dropped, or when the program holding it exits or is killed. The lock file itself
+/// is left in place, and does not keep anyone out. Locks are advisory: they only keep out
+/// programs that take the lock as well.
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    // Holds the lock while it is open
+    _file: File,
+}
+
+impl FileLock {
+    /// Take the lock on the graph file at `graph_path`, shared or exclusive, if nobody
+    /// holds a lock that conflicts with it
+    fn try_acquire(graph_path: &Path, shared: bool) -> Result<Option<FileLock>> {
+        let path = sibling(graph_path, "lock");
+        let file = OpenOptions::new().write(true).create(true).truncate(false).open(&path)?;
+        let locked = match shared {
+            true => file.try_lock_shared(),
+            false => file.try_lock(),
+        };
+        match locked {
+            Ok(()) => Ok(Some(FileLock { path, _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    fn locked_error(graph_path: &Path) -> ThoughtGraphError {
+        ThoughtGraphError::FileLocked(sibling(graph_path, "lock").display().to_string())
+    }
+
+    /// The path of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use self::asynchronous::{AsyncFileStorage, AsyncStorage};
+
+#[cfg(feature = "tokio")]
+mod asynchronous {
+    use std::future::Future;
+    use std::io;
+
+    use super::*;
+
+    /// A place where a graph can be stored, for use from async code
+    pub trait AsyncStorage {
+        /// Load the stored graph
+        fn load(&self) -> impl Future<Output = Result<ThoughtGraph>> + Send;
+
+        /// Store the graph, replacing the stored one
+        fn save(&self, graph: &ThoughtGraph) -> impl Future<Output = Result<()>> + Send;
+    }
+
+    /// Storage of a graph in a file, for use from async code.
+    ///
+    /// Decoding and encoding run on tokio's blocking thread pool. Saving encodes a copy of
+    /// the graph, so that the caller's graph is not borrowed while it is written, and
+    /// writes it as `ThoughtGraph::save_to_file` does.
+    #[derive(Clone, Debug)]
+    pub struct AsyncFileStorage {
+        path: PathBuf,
+    }
+
+    impl AsyncFileStorage {
+        /// Store the graph in the file at `path`
+        pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+            Self { path: path.into() }
+        }
+
+        /// The path of the graph file
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        /// Take the lock on the graph file, waiting up to `timeout` without blocking the
+        /// runtime if someone else holds it. See `FileStorage::lock`.
+        pub async fn lock(&self, timeout: Duration) -> Result<FileLock> {
+            let deadline = Instant::now() + timeout;
+            loop {
+                if let Some(lock) = FileLock::try_acquire(&self.path, false)? {
+                    return Ok(lock);
+                }
+                if Instant::now() >= deadline {
+                    return Err(FileLock::locked_error(&self.path));
+                }
+                tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+            }
+        }
+    }
+
+    /// Wait for a task on the blocking thread pool
+    async fn run_blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+        tokio::task::spawn_blocking(f)
+            .await
+            .map_err(|e| ThoughtGraphError::IoError(io::Error::other(e)))?
+    }
+
+    impl AsyncStorage for AsyncFileStorage {
+        async fn load(&self) -> Result<ThoughtGraph> {
+            let data = tokio::fs::read(&self.path).await?;
+            run_blocking(move || ThoughtGraph::from_bytes(&data)).await
+        }
+
+        /// Store the graph as `ThoughtGraph::save_to_file` does, synced to disk before it
+        /// replaces the graph file
+        fn save(&self, graph: &ThoughtGraph) -> impl Future<Output = Result<()>> + Send {
+            let snapshot = graph.clone();
+            let path = self.path.clone();
+            run_blocking(move || snapshot.save_to_file(path))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThoughtID;
+
+    fn sample_graph() -> ThoughtGraph {
+        let mut graph = ThoughtGraph::new();
+        graph
+            .create_thought(ThoughtID::new("a".to_string()), None, "https://example.com".to_string(), vec![], vec![])
+            .unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_file_storage_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileStorage::new(dir.path().join("thoughts.bin"));
+        storage.save(&sample_graph()).unwrap();
+
+        let loaded = storage.load().unwrap();
+        assert_eq!(loaded.list_thoughts().len(), 1);
+        assert_eq!(loaded.external_links().len(), 1);
+        assert!(!sibling(storage.path(), "tmp").exists());
+    }
+
+    #[test]
+    fn test_lock_is_exclusive_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FileStorage::new(dir.path().join("thoughts.bin"));
+
+        let lock = storage.lock(DEFAULT_LOCK_TIMEOUT).unwrap();
+        assert!(lock.path().exists());
+        assert!(matches!(storage.lock(Duration::from_millis(60)), Err(ThoughtGraphError::FileLocked(_))));
+        assert!(storage.lock_shared(Duration::ZERO).is_err());
+        drop(lock);
+
+        // The lock file left behind keeps nobody out
+        assert!(sibling(storage.path(), "lock").exists());
+        let shared = storage.lock_shared(Duration::ZERO).unwrap();
+        assert!(storage.lock_shared(Duration::ZERO).is_ok());
+        assert!(storage.lock(Duration::ZERO).is_err());
+        drop(shared);
+        assert!(storage.lock(Duration::ZERO).is_ok());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_async_file_storage() {
+        for runtime in [
+            tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap(),
+            tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap(),
+        ] {
+            runtime.block_on(async {
+                let dir = tempfile::tempdir().unwrap();
+                let storage = AsyncFileStorage::new(dir.path().join("thoughts.bin"));
+
+                let lock = storage.lock(DEFAULT_LOCK_TIMEOUT).await.unwrap();
+                assert!(storage.lock(Duration::ZERO).await.is_err());
+                storage.save(&sample_graph()).await.unwrap();
+                drop(lock);
+
+                assert_eq!(storage.load().await.unwrap().list_thoughts().len(), 1);
+            });
+        }
+    }
+}
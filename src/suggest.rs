@@ -0,0 +1,339 @@
+//! Suggestions for organizing thoughts
+//!
+//! This module looks at the words in a thought and at how tags are already used across
+//! the graph to suggest tags that fit a thought, helping to keep tagging consistent as
+//! a graph grows, and to suggest the thoughts it could link to. Words are compared by
+//! their stems, so a thought about "running" is suggested the tag `run`, leaving out
+//! stop words, which are `text::STOP_WORDS` unless others are given.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::parallel;
+use crate::text::{stem, stemmed_tokens};
+use crate::{TagID, Thought, ThoughtGraph, ThoughtID};
+
+/// Maximum number of tags returned by `ThoughtGraph::suggest_tags`
+pub const MAX_TAG_SUGGESTIONS: usize = 5;
+
+/// Minimum score a tag needs before it is suggested
+const MIN_SUGGESTION_SCORE: f32 = 0.5;
+
+/// Weight given to the keyword overlap with other thoughts when scoring their tags
+const SIMILAR_THOUGHT_WEIGHT: f32 = 3.0;
+
+/// Maximum number of thoughts returned by `ThoughtGraph::suggest_links`
+pub const MAX_LINK_SUGGESTIONS: usize = 5;
+
+/// Minimum score a thought needs before a link to it is suggested
+const MIN_LINK_SCORE: f32 = 0.15;
+
+/// Weight given to shared tags, relative to keyword overlap, when scoring links
+const SHARED_TAG_WEIGHT: f32 = 0.5;
+
+/// All tokens of a thought's title and content, stemmed
+fn thought_tokens(thought: &Thought, stop_words: Option<&HashSet<String>>) -> Vec<String> {
+    let mut tokens = stemmed_tokens(thought.title.as_deref().unwrap_or_default(), stop_words);
+    tokens.extend(stemmed_tokens(&thought.contents, stop_words));
+    tokens
+}
+
+impl ThoughtGraph {
+    /// Suggest existing tags that would fit the given thought.
+    ///
+    /// Tags are scored using three signals:
+    ///
+    /// * the tag's ID appearing as a keyword in the thought's title or content,
+    /// * tags used by other thoughts with overlapping keywords, and
+    /// * tags that usually accompany the tags the thought already has.
+    ///
+    /// Tags already attached to the thought are never suggested.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to suggest tags for
+    ///
+    /// # Returns
+    ///
+    /// Up to `MAX_TAG_SUGGESTIONS` tag IDs, best match first. The result is empty if the
+    /// thought does not exist or nothing fits well enough.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, TagID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// graph.create_tag(TagID::new("rust".to_string()), "Rust language".to_string()).unwrap();
+    ///
+    /// let id = ThoughtID::new("note".to_string());
+    /// graph.create_thought(id.clone(), None, "Learning Rust lifetimes".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// assert_eq!(graph.suggest_tags(&id), vec![TagID::new("rust".to_string())]);
+    /// ```
+    pub fn suggest_tags(&self, id: &ThoughtID) -> Vec<TagID> {
+        self.suggest_tags_with(id, None)
+    }
+
+    /// Suggest existing tags that would fit the given thought, as `suggest_tags` does,
+    /// leaving out the given stop words.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to suggest tags for
+    /// * `stop_words` - The words to leave out, written in lowercase without accents, or
+    ///   `None` for `text::STOP_WORDS`
+    pub fn suggest_tags_with(&self, id: &ThoughtID, stop_words: Option<&HashSet<String>>) -> Vec<TagID> {
+        let thought = match self.get_thought(id) {
+            Some(thought) => thought,
+            None => return Vec::new(),
+        };
+
+        let mut token_counts: HashMap<String, usize> = HashMap::new();
+        for token in thought_tokens(thought, stop_words) {
+            *token_counts.entry(token).or_default() += 1;
+        }
+        let token_set: HashSet<&String> = token_counts.keys().collect();
+
+        let is_candidate =
+            |tag_id: &TagID| self.tags.contains_key(tag_id) && !thought.tags.contains(tag_id);
+        let mut scores: HashMap<&TagID, f32> = HashMap::new();
+
+        // Tags whose ID is mentioned in the thought itself
+        for tag_id in self.tags.keys().filter(|t| is_candidate(t)) {
+            let tag_name = tag_id.id.to_lowercase();
+            if let Some(count) = token_counts.get(&stem(&tag_name)) {
+                *scores.entry(tag_id).or_default() += 2.0 + *count as f32;
+                continue;
+            }
+
+            // Multi-word tags such as "machine-learning" match when every part is present
+            let parts: Vec<String> = stemmed_tokens(&tag_name.replace(['-', '_'], " "), stop_words);
+            if parts.len() > 1 && parts.iter().all(|part| token_counts.contains_key(part)) {
+                *scores.entry(tag_id).or_default() += 1.0;
+            }
+        }
+
+        // Tags used by other thoughts that talk about the same things
+        if !token_set.is_empty() {
+            // Tokenizing every other thought dominates the cost on large graphs
+            let similar = parallel::filter_map(&self.thoughts, |other_id, other| {
+                if other_id == id || other.tags.is_empty() {
+                    return None;
+                }
+
+                let other_tokens: HashSet<String> = thought_tokens(other, stop_words).into_iter().collect();
+                let shared = other_tokens.iter().filter(|t| token_set.contains(t)).count();
+                if shared == 0 {
+                    return None;
+                }
+                let union = token_set.len() + other_tokens.len() - shared;
+                Some((other, shared as f32 / union as f32))
+            });
+
+            for (other, similarity) in similar {
+                for tag_id in other.tags.iter().filter(|t| is_candidate(t)) {
+                    *scores.entry(tag_id).or_default() += similarity * SIMILAR_THOUGHT_WEIGHT;
+                }
+            }
+        }
+
+        // Tags that usually accompany the thought's existing tags
+        for existing in &thought.tags {
+            let companions: Vec<&Thought> = self.thoughts
+                .iter()
+                .filter(|(other_id, other)| *other_id != id && other.tags.contains(existing))
+                .map(|(_, other)| other)
+                .collect();
+            if companions.is_empty() {
+                continue;
+            }
+
+            let mut co_counts: HashMap<&TagID, usize> = HashMap::new();
+            for other in &companions {
+                for tag_id in other.tags.iter().filter(|t| is_candidate(t)) {
+                    *co_counts.entry(tag_id).or_default() += 1;
+                }
+            }
+            for (tag_id, count) in co_counts {
+                *scores.entry(tag_id).or_default() += count as f32 / companions.len() as f32;
+            }
+        }
+
+        let mut ranked: Vec<(&TagID, f32)> = scores
+            .into_iter()
+            .filter(|(_, score)| *score >= MIN_SUGGESTION_SCORE)
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        ranked
+            .into_iter()
+            .take(MAX_TAG_SUGGESTIONS)
+            .map(|(tag_id, _)| tag_id.clone())
+            .collect()
+    }
+
+    /// Suggest existing thoughts that the given thought could reference.
+    ///
+    /// Thoughts are scored by the overlap of their keywords with the thought's, and by the
+    /// tags they share with it. Thoughts already linked to it, in either direction, are
+    /// never suggested.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to suggest links for
+    ///
+    /// # Returns
+    ///
+    /// Up to `MAX_LINK_SUGGESTIONS` thought IDs, best match first. The result is empty if
+    /// the thought does not exist or nothing is related closely enough.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = |name: &str| ThoughtID::new(name.to_string());
+    /// graph.create_thought(id("tokio"), None, "The tokio runtime schedules futures".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(id("bread"), None, "Sourdough needs a starter".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(id("note"), None, "Futures on a work-stealing runtime".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// assert_eq!(graph.suggest_links(&id("note")), vec![id("tokio")]);
+    /// ```
+    pub fn suggest_links(&self, id: &ThoughtID) -> Vec<ThoughtID> {
+        self.suggest_links_with(id, None)
+    }
+
+    /// Suggest existing thoughts that the given thought could reference, as
+    /// `suggest_links` does, leaving out the given stop words.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to suggest links for
+    /// * `stop_words` - The words to leave out, written in lowercase without accents, or
+    ///   `None` for `text::STOP_WORDS`
+    pub fn suggest_links_with(&self, id: &ThoughtID, stop_words: Option<&HashSet<String>>) -> Vec<ThoughtID> {
+        let thought = match self.get_thought(id) {
+            Some(thought) => thought,
+            None => return Vec::new(),
+        };
+        let tokens: HashSet<String> = thought_tokens(thought, stop_words).into_iter().collect();
+        let linked: HashSet<&ThoughtID> = thought.references.iter().map(|r| &r.id).collect();
+        let backlinks = self.get_backlinks(id);
+
+        let mut ranked = parallel::filter_map(&self.thoughts, |other_id, other| {
+            if other_id == id || linked.contains(other_id) || backlinks.contains(other_id) {
+                return None;
+            }
+
+            let other_tokens: HashSet<String> = thought_tokens(other, stop_words).into_iter().collect();
+            let shared = other_tokens.iter().filter(|t| tokens.contains(*t)).count();
+            let union = tokens.len() + other_tokens.len() - shared;
+            let mut score = if union == 0 { 0.0 } else { shared as f32 / union as f32 };
+
+            let shared_tags = other.tags.iter().filter(|t| thought.tags.contains(t)).count();
+            if shared_tags > 0 {
+                score += SHARED_TAG_WEIGHT * shared_tags as f32 / thought.tags.len().max(other.tags.len()) as f32;
+            }
+            (score >= MIN_LINK_SCORE).then_some((other_id, score))
+        });
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        ranked
+            .into_iter()
+            .take(MAX_LINK_SUGGESTIONS)
+            .map(|(other_id, _)| other_id.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(graph: &mut ThoughtGraph, id: &str) -> TagID {
+        let tag_id = TagID::new(id.to_string());
+        graph.create_tag(tag_id.clone(), format!("{} tag", id)).unwrap();
+        tag_id
+    }
+
+    fn thought(graph: &mut ThoughtGraph, id: &str, contents: &str, tags: Vec<TagID>) -> ThoughtID {
+        let thought_id = ThoughtID::new(id.to_string());
+        graph
+            .create_thought(thought_id.clone(), None, contents.to_string(), tags, vec![])
+            .unwrap();
+        thought_id
+    }
+
+    #[test]
+    fn test_suggests_tags_mentioned_in_content() {
+        let mut graph = ThoughtGraph::new();
+        let rust = tag(&mut graph, "rust");
+        let machine_learning = tag(&mut graph, "machine-learning");
+        let cooking = tag(&mut graph, "cooking");
+
+        let id = thought(&mut graph, "note", "Rust for machine learning pipelines", vec![]);
+
+        let suggestions = graph.suggest_tags(&id);
+        assert_eq!(suggestions, vec![rust, machine_learning.clone()]);
+
+        // Other forms of a tag's words count too
+        let id = thought(&mut graph, "recipe", "Cooked slowly, as machines learned", vec![]);
+        assert_eq!(graph.suggest_tags(&id), vec![cooking, machine_learning.clone()]);
+
+        // Stop words are not taken for tags
+        let id = ThoughtID::new("note".to_string());
+        let stop_words = HashSet::from(["rust".to_string()]);
+        assert_eq!(graph.suggest_tags_with(&id, Some(&stop_words)), vec![machine_learning]);
+    }
+
+    #[test]
+    fn test_suggests_tags_from_similar_thoughts() {
+        let mut graph = ThoughtGraph::new();
+        let async_tag = tag(&mut graph, "async");
+
+        thought(&mut graph, "a", "tokio runtime executor futures", vec![async_tag.clone()]);
+        let id = thought(&mut graph, "b", "executor design for tokio futures", vec![]);
+
+        assert_eq!(graph.suggest_tags(&id), vec![async_tag]);
+    }
+
+    #[test]
+    fn test_suggests_companion_tags() {
+        let mut graph = ThoughtGraph::new();
+        let book = tag(&mut graph, "book");
+        let reading = tag(&mut graph, "reading");
+
+        thought(&mut graph, "a", "alpha", vec![book.clone(), reading.clone()]);
+        thought(&mut graph, "b", "beta", vec![book.clone(), reading.clone()]);
+        let id = thought(&mut graph, "c", "gamma", vec![book]);
+
+        assert_eq!(graph.suggest_tags(&id), vec![reading]);
+    }
+
+    #[test]
+    fn test_suggests_links() {
+        let mut graph = ThoughtGraph::new();
+        let garden = tag(&mut graph, "garden");
+
+        thought(&mut graph, "tomatoes", "tomatoes need sun and water", vec![]);
+        thought(&mut graph, "compost", "turning compost", vec![garden.clone()]);
+        thought(&mut graph, "linked", "tomatoes sun water", vec![]);
+        thought(&mut graph, "unrelated", "tax forms", vec![]);
+        let id = thought(&mut graph, "note", "planting tomatoes in the sun [linked]", vec![garden]);
+        graph.process_auto_references(&id).unwrap();
+
+        assert_eq!(graph.suggest_links(&id), vec![ThoughtID::new("compost".to_string()), ThoughtID::new("tomatoes".to_string())]);
+        assert!(graph.suggest_links(&ThoughtID::new("missing".to_string())).is_empty());
+    }
+
+    #[test]
+    fn test_existing_tags_and_unknown_thoughts() {
+        let mut graph = ThoughtGraph::new();
+        let rust = tag(&mut graph, "rust");
+        let id = thought(&mut graph, "note", "rust rust rust", vec![rust]);
+
+        assert!(graph.suggest_tags(&id).is_empty());
+        assert!(graph.suggest_tags(&ThoughtID::new("missing".to_string())).is_empty());
+    }
+}
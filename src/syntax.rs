@@ -0,0 +1,546 @@
+//! The text syntax of queries
+//!
+//! Queries can be written as text, such as `tag:rust AND (references:cargo OR NOT
+//! tag:draft)`, and are displayed the same way:
+//!
+//! ```text
+//! query     = and ("OR" and)*
+//! and       = not ("AND" not)*
+//! not       = "NOT" not | condition
+//! condition = "(" query ")" | field ":" value
+//! field     = "id" | "tag" | "references" | "referenced-by"
+//!           | ("reachable-from" | "reachable-to") ["[" depth "]"]
+//!           | "title" | "content" | "title-regex" | "content-regex" | "notes"
+//!           | "tag-glob" | "id-glob" | "min-backlinks" | "max-backlinks" | "is"
+//! ```
+//!
+//! Operators and fields may be written in any case. `NOT` binds most tightly, then
+//! `AND`, then `OR`.
+//!
+//! `id:`, `references:` and `referenced-by:` take a thought ID, and `tag:` a tag ID.
+//! `reachable-from:` and `reachable-to:` take a thought ID and follow references any
+//! number of hops, or at most `depth`, as in `reachable-from[2]:index`.
+//!
+//! `title:`, `content:` and `notes:` take text to look for, in the title, the contents,
+//! or the notes of a thought's references. `title-regex:` and `content-regex:` take a
+//! regular expression, and `tag-glob:` and `id-glob:` an ID with `*` wildcards, such as
+//! `tag-glob:project/*`.
+//!
+//! `min-backlinks:` and `max-backlinks:` take a number, and find the thoughts referenced
+//! by at least or at most that many others. `is:` takes `untagged` for thoughts without
+//! tags, `orphan` for thoughts without references in either direction, or `all`.
+//!
+//! Values with spaces, parentheses or quotes are written in double quotes, such as
+//! `title:"graph theory"`, with `\"` for a quote and `\\` for a backslash.
+//!
+//! Errors name the column where the query stops making sense.
+
+use std::fmt;
+
+use crate::{Query, Result, TagID, ThoughtGraphError, ThoughtID};
+
+/// A token of query text
+#[derive(Clone, Debug, PartialEq)]
+enum Token<'a> {
+    Open,
+    Close,
+    Word(&'a str),
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Word(word) => write!(f, "'{}'", word),
+        }
+    }
+}
+
+/// Split query text into parentheses and the words between them, each with the byte
+/// offset it starts at. Spaces and parentheses between double quotes are part of a word.
+fn tokenize(text: &str) -> Vec<(usize, Token<'_>)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if quoted {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => {},
+            }
+        } else if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(start) = start.take() {
+                tokens.push((start, Token::Word(&text[start..i])));
+            }
+            match c {
+                '(' => tokens.push((i, Token::Open)),
+                ')' => tokens.push((i, Token::Close)),
+                _ => {},
+            }
+        } else {
+            start.get_or_insert(i);
+            quoted = c == '"';
+        }
+    }
+    if let Some(start) = start {
+        tokens.push((start, Token::Word(&text[start..])));
+    }
+    tokens
+}
+
+fn parse_error(message: String) -> ThoughtGraphError {
+    ThoughtGraphError::ParseError(format!("query: {}", message))
+}
+
+/// The text of a value, removing the quotes and escapes of a quoted value, or `None` if
+/// its quotes are unbalanced. A backslash before anything but a quote or a backslash is
+/// kept, so that regular expressions such as `"\d+ items"` can be written as they are.
+fn unquote(value: &str) -> Option<String> {
+    let Some(quoted) = value.strip_prefix('"') else {
+        return (!value.contains('"')).then(|| value.to_string());
+    };
+    let mut text = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                escaped @ ('"' | '\\') => text.push(escaped),
+                other => text.extend(['\\', other]),
+            },
+            '"' => return chars.next().is_none().then_some(text),
+            c => text.push(c),
+        }
+    }
+    None
+}
+
+/// Text as a value, quoted if it would otherwise not parse back
+fn quote(text: &str) -> String {
+    let plain = !text.is_empty() && !text.chars().any(|c| c.is_whitespace() || "()\"".contains(c));
+    match plain {
+        true => text.to_string(),
+        false => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+/// Whether a word is one of the operators, in any case
+fn is_operator(word: &str) -> bool {
+    ["AND", "OR", "NOT"].iter().any(|operator| word.eq_ignore_ascii_case(operator))
+}
+
+/// A recursive descent parser over the tokens of a query
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(usize, Token<'a>)>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    /// Consume the next token if it is the given keyword, in any case
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// An error at the token `position`, or at the end of the text if there are no more
+    /// tokens, showing the query with a caret under the column
+    fn error_at(&self, position: usize, message: String) -> ThoughtGraphError {
+        let offset = self.tokens.get(position).map_or(self.text.len(), |(offset, _)| *offset);
+        let column = self.text[..offset].chars().count();
+        parse_error(format!("{} at column {}\n  {}\n  {:>column$}^", message, column + 1, self.text, "", column = column))
+    }
+
+    /// An error at the next token
+    fn error(&self, message: String) -> ThoughtGraphError {
+        self.error_at(self.position, message)
+    }
+
+    /// Conditions joined by `OR`
+    fn or(&mut self) -> Result<Query> {
+        let mut queries = vec![self.and()?];
+        while self.keyword("OR") {
+            queries.push(self.and()?);
+        }
+        Ok(combine(queries, Query::Or))
+    }
+
+    /// Possibly negated conditions joined by `AND`
+    fn and(&mut self) -> Result<Query> {
+        let mut queries = vec![self.not()?];
+        while self.keyword("AND") {
+            queries.push(self.not()?);
+        }
+        Ok(combine(queries, Query::And))
+    }
+
+    /// A condition, possibly preceded by `NOT`
+    fn not(&mut self) -> Result<Query> {
+        match self.keyword("NOT") {
+            true => Ok(Query::Not(Box::new(self.not()?))),
+            false => self.condition(),
+        }
+    }
+
+    /// A single condition, or a query in parentheses
+    fn condition(&mut self) -> Result<Query> {
+        let start = self.position;
+        let token = self.peek().cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Open) => {
+                let query = self.or()?;
+                match self.peek() {
+                    Some(Token::Close) => {
+                        self.position += 1;
+                        Ok(query)
+                    },
+                    Some(token) => Err(self.error(format!("expected ')' or an operator, found {}", token))),
+                    None => Err(self.error_at(start, "this '(' is never closed".to_string())),
+                }
+            },
+            Some(Token::Word(word)) if is_operator(word) => {
+                Err(self.error_at(start, format!("expected a condition before '{}'", word)))
+            },
+            Some(Token::Word(word)) => {
+                let (field, value) = word.split_once(':').ok_or_else(|| {
+                    self.error_at(start, format!("expected a condition such as tag:rust, found '{}'", word))
+                })?;
+                let text = || match (value, unquote(value)) {
+                    ("", _) => Err(self.error_at(start, format!("expected text after '{}:'", field))),
+                    (_, Some(text)) => Ok(text),
+                    (_, None) => Err(self.error_at(start, format!("unbalanced quotes in '{}'", word))),
+                };
+                let (field, depth) = match field.split_once('[') {
+                    Some((name, depth)) => {
+                        let depth = depth.strip_suffix(']').and_then(|depth| depth.parse().ok()).ok_or_else(|| {
+                            self.error_at(start, format!("expected a depth such as {}[2], found '{}'", name, field))
+                        })?;
+                        (name, Some(depth))
+                    },
+                    None => (field, None),
+                };
+                let field = field.to_lowercase();
+                if depth.is_some() && !field.starts_with("reachable-") {
+                    return Err(self.error_at(start, format!("'{}' does not take a depth", field)));
+                }
+                let depth = depth.unwrap_or(usize::MAX);
+                match field.as_str() {
+                    "id" => Ok(Query::Id(ThoughtID::parse(&text()?)?)),
+                    "tag" => Ok(Query::Tag(TagID::parse(&text()?)?)),
+                    "references" => Ok(Query::References(ThoughtID::parse(&text()?)?)),
+                    "referenced-by" => Ok(Query::ReferencedBy(ThoughtID::parse(&text()?)?)),
+                    "reachable-from" => Ok(Query::ReachableFrom { id: ThoughtID::parse(&text()?)?, depth }),
+                    "reachable-to" => Ok(Query::ReachableTo { id: ThoughtID::parse(&text()?)?, depth }),
+                    "title" => Ok(Query::TitleContains(text()?)),
+                    "content" => Ok(Query::ContentContains(text()?)),
+                    "title-regex" => Query::title_regex(&text()?),
+                    "content-regex" => Query::content_regex(&text()?),
+                    "notes" => Ok(Query::ReferenceNotesContain(text()?)),
+                    "tag-glob" => Ok(Query::TagGlob(text()?)),
+                    "id-glob" => Ok(Query::IdGlob(text()?)),
+                    "min-backlinks" | "max-backlinks" => {
+                        let count = value.parse().map_err(|_| {
+                            self.error_at(start, format!("expected a number of backlinks after '{}:', found '{}'", field, value))
+                        })?;
+                        match field.as_str() {
+                            "min-backlinks" => Ok(Query::MinBacklinks(count)),
+                            _ => Ok(Query::MaxBacklinks(count)),
+                        }
+                    },
+                    "is" => match value.to_lowercase().as_str() {
+                        "all" => Ok(Query::All),
+                        "untagged" => Ok(Query::Untagged),
+                        "orphan" => Ok(Query::Orphan),
+                        _ => Err(self.error_at(start, format!("unknown kind of thought '{}' (expected all, untagged or orphan)", value))),
+                    },
+                    _ => Err(self.error_at(start, format!(
+                        "unknown field '{}' (expected id, tag, references, referenced-by, reachable-from, reachable-to, title, content, title-regex, content-regex, notes, tag-glob, id-glob, min-backlinks, max-backlinks or is)",
+                        field
+                    ))),
+                }
+            },
+            Some(Token::Close) => Err(self.error_at(start, "expected a condition, found ')'".to_string())),
+            None => Err(self.error_at(start, "expected a condition at the end of the query".to_string())),
+        }
+    }
+}
+
+/// A single query, or several combined with `AND` or `OR`
+fn combine(mut queries: Vec<Query>, combinator: fn(Vec<Box<Query>>) -> Query) -> Query {
+    match queries.len() {
+        1 => queries.remove(0),
+        _ => combinator(queries.into_iter().map(Box::new).collect()),
+    }
+}
+
+impl Query {
+    /// Parse a query from its text syntax, described in the `syntax` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The query, e.g. `tag:rust AND references:cargo`
+    ///
+    /// # Returns
+    ///
+    /// The query, `ParseError` if the text is not a query, or `InvalidTagID` or
+    /// `InvalidThoughtID` if it names an invalid ID
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{Query, TagID, ThoughtID};
+    ///
+    /// let query = Query::parse("tag:rust and (references:cargo or not tag:draft)").unwrap();
+    /// assert_eq!(query, Query::And(vec![
+    ///     Box::new(Query::Tag(TagID::new("rust".to_string()))),
+    ///     Box::new(Query::Or(vec![
+    ///         Box::new(Query::References(ThoughtID::new("cargo".to_string()))),
+    ///         Box::new(Query::Not(Box::new(Query::Tag(TagID::new("draft".to_string()))))),
+    ///     ])),
+    /// ]));
+    /// assert_eq!(query.to_string(), "tag:rust AND (references:cargo OR NOT tag:draft)");
+    /// ```
+    pub fn parse(text: &str) -> Result<Query> {
+        let mut parser = Parser { text, tokens: tokenize(text), position: 0 };
+        if parser.tokens.is_empty() {
+            return Err(parse_error("the query is empty".to_string()));
+        }
+        let query = parser.or()?;
+        match parser.peek() {
+            None => Ok(query),
+            Some(Token::Close) => Err(parser.error("this ')' has no matching '('".to_string())),
+            Some(token) => Err(parser.error(format!("expected AND or OR, found {}", token))),
+        }
+    }
+}
+
+/// The depth of a reachability condition, written after its field unless unlimited
+struct Depth(usize);
+
+impl fmt::Display for Depth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            usize::MAX => Ok(()),
+            depth => write!(f, "[{}]", depth),
+        }
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_all = |f: &mut fmt::Formatter<'_>, queries: &[Box<Query>], operator: &str| {
+            for (i, query) in queries.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {} ", operator)?;
+                }
+                match query.as_ref() {
+                    Query::And(_) | Query::Or(_) => write!(f, "({})", query)?,
+                    query => write!(f, "{}", query)?,
+                }
+            }
+            Ok(())
+        };
+        match self {
+            Query::All => write!(f, "is:all"),
+            Query::Id(id) => write!(f, "id:{}", quote(&id.id)),
+            Query::Tag(tag) => write!(f, "tag:{}", quote(&tag.id)),
+            Query::References(id) => write!(f, "references:{}", quote(&id.id)),
+            Query::ReferencedBy(id) => write!(f, "referenced-by:{}", quote(&id.id)),
+            Query::ReachableFrom { id, depth } => write!(f, "reachable-from{}:{}", Depth(*depth), quote(&id.id)),
+            Query::ReachableTo { id, depth } => write!(f, "reachable-to{}:{}", Depth(*depth), quote(&id.id)),
+            Query::TitleContains(text) => write!(f, "title:{}", quote(text)),
+            Query::ContentContains(text) => write!(f, "content:{}", quote(text)),
+            Query::TitleRegex(pattern) => write!(f, "title-regex:{}", quote(pattern)),
+            Query::ContentRegex(pattern) => write!(f, "content-regex:{}", quote(pattern)),
+            Query::ReferenceNotesContain(text) => write!(f, "notes:{}", quote(text)),
+            Query::TagGlob(glob) => write!(f, "tag-glob:{}", quote(glob)),
+            Query::IdGlob(glob) => write!(f, "id-glob:{}", quote(glob)),
+            Query::Untagged => write!(f, "is:untagged"),
+            Query::Orphan => write!(f, "is:orphan"),
+            Query::MinBacklinks(count) => write!(f, "min-backlinks:{}", count),
+            Query::MaxBacklinks(count) => write!(f, "max-backlinks:{}", count),
+            Query::And(queries) => write_all(f, queries, "AND"),
+            Query::Or(queries) => write_all(f, queries, "OR"),
+            Query::Not(query) => match query.as_ref() {
+                Query::And(_) | Query::Or(_) => write!(f, "NOT ({})", query),
+                query => write!(f, "NOT {}", query),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        let tag = |name: &str| Box::new(Query::Tag(TagID::new(name.to_string())));
+        assert_eq!(Query::parse("  tag:rust ").unwrap(), *tag("rust"));
+        assert_eq!(
+            Query::parse("tag:a OR tag:b AND tag:c").unwrap(),
+            Query::Or(vec![tag("a"), Box::new(Query::And(vec![tag("b"), tag("c")]))])
+        );
+        assert_eq!(Query::parse("((tag:a))").unwrap(), *tag("a"));
+
+        let text = "referenced-by:index AND (references:a/b OR tag:x) AND tag:y";
+        assert_eq!(Query::parse(text).unwrap().to_string(), text);
+
+        // IDs that would not parse back as they are, such as ones with parentheses, are quoted
+        let odd = Query::And(vec![
+            Box::new(Query::Id(ThoughtID::new("f(x)".to_string()))),
+            Box::new(Query::References(ThoughtID::new("notes:(draft)".to_string()))),
+            Box::new(Query::Tag(TagID::new("lang:rust".to_string()))),
+        ]);
+        assert_eq!(odd.to_string(), "id:\"f(x)\" AND references:\"notes:(draft)\" AND tag:lang:rust");
+        assert_eq!(Query::parse(&odd.to_string()).unwrap(), odd);
+
+        for bad in ["", "rust", "tag:a AND", "(tag:a", "tag:a)", "tag:a tag:b", "heading:x", "tag:", ")", "NOT", "tag:a NOT tag:b", "AND tag:a"] {
+            assert!(Query::parse(bad).is_err(), "{:?} should not parse", bad);
+        }
+        assert!(matches!(Query::parse("color:red"), Err(ThoughtGraphError::ParseError(message)) if message.contains("'color'")));
+    }
+
+    #[test]
+    fn test_parse_text() {
+        assert_eq!(
+            Query::parse("title:\"graph (theory)\" AND content:cycles").unwrap(),
+            Query::And(vec![
+                Box::new(Query::TitleContains("graph (theory)".to_string())),
+                Box::new(Query::ContentContains("cycles".to_string())),
+            ])
+        );
+        assert_eq!(Query::parse(r#"content:"say \"hi\" \\o/""#).unwrap(), Query::ContentContains(r#"say "hi" \o/"#.to_string()));
+        for text in ["x", "two words", "", "a\"b", "back\\slash", "(", "café"] {
+            let query = Query::Not(Box::new(Query::TitleContains(text.to_string())));
+            assert_eq!(Query::parse(&query.to_string()).unwrap(), query, "{}", query);
+        }
+        for bad in ["title:", "title:\"open", "title:a\"b", "content:\"a\"b", "title:\"a\\\""] {
+            assert!(matches!(Query::parse(bad), Err(ThoughtGraphError::ParseError(_))), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_regex() {
+        assert_eq!(
+            Query::parse(r#"content-regex:"(TODO|FIXME):" AND NOT title-regex:^Draft"#).unwrap(),
+            Query::And(vec![
+                Box::new(Query::ContentRegex("(TODO|FIXME):".to_string())),
+                Box::new(Query::Not(Box::new(Query::TitleRegex("^Draft".to_string())))),
+            ])
+        );
+        assert_eq!(Query::parse(r#"content-regex:"\d+ \"x\"""#).unwrap(), Query::ContentRegex(r#"\d+ "x""#.to_string()));
+        for pattern in [r"[A-Z]+-\d+", r"\d+ \\"] {
+            let query = Query::ContentRegex(pattern.to_string());
+            assert_eq!(Query::parse(&query.to_string()).unwrap(), query, "{}", query);
+        }
+        assert!(matches!(Query::parse("content-regex:\"(unclosed\""), Err(ThoughtGraphError::InvalidPattern(_))));
+    }
+
+    #[test]
+    fn test_parse_kinds() {
+        assert_eq!(
+            Query::parse("is:Untagged OR (is:orphan AND NOT tag:inbox)").unwrap(),
+            Query::Or(vec![
+                Box::new(Query::Untagged),
+                Box::new(Query::And(vec![
+                    Box::new(Query::Orphan),
+                    Box::new(Query::Not(Box::new(Query::Tag(TagID::new("inbox".to_string()))))),
+                ])),
+            ])
+        );
+        assert_eq!(Query::parse("is:untagged OR is:orphan").unwrap().to_string(), "is:untagged OR is:orphan");
+        assert_eq!(
+            Query::parse("is:all AND NOT tag:x OR id:index").unwrap(),
+            Query::Or(vec![
+                Box::new(Query::And(vec![
+                    Box::new(Query::All),
+                    Box::new(Query::Not(Box::new(Query::Tag(TagID::new("x".to_string()))))),
+                ])),
+                Box::new(Query::Id(ThoughtID::new("index".to_string()))),
+            ])
+        );
+        assert_eq!(Query::parse("id:a/b OR is:all").unwrap().to_string(), "id:a/b OR is:all");
+        assert!(matches!(Query::parse("id:two words"), Err(ThoughtGraphError::ParseError(_))));
+        assert!(matches!(Query::parse("is:lonely"), Err(ThoughtGraphError::ParseError(message)) if message.contains("'lonely'")));
+    }
+
+    #[test]
+    fn test_parse_globs() {
+        assert_eq!(
+            Query::parse("tag-glob:project/* OR id-glob:book-chapter-*").unwrap(),
+            Query::Or(vec![
+                Box::new(Query::TagGlob("project/*".to_string())),
+                Box::new(Query::IdGlob("book-chapter-*".to_string())),
+            ])
+        );
+        assert_eq!(Query::parse("NOT tag-glob:*draft*").unwrap().to_string(), "NOT tag-glob:*draft*");
+        assert!(Query::parse("id-glob:").is_err());
+    }
+
+    #[test]
+    fn test_parse_notes() {
+        assert_eq!(Query::parse("notes:contradicts").unwrap(), Query::ReferenceNotesContain("contradicts".to_string()));
+        let text = r#"NOT notes:"builds on" AND tag:rust"#;
+        assert_eq!(Query::parse(text).unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn test_parse_backlinks() {
+        assert_eq!(Query::parse("min-backlinks:5").unwrap(), Query::MinBacklinks(5));
+        let text = "max-backlinks:0 AND NOT is:orphan";
+        assert_eq!(Query::parse(text).unwrap().to_string(), text);
+        assert!(Query::parse("min-backlinks:many").is_err());
+        assert!(Query::parse("max-backlinks:-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_reachable() {
+        let root = ThoughtID::new("projects/x".to_string());
+        assert_eq!(Query::parse("reachable-from:projects/x").unwrap(), Query::ReachableFrom { id: root.clone(), depth: usize::MAX });
+        assert_eq!(Query::parse("Reachable-To[2]:projects/x").unwrap(), Query::ReachableTo { id: root, depth: 2 });
+        let text = "reachable-from[3]:a:b OR reachable-to:c";
+        assert_eq!(Query::parse(text).unwrap().to_string(), text);
+        for bad in ["reachable-from[]:a", "reachable-from[x]:a", "reachable-from[2:a", "tag[2]:a", "reachable-to:"] {
+            assert!(Query::parse(bad).is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_not() {
+        let tag = |name: &str| Box::new(Query::Tag(TagID::new(name.to_string())));
+        let not = |query: Box<Query>| Box::new(Query::Not(query));
+        assert_eq!(
+            Query::parse("tag:a AND NOT tag:b OR not not tag:c").unwrap(),
+            Query::Or(vec![Box::new(Query::And(vec![tag("a"), not(tag("b"))])), not(not(tag("c")))])
+        );
+        assert_eq!(Query::parse("NOT (tag:a OR tag:b)").unwrap(), *not(Box::new(Query::Or(vec![tag("a"), tag("b")]))));
+        let text = "NOT (tag:a OR NOT tag:b) AND NOT referenced-by:c";
+        assert_eq!(Query::parse(text).unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let message = |text: &str| match Query::parse(text) {
+            Err(ThoughtGraphError::ParseError(message)) => message,
+            result => panic!("{:?} parsed as {:?}", text, result),
+        };
+        assert_eq!(message("tag:a AND (tag:b OR"), "query: expected a condition at the end of the query at column 20\n  tag:a AND (tag:b OR\n                     ^");
+        assert!(message("tag:a AND (tag:b").starts_with("query: this '(' is never closed at column 11"));
+        assert!(message("tag:é AND OR tag:b").starts_with("query: expected a condition before 'OR' at column 11"));
+        assert!(message("tag:a) AND tag:b").starts_with("query: this ')' has no matching '(' at column 6"));
+    }
+}
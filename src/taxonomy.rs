@@ -0,0 +1,225 @@
+//! Tag taxonomies
+//!
+//! A taxonomy is the set of tags of a graph without the thoughts: each tag's description,
+//! parent, color and aliases. Exported from one graph and imported into another, it lets
+//! people who keep separate graphs tag their thoughts the same way. A `Taxonomy` can be
+//! serialized with any serde format; the `thoughts` tool reads and writes it as TOML or
+//! JSON, with a table or object per tag:
+//!
+//! ```toml
+//! [tags.rust]
+//! description = "The Rust programming language"
+//! parent = "programming"
+//! color = "red"
+//! aliases = ["rs"]
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{Command, Result, Tag, TagID, ThoughtGraph, ThoughtGraphError};
+
+/// A tag of a taxonomy: a tag without its timestamps
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaxonomyTag {
+    /// What the tag is for
+    #[serde(default)]
+    pub description: String,
+    /// The broader tag this tag is a kind of, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<TagID>,
+    /// The color to show the tag in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Other names the tag is known by
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+impl TaxonomyTag {
+    /// Whether `tag` has the description, parent, color and aliases of this tag
+    fn describes(&self, tag: &Tag) -> bool {
+        self.description == tag.description && self.parent == tag.parent && self.color == tag.color && self.aliases == tag.aliases
+    }
+}
+
+/// A set of tags, by ID
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Taxonomy {
+    #[serde(default)]
+    pub tags: BTreeMap<TagID, TaxonomyTag>,
+}
+
+/// The tags changed by `ThoughtGraph::import_taxonomy`, each in order of ID
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaxonomyImport {
+    /// Tags that did not exist before
+    pub created: Vec<TagID>,
+    /// Existing tags whose description, parent, color or aliases changed
+    pub updated: Vec<TagID>,
+}
+
+impl ThoughtGraph {
+    /// The taxonomy of the graph: all of its tags, without their timestamps.
+    pub fn taxonomy(&self) -> Taxonomy {
+        let tags = self
+            .tags
+            .iter()
+            .map(|(id, tag)| {
+                let entry = TaxonomyTag {
+                    description: tag.description.clone(),
+                    parent: tag.parent.clone(),
+                    color: tag.color.clone(),
+                    aliases: tag.aliases.clone(),
+                };
+                (id.clone(), entry)
+            })
+            .collect();
+        Taxonomy { tags }
+    }
+
+    /// Create and update the tags of a taxonomy, keeping the other tags of the graph.
+    ///
+    /// The changes are made as one batch of commands, validated as by `try_command`, so
+    /// either all tags are imported or none are.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxonomy` - The tags to import
+    ///
+    /// # Returns
+    ///
+    /// The tags created and updated, `InvalidTagID` if an ID or parent is malformed, or
+    /// `HierarchyCycle` if a tag would be its own ancestor
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::taxonomy::{Taxonomy, TaxonomyTag};
+    /// use thoughtgraph::{TagID, ThoughtGraph};
+    ///
+    /// let mut shared = ThoughtGraph::new();
+    /// shared.create_tag(TagID::new("programming".to_string()), "Code".to_string()).unwrap();
+    /// let mut taxonomy = shared.taxonomy();
+    /// let rust = TaxonomyTag { parent: Some(TagID::new("programming".to_string())), ..TaxonomyTag::default() };
+    /// taxonomy.tags.insert(TagID::new("rust".to_string()), rust);
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let imported = graph.import_taxonomy(&taxonomy).unwrap();
+    /// assert_eq!(imported.created.len(), 2);
+    /// assert_eq!(graph.tag_ancestors(&TagID::new("rust".to_string())), vec![&TagID::new("programming".to_string())]);
+    /// ```
+    pub fn import_taxonomy(&mut self, taxonomy: &Taxonomy) -> Result<TaxonomyImport> {
+        let now = Utc::now();
+        let mut imported = TaxonomyImport::default();
+        let mut commands = Vec::new();
+        for (id, entry) in &taxonomy.tags {
+            let tag = match self.tags.get(id) {
+                Some(tag) if entry.describes(tag) => continue,
+                Some(tag) => {
+                    imported.updated.push(id.clone());
+                    Tag { updated_at: now, ..tag.clone() }
+                },
+                None => {
+                    imported.created.push(id.clone());
+                    Tag::new(String::new())
+                },
+            };
+            let tag = Tag {
+                description: entry.description.clone(),
+                parent: entry.parent.clone(),
+                color: entry.color.clone(),
+                aliases: entry.aliases.clone(),
+                ..tag
+            };
+            commands.push(Command::PutTag { id: id.clone(), tag });
+        }
+        if !commands.is_empty() {
+            self.try_command(&Command::Batch(commands))?;
+        }
+        Ok(imported)
+    }
+
+    /// The parent of a tag, its parent, and so on, up to a tag without a parent or one
+    /// that does not exist
+    pub fn tag_ancestors(&self, id: &TagID) -> Vec<&TagID> {
+        let mut ancestors = Vec::new();
+        let mut seen = HashSet::from([id]);
+        let mut tag = self.tags.get(id);
+        while let Some(parent) = tag.and_then(|tag| tag.parent.as_ref()) {
+            if !seen.insert(parent) {
+                break;
+            }
+            ancestors.push(parent);
+            tag = self.tags.get(parent);
+        }
+        ancestors
+    }
+
+    /// The tags in the order of their hierarchy, each with its depth: every tag without
+    /// a parent in the graph, in order of ID, followed by its children, and so on.
+    pub fn tags_in_hierarchy(&self) -> Vec<(usize, &TagID, &Tag)> {
+        let mut children: BTreeMap<Option<&TagID>, Vec<&TagID>> = BTreeMap::new();
+        for (id, tag) in &self.tags {
+            let parent = tag.parent.as_ref().filter(|parent| self.tags.contains_key(*parent) && *parent != id);
+            children.entry(parent).or_default().push(id);
+        }
+        for ids in children.values_mut() {
+            ids.sort();
+        }
+
+        let mut ordered = Vec::with_capacity(self.tags.len());
+        let mut stack: Vec<(usize, &TagID)> = children.get(&None).into_iter().flatten().rev().map(|id| (0, *id)).collect();
+        while let Some((depth, id)) = stack.pop() {
+            ordered.push((depth, id, &self.tags[id]));
+            stack.extend(children.get(&Some(id)).into_iter().flatten().rev().map(|child| (depth + 1, *child)));
+        }
+        ordered
+    }
+}
+
+/// Read a taxonomy from JSON
+pub fn from_json(json: &str) -> Result<Taxonomy> {
+    serde_json::from_str(json).map_err(|e| ThoughtGraphError::ParseError(format!("taxonomy: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_taxonomy() {
+        let tag = |name: &str| TagID::new(name.to_string());
+        let taxonomy = from_json(
+            r#"{"tags": {
+                "rust": {"description": "Rust", "parent": "programming", "color": "red", "aliases": ["rs"]},
+                "programming": {"description": "Code"},
+                "async": {"parent": "rust"}
+            }}"#,
+        )
+        .unwrap();
+
+        let mut graph = ThoughtGraph::new();
+        graph.create_tag(tag("programming"), "Code".to_string()).unwrap();
+        graph.create_tag(tag("bread"), "Baking".to_string()).unwrap();
+        let imported = graph.import_taxonomy(&taxonomy).unwrap();
+        assert_eq!(imported.created, vec![tag("async"), tag("rust")]);
+        assert!(imported.updated.is_empty());
+        assert_eq!(graph.tags[&tag("rust")].aliases, vec!["rs".to_string()]);
+        assert_eq!(graph.taxonomy().tags.len(), 4);
+        let order: Vec<(usize, &str)> = graph.tags_in_hierarchy().into_iter().map(|(depth, id, _)| (depth, id.as_str())).collect();
+        assert_eq!(order, vec![(0, "bread"), (0, "programming"), (1, "rust"), (2, "async")]);
+        assert_eq!(graph.tag_ancestors(&tag("async")), vec![&tag("rust"), &tag("programming")]);
+
+        // Importing again changes nothing; a cycle is rejected as a whole
+        assert_eq!(graph.import_taxonomy(&taxonomy).unwrap(), TaxonomyImport::default());
+        let mut cycle = taxonomy.clone();
+        cycle.tags.get_mut(&tag("programming")).unwrap().parent = Some(tag("async"));
+        cycle.tags.get_mut(&tag("rust")).unwrap().description = "Changed".to_string();
+        assert!(matches!(graph.import_taxonomy(&cycle), Err(ThoughtGraphError::HierarchyCycle(_))));
+        assert_eq!(graph.tags[&tag("rust")].description, "Rust");
+        assert!(from_json("{\"tags\": {\"a b\": {}}}").is_ok_and(|taxonomy| graph.import_taxonomy(&taxonomy).is_err()));
+    }
+}
@@ -0,0 +1,199 @@
+//! Output templates
+//!
+//! Commands that list thoughts can print each one through a template instead of as a
+//! table, to feed other tools such as `fzf` or `awk`. A template is text with fields in
+//! braces, such as `{id}\t{title}\t{updated}`:
+//!
+//! * `{id}`, `{title}` and `{content}`
+//! * `{tags}`, `{references}` and `{backlinks}`, as comma-separated IDs
+//! * `{created}` and `{updated}`, as RFC 3339 times in UTC
+//!
+//! `\t`, `\n` and `\\` in a template are a tab, a line break and a backslash, and `{{`
+//! and `}}` are literal braces. Tabs and line breaks inside the values of fields are
+//! replaced with spaces, so that every thought stays on one line.
+
+use chrono::SecondsFormat;
+
+use crate::{Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// A field of a thought in a template
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Id,
+    Title,
+    Content,
+    Tags,
+    References,
+    Backlinks,
+    Created,
+    Updated,
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 8] = [
+        ("id", Field::Id),
+        ("title", Field::Title),
+        ("content", Field::Content),
+        ("tags", Field::Tags),
+        ("references", Field::References),
+        ("backlinks", Field::Backlinks),
+        ("created", Field::Created),
+        ("updated", Field::Updated),
+    ];
+}
+
+/// A part of a template
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// A template for printing thoughts, described in the `template` module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+fn template_error(message: String) -> ThoughtGraphError {
+    ThoughtGraphError::ParseError(format!("template: {}", message))
+}
+
+impl Template {
+    /// Parse a template.
+    ///
+    /// # Returns
+    ///
+    /// The template, or `ParseError` if a field is unknown or a brace is not closed
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::template::Template;
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = ThoughtID::new("rust".to_string());
+    /// graph.create_thought(id.clone(), Some("Rust".to_string()), "Ownership".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let template = Template::parse(r"{id}\t{title}").unwrap();
+    /// assert_eq!(template.render(&graph, &id, graph.get_thought(&id).unwrap()), "rust\tRust");
+    /// assert!(Template::parse("{author}").is_err());
+    /// ```
+    pub fn parse(text: &str) -> Result<Template> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    },
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(template_error(format!("'{{{}' is missing a '}}'", name))),
+                        }
+                    }
+                    let field = Field::ALL.iter().find(|(field, _)| *field == name.trim()).map(|(_, field)| *field);
+                    let field = field.ok_or_else(|| {
+                        let names: Vec<&str> = Field::ALL.iter().map(|(name, _)| *name).collect();
+                        template_error(format!("unknown field '{{{}}}' (expected one of {})", name, names.join(", ")))
+                    })?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field));
+                },
+                '}' => return Err(template_error("unmatched '}' (write '}}' for a brace)".to_string())),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Print a thought through the template
+    pub fn render(&self, graph: &ThoughtGraph, id: &ThoughtID, thought: &Thought) -> String {
+        let join = |ids: Vec<&str>| ids.join(",");
+        let mut output = String::new();
+        for part in &self.parts {
+            let value = match part {
+                Part::Text(text) => {
+                    output.push_str(text);
+                    continue;
+                },
+                Part::Field(Field::Id) => id.id.to_string(),
+                Part::Field(Field::Title) => thought.title.clone().unwrap_or_default(),
+                Part::Field(Field::Content) => thought.contents.clone(),
+                Part::Field(Field::Tags) => join(thought.tags.iter().map(|tag| tag.as_str()).collect()),
+                Part::Field(Field::References) => join(thought.references.iter().map(|r| r.id.as_str()).collect()),
+                Part::Field(Field::Backlinks) => join(graph.get_backlinks(id).iter().map(|id| id.as_str()).collect()),
+                Part::Field(Field::Created) => thought.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                Part::Field(Field::Updated) => thought.updated_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            };
+            output.extend(value.chars().map(|c| if c == '\t' || c == '\n' || c == '\r' { ' ' } else { c }));
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Reference, TagID};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_render_template() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let at = Utc.with_ymd_and_hms(2025, 2, 26, 9, 30, 0).unwrap();
+        graph.create_tag(TagID::new("a".to_string()), String::new()).unwrap();
+        graph.create_tag(TagID::new("b".to_string()), String::new()).unwrap();
+        graph.create_thought(id("target"), None, String::new(), vec![], vec![]).unwrap();
+        graph
+            .create_thought(
+                id("note"),
+                None,
+                "Line one\n\tLine two".to_string(),
+                vec![TagID::new("a".to_string()), TagID::new("b".to_string())],
+                vec![Reference::new(id("target"), String::new(), at)],
+            )
+            .unwrap();
+        let render = |template: &str, name: &str| {
+            Template::parse(template).unwrap().render(&graph, &id(name), graph.get_thought(&id(name)).unwrap())
+        };
+
+        assert_eq!(render(r"{id}\t{title}|{tags}\n", "note"), "note\t|a,b\n");
+        assert_eq!(render("{content}", "note"), "Line one  Line two");
+        assert_eq!(render("{references} {{ {backlinks} }}", "note"), "target {  }");
+        assert_eq!(render("{ backlinks }", "target"), "note");
+        assert!(render("{created}", "note").ends_with('Z'));
+        assert_eq!(render(r"C:\path", "note"), r"C:\path");
+
+        for bad in ["{author}", "{id", "id}"] {
+            assert!(matches!(Template::parse(bad), Err(ThoughtGraphError::ParseError(_))), "{:?} should not parse", bad);
+        }
+    }
+}
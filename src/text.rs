@@ -0,0 +1,308 @@
+//! Text processing utilities
+//!
+//! This module contains the tokenizer shared by the features that need to reason about
+//! the words in a thought, such as tag suggestions, keyword extraction and stemmed
+//! search, and the Unicode normalization that makes "café" typed one way match "café"
+//! typed another, and "cafe" too where accents are ignored. The tokenizer can also reduce
+//! English words to their stems, so that "running", "runs" and "ran" are the same word.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Common English words that carry little meaning on their own
+pub const STOP_WORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "also", "am", "an", "and",
+    "any", "are", "as", "at", "be", "because", "been", "before", "being", "below",
+    "between", "both", "but", "by", "can", "could", "did", "do", "does", "doing", "down",
+    "during", "each", "few", "for", "from", "further", "had", "has", "have", "having", "he",
+    "her", "here", "hers", "herself", "him", "himself", "his", "how", "i", "if", "in",
+    "into", "is", "it", "its", "itself", "just", "me", "more", "most", "my", "myself", "no",
+    "nor", "not", "now", "of", "off", "on", "once", "only", "or", "other", "our", "ours",
+    "ourselves", "out", "over", "own", "same", "she", "should", "so", "some", "such",
+    "than", "that", "the", "their", "theirs", "them", "themselves", "then", "there",
+    "these", "they", "this", "those", "through", "to", "too", "under", "until", "up",
+    "very", "was", "we", "were", "what", "when", "where", "which", "while", "who", "whom",
+    "why", "will", "with", "would", "you", "your", "yours", "yourself", "yourselves",
+];
+
+/// Minimum number of characters for a token to be considered meaningful
+const MIN_TOKEN_LENGTH: usize = 2;
+
+/// Irregular English word forms and the word they are a form of
+const IRREGULAR_FORMS: &[(&str, &str)] = &[
+    ("ran", "run"), ("went", "go"), ("gone", "go"), ("began", "begin"), ("begun", "begin"),
+    ("wrote", "write"), ("written", "write"), ("took", "take"), ("taken", "take"),
+    ("gave", "give"), ("given", "give"), ("saw", "see"), ("seen", "see"), ("knew", "know"),
+    ("known", "know"), ("grew", "grow"), ("grown", "grow"), ("chose", "choose"),
+    ("chosen", "choose"), ("spoke", "speak"), ("spoken", "speak"), ("ate", "eat"),
+    ("eaten", "eat"), ("made", "make"), ("built", "build"), ("found", "find"),
+    ("children", "child"), ("people", "person"), ("men", "man"), ("women", "woman"),
+    ("mice", "mouse"), ("feet", "foot"), ("teeth", "tooth"),
+];
+
+/// Splits text into the words that matter when comparing texts, as `tokenize` does, with
+/// a choice of stop words and of whether to reduce words to their stems.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::text::Tokenizer;
+///
+/// let tokenizer = Tokenizer::stemming();
+/// assert_eq!(tokenizer.tokenize("She ran; they are running"), vec!["run", "run"]);
+///
+/// let mut tokenizer = Tokenizer::default();
+/// tokenizer.stop_words.insert("rust".to_string());
+/// assert_eq!(tokenizer.tokenize("Rust lifetimes"), vec!["lifetimes"]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tokenizer {
+    /// Words left out, written in lowercase without accents; `STOP_WORDS` by default
+    pub stop_words: HashSet<String>,
+    /// Whether to reduce words to their stems with `stem`
+    pub stem: bool,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self { stop_words: STOP_WORDS.iter().map(|word| word.to_string()).collect(), stem: false }
+    }
+}
+
+impl Tokenizer {
+    /// A tokenizer that leaves out `STOP_WORDS` and reduces words to their stems
+    pub fn stemming() -> Self {
+        Self { stem: true, ..Self::default() }
+    }
+
+    /// Split text into lowercase word tokens without diacritics, dropping stop words and
+    /// very short tokens, and stemming the rest if asked to
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        tokens(text, |word| self.stop_words.contains(word), self.stem)
+    }
+}
+
+/// Put text in Unicode normalization form C, so that characters which can be written
+/// either precomposed or with combining marks are always written the same way.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::text::normalize;
+///
+/// assert_eq!(normalize("cafe\u{301}"), "caf\u{e9}");
+/// ```
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    match is_nfc(text) {
+        true => Cow::Borrowed(text),
+        false => Cow::Owned(text.nfc().collect()),
+    }
+}
+
+/// Normalize text for comparisons that ignore case and, optionally, accents.
+///
+/// The text is lowercased, then normalized. Removing diacritics decomposes it first
+/// (NFKD), which also turns compatibility characters such as the "ﬁ" ligature into their
+/// plain equivalents, and drops the combining marks.
+///
+/// # Arguments
+///
+/// * `text` - The text to fold
+/// * `strip_diacritics` - Whether to remove accents and other diacritics
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::text::fold;
+///
+/// assert_eq!(fold("Café Crème", true), "cafe creme");
+/// assert_eq!(fold("Café Crème", false), "café crème");
+/// ```
+pub fn fold(text: &str, strip_diacritics: bool) -> String {
+    match strip_diacritics {
+        true => self::strip_diacritics(&text.to_lowercase()),
+        false => normalize(&text.to_lowercase()).into_owned(),
+    }
+}
+
+/// Remove accents and other diacritics from text, keeping its case, after decomposing
+/// it (NFKD)
+pub fn strip_diacritics(text: &str) -> String {
+    text.nfkd().filter(|c| !is_combining_mark(*c)).collect()
+}
+
+/// Split text into lowercase word tokens without diacritics, dropping stop words and very
+/// short tokens.
+///
+/// Words are sequences of alphanumeric characters; hyphens and underscores inside a word
+/// are kept so that identifiers such as `async-rust` survive as a single token.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::text::tokenize;
+///
+/// let tokens = tokenize("The borrow-checker is part of Rust!");
+/// assert_eq!(tokens, vec!["borrow-checker", "part", "rust"]);
+/// ```
+pub fn tokenize(text: &str) -> Vec<String> {
+    tokens(text, |word| STOP_WORDS.contains(&word), false)
+}
+
+/// Split text into tokens as `Tokenizer::stemming` does, leaving out the given stop
+/// words, or `STOP_WORDS` if there are none
+pub(crate) fn stemmed_tokens(text: &str, stop_words: Option<&HashSet<String>>) -> Vec<String> {
+    match stop_words {
+        Some(stop_words) => tokens(text, |word| stop_words.contains(word), true),
+        None => tokens(text, |word| STOP_WORDS.contains(&word), true),
+    }
+}
+
+fn tokens(text: &str, is_stop_word: impl Fn(&str) -> bool, stem: bool) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+        .map(|word| word.trim_matches(|c| c == '-' || c == '_'))
+        .filter(|word| word.chars().count() >= MIN_TOKEN_LENGTH)
+        .map(|word| fold(word, true))
+        .filter(|word| !is_stop_word(word))
+        .map(|word| if stem { self::stem(&word) } else { word })
+        .collect()
+}
+
+/// Reduce a lowercase English word to its stem, so that the forms of a word share one
+/// stem: "running", "runs" and "ran" all become "run".
+///
+/// Stems are only meant to be compared with each other and need not be words themselves
+/// ("making" and "make" become "mak"). Only the common irregular forms are known, and
+/// words in other languages are stemmed as if they were English.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::text::stem;
+///
+/// assert_eq!(stem("running"), "run");
+/// assert_eq!(stem("studies"), stem("studied"));
+/// assert_eq!(stem("boxes"), stem("box"));
+/// ```
+pub fn stem(word: &str) -> String {
+    let has_vowel = |stem: &str| stem.chars().any(|c| "aeiouy".contains(c));
+    let mut word = match IRREGULAR_FORMS.iter().find(|(form, _)| *form == word) {
+        Some((_, base)) => base.to_string(),
+        None => word.to_string(),
+    };
+
+    // Plurals and the third person
+    if let Some(stem) = word.strip_suffix("sses") {
+        word = format!("{}ss", stem);
+    } else if let Some(stem) = word.strip_suffix("ies").or_else(|| word.strip_suffix("ied")) {
+        word = format!("{}{}", stem, if stem.chars().count() > 1 { "y" } else { "ie" });
+    } else if word.ends_with('s') && !["ss", "us", "is"].iter().any(|end| word.ends_with(end)) && word.chars().count() > 3 {
+        word.pop();
+    }
+
+    // The past tense and the present participle
+    if let Some(stem) = word.strip_suffix("eed") {
+        if has_vowel(stem) {
+            word.pop();
+        }
+    } else if let Some(stem) = word.strip_suffix("ed").or_else(|| word.strip_suffix("ing")) {
+        if stem.chars().count() >= 2 && has_vowel(stem) {
+            let mut stem = stem.to_string();
+            let mut last = stem.chars().rev();
+            if let (Some(a), Some(b)) = (last.next(), last.next()) {
+                if a == b && !"aeiouylsz".contains(a) {
+                    stem.pop();
+                }
+            }
+            word = stem;
+        }
+    }
+
+    if word.ends_with('e') && word.chars().count() > 2 {
+        word.pop();
+    }
+    word
+}
+
+/// Extract the most frequent keywords of a text.
+///
+/// # Arguments
+///
+/// * `text` - The text to analyze
+/// * `limit` - The maximum number of keywords to return
+///
+/// # Returns
+///
+/// Keywords with their number of occurrences, most frequent first. Ties are broken
+/// alphabetically so the result is deterministic.
+pub fn keywords(text: &str, limit: usize) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for token in tokenize(text) {
+        *counts.entry(token).or_default() += 1;
+    }
+
+    let mut keywords: Vec<(String, usize)> = counts.into_iter().collect();
+    keywords.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    keywords.truncate(limit);
+    keywords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_drops_stop_words_and_punctuation() {
+        assert_eq!(
+            tokenize("I think that async/await, in Rust, is great."),
+            vec!["think", "async", "await", "rust", "great"]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_keeps_inner_hyphens() {
+        assert_eq!(tokenize("--kebab-case_name-- x"), vec!["kebab-case_name"]);
+    }
+
+    #[test]
+    fn test_fold_and_tokenize_unicode() {
+        let decomposed = "Cafe\u{301} NAI\u{308}VE";
+        assert_eq!(normalize(decomposed), "Caf\u{e9} NA\u{cf}VE");
+        assert_eq!(fold(decomposed, false), fold("Café Naïve", false));
+        assert_eq!(fold(decomposed, true), "cafe naive");
+        assert_eq!(fold("Ångström ﬁle", true), "angstrom file");
+        assert_eq!(tokenize("Crème brûlée"), vec!["creme", "brulee"]);
+        assert_eq!(crate::ThoughtID::new(decomposed.to_string()).as_str(), "Caf\u{e9} NA\u{cf}VE");
+    }
+
+    #[test]
+    fn test_stem() {
+        for (forms, expected) in [
+            (&["run", "runs", "running", "ran"][..], "run"),
+            (&["make", "makes", "making", "made"][..], "mak"),
+            (&["study", "studies", "studied", "studying"][..], "study"),
+            (&["class", "classes"][..], "class"),
+            (&["stop", "stopped", "stopping"][..], "stop"),
+            (&["fall", "falling"][..], "fall"),
+            (&["agree", "agreed"][..], "agre"),
+            (&["need", "needs"][..], "need"),
+        ] {
+            for form in forms {
+                assert_eq!(stem(form), expected, "{}", form);
+            }
+        }
+        // Words that only look like inflected forms are left alone
+        for word in ["thing", "red", "bus", "gas"] {
+            assert_eq!(stem(word), word);
+        }
+    }
+
+    #[test]
+    fn test_keywords_by_frequency() {
+        let kw = keywords("rust tokio rust async tokio rust", 2);
+        assert_eq!(kw, vec![("rust".to_string(), 3), ("tokio".to_string(), 2)]);
+    }
+}
@@ -0,0 +1,375 @@
+//! Topic clustering
+//!
+//! Graphs that grow organically tend to develop clusters of closely related thoughts
+//! that were never given a common tag. This module finds those clusters and gives each
+//! one a readable label, which helps when reorganizing a graph.
+//!
+//! Thoughts are connected by weighted edges, either structural (references, shared tags
+//! and shared external links) or semantic (similar embeddings), and then grouped into
+//! communities that are more densely connected internally than with the rest of the
+//! graph.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::semantic::{cosine_similarity, VectorIndex};
+use crate::text::keywords;
+use crate::{ThoughtGraph, ThoughtID};
+
+/// Minimum number of thoughts in a topic
+pub const MIN_TOPIC_SIZE: usize = 2;
+
+/// Maximum number of passes over the thoughts when forming communities
+const MAX_ROUNDS: usize = 20;
+
+/// Weight of a reference between two thoughts
+const REFERENCE_WEIGHT: f32 = 1.0;
+
+/// Weight of an external link mentioned by two thoughts
+const SHARED_LINK_WEIGHT: f32 = 1.0;
+
+/// Total weight spread over the thoughts sharing a tag; rare tags connect more strongly
+const SHARED_TAG_WEIGHT: f32 = 2.0;
+
+/// A group of related thoughts
+#[derive(Clone, Debug, PartialEq)]
+pub struct Topic {
+    /// A short description of the topic, derived from its tags or keywords
+    pub label: String,
+    /// The thoughts in the topic, sorted by ID
+    pub thoughts: Vec<ThoughtID>,
+}
+
+/// Weighted, undirected edges between the thoughts of a graph, by position in `ids`
+struct SimilarityGraph<'a> {
+    ids: Vec<&'a ThoughtID>,
+    edges: Vec<HashMap<usize, f32>>,
+}
+
+impl<'a> SimilarityGraph<'a> {
+    fn new(graph: &'a ThoughtGraph) -> Self {
+        let mut ids: Vec<&ThoughtID> = graph.thoughts.keys().collect();
+        ids.sort();
+        let edges = vec![HashMap::new(); ids.len()];
+        Self { ids, edges }
+    }
+
+    fn position(&self, id: &ThoughtID) -> Option<usize> {
+        self.ids.binary_search(&id).ok()
+    }
+
+    fn connect(&mut self, a: usize, b: usize, weight: f32) {
+        if a != b {
+            *self.edges[a].entry(b).or_default() += weight;
+            *self.edges[b].entry(a).or_default() += weight;
+        }
+    }
+
+    /// Connect every pair of thoughts in a group with the given weight
+    fn connect_all(&mut self, group: &[usize], weight: f32) {
+        for (i, a) in group.iter().enumerate() {
+            for b in &group[i + 1..] {
+                self.connect(*a, *b, weight);
+            }
+        }
+    }
+
+    /// Group the thoughts into communities and return the groups of positions.
+    ///
+    /// Each thought starts in its own community and is repeatedly moved to the neighboring
+    /// community that increases modularity the most, i.e. the one it is connected to more
+    /// strongly than would be expected given the community's total connectivity. Unlike
+    /// plain label propagation, this keeps a single strong bridge from merging two
+    /// otherwise separate groups.
+    fn clusters(&self) -> Vec<Vec<usize>> {
+        let degrees: Vec<f32> = self.edges.iter().map(|edges| edges.values().sum()).collect();
+        let total: f32 = degrees.iter().sum();
+        let mut community: Vec<usize> = (0..self.ids.len()).collect();
+
+        if total > 0.0 {
+            let mut community_degree = degrees.clone();
+
+            for _ in 0..MAX_ROUNDS {
+                let mut moved = false;
+                for node in 0..self.ids.len() {
+                    let own = community[node];
+                    let mut links: HashMap<usize, f32> = HashMap::new();
+                    for (neighbor, weight) in &self.edges[node] {
+                        *links.entry(community[*neighbor]).or_default() += weight;
+                    }
+                    community_degree[own] -= degrees[node];
+
+                    let gain = |c: usize| {
+                        links.get(&c).copied().unwrap_or_default()
+                            - degrees[node] * community_degree[c] / total
+                    };
+                    let mut candidates: Vec<usize> = links.keys().copied().collect();
+                    candidates.sort_unstable();
+
+                    let mut best = own;
+                    let mut best_gain = gain(own);
+                    for c in candidates {
+                        let g = gain(c);
+                        if g > best_gain + f32::EPSILON {
+                            best = c;
+                            best_gain = g;
+                        }
+                    }
+
+                    community_degree[best] += degrees[node];
+                    if best != own {
+                        community[node] = best;
+                        moved = true;
+                    }
+                }
+                if !moved {
+                    break;
+                }
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (node, c) in community.into_iter().enumerate() {
+            groups.entry(c).or_default().push(node);
+        }
+        groups.into_values().collect()
+    }
+}
+
+impl ThoughtGraph {
+    /// Cluster thoughts into topics using the structure of the graph.
+    ///
+    /// Thoughts are considered related when one references the other, when they share
+    /// tags (rare tags count more than common ones) and when they mention the same
+    /// external links.
+    ///
+    /// # Returns
+    ///
+    /// Topics with at least `MIN_TOPIC_SIZE` thoughts, largest first. Thoughts without
+    /// any related thoughts are not part of any topic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, TagID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let rust = TagID::new("rust".to_string());
+    /// graph.create_tag(rust.clone(), "Rust".to_string()).unwrap();
+    /// for id in ["borrowing", "lifetimes"] {
+    ///     graph.create_thought(ThoughtID::new(id.to_string()), None, id.to_string(), vec![rust.clone()], vec![]).unwrap();
+    /// }
+    /// graph.create_thought(ThoughtID::new("bread".to_string()), None, "Sourdough".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let topics = graph.topics();
+    /// assert_eq!(topics.len(), 1);
+    /// assert_eq!(topics[0].label, "rust");
+    /// assert_eq!(topics[0].thoughts.len(), 2);
+    /// ```
+    pub fn topics(&self) -> Vec<Topic> {
+        let mut similarity = SimilarityGraph::new(self);
+
+        for (index, id) in similarity.ids.clone().into_iter().enumerate() {
+            let thought = &self.thoughts[id];
+            for reference in &thought.references {
+                if let Some(target) = similarity.position(&reference.id) {
+                    similarity.connect(index, target, REFERENCE_WEIGHT);
+                }
+            }
+        }
+
+        let mut tag_members: HashMap<_, Vec<usize>> = HashMap::new();
+        for (index, id) in similarity.ids.iter().enumerate() {
+            let unique_tags: HashSet<_> = self.thoughts[*id].tags.iter().collect();
+            for tag in unique_tags {
+                tag_members.entry(tag).or_default().push(index);
+            }
+        }
+        for members in tag_members.values().filter(|members| members.len() > 1) {
+            similarity.connect_all(members, SHARED_TAG_WEIGHT / members.len() as f32);
+        }
+
+        for (_, ids) in self.external_links() {
+            let members: Vec<usize> = ids.iter().filter_map(|id| similarity.position(id)).collect();
+            similarity.connect_all(&members, SHARED_LINK_WEIGHT);
+        }
+
+        self.label_topics(&similarity)
+    }
+
+    /// Cluster thoughts into topics using their embeddings.
+    ///
+    /// Two thoughts are related when the cosine similarity of their vectors is at least
+    /// `threshold`. Thoughts missing from the index are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An up-to-date vector index of the graph
+    /// * `threshold` - The minimum similarity for two thoughts to be related
+    ///
+    /// # Returns
+    ///
+    /// Topics with at least `MIN_TOPIC_SIZE` thoughts, largest first
+    pub fn topics_by_similarity(&self, index: &VectorIndex, threshold: f32) -> Vec<Topic> {
+        let mut similarity = SimilarityGraph::new(self);
+        let vectors: Vec<Option<&[f32]>> = similarity.ids.iter().map(|id| index.vector(id)).collect();
+
+        for a in 0..vectors.len() {
+            for b in a + 1..vectors.len() {
+                if let (Some(va), Some(vb)) = (vectors[a], vectors[b]) {
+                    let score = cosine_similarity(va, vb);
+                    if score >= threshold {
+                        similarity.connect(a, b, score);
+                    }
+                }
+            }
+        }
+
+        self.label_topics(&similarity)
+    }
+
+    /// Cluster a similarity graph and label the resulting topics
+    fn label_topics(&self, similarity: &SimilarityGraph) -> Vec<Topic> {
+        let mut topics: Vec<Topic> = similarity
+            .clusters()
+            .into_iter()
+            .filter(|members| members.len() >= MIN_TOPIC_SIZE)
+            .map(|members| {
+                let mut thoughts: Vec<ThoughtID> =
+                    members.iter().map(|index| similarity.ids[*index].clone()).collect();
+                thoughts.sort();
+                Topic { label: self.topic_label(&thoughts), thoughts }
+            })
+            .collect();
+
+        topics.sort_by(|a, b| b.thoughts.len().cmp(&a.thoughts.len()).then_with(|| a.label.cmp(&b.label)));
+        topics
+    }
+
+    /// Describe a group of thoughts by the tags most of them share, or else by the
+    /// most frequent keywords of their titles and content
+    fn topic_label(&self, members: &[ThoughtID]) -> String {
+        let mut tag_counts: HashMap<&str, usize> = HashMap::new();
+        for id in members {
+            let unique_tags: HashSet<&str> = self.thoughts[id].tags.iter().map(|t| t.as_str()).collect();
+            for tag in unique_tags {
+                *tag_counts.entry(tag).or_default() += 1;
+            }
+        }
+
+        let mut common_tags: Vec<(&str, usize)> = tag_counts
+            .into_iter()
+            .filter(|(_, count)| count * 2 >= members.len())
+            .collect();
+        common_tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        if !common_tags.is_empty() {
+            let labels: Vec<&str> = common_tags.iter().take(2).map(|(tag, _)| *tag).collect();
+            return labels.join(" / ");
+        }
+
+        let text: Vec<String> = members
+            .iter()
+            .map(|id| {
+                let thought = &self.thoughts[id];
+                format!("{} {}", thought.title.as_deref().unwrap_or_default(), thought.contents)
+            })
+            .collect();
+        let words: Vec<String> = keywords(&text.join("\n"), 3).into_iter().map(|(word, _)| word).collect();
+        if words.is_empty() {
+            "untitled".to_string()
+        } else {
+            words.join(", ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic::HashingEmbedder;
+    use crate::{Reference, TagID};
+    use chrono::Utc;
+
+    fn add(graph: &mut ThoughtGraph, id: &str, contents: &str, tags: &[&str], refs: &[&str]) {
+        let tags: Vec<TagID> = tags.iter().map(|t| TagID::new(t.to_string())).collect();
+        for tag in &tags {
+            if !graph.tags.contains_key(tag) {
+                graph.create_tag(tag.clone(), String::new()).unwrap();
+            }
+        }
+        let refs = refs
+            .iter()
+            .map(|r| Reference::new(ThoughtID::new(r.to_string()), String::new(), Utc::now()))
+            .collect();
+        graph
+            .create_thought(ThoughtID::new(id.to_string()), None, contents.to_string(), tags, refs)
+            .unwrap();
+    }
+
+    fn ids(topic: &Topic) -> Vec<&str> {
+        topic.thoughts.iter().map(|id| id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_references_and_links_form_topics() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "a", "consensus protocols", &[], &[]);
+        add(&mut graph, "b", "raft leader election", &[], &["a"]);
+        add(&mut graph, "c", "paxos", &[], &["b"]);
+        add(&mut graph, "x", "see https://bread.example", &[], &[]);
+        add(&mut graph, "y", "also https://bread.example", &[], &[]);
+        add(&mut graph, "lonely", "nothing related", &[], &[]);
+
+        let topics = graph.topics();
+        assert_eq!(topics.len(), 2);
+        assert_eq!(ids(&topics[0]), vec!["a", "b", "c"]);
+        assert_eq!(ids(&topics[1]), vec!["x", "y"]);
+        // Without common tags, topics are labeled by keywords
+        assert_eq!(topics[1].label, "bread, example, https");
+    }
+
+    #[test]
+    fn test_tag_labels() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "a", "one", &["rust", "async"], &[]);
+        add(&mut graph, "b", "two", &["rust", "async"], &[]);
+        add(&mut graph, "c", "three", &["rust"], &[]);
+
+        let topics = graph.topics();
+        assert_eq!(topics.len(), 1);
+        assert_eq!(topics[0].label, "rust / async");
+    }
+
+    #[test]
+    fn test_weak_bridges_do_not_merge_topics() {
+        let mut graph = ThoughtGraph::new();
+        for id in ["a1", "a2", "a3"] {
+            add(&mut graph, id, id, &["alpha"], &[]);
+        }
+        for id in ["b1", "b2", "b3"] {
+            add(&mut graph, id, id, &["beta"], &[]);
+        }
+        // A single reference between the two groups
+        add(&mut graph, "a4", "a4", &["alpha"], &["b1"]);
+
+        let topics = graph.topics();
+        assert_eq!(topics.len(), 2);
+        assert_eq!(topics[0].label, "alpha");
+        assert_eq!(topics[1].label, "beta");
+    }
+
+    #[test]
+    fn test_topics_by_similarity() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "r1", "retry failed requests with backoff", &[], &[]);
+        add(&mut graph, "r2", "retrying failed requests needs jitter", &[], &[]);
+        add(&mut graph, "g1", "planting tomatoes in the garden", &[], &[]);
+
+        let embedder = HashingEmbedder::default();
+        let mut index = VectorIndex::new();
+        index.update(&graph, &embedder).unwrap();
+
+        let topics = graph.topics_by_similarity(&index, 0.3);
+        assert_eq!(topics.len(), 1);
+        assert_eq!(ids(&topics[0]), vec!["r1", "r2"]);
+    }
+}
@@ -0,0 +1,171 @@
+//! Transclusion (embedding) of thoughts into one another
+//!
+//! This module implements the `{{embed:thought-id}}` syntax, which lets a thought
+//! pull in the full content of another thought when it is rendered or exported.
+//! Composite documents can therefore be assembled from small, atomic notes while
+//! each note is still maintained in exactly one place.
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::{Result, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Returns the compiled pattern matching `{{embed:thought-id}}` directives
+fn embed_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\{\{embed:([^{}\s]+)\}\}").unwrap())
+}
+
+/// Extract the IDs of all thoughts embedded in the given content.
+///
+/// IDs are returned in the order in which they appear, including duplicates.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::transclusion::extract_embeds;
+///
+/// let embeds = extract_embeds("Intro\n{{embed:part-one}}\n{{embed:part-two}}");
+/// assert_eq!(embeds.len(), 2);
+/// assert_eq!(embeds[0].as_str(), "part-one");
+/// ```
+pub fn extract_embeds(contents: &str) -> Vec<ThoughtID> {
+    embed_pattern()
+        .captures_iter(contents)
+        .filter_map(|cap| cap.get(1))
+        .map(|m| ThoughtID::new(m.as_str().to_string()))
+        .collect()
+}
+
+impl ThoughtGraph {
+    /// Render the content of a thought with all `{{embed:thought-id}}` directives resolved.
+    ///
+    /// Each directive is replaced by the (recursively rendered) content of the embedded
+    /// thought. Directives pointing at thoughts that do not exist are left untouched so
+    /// that the gap stays visible in the rendered output.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the thought to render
+    ///
+    /// # Returns
+    ///
+    /// The rendered content, `ThoughtNotFound` if `id` does not exist, or
+    /// `TransclusionCycle` if a thought ends up embedding itself.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let part = ThoughtID::new("part".to_string());
+    /// let essay = ThoughtID::new("essay".to_string());
+    ///
+    /// graph.create_thought(part.clone(), None, "Atomic note".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(essay.clone(), None, "Intro\n{{embed:part}}".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// assert_eq!(graph.render_contents(&essay).unwrap(), "Intro\nAtomic note");
+    /// ```
+    pub fn render_contents(&self, id: &ThoughtID) -> Result<String> {
+        let mut stack = Vec::new();
+        self.render_with_stack(id, &mut stack)
+    }
+
+    /// Recursive helper for `render_contents` that tracks the chain of embeds being rendered
+    fn render_with_stack(&self, id: &ThoughtID, stack: &mut Vec<ThoughtID>) -> Result<String> {
+        if stack.contains(id) {
+            let mut chain: Vec<&str> = stack.iter().map(|t| t.as_str()).collect();
+            chain.push(&id.id);
+            return Err(ThoughtGraphError::TransclusionCycle(chain.join(" -> ")));
+        }
+
+        let thought = self
+            .get_thought(id)
+            .ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+
+        stack.push(id.clone());
+
+        let mut rendered = String::with_capacity(thought.contents.len());
+        let mut last_end = 0;
+        for cap in embed_pattern().captures_iter(&thought.contents) {
+            let directive = cap.get(0).unwrap();
+            let embedded_id = ThoughtID::new(cap[1].to_string());
+
+            rendered.push_str(&thought.contents[last_end..directive.start()]);
+            if self.thoughts.contains_key(&embedded_id) {
+                let embedded = self.render_with_stack(&embedded_id, stack)?;
+                rendered.push_str(&embedded);
+            } else {
+                // Leave directives to missing thoughts visible
+                rendered.push_str(directive.as_str());
+            }
+            last_end = directive.end();
+        }
+        rendered.push_str(&thought.contents[last_end..]);
+
+        stack.pop();
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(graph: &mut ThoughtGraph, id: &str, contents: &str) -> ThoughtID {
+        let thought_id = ThoughtID::new(id.to_string());
+        graph
+            .create_thought(thought_id.clone(), None, contents.to_string(), vec![], vec![])
+            .unwrap();
+        thought_id
+    }
+
+    #[test]
+    fn test_nested_embeds() {
+        let mut graph = ThoughtGraph::new();
+        add(&mut graph, "leaf", "leaf text");
+        add(&mut graph, "middle", "before {{embed:leaf}} after");
+        let root = add(&mut graph, "root", "# Root\n{{embed:middle}}\n{{embed:leaf}}");
+
+        let rendered = graph.render_contents(&root).unwrap();
+        assert_eq!(rendered, "# Root\nbefore leaf text after\nleaf text");
+    }
+
+    #[test]
+    fn test_missing_embed_is_left_in_place() {
+        let mut graph = ThoughtGraph::new();
+        let root = add(&mut graph, "root", "see {{embed:nowhere}}");
+
+        assert_eq!(graph.render_contents(&root).unwrap(), "see {{embed:nowhere}}");
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        let mut graph = ThoughtGraph::new();
+        let a = add(&mut graph, "a", "A embeds {{embed:b}}");
+        add(&mut graph, "b", "B embeds {{embed:a}}");
+
+        match graph.render_contents(&a) {
+            Err(ThoughtGraphError::TransclusionCycle(chain)) => assert_eq!(chain, "a -> b -> a"),
+            other => panic!("expected a cycle error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_self_embed_is_a_cycle() {
+        let mut graph = ThoughtGraph::new();
+        let a = add(&mut graph, "a", "{{embed:a}}");
+
+        assert!(matches!(
+            graph.render_contents(&a),
+            Err(ThoughtGraphError::TransclusionCycle(_))
+        ));
+    }
+
+    #[test]
+    fn test_extract_embeds() {
+        let embeds = extract_embeds("{{embed:one}} text {{embed:two}} {{embed:}}");
+        assert_eq!(embeds, vec![ThoughtID::new("one".to_string()), ThoughtID::new("two".to_string())]);
+    }
+}
@@ -0,0 +1,179 @@
+//! The trash
+//!
+//! Deleting a thought with `ThoughtGraph::trash_thought` keeps a copy of it in the
+//! graph's trash, from which it can be restored with the backlinks it had. `Command::
+//! DeleteThought` still removes a thought for good. Trashed thoughts are kept until the
+//! trash is emptied, either completely or of the thoughts deleted before some time.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{ChangeSet, Command, Result, Thought, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// A deleted thought kept in the trash
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrashedThought {
+    /// The thought as it was when it was deleted
+    pub thought: Thought,
+    /// When the thought was deleted
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl ThoughtGraph {
+    /// Delete a thought, keeping a copy in the trash.
+    ///
+    /// If the trash already holds a thought with the same ID, it is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The thought to delete
+    /// * `at` - The time of deletion
+    ///
+    /// # Returns
+    ///
+    /// The changes made, `ThoughtNotFound` if the thought does not exist, or
+    /// `ThoughtLocked` if it is locked
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = ThoughtID::new("draft".to_string());
+    /// graph.create_thought(id.clone(), None, "Rough idea".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// graph.trash_thought(&id, Utc::now()).unwrap();
+    /// assert!(graph.get_thought(&id).is_none());
+    /// graph.restore_thought(&id).unwrap();
+    /// assert_eq!(graph.get_thought(&id).unwrap().contents, "Rough idea");
+    /// ```
+    pub fn trash_thought(&mut self, id: &ThoughtID, at: DateTime<Utc>) -> Result<ChangeSet> {
+        let thought = self.get_thought(id).cloned().ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+        let changes = self.try_command(&Command::DeleteThought { id: id.clone() })?;
+        self.trash.insert(id.clone(), TrashedThought { thought, deleted_at: at });
+        Ok(changes)
+    }
+
+    /// The thoughts in the trash, most recently deleted first
+    pub fn trashed_thoughts(&self) -> Vec<(&ThoughtID, &TrashedThought)> {
+        let mut trashed: Vec<(&ThoughtID, &TrashedThought)> = self.trash.iter().collect();
+        trashed.sort_by(|(a_id, a), (b_id, b)| b.deleted_at.cmp(&a.deleted_at).then_with(|| a_id.cmp(b_id)));
+        trashed
+    }
+
+    /// Restore a thought from the trash, along with the backlinks of the thoughts that
+    /// still reference it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The thought to restore
+    ///
+    /// # Returns
+    ///
+    /// The changes made, `ThoughtNotFound` if the trash holds no thought with this ID,
+    /// or `InvalidThoughtID` if a thought with this ID has been created since
+    pub fn restore_thought(&mut self, id: &ThoughtID) -> Result<ChangeSet> {
+        if !self.trash.contains_key(id) {
+            return Err(ThoughtGraphError::ThoughtNotFound(id.id.to_string()));
+        }
+        if self.thoughts.contains_key(id) {
+            return Err(ThoughtGraphError::InvalidThoughtID(format!("'{}' is already in use", id.id)));
+        }
+        let trashed = self.trash.remove(id).ok_or_else(|| ThoughtGraphError::ThoughtNotFound(id.id.to_string()))?;
+        let mut changes = self.command(&Command::PutThought { id: id.clone(), thought: trashed.thought });
+        changes.backlinks_added.extend(self.relink_backreferences(id));
+        Ok(changes)
+    }
+
+    /// Add back the backlinks of the thoughts that reference a thought, which deleting it
+    /// dropped, returning the backlinks added
+    pub(crate) fn relink_backreferences(&mut self, id: &ThoughtID) -> Vec<(ThoughtID, ThoughtID)> {
+        let sources: HashSet<ThoughtID> = self
+            .thoughts
+            .iter()
+            .filter(|(source, thought)| *source != id && thought.references.iter().any(|r| r.id == *id))
+            .map(|(source, _)| source.clone())
+            .collect();
+        let backlinks = self.backreferences.entry(id.clone()).or_default();
+        let mut added = Vec::new();
+        for source in sources {
+            if backlinks.insert(source.clone()) {
+                added.push((source, id.clone()));
+            }
+        }
+        if backlinks.is_empty() {
+            self.backreferences.remove(id);
+        }
+        self.query_cache.clear();
+        added
+    }
+
+    /// Permanently remove thoughts from the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `before` - Only remove the thoughts deleted before this time, or all of them if
+    ///   `None`
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the removed thoughts, in order
+    pub fn empty_trash(&mut self, before: Option<DateTime<Utc>>) -> Vec<ThoughtID> {
+        let mut removed: Vec<ThoughtID> = self
+            .trash
+            .iter()
+            .filter(|(_, trashed)| before.is_none_or(|before| trashed.deleted_at < before))
+            .map(|(id, _)| id.clone())
+            .collect();
+        removed.sort();
+        for id in &removed {
+            self.trash.remove(id);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_trash_and_restore() {
+        let mut graph = ThoughtGraph::new();
+        let id = |name: &str| ThoughtID::new(name.to_string());
+        let at = Utc.with_ymd_and_hms(2025, 2, 26, 9, 0, 0).unwrap();
+        graph.create_thought(id("old"), None, "Old".to_string(), vec![], vec![]).unwrap();
+        graph.create_thought(id("draft"), None, "Draft".to_string(), vec![], vec![]).unwrap();
+        graph
+            .create_thought(id("essay"), None, "Essay".to_string(), vec![], vec![Reference::new(id("draft"), String::new(), at)])
+            .unwrap();
+
+        graph.trash_thought(&id("old"), at - Duration::days(40)).unwrap();
+        let changes = graph.trash_thought(&id("draft"), at).unwrap();
+        assert_eq!(changes.thoughts_deleted, vec![id("draft")]);
+        assert!(graph.get_backlinks(&id("draft")).is_empty());
+        let trashed: Vec<&ThoughtID> = graph.trashed_thoughts().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(trashed, vec![&id("draft"), &id("old")]);
+
+        // The trash is saved with the graph
+        let mut graph = ThoughtGraph::from_bytes(&graph.to_bytes().unwrap()).unwrap();
+        let changes = graph.restore_thought(&id("draft")).unwrap();
+        assert_eq!(changes.backlinks_added, vec![(id("essay"), id("draft"))]);
+        assert_eq!(graph.get_backlinks(&id("draft")), vec![id("essay")]);
+        assert!(matches!(graph.restore_thought(&id("draft")), Err(ThoughtGraphError::ThoughtNotFound(_))));
+
+        graph.trash_thought(&id("draft"), at).unwrap();
+        graph.create_thought(id("draft"), None, "New draft".to_string(), vec![], vec![]).unwrap();
+        assert!(matches!(graph.restore_thought(&id("draft")), Err(ThoughtGraphError::InvalidThoughtID(_))));
+
+        assert_eq!(graph.empty_trash(Some(at - Duration::days(30))), vec![id("old")]);
+        assert_eq!(graph.empty_trash(None), vec![id("draft")]);
+        assert!(matches!(graph.trash_thought(&id("missing"), at), Err(ThoughtGraphError::ThoughtNotFound(_))));
+    }
+}
@@ -0,0 +1,254 @@
+//! Traversal of the graph starting from a thought
+//!
+//! `ThoughtGraph::walk` visits the thoughts reachable from a starting thought, following
+//! references, backlinks or both, breadth-first or depth-first and optionally only up to
+//! a maximum distance. Each thought is visited once, together with its depth: the number
+//! of links followed to reach it.
+//!
+//! References to thoughts that do not exist are skipped. Neighbours are visited in a
+//! fixed order (references in the order they were added, then backlinks by ID), so a walk
+//! over the same graph always produces the same sequence.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{ReferenceKind, Thought, ThoughtGraph, ThoughtID};
+
+/// Which links a walk follows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    /// From a thought to the thoughts it references
+    #[default]
+    Outgoing,
+    /// From a thought to the thoughts that reference it
+    Incoming,
+    /// Both references and backlinks
+    Both,
+}
+
+/// The order in which a walk visits thoughts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Order {
+    /// All thoughts at depth 1, then all at depth 2, and so on
+    #[default]
+    BreadthFirst,
+    /// Follow each path as far as possible before backtracking
+    DepthFirst,
+}
+
+/// An iterator over the thoughts reachable from a starting thought.
+///
+/// Created by `ThoughtGraph::walk`; configure it with the builder methods before
+/// iterating. It yields `(id, thought, depth)` triples, starting with the starting
+/// thought at depth 0.
+pub struct Walk<'a> {
+    graph: &'a ThoughtGraph,
+    direction: Direction,
+    order: Order,
+    max_depth: Option<usize>,
+    /// Thoughts waiting to be visited, with their depth
+    pending: VecDeque<(&'a ThoughtID, usize)>,
+    visited: HashSet<&'a ThoughtID>,
+}
+
+impl<'a> Walk<'a> {
+    /// Follow the given links (default: `Direction::Outgoing`)
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Visit thoughts in the given order (default: `Order::BreadthFirst`)
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Visit thoughts depth-first; short for `order(Order::DepthFirst)`
+    pub fn depth_first(self) -> Self {
+        self.order(Order::DepthFirst)
+    }
+
+    /// Do not follow links from thoughts at this depth, so that no thought further than
+    /// `max_depth` links from the start is visited
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// The existing thoughts linked to `id` in the walk's direction, in visiting order
+    fn neighbours(&self, id: &ThoughtID, thought: &'a Thought) -> Vec<&'a ThoughtID> {
+        let mut neighbours = Vec::new();
+        if self.direction != Direction::Incoming {
+            neighbours.extend(
+                thought
+                    .references
+                    .iter()
+                    .filter_map(|reference| self.graph.thoughts.get_key_value(&reference.id))
+                    .map(|(id, _)| id),
+            );
+        }
+        if self.direction != Direction::Outgoing {
+            if let Some(backlinks) = self.graph.backreferences.get(id) {
+                let mut backlinks: Vec<&'a ThoughtID> = backlinks
+                    .iter()
+                    .filter(|source| self.graph.thoughts.contains_key(*source))
+                    .collect();
+                backlinks.sort();
+                neighbours.extend(backlinks);
+            }
+        }
+        neighbours
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (&'a ThoughtID, &'a Thought, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (id, depth) = match self.order {
+                Order::BreadthFirst => self.pending.pop_front()?,
+                Order::DepthFirst => self.pending.pop_back()?,
+            };
+            if !self.visited.insert(id) {
+                continue;
+            }
+            let thought = &self.graph.thoughts[id];
+
+            if self.max_depth.is_none_or(|max| depth < max) {
+                let neighbours = self.neighbours(id, thought);
+                let unvisited = neighbours.into_iter().filter(|n| !self.visited.contains(*n));
+                match self.order {
+                    Order::BreadthFirst => self.pending.extend(unvisited.map(|n| (n, depth + 1))),
+                    // Pushed in reverse so that the first neighbour is visited first
+                    Order::DepthFirst => {
+                        let unvisited: Vec<_> = unvisited.collect();
+                        self.pending.extend(unvisited.into_iter().rev().map(|n| (n, depth + 1)));
+                    },
+                }
+            }
+
+            return Some((id, thought, depth));
+        }
+    }
+}
+
+impl ThoughtGraph {
+    /// Walk the graph starting from a thought.
+    ///
+    /// By default the walk follows references breadth-first without a depth limit; use
+    /// the methods of `Walk` to change this. If the starting thought does not exist, the
+    /// walk is empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The thought to start from
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Reference};
+    /// use thoughtgraph::traverse::Direction;
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let id = |name: &str| ThoughtID::new(name.to_string());
+    /// graph.create_thought(id("a"), None, String::new(), vec![], vec![]).unwrap();
+    /// graph.create_thought(id("b"), None, String::new(), vec![], vec![Reference::new(id("a"), String::new(), Utc::now())]).unwrap();
+    /// graph.create_thought(id("c"), None, String::new(), vec![], vec![Reference::new(id("b"), String::new(), Utc::now())]).unwrap();
+    ///
+    /// let walked: Vec<(&str, usize)> = graph
+    ///     .walk(&id("a"))
+    ///     .direction(Direction::Both)
+    ///     .max_depth(1)
+    ///     .map(|(id, _, depth)| (id.as_str(), depth))
+    ///     .collect();
+    /// assert_eq!(walked, vec![("a", 0), ("b", 1)]);
+    /// ```
+    pub fn walk(&self, start: &ThoughtID) -> Walk<'_> {
+        Walk {
+            graph: self,
+            direction: Direction::default(),
+            order: Order::default(),
+            max_depth: None,
+            pending: self.thoughts.get_key_value(start).map(|(id, _)| (id, 0)).into_iter().collect(),
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Follow a chain of references of one kind from a thought.
+    ///
+    /// From each thought, the chain continues with the first reference of `kind` to a
+    /// thought that exists and is not already in the chain, so chains always end, even if
+    /// the references form a loop.
+    ///
+    /// # Returns
+    ///
+    /// The thoughts in the chain, starting with `start`, or nothing if `start` does not exist
+    pub fn reference_chain(&self, start: &ThoughtID, kind: ReferenceKind) -> Vec<&ThoughtID> {
+        let mut chain: Vec<&ThoughtID> = Vec::new();
+        let mut next = self.thoughts.get_key_value(start);
+        while let Some((id, thought)) = next {
+            chain.push(id);
+            next = thought
+                .references
+                .iter()
+                .filter(|reference| reference.kind == kind && !chain.contains(&&reference.id))
+                .find_map(|reference| self.thoughts.get_key_value(&reference.id));
+        }
+        chain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reference;
+    use chrono::Utc;
+
+    fn id(name: &str) -> ThoughtID {
+        ThoughtID::new(name.to_string())
+    }
+
+    /// a -> b -> d, a -> c -> d, d -> a, e -> c, and a dangling reference from b
+    fn diamond() -> ThoughtGraph {
+        let mut graph = ThoughtGraph::new();
+        for (name, refs) in [("a", vec!["b", "c"]), ("b", vec!["d", "missing"]), ("c", vec!["d"]), ("d", vec!["a"]), ("e", vec!["c"])] {
+            let references = refs.into_iter().map(|r| Reference::new(id(r), String::new(), Utc::now())).collect();
+            graph.create_thought(id(name), None, String::new(), vec![], references).unwrap();
+        }
+        graph
+    }
+
+    fn names<'a>(walk: impl Iterator<Item = (&'a ThoughtID, &'a Thought, usize)>) -> Vec<(&'a str, usize)> {
+        walk.map(|(id, _, depth)| (id.as_str(), depth)).collect()
+    }
+
+    #[test]
+    fn test_orders() {
+        let graph = diamond();
+        assert_eq!(names(graph.walk(&id("a"))), vec![("a", 0), ("b", 1), ("c", 1), ("d", 2)]);
+        assert_eq!(names(graph.walk(&id("a")).depth_first()), vec![("a", 0), ("b", 1), ("d", 2), ("c", 1)]);
+    }
+
+    #[test]
+    fn test_directions_and_depth() {
+        let graph = diamond();
+        assert_eq!(names(graph.walk(&id("c")).direction(Direction::Incoming)), vec![("c", 0), ("a", 1), ("e", 1), ("d", 2), ("b", 3)]);
+        assert_eq!(
+            names(graph.walk(&id("c")).direction(Direction::Both).max_depth(1)),
+            vec![("c", 0), ("d", 1), ("a", 1), ("e", 1)]
+        );
+        assert_eq!(names(graph.walk(&id("a")).max_depth(0)), vec![("a", 0)]);
+        assert!(graph.walk(&id("missing")).next().is_none());
+    }
+
+    #[test]
+    fn test_reference_chain() {
+        let graph = diamond();
+        let chain = |start: &str, kind| graph.reference_chain(&id(start), kind).into_iter().map(|id| id.as_str()).collect::<Vec<_>>();
+        assert_eq!(chain("e", ReferenceKind::Related), vec!["e", "c", "d", "a", "b"]);
+        assert_eq!(chain("e", ReferenceKind::Parent), vec!["e"]);
+        assert!(chain("missing", ReferenceKind::Related).is_empty());
+    }
+}
@@ -4,16 +4,128 @@
 //! including interactive menus, progress indicators, and improved text rendering.
 
 use anyhow::Result;
-use console::{style, Term};
+use console::{style, Key, Term};
 use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, MultiSelect, Select};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
 use indicatif::{ProgressBar, ProgressStyle};
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use std::time::Duration;
 
-use crate::{Tag, TagID, Thought, ThoughtGraph, ThoughtID};
+use crate::dates::DateFormat;
+use crate::footnotes::extract_footnotes;
+use crate::keys::{BrowseAction, BrowseKeys, KeyMatcher};
+use crate::namespace::{Namespace, NAMESPACE_SEPARATOR};
+use crate::progress::{Progress, ProgressFn};
+use crate::sort::SortBy;
+use crate::text;
+use crate::{ReferenceKind, Tag, TagID, Thought, ThoughtGraph, ThoughtID};
 
-/// Format a string with the given width for display
+/// Format a string with the given width for display, measured in terminal columns so
+/// that wide and combining characters line up
 pub fn format_column(text: &str, width: usize) -> String {
-    format!("{:<width$}", text, width = width)
+    console::pad_str(text, width, console::Alignment::Left, None).into_owned()
+}
+
+/// Width assumed for tables when the output is not a terminal
+const DEFAULT_TERMINAL_WIDTH: usize = 100;
+
+/// Narrowest an ID column is shrunk to on a narrow terminal
+const MIN_ID_WIDTH: usize = 12;
+
+/// Whether ID columns are kept wide enough for every ID, however narrow the terminal
+static FULL_IDS: AtomicBool = AtomicBool::new(false);
+
+/// Never shorten IDs to fit tables in the terminal
+pub fn show_full_ids(full: bool) {
+    FULL_IDS.store(full, Ordering::Relaxed);
+}
+
+/// The width of the terminal, or `DEFAULT_TERMINAL_WIDTH` if the output is not one
+pub fn terminal_width() -> usize {
+    Term::stdout().size_checked().map(|(_, columns)| columns as usize).unwrap_or(DEFAULT_TERMINAL_WIDTH)
+}
+
+/// The narrowest an ID column whose widest cell is `natural` may be
+pub fn min_id_width(natural: usize) -> usize {
+    match FULL_IDS.load(Ordering::Relaxed) {
+        true => natural,
+        false => natural.min(MIN_ID_WIDTH),
+    }
+}
+
+/// Widths for the columns of a table, separated by single spaces, that fit in the
+/// terminal where possible.
+///
+/// Each column starts as wide as its widest cell, `natural`. While the table is too
+/// wide, the widest column that is still wider than its `minimum` loses a character.
+pub fn fit_columns(natural: &[usize], minimum: &[usize]) -> Vec<usize> {
+    let mut widths = natural.to_vec();
+    let available = terminal_width().saturating_sub(natural.len().saturating_sub(1));
+    while widths.iter().sum::<usize>() > available {
+        let widest = (0..widths.len()).filter(|&i| widths[i] > minimum[i]).max_by_key(|&i| widths[i]);
+        match widest {
+            Some(i) => widths[i] -= 1,
+            None => break,
+        }
+    }
+    widths
+}
+
+/// Pad text to a column width, shortening it with an ellipsis if it is too wide
+pub fn fit_column(text: &str, width: usize) -> String {
+    match console::measure_text_width(text) > width {
+        true => console::truncate_str(text, width, "…").into_owned(),
+        false => console::pad_str(text, width, console::Alignment::Left, None).into_owned(),
+    }
+}
+
+/// The widest of a header and the cells of its column
+pub fn column_width<'a>(header: &str, cells: impl IntoIterator<Item = &'a str>) -> usize {
+    cells.into_iter().map(console::measure_text_width).chain([header.len()]).max().unwrap_or_default()
+}
+
+/// The partial blocks that end a bar of a chart, by eighths of a character
+const BAR_EIGHTHS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+/// Print a bar chart under a heading, one labelled bar per row, with the longest bar
+/// as wide as the terminal allows
+pub fn print_bar_chart(heading: &str, rows: &[(String, usize)]) {
+    println!("{}", style(heading).bold().underlined());
+    let width = terminal_width();
+    let label_width = column_width("", rows.iter().map(|(label, _)| label.as_str())).min(width / 3);
+    let max = rows.iter().map(|(_, count)| *count).max().unwrap_or_default();
+    let count_width = max.to_string().len();
+    let bar_width = width.saturating_sub(label_width + count_width + 2).max(10);
+    for (label, count) in rows {
+        let eighths = match max {
+            0 => 0,
+            _ => (count * bar_width * 8 / max).max(usize::from(*count > 0)),
+        };
+        let bar = format!("{}{}", "█".repeat(eighths / 8), BAR_EIGHTHS[eighths % 8]);
+        println!("{} {:>count_width$} {}", fit_column(label, label_width), count, style(bar).cyan());
+    }
+}
+
+/// How times are shown, set once from the configuration
+static DATE_FORMAT: OnceLock<DateFormat> = OnceLock::new();
+
+/// Show every time with these settings instead of the defaults; only the first call
+/// has an effect
+pub fn set_date_format(format: DateFormat) {
+    let _ = DATE_FORMAT.set(format);
+}
+
+/// A time for a column of a table, relative to now if it is recent
+pub fn format_time(at: DateTime<Utc>) -> String {
+    DATE_FORMAT.get_or_init(DateFormat::default).short(at, Utc::now())
+}
+
+/// A time in full, with how long ago it was if it is recent
+pub fn format_time_long(at: DateTime<Utc>) -> String {
+    DATE_FORMAT.get_or_init(DateFormat::default).long(at, Utc::now())
 }
 
 /// UI Theme to use consistently throughout the application
@@ -23,7 +135,7 @@ pub fn get_theme() -> ColorfulTheme {
 
 /// Display a multi-select menu to choose tags from existing tags
 pub fn select_tags(graph: &ThoughtGraph, initial_selection: &[TagID]) -> Result<Vec<TagID>> {
-    let tags: Vec<(&TagID, &Tag)> = graph.tags.iter().collect();
+    let tags: Vec<(&TagID, &Tag)> = graph.iter_tags_sorted().collect();
     
     if tags.is_empty() {
         return Ok(Vec::new());
@@ -56,29 +168,181 @@ pub fn select_tags(graph: &ThoughtGraph, initial_selection: &[TagID]) -> Result<
     Ok(selected_tags)
 }
 
-/// Interactive thought selection with fuzzy search
+/// Number of words of a thought's contents that fuzzy selection matches against
+const SELECTION_CONTENT_WORDS: usize = 16;
+
+/// The line a thought is shown as in fuzzy selection: its ID and title, then its tags and
+/// the start of its contents, so that a thought can be found by a phrase from its body
+fn selection_item(id: &ThoughtID, thought: &Thought) -> String {
+    let title = thought.title.as_deref().unwrap_or("(Untitled)");
+    let mut item = format!("{} - {}", id.id, title);
+    for tag in &thought.tags {
+        item.push_str(&format!(" #{}", tag.id));
+    }
+    let words: Vec<&str> = thought.contents.split_whitespace().collect();
+    if !words.is_empty() {
+        item.push_str(" · ");
+        item.push_str(&words[..words.len().min(SELECTION_CONTENT_WORDS)].join(" "));
+        if words.len() > SELECTION_CONTENT_WORDS {
+            item.push('…');
+        }
+    }
+    item
+}
+
+/// Narrowest terminal the preview pane is shown in
+const MIN_PREVIEW_WIDTH: usize = 80;
+
+/// Wrap text into lines of at most `width` terminal columns, breaking between words
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && console::measure_text_width(&line) + 1 + console::measure_text_width(word) > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// The preview of a thought shown beside the selection list: its title, tags, contents
+/// and backlinks
+fn preview_lines(graph: &ThoughtGraph, id: &ThoughtID, width: usize) -> Vec<String> {
+    let thought = match graph.get_thought(id) {
+        Some(thought) => thought,
+        None => return Vec::new(),
+    };
+    let mut lines = vec![style(thought.title.as_deref().unwrap_or("(Untitled)")).bold().green().to_string()];
+    if !thought.tags.is_empty() {
+        let tags: Vec<String> = thought.tags.iter().map(|t| format!("#{}", t.id)).collect();
+        lines.extend(wrap_text(&tags.join(" "), width).into_iter().map(|line| style(line).yellow().to_string()));
+    }
+    lines.push(String::new());
+    lines.extend(wrap_text(&thought.contents, width));
+
+    let backlinks = graph.get_backlinks(id);
+    if !backlinks.is_empty() {
+        lines.push(String::new());
+        lines.push(style("Backlinks:").bold().to_string());
+        for backlink in backlinks {
+            let title = graph.get_thought(&backlink).and_then(|t| t.title.clone()).unwrap_or_else(|| "(Untitled)".to_string());
+            lines.push(format!("← {} - {}", backlink.id, title));
+        }
+    }
+    lines
+}
+
+/// Interactive thought selection with fuzzy search over IDs, titles, tags and the start
+/// of each thought's contents.
+///
+/// Where the terminal is wide enough, the highlighted thought's contents, tags and
+/// backlinks are previewed beside the list, so that the right thought can be confirmed
+/// before acting on it.
 pub fn select_thought(graph: &ThoughtGraph, prompt: &str) -> Result<Option<ThoughtID>> {
-    let thoughts: Vec<(&ThoughtID, &Thought)> = graph.thoughts.iter().collect();
+    let thoughts: Vec<(&ThoughtID, &Thought)> = graph.iter_thoughts_sorted(SortBy::Updated).collect();
     
     if thoughts.is_empty() {
         return Ok(None);
     }
     
-    let items: Vec<String> = thoughts
-        .iter()
-        .map(|(id, thought)| {
-            let title = thought.title.as_deref().unwrap_or("(Untitled)");
-            format!("{} - {}", id.id, title)
-        })
-        .collect();
+    let items: Vec<String> = thoughts.iter().map(|(id, thought)| selection_item(id, thought)).collect();
     
-    let selection = FuzzySelect::with_theme(&get_theme())
-        .with_prompt(prompt)
-        .default(0)
-        .items(&items)
-        .interact_opt()?;
+    let term = Term::stderr();
+    let (rows, columns) = term.size();
+    let (rows, columns) = (rows as usize, columns as usize);
+    if columns < MIN_PREVIEW_WIDTH {
+        let selection = FuzzySelect::with_theme(&get_theme())
+            .with_prompt(prompt)
+            .default(0)
+            .items(&items)
+            .interact_opt()?;
+        return Ok(selection.map(|i| thoughts[i].0.clone()));
+    }
+    
+    // Matched without case or accents, so that "cafe" finds "Café"
+    let folded: Vec<String> = items.iter().map(|item| text::fold(item, true)).collect();
+    let matcher = SkimMatcherV2::default();
+    let list_width = columns * 2 / 5;
+    let preview_width = columns - list_width - 3;
+    let height = rows.saturating_sub(3).clamp(5, 20);
+    let mut query = String::new();
+    let mut cursor = 0;
+    let mut drawn = 0;
+    term.hide_cursor()?;
     
-    Ok(selection.map(|i| thoughts[i].0.clone()))
+    let selection = loop {
+        let folded_query = text::fold(&query, true);
+        let mut matches: Vec<(i64, usize)> = folded
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| matcher.fuzzy_match(item, &folded_query).map(|score| (score, i)))
+            .collect();
+        // Best matches first, keeping the most recently updated first among equals
+        matches.sort_by_key(|(score, i)| (std::cmp::Reverse(*score), *i));
+        cursor = cursor.min(matches.len().saturating_sub(1));
+        
+        let first = cursor.saturating_sub(height - 1);
+        let list: Vec<String> = matches
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .map(|(position, (_, i))| {
+                let item = console::truncate_str(&items[*i], list_width - 2, "…").into_owned();
+                if position == cursor {
+                    format!("{} {}", style("›").cyan(), style(item).cyan())
+                } else {
+                    format!("  {}", item)
+                }
+            })
+            .collect();
+        let preview = matches
+            .get(cursor)
+            .map(|(_, i)| preview_lines(graph, thoughts[*i].0, preview_width))
+            .unwrap_or_default();
+        
+        term.clear_last_lines(drawn)?;
+        term.write_line(&format!("{} {} {}", style("?").yellow(), style(prompt).bold(), query))?;
+        let shown = list.len().max(preview.len()).clamp(1, height);
+        for row in 0..shown {
+            let left = list.get(row).map(String::as_str).unwrap_or("");
+            let right = preview.get(row).map(String::as_str).unwrap_or("");
+            term.write_line(&format!(
+                "{} {} {}",
+                console::pad_str(left, list_width, console::Alignment::Left, None),
+                style("│").dim(),
+                console::truncate_str(right, preview_width, "…")
+            ))?;
+        }
+        drawn = shown + 1;
+        
+        match term.read_key()? {
+            Key::Enter => break matches.get(cursor).map(|(_, i)| thoughts[*i].0.clone()),
+            Key::Escape => break None,
+            Key::ArrowDown | Key::Tab => cursor = (cursor + 1).min(matches.len().saturating_sub(1)),
+            Key::ArrowUp | Key::BackTab => cursor = cursor.saturating_sub(1),
+            Key::Backspace => {
+                query.pop();
+                cursor = 0;
+            },
+            Key::Char(c) if !c.is_control() => {
+                query.push(c);
+                cursor = 0;
+            },
+            _ => {},
+        }
+    };
+    
+    term.clear_last_lines(drawn)?;
+    term.show_cursor()?;
+    Ok(selection)
 }
 
 /// Display a progress bar while loading a thought graph
@@ -102,111 +366,203 @@ where
     result
 }
 
-/// Interactive thought browser that allows exploring references
-pub fn browse_thoughts(graph: &ThoughtGraph) -> Result<()> {
+/// Display a progress bar while running an operation that reports its progress, or a
+/// spinner with a count of the work done while the total is not known
+pub fn with_progress_bar<F, T>(message: &str, operation: F) -> T
+where
+    F: FnOnce(ProgressFn) -> T,
+{
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
+            .template("{spinner:.blue} {msg}")
+            .unwrap(),
+    );
+    pb.set_message(message.to_string());
+    pb.enable_steady_tick(Duration::from_millis(80));
+    
+    let bar = ProgressStyle::default_bar()
+        .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
+        .template("{spinner:.blue} {msg} [{bar:30.cyan/blue}] {percent}%")
+        .unwrap()
+        .progress_chars("█▉▊▋▌▍▎▏ ");
+    let mut has_total = false;
+    let result = operation(&mut |progress: Progress| match progress.total {
+        Some(total) => {
+            if !has_total {
+                pb.set_style(bar.clone());
+                has_total = true;
+            }
+            pb.set_length(total);
+            pb.set_position(progress.done);
+        },
+        None => pb.set_message(format!("{} {}", message, progress.done)),
+    });
+    
+    pb.finish_with_message(format!("{} Done!", message));
+    result
+}
+
+/// Which links of the current thought the browser lists
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BrowseList {
+    References,
+    Backlinks,
+}
+
+/// Interactive thought browser that allows exploring references.
+///
+/// The browser is driven by the key bindings in `keys`: moving through the links of the
+/// current thought, following them, going back, searching and editing.
+///
+/// # Arguments
+///
+/// * `graph` - The graph to browse
+/// * `start` - The thought to start at, or `None` to choose one first
+/// * `keys` - The key bindings
+/// * `edit` - Called to edit the current thought
+pub fn browse_thoughts(
+    graph: &mut ThoughtGraph,
+    start: Option<ThoughtID>,
+    keys: &BrowseKeys,
+    edit: &mut dyn FnMut(&mut ThoughtGraph, &ThoughtID) -> Result<()>,
+) -> Result<()> {
     let term = Term::stdout();
-    let mut current_id: Option<ThoughtID> = None;
+    let mut matcher = KeyMatcher::new(keys);
+    let mut current_id = start;
+    let mut history: Vec<ThoughtID> = Vec::new();
+    let mut list = BrowseList::References;
+    let mut cursor = 0;
     
     loop {
+        let id = match current_id.clone() {
+            Some(id) => id,
+            None => match select_thought(graph, "Select a thought to view")? {
+                Some(id) => {
+                    current_id = Some(id);
+                    continue;
+                },
+                None => return Ok(()),
+            },
+        };
+        let thought = match graph.get_thought(&id) {
+            Some(thought) => thought,
+            None => {
+                println!("Thought '{}' not found.", id.id);
+                term.read_key()?;
+                current_id = history.pop();
+                continue;
+            },
+        };
+        
+        let links: Vec<ThoughtID> = match list {
+            BrowseList::References => thought.references.iter().map(|r| r.id.clone()).collect(),
+            BrowseList::Backlinks => graph.get_backlinks(&id),
+        };
+        cursor = cursor.min(links.len().saturating_sub(1));
+        
         term.clear_screen()?;
+        display_thought_details(graph, &id, thought)?;
+        let heading = match list {
+            BrowseList::References => "Go to reference:",
+            BrowseList::Backlinks => "Go to backlink:",
+        };
+        println!("\n{}", style(heading).bold());
+        if links.is_empty() {
+            println!("  {}", style("(none)").dim());
+        }
+        for (i, link) in links.iter().enumerate() {
+            let title = graph.get_thought(link)
+                .and_then(|t| t.title.clone())
+                .unwrap_or_else(|| "(Untitled)".to_string());
+            let item = format!("{} - {}", link.id, title);
+            if i == cursor {
+                println!("{} {}", style("›").cyan(), style(item).cyan());
+            } else {
+                println!("  {}", item);
+            }
+        }
+        println!(
+            "\n{}",
+            style(format!(
+                "{}/{} move  {} open  {} back  {} references  {} backlinks  {} search  {} edit  {} quit",
+                keys.down, keys.up, keys.open, keys.back, keys.references, keys.backlinks, keys.search, keys.edit, keys.quit
+            ))
+            .dim()
+        );
         
-        if let Some(id) = &current_id {
-            // Display current thought
-            if let Some(thought) = graph.get_thought(id) {
-                display_thought_details(graph, id, thought)?;
-                
-                println!("\n{}", style("Actions:").bold());
-                let actions = &[
-                    "View references",
-                    "View backlinks",
-                    "Select another thought",
-                    "Back to main menu"
-                ];
-                
+        let action = loop {
+            if let Some(action) = matcher.press(term.read_key()?) {
+                break action;
+            }
+        };
+        match action {
+            BrowseAction::Down => cursor = (cursor + 1).min(links.len().saturating_sub(1)),
+            BrowseAction::Up => cursor = cursor.saturating_sub(1),
+            BrowseAction::Open => {
+                if let Some(link) = links.get(cursor) {
+                    history.push(id.clone());
+                    current_id = Some(link.clone());
+                    cursor = 0;
+                }
+            },
+            BrowseAction::Back => {
+                if let Some(previous) = history.pop() {
+                    current_id = Some(previous);
+                    cursor = 0;
+                }
+            },
+            BrowseAction::References => {
+                list = BrowseList::References;
+                cursor = 0;
+            },
+            BrowseAction::Backlinks => {
+                list = BrowseList::Backlinks;
+                cursor = 0;
+            },
+            BrowseAction::Search => {
+                let query: String = Input::with_theme(&get_theme())
+                    .with_prompt("Search")
+                    .allow_empty(true)
+                    .interact_text()?;
+                let terms: Vec<&str> = query.split_whitespace().collect();
+                if terms.is_empty() {
+                    continue;
+                }
+                let results: Vec<ThoughtID> = graph.search(&terms).into_iter().map(|(id, _)| id.clone()).collect();
+                if results.is_empty() {
+                    println!("No thoughts found.");
+                    term.read_key()?;
+                    continue;
+                }
+                let items: Vec<String> = results
+                    .iter()
+                    .map(|result| {
+                        let title = graph.get_thought(result)
+                            .and_then(|t| t.title.clone())
+                            .unwrap_or_else(|| "(Untitled)".to_string());
+                        format!("{} - {}", result.id, title)
+                    })
+                    .collect();
                 let selection = Select::with_theme(&get_theme())
-                    .items(actions)
+                    .with_prompt("Go to")
+                    .items(&items)
                     .default(0)
-                    .interact()?;
-                
-                match selection {
-                    0 => {
-                        // View references
-                        if thought.references.is_empty() {
-                            println!("No references to display.");
-                            term.read_key()?;
-                            continue;
-                        }
-                        
-                        let references: Vec<String> = thought.references
-                            .iter()
-                            .map(|r| {
-                                let title = graph.get_thought(&r.id)
-                                    .and_then(|t| t.title.clone())
-                                    .unwrap_or_else(|| "(Untitled)".to_string());
-                                format!("{} - {}", r.id.id, title)
-                            })
-                            .collect();
-                        
-                        let ref_selection = Select::with_theme(&get_theme())
-                            .with_prompt("Select a reference to view")
-                            .items(&references)
-                            .default(0)
-                            .interact_opt()?;
-                        
-                        if let Some(index) = ref_selection {
-                            current_id = Some(thought.references[index].id.clone());
-                        }
-                    },
-                    1 => {
-                        // View backlinks
-                        let backlinks = graph.get_backlinks(id);
-                        
-                        if backlinks.is_empty() {
-                            println!("No backlinks to display.");
-                            term.read_key()?;
-                            continue;
-                        }
-                        
-                        let backlink_items: Vec<String> = backlinks
-                            .iter()
-                            .map(|link_id| {
-                                let title = graph.get_thought(link_id)
-                                    .and_then(|t| t.title.clone())
-                                    .unwrap_or_else(|| "(Untitled)".to_string());
-                                format!("{} - {}", link_id.id, title)
-                            })
-                            .collect();
-                        
-                        let backlink_selection = Select::with_theme(&get_theme())
-                            .with_prompt("Select a backlink to view")
-                            .items(&backlink_items)
-                            .default(0)
-                            .interact_opt()?;
-                        
-                        if let Some(index) = backlink_selection {
-                            current_id = Some(backlinks[index].clone());
-                        }
-                    },
-                    2 => {
-                        // Select another thought
-                        current_id = select_thought(graph, "Select a thought to view")?;
-                        if current_id.is_none() {
-                            return Ok(());
-                        }
-                    },
-                    3 | _ => return Ok(()),
+                    .interact_opt()?;
+                if let Some(index) = selection {
+                    history.push(id.clone());
+                    current_id = Some(results[index].clone());
+                    cursor = 0;
                 }
-            } else {
-                println!("Thought not found.");
-                term.read_key()?;
-                current_id = None;
-            }
-        } else {
-            // No thought selected yet
-            current_id = select_thought(graph, "Select a thought to view")?;
-            if current_id.is_none() {
-                return Ok(());
-            }
+            },
+            BrowseAction::Edit => {
+                if let Err(e) = edit(graph, &id) {
+                    println!("{}", style(format!("Error: {}", e)).red());
+                    term.read_key()?;
+                }
+            },
+            BrowseAction::Quit => return Ok(()),
         }
     }
 }
@@ -220,11 +576,15 @@ pub fn display_thought_details(graph: &ThoughtGraph, id: &ThoughtID, thought: &T
         println!("{}", style("(Untitled)").bold());
     }
     
-    println!("ID: {}", style(&id.id).blue());
+    if graph.is_locked(id) {
+        println!("ID: {} {}", style(&id.id).blue(), style("(locked)").red());
+    } else {
+        println!("ID: {}", style(&id.id).blue());
+    }
     
     // Display metadata
-    println!("Created: {}", style(thought.created_at.format("%Y-%m-%d %H:%M:%S")).dim());
-    println!("Updated: {}", style(thought.updated_at.format("%Y-%m-%d %H:%M:%S")).dim());
+    println!("Created: {}", style(format_time_long(thought.created_at)).dim());
+    println!("Updated: {}", style(format_time_long(thought.updated_at)).dim());
     
     // Display tags
     if !thought.tags.is_empty() {
@@ -237,6 +597,14 @@ pub fn display_thought_details(graph: &ThoughtGraph, id: &ThoughtID, thought: &T
         }
     }
     
+    // Display metadata such as the author and year of a source
+    if !thought.metadata.is_empty() {
+        println!("\n{}", style("Metadata:").bold());
+        for (key, value) in &thought.metadata {
+            println!("  {} {}", style(format!("{}:", key)).cyan(), value);
+        }
+    }
+    
     // Display references
     if !thought.references.is_empty() {
         println!("\n{}", style("References:").bold());
@@ -246,7 +614,11 @@ pub fn display_thought_details(graph: &ThoughtGraph, id: &ThoughtID, thought: &T
                 .and_then(|t| t.title.clone())
                 .unwrap_or_else(|| "(Untitled)".to_string());
             
-            println!("  → {} {}", style(&ref_id.id).blue(), title);
+            match reference.kind {
+                ReferenceKind::Parent => println!("  ↑ {} {} {}", style(&ref_id.id).blue(), title, style("(parent)").dim()),
+                ReferenceKind::Next => println!("  → {} {} {}", style(&ref_id.id).blue(), title, style("(next)").dim()),
+                ReferenceKind::Related => println!("  → {} {}", style(&ref_id.id).blue(), title),
+            }
             if !reference.notes.is_empty() {
                 println!("    {}", style(&reference.notes).dim());
             }
@@ -266,14 +638,49 @@ pub fn display_thought_details(graph: &ThoughtGraph, id: &ThoughtID, thought: &T
         }
     }
     
-    // Display content
+    // Display content with any embedded thoughts resolved
+    let contents = match graph.render_contents(id) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            println!("\n{}", style(format!("Warning: {}", e)).red());
+            thought.contents.clone()
+        }
+    };
+    let (contents, footnotes) = extract_footnotes(&contents);
     println!("\n{}", style("═".repeat(80)).dim());
-    println!("{}", thought.contents);
+    println!("{}", contents);
+    if !footnotes.is_empty() {
+        println!("\n{}", style("Footnotes:").bold());
+        for footnote in &footnotes {
+            let lines: Vec<String> = footnote.references.iter().map(|line| line.to_string()).collect();
+            let backlinks = match lines.len() {
+                0 => String::new(),
+                1 => format!(" ↩ line {}", lines[0]),
+                _ => format!(" ↩ lines {}", lines.join(", ")),
+            };
+            println!("  {} {}{}", style(format!("[{}]", footnote.number)).cyan(),
+                footnote.text.replace('\n', "\n      "), style(backlinks).dim());
+        }
+    }
     println!("{}", style("═".repeat(80)).dim());
     
     Ok(())
 }
 
+/// Display a namespace tree, indenting nested namespaces by `depth` levels
+pub fn display_namespace_tree(graph: &ThoughtGraph, namespace: &Namespace, depth: usize) {
+    let indent = "  ".repeat(depth);
+    for (name, child) in &namespace.children {
+        println!("{}{}{} {}", indent, style(name).bold().cyan(), NAMESPACE_SEPARATOR,
+            style(format!("({})", child.len())).dim());
+        display_namespace_tree(graph, child, depth + 1);
+    }
+    for id in &namespace.thoughts {
+        let title = graph.get_thought(id).and_then(|t| t.title.as_deref()).unwrap_or("");
+        println!("{}{} {}", indent, style(id.name()).blue(), style(title).dim());
+    }
+}
+
 /// Display a list of thoughts with enhanced formatting
 pub fn display_thought_list(_graph: &ThoughtGraph, thoughts: &[(&ThoughtID, &Thought)], max_display_length: usize) -> Result<()> {
     if thoughts.is_empty() {
@@ -281,28 +688,33 @@ pub fn display_thought_list(_graph: &ThoughtGraph, thoughts: &[(&ThoughtID, &Tho
         return Ok(());
     }
 
-    // Create a table-like display
+    // Size the columns of a table-like display to the terminal
+    let titles: Vec<&str> = thoughts.iter().map(|(_, thought)| thought.title.as_deref().unwrap_or("(Untitled)")).collect();
+    let dates: Vec<String> = thoughts.iter().map(|(_, thought)| format_time(thought.updated_at)).collect();
+    let id_width = column_width("ID", thoughts.iter().map(|(id, _)| id.as_str()));
+    let date_width = column_width("UPDATED", dates.iter().map(String::as_str));
+    let widths = fit_columns(
+        &[id_width, column_width("TITLE", titles.iter().copied()), date_width],
+        &[min_id_width(id_width), 10, date_width],
+    );
     println!("{} {} {}",
-        style(format_column("ID", 20)).bold().underlined(),
-        style(format_column("TITLE", 30)).bold().underlined(),
-        style(format_column("UPDATED", 20)).bold().underlined()
+        style(format_column("ID", widths[0])).bold().underlined(),
+        style(format_column("TITLE", widths[1])).bold().underlined(),
+        style(format_column("UPDATED", widths[2])).bold().underlined()
     );
+    let preview_width = max_display_length.min(terminal_width().saturating_sub(2));
     
-    for (id, thought) in thoughts {
-        let title = thought.title.as_deref().unwrap_or("(Untitled)");
-        let date = thought.updated_at.format("%Y-%m-%d %H:%M");
-        
+    for (((id, thought), title), date) in thoughts.iter().zip(titles).zip(dates) {
         println!("{} {} {}",
-            style(format_column(&id.id, 20)).blue(),
-            style(format_column(title, 30)),
-            style(format_column(&date.to_string(), 20)).dim()
+            style(fit_column(&id.id, widths[0])).blue(),
+            style(fit_column(title, widths[1])),
+            style(fit_column(&date, widths[2])).dim()
         );
         
         // Print truncated content
-        let preview = if thought.contents.len() > max_display_length {
-            format!("{}...", &thought.contents[..max_display_length])
-        } else {
-            thought.contents.clone()
+        let preview = match console::measure_text_width(&thought.contents) > preview_width {
+            true => console::truncate_str(&thought.contents, preview_width, "..."),
+            false => thought.contents.as_str().into(),
         };
         println!("  {}", style(preview).dim());
         
@@ -322,8 +734,8 @@ pub fn display_thought_list(_graph: &ThoughtGraph, thoughts: &[(&ThoughtID, &Tho
 
 /// Interactive tag selection or creation
 pub fn tag_selector(graph: &ThoughtGraph) -> Result<(TagID, Option<String>)> {
-    let existing_tags: Vec<String> = graph.tags.keys()
-        .map(|tag| tag.id.clone())
+    let existing_tags: Vec<String> = graph.iter_tags_sorted()
+        .map(|(tag, _)| tag.id.to_string())
         .collect();
     
     let options = vec!["Select existing tag", "Create new tag"];
@@ -346,7 +758,7 @@ pub fn tag_selector(graph: &ThoughtGraph) -> Result<(TagID, Option<String>)> {
                     .with_prompt("Enter a description for the tag")
                     .interact()?;
                 
-                Ok((TagID::new(tag_id), Some(description)))
+                Ok((TagID::parse(&tag_id)?, Some(description)))
             } else {
                 let tag_selection = Select::with_theme(&get_theme())
                     .with_prompt("Select a tag")
@@ -357,7 +769,7 @@ pub fn tag_selector(graph: &ThoughtGraph) -> Result<(TagID, Option<String>)> {
                 Ok((TagID::new(existing_tags[tag_selection].clone()), None))
             }
         },
-        1 | _ => {
+        _ => {
             // Create new tag
             let tag_id = Input::<String>::with_theme(&get_theme())
                 .with_prompt("Enter a new tag ID")
@@ -367,33 +779,35 @@ pub fn tag_selector(graph: &ThoughtGraph) -> Result<(TagID, Option<String>)> {
                 .with_prompt("Enter a description for the tag")
                 .interact()?;
             
-            Ok((TagID::new(tag_id), Some(description)))
+            Ok((TagID::parse(&tag_id)?, Some(description)))
         }
     }
 }
 
+/// The actions of the interactive mode's main menu, in order
+pub const INTERACTIVE_COMMANDS: &[&str] = &[
+    "Create a new thought",
+    "List thoughts",
+    "View thought details",
+    "Edit a thought",
+    "Delete a thought",
+    "Tag a thought",
+    "Untag a thought",
+    "Add a reference between thoughts",
+    "Search thoughts",
+    "Browse thoughts interactively",
+    "List all tags",
+    "Visualize thought graph",
+    "Browse namespaces",
+    "Exit",
+];
+
 /// Command selector for the main menu
 pub fn command_selector() -> Result<usize> {
-    let commands = vec![
-        "Create a new thought",
-        "List thoughts",
-        "View thought details",
-        "Edit a thought",
-        "Delete a thought",
-        "Tag a thought",
-        "Untag a thought",
-        "Add a reference between thoughts",
-        "Search thoughts",
-        "Browse thoughts interactively",
-        "List all tags",
-        "Visualize thought graph",
-        "Exit"
-    ];
-    
     let selection = Select::with_theme(&get_theme())
         .with_prompt("What would you like to do?")
         .default(0)
-        .items(&commands)
+        .items(INTERACTIVE_COMMANDS)
         .interact()?;
     
     Ok(selection)
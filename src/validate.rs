@@ -0,0 +1,350 @@
+//! Validating commands before applying them
+//!
+//! `ThoughtGraph::command` accepts any command, which keeps it simple and fast but lets
+//! inconsistent data into the graph: thoughts tagged with tags that do not exist, malformed
+//! IDs, or references to thoughts that were never created. `ThoughtGraph::try_command`
+//! checks a command first and leaves the graph untouched if it is rejected.
+
+use crate::search::compile_pattern;
+use crate::{ChangeSet, Command, Query, Reference, Result, TagID, ThoughtGraph, ThoughtGraphError, ThoughtID};
+
+/// Checks performed by `ThoughtGraph::try_command_with` in addition to the basic ones
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValidationOptions {
+    /// Reject references to thoughts that are not in the graph.
+    ///
+    /// Off by default, since it is common to reference a thought that will only be
+    /// written later.
+    pub require_existing_references: bool,
+}
+
+fn check_thought_id(id: &ThoughtID) -> Result<()> {
+    ThoughtID::parse(id.as_str()).map(|_| ())
+}
+
+fn check_tag_id(id: &TagID) -> Result<()> {
+    TagID::parse(id.as_str()).map(|_| ())
+}
+
+/// Check the IDs and patterns a query mentions; the IDs need not exist, since a saved
+/// query may be meant for thoughts and tags that are created later
+fn check_query(query: &Query) -> Result<()> {
+    match query {
+        Query::Tag(tag) => check_tag_id(tag),
+        Query::Id(id)
+        | Query::References(id)
+        | Query::ReferencedBy(id)
+        | Query::ReachableFrom { id, .. }
+        | Query::ReachableTo { id, .. } => check_thought_id(id),
+        Query::And(queries) | Query::Or(queries) => queries.iter().try_for_each(|query| check_query(query)),
+        Query::All
+        | Query::TitleContains(_)
+        | Query::ContentContains(_)
+        | Query::ReferenceNotesContain(_)
+        | Query::TagGlob(_)
+        | Query::IdGlob(_)
+        | Query::Untagged
+        | Query::Orphan
+        | Query::MinBacklinks(_)
+        | Query::MaxBacklinks(_) => Ok(()),
+        Query::TitleRegex(pattern) | Query::ContentRegex(pattern) => compile_pattern(pattern).map(|_| ()),
+        Query::Not(query) => check_query(query),
+    }
+}
+
+impl ThoughtGraph {
+    /// Check whether a command can be applied without making the graph inconsistent.
+    ///
+    /// A command is rejected if it
+    ///
+    /// * uses a malformed thought or tag ID, as defined by `ThoughtID::parse`
+    ///   (`InvalidThoughtID`, `InvalidTagID`),
+    /// * tags a thought with a tag that does not exist (`TagNotFound`),
+    /// * makes a tag its own ancestor through the parents of tags (`HierarchyCycle`); the
+    ///   parent need not exist yet,
+    /// * deletes a thought or tag that does not exist (`ThoughtNotFound`, `TagNotFound`),
+    /// * changes part of a thought that does not exist (`ThoughtNotFound`),
+    /// * replaces, changes or deletes a locked thought (`ThoughtLocked`),
+    /// * locks a thought that does not exist (`ThoughtNotFound`),
+    /// * saves a query with a regular expression that does not compile (`InvalidPattern`),
+    /// * makes a smart tag match through itself, directly or through other smart tags
+    ///   (`SmartTagCycle`),
+    /// * is a batch containing a command that is rejected, or
+    /// * references a thought that does not exist, if `require_existing_references` is
+    ///   set (`ThoughtNotFound`).
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to check
+    /// * `options` - Which optional checks to perform
+    pub fn validate_command(&self, command: &Command, options: &ValidationOptions) -> Result<()> {
+        match command {
+            Command::PutThought { id, thought } => {
+                check_thought_id(id)?;
+                self.check_unlocked(id)?;
+                for tag in &thought.tags {
+                    check_tag_id(tag)?;
+                    if !self.tags.contains_key(tag) {
+                        return Err(ThoughtGraphError::TagNotFound(tag.id.to_string()));
+                    }
+                }
+                for reference in &thought.references {
+                    check_thought_id(&reference.id)?;
+                    // A thought may refer to itself even before it is first stored
+                    let exists = &reference.id == id || self.thoughts.contains_key(&reference.id);
+                    if options.require_existing_references && !exists {
+                        return Err(ThoughtGraphError::ThoughtNotFound(reference.id.id.to_string()));
+                    }
+                }
+            },
+            Command::DeleteThought { id } => {
+                if !self.thoughts.contains_key(id) {
+                    return Err(ThoughtGraphError::ThoughtNotFound(id.id.to_string()));
+                }
+                self.check_unlocked(id)?;
+            },
+            Command::PutTag { id, tag } => {
+                check_tag_id(id)?;
+                if let Some(parent) = &tag.parent {
+                    check_tag_id(parent)?;
+                    self.check_tag_parent(id, parent)?;
+                }
+                if let Some(query) = &tag.query {
+                    check_query(query)?;
+                    if let Some(cycle) = self.smart_tag_cycle(id, query) {
+                        let cycle: Vec<&str> = cycle.into_iter().map(TagID::as_str).collect();
+                        return Err(ThoughtGraphError::SmartTagCycle(cycle.join(" -> ")));
+                    }
+                }
+            },
+            Command::DeleteTag { id } => {
+                if !self.tags.contains_key(id) {
+                    return Err(ThoughtGraphError::TagNotFound(id.id.to_string()));
+                }
+            },
+            // Rules are validated when they are created, and may name tags that do not
+            // exist yet; those are created when the rule is applied
+            Command::PutTagRule { rule, .. } => check_tag_id(&rule.tag)?,
+            Command::DeleteTagRule { .. } => {},
+            Command::PutSavedQuery { query, .. } => check_query(query)?,
+            Command::DeleteSavedQuery { .. } => {},
+            Command::LockThought { id } => {
+                if !self.thoughts.contains_key(id) {
+                    return Err(ThoughtGraphError::ThoughtNotFound(id.id.to_string()));
+                }
+            },
+            Command::UnlockThought { .. } => {},
+            Command::PatchThought { id, add_tags, add_references, .. } => {
+                self.check_partial_change(id, add_tags, add_references, options)?;
+            },
+            Command::AddTag { id, tag } => {
+                self.check_partial_change(id, std::slice::from_ref(tag), &[], options)?;
+            },
+            Command::AddReference { id, reference } => {
+                self.check_partial_change(id, &[], std::slice::from_ref(reference), options)?;
+            },
+            Command::RemoveTag { id, .. } | Command::RemoveReference { id, .. } => {
+                self.check_partial_change(id, &[], &[], options)?;
+            },
+            Command::Batch(commands) => {
+                // Each command must be valid in the graph left by the ones before it
+                let mut scratch = self.clone();
+                scratch.disable_query_cache();
+                for command in commands {
+                    scratch.validate_command(command, options)?;
+                    scratch.command(command);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Check a change to part of the thought `id` that adds the given tags and references
+    fn check_partial_change(
+        &self,
+        id: &ThoughtID,
+        tags: &[TagID],
+        references: &[Reference],
+        options: &ValidationOptions,
+    ) -> Result<()> {
+        if !self.thoughts.contains_key(id) {
+            return Err(ThoughtGraphError::ThoughtNotFound(id.id.to_string()));
+        }
+        self.check_unlocked(id)?;
+        for tag in tags {
+            check_tag_id(tag)?;
+            if !self.tags.contains_key(tag) {
+                return Err(ThoughtGraphError::TagNotFound(tag.id.to_string()));
+            }
+        }
+        for reference in references {
+            check_thought_id(&reference.id)?;
+            if options.require_existing_references && !self.thoughts.contains_key(&reference.id) {
+                return Err(ThoughtGraphError::ThoughtNotFound(reference.id.id.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that giving the tag `id` the parent `parent` does not make it its own ancestor
+    fn check_tag_parent(&self, id: &TagID, parent: &TagID) -> Result<()> {
+        let mut path = vec![id.id.to_string()];
+        let mut ancestor = Some(parent);
+        while let Some(tag) = ancestor {
+            path.push(tag.id.to_string());
+            if tag == id {
+                return Err(ThoughtGraphError::HierarchyCycle(path.join(" -> ")));
+            }
+            // Cycles not through `id` were rejected when they were made
+            if path.len() > self.tags.len() + 1 {
+                break;
+            }
+            ancestor = self.tags.get(tag).and_then(|tag| tag.parent.as_ref());
+        }
+        Ok(())
+    }
+
+    fn check_unlocked(&self, id: &ThoughtID) -> Result<()> {
+        if self.locked.contains(id) {
+            return Err(ThoughtGraphError::ThoughtLocked(id.id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Apply a command after validating it with the default options.
+    ///
+    /// See `validate_command` for the checks that are performed. If the command is
+    /// rejected, the graph is left unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to apply
+    ///
+    /// # Returns
+    ///
+    /// The changes made by the command, or the reason it was rejected
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtGraphError, ThoughtID, TagID, Thought, Command};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let thought = Thought::new(None, "Tagged".to_string(), vec![TagID::new("missing".to_string())], vec![]);
+    /// let result = graph.try_command(&Command::PutThought { id: ThoughtID::new("t".to_string()), thought });
+    ///
+    /// assert!(matches!(result, Err(ThoughtGraphError::TagNotFound(_))));
+    /// assert!(graph.list_thoughts().is_empty());
+    /// ```
+    pub fn try_command(&mut self, command: &Command) -> Result<ChangeSet> {
+        self.try_command_with(command, &ValidationOptions::default())
+    }
+
+    /// Apply a command after validating it with the given options.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to apply
+    /// * `options` - Which optional checks to perform
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID, Thought, Reference, Command};
+    /// use thoughtgraph::validate::ValidationOptions;
+    /// use chrono::Utc;
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let reference = Reference::new(ThoughtID::new("later".to_string()), String::new(), Utc::now());
+    /// let command = Command::PutThought {
+    ///     id: ThoughtID::new("now".to_string()),
+    ///     thought: Thought::new(None, "See later".to_string(), vec![], vec![reference]),
+    /// };
+    ///
+    /// let strict = ValidationOptions { require_existing_references: true };
+    /// assert!(graph.try_command_with(&command, &strict).is_err());
+    /// assert!(graph.try_command(&command).is_ok());
+    /// ```
+    pub fn try_command_with(&mut self, command: &Command, options: &ValidationOptions) -> Result<ChangeSet> {
+        self.validate_command(command, options)?;
+        Ok(self.command(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Reference, Tag, Thought};
+    use chrono::Utc;
+
+    fn put(id: &str, tags: &[&str], refs: &[&str]) -> Command {
+        Command::PutThought {
+            id: ThoughtID::new(id.to_string()),
+            thought: Thought::new(
+                None,
+                String::new(),
+                tags.iter().map(|t| TagID::new(t.to_string())).collect(),
+                refs.iter()
+                    .map(|r| Reference::new(ThoughtID::new(r.to_string()), String::new(), Utc::now()))
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_rejections_leave_graph_unchanged() {
+        let mut graph = ThoughtGraph::new();
+        graph.create_tag(TagID::new("known".to_string()), String::new()).unwrap();
+
+        assert!(matches!(graph.try_command(&put(" ", &[], &[])), Err(ThoughtGraphError::InvalidThoughtID(_))));
+        assert!(matches!(graph.try_command(&put("a", &["unknown"], &[])), Err(ThoughtGraphError::TagNotFound(_))));
+        assert!(matches!(graph.try_command(&put("a", &[], &[""])), Err(ThoughtGraphError::InvalidThoughtID(_))));
+        assert!(matches!(
+            graph.try_command(&Command::PutTag { id: TagID::new(String::new()), tag: Tag::new(String::new()) }),
+            Err(ThoughtGraphError::InvalidTagID(_))
+        ));
+        assert!(matches!(
+            graph.try_command(&Command::DeleteThought { id: ThoughtID::new("a".to_string()) }),
+            Err(ThoughtGraphError::ThoughtNotFound(_))
+        ));
+        assert!(graph.list_thoughts().is_empty());
+        assert!(graph.backreferences.is_empty());
+
+        graph.try_command(&put("a", &["known"], &["b"])).unwrap();
+        assert_eq!(graph.list_thoughts().len(), 1);
+    }
+
+    #[test]
+    fn test_existing_references_are_opt_in() {
+        let mut graph = ThoughtGraph::new();
+        let strict = ValidationOptions { require_existing_references: true };
+
+        assert!(matches!(
+            graph.try_command_with(&put("a", &[], &["b"]), &strict),
+            Err(ThoughtGraphError::ThoughtNotFound(_))
+        ));
+        // Self references and references to existing thoughts are fine
+        graph.try_command_with(&put("a", &[], &["a"]), &strict).unwrap();
+        graph.try_command_with(&put("b", &[], &["a"]), &strict).unwrap();
+        assert_eq!(graph.list_thoughts().len(), 2);
+    }
+
+    #[test]
+    fn test_batches_are_validated_in_order() {
+        let mut graph = ThoughtGraph::new();
+        let tag = Command::PutTag { id: TagID::new("new".to_string()), tag: Tag::new(String::new()) };
+
+        // A command may rely on the commands before it in the batch
+        graph.try_command(&Command::Batch(vec![tag.clone(), put("a", &["new"], &[])])).unwrap();
+        assert_eq!(graph.list_thoughts().len(), 1);
+
+        // One rejected command rejects the whole batch
+        let batch = Command::Batch(vec![
+            put("b", &[], &[]),
+            Command::DeleteThought { id: ThoughtID::new("a".to_string()) },
+            Command::DeleteThought { id: ThoughtID::new("a".to_string()) },
+        ]);
+        assert!(matches!(graph.try_command(&batch), Err(ThoughtGraphError::ThoughtNotFound(_))));
+        assert_eq!(graph.list_thoughts(), vec![&ThoughtID::new("a".to_string())]);
+    }
+}
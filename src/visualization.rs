@@ -4,6 +4,9 @@
 //! in a ThoughtGraph by generating formats suitable for rendering as a network graph.
 
 use std::collections::HashSet;
+
+use crate::sort::SortBy;
+use crate::traverse::Direction;
 use crate::{ThoughtGraph, ThoughtID};
 
 /// GraphData structure representing the graph for visualization
@@ -51,7 +54,7 @@ impl GraphData {
             dot.push_str(&format!("  \"{}\" [label=\"{}\"];\n", node.id, label));
         }
         
-        dot.push_str("\n");
+        dot.push('\n');
         
         // Add edges
         for edge in &self.edges {
@@ -99,12 +102,12 @@ pub fn generate_graph_data(graph: &ThoughtGraph) -> GraphData {
     let mut edge_id = 0;
     
     // Process all thoughts in the graph
-    for (thought_id, thought) in &graph.thoughts {
+    for (thought_id, thought) in graph.iter_thoughts_sorted(SortBy::Id) {
         // Add the thought as a node
         nodes.push(Node {
-            id: thought_id.id.clone(),
-            label: thought.title.clone().unwrap_or_else(|| thought_id.id.clone()),
-            tags: thought.tags.iter().map(|tag_id| tag_id.id.clone()).collect(),
+            id: thought_id.id.to_string(),
+            label: thought.title.clone().unwrap_or_else(|| thought_id.id.to_string()),
+            tags: thought.tags.iter().map(|tag_id| tag_id.id.to_string()).collect(),
         });
         
         // Process all references as edges
@@ -112,8 +115,36 @@ pub fn generate_graph_data(graph: &ThoughtGraph) -> GraphData {
             edge_id += 1;
             edges.push(Edge {
                 id: format!("edge_{}", edge_id),
-                source: thought_id.id.clone(),
-                target: reference.id.id.clone(),
+                source: thought_id.id.to_string(),
+                target: reference.id.id.to_string(),
+                label: reference.notes.clone(),
+            });
+        }
+    }
+    
+    GraphData { nodes, edges }
+}
+
+/// Generate visualization data for some of the thoughts in a graph, such as those
+/// matching a query, with only the references between them as edges
+pub fn generate_subgraph_data(graph: &ThoughtGraph, ids: &HashSet<ThoughtID>) -> GraphData {
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut edge_id = 0;
+    
+    for (thought_id, thought) in graph.iter_thoughts_sorted(SortBy::Id).filter(|(id, _)| ids.contains(*id)) {
+        nodes.push(Node {
+            id: thought_id.id.to_string(),
+            label: thought.title.clone().unwrap_or_else(|| thought_id.id.to_string()),
+            tags: thought.tags.iter().map(|tag_id| tag_id.id.to_string()).collect(),
+        });
+        
+        for reference in thought.references.iter().filter(|r| ids.contains(&r.id)) {
+            edge_id += 1;
+            edges.push(Edge {
+                id: format!("edge_{}", edge_id),
+                source: thought_id.id.to_string(),
+                target: reference.id.id.to_string(),
                 label: reference.notes.clone(),
             });
         }
@@ -128,73 +159,29 @@ pub fn generate_focused_graph(
     center_id: &ThoughtID, 
     depth: usize
 ) -> GraphData {
-    let mut visited = HashSet::new();
     let mut nodes = Vec::new();
     let mut edges = Vec::new();
     let mut edge_id = 0;
     
-    // Start with the central thought
-    if let Some(_center_thought) = graph.get_thought(center_id) {
-        // BFS traversal of the graph up to specified depth
-        let mut queue = vec![(center_id.clone(), 0)];
-        visited.insert(center_id.clone());
+    // Every thought within `depth` links of the center, in either direction
+    let walk = graph.walk(center_id).direction(Direction::Both).max_depth(depth);
+    for (current_id, thought, _) in walk {
+        nodes.push(Node {
+            id: current_id.id.to_string(),
+            label: thought.title.clone().unwrap_or_else(|| current_id.id.to_string()),
+            tags: thought.tags.iter().map(|tag_id| tag_id.id.to_string()).collect(),
+        });
         
-        while let Some((current_id, current_depth)) = queue.pop() {
-            if current_depth > depth {
-                continue;
-            }
-            
-            if let Some(thought) = graph.get_thought(&current_id) {
-                // Add current thought as a node
-                nodes.push(Node {
-                    id: current_id.id.clone(),
-                    label: thought.title.clone().unwrap_or_else(|| current_id.id.clone()),
-                    tags: thought.tags.iter().map(|tag_id| tag_id.id.clone()).collect(),
-                });
-                
-                // Process outgoing references
-                for reference in &thought.references {
-                    if !visited.contains(&reference.id) && current_depth < depth {
-                        queue.push((reference.id.clone(), current_depth + 1));
-                        visited.insert(reference.id.clone());
-                    }
-                    
-                    // Add the edge regardless of whether we traverse to that node
-                    edge_id += 1;
-                    edges.push(Edge {
-                        id: format!("edge_{}", edge_id),
-                        source: current_id.id.clone(),
-                        target: reference.id.id.clone(),
-                        label: reference.notes.clone(),
-                    });
-                }
-                
-                // Process incoming references (backlinks)
-                for backlink_id in graph.get_backlinks(&current_id) {
-                    if !visited.contains(&backlink_id) && current_depth < depth {
-                        queue.push((backlink_id.clone(), current_depth + 1));
-                        visited.insert(backlink_id.clone());
-                    }
-                    
-                    // Add the edge if the source is already in our visited set
-                    if visited.contains(&backlink_id) {
-                        // Find the reference from the backlink to the current thought
-                        if let Some(backlink_thought) = graph.get_thought(&backlink_id) {
-                            for reference in &backlink_thought.references {
-                                if reference.id == current_id {
-                                    edge_id += 1;
-                                    edges.push(Edge {
-                                        id: format!("edge_{}", edge_id),
-                                        source: backlink_id.id.clone(),
-                                        target: current_id.id.clone(),
-                                        label: reference.notes.clone(),
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        // Add the outgoing references as edges, whether or not we traverse to their
+        // targets; edges from backlinks are added with the thoughts they come from
+        for reference in &thought.references {
+            edge_id += 1;
+            edges.push(Edge {
+                id: format!("edge_{}", edge_id),
+                source: current_id.id.to_string(),
+                target: reference.id.id.to_string(),
+                label: reference.notes.clone(),
+            });
         }
     }
     
@@ -304,6 +291,7 @@ mod tests {
         // Depth 1 should include rust and its immediate connections
         let focused_graph_d1 = generate_focused_graph(&graph, &center_id, 1);
         assert_eq!(focused_graph_d1.nodes.len(), 3); // rust, programming, memory-safety
+        assert_eq!(focused_graph_d1.edges.len(), 2); // each reference appears once
         
         // Check that memory-safety -> rust edge exists
         let memory_to_rust_edge = focused_graph_d1.edges.iter()
@@ -312,6 +300,24 @@ mod tests {
         assert_eq!(memory_to_rust_edge.label, "Rust enforces memory safety");
     }
     
+    #[test]
+    fn test_subgraph_data() {
+        let graph = create_test_graph();
+        let concepts: HashSet<ThoughtID> = graph
+            .find_thoughts(&crate::Query::parse("tag:concept OR tag:programming").unwrap())
+            .into_iter()
+            .filter(|(id, _)| id.as_str() != "programming")
+            .map(|(id, _)| id.clone())
+            .collect();
+        
+        // The reference from rust to programming leaves the subgraph, so it is dropped
+        let subgraph = generate_subgraph_data(&graph, &concepts);
+        let nodes: Vec<&str> = subgraph.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(nodes, vec!["memory-safety", "rust"]);
+        assert_eq!(subgraph.edges.len(), 1);
+        assert_eq!((subgraph.edges[0].source.as_str(), subgraph.edges[0].target.as_str()), ("memory-safety", "rust"));
+    }
+    
     #[test]
     fn test_dot_format() {
         let graph = create_test_graph();
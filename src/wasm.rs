@@ -0,0 +1,125 @@
+//! JavaScript bindings for WebAssembly
+//!
+//! With the `wasm` feature, the crate can be compiled for `wasm32-unknown-unknown` and
+//! used from JavaScript through `wasm-bindgen`, so that browser-based viewers and editors
+//! share the graph logic of the CLI. Build without the default `cli` feature:
+//!
+//! ```text
+//! wasm-pack build --target web -- --no-default-features --features wasm
+//! ```
+//!
+//! The bindings exchange structured values as JSON strings in the same shape as the
+//! crate's serde representation: commands and queries are parsed from JSON, and thoughts
+//! and change sets are returned as JSON. Graphs are loaded from and saved to the same
+//! binary format as graph files on disk.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Command, Query, ThoughtGraph, ThoughtID};
+
+/// A thought graph, exposed to JavaScript as `ThoughtGraph`
+#[wasm_bindgen(js_name = ThoughtGraph)]
+#[derive(Default)]
+pub struct WasmGraph {
+    graph: ThoughtGraph,
+}
+
+/// Sorted string forms of a collection of IDs
+fn id_strings<'a>(ids: impl IntoIterator<Item = &'a ThoughtID>) -> Vec<String> {
+    let mut ids: Vec<String> = ids.into_iter().map(|id| id.as_str().to_string()).collect();
+    ids.sort();
+    ids
+}
+
+#[wasm_bindgen(js_class = ThoughtGraph)]
+impl WasmGraph {
+    /// Create an empty graph
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a graph from the contents of a graph file
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(data: &[u8]) -> Result<WasmGraph, JsError> {
+        Ok(Self { graph: ThoughtGraph::from_bytes(data)? })
+    }
+
+    /// Encode the graph in the graph file format
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.graph.to_bytes()?)
+    }
+
+    /// Encode the whole graph as JSON
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> Result<String, JsError> {
+        Ok(serde_json::to_string(&self.graph)?)
+    }
+
+    /// Validate and apply a JSON-encoded command, returning its changes as JSON.
+    ///
+    /// Invalid commands are rejected with an error and leave the graph unchanged.
+    pub fn command(&mut self, command: &str) -> Result<String, JsError> {
+        let command: Command = serde_json::from_str(command)?;
+        let changes = self.graph.try_command(&command)?;
+        Ok(serde_json::to_string(&changes)?)
+    }
+
+    /// The IDs of the thoughts matching a JSON-encoded query, in order
+    pub fn query(&self, query: &str) -> Result<Vec<String>, JsError> {
+        let query: Query = serde_json::from_str(query)?;
+        Ok(id_strings(&self.graph.query(&query)))
+    }
+
+    /// The IDs of the thoughts containing all of the whitespace-separated terms, in order
+    pub fn search(&self, terms: &str) -> Vec<String> {
+        let terms: Vec<&str> = terms.split_whitespace().collect();
+        id_strings(self.graph.search(&terms).into_iter().map(|(id, _)| id))
+    }
+
+    /// The thought with the given ID as JSON, or `undefined` if there is none
+    pub fn thought(&self, id: &str) -> Result<Option<String>, JsError> {
+        match self.graph.get_thought(&ThoughtID::new(id.to_string())) {
+            Some(thought) => Ok(Some(serde_json::to_string(thought)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The IDs of all thoughts, in order
+    #[wasm_bindgen(js_name = thoughtIds)]
+    pub fn thought_ids(&self) -> Vec<String> {
+        id_strings(self.graph.thoughts.keys())
+    }
+
+    /// The IDs of the thoughts referencing the given thought, in order
+    pub fn backlinks(&self, id: &str) -> Vec<String> {
+        id_strings(&self.graph.get_backlinks(&ThoughtID::new(id.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Errors cannot be created outside of a JavaScript host, so only successful calls
+    // are tested here
+    #[test]
+    fn test_commands_and_queries() {
+        let mut graph = WasmGraph::new();
+        graph.command(r#"{"PutTag": {"id": "rust", "tag": {"description": "", "created_at": "2025-01-01T00:00:00Z", "updated_at": "2025-01-01T00:00:00Z"}}}"#).unwrap();
+        let changes = graph
+            .command(r#"{"PutThought": {"id": "a", "thought": {"title": null, "contents": "Ownership rules", "tags": ["rust"], "references": [{"id": "b", "notes": "", "access_date": "2025-01-01T00:00:00Z"}], "created_at": "2025-01-01T00:00:00Z", "updated_at": "2025-01-01T00:00:00Z"}}}"#)
+            .unwrap();
+        assert!(changes.contains(r#""thoughts_created":["a"]"#));
+
+        assert_eq!(graph.query(r#"{"Tag": "rust"}"#).unwrap(), vec!["a"]);
+        assert_eq!(graph.search("OWNERSHIP"), vec!["a"]);
+        assert_eq!(graph.backlinks("b"), vec!["a"]);
+        assert!(graph.thought("missing").unwrap().is_none());
+
+        let copy = WasmGraph::from_bytes(&graph.to_bytes().unwrap()).unwrap();
+        assert_eq!(copy.thought_ids(), vec!["a"]);
+        assert_eq!(copy.thought("a").unwrap(), graph.thought("a").unwrap());
+    }
+}
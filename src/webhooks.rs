@@ -0,0 +1,124 @@
+//! Webhooks notified when the graph changes
+//!
+//! With the `webhooks` feature, the `[webhooks]` section of the configuration file lists
+//! URLs that are sent the `ChangeSet` of every command that changes the graph, as JSON in
+//! the body of a POST request, once the graph has been saved. External systems such as
+//! task managers or static site generators can use this to react to changes as they
+//! happen:
+//!
+//! ```toml
+//! [webhooks]
+//! urls = ["http://localhost:8080/thoughts-changed"]
+//! timeout-seconds = 5
+//! ```
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::{ChangeSet, Result, ThoughtGraphError};
+
+/// How long to wait for a webhook to respond, unless configured otherwise
+pub const DEFAULT_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The URLs to notify of changes, from the `[webhooks]` section of the configuration
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Webhooks {
+    /// URLs sent each change set
+    pub urls: Vec<String>,
+    /// How long to wait for each URL to respond
+    pub timeout_seconds: Option<u64>,
+}
+
+impl Webhooks {
+    /// Whether no webhooks are configured
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty()
+    }
+
+    /// POST the changes to every URL, in order.
+    ///
+    /// An unreachable URL does not stop the others from being notified. Nothing is sent
+    /// if the change set is empty.
+    ///
+    /// # Returns
+    ///
+    /// `HookFailed` describing every URL that could not be reached or responded with an
+    /// error status
+    pub fn notify(&self, changes: &ChangeSet) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let timeout = self.timeout_seconds.map_or(DEFAULT_WEBHOOK_TIMEOUT, Duration::from_secs);
+        let failures: Vec<String> = self
+            .urls
+            .iter()
+            .filter_map(|url| {
+                ureq::post(url)
+                    .timeout(timeout)
+                    .send_json(changes)
+                    .err()
+                    .map(|e| format!("webhook {}: {}", url, e))
+            })
+            .collect();
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(ThoughtGraphError::HookFailed(failures.join("; ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThoughtID;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Accept one request, answering with `status`, and return its body
+    fn serve_once(listener: TcpListener, status: &'static str) -> std::thread::JoinHandle<String> {
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(reader.get_mut(), "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+            String::from_utf8(body).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_notify() {
+        let changes = ChangeSet { thoughts_created: vec![ThoughtID::new("a".to_string())], ..ChangeSet::default() };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/changed", listener.local_addr().unwrap());
+        let server = serve_once(listener, "204 No Content");
+        let webhooks = Webhooks { urls: vec![url], timeout_seconds: None };
+        webhooks.notify(&ChangeSet::default()).unwrap();
+        webhooks.notify(&changes).unwrap();
+        assert_eq!(server.join().unwrap(), serde_json::to_string(&changes).unwrap());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/changed", listener.local_addr().unwrap());
+        let server = serve_once(listener, "500 Internal Server Error");
+        let webhooks = Webhooks { urls: vec![url], timeout_seconds: Some(5) };
+        assert!(matches!(webhooks.notify(&changes), Err(ThoughtGraphError::HookFailed(_))));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! Word clouds
+//!
+//! The words used most across a set of thoughts hint at the themes of a project. This
+//! module counts the words of thoughts, leaving out stop words as `text::tokenize`
+//! does, and lays the most frequent out as an SVG word cloud: each word is sized by how
+//! often it is used and placed along a spiral from the center, biggest first, where it
+//! does not overlap the words already placed. Words that do not fit are left out.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::text::tokenize;
+use crate::{ThoughtGraph, ThoughtID};
+
+/// Width of a word cloud, in pixels
+pub const CLOUD_WIDTH: f64 = 800.0;
+
+/// Height of a word cloud, in pixels
+pub const CLOUD_HEIGHT: f64 = 500.0;
+
+/// Font size of the least frequent words
+const MIN_FONT_SIZE: f64 = 12.0;
+
+/// Font size of the most frequent word
+const MAX_FONT_SIZE: f64 = 72.0;
+
+/// Width of a character relative to the font size, which is about right for
+/// sans-serif fonts
+const CHAR_WIDTH: f64 = 0.6;
+
+/// Colors of the words, in turn
+const COLORS: [&str; 6] = ["#1f77b4", "#d62728", "#2ca02c", "#9467bd", "#ff7f0e", "#17becf"];
+
+/// A word placed in a word cloud
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedWord {
+    /// The word
+    pub text: String,
+    /// Font size, in pixels
+    pub size: f64,
+    /// Horizontal position of the center of the word
+    pub x: f64,
+    /// Vertical position of the center of the word
+    pub y: f64,
+}
+
+impl PlacedWord {
+    /// The box taken up by the word, as `(left, top, right, bottom)`
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        let half_width = self.text.chars().count() as f64 * self.size * CHAR_WIDTH / 2.0;
+        let half_height = self.size / 2.0;
+        (self.x - half_width, self.y - half_height, self.x + half_width, self.y + half_height)
+    }
+
+    fn overlaps(&self, other: &PlacedWord) -> bool {
+        let (left, top, right, bottom) = self.bounds();
+        let (other_left, other_top, other_right, other_bottom) = other.bounds();
+        left < other_right && other_left < right && top < other_bottom && other_top < bottom
+    }
+
+    fn fits(&self) -> bool {
+        let (left, top, right, bottom) = self.bounds();
+        left >= 0.0 && top >= 0.0 && right <= CLOUD_WIDTH && bottom <= CLOUD_HEIGHT
+    }
+}
+
+impl ThoughtGraph {
+    /// Count the words in the titles and contents of some thoughts.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The thoughts; IDs of thoughts that do not exist are ignored
+    /// * `limit` - The maximum number of words to return
+    ///
+    /// # Returns
+    ///
+    /// Words with their number of occurrences, most frequent first, with ties in
+    /// alphabetical order
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use thoughtgraph::{ThoughtGraph, ThoughtID};
+    ///
+    /// let mut graph = ThoughtGraph::new();
+    /// let a = ThoughtID::new("a".to_string());
+    /// let b = ThoughtID::new("b".to_string());
+    /// graph.create_thought(a.clone(), Some("Rust".to_string()), "Ownership in Rust".to_string(), vec![], vec![]).unwrap();
+    /// graph.create_thought(b.clone(), None, "Borrowing and ownership".to_string(), vec![], vec![]).unwrap();
+    ///
+    /// let terms = graph.term_frequencies(&[a, b], 2);
+    /// assert_eq!(terms, vec![("ownership".to_string(), 2), ("rust".to_string(), 2)]);
+    /// ```
+    pub fn term_frequencies(&self, ids: &[ThoughtID], limit: usize) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for thought in ids.iter().filter_map(|id| self.get_thought(id)) {
+            let title = thought.title.as_deref().unwrap_or_default();
+            for token in tokenize(title).into_iter().chain(tokenize(&thought.contents)) {
+                *counts.entry(token).or_default() += 1;
+            }
+        }
+        let mut terms: Vec<(String, usize)> = counts.into_iter().collect();
+        terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        terms.truncate(limit);
+        terms
+    }
+}
+
+/// Lay out words with their frequencies as a word cloud.
+///
+/// # Arguments
+///
+/// * `terms` - Words with their frequencies, most frequent first, as from
+///   `ThoughtGraph::term_frequencies`
+///
+/// # Returns
+///
+/// The words that fit, in the order given
+pub fn layout(terms: &[(String, usize)]) -> Vec<PlacedWord> {
+    let most = terms.iter().map(|(_, count)| *count).max().unwrap_or_default() as f64;
+    let least = terms.iter().map(|(_, count)| *count).min().unwrap_or_default() as f64;
+    let mut placed: Vec<PlacedWord> = Vec::new();
+    for (text, count) in terms {
+        let weight = match most > least {
+            true => ((*count as f64 - least) / (most - least)).sqrt(),
+            false => 1.0,
+        };
+        let mut word = PlacedWord {
+            text: text.clone(),
+            size: (MIN_FONT_SIZE + (MAX_FONT_SIZE - MIN_FONT_SIZE) * weight).round(),
+            x: 0.0,
+            y: 0.0,
+        };
+        // Walk out along a spiral, stretched to the shape of the cloud
+        let mut angle: f64 = 0.0;
+        while angle < 200.0 {
+            let radius = 2.0 * angle;
+            word.x = CLOUD_WIDTH / 2.0 + radius * angle.cos();
+            word.y = CLOUD_HEIGHT / 2.0 + radius * angle.sin() * CLOUD_HEIGHT / CLOUD_WIDTH;
+            if word.fits() && !placed.iter().any(|other| word.overlaps(other)) {
+                placed.push(word);
+                break;
+            }
+            angle += 0.1;
+        }
+    }
+    placed
+}
+
+/// Escape text for XML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Draw a word cloud as an SVG image, laid out by `layout`.
+///
+/// # Example
+///
+/// ```
+/// use thoughtgraph::wordcloud::render_svg;
+///
+/// let svg = render_svg(&[("rust".to_string(), 5), ("tokio".to_string(), 2)]);
+/// assert!(svg.starts_with("<svg"));
+/// assert!(svg.contains(">rust</text>"));
+/// ```
+pub fn render_svg(terms: &[(String, usize)]) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = CLOUD_WIDTH,
+        h = CLOUD_HEIGHT
+    );
+    svg.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+    for (i, word) in layout(terms).iter().enumerate() {
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+            word.x,
+            word.y,
+            word.size,
+            COLORS[i % COLORS.len()],
+            escape(&word.text)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let terms: Vec<(String, usize)> = (0..60).map(|i| (format!("word{}", i), 120 / (i + 1))).collect();
+        let words = layout(&terms);
+        assert!(words.len() > 30);
+        assert_eq!((words[0].x, words[0].y, words[0].size), (CLOUD_WIDTH / 2.0, CLOUD_HEIGHT / 2.0, MAX_FONT_SIZE));
+        assert!(words.last().unwrap().size < words[0].size);
+        for (i, word) in words.iter().enumerate() {
+            assert!(word.fits());
+            assert!(words[..i].iter().all(|other| !word.overlaps(other)), "{} overlaps", word.text);
+        }
+        assert_eq!(layout(&terms), words);
+
+        let long = vec![("a".repeat(100), 1)];
+        assert!(layout(&long).is_empty());
+        assert!(render_svg(&[("<b>&".to_string(), 1)]).contains(">&lt;b&gt;&amp;</text>"));
+    }
+}